@@ -32,7 +32,7 @@ use futures::stream::StreamExt;
 use ii_async_compat::{futures, tokio};
 use tokio::task;
 
-use embedded_hal::blocking::i2c::{Read, Write};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
 use linux_embedded_hal::I2cdev;
 
 use crate::error::{self, ErrorKind};
@@ -54,6 +54,14 @@ enum Request {
         /// Channel used to send back result
         reply: oneshot::Sender<error::Result<()>>,
     },
+    /// Write followed by read in a single transaction (with repeated start)
+    WriteRead {
+        address: u8,
+        bytes: Vec<u8>,
+        num_bytes: usize,
+        /// Channel used to send back result
+        reply: oneshot::Sender<error::Result<Vec<u8>>>,
+    },
 }
 
 /// Server for I2C read/write requests
@@ -93,6 +101,22 @@ fn serve_requests(
                     warn!("AsyncI2c reply send failed - remote side may have ended");
                 }
             }
+            Request::WriteRead {
+                address,
+                bytes,
+                num_bytes,
+                reply,
+            } => {
+                let mut buffer = vec![0; num_bytes];
+                let result = i2c_device
+                    .write_read(address, &bytes, &mut buffer)
+                    .with_context(|e| ErrorKind::I2c(e.to_string()))
+                    .map(|_| buffer)
+                    .map_err(|e| e.into());
+                if reply.send(result).is_err() {
+                    warn!("AsyncI2c reply send failed - remote side may have ended");
+                }
+            }
         }
     }
     Ok(())
@@ -151,6 +175,27 @@ impl AsyncI2cDev {
             .expect("I2C request failed");
         reply_rx.await.expect("failed to receive I2C reply")
     }
+
+    /// Write `bytes` and read `num_bytes` in a single transaction. This is required by SMBus
+    /// devices which select the register to read by the written command code.
+    pub async fn write_read(
+        &self,
+        address: u8,
+        bytes: Vec<u8>,
+        num_bytes: usize,
+    ) -> error::Result<Vec<u8>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request = Request::WriteRead {
+            address,
+            bytes,
+            num_bytes,
+            reply: reply_tx,
+        };
+        self.request_tx
+            .unbounded_send(request)
+            .expect("I2C request failed");
+        reply_rx.await.expect("failed to receive I2C reply")
+    }
 }
 
 // Please somebody write tests here
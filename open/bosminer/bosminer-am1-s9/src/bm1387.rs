@@ -22,8 +22,14 @@
 
 pub mod i2c;
 
+use crate::chip;
+use crate::command::{self, Interface};
 use crate::error::{self, ErrorKind};
 
+use async_trait::async_trait;
+
+use ii_logging::macros::*;
+
 use packed_struct::prelude::*;
 use packed_struct_codegen::PackedStruct;
 use packed_struct_codegen::{PrimitiveEnum_u16, PrimitiveEnum_u8};
@@ -32,10 +38,16 @@ use once_cell::sync::Lazy;
 
 use ii_fpga_io_am1_s9::common::ctrl_reg::MIDSTATE_CNT_A;
 
+use bosminer::hal::nonce_space;
+
+use ii_async_compat::tokio;
+use tokio::time::delay_for;
+
 use std::convert::TryInto;
 use std::default::Default;
 use std::fmt::Debug;
 use std::mem::size_of;
+use std::time::Duration;
 
 #[allow(dead_code)]
 pub const HASH_COUNTING_REG: u8 = 0x14;
@@ -49,6 +61,9 @@ pub const CHIP_OSC_CLK_BASE_BAUD_DIV: usize = 8;
 /// How many cores are on the chip
 pub const NUM_CORES_ON_CHIP: usize = 114;
 
+/// Delay after `InactivateFromChainCmd` to let the chips process it
+const INACTIVATE_FROM_CHAIN_DELAY: Duration = Duration::from_millis(100);
+
 /// `MidstateCount` represents the number of midstates S9 FPGA sends to chips.
 /// This information needs to be accessible to everyone that processes `work_id`.
 ///
@@ -135,6 +150,12 @@ impl ChipAddress {
     }
 }
 
+/// Division of the nonce space among chips and their cores. Chip index is stored in bits 2-7 and
+/// core index in bits 24-30 of each nonce. Only `NUM_CORES_ON_CHIP` of 128 core subranges are
+/// searched by each chip.
+pub static NONCE_PARTITION: Lazy<nonce_space::Partition> =
+    Lazy::new(|| nonce_space::Partition::new(2, 6, 24, 7, NUM_CORES_ON_CHIP));
+
 /// This is scheme to address particular core on chain
 ///
 /// Every nonce returned by chip (except those sent by opencore) encodes address of the
@@ -149,10 +170,9 @@ pub struct CoreAddress {
 
 impl CoreAddress {
     pub fn new(nonce: u32) -> Self {
-        let nonce = nonce as usize;
         Self {
-            chip: (nonce >> 2) & 0x3f,
-            core: (nonce >> 24) & 0x7f,
+            chip: NONCE_PARTITION.chip(nonce),
+            core: NONCE_PARTITION.core(nonce),
         }
     }
 }
@@ -387,6 +407,8 @@ impl Register for GetAddressReg {
 #[derive(PrimitiveEnum_u16, Clone, Copy, Debug, PartialEq)]
 pub enum ChipRev {
     Bm1387 = 0x1387,
+    /// Chip of Antminer S17 hashboards, it is recognized only to report unsupported hashboard
+    Bm1391 = 0x1391,
 }
 
 impl Default for ChipRev {
@@ -731,6 +753,170 @@ impl PllFrequency {
     }
 }
 
+/// BM1387 implementation of chip control
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+#[async_trait]
+impl chip::Driver for Driver {
+    fn chip_rev(&self) -> ChipRev {
+        ChipRev::Bm1387
+    }
+
+    fn core_count(&self) -> usize {
+        NUM_CORES_ON_CHIP
+    }
+
+    fn read_register_cmd(&self, chip_address: ChipAddress, reg_num: u8) -> Vec<u8> {
+        GetStatusCmd::new(chip_address, reg_num).pack().to_vec()
+    }
+
+    fn write_register_cmd(&self, chip_address: ChipAddress, reg_num: u8, value: u32) -> Vec<u8> {
+        SetConfigCmd::new(chip_address, reg_num, value)
+            .pack()
+            .to_vec()
+    }
+
+    async fn enumerate(&self, command_context: &command::Context) -> error::Result<usize> {
+        // Enumerate all chips (broadcast read address register request)
+        let responses = command_context
+            .read_register::<GetAddressReg>(ChipAddress::All)
+            .await?;
+
+        // Check if are responses meaningful
+        let mut chip_count = 0;
+        for (address, addr_reg) in responses.iter().enumerate() {
+            if addr_reg.chip_rev != CHIP_REV_BM1387 {
+                Err(ErrorKind::ChipEnumeration(format!(
+                    "unexpected revision of chip {} (expected: {:#x?} received: {:#x?})",
+                    address, CHIP_REV_BM1387, addr_reg.chip_rev,
+                )))?
+            }
+            chip_count += 1;
+        }
+        if chip_count >= crate::MAX_CHIPS_ON_CHAIN {
+            Err(ErrorKind::ChipEnumeration(format!(
+                "detected {} chips, expected less than {} chips on one chain. Possibly a hardware issue?",
+                chip_count,
+                crate::MAX_CHIPS_ON_CHAIN,
+            )))?
+        }
+        if chip_count == 0 {
+            Err(ErrorKind::ChipEnumeration(
+                "no chips detected on the current chain".to_string(),
+            ))?
+        }
+
+        // Set all chips to be offline before address assignment. This is important so that each
+        // chip after initially accepting the address will pass on further addresses down the chain
+        let inactivate_from_chain_cmd = InactivateFromChainCmd::new().pack();
+        // make sure all chips receive inactivation request
+        for _ in 0..3 {
+            command_context
+                .send_raw_command(inactivate_from_chain_cmd.to_vec(), false)
+                .await;
+            delay_for(INACTIVATE_FROM_CHAIN_DELAY).await;
+        }
+
+        // Assign address to each chip
+        for i in 0..chip_count {
+            let cmd = SetChipAddressCmd::new(ChipAddress::One(i));
+            command_context
+                .send_raw_command(cmd.pack().to_vec(), false)
+                .await;
+        }
+
+        Ok(chip_count)
+    }
+
+    async fn set_pll(
+        &self,
+        command_context: &command::Context,
+        chip_address: ChipAddress,
+        frequency: usize,
+    ) -> error::Result<usize> {
+        // convert frequency to PLL setting register
+        let pll = PllFrequency::lookup_freq(frequency)?;
+
+        // NOTE: When PLL register is read back, it is or-ed with 0x8000_0000, not sure why.
+        //  Avoid reading it back to prevent disappointment.
+        command_context
+            .write_register(chip_address, &pll.reg)
+            .await?;
+
+        Ok(pll.frequency)
+    }
+
+    async fn set_baud_rate(
+        &self,
+        command_context: &command::Context,
+        baud_rate: usize,
+        not_set_baud: bool,
+        gate_block: bool,
+    ) -> error::Result<usize> {
+        let (baud_clock_div, actual_baud_rate) = crate::calc_baud_clock_div(
+            baud_rate,
+            crate::CHIP_OSC_CLK_HZ,
+            CHIP_OSC_CLK_BASE_BAUD_DIV,
+        )?;
+        info!(
+            "Setting Hash chain baud rate @ requested: {}, actual: {}, divisor {:#04x}",
+            baud_rate, actual_baud_rate, baud_clock_div
+        );
+        // Each chip is always configured with inverted clock
+        let ctl_reg = MiscCtrlReg::new(not_set_baud, true, baud_clock_div, gate_block, true)?;
+        // Do not read back the MiscCtrl register when setting baud rate: it will result
+        // in serial speed mismatch and nothing being read.
+        command_context
+            .write_register(ChipAddress::All, &ctl_reg)
+            .await?;
+        Ok(actual_baud_rate)
+    }
+
+    async fn set_ticket_mask(
+        &self,
+        command_context: &command::Context,
+        difficulty: usize,
+    ) -> error::Result<()> {
+        let tm_reg = TicketMaskReg::new(difficulty as u32)?;
+        trace!(
+            "Setting ticket mask register for difficulty {}, value {:#010x?}",
+            difficulty,
+            tm_reg
+        );
+        command_context
+            .write_register_readback(ChipAddress::All, &tm_reg)
+            .await?;
+        Ok(())
+    }
+
+    async fn read_registers(
+        &self,
+        command_context: &command::Context,
+        chip_address: ChipAddress,
+    ) -> error::Result<Vec<chip::RegisterValue>> {
+        const REGISTERS: [(&str, u8); 6] = [
+            ("ChipAddress", GetAddressReg::REG_NUM),
+            ("Hashrate", HashrateReg::REG_NUM),
+            ("Pll", PllReg::REG_NUM),
+            ("TicketMask", TicketMaskReg::REG_NUM),
+            ("MiscCtrl", MiscCtrlReg::REG_NUM),
+            ("I2cControl", I2cControlReg::REG_NUM),
+        ];
+        let mut registers = Vec::with_capacity(REGISTERS.len());
+        for &(name, reg_num) in REGISTERS.iter() {
+            registers.push(chip::RegisterValue {
+                name,
+                reg_num,
+                values: command_context
+                    .read_raw_register(chip_address, reg_num)
+                    .await?,
+            });
+        }
+        Ok(registers)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -901,6 +1087,14 @@ mod test {
         assert_eq!(reg.chip_rev, EnumCatchAll::CatchAll(0x1386));
     }
 
+    #[test]
+    fn test_bm1391_chip_addr_value() {
+        let reg_bytes = [0x13u8, 0x91, 0x90, 0x00];
+        let reg = GetAddressReg::unpack_from_slice(&reg_bytes).expect("unpack failed");
+        assert_eq!(reg.chip_rev, EnumCatchAll::Enum(ChipRev::Bm1391));
+        assert_ne!(reg.chip_rev, CHIP_REV_BM1387);
+    }
+
     #[test]
     fn build_misc_control_reg() {
         let reg = MiscCtrlReg {
@@ -0,0 +1,118 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Detection of BM1391 chips (Antminer S17 hashboard)
+//!
+//! The only thing known about BM1391 is the revision it reports in the chip address register
+//! (see `bm1387::GetAddressReg`). Its command set, register map and PLL layout are not
+//! implemented, so this driver only recognizes a hash chain with BM1391 chips during chip
+//! detection and refuses to bring it up. Note that BM139x chips also require the BM139x mode of
+//! the `axi_bm13xx` IP core (see `open/hw/zynq-io-am1-s9/README.md`) which is not enabled by
+//! the hash chain.
+
+use crate::bm1387::{self, ChipAddress, ChipRev, GetAddressReg, Register};
+use crate::chip;
+use crate::command;
+use crate::error::{self, ErrorKind};
+
+use async_trait::async_trait;
+
+/// Error returned by all operations that would require the unknown parts of the chip control
+fn unsupported<T>(operation: &str) -> error::Result<T> {
+    Err(ErrorKind::Hashchip(format!(
+        "cannot {}, BM1391 chips are not supported",
+        operation
+    )))?
+}
+
+/// BM1391 driver that is only able to detect the chips
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Driver;
+
+#[async_trait]
+impl chip::Driver for Driver {
+    fn chip_rev(&self) -> ChipRev {
+        ChipRev::Bm1391
+    }
+
+    /// The number of cores is not known, the hash chain is never brought up (see `enumerate`)
+    fn core_count(&self) -> usize {
+        0
+    }
+
+    /// The chip address register is read with the BM1387 command that is used for the chip
+    /// detection
+    fn read_register_cmd(&self, chip_address: ChipAddress, reg_num: u8) -> Vec<u8> {
+        chip::Driver::read_register_cmd(&bm1387::Driver, chip_address, reg_num)
+    }
+
+    fn write_register_cmd(&self, chip_address: ChipAddress, reg_num: u8, value: u32) -> Vec<u8> {
+        chip::Driver::write_register_cmd(&bm1387::Driver, chip_address, reg_num, value)
+    }
+
+    async fn enumerate(&self, _command_context: &command::Context) -> error::Result<usize> {
+        Err(ErrorKind::ChipEnumeration(
+            "detected BM1391 chips (Antminer S17 hashboard) which are not supported".to_string(),
+        ))?
+    }
+
+    async fn set_pll(
+        &self,
+        _command_context: &command::Context,
+        _chip_address: ChipAddress,
+        _frequency: usize,
+    ) -> error::Result<usize> {
+        unsupported("set PLL")
+    }
+
+    async fn set_baud_rate(
+        &self,
+        _command_context: &command::Context,
+        _baud_rate: usize,
+        _not_set_baud: bool,
+        _gate_block: bool,
+    ) -> error::Result<usize> {
+        unsupported("set baud rate")
+    }
+
+    async fn set_ticket_mask(
+        &self,
+        _command_context: &command::Context,
+        _difficulty: usize,
+    ) -> error::Result<()> {
+        unsupported("set ticket mask")
+    }
+
+    async fn read_registers(
+        &self,
+        command_context: &command::Context,
+        chip_address: ChipAddress,
+    ) -> error::Result<Vec<chip::RegisterValue>> {
+        Ok(vec![chip::RegisterValue {
+            name: "ChipAddress",
+            reg_num: GetAddressReg::REG_NUM,
+            values: command_context
+                .read_raw_register(chip_address, GetAddressReg::REG_NUM)
+                .await?,
+        }])
+    }
+}
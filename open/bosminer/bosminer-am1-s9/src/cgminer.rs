@@ -20,20 +20,41 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
-use ii_cgminer_api::command::{DEVDETAILS, FANS, TEMPCTRL, TEMPS};
-use ii_cgminer_api::{command, commands, response};
+use ii_cgminer_api::command::{
+    CHIPREGS, CHIPREGSET, DEVDETAILS, FANS, HASHBOARDS, HASHBOARD_START, HASHBOARD_STOP, PROFILES,
+    PROFILE_SET, PSU, TEMPCTRL, TEMPS,
+};
+use ii_cgminer_api::{command, commands, response, PARAMETER_DELIMITER};
 
 use serde::Serialize;
+use serde_json as json;
 
 use std::sync::Arc;
 
+use crate::bm1387::ChipAddress;
+use crate::hooks;
 use crate::monitor;
+use crate::psu;
 use crate::sensor;
 
+use ii_async_compat::tokio;
+use ii_logging::macros::*;
+
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 #[repr(u32)]
 pub enum StatusCode {
     NotReady = 1,
+    InvalidChipRegsParameter = 2,
+    HashboardNotRunning = 3,
+    InvalidChip = 4,
+    ChipRegisterAccess = 5,
+    MissingHashboardParameter = 6,
+    InvalidHashboard = 7,
+    HashboardControl = 8,
+    MissingProfileParameter = 9,
+    InvalidProfile = 10,
+    PsuNotAvailable = 11,
+    HardwareControlDisabled = 12,
 }
 
 impl From<StatusCode> for u32 {
@@ -44,18 +65,144 @@ impl From<StatusCode> for u32 {
 
 pub enum ErrorCode {
     NotReady,
+    InvalidChipRegsParameter(String),
+    HashboardNotRunning(usize),
+    InvalidChip(usize, usize),
+    ChipRegisterAccess(usize, String),
+    MissingHashboardParameter,
+    InvalidHashboard(i32),
+    HashboardControl(usize, String),
+    MissingProfileParameter,
+    InvalidProfile(String),
+    PsuNotAvailable(String),
+    HardwareControlDisabled(String),
 }
 
 impl From<ErrorCode> for response::Error {
     fn from(code: ErrorCode) -> Self {
         let (code, msg) = match code {
             ErrorCode::NotReady => (StatusCode::NotReady, "Not ready".to_string()),
+            ErrorCode::InvalidChipRegsParameter(command) => (
+                StatusCode::InvalidChipRegsParameter,
+                format!("Invalid or missing parameter of command '{}'", command),
+            ),
+            ErrorCode::HashboardNotRunning(hashboard_idx) => (
+                StatusCode::HashboardNotRunning,
+                format!("Hashboard {} is not running", hashboard_idx),
+            ),
+            ErrorCode::InvalidChip(hashboard_idx, chip) => (
+                StatusCode::InvalidChip,
+                format!("Invalid chip {} on hashboard {}", chip, hashboard_idx),
+            ),
+            ErrorCode::ChipRegisterAccess(hashboard_idx, error) => (
+                StatusCode::ChipRegisterAccess,
+                format!(
+                    "Cannot access chip register on hashboard {}: {}",
+                    hashboard_idx, error
+                ),
+            ),
+            ErrorCode::MissingHashboardParameter => (
+                StatusCode::MissingHashboardParameter,
+                "Missing hashboard number".to_string(),
+            ),
+            ErrorCode::InvalidHashboard(id) => (
+                StatusCode::InvalidHashboard,
+                format!("Hashboard {} is not present", id),
+            ),
+            ErrorCode::HashboardControl(hashboard_idx, error) => (
+                StatusCode::HashboardControl,
+                format!("Hashboard {}: {}", hashboard_idx, error),
+            ),
+            ErrorCode::MissingProfileParameter => (
+                StatusCode::MissingProfileParameter,
+                "Missing profile name".to_string(),
+            ),
+            ErrorCode::InvalidProfile(name) => (
+                StatusCode::InvalidProfile,
+                format!("Profile '{}' is not configured", name),
+            ),
+            ErrorCode::PsuNotAvailable(reason) => (
+                StatusCode::PsuNotAvailable,
+                format!("PSU is not available: {}", reason),
+            ),
+            ErrorCode::HardwareControlDisabled(command) => (
+                StatusCode::HardwareControlDisabled,
+                format!(
+                    "Command '{}' is disabled, enable 'api.hardware_control' in configuration",
+                    command
+                ),
+            ),
         };
 
         Self::from_custom_error(code, msg)
     }
 }
 
+/// Parameter of chip register diagnostic commands in format
+/// `hashboard,chip[,register[,value]]`. Numbers can be given in decimal or in hexadecimal with
+/// `0x` prefix.
+#[derive(PartialEq, Clone, Debug)]
+struct ChipRegisterParameter {
+    hashboard_idx: usize,
+    chip: usize,
+    register: Option<u8>,
+    value: Option<u32>,
+}
+
+impl ChipRegisterParameter {
+    fn parse_number(number: &str) -> Option<u32> {
+        let number = number.trim();
+        if number.starts_with("0x") || number.starts_with("0X") {
+            u32::from_str_radix(&number[2..], 16).ok()
+        } else {
+            number.parse().ok()
+        }
+    }
+
+    /// Parse parameter of command `command` which has between `min_args` and `max_args`
+    /// arguments in total
+    fn parse(
+        command: &str,
+        parameter: Option<&json::Value>,
+        min_args: usize,
+        max_args: usize,
+    ) -> command::Result<Self> {
+        let error = || -> response::Error {
+            ErrorCode::InvalidChipRegsParameter(command.to_string()).into()
+        };
+        let args = match parameter {
+            Some(json::Value::String(value)) => value
+                .split(PARAMETER_DELIMITER)
+                .map(Self::parse_number)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(error)?,
+            _ => Err(error())?,
+        };
+        if args.len() < min_args || args.len() > max_args {
+            Err(error())?
+        }
+        let register = match args.get(2) {
+            Some(&register) if register > u8::max_value() as u32 => Err(error())?,
+            register => register.map(|&register| register as u8),
+        };
+
+        Ok(Self {
+            hashboard_idx: args[0] as usize,
+            chip: args[1] as usize,
+            register,
+            value: args.get(3).cloned(),
+        })
+    }
+
+    fn parse_chip_regs(parameter: Option<&json::Value>) -> command::Result<Self> {
+        Self::parse(CHIPREGS, parameter, 2, 3)
+    }
+
+    fn parse_chip_reg_set(parameter: Option<&json::Value>) -> command::Result<Self> {
+        Self::parse(CHIPREGSET, parameter, 4, 4)
+    }
+}
+
 #[derive(Serialize, PartialEq, Clone, Debug)]
 pub struct DevDetailInfo {
     #[serde(rename = "Voltage")]
@@ -66,6 +213,10 @@ pub struct DevDetailInfo {
     pub chips: u32,
     #[serde(rename = "Cores")]
     pub cores: u32,
+    /// Active performance profile
+    #[serde(rename = "Profile")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
 }
 
 #[derive(Serialize, PartialEq, Clone, Debug)]
@@ -80,6 +231,11 @@ pub struct Handler {
     model: String,
     managers: Vec<Arc<crate::Manager>>,
     monitor: Arc<monitor::Monitor>,
+    psu: Option<Arc<psu::Psu>>,
+    /// Commands controlling the hardware are allowed
+    hardware_control: bool,
+    /// Hooks deciding whether a hashboard may be started
+    hooks: Arc<dyn hooks::Hooks>,
 }
 
 impl Handler {
@@ -87,14 +243,29 @@ impl Handler {
         model: String,
         managers: Vec<Arc<crate::Manager>>,
         monitor: Arc<monitor::Monitor>,
+        psu: Option<Arc<psu::Psu>>,
+        hardware_control: bool,
+        hooks: Arc<dyn hooks::Hooks>,
     ) -> Self {
         Self {
             model,
             managers,
             monitor,
+            psu,
+            hardware_control,
+            hooks,
         }
     }
 
+    /// The API is not authenticated so commands controlling the hardware have to be explicitly
+    /// enabled in configuration
+    fn check_hardware_control(&self, command: &str) -> command::Result<()> {
+        if !self.hardware_control {
+            Err(ErrorCode::HardwareControlDisabled(command.to_string()))?
+        }
+        Ok(())
+    }
+
     fn get_monitor_status(&self) -> command::Result<monitor::Status> {
         match self.monitor.status_receiver.borrow().clone() {
             Some(status) => Ok(status),
@@ -127,6 +298,7 @@ impl Handler {
                     frequency,
                     chips: chip_count as u32,
                     cores: (chip_count * crate::bm1387::NUM_CORES_ON_CHIP) as u32,
+                    profile: manager.active_profile(),
                 },
             });
         }
@@ -202,21 +374,375 @@ impl Handler {
                 .collect(),
         })
     }
+
+    /// Lists all hashboard connectors including the empty ones
+    async fn handle_hashboards(&self) -> command::Result<response::ext::Hashboards> {
+        let mut list = vec![];
+        for hashboard_idx in 1..=crate::MAX_HASHBOARDS {
+            // Managers are created only for the detected hashboards
+            let manager = self
+                .managers
+                .iter()
+                .find(|manager| manager.hashboard_idx == hashboard_idx);
+            let mut hashboard = response::ext::Hashboard {
+                idx: list.len() as i32,
+                id: hashboard_idx as i32,
+                present: manager.is_some(),
+                enabled: false,
+                running: false,
+                chips: None,
+            };
+            if let Some(manager) = manager {
+                let inner = manager.inner.lock().await;
+                hashboard.enabled = manager.chain_config.enabled;
+                hashboard.running = inner.hash_chain.is_some();
+                hashboard.chips = inner.chip_count.map(|count| count as u32);
+            }
+            list.push(hashboard);
+        }
+        Ok(response::ext::Hashboards { list })
+    }
+
+    async fn handle_psu(&self) -> command::Result<response::ext::Psus> {
+        let psu = self
+            .psu
+            .as_ref()
+            .ok_or_else(|| ErrorCode::PsuNotAvailable("PMBus metering is disabled".to_string()))?;
+        let readout = psu
+            .read()
+            .await
+            .map_err(|e| ErrorCode::PsuNotAvailable(e.to_string()))?;
+        Ok(response::ext::Psus {
+            list: vec![response::ext::Psu {
+                idx: 0,
+                input_voltage: readout.input_voltage,
+                input_current: readout.input_current,
+                input_power: readout.input_power,
+            }],
+        })
+    }
+
+    /// Find manager of the hashboard specified by the hashboard number in `parameter`
+    fn get_manager(&self, parameter: Option<&json::Value>) -> command::Result<Arc<crate::Manager>> {
+        let id = parameter
+            .and_then(json::Value::as_i64)
+            .expect("BUG: missing hashboard parameter") as i32;
+        self.managers
+            .iter()
+            .find(|manager| manager.hashboard_idx as i32 == id)
+            .cloned()
+            .ok_or_else(|| ErrorCode::InvalidHashboard(id).into())
+    }
+
+    /// Starts the hashboard in background because the initialization takes a long time
+    async fn handle_hashboard_start(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::HashboardStart> {
+        self.check_hardware_control(HASHBOARD_START)?;
+        let manager = self.get_manager(parameter)?;
+        let hashboard_idx = manager.hashboard_idx;
+        if !manager.chain_config.enabled {
+            Err(ErrorCode::HashboardControl(
+                hashboard_idx,
+                "disabled in configuration".to_string(),
+            ))?
+        }
+        if !self.hooks.can_start_chain(manager.clone()).await {
+            Err(ErrorCode::HashboardControl(
+                hashboard_idx,
+                "start refused by hooks".to_string(),
+            ))?
+        }
+        if let Some(owner) = manager.owner() {
+            Err(ErrorCode::HashboardControl(
+                hashboard_idx,
+                format!("controlled by {}", owner),
+            ))?
+        }
+        if manager.inner.lock().await.hash_chain.is_some() {
+            Err(ErrorCode::HashboardControl(
+                hashboard_idx,
+                "already running".to_string(),
+            ))?
+        }
+
+        tokio::spawn(async move {
+            if let Err(e) = manager.start_controlled("api").await {
+                error!("Hashboard {}: start failed: {}", hashboard_idx, e);
+            }
+        });
+        Ok(response::ext::HashboardStart {
+            id: hashboard_idx as i32,
+        })
+    }
+
+    async fn handle_hashboard_stop(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::HashboardStop> {
+        self.check_hardware_control(HASHBOARD_STOP)?;
+        let manager = self.get_manager(parameter)?;
+        let hashboard_idx = manager.hashboard_idx;
+        manager
+            .stop_controlled("api")
+            .await
+            .map_err(|e| ErrorCode::HashboardControl(hashboard_idx, e.to_string()))?;
+        Ok(response::ext::HashboardStop {
+            id: hashboard_idx as i32,
+        })
+    }
+
+    async fn handle_profiles(&self) -> command::Result<response::ext::Profiles> {
+        // all hashboards share the same profiles from the configuration
+        let names = self
+            .managers
+            .first()
+            .map(|manager| manager.profile_names())
+            .unwrap_or_default();
+        let list = names
+            .into_iter()
+            .enumerate()
+            .map(|(idx, name)| response::ext::Profile {
+                idx: idx as i32,
+                active: self
+                    .managers
+                    .iter()
+                    .all(|manager| manager.active_profile().as_ref() == Some(&name)),
+                name,
+            })
+            .collect();
+        Ok(response::ext::Profiles { list })
+    }
+
+    async fn handle_profile_set(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::ProfileSet> {
+        let name = parameter
+            .and_then(json::Value::as_str)
+            .expect("BUG: missing profile parameter");
+        let is_configured = self
+            .managers
+            .iter()
+            .all(|manager| manager.profile_names().iter().any(|n| n == name));
+        if !is_configured {
+            Err(ErrorCode::InvalidProfile(name.to_string()))?
+        }
+
+        // switch all hashboards even if some of them fail
+        let mut result = Ok(());
+        for manager in self.managers.iter() {
+            let hashboard_idx = manager.hashboard_idx;
+            if let Err(e) = manager.clone().set_profile(name, "api").await {
+                error!("Hashboard {}: profile switch failed: {}", hashboard_idx, e);
+                if result.is_ok() {
+                    result = Err(ErrorCode::HashboardControl(hashboard_idx, e.to_string()));
+                }
+            }
+        }
+        result?;
+        Ok(response::ext::ProfileSet {
+            name: name.to_string(),
+        })
+    }
+
+    /// Find running hash chain and check that the chip addressed by `parameter` exists
+    async fn get_running_hash_chain(
+        &self,
+        parameter: &ChipRegisterParameter,
+    ) -> command::Result<Arc<crate::HashChain>> {
+        let hash_chain = match self
+            .managers
+            .iter()
+            .find(|manager| manager.hashboard_idx == parameter.hashboard_idx)
+        {
+            Some(manager) => manager.inner.lock().await.hash_chain.clone(),
+            None => None,
+        }
+        .ok_or_else(|| ErrorCode::HashboardNotRunning(parameter.hashboard_idx))?;
+
+        if parameter.chip >= hash_chain.chip_count {
+            Err(ErrorCode::InvalidChip(
+                parameter.hashboard_idx,
+                parameter.chip,
+            ))?
+        }
+        Ok(hash_chain)
+    }
+
+    /// Reads either all known registers or one selected register of a chip
+    async fn handle_chip_regs(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::ChipRegisters> {
+        let parameter = ChipRegisterParameter::parse_chip_regs(parameter)?;
+        let hash_chain = self.get_running_hash_chain(&parameter).await?;
+        let chip_address = ChipAddress::One(parameter.chip);
+        let access_error = |e: crate::error::Error| {
+            ErrorCode::ChipRegisterAccess(parameter.hashboard_idx, e.to_string())
+        };
+
+        let registers = match parameter.register {
+            Some(reg_num) => vec![(
+                String::new(),
+                reg_num,
+                hash_chain
+                    .read_raw_register(chip_address, reg_num)
+                    .await
+                    .map_err(access_error)?,
+            )],
+            None => hash_chain
+                .read_registers(chip_address)
+                .await
+                .map_err(access_error)?
+                .into_iter()
+                .map(|register| (register.name.to_string(), register.reg_num, register.values))
+                .collect(),
+        };
+
+        let mut list = vec![];
+        for (name, reg_num, values) in registers {
+            for value in values {
+                list.push(response::ext::ChipRegister {
+                    idx: list.len() as i32,
+                    name: name.clone(),
+                    register: reg_num as u32,
+                    value,
+                });
+            }
+        }
+        Ok(response::ext::ChipRegisters {
+            hashboard_id: parameter.hashboard_idx as i32,
+            chip: parameter.chip as u32,
+            list,
+        })
+    }
+
+    /// Writes a chip register and reads it back
+    async fn handle_chip_reg_set(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::ChipRegisterSet> {
+        self.check_hardware_control(CHIPREGSET)?;
+        let parameter = ChipRegisterParameter::parse_chip_reg_set(parameter)?;
+        let hash_chain = self.get_running_hash_chain(&parameter).await?;
+        let chip_address = ChipAddress::One(parameter.chip);
+        let reg_num = parameter.register.expect("BUG: missing register");
+        let value = parameter.value.expect("BUG: missing register value");
+        let access_error = |e: crate::error::Error| {
+            ErrorCode::ChipRegisterAccess(parameter.hashboard_idx, e.to_string())
+        };
+
+        hash_chain
+            .write_raw_register(chip_address, reg_num, value)
+            .await
+            .map_err(access_error)?;
+        let value = hash_chain
+            .read_raw_register(chip_address, reg_num)
+            .await
+            .map_err(access_error)?
+            .remove(0);
+
+        Ok(response::ext::ChipRegisterSet {
+            hashboard_id: parameter.hashboard_idx as i32,
+            chip: parameter.chip as u32,
+            register: response::ext::ChipRegister {
+                idx: 0,
+                name: String::new(),
+                register: reg_num as u32,
+                value,
+            },
+        })
+    }
 }
 
 pub fn create_custom_commands(
     backend: Arc<crate::Backend>,
     managers: Vec<Arc<crate::Manager>>,
     monitor: Arc<monitor::Monitor>,
+    psu: Option<Arc<psu::Psu>>,
+    hardware_control: bool,
+    hooks: Arc<dyn hooks::Hooks>,
 ) -> Option<command::Map> {
-    let handler = Arc::new(Handler::new(backend.to_string(), managers, monitor));
+    let handler = Arc::new(Handler::new(
+        backend.to_string(),
+        managers,
+        monitor,
+        psu,
+        hardware_control,
+        hooks,
+    ));
+
+    let check_hashboard: command::ParameterCheckHandler =
+        Box::new(|_command, parameter| match parameter {
+            Some(value) if value.is_i64() => Ok(()),
+            _ => Err(ErrorCode::MissingHashboardParameter.into()),
+        });
+    let check_profile: command::ParameterCheckHandler =
+        Box::new(|_command, parameter| match parameter {
+            Some(value) if value.is_string() => Ok(()),
+            _ => Err(ErrorCode::MissingProfileParameter.into()),
+        });
+    let check_chip_regs: command::ParameterCheckHandler = Box::new(|_command, parameter| {
+        ChipRegisterParameter::parse_chip_regs(*parameter).map(|_| ())
+    });
+    let check_chip_reg_set: command::ParameterCheckHandler = Box::new(|_command, parameter| {
+        ChipRegisterParameter::parse_chip_reg_set(*parameter).map(|_| ())
+    });
 
     let custom_commands = commands![
         (DEVDETAILS: ParameterLess -> handler.handle_dev_details),
         (TEMPCTRL: ParameterLess -> handler.handle_temp_ctrl),
         (TEMPS: ParameterLess -> handler.handle_temps),
-        (FANS: ParameterLess -> handler.handle_fans)
+        (FANS: ParameterLess -> handler.handle_fans),
+        (HASHBOARDS: ParameterLess -> handler.handle_hashboards),
+        (CHIPREGS: Parameter(check_chip_regs) -> handler.handle_chip_regs),
+        (CHIPREGSET: Parameter(check_chip_reg_set) -> handler.handle_chip_reg_set),
+        (HASHBOARD_START: Parameter(check_hashboard) -> handler.handle_hashboard_start),
+        (HASHBOARD_STOP: Parameter(check_hashboard) -> handler.handle_hashboard_stop),
+        (PROFILES: ParameterLess -> handler.handle_profiles),
+        (PROFILE_SET: Parameter(check_profile) -> handler.handle_profile_set),
+        (PSU: ParameterLess -> handler.handle_psu)
     ];
 
     Some(custom_commands)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chip_register_parameter() {
+        let parameter = json::Value::String("8,3,0x0c".to_string());
+        assert_eq!(
+            ChipRegisterParameter::parse_chip_regs(Some(&parameter)).expect("BUG: parse failed"),
+            ChipRegisterParameter {
+                hashboard_idx: 8,
+                chip: 3,
+                register: Some(0x0c),
+                value: None,
+            }
+        );
+
+        let parameter = json::Value::String("6,0,24,0xdeadbeef".to_string());
+        assert_eq!(
+            ChipRegisterParameter::parse_chip_reg_set(Some(&parameter)).expect("BUG: parse failed"),
+            ChipRegisterParameter {
+                hashboard_idx: 6,
+                chip: 0,
+                register: Some(24),
+                value: Some(0xdeadbeef),
+            }
+        );
+
+        // missing register value, register number out of range and garbage
+        for parameter in ["6,0,24", "6,0,0x100,1", "6,x,1,1"].iter() {
+            let parameter = json::Value::String(parameter.to_string());
+            assert!(ChipRegisterParameter::parse_chip_reg_set(Some(&parameter)).is_err());
+        }
+        assert!(ChipRegisterParameter::parse_chip_regs(None).is_err());
+        assert!(ChipRegisterParameter::parse_chip_regs(Some(&json::Value::Bool(true))).is_err());
+    }
+}
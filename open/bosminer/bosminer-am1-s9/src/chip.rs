@@ -0,0 +1,142 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Interface of a hashing chip generation. The hash chain is brought up through this interface
+//! so that support for another chip only has to provide the chip specific commands and register
+//! layouts.
+
+use crate::bm1387::{self, ChipAddress, ChipRev};
+use crate::bm1391;
+use crate::command::{self, Interface};
+use crate::error::{self, ErrorKind};
+
+use async_trait::async_trait;
+
+use packed_struct::prelude::*;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Raw value of chip register read back for diagnostics
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterValue {
+    pub name: &'static str,
+    pub reg_num: u8,
+    /// One value per each addressed chip
+    pub values: Vec<u32>,
+}
+
+#[async_trait]
+pub trait Driver: Send + Sync + fmt::Debug {
+    /// Chip revision reported in the chip address register by chips handled by this driver
+    fn chip_rev(&self) -> ChipRev;
+
+    /// Number of cores on one chip
+    fn core_count(&self) -> usize;
+
+    /// Build command that reads register `reg_num` of chip(s)
+    fn read_register_cmd(&self, chip_address: ChipAddress, reg_num: u8) -> Vec<u8>;
+
+    /// Build command that writes `value` to register `reg_num` of chip(s)
+    fn write_register_cmd(&self, chip_address: ChipAddress, reg_num: u8, value: u32) -> Vec<u8>;
+
+    /// Find all chips on the chain and assign an address to each of them
+    ///
+    /// Returns number of chips that responded
+    async fn enumerate(&self, command_context: &command::Context) -> error::Result<usize>;
+
+    /// Program PLL of chip(s) to the closest possible frequency
+    ///
+    /// Returns actual frequency that has been set
+    async fn set_pll(
+        &self,
+        command_context: &command::Context,
+        chip_address: ChipAddress,
+        frequency: usize,
+    ) -> error::Result<usize>;
+
+    /// Switch communication speed of all chips on the chain. The IP core has to be switched
+    /// to the same speed afterwards.
+    ///
+    /// * `not_set_baud` - chips ignore the new baud rate, see `bm1387::MiscCtrlReg`
+    /// * `gate_block` - allows gradual startup of the chips in the chain
+    ///
+    /// Returns actual baud rate that has been set
+    async fn set_baud_rate(
+        &self,
+        command_context: &command::Context,
+        baud_rate: usize,
+        not_set_baud: bool,
+        gate_block: bool,
+    ) -> error::Result<usize>;
+
+    /// Configure difficulty of solutions reported by all chips (ticket mask)
+    async fn set_ticket_mask(
+        &self,
+        command_context: &command::Context,
+        difficulty: usize,
+    ) -> error::Result<()>;
+
+    /// Read back all known registers of chip(s)
+    async fn read_registers(
+        &self,
+        command_context: &command::Context,
+        chip_address: ChipAddress,
+    ) -> error::Result<Vec<RegisterValue>>;
+}
+
+/// All supported chip generations in the order they are probed
+pub fn drivers() -> Vec<Arc<dyn Driver>> {
+    vec![Arc::new(bm1387::Driver), Arc::new(bm1391::Driver)]
+}
+
+/// Detect generation of chips on the chain and configure `command_context` to talk to them
+///
+/// Chips are probed by a broadcast read of the chip address register with the command set of
+/// each supported driver. The driver matching the revision of the first responding chip is
+/// returned.
+pub async fn detect(command_context: &command::Context) -> error::Result<Arc<dyn Driver>> {
+    let mut received_rev = None;
+    for driver in drivers() {
+        command_context.set_chip_driver(driver.clone()).await;
+        let responses = command_context
+            .read_register::<bm1387::GetAddressReg>(ChipAddress::All)
+            .await?;
+        let chip_rev = match responses.first() {
+            Some(addr_reg) => addr_reg.chip_rev,
+            None => continue,
+        };
+        if let Some(driver) = drivers()
+            .into_iter()
+            .find(|driver| chip_rev == EnumCatchAll::Enum(driver.chip_rev()))
+        {
+            command_context.set_chip_driver(driver.clone()).await;
+            return Ok(driver);
+        }
+        received_rev = Some(chip_rev);
+    }
+    let message = match received_rev {
+        Some(chip_rev) => format!("unsupported chip revision {:#x?}", chip_rev),
+        None => "no chips detected on the current chain".to_string(),
+    };
+    Err(ErrorKind::ChipEnumeration(message))?
+}
@@ -31,10 +31,11 @@ use ii_logging::macros::*;
 use async_trait::async_trait;
 
 use crate::bm1387::{self, ChipAddress};
+use crate::chip;
 use crate::io;
 use std::time::Duration;
 
-use packed_struct::{PackedStruct, PackedStructSlice};
+use packed_struct::PackedStructSlice;
 
 use futures::lock::Mutex;
 use ii_async_compat::futures;
@@ -117,6 +118,8 @@ pub struct InnerContext {
     /// If `chip_count` is `None`, number of chips haven't been determined yet so
     /// skip the check.
     chip_count: Option<usize>,
+    /// Chip generation specific layout of register commands
+    chip_driver: Arc<dyn chip::Driver>,
 }
 
 /// Interface to access chip registers via series of commands
@@ -135,11 +138,24 @@ impl InnerContext {
         &mut self,
         chip_address: ChipAddress,
     ) -> error::Result<Vec<T>> {
-        let cmd = bm1387::GetStatusCmd::new(chip_address, T::REG_NUM);
+        // convert to registers
+        Ok(self
+            .read_raw_register(chip_address, T::REG_NUM)
+            .await?
+            .into_iter()
+            .map(|x| T::from_reg(x))
+            .collect::<Vec<T>>())
+    }
+
+    /// Read raw value of register `reg_num`
+    async fn read_raw_register(
+        &mut self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+    ) -> error::Result<Vec<u32>> {
+        let cmd = self.chip_driver.read_register_cmd(chip_address, reg_num);
         // send command, do not wait for it to be sent out
-        self.command_io
-            .send_command(cmd.pack().to_vec(), false)
-            .await;
+        self.command_io.send_command(cmd, false).await;
 
         // wait for all responses and collect them
         let mut responses = Vec::new();
@@ -171,7 +187,7 @@ impl InnerContext {
                     Err(ErrorKind::Hashchip(format!(
                         "Number of responses {} of GetStatusCmd(reg={:#x}) doesn't match chip count {}",
                         responses.len(),
-                        T::REG_NUM,
+                        reg_num,
                         chip_count
                     )))?;
                 }
@@ -180,17 +196,12 @@ impl InnerContext {
             if responses.len() != 1 {
                 Err(ErrorKind::Hashchip(format!(
                     "No response for GetStatusCmd(reg={:#x}) from chip {:?}",
-                    T::REG_NUM,
-                    chip_address
+                    reg_num, chip_address
                 )))?;
             }
         }
 
-        // convert to registers
-        Ok(responses
-            .into_iter()
-            .map(|x| T::from_reg(x))
-            .collect::<Vec<T>>())
+        Ok(responses)
     }
 
     async fn flush_command_rx(&mut self) -> error::Result<()> {
@@ -210,11 +221,22 @@ impl InnerContext {
         chip_address: ChipAddress,
         value: &'a T,
     ) -> error::Result<()> {
-        let cmd = bm1387::SetConfigCmd::new(chip_address, T::REG_NUM, value.to_reg());
+        self.write_raw_register(chip_address, T::REG_NUM, value.to_reg())
+            .await
+    }
+
+    /// Write raw value to register `reg_num`
+    async fn write_raw_register(
+        &mut self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+        value: u32,
+    ) -> error::Result<()> {
+        let cmd = self
+            .chip_driver
+            .write_register_cmd(chip_address, reg_num, value);
         // wait for command to be sent out
-        self.command_io
-            .send_command(cmd.pack().to_vec(), true)
-            .await;
+        self.command_io.send_command(cmd, true).await;
         // This is workaround for chips sending garbage when they transmit nonce while someone
         // changes their PLL: sometimes the garbage can have correct CRC and command bit set.
         // Then we get unsolicited message in our command-rx queue and the next read register
@@ -236,10 +258,16 @@ impl InnerContext {
         self.chip_count = Some(chip_count);
     }
 
+    /// Switch command set to another chip generation
+    fn set_chip_driver(&mut self, chip_driver: Arc<dyn chip::Driver>) {
+        self.chip_driver = chip_driver;
+    }
+
     pub fn new(command_io: io::CommandRxTx) -> Self {
         Self {
             command_io,
             chip_count: None,
+            chip_driver: Arc::new(bm1387::Driver),
         }
     }
 }
@@ -271,6 +299,27 @@ impl Interface for Context {
 }
 
 impl Context {
+    /// Read raw value of register that has no `bm1387::Register` representation
+    pub async fn read_raw_register(
+        &self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+    ) -> error::Result<Vec<u32>> {
+        let mut inner = self.inner.lock().await;
+        inner.read_raw_register(chip_address, reg_num).await
+    }
+
+    /// Write raw value to register that has no `bm1387::Register` representation
+    pub async fn write_raw_register(
+        &self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+        value: u32,
+    ) -> error::Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner.write_raw_register(chip_address, reg_num, value).await
+    }
+
     pub async fn send_raw_command(&self, cmd: Vec<u8>, wait: bool) {
         let mut inner = self.inner.lock().await;
         inner.send_raw_command(cmd, wait).await
@@ -281,6 +330,11 @@ impl Context {
         inner.set_chip_count(chip_count);
     }
 
+    pub async fn set_chip_driver(&self, chip_driver: Arc<dyn chip::Driver>) {
+        let mut inner = self.inner.lock().await;
+        inner.set_chip_driver(chip_driver);
+    }
+
     pub fn new(command_io: io::CommandRxTx) -> Self {
         Self {
             inner: Arc::new(Mutex::new(InnerContext::new(command_io))),
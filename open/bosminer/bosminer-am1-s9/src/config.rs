@@ -38,12 +38,16 @@ use crate::fan;
 use crate::hooks;
 use crate::monitor;
 use crate::power;
+use crate::tuner;
 use crate::FrequencySettings;
 
 use support::OptionDefault;
 
 use bosminer::client;
 use bosminer::hal::{self, BackendConfig as _};
+use bosminer::job;
+use bosminer::stats;
+use bosminer::translation_proxy;
 
 use bosminer_config::{ClientDescriptor, ClientUserInfo};
 
@@ -52,6 +56,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -100,12 +105,44 @@ pub const DEFAULT_TARGET_TEMP_C: f64 = 89.0;
 pub const DEFAULT_HOT_TEMP_C: f64 = 100.0;
 pub const DEFAULT_DANGEROUS_TEMP_C: f64 = 110.0;
 
+/// Default time in seconds for which an overheated hashchain is stopped by thermal cutoff
+pub const DEFAULT_THERMAL_CUTOFF_COOLDOWN_S: u64 = 300;
+
+/// Range of thermal cutoff cooldown in seconds
+pub const THERMAL_CUTOFF_COOLDOWN_S_MIN: u64 = 30;
+pub const THERMAL_CUTOFF_COOLDOWN_S_MAX: u64 = 3600;
+
 /// Default fan speed for manual target speed
 pub const DEFAULT_FAN_SPEED: usize = 100;
 
+/// Default lowest fan speed set by automatic fan control
+pub const DEFAULT_FAN_MIN_SPEED: usize = 1;
+
 /// Default minimal running fans for monitoring
 pub const DEFAULT_MIN_FANS: usize = 1;
 
+/// Default value for reading of power supply measurements over PMBus
+pub const DEFAULT_PSU_PMBUS: bool = false;
+
+/// The API is not authenticated so commands controlling the hardware are disabled by default
+pub const DEFAULT_API_HARDWARE_CONTROL: bool = false;
+
+/// Default settings of share difficulty ramp for newly connected pools
+pub const DEFAULT_DIFFICULTY_RAMP_ENABLED: bool = false;
+pub const DEFAULT_DIFFICULTY_RAMP_START_DIFFICULTY: usize = 64;
+pub const DEFAULT_DIFFICULTY_RAMP_PERIOD_S: u64 = 300;
+
+/// Range of share difficulty the ramp starts at
+pub const DIFFICULTY_RAMP_START_DIFFICULTY_MIN: usize = 1;
+pub const DIFFICULTY_RAMP_START_DIFFICULTY_MAX: usize = 65536;
+
+/// Range of difficulty ramp period in seconds
+pub const DIFFICULTY_RAMP_PERIOD_S_MIN: u64 = 10;
+pub const DIFFICULTY_RAMP_PERIOD_S_MAX: u64 = 3600;
+
+/// Only headers of found blocks are exported by default to limit the volume
+pub const DEFAULT_AUDIT_HEADER_EXPORT_BLOCKS_ONLY: bool = true;
+
 /// Index of hashboard that is to be instantiated
 pub const S9_HASHBOARD_INDEX: usize = 8;
 
@@ -113,10 +150,33 @@ pub const S9_HASHBOARD_INDEX: usize = 8;
 pub const HASH_CHAIN_INDEX_MIN: usize = 6;
 pub const HASH_CHAIN_INDEX_MAX: usize = 8;
 
+/// Default time without any nonce after which the hashchain is restarted
+pub const DEFAULT_WATCHDOG_TIMEOUT_S: u64 = 120;
+
+/// Range of hashchain watchdog timeout in seconds (zero disables the watchdog)
+pub const WATCHDOG_TIMEOUT_S_MIN: u64 = 30;
+pub const WATCHDOG_TIMEOUT_S_MAX: u64 = 3600;
+
 /// Range of PLL frequency for clocking the chips in MHz
 pub const FREQUENCY_MHZ_MIN: f64 = 200.0;
 pub const FREQUENCY_MHZ_MAX: f64 = 900.0;
 
+/// Default settings of per-chip frequency autotuning
+pub const DEFAULT_AUTOTUNING_ENABLED: bool = false;
+pub const DEFAULT_AUTOTUNING_FREQUENCY_STEP_MHZ: f64 = 5.0;
+pub const DEFAULT_AUTOTUNING_MAX_HW_ERRORS: f64 = 2.0;
+pub const DEFAULT_AUTOTUNING_MIN_NONCES: f64 = 80.0;
+pub const DEFAULT_AUTOTUNING_PERIOD: Duration = Duration::from_secs(600);
+pub const DEFAULT_AUTOTUNING_VOLTAGE_STEP_V: f32 = 0.1;
+
+/// Range of frequency step used by autotuning in MHz
+pub const AUTOTUNING_FREQUENCY_STEP_MHZ_MIN: f64 = 1.0;
+pub const AUTOTUNING_FREQUENCY_STEP_MHZ_MAX: f64 = 50.0;
+
+/// Range of power target of the whole miner in watts
+pub const POWER_TARGET_W_MIN: f64 = 100.0;
+pub const POWER_TARGET_W_MAX: f64 = 5000.0;
+
 /// Range of hash chain voltage
 pub const VOLTAGE_V_MIN: f64 = 7.95;
 pub const VOLTAGE_V_MAX: f64 = 9.4;
@@ -142,11 +202,18 @@ pub const DEFAULT_HASHRATE_INTERVAL: Duration = Duration::from_secs(60);
 /// Maximum time it takes to compute one job under normal circumstances
 pub const JOB_TIMEOUT: Duration = Duration::from_secs(5);
 
+#[derive(Clone)]
 pub struct ResolvedChainConfig {
+    /// Performance profile the settings have been resolved for
+    pub profile: Option<String>,
     pub midstate_count: MidstateCount,
     pub frequency: FrequencySettings,
     pub voltage: power::Voltage,
     pub enabled: bool,
+    pub thermal_throttle: Option<monitor::ThermalThrottleConfig>,
+    pub thermal_cutoff: Option<monitor::ThermalCutoffConfig>,
+    pub watchdog_timeout: Option<Duration>,
+    pub tuner: Option<tuner::Config>,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug)]
@@ -182,10 +249,41 @@ pub struct Format {
 pub struct HashChainGlobal {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub asic_boost: Option<bool>,
+    /// Time in seconds without any nonce after which the hashchain is restarted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watchdog_timeout: Option<u64>,
+    /// Name of performance profile which is active after start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
     #[serde(flatten)]
     pub overridable: Option<HashChain>,
 }
 
+impl HashChainGlobal {
+    fn sanity_check(&self) -> Result<(), String> {
+        if let Some(watchdog_timeout) = self.watchdog_timeout {
+            if watchdog_timeout != 0
+                && !(WATCHDOG_TIMEOUT_S_MIN..=WATCHDOG_TIMEOUT_S_MAX).contains(&watchdog_timeout)
+            {
+                Err(format!(
+                    "watchdog timeout '{}' is out of range '{}..{}'",
+                    watchdog_timeout, WATCHDOG_TIMEOUT_S_MIN, WATCHDOG_TIMEOUT_S_MAX
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Watchdog timeout or `None` when the watchdog is disabled
+    fn watchdog_timeout(&self) -> Option<Duration> {
+        match self.watchdog_timeout {
+            Some(0) => None,
+            Some(timeout) => Some(Duration::from_secs(timeout)),
+            None => Some(Duration::from_secs(DEFAULT_WATCHDOG_TIMEOUT_S)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct HashChain {
@@ -197,6 +295,51 @@ pub struct HashChain {
     pub voltage: Option<f64>,
 }
 
+/// Named performance profile (e.g. `eco` or `turbo`) replacing the global hash chain settings.
+/// Per-chain overrides still take precedence over the profile.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f64>,
+    /// Power budget of the whole miner in watts, it is used only when autotuning is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_target: Option<f64>,
+}
+
+impl Profile {
+    fn sanity_check(&self, name: &str) -> Result<(), String> {
+        let ranges = [
+            (
+                "frequency",
+                self.frequency,
+                FREQUENCY_MHZ_MIN,
+                FREQUENCY_MHZ_MAX,
+            ),
+            ("voltage", self.voltage, VOLTAGE_V_MIN, VOLTAGE_V_MAX),
+            (
+                "power target",
+                self.power_target,
+                POWER_TARGET_W_MIN,
+                POWER_TARGET_W_MAX,
+            ),
+        ];
+        for &(field, value, min, max) in ranges.iter() {
+            if let Some(value) = value {
+                if !(min..=max).contains(&value) {
+                    Err(format!(
+                        "profile '{}' {} '{}' is out of range '{}..{}'",
+                        name, field, value, min, max
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct TempControl {
@@ -208,6 +351,37 @@ pub struct TempControl {
     hot_temp: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     dangerous_temp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    throttle_temp: Option<f64>,
+    /// Temperature at which the hashchain is stopped until it cools down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cutoff_temp: Option<f64>,
+    /// Time in seconds for which the hashchain stays stopped after cutoff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cutoff_cooldown: Option<u64>,
+}
+
+impl TempControl {
+    fn sanity_check(&self) -> Result<(), String> {
+        if let Some(cutoff_cooldown) = self.cutoff_cooldown {
+            if !(THERMAL_CUTOFF_COOLDOWN_S_MIN..=THERMAL_CUTOFF_COOLDOWN_S_MAX)
+                .contains(&cutoff_cooldown)
+            {
+                Err(format!(
+                    "cutoff cooldown '{}' is out of range '{}..{}'",
+                    cutoff_cooldown, THERMAL_CUTOFF_COOLDOWN_S_MIN, THERMAL_CUTOFF_COOLDOWN_S_MAX
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cutoff_cooldown(&self) -> Duration {
+        Duration::from_secs(
+            self.cutoff_cooldown
+                .unwrap_or(DEFAULT_THERMAL_CUTOFF_COOLDOWN_S),
+        )
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -215,10 +389,412 @@ pub struct TempControl {
 pub struct FanControl {
     #[serde(skip_serializing_if = "Option::is_none")]
     speed: Option<usize>,
+    /// Lowest fan speed in percent when fans are controlled by target temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_speed: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     min_fans: Option<usize>,
 }
 
+impl FanControl {
+    fn sanity_check(&self) -> Result<(), String> {
+        for (name, value) in &[("speed", self.speed), ("min_speed", self.min_speed)] {
+            if let Some(value) = value {
+                if !(FAN_SPEED_MIN..=FAN_SPEED_MAX).contains(value) {
+                    Err(format!(
+                        "fan {} '{}' is out of range '{}..{}'",
+                        name, value, FAN_SPEED_MIN, FAN_SPEED_MAX
+                    ))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Psu {
+    /// Read input power of the power supply over PMBus
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pmbus: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Api {
+    /// Allow API commands which start and stop hashboards or write chip registers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hardware_control: Option<bool>,
+    /// Publish Prometheus metrics on this address (`host:port`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metrics_listen: Option<String>,
+}
+
+impl Api {
+    fn parse_metrics_listen(listen: &str) -> Result<SocketAddr, String> {
+        listen
+            .parse()
+            .map_err(|_| format!("invalid metrics listen address '{}'", listen))
+    }
+
+    fn sanity_check(&self) -> Result<(), String> {
+        if let Some(listen) = &self.metrics_listen {
+            Self::parse_metrics_listen(listen)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DifficultyRamp {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    /// Share difficulty requested right after the pool is connected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_difficulty: Option<usize>,
+    /// Time in seconds over which the difficulty is raised to the pool difficulty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period: Option<u64>,
+}
+
+impl DifficultyRamp {
+    fn sanity_check(&self) -> Result<(), String> {
+        if let Some(start_difficulty) = self.start_difficulty {
+            if !(DIFFICULTY_RAMP_START_DIFFICULTY_MIN..=DIFFICULTY_RAMP_START_DIFFICULTY_MAX)
+                .contains(&start_difficulty)
+            {
+                Err(format!(
+                    "difficulty ramp start difficulty '{}' is out of range '{}..{}'",
+                    start_difficulty,
+                    DIFFICULTY_RAMP_START_DIFFICULTY_MIN,
+                    DIFFICULTY_RAMP_START_DIFFICULTY_MAX
+                ))?;
+            }
+        }
+        if let Some(period) = self.period {
+            if !(DIFFICULTY_RAMP_PERIOD_S_MIN..=DIFFICULTY_RAMP_PERIOD_S_MAX).contains(&period) {
+                Err(format!(
+                    "difficulty ramp period '{}' is out of range '{}..{}'",
+                    period, DIFFICULTY_RAMP_PERIOD_S_MIN, DIFFICULTY_RAMP_PERIOD_S_MAX
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Difficulty ramp or `None` when it is disabled
+    fn difficulty_ramp(&self) -> Option<job::DifficultyRamp> {
+        if !self.enabled.unwrap_or(DEFAULT_DIFFICULTY_RAMP_ENABLED) {
+            return None;
+        }
+        Some(job::DifficultyRamp::new(
+            self.start_difficulty
+                .unwrap_or(DEFAULT_DIFFICULTY_RAMP_START_DIFFICULTY),
+            Duration::from_secs(self.period.unwrap_or(DEFAULT_DIFFICULTY_RAMP_PERIOD_S)),
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Audit {
+    /// Append block headers of found shares to this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header_export: Option<String>,
+    /// Export only headers of found blocks instead of all shares
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header_export_blocks_only: Option<bool>,
+    /// Append details of found blocks to this file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_archive: Option<String>,
+}
+
+impl Audit {
+    fn sanity_check(&self) -> Result<(), String> {
+        for (name, path) in &[
+            ("header_export", &self.header_export),
+            ("block_archive", &self.block_archive),
+        ] {
+            if let Some(path) = path {
+                if path.is_empty() {
+                    Err(format!("audit '{}' file path is empty", name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Autotuning {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    /// Frequency change of one tuning step in MHz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_step: Option<f64>,
+    /// Range of chip frequencies in MHz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_frequency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_frequency: Option<f64>,
+    /// Maximal tolerated ratio of hardware errors in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_hw_errors: Option<f64>,
+    /// Minimal ratio of valid nonces to expected ones in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_nonces: Option<f64>,
+    /// Power budget of the whole miner in watts which turns on scaling of voltage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power_target: Option<f64>,
+}
+
+impl Autotuning {
+    fn frequency_range(&self) -> (f64, f64) {
+        (
+            self.min_frequency.unwrap_or(FREQUENCY_MHZ_MIN),
+            self.max_frequency.unwrap_or(FREQUENCY_MHZ_MAX),
+        )
+    }
+
+    fn sanity_check(&self) -> Result<(), String> {
+        let (min_frequency, max_frequency) = self.frequency_range();
+        let frequency_range = FREQUENCY_MHZ_MIN..=FREQUENCY_MHZ_MAX;
+        if !frequency_range.contains(&min_frequency)
+            || !frequency_range.contains(&max_frequency)
+            || min_frequency > max_frequency
+        {
+            Err(format!(
+                "autotuning frequency range '{}..{}' is out of range '{}..{}'",
+                min_frequency, max_frequency, FREQUENCY_MHZ_MIN, FREQUENCY_MHZ_MAX
+            ))?;
+        }
+        if let Some(frequency_step) = self.frequency_step {
+            if !(AUTOTUNING_FREQUENCY_STEP_MHZ_MIN..=AUTOTUNING_FREQUENCY_STEP_MHZ_MAX)
+                .contains(&frequency_step)
+            {
+                Err(format!(
+                    "autotuning frequency step '{}' is out of range '{}..{}'",
+                    frequency_step,
+                    AUTOTUNING_FREQUENCY_STEP_MHZ_MIN,
+                    AUTOTUNING_FREQUENCY_STEP_MHZ_MAX
+                ))?;
+            }
+        }
+        for (name, value) in &[
+            ("max_hw_errors", self.max_hw_errors),
+            ("min_nonces", self.min_nonces),
+        ] {
+            if let Some(value) = value {
+                if !(0.0..=100.0).contains(value) {
+                    Err(format!(
+                        "autotuning '{}' value '{}' is not percentage",
+                        name, value
+                    ))?;
+                }
+            }
+        }
+        if let Some(power_target) = self.power_target {
+            if !(POWER_TARGET_W_MIN..=POWER_TARGET_W_MAX).contains(&power_target) {
+                Err(format!(
+                    "autotuning power target '{}' is out of range '{}..{}'",
+                    power_target, POWER_TARGET_W_MIN, POWER_TARGET_W_MAX
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve(&self) -> Option<tuner::Config> {
+        if !self.enabled.unwrap_or(DEFAULT_AUTOTUNING_ENABLED) {
+            return None;
+        }
+        let (min_frequency, max_frequency) = self.frequency_range();
+        let frequency_step = self
+            .frequency_step
+            .unwrap_or(DEFAULT_AUTOTUNING_FREQUENCY_STEP_MHZ);
+        Some(tuner::Config {
+            frequency_step: (frequency_step * 1_000_000.0) as usize,
+            min_frequency: (min_frequency * 1_000_000.0) as usize,
+            max_frequency: (max_frequency * 1_000_000.0) as usize,
+            max_error_ratio: self
+                .max_hw_errors
+                .unwrap_or(DEFAULT_AUTOTUNING_MAX_HW_ERRORS)
+                / 100.0,
+            min_return_ratio: self.min_nonces.unwrap_or(DEFAULT_AUTOTUNING_MIN_NONCES) / 100.0,
+            period: DEFAULT_AUTOTUNING_PERIOD,
+            power_target: self.power_target.map(|power| tuner::PowerTarget {
+                power,
+                voltage_step: DEFAULT_AUTOTUNING_VOLTAGE_STEP_V,
+                min_voltage: VOLTAGE_V_MIN as f32,
+                model: tuner::PowerModel::S9,
+            }),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Logging {
+    /// One of `off`, `error`, `warning`, `info`, `debug` or `trace`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Log into this file instead of the standard error output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Per-module levels in `RUST_LOG` syntax (e.g. `bosminer::client=debug`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// One of `full` or `json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Also send logs to this syslog server (`host:port`, UDP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syslog_server: Option<String>,
+    /// Local address the syslog messages are sent from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syslog_bind: Option<String>,
+    /// Also send logs as JSON lines to this server (`host:port`, TCP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_server: Option<String>,
+}
+
+impl Logging {
+    /// Read only the logging section of the configuration file. It is needed before the logger
+    /// is set up so any error is silently ignored here and reported later when the whole
+    /// configuration is parsed.
+    pub fn parse(config_path: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Section {
+            logging: Option<Logging>,
+        }
+
+        bosminer_config::parse::<Section>(config_path)
+            .ok()
+            .and_then(|section| section.logging)
+            .unwrap_or_default()
+    }
+
+    /// Map level names explicitly, `Level::from_str` panics on "off"
+    fn parse_level(level: &str) -> Result<ii_logging::Level, String> {
+        match level.to_ascii_lowercase().as_str() {
+            // The logger cannot be turned off completely, keep at least critical messages
+            "off" | "critical" => Ok(ii_logging::Level::Critical),
+            "error" => Ok(ii_logging::Level::Error),
+            "warn" | "warning" => Ok(ii_logging::Level::Warning),
+            "info" => Ok(ii_logging::Level::Info),
+            "debug" => Ok(ii_logging::Level::Debug),
+            "trace" => Ok(ii_logging::Level::Trace),
+            _ => Err(format!("unknown logging level '{}'", level)),
+        }
+    }
+
+    fn parse_format(format: &str) -> Result<ii_logging::LoggingFormat, String> {
+        match format {
+            "full" => Ok(ii_logging::LoggingFormat::Full),
+            "json" => Ok(ii_logging::LoggingFormat::Json),
+            _ => Err(format!("unknown logging format '{}'", format)),
+        }
+    }
+
+    /// Check that remote server address is in `host:port` format
+    fn check_server(server: &str) -> Result<(), String> {
+        match server.rfind(':') {
+            Some(i) if i > 0 && server[i + 1..].parse::<u16>().is_ok() => Ok(()),
+            _ => Err(format!(
+                "invalid logging server '{}' (expected 'host:port')",
+                server
+            )),
+        }
+    }
+
+    fn parse_bind(bind: &str) -> Result<IpAddr, String> {
+        bind.parse()
+            .map_err(|_| format!("invalid syslog bind address '{}'", bind))
+    }
+
+    fn sanity_check(&self) -> Result<(), String> {
+        for server in self.syslog_server.iter().chain(self.json_server.iter()) {
+            Self::check_server(server)?;
+        }
+        if let Some(bind) = &self.syslog_bind {
+            Self::parse_bind(bind)?;
+        }
+        if let Some(level) = &self.level {
+            Self::parse_level(level)?;
+        }
+        if let Some(format) = &self.format {
+            Self::parse_format(format)?;
+        }
+        Ok(())
+    }
+
+    /// Build logger configuration where `level` (e.g. from command line) takes precedence over
+    /// the level from configuration file
+    pub fn logging_config(&self, level: Option<&str>) -> Result<ii_logging::LoggingConfig, String> {
+        let mut config = ii_logging::LoggingConfig::for_app(ASYNC_LOGGER_DRAIN_CHANNEL_SIZE);
+        if let Some(level) = level.or(self.level.as_deref()) {
+            config.level = Self::parse_level(level)?;
+        }
+        if let Some(file) = &self.file {
+            config.target = ii_logging::LoggingTarget::File(file.into());
+        }
+        if let Some(format) = &self.format {
+            config.format = Self::parse_format(format)?;
+        }
+        config.filter = self.filter.clone();
+        for server in &self.syslog_server {
+            Self::check_server(server)?;
+            let bind = match &self.syslog_bind {
+                Some(bind) => Some(Self::parse_bind(bind)?),
+                None => None,
+            };
+            config.sinks.push(ii_logging::LoggingSink::Syslog {
+                server: server.clone(),
+                bind,
+            });
+        }
+        for server in &self.json_server {
+            Self::check_server(server)?;
+            config
+                .sinks
+                .push(ii_logging::LoggingSink::Tcp(server.clone()));
+        }
+        Ok(config)
+    }
+}
+
+/// Stratum V1->V2 translation proxy for legacy miners running alongside the mining
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TranslationProxy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Address for incoming Stratum V1 connections (`host:port`)
+    pub listen: String,
+    /// Upstream Stratum V2 server URL
+    pub upstream: String,
+    /// User of the upstream channels opened for the downstream miners
+    pub user: String,
+}
+
+impl TranslationProxy {
+    fn proxy_config(&self) -> Result<translation_proxy::Config, String> {
+        translation_proxy::Config::parse(
+            self.listen.as_str(),
+            self.upstream.as_str(),
+            self.user.as_str(),
+        )
+        .map_err(|e| format!("translation proxy: {}", e))
+    }
+
+    fn sanity_check(&self) -> Result<(), String> {
+        self.proxy_config().map(|_| ())
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Backend {
@@ -234,10 +810,27 @@ pub struct Backend {
     #[serde(rename = "hash_chain")]
     #[serde(skip_serializing_if = "Option::is_none")]
     hash_chains: Option<BTreeMap<String, HashChain>>,
+    #[serde(rename = "profile")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profiles: Option<BTreeMap<String, Profile>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temp_control: Option<TempControl>,
     #[serde(skip_serializing_if = "Option::is_none")]
     fan_control: Option<FanControl>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    psu: Option<Psu>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api: Option<Api>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    autotuning: Option<Autotuning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    difficulty_ramp: Option<DifficultyRamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audit: Option<Audit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Logging>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation_proxy: Option<TranslationProxy>,
     #[serde(rename = "group")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub groups: Option<Vec<bosminer_config::GroupConfig>>,
@@ -245,6 +838,15 @@ pub struct Backend {
     pub hooks: Option<Arc<dyn hooks::Hooks>>,
     #[serde(skip)]
     pub fans_on_while_warming_up: Option<bool>,
+    /// Path of the configuration file the pools are reloaded from on `SIGHUP`
+    #[serde(skip)]
+    pub config_path: Option<String>,
+    /// Pools given on command line override the ones from configuration file even on reload
+    #[serde(skip)]
+    pub cli_pools: bool,
+    /// Recorded session replayed instead of connecting to the pools
+    #[serde(skip)]
+    pub replay_session: Option<String>,
 }
 
 pub trait ConfigBody
@@ -338,6 +940,23 @@ where
             Err(e) => Err(e),
         }
     }
+
+    /// Parse config file and return only its body. Incompatible format version is treated as
+    /// a warning. A file which cannot be parsed (e.g. it has been truncated due to power loss
+    /// during write) is an error and it is never replaced with the default configuration.
+    pub fn parse_body(config_path: &str) -> Result<B, FormatWrapperError<B>> {
+        match Self::parse(config_path) {
+            Ok(config) => Ok(config.body),
+            Err(FormatWrapperError::IncompatibleVersion(version, Some(config))) => {
+                warn!(
+                    "Incompatible format version '{}', but continuing anyway",
+                    version
+                );
+                Ok(config.body)
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 impl Backend {
@@ -345,6 +964,39 @@ impl Backend {
         self.groups.as_ref().map(|v| !v.is_empty()).unwrap_or(false)
     }
 
+    /// Indexes of hash chains with explicit per-chain configuration
+    pub fn configured_hash_chains(&self) -> Vec<usize> {
+        self.hash_chains
+            .as_ref()
+            .map(|m| m.keys().filter_map(|idx| idx.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Power supply measurements are read over PMBus
+    pub fn psu_pmbus(&self) -> bool {
+        self.psu
+            .as_ref()
+            .and_then(|v| v.pmbus)
+            .unwrap_or(DEFAULT_PSU_PMBUS)
+    }
+
+    /// API commands controlling the hardware are enabled
+    pub fn api_hardware_control(&self) -> bool {
+        self.api
+            .as_ref()
+            .and_then(|v| v.hardware_control)
+            .unwrap_or(DEFAULT_API_HARDWARE_CONTROL)
+    }
+
+    /// Optional Stratum V1->V2 translation proxy running alongside the miner
+    pub fn translation_proxy(&self) -> Option<translation_proxy::Config> {
+        self.translation_proxy
+            .as_ref()
+            .filter(|proxy| proxy.enabled.unwrap_or(true))
+            // configuration has been already checked by `sanity_check`
+            .and_then(|proxy| proxy.proxy_config().ok())
+    }
+
     pub fn has_pools(&self) -> bool {
         match &self.groups {
             Some(groups) => groups
@@ -354,7 +1006,39 @@ impl Backend {
         }
     }
 
+    /// Names of all configured performance profiles
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles
+            .as_ref()
+            .map(|m| m.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Name of performance profile which is active after start
+    pub fn active_profile(&self) -> Option<&str> {
+        self.hash_chain_global
+            .as_ref()
+            .and_then(|v| v.profile.as_deref())
+    }
+
     pub fn resolve_chain_config(&self, hash_chain_idx: usize) -> ResolvedChainConfig {
+        self.resolve_chain_config_for_profile(hash_chain_idx, self.active_profile())
+    }
+
+    /// Resolve hash chain configuration with performance profile `profile_name` applied.
+    /// Unknown profile is ignored.
+    pub fn resolve_chain_config_for_profile(
+        &self,
+        hash_chain_idx: usize,
+        profile_name: Option<&str>,
+    ) -> ResolvedChainConfig {
+        let profile = profile_name.and_then(|name| {
+            self.profiles
+                .as_ref()
+                .and_then(|m| m.get(name))
+                .map(|profile| (name, profile))
+        });
+
         // Take global hash chain configuration or default value
         let overridable = self
             .hash_chain_global
@@ -368,8 +1052,44 @@ impl Backend {
             overridable.as_ref().and_then(|v| v.voltage),
             DEFAULT_VOLTAGE_V,
         );
+        let mut autotuning = self.autotuning.clone();
+
+        // Performance profile replaces the global settings
+        if let Some((_, profile)) = profile {
+            frequency = profile
+                .frequency
+                .map(|v| OptionDefault::Some(v))
+                .unwrap_or(frequency);
+            voltage = profile
+                .voltage
+                .map(|v| OptionDefault::Some(v))
+                .unwrap_or(voltage);
+            if let Some(autotuning) = autotuning.as_mut() {
+                autotuning.power_target = profile.power_target.or(autotuning.power_target);
+            }
+        }
         let mut enabled = DEFAULT_HASH_CHAIN_ENABLED;
 
+        // Thermal throttle is optional and it is not available without temperature control
+        let thermal_throttle = self.temp_control.as_ref().and_then(|v| match v.mode {
+            Some(TempControlMode::Disabled) => None,
+            _ => v
+                .throttle_temp
+                .map(|throttle_temp| monitor::ThermalThrottleConfig {
+                    throttle_temp: throttle_temp as f32,
+                }),
+        });
+        // Thermal cutoff has the same constraints
+        let thermal_cutoff = self.temp_control.as_ref().and_then(|v| match v.mode {
+            Some(TempControlMode::Disabled) => None,
+            _ => v
+                .cutoff_temp
+                .map(|cutoff_temp| monitor::ThermalCutoffConfig {
+                    cutoff_temp: cutoff_temp as f32,
+                    cooldown: v.cutoff_cooldown(),
+                }),
+        });
+
         // If there's a per-chain override then apply it
         if let Some(hash_chain) = self
             .hash_chains
@@ -389,12 +1109,20 @@ impl Backend {
 
         // Computed s9-specific values
         ResolvedChainConfig {
+            profile: profile.map(|(name, _)| name.to_string()),
             midstate_count: MidstateCount::new(self.midstate_count()),
             frequency: FrequencySettings::from_frequency((*frequency * 1_000_000.0) as usize),
             // TODO: handle config errors
             voltage: power::Voltage::from_volts(*voltage as f32)
                 .expect("TODO: bad voltage requested"),
             enabled,
+            thermal_throttle,
+            thermal_cutoff,
+            watchdog_timeout: match &self.hash_chain_global {
+                Some(hash_chain_global) => hash_chain_global.watchdog_timeout(),
+                None => Some(Duration::from_secs(DEFAULT_WATCHDOG_TIMEOUT_S)),
+            },
+            tuner: autotuning.as_ref().and_then(|v| v.resolve()),
         }
     }
 
@@ -422,6 +1150,10 @@ impl Backend {
             self.fan_control.as_ref().and_then(|v| v.speed),
             DEFAULT_FAN_SPEED,
         );
+        let fan_min_speed = OptionDefault::new(
+            self.fan_control.as_ref().and_then(|v| v.min_speed),
+            DEFAULT_FAN_MIN_SPEED,
+        );
         let min_fans = OptionDefault::new(
             self.fan_control.as_ref().and_then(|v| v.min_fans),
             DEFAULT_MIN_FANS,
@@ -461,6 +1193,7 @@ impl Backend {
             TempControlMode::Auto => {
                 fan_config = Some(monitor::FanControlConfig {
                     mode: monitor::FanControlMode::TargetTemperature(*target_temp as f32),
+                    min_speed: fan::Speed::new(*fan_min_speed),
                     min_fans: *min_fans,
                 });
                 // do sanity checks
@@ -478,9 +1211,16 @@ impl Backend {
                 } else {
                     Some(monitor::FanControlConfig {
                         mode: monitor::FanControlMode::FixedSpeed(fan::Speed::new(*fan_speed)),
+                        min_speed: fan::Speed::STOPPED,
                         min_fans: *min_fans,
                     })
                 };
+                if fan_min_speed.is_some() {
+                    warn!(
+                        "Unused fan 'min_speed' ({}) because 'auto' mode is not set",
+                        *fan_min_speed
+                    );
+                }
                 // do sanity checks
                 if target_temp.is_some() {
                     warn!(
@@ -542,6 +1282,53 @@ impl ConfigBody for Backend {
             }
         }
 
+        if let Some(hash_chain_global) = &self.hash_chain_global {
+            hash_chain_global.sanity_check()?;
+        }
+
+        if let Some(profiles) = &self.profiles {
+            for (name, profile) in profiles.iter() {
+                profile.sanity_check(name)?;
+            }
+        }
+        if let Some(profile) = self.active_profile() {
+            if !self.profile_names().iter().any(|name| name == profile) {
+                Err(format!("active profile '{}' is not configured", profile))?;
+            }
+        }
+
+        if let Some(temp_control) = &self.temp_control {
+            temp_control.sanity_check()?;
+        }
+
+        if let Some(fan_control) = &self.fan_control {
+            fan_control.sanity_check()?;
+        }
+
+        if let Some(autotuning) = &self.autotuning {
+            autotuning.sanity_check()?;
+        }
+
+        if let Some(difficulty_ramp) = &self.difficulty_ramp {
+            difficulty_ramp.sanity_check()?;
+        }
+
+        if let Some(api) = &self.api {
+            api.sanity_check()?;
+        }
+
+        if let Some(audit) = &self.audit {
+            audit.sanity_check()?;
+        }
+
+        if let Some(logging) = &self.logging {
+            logging.sanity_check()?;
+        }
+
+        if let Some(translation_proxy) = &self.translation_proxy {
+            translation_proxy.sanity_check()?;
+        }
+
         // Analyze group configuration, make sure the groups are unique, and build descriptor
         // topology out of the configuration data
         // Don't worry if is this section missing, maybe there are some pools on command line
@@ -597,7 +1384,567 @@ impl hal::BackendConfig for Backend {
         self.client_manager.replace(client_manager);
     }
 
+    fn difficulty_ramp(&self) -> Option<job::DifficultyRamp> {
+        self.difficulty_ramp
+            .as_ref()
+            .and_then(|v| v.difficulty_ramp())
+    }
+
+    fn header_export(&self) -> Option<job::HeaderExportConfig> {
+        self.audit.as_ref().and_then(|v| {
+            v.header_export
+                .as_ref()
+                .map(|path| job::HeaderExportConfig {
+                    path: path.into(),
+                    blocks_only: v
+                        .header_export_blocks_only
+                        .unwrap_or(DEFAULT_AUDIT_HEADER_EXPORT_BLOCKS_ONLY),
+                })
+        })
+    }
+
+    fn block_archive(&self) -> Option<stats::BlockArchiveConfig> {
+        self.audit
+            .as_ref()
+            .and_then(|v| v.block_archive.as_ref())
+            .map(|path| stats::BlockArchiveConfig { path: path.into() })
+    }
+
+    fn metrics_listen_addr(&self) -> Option<SocketAddr> {
+        self.api
+            .as_ref()
+            .and_then(|v| v.metrics_listen.as_ref())
+            // configuration has been already checked by `sanity_check`
+            .and_then(|listen| Api::parse_metrics_listen(listen).ok())
+    }
+
     fn info(&self) -> Option<hal::BackendInfo> {
         Some(self.info.clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_parse_truncated_config() {
+        let config_path =
+            env::temp_dir().join(format!("bosminer-truncated-{}.toml", process::id()));
+        // Simulate power loss in the middle of writing the configuration file
+        fs::write(
+            &config_path,
+            "[format]\nversion = \"1.0\"\nmodel = \"Antminer S9\"\n\n[[group]]\nname = \"Def",
+        )
+        .expect("BUG: cannot write config file");
+
+        let config_path_str = config_path.to_str().expect("BUG: invalid path");
+        assert!(match FormatWrapper::<Backend>::parse(config_path_str) {
+            Err(FormatWrapperError::ParsingError(_)) => true,
+            _ => false,
+        });
+        assert!(
+            match FormatWrapper::<Backend>::parse_body(config_path_str) {
+                Err(FormatWrapperError::ParsingError(_)) => true,
+                _ => false,
+            }
+        );
+        // the corrupted file is left untouched for the user
+        assert!(fs::read_to_string(&config_path)
+            .expect("BUG: cannot read config file")
+            .ends_with("name = \"Def"));
+
+        fs::remove_file(&config_path).expect("BUG: cannot remove config file");
+
+        // Missing configuration file is still an error
+        assert!(FormatWrapper::<Backend>::parse_body(config_path_str).is_err());
+    }
+
+    #[test]
+    fn test_configured_hash_chains() {
+        assert!(Backend::default().configured_hash_chains().is_empty());
+
+        let mut hash_chains = BTreeMap::new();
+        hash_chains.insert("6".to_string(), HashChain::default());
+        hash_chains.insert("8".to_string(), HashChain::default());
+        let backend = Backend {
+            hash_chains: Some(hash_chains),
+            ..Default::default()
+        };
+        assert_eq!(backend.configured_hash_chains(), vec![6, 8]);
+    }
+
+    #[test]
+    fn test_profile_config() {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(
+            "eco".to_string(),
+            Profile {
+                frequency: Some(550.0),
+                voltage: Some(8.4),
+                power_target: Some(1000.0),
+            },
+        );
+        profiles.insert(
+            "turbo".to_string(),
+            Profile {
+                frequency: Some(750.0),
+                ..Default::default()
+            },
+        );
+        let mut hash_chains = BTreeMap::new();
+        hash_chains.insert(
+            "8".to_string(),
+            HashChain {
+                frequency: Some(600.0),
+                ..Default::default()
+            },
+        );
+        let mut backend = Backend {
+            hash_chain_global: Some(HashChainGlobal {
+                profile: Some("eco".to_string()),
+                ..Default::default()
+            }),
+            hash_chains: Some(hash_chains),
+            profiles: Some(profiles),
+            autotuning: Some(Autotuning {
+                enabled: Some(true),
+                power_target: Some(1500.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(backend.sanity_check().is_ok());
+        assert_eq!(backend.profile_names(), vec!["eco", "turbo"]);
+        assert_eq!(backend.active_profile(), Some("eco"));
+
+        let config = backend.resolve_chain_config(6);
+        assert_eq!(config.profile, Some("eco".to_string()));
+        assert_eq!(config.frequency.avg(), 550_000_000);
+        assert!(config.voltage == power::Voltage::from_volts(8.4).unwrap());
+        assert_eq!(
+            config
+                .tuner
+                .and_then(|config| config.power_target)
+                .map(|power_target| power_target.power),
+            Some(1000.0)
+        );
+        // per-chain override takes precedence over the profile
+        assert_eq!(backend.resolve_chain_config(8).frequency.avg(), 600_000_000);
+
+        // profile without power target keeps the one from autotuning
+        let config = backend.resolve_chain_config_for_profile(6, Some("turbo"));
+        assert_eq!(config.profile, Some("turbo".to_string()));
+        assert_eq!(config.frequency.avg(), 750_000_000);
+        assert!(config.voltage == power::Voltage::from_volts(DEFAULT_VOLTAGE_V as f32).unwrap());
+        assert_eq!(
+            config
+                .tuner
+                .and_then(|config| config.power_target)
+                .map(|power_target| power_target.power),
+            Some(1500.0)
+        );
+
+        backend
+            .hash_chain_global
+            .as_mut()
+            .expect("BUG: missing global settings")
+            .profile = Some("balanced".to_string());
+        assert!(backend.sanity_check().is_err());
+        assert!(Profile {
+            frequency: Some(1000.0),
+            ..Default::default()
+        }
+        .sanity_check("overclock")
+        .is_err());
+    }
+
+    #[test]
+    fn test_api_config() {
+        // The metrics are not published unless the address is configured
+        let mut backend = Backend::default();
+        assert!(backend.metrics_listen_addr().is_none());
+
+        backend.api = Some(Api {
+            metrics_listen: Some("127.0.0.1:8081".to_string()),
+            ..Default::default()
+        });
+        assert!(backend.sanity_check().is_ok());
+        assert_eq!(
+            backend.metrics_listen_addr(),
+            Some("127.0.0.1:8081".parse().expect("BUG: invalid address"))
+        );
+
+        for listen in &["", "127.0.0.1", ":8081", "localhost:8081"] {
+            let api = Api {
+                metrics_listen: Some(listen.to_string()),
+                ..Default::default()
+            };
+            assert!(api.sanity_check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_translation_proxy_config() {
+        let mut backend = Backend {
+            translation_proxy: Some(TranslationProxy {
+                listen: "0.0.0.0:3333".to_string(),
+                upstream: "stratum2+tcp+insecure://v2.pool.example.com:3336".to_string(),
+                user: "user.worker".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(backend.sanity_check().is_ok());
+        let config = backend
+            .translation_proxy()
+            .expect("BUG: missing translation proxy");
+        assert_eq!(config.listen_addr.to_string(), "0.0.0.0:3333");
+        assert_eq!(
+            config.upstream.get_url(true, true, true),
+            "stratum2+tcp+insecure://user.worker@v2.pool.example.com:3336"
+        );
+
+        let translation_proxy = backend.translation_proxy.as_mut().expect("BUG: no proxy");
+        translation_proxy.enabled = Some(false);
+        assert!(backend.translation_proxy().is_none());
+
+        for translation_proxy in &[
+            // The proxy never listens unless the address is configured
+            TranslationProxy {
+                upstream: "stratum2+tcp+insecure://v2.pool.example.com".to_string(),
+                user: "user.worker".to_string(),
+                ..Default::default()
+            },
+            TranslationProxy {
+                listen: "0.0.0.0:3333".to_string(),
+                upstream: "stratum+tcp://pool.example.com:3333".to_string(),
+                user: "user.worker".to_string(),
+                ..Default::default()
+            },
+        ] {
+            assert!(translation_proxy.sanity_check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_autotuning_config() {
+        let autotuning = Autotuning {
+            min_frequency: Some(600.0),
+            max_frequency: Some(700.0),
+            ..Default::default()
+        };
+        assert!(autotuning.sanity_check().is_ok());
+        // autotuning is disabled by default
+        assert!(autotuning.resolve().is_none());
+
+        let autotuning = Autotuning {
+            enabled: Some(true),
+            ..autotuning
+        };
+        assert_eq!(
+            autotuning.resolve(),
+            Some(tuner::Config {
+                frequency_step: 5_000_000,
+                min_frequency: 600_000_000,
+                max_frequency: 700_000_000,
+                max_error_ratio: 0.02,
+                min_return_ratio: 0.8,
+                period: DEFAULT_AUTOTUNING_PERIOD,
+                power_target: None,
+            })
+        );
+        let autotuning = Autotuning {
+            power_target: Some(1200.0),
+            ..autotuning
+        };
+        assert_eq!(
+            autotuning
+                .resolve()
+                .and_then(|config| config.power_target)
+                .map(|power_target| power_target.power),
+            Some(1200.0)
+        );
+
+        for autotuning in &[
+            Autotuning {
+                min_frequency: Some(800.0),
+                max_frequency: Some(700.0),
+                ..Default::default()
+            },
+            Autotuning {
+                frequency_step: Some(0.0),
+                ..Default::default()
+            },
+            Autotuning {
+                max_hw_errors: Some(101.0),
+                ..Default::default()
+            },
+            Autotuning {
+                power_target: Some(10.0),
+                ..Default::default()
+            },
+        ] {
+            assert!(autotuning.sanity_check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_watchdog_config() {
+        let hash_chain_global = |watchdog_timeout| HashChainGlobal {
+            watchdog_timeout,
+            ..Default::default()
+        };
+        assert_eq!(
+            hash_chain_global(None).watchdog_timeout(),
+            Some(Duration::from_secs(DEFAULT_WATCHDOG_TIMEOUT_S))
+        );
+        assert_eq!(hash_chain_global(Some(0)).watchdog_timeout(), None);
+        assert_eq!(
+            hash_chain_global(Some(60)).watchdog_timeout(),
+            Some(Duration::from_secs(60))
+        );
+
+        assert!(hash_chain_global(Some(0)).sanity_check().is_ok());
+        assert!(hash_chain_global(Some(WATCHDOG_TIMEOUT_S_MIN))
+            .sanity_check()
+            .is_ok());
+        assert!(hash_chain_global(Some(WATCHDOG_TIMEOUT_S_MIN - 1))
+            .sanity_check()
+            .is_err());
+        assert!(hash_chain_global(Some(WATCHDOG_TIMEOUT_S_MAX + 1))
+            .sanity_check()
+            .is_err());
+    }
+
+    #[test]
+    fn test_difficulty_ramp_config() {
+        let mut backend = Backend {
+            difficulty_ramp: Some(DifficultyRamp {
+                start_difficulty: Some(16),
+                period: Some(60),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // the ramp is disabled unless it is explicitly enabled
+        assert_eq!(backend.difficulty_ramp(), None);
+
+        backend
+            .difficulty_ramp
+            .as_mut()
+            .expect("BUG: missing difficulty ramp")
+            .enabled = Some(true);
+        assert!(backend.sanity_check().is_ok());
+        assert_eq!(
+            backend.difficulty_ramp(),
+            Some(job::DifficultyRamp::new(16, Duration::from_secs(60)))
+        );
+
+        for (start_difficulty, period) in &[
+            (DIFFICULTY_RAMP_START_DIFFICULTY_MIN - 1, 60),
+            (DIFFICULTY_RAMP_START_DIFFICULTY_MAX + 1, 60),
+            (16, DIFFICULTY_RAMP_PERIOD_S_MIN - 1),
+            (16, DIFFICULTY_RAMP_PERIOD_S_MAX + 1),
+        ] {
+            let difficulty_ramp = DifficultyRamp {
+                enabled: Some(true),
+                start_difficulty: Some(*start_difficulty),
+                period: Some(*period),
+            };
+            assert!(difficulty_ramp.sanity_check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_audit_config() {
+        let backend = Backend::default();
+        assert_eq!(backend.header_export(), None);
+        assert_eq!(backend.block_archive(), None);
+
+        let backend = Backend {
+            audit: Some(Audit {
+                header_export: Some("/tmp/headers.log".to_string()),
+                block_archive: Some("/tmp/blocks.log".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(backend.sanity_check().is_ok());
+        assert_eq!(
+            backend.header_export(),
+            Some(job::HeaderExportConfig {
+                path: "/tmp/headers.log".into(),
+                blocks_only: DEFAULT_AUDIT_HEADER_EXPORT_BLOCKS_ONLY,
+            })
+        );
+        assert_eq!(
+            backend.block_archive(),
+            Some(stats::BlockArchiveConfig {
+                path: "/tmp/blocks.log".into(),
+            })
+        );
+
+        let audit = Audit {
+            block_archive: Some("".to_string()),
+            ..Default::default()
+        };
+        assert!(audit.sanity_check().is_err());
+    }
+
+    #[test]
+    fn test_thermal_cutoff_cooldown_config() {
+        let temp_control = |cutoff_cooldown| TempControl {
+            cutoff_cooldown,
+            ..Default::default()
+        };
+        assert_eq!(
+            temp_control(None).cutoff_cooldown(),
+            Duration::from_secs(DEFAULT_THERMAL_CUTOFF_COOLDOWN_S)
+        );
+        assert_eq!(
+            temp_control(Some(60)).cutoff_cooldown(),
+            Duration::from_secs(60)
+        );
+
+        assert!(temp_control(None).sanity_check().is_ok());
+        assert!(temp_control(Some(THERMAL_CUTOFF_COOLDOWN_S_MIN))
+            .sanity_check()
+            .is_ok());
+        assert!(temp_control(Some(THERMAL_CUTOFF_COOLDOWN_S_MIN - 1))
+            .sanity_check()
+            .is_err());
+        assert!(temp_control(Some(THERMAL_CUTOFF_COOLDOWN_S_MAX + 1))
+            .sanity_check()
+            .is_err());
+    }
+
+    #[test]
+    fn test_fan_control_config() {
+        let fan_control = FanControl {
+            speed: Some(70),
+            min_speed: Some(20),
+            ..Default::default()
+        };
+        assert!(fan_control.sanity_check().is_ok());
+        for fan_control in &[
+            FanControl {
+                speed: Some(101),
+                ..Default::default()
+            },
+            FanControl {
+                min_speed: Some(101),
+                ..Default::default()
+            },
+        ] {
+            assert!(fan_control.sanity_check().is_err());
+        }
+    }
+
+    #[test]
+    fn test_logging_override() {
+        let config_path = env::temp_dir().join(format!("bosminer-logging-{}.toml", process::id()));
+        fs::write(
+            &config_path,
+            "[format]\nversion = \"1.0\"\nmodel = \"Antminer S9\"\n\n[logging]\nlevel = \"warning\"\n",
+        )
+        .expect("BUG: cannot write config file");
+
+        let config_path_str = config_path.to_str().expect("BUG: invalid path");
+        let logging = Logging::parse(config_path_str);
+        assert_eq!(logging.level.as_deref(), Some("warning"));
+        assert_eq!(
+            logging
+                .logging_config(None)
+                .expect("BUG: invalid level")
+                .level,
+            ii_logging::Level::Warning
+        );
+        // Command line takes precedence over configuration file
+        assert_eq!(
+            logging
+                .logging_config(Some("trace"))
+                .expect("BUG: invalid level")
+                .level,
+            ii_logging::Level::Trace
+        );
+        assert!(logging.logging_config(Some("verbose")).is_err());
+        assert!(logging.logging_config(Some("")).is_err());
+        assert_eq!(
+            logging
+                .logging_config(Some("off"))
+                .expect("BUG: invalid level")
+                .level,
+            ii_logging::Level::Critical
+        );
+
+        fs::remove_file(&config_path).expect("BUG: cannot remove config file");
+
+        // Missing configuration file doesn't prevent the logger from being set up
+        assert!(Logging::parse(config_path_str).level.is_none());
+    }
+
+    #[test]
+    fn test_logging_format() {
+        let logging = Logging {
+            filter: Some("bosminer::client=debug".to_string()),
+            format: Some("json".to_string()),
+            ..Default::default()
+        };
+        assert!(logging.sanity_check().is_ok());
+        let config = logging.logging_config(None).expect("BUG: invalid format");
+        assert_eq!(config.format, ii_logging::LoggingFormat::Json);
+        assert_eq!(config.filter.as_deref(), Some("bosminer::client=debug"));
+
+        let logging = Logging {
+            format: Some("xml".to_string()),
+            ..Default::default()
+        };
+        assert!(logging.sanity_check().is_err());
+        assert!(logging.logging_config(None).is_err());
+    }
+
+    #[test]
+    fn test_logging_servers() {
+        let logging = Logging {
+            syslog_server: Some("10.0.0.1:514".to_string()),
+            syslog_bind: Some("10.0.0.2".to_string()),
+            json_server: Some("logs.example.com:5170".to_string()),
+            ..Default::default()
+        };
+        assert!(logging.sanity_check().is_ok());
+        assert_eq!(
+            logging
+                .logging_config(None)
+                .expect("BUG: invalid server")
+                .sinks,
+            vec![
+                ii_logging::LoggingSink::Syslog {
+                    server: "10.0.0.1:514".to_string(),
+                    bind: Some("10.0.0.2".parse().expect("BUG: invalid address")),
+                },
+                ii_logging::LoggingSink::Tcp("logs.example.com:5170".to_string()),
+            ]
+        );
+
+        for server in &["10.0.0.1", ":514", "10.0.0.1:syslog", "10.0.0.1:65536"] {
+            let logging = Logging {
+                syslog_server: Some(server.to_string()),
+                ..Default::default()
+            };
+            assert!(logging.sanity_check().is_err());
+            assert!(logging.logging_config(None).is_err());
+        }
+
+        let logging = Logging {
+            syslog_server: Some("10.0.0.1:514".to_string()),
+            syslog_bind: Some("10.0.0.2:514".to_string()),
+            ..Default::default()
+        };
+        assert!(logging.sanity_check().is_err());
+        assert!(logging.logging_config(None).is_err());
+    }
+}
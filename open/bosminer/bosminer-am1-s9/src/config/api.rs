@@ -251,6 +251,8 @@ impl<'a> Handler<'a> {
         let config_path = Path::new(self.config_path);
         let config_tmp_path = config_path.with_extension(Self::CONFIG_TMP_EXTENSION);
 
+        // Remove stale temporary file possibly left behind by interrupted save
+        let _ = fs::remove_file(&config_tmp_path);
         let mut file = FileGuard::create(&config_tmp_path).expect("TODO: File::create");
 
         file.write_all(
@@ -259,6 +261,8 @@ impl<'a> Handler<'a> {
                 .as_bytes(),
         )
         .expect("TODO: file.write_all");
+        // Make sure the content is on the disk before the original config file is replaced
+        file.sync_all().expect("TODO: file.sync_all");
 
         file.persist(config_path).expect("TODO: file.persist");
 
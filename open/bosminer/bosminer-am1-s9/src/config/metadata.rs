@@ -24,7 +24,11 @@
 
 use super::*;
 
-use bosminer_config::CLIENT_URL_JAVA_SCRIPT_REGEX;
+use bosminer::work;
+use bosminer_config::{
+    ClientNtimePolicy, ClientParseErrorPolicy, ClientReconnectPolicy, ClientRejectAction,
+    ClientRejectReason, CLIENT_URL_JAVA_SCRIPT_REGEX,
+};
 
 const DESCRIPTION_CAUTION_OVERCLOCKING: &'static str =
     "Caution: Overclocking may damage your device. Proceed at your own risk!";
@@ -33,6 +37,49 @@ const DESCRIPTION_CAUTION_CHANGING_DEFAULT: &'static str =
      shutdown of the system or even irreversible hardware damage. Proceed at your own risk!";
 const DESCRIPTION_NUMBER_OF_FANS: &'static str =
     "Number of fans required for system to run. For immersion cooling, use the value '0'.";
+const DESCRIPTION_WATCHDOG_TIMEOUT: &'static str =
+    "Hash chain which returns no nonces for this time is restarted. Use the value '0' to disable \
+     the watchdog.";
+const DESCRIPTION_CUTOFF_COOLDOWN: &'static str =
+    "Hash chain which reaches the cutoff temperature is stopped for this time and then started \
+     again with the configured frequency and voltage.";
+const DESCRIPTION_PROFILE: &'static str =
+    "Name of performance profile which replaces the global frequency, voltage and power target. \
+     Profiles can be switched at runtime without restart.";
+const DESCRIPTION_PSU_PMBUS: &'static str =
+    "Read input power of power supply with PMBus interface (e.g. APW series) and use it for \
+     power target instead of estimated consumption.";
+const DESCRIPTION_DIFFICULTY_RAMP: &'static str =
+    "Newly connected pool is asked for low share difficulty first which is raised back to the \
+     pool difficulty over the period so that the pool can settle its variable difficulty.";
+const DESCRIPTION_API_HARDWARE_CONTROL: &'static str =
+    "Allow API commands which start and stop hashboards or write chip registers.";
+const DESCRIPTION_API_METRICS_LISTEN: &'static str =
+    "Publish Prometheus metrics on this address, use the format 'host:port'. The metrics are not \
+     published when it is empty.";
+const DESCRIPTION_AUDIT_HEADER_EXPORT: &'static str =
+    "Append block headers of found shares to this file (one hexadecimal header per line) for \
+     external verification.";
+const DESCRIPTION_AUDIT_BLOCK_ARCHIVE: &'static str =
+    "Append details of every found block to this file.";
+const DESCRIPTION_LOGGING_FILTER: &'static str =
+    "Comma separated levels of particular modules overriding the default level \
+     (e.g. 'bosminer::client=debug,bosminer_am1_s9::tuner=trace').";
+const DESCRIPTION_LOGGING_SYSLOG_SERVER: &'static str =
+    "Send logs also to this syslog server over UDP, use the format 'host:port'.";
+const DESCRIPTION_LOGGING_SYSLOG_BIND: &'static str =
+    "Local IP address the syslog messages are sent from, any address is used by default.";
+const DESCRIPTION_LOGGING_JSON_SERVER: &'static str =
+    "Send logs also to this server as JSON lines over TCP, use the format 'host:port'.";
+
+const DESCRIPTION_TRANSLATION_PROXY: &'static str =
+    "Accept Stratum V1 connections from legacy miners and translate them to a Stratum V2 pool.";
+
+const DESCRIPTION_TRANSLATION_PROXY_LISTEN: &'static str =
+    "Address the proxy listens on for Stratum V1 miners, use the format 'host:port'.";
+
+const DESCRIPTION_TRANSLATION_PROXY_UPSTREAM: &'static str =
+    "Stratum V2 pool the translated connections are forwarded to.";
 
 use serde_json::{self, json};
 
@@ -124,6 +171,16 @@ pub fn for_backend() -> serde_json::Value {
                                 "span": 3
                             }
                         ],
+                        [
+                            "max_probing_pools",
+                            {
+                                "type": "number",
+                                "label": "Max. Concurrent Pool Connections",
+                                "min": 1,
+                                "optional": true,
+                                "default": null
+                            }
+                        ],
                         [
                             "pool",
                             {
@@ -171,6 +228,261 @@ pub fn for_backend() -> serde_json::Value {
                                                 "default": null,
                                                 "span": 5
                                             }
+                                        ],
+                                        [
+                                            "parse_error_policy",
+                                            {
+                                                "type": "enum",
+                                                "label": "On Invalid Message",
+                                                "values": [
+                                                    {
+                                                        "key": ClientParseErrorPolicy::Disconnect.to_string(),
+                                                        "label": "Reconnect"
+                                                    },
+                                                    {
+                                                        "key": ClientParseErrorPolicy::Skip.to_string(),
+                                                        "label": "Skip"
+                                                    }
+                                                ],
+                                                "default": ClientParseErrorPolicy::Disconnect.to_string()
+                                            }
+                                        ],
+                                        [
+                                            "alternate_users",
+                                            {
+                                                "type": "array",
+                                                "label": "Alternate Usernames",
+                                                "add_label": "Add Alternate Username",
+                                                "sortable": true,
+                                                "optional": true,
+                                                "item": {
+                                                    "type": "string",
+                                                    "min_length": 1
+                                                }
+                                            }
+                                        ],
+                                        [
+                                            "submit_jitter_ms",
+                                            {
+                                                "type": "number",
+                                                "label": "Submit Jitter",
+                                                "unit": "ms",
+                                                "min": 0,
+                                                "default": 0
+                                            }
+                                        ],
+                                        [
+                                            "compression",
+                                            {
+                                                "type": "bool",
+                                                "label": "Compression",
+                                                "default": false
+                                            }
+                                        ],
+                                        [
+                                            "suggested_difficulty",
+                                            {
+                                                "type": "number",
+                                                "label": "Suggested Difficulty",
+                                                "min": 1,
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "tls_verify",
+                                            {
+                                                "type": "bool",
+                                                "label": "Verify TLS Certificate",
+                                                "default": true
+                                            }
+                                        ],
+                                        [
+                                            "tls_ca_file",
+                                            {
+                                                "type": "string",
+                                                "label": "TLS CA Certificates File",
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "tls_server_name",
+                                            {
+                                                "type": "string",
+                                                "label": "TLS Server Name",
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "proxy",
+                                            {
+                                                "type": "string",
+                                                "label": "SOCKS5 Proxy",
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "record_session",
+                                            {
+                                                "type": "string",
+                                                "label": "Session Record File",
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "ntime_tolerance_s",
+                                            {
+                                                "type": "number",
+                                                "label": "Job Time Tolerance",
+                                                "unit": "s",
+                                                "min": 0,
+                                                "optional": true,
+                                                "default": null
+                                            }
+                                        ],
+                                        [
+                                            "ntime_policy",
+                                            {
+                                                "type": "enum",
+                                                "label": "On Job Time Out Of Tolerance",
+                                                "values": [
+                                                    {
+                                                        "key": ClientNtimePolicy::Accept.to_string(),
+                                                        "label": "Accept"
+                                                    },
+                                                    {
+                                                        "key": ClientNtimePolicy::Clamp.to_string(),
+                                                        "label": "Clamp"
+                                                    },
+                                                    {
+                                                        "key": ClientNtimePolicy::Reject.to_string(),
+                                                        "label": "Reject"
+                                                    }
+                                                ],
+                                                "default": ClientNtimePolicy::Accept.to_string()
+                                            }
+                                        ],
+                                        [
+                                            "ntime_rolling_s",
+                                            {
+                                                "type": "number",
+                                                "label": "Job Time Rolling",
+                                                "unit": "s",
+                                                "min": 0,
+                                                "max": work::engine::MAX_NTIME_ROLLING_LIMIT,
+                                                "default": work::engine::DEFAULT_NTIME_ROLLING_LIMIT
+                                            }
+                                        ],
+                                        [
+                                            "reject_alert",
+                                            {
+                                                "type": "array",
+                                                "label": "Reject Alerts",
+                                                "add_label": "Add Reject Alert",
+                                                "optional": true,
+                                                "item": {
+                                                    "type": "object",
+                                                    "fields": [
+                                                        [
+                                                            "reason",
+                                                            {
+                                                                "type": "enum",
+                                                                "label": "Reject Reason",
+                                                                "values": [
+                                                                    {
+                                                                        "key": ClientRejectReason::LowDifficulty.to_string(),
+                                                                        "label": "Low Difficulty"
+                                                                    },
+                                                                    {
+                                                                        "key": ClientRejectReason::Stale.to_string(),
+                                                                        "label": "Stale"
+                                                                    },
+                                                                    {
+                                                                        "key": ClientRejectReason::Duplicate.to_string(),
+                                                                        "label": "Duplicate"
+                                                                    },
+                                                                    {
+                                                                        "key": ClientRejectReason::JobNotFound.to_string(),
+                                                                        "label": "Job Not Found"
+                                                                    },
+                                                                    {
+                                                                        "key": ClientRejectReason::Other.to_string(),
+                                                                        "label": "Other"
+                                                                    }
+                                                                ]
+                                                            }
+                                                        ],
+                                                        [
+                                                            "max_ratio",
+                                                            {
+                                                                "type": "number",
+                                                                "label": "Maximal Ratio",
+                                                                "min": 0.0,
+                                                                "max": 1.0,
+                                                                "step": 0.01,
+                                                                "float": true
+                                                            }
+                                                        ],
+                                                        [
+                                                            "action",
+                                                            {
+                                                                "type": "enum",
+                                                                "label": "Action",
+                                                                "values": [
+                                                                    {
+                                                                        "key": ClientRejectAction::Warn.to_string(),
+                                                                        "label": "Warn"
+                                                                    },
+                                                                    {
+                                                                        "key": ClientRejectAction::Switch.to_string(),
+                                                                        "label": "Switch Pool"
+                                                                    }
+                                                                ],
+                                                                "default": ClientRejectAction::Warn.to_string()
+                                                            }
+                                                        ]
+                                                    ]
+                                                }
+                                            }
+                                        ],
+                                        [
+                                            "reconnect_policy",
+                                            {
+                                                "type": "enum",
+                                                "label": "Follow Pool Reconnect Requests",
+                                                "values": [
+                                                    {
+                                                        "key": ClientReconnectPolicy::Deny.to_string(),
+                                                        "label": "Never"
+                                                    },
+                                                    {
+                                                        "key": ClientReconnectPolicy::SameDomain.to_string(),
+                                                        "label": "Same Domain"
+                                                    },
+                                                    {
+                                                        "key": ClientReconnectPolicy::Allowlist.to_string(),
+                                                        "label": "Allowed Hosts"
+                                                    }
+                                                ],
+                                                "default": ClientReconnectPolicy::Deny.to_string()
+                                            }
+                                        ],
+                                        [
+                                            "reconnect_allowlist",
+                                            {
+                                                "type": "array",
+                                                "label": "Allowed Reconnect Hosts",
+                                                "add_label": "Add Allowed Host",
+                                                "optional": true,
+                                                "item": {
+                                                    "type": "string",
+                                                    "min_length": 1
+                                                }
+                                            }
                                         ]
                                     ]
                                 }
@@ -195,6 +507,27 @@ pub fn for_backend() -> serde_json::Value {
                             "default": DEFAULT_ASIC_BOOST
                         }
                     ],
+                    [
+                        "watchdog_timeout",
+                        {
+                            "type": "number",
+                            "label": "Watchdog Timeout",
+                            "unit": "s",
+                            "min": 0,
+                            "max": WATCHDOG_TIMEOUT_S_MAX,
+                            "description": DESCRIPTION_WATCHDOG_TIMEOUT,
+                            "default": DEFAULT_WATCHDOG_TIMEOUT_S
+                        }
+                    ],
+                    [
+                        "profile",
+                        {
+                            "type": "string",
+                            "label": "Performance Profile",
+                            "description": DESCRIPTION_PROFILE,
+                            "default": null
+                        }
+                    ],
                     [
                         "frequency",
                         {
@@ -273,6 +606,61 @@ pub fn for_backend() -> serde_json::Value {
                 }
             }
         ],
+        [
+            "profile",
+            {
+                "type": "dict",
+                "label": "Performance Profiles",
+                "description": DESCRIPTION_CAUTION_OVERCLOCKING,
+                "key": {
+                    "type": "string"
+                },
+                "value": {
+                    "type": "object",
+                    "fields": [
+                        [
+                            "frequency",
+                            {
+                                "type": "number",
+                                "label": "Frequency",
+                                "unit": "MHz",
+                                "min": FREQUENCY_MHZ_MIN,
+                                "max": FREQUENCY_MHZ_MAX,
+                                "float": true,
+                                "default": ["$get", "hash_chain_global", "frequency"],
+                                "span": 4
+                            }
+                        ],
+                        [
+                            "voltage",
+                            {
+                                "type": "number",
+                                "label": "Voltage",
+                                "unit": "V",
+                                "min": VOLTAGE_V_MIN,
+                                "max": VOLTAGE_V_MAX,
+                                "float": true,
+                                "default": ["$get", "hash_chain_global", "voltage"],
+                                "span": 4
+                            }
+                        ],
+                        [
+                            "power_target",
+                            {
+                                "type": "number",
+                                "label": "Power Target",
+                                "unit": "W",
+                                "min": POWER_TARGET_W_MIN,
+                                "max": POWER_TARGET_W_MAX,
+                                "optional": true,
+                                "default": null,
+                                "span": 4
+                            }
+                        ]
+                    ]
+                }
+            }
+        ],
         [
             "temp_control",
             {
@@ -347,6 +735,48 @@ pub fn for_backend() -> serde_json::Value {
                             "disabled": ["$eq", ["$get", "temp_control", "mode"], "disabled"],
                             "span": 4
                         }
+                    ],
+                    [
+                        "throttle_temp",
+                        {
+                            "type": "number",
+                            "label": "Throttle Temperature",
+                            "unit": "°C",
+                            "min": TEMPERATURE_C_MIN,
+                            "max": TEMPERATURE_C_MAX,
+                            "step": 0.1,
+                            "float": true,
+                            "disabled": ["$eq", ["$get", "temp_control", "mode"], "disabled"],
+                            "span": 4
+                        }
+                    ],
+                    [
+                        "cutoff_temp",
+                        {
+                            "type": "number",
+                            "label": "Cutoff Temperature",
+                            "unit": "°C",
+                            "min": TEMPERATURE_C_MIN,
+                            "max": TEMPERATURE_C_MAX,
+                            "step": 0.1,
+                            "float": true,
+                            "disabled": ["$eq", ["$get", "temp_control", "mode"], "disabled"],
+                            "span": 4
+                        }
+                    ],
+                    [
+                        "cutoff_cooldown",
+                        {
+                            "type": "number",
+                            "label": "Cutoff Cooldown",
+                            "unit": "s",
+                            "min": THERMAL_CUTOFF_COOLDOWN_S_MIN,
+                            "max": THERMAL_CUTOFF_COOLDOWN_S_MAX,
+                            "description": DESCRIPTION_CUTOFF_COOLDOWN,
+                            "default": DEFAULT_THERMAL_CUTOFF_COOLDOWN_S,
+                            "disabled": ["$eq", ["$get", "temp_control", "mode"], "disabled"],
+                            "span": 4
+                        }
                     ]
                 ]
             }
@@ -370,6 +800,19 @@ pub fn for_backend() -> serde_json::Value {
                             "disabled": ["$eq", ["$get", "temp_control", "mode"], "auto"]
                         }
                     ],
+                    [
+                        "min_speed",
+                        {
+                            "type": "number",
+                            "label": "Minimum Speed",
+                            "unit": "%",
+                            "min": FAN_SPEED_MIN,
+                            "max": FAN_SPEED_MAX,
+                            "step": 1,
+                            "default": DEFAULT_FAN_MIN_SPEED,
+                            "disabled": ["$neq", ["$get", "temp_control", "mode"], "auto"]
+                        }
+                    ],
                     [
                         "min_fans",
                         {
@@ -384,6 +827,332 @@ pub fn for_backend() -> serde_json::Value {
                     ]
                 ]
             }
+        ],
+        [
+            "psu",
+            {
+                "type": "object",
+                "label": "Power Supply",
+                "fields": [
+                    [
+                        "pmbus",
+                        {
+                            "type": "bool",
+                            "label": "PMBus Metering",
+                            "description": DESCRIPTION_PSU_PMBUS,
+                            "default": DEFAULT_PSU_PMBUS
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "autotuning",
+            {
+                "type": "object",
+                "label": "Frequency Autotuning",
+                "description": DESCRIPTION_CAUTION_OVERCLOCKING,
+                "fields": [
+                    [
+                        "enabled",
+                        {
+                            "type": "bool",
+                            "label": "Enabled",
+                            "default": DEFAULT_AUTOTUNING_ENABLED
+                        }
+                    ],
+                    [
+                        "frequency_step",
+                        {
+                            "type": "number",
+                            "label": "Frequency Step",
+                            "unit": "MHz",
+                            "min": AUTOTUNING_FREQUENCY_STEP_MHZ_MIN,
+                            "max": AUTOTUNING_FREQUENCY_STEP_MHZ_MAX,
+                            "float": true,
+                            "default": DEFAULT_AUTOTUNING_FREQUENCY_STEP_MHZ
+                        }
+                    ],
+                    [
+                        "min_frequency",
+                        {
+                            "type": "number",
+                            "label": "Minimal Frequency",
+                            "unit": "MHz",
+                            "min": FREQUENCY_MHZ_MIN,
+                            "max": FREQUENCY_MHZ_MAX,
+                            "float": true,
+                            "default": FREQUENCY_MHZ_MIN
+                        }
+                    ],
+                    [
+                        "max_frequency",
+                        {
+                            "type": "number",
+                            "label": "Maximal Frequency",
+                            "unit": "MHz",
+                            "min": FREQUENCY_MHZ_MIN,
+                            "max": FREQUENCY_MHZ_MAX,
+                            "float": true,
+                            "default": FREQUENCY_MHZ_MAX
+                        }
+                    ],
+                    [
+                        "max_hw_errors",
+                        {
+                            "type": "number",
+                            "label": "Maximal Hardware Errors",
+                            "unit": "%",
+                            "min": 0,
+                            "max": 100,
+                            "float": true,
+                            "default": DEFAULT_AUTOTUNING_MAX_HW_ERRORS
+                        }
+                    ],
+                    [
+                        "min_nonces",
+                        {
+                            "type": "number",
+                            "label": "Minimal Returned Nonces",
+                            "unit": "%",
+                            "min": 0,
+                            "max": 100,
+                            "float": true,
+                            "default": DEFAULT_AUTOTUNING_MIN_NONCES
+                        }
+                    ],
+                    [
+                        "power_target",
+                        {
+                            "type": "number",
+                            "label": "Power Target",
+                            "unit": "W",
+                            "min": POWER_TARGET_W_MIN,
+                            "max": POWER_TARGET_W_MAX,
+                            "optional": true,
+                            "default": null
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "difficulty_ramp",
+            {
+                "type": "object",
+                "label": "Share Difficulty Ramp",
+                "description": DESCRIPTION_DIFFICULTY_RAMP,
+                "fields": [
+                    [
+                        "enabled",
+                        {
+                            "type": "bool",
+                            "label": "Enabled",
+                            "default": DEFAULT_DIFFICULTY_RAMP_ENABLED
+                        }
+                    ],
+                    [
+                        "start_difficulty",
+                        {
+                            "type": "number",
+                            "label": "Start Difficulty",
+                            "min": DIFFICULTY_RAMP_START_DIFFICULTY_MIN,
+                            "max": DIFFICULTY_RAMP_START_DIFFICULTY_MAX,
+                            "default": DEFAULT_DIFFICULTY_RAMP_START_DIFFICULTY
+                        }
+                    ],
+                    [
+                        "period",
+                        {
+                            "type": "number",
+                            "label": "Period",
+                            "unit": "s",
+                            "min": DIFFICULTY_RAMP_PERIOD_S_MIN,
+                            "max": DIFFICULTY_RAMP_PERIOD_S_MAX,
+                            "default": DEFAULT_DIFFICULTY_RAMP_PERIOD_S
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "api",
+            {
+                "type": "object",
+                "label": "API",
+                "fields": [
+                    [
+                        "hardware_control",
+                        {
+                            "type": "bool",
+                            "label": "Hardware Control",
+                            "default": DEFAULT_API_HARDWARE_CONTROL,
+                            "description": DESCRIPTION_API_HARDWARE_CONTROL
+                        }
+                    ],
+                    [
+                        "metrics_listen",
+                        {
+                            "type": "string",
+                            "label": "Metrics Listen Address",
+                            "description": DESCRIPTION_API_METRICS_LISTEN
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "audit",
+            {
+                "type": "object",
+                "label": "Audit",
+                "fields": [
+                    [
+                        "header_export",
+                        {
+                            "type": "string",
+                            "label": "Header Export File",
+                            "description": DESCRIPTION_AUDIT_HEADER_EXPORT
+                        }
+                    ],
+                    [
+                        "header_export_blocks_only",
+                        {
+                            "type": "bool",
+                            "label": "Export Only Blocks",
+                            "default": DEFAULT_AUDIT_HEADER_EXPORT_BLOCKS_ONLY
+                        }
+                    ],
+                    [
+                        "block_archive",
+                        {
+                            "type": "string",
+                            "label": "Found Block Archive File",
+                            "description": DESCRIPTION_AUDIT_BLOCK_ARCHIVE
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "logging",
+            {
+                "type": "object",
+                "label": "Logging",
+                "fields": [
+                    [
+                        "level",
+                        {
+                            "type": "enum",
+                            "label": "Level",
+                            "values": [
+                                { "key": "error", "label": "Error" },
+                                { "key": "warning", "label": "Warning" },
+                                { "key": "info", "label": "Info" },
+                                { "key": "debug", "label": "Debug" },
+                                { "key": "trace", "label": "Trace" }
+                            ],
+                            "default": "info"
+                        }
+                    ],
+                    [
+                        "file",
+                        {
+                            "type": "string",
+                            "label": "Log File"
+                        }
+                    ],
+                    [
+                        "filter",
+                        {
+                            "type": "string",
+                            "label": "Module Levels",
+                            "description": DESCRIPTION_LOGGING_FILTER
+                        }
+                    ],
+                    [
+                        "format",
+                        {
+                            "type": "enum",
+                            "label": "Format",
+                            "values": [
+                                { "key": "full", "label": "Full" },
+                                { "key": "json", "label": "JSON" }
+                            ],
+                            "default": "full"
+                        }
+                    ],
+                    [
+                        "syslog_server",
+                        {
+                            "type": "string",
+                            "label": "Syslog Server",
+                            "description": DESCRIPTION_LOGGING_SYSLOG_SERVER
+                        }
+                    ],
+                    [
+                        "syslog_bind",
+                        {
+                            "type": "string",
+                            "label": "Syslog Bind Address",
+                            "description": DESCRIPTION_LOGGING_SYSLOG_BIND
+                        }
+                    ],
+                    [
+                        "json_server",
+                        {
+                            "type": "string",
+                            "label": "JSON Log Server",
+                            "description": DESCRIPTION_LOGGING_JSON_SERVER
+                        }
+                    ]
+                ]
+            }
+        ],
+        [
+            "translation_proxy",
+            {
+                "type": "object",
+                "label": "Stratum V1 Translation Proxy",
+                "description": DESCRIPTION_TRANSLATION_PROXY,
+                "fields": [
+                    [
+                        "enabled",
+                        {
+                            "type": "bool",
+                            "label": "Enabled",
+                            "default": true
+                        }
+                    ],
+                    [
+                        "listen",
+                        {
+                            "type": "string",
+                            "label": "Listen Address",
+                            "min_length": 1,
+                            "description": DESCRIPTION_TRANSLATION_PROXY_LISTEN
+                        }
+                    ],
+                    [
+                        "upstream",
+                        {
+                            "type": "url",
+                            "label": "Upstream V2 Pool",
+                            "min_length": 1,
+                            "match": CLIENT_URL_JAVA_SCRIPT_REGEX,
+                            "description": DESCRIPTION_TRANSLATION_PROXY_UPSTREAM
+                        }
+                    ],
+                    [
+                        "user",
+                        {
+                            "type": "string",
+                            "label": "Username",
+                            "min_length": 1
+                        }
+                    ]
+                ]
+            }
         ]
     ])
 }
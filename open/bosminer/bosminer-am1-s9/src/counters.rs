@@ -26,8 +26,16 @@
 
 use crate::bm1387;
 
+use bosminer::stats;
+
 use std::time::{Duration, Instant};
 
+/// Ratio of hardware `errors` to all nonces returned by chips where `valid` is in shares of
+/// `asic_difficulty`
+pub fn error_ratio(valid: usize, errors: usize, asic_difficulty: usize) -> f64 {
+    stats::hardware_error_ratio((valid / asic_difficulty.max(1)) as u64, errors as u64)
+}
+
 /// Per-core counters for valid nonces/errors
 #[derive(Clone, Copy)]
 pub struct Core {
@@ -149,4 +157,40 @@ impl HashChain {
     pub fn chip_count(&self) -> usize {
         self.chip.len()
     }
+
+    /// Ratio of hardware errors to all nonces returned by the whole hashchain
+    pub fn error_ratio(&self) -> f64 {
+        error_ratio(self.valid, self.errors, self.asic_difficulty)
+    }
+
+    /// Ratio of hardware errors to all nonces returned by chip with index `chip`
+    pub fn chip_error_ratio(&self, chip: usize) -> f64 {
+        let chip = &self.chip[chip];
+        error_ratio(chip.valid, chip.errors, self.asic_difficulty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_ratio() {
+        let mut counter = HashChain::new(2, 64);
+        assert_eq!(counter.error_ratio(), 0.0);
+
+        let good_core = bm1387::CoreAddress { chip: 0, core: 0 };
+        let bad_core = bm1387::CoreAddress { chip: 1, core: 0 };
+        for _ in 0..3 {
+            counter.add_valid(good_core);
+            counter.add_valid(bad_core);
+        }
+        counter.add_error(bad_core);
+        // nonce from non-existent chip is ignored
+        counter.add_error(bm1387::CoreAddress { chip: 2, core: 0 });
+
+        assert_eq!(counter.chip_error_ratio(0), 0.0);
+        assert_eq!(counter.chip_error_ratio(1), 0.25);
+        assert_eq!(counter.error_ratio(), 1.0 / 7.0);
+    }
 }
@@ -207,6 +207,18 @@ impl From<uio_async::UioError> for Error {
     }
 }
 
+impl From<bosminer::error::Error> for Error {
+    fn from(bosminer_error: bosminer::error::Error) -> Self {
+        let kind = match bosminer_error.kind() {
+            bosminer::error::ErrorKind::UioDevice(name, msg) => ErrorKind::UioDevice(name, msg),
+            kind => ErrorKind::General(kind.to_string()),
+        };
+        Self {
+            inner: bosminer_error.context(kind),
+        }
+    }
+}
+
 impl From<sysfs_gpio::Error> for Error {
     fn from(gpio_error: sysfs_gpio::Error) -> Self {
         let msg = gpio_error.to_string();
@@ -24,10 +24,8 @@
 
 pub mod pid;
 
-use crate::error::{self, ErrorKind};
-use failure::ResultExt;
-
-use uio_async;
+use crate::error;
+use bosminer::backend::fpga;
 
 /// Structure representing PWM of fan
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -62,21 +60,13 @@ impl Feedback {
 
 /// Memory-mapped fan controller
 pub struct Control {
-    regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::fan_ctrl::RegisterBlock>,
+    regs: fpga::Device<ii_fpga_io_am1_s9::fan_ctrl::RegisterBlock>,
 }
 
 impl Control {
     pub fn new() -> error::Result<Self> {
-        let name = "fan-control".to_string();
-        let uio = uio_async::UioDevice::open_by_name(&name).with_context(|_| {
-            ErrorKind::UioDevice(name.clone(), "cannot open uio device".to_string())
-        })?;
-        let map = uio.map_mapping(0).with_context(|_| {
-            ErrorKind::UioDevice(name.clone(), "cannot map uio device".to_string())
-        })?;
-
         Ok(Self {
-            regs: map.into_typed(),
+            regs: fpga::Device::open("fan-control".to_string())?,
         })
     }
 
@@ -35,6 +35,9 @@ pub struct TempControl {
 }
 
 impl TempControl {
+    /// Fans are kept at least at this speed while miner is warming up
+    const WARM_UP_MIN_SPEED: usize = 60;
+
     pub fn new() -> Self {
         // kp/ki/kd constants are negative because the PID works in reverse direction
         // (the lower the PWM, the higher the temperature)
@@ -44,18 +47,19 @@ impl TempControl {
             pid,
             last_update: Instant::now(),
         };
-        temp_control.set_warm_up_limits();
+        temp_control.set_warm_up_limits(Speed::STOPPED);
         return temp_control;
     }
 
     /// set fan limits when warming up
-    pub fn set_warm_up_limits(&mut self) {
-        self.pid.set_limits(60.0, 100.0);
+    pub fn set_warm_up_limits(&mut self, min_speed: Speed) {
+        self.set_normal_limits(Speed::new(min_speed.to_pwm().max(Self::WARM_UP_MIN_SPEED)));
     }
 
     /// set fan limits when in operation
-    pub fn set_normal_limits(&mut self) {
-        self.pid.set_limits(1.0, 100.0);
+    pub fn set_normal_limits(&mut self, min_speed: Speed) {
+        self.pid
+            .set_limits(min_speed.to_pwm() as f64, Speed::FULL_SPEED.to_pwm() as f64);
     }
 
     pub fn set_target(&mut self, target: f64) {
@@ -36,6 +36,10 @@ use std::time::Duration;
 use crate::error;
 use error::ErrorKind;
 
+use bosminer::hal;
+
+use async_trait::async_trait;
+
 use futures::channel::mpsc;
 use futures::future::{select, Either};
 use futures::lock::Mutex;
@@ -205,7 +209,7 @@ impl Sender {
     /// tasks was halted (we send them channel to reply back) and one of them would be dropped
     /// before it had a chance to run (ie. as a result of another task that is being terminated
     /// dropping it in termination handler) it wouldn't respond with "termination successful".
-    async fn send_halt_internal(self: Arc<Self>) -> error::Result<()> {
+    async fn halt_clients(&self) -> error::Result<()> {
         // take the list of clients
         let mut clients: Vec<_> = self.clients.lock().await.drain(..).collect();
 
@@ -232,6 +236,12 @@ impl Sender {
                 )))?,
             }
         }
+        Ok(())
+    }
+
+    /// Halt all clients and then run the exit hooks
+    async fn send_halt_internal(self: Arc<Self>) -> error::Result<()> {
+        self.halt_clients().await?;
 
         // run exit hooks (in order they came in)
         for hook in self.exit_hooks.lock().await.drain(..) {
@@ -243,12 +253,8 @@ impl Sender {
     /// This is a hack around `halt_sender` having to be run from tokio context, because it spawns
     /// additional threads.
     pub fn hook_termination_signals(self: Arc<Self>) {
-        // Hook `SIGINT`, `SIGHUP` and `SIGTERM`
-        for signal_type in vec![
-            SignalKind::interrupt(),
-            SignalKind::hangup(),
-            SignalKind::terminate(),
-        ] {
+        // Hook `SIGINT` and `SIGTERM` (`SIGHUP` is used for reloading of configuration)
+        for signal_type in vec![SignalKind::interrupt(), SignalKind::terminate()] {
             let halt_sender = self.clone();
             tokio::spawn(async move {
                 if let Some(_) = signal(signal_type)
@@ -278,6 +284,15 @@ impl Sender {
     }
 }
 
+/// Frontend halts all clients but the exit hooks are not run so that the program can terminate
+/// on its own
+#[async_trait]
+impl hal::Halt for Sender {
+    async fn halt(&self) {
+        self.halt_clients().await.expect("halt failed");
+    }
+}
+
 /// Build a halt sender/receiver pair
 pub fn make_pair(halt_timeout: Duration) -> (Arc<Sender>, Receiver) {
     let sender = Sender::new(halt_timeout);
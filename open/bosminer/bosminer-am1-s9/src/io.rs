@@ -28,13 +28,19 @@
 //!   * `Control` layer knows about chip configuration (number of midstates)
 //!     and implements few higher-level functions to read/write work
 
+mod backlog;
 mod ext_work_id;
+mod pause;
 mod uio;
 
 use crate::error::{self, ErrorKind};
 use crate::MidstateCount;
 use ext_work_id::ExtWorkId;
 
+pub use backlog::Backlog;
+pub use pause::WorkTxPause;
+
+use bosminer::backend::fpga::{self, RxFifo, TxFifo};
 use bosminer::work;
 use std::convert::TryInto;
 use std::fmt;
@@ -131,58 +137,80 @@ pub struct Solution {
 }
 
 struct WorkRxFifo {
-    regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::workrx::RegisterBlock>,
-    uio: uio_async::UioDevice,
+    device: fpga::Device<ii_fpga_io_am1_s9::workrx::RegisterBlock>,
 }
 
-impl WorkRxFifo {
+impl fpga::RxFifo for WorkRxFifo {
+    type Regs = ii_fpga_io_am1_s9::workrx::RegisterBlock;
+
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.regs.work_rx_stat_reg.read().rx_empty().bit()
+    fn device(&self) -> &fpga::Device<Self::Regs> {
+        &self.device
     }
 
-    /// Try to read from work rx fifo.
-    /// Performs blocking read with timeout. Uses IRQ.
-    #[allow(dead_code)]
     #[inline]
-    pub fn read(&mut self, timeout: Option<Duration>) -> error::Result<Option<u32>> {
-        let cond = || !self.is_empty();
-        let got_irq = self.uio.irq_wait_cond(cond, timeout)?;
-        Ok(got_irq.and_then(|_| Some(self.regs.work_rx_fifo.read().bits())))
+    fn is_empty(&self) -> bool {
+        self.device.work_rx_stat_reg.read().rx_empty().bit()
     }
 
-    /// Try to read from work rx fifo.
-    /// Async variant. Uses IRQ.
-    pub async fn async_read(&mut self) -> error::Result<u32> {
-        let cond = || !self.is_empty();
-        self.uio.async_irq_wait_cond(cond).await?;
-        Ok(self.regs.work_rx_fifo.read().bits())
+    #[inline]
+    fn read_unchecked(&self) -> u32 {
+        self.device.work_rx_fifo.read().bits()
     }
+}
 
+impl WorkRxFifo {
     pub fn init(&mut self) -> error::Result<()> {
         // reset input FIFO
-        self.regs
+        self.device
             .work_rx_ctrl_reg
             .modify(|_, w| w.rst_rx_fifo().set_bit());
         // enable IRQ_WORK_RX interrupt
-        self.regs
+        self.device
             .work_rx_ctrl_reg
             .modify(|_, w| w.irq_en().set_bit());
         Ok(())
     }
 
     pub fn new(hashboard_idx: usize) -> error::Result<Self> {
-        let uio = uio::Device::open(hashboard_idx, uio::Type::WorkRx)?;
         Ok(Self {
-            regs: uio.map()?,
-            uio: uio.uio,
+            device: uio::open_ip_core(hashboard_idx, uio::Type::WorkRx)?,
         })
     }
 }
 
 struct WorkTxFifo {
-    regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::worktx::RegisterBlock>,
-    uio: uio_async::UioDevice,
+    device: fpga::Device<ii_fpga_io_am1_s9::worktx::RegisterBlock>,
+}
+
+impl fpga::TxFifo for WorkTxFifo {
+    type Regs = ii_fpga_io_am1_s9::worktx::RegisterBlock;
+
+    #[inline]
+    fn device(&self) -> &fpga::Device<Self::Regs> {
+        &self.device
+    }
+
+    #[inline]
+    fn is_full(&self) -> bool {
+        self.device.work_tx_stat_reg.read().tx_full().bit()
+    }
+
+    /// The IP core only signals that the FIFO fill level is under the threshold which always
+    /// leaves room for the biggest work
+    #[inline]
+    fn has_room(&self, count: usize) -> bool {
+        assert!(
+            count <= Self::BIGGEST_WORK as usize,
+            "BUG: work does not fit into work TX FIFO"
+        );
+        self.has_space_for_one_job()
+    }
+
+    #[inline]
+    fn write_unchecked(&self, word: u32) {
+        self.device.work_tx_fifo.write(|w| unsafe { w.bits(word) });
+    }
 }
 
 impl WorkTxFifo {
@@ -197,63 +225,54 @@ impl WorkTxFifo {
     const FIFO_THRESHOLD: u32 = Self::FIFO_SIZE - Self::BIGGEST_WORK;
 
     #[inline]
-    pub fn is_full(&self) -> bool {
-        self.regs.work_tx_stat_reg.read().tx_full().bit()
+    pub fn is_empty(&self) -> bool {
+        self.device.work_tx_stat_reg.read().tx_empty().bit()
     }
 
     #[inline]
     pub fn has_space_for_one_job(&self) -> bool {
-        self.regs.work_tx_stat_reg.read().irq_pend().bit()
+        self.device.work_tx_stat_reg.read().irq_pend().bit()
+    }
+
+    /// Set number of entries in FIFO queue under which the interrupt is raised (and more work
+    /// is requested)
+    pub fn set_threshold(&mut self, threshold: u32) {
+        assert!(
+            threshold <= Self::FIFO_THRESHOLD,
+            "BUG: work TX threshold leaves no room for work"
+        );
+        self.device
+            .work_tx_irq_thr
+            .write(|w| unsafe { w.bits(threshold) });
     }
 
     /// Return the value of last work ID send to ASICs
     #[inline]
     #[allow(dead_code)]
     pub fn get_last_work_id(&mut self) -> u32 {
-        self.regs.work_tx_last_id.read().bits()
-    }
-
-    /// Try to write work item to work TX FIFO.
-    /// Performs blocking write without timeout. Uses IRQ.
-    /// The idea is that you don't call this function until you are sure you
-    /// can fit in all the entries you want.
-    #[inline]
-    pub fn write(&mut self, item: u32) -> error::Result<()> {
-        let cond = || !self.is_full();
-        self.uio.irq_wait_cond(cond, None)?;
-        self.regs.work_tx_fifo.write(|w| unsafe { w.bits(item) });
-        Ok(())
-    }
-
-    /// Wait for output FIFO to make room for one work
-    pub async fn async_wait_for_room(&self) -> error::Result<()> {
-        let cond = || self.has_space_for_one_job();
-        self.uio.async_irq_wait_cond(cond).await?;
-        Ok(())
+        self.device.work_tx_last_id.read().bits()
     }
 
     pub fn init(&mut self) -> error::Result<()> {
         // Set threshold for work TX so that there's space for
         // at least one job.
-        self.regs
+        self.device
             .work_tx_irq_thr
             .write(|w| unsafe { w.bits(Self::FIFO_THRESHOLD) });
         // reset output FIFO
-        self.regs
+        self.device
             .work_tx_ctrl_reg
             .modify(|_, w| w.rst_tx_fifo().set_bit());
         // enable IRQ_WORK_TX interrupt
-        self.regs
+        self.device
             .work_tx_ctrl_reg
             .modify(|_, w| w.irq_en().set_bit());
         Ok(())
     }
 
     pub fn new(hashboard_idx: usize) -> error::Result<Self> {
-        let uio = uio::Device::open(hashboard_idx, uio::Type::WorkTx)?;
         Ok(Self {
-            regs: uio.map()?,
-            uio: uio.uio,
+            device: uio::open_ip_core(hashboard_idx, uio::Type::WorkTx)?,
         })
     }
 }
@@ -263,29 +282,47 @@ impl WorkTxFifo {
 ///
 /// TODO: Split this FIFO into two FIFOs.
 pub struct CommandRxTxFifos {
-    regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::command::RegisterBlock>,
-    uio: uio_async::UioDevice,
+    device: fpga::Device<ii_fpga_io_am1_s9::command::RegisterBlock>,
+}
+
+impl fpga::RxFifo for CommandRxTxFifos {
+    type Regs = ii_fpga_io_am1_s9::command::RegisterBlock;
+
+    #[inline]
+    fn device(&self) -> &fpga::Device<Self::Regs> {
+        &self.device
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.is_rx_empty()
+    }
+
+    #[inline]
+    fn read_unchecked(&self) -> u32 {
+        self.device.cmd_rx_fifo.read().bits()
+    }
 }
 
 impl CommandRxTxFifos {
     #[inline]
     pub fn get_stat_reg(&self) -> u32 {
-        self.regs.cmd_stat_reg.read().bits()
+        self.device.cmd_stat_reg.read().bits()
     }
 
     #[inline]
     pub fn is_rx_empty(&self) -> bool {
-        self.regs.cmd_stat_reg.read().rx_empty().bit()
+        self.device.cmd_stat_reg.read().rx_empty().bit()
     }
 
     #[inline]
     pub fn is_tx_empty(&self) -> bool {
-        self.regs.cmd_stat_reg.read().tx_empty().bit()
+        self.device.cmd_stat_reg.read().tx_empty().bit()
     }
 
     #[inline]
     pub fn is_tx_full(&self) -> bool {
-        self.regs.cmd_stat_reg.read().tx_full().bit()
+        self.device.cmd_stat_reg.read().tx_full().bit()
     }
 
     /// Wait for command FIFO to become empty
@@ -304,15 +341,7 @@ impl CommandRxTxFifos {
             delay_for(Duration::from_millis(1)).await;
         }
         // write command word
-        self.regs.cmd_tx_fifo.write(|w| unsafe { w.bits(item) });
-    }
-
-    /// Read command from cmd rx fifo
-    /// Async variant. Uses IRQ.
-    pub async fn read(&mut self) -> error::Result<u32> {
-        let cond = || !self.is_rx_empty();
-        self.uio.async_irq_wait_cond(cond).await?;
-        Ok(self.regs.cmd_rx_fifo.read().bits())
+        self.device.cmd_tx_fifo.write(|w| unsafe { w.bits(item) });
     }
 
     /// Read command from cmd rx fifo with timeout
@@ -322,14 +351,14 @@ impl CommandRxTxFifos {
     ///     * `Ok(Some(_))` if something was received
     ///     * `Err(_)` if error occured
     pub async fn read_with_timeout(&mut self, timeout: Duration) -> error::Result<Option<u32>> {
-        match self.read().timeout(timeout).await {
-            Ok(Ok(word)) => Ok(Some(word)), // Read complete on time
-            Ok(Err(err)) => Err(err),       // Read I/O error
+        match self.async_read().timeout(timeout).await {
+            Ok(Ok(word)) => Ok(Some(word)),  // Read complete on time
+            Ok(Err(err)) => Err(err.into()), // Read I/O error
             Err(_) => {
                 // Read timeout
                 if !self.is_rx_empty() {
                     // XXX workaround when cpu is 100% full
-                    Ok(Some(self.read().await?))
+                    Ok(Some(self.async_read().await?))
                 } else {
                     Ok(None)
                 }
@@ -339,19 +368,17 @@ impl CommandRxTxFifos {
 
     pub fn init(&mut self) -> error::Result<()> {
         // reset input FIFO
-        self.regs
+        self.device
             .cmd_ctrl_reg
             .modify(|_, w| w.rst_rx_fifo().set_bit().rst_tx_fifo().set_bit());
         // enable IRQ_CMD_RX interrupt
-        self.regs.cmd_ctrl_reg.modify(|_, w| w.irq_en().set_bit());
+        self.device.cmd_ctrl_reg.modify(|_, w| w.irq_en().set_bit());
         Ok(())
     }
 
     pub fn new(hashboard_idx: usize) -> error::Result<Self> {
-        let uio = uio::Device::open(hashboard_idx, uio::Type::Command)?;
         Ok(Self {
-            regs: uio.map()?,
-            uio: uio.uio,
+            device: uio::open_ip_core(hashboard_idx, uio::Type::Command)?,
         })
     }
 }
@@ -422,8 +449,37 @@ pub struct WorkTx {
 }
 
 impl WorkTx {
+    /// Size of work header (work ID, nbits, ntime and merkle root tail) in u32 words
+    const WORK_HEADER_SIZE: u32 = 4;
+    /// Size of one midstate in u32 words
+    const MIDSTATE_SIZE: u32 = 8;
+
+    /// Wait for output FIFO to make room for one work
     pub async fn wait_for_room(&self) -> error::Result<()> {
-        self.fifo.async_wait_for_room().await
+        Ok(self
+            .fifo
+            .async_wait_for_room(self.work_size() as usize)
+            .await?)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fifo.is_empty()
+    }
+
+    /// Size of one work in u32 words
+    fn work_size(&self) -> u32 {
+        Self::WORK_HEADER_SIZE + Self::MIDSTATE_SIZE * self.midstate_count.to_count() as u32
+    }
+
+    /// Maximal number of work items which can be buffered in the FIFO
+    pub fn max_backlog(&self) -> usize {
+        (WorkTxFifo::FIFO_THRESHOLD / self.work_size()) as usize
+    }
+
+    /// Limit number of work items buffered in the FIFO to `depth`
+    pub fn set_backlog(&mut self, depth: usize) {
+        self.fifo.set_threshold(depth as u32 * self.work_size());
     }
 
     pub fn assert_midstate_count(&self, expected_midstate_count: usize) {
@@ -444,17 +500,29 @@ impl WorkTx {
         self.assert_midstate_count(work.midstates.len());
         let ext_work_id = ExtWorkId::new(work_id, 0);
 
-        self.fifo
-            .write(ext_work_id.to_hw(self.midstate_count).to_le())?;
-        self.fifo.write(work.bits().to_le())?;
-        self.fifo.write(work.ntime.to_le())?;
-        self.fifo.write(work.merkle_root_tail().to_le())?;
-
-        for mid in work.midstates.iter() {
-            for midstate_word in mid.state.words::<u32>().rev() {
-                self.fifo.write(midstate_word.to_be())?;
-            }
+        // the work is assembled on stack and written to the FIFO at once
+        let mut buf = [0u32; WorkTxFifo::BIGGEST_WORK as usize];
+        let header = [
+            ext_work_id.to_hw(self.midstate_count).to_le(),
+            work.bits().to_le(),
+            work.ntime.to_le(),
+            work.merkle_root_tail().to_le(),
+        ];
+        let midstate_words = work
+            .midstates
+            .iter()
+            .flat_map(|mid| mid.state.words::<u32>().rev().map(u32::to_be));
+        let mut len = 0;
+        for (slot, word) in buf
+            .iter_mut()
+            .zip(header.iter().copied().chain(midstate_words))
+        {
+            *slot = word;
+            len += 1;
         }
+        assert_eq!(len, self.work_size() as usize, "BUG: invalid work size");
+
+        self.fifo.write_buffer(&buf[..len])?;
         Ok(())
     }
 
@@ -552,7 +620,7 @@ impl CommandRxTx {
 /// Structure holding the `common` register block
 pub struct Common {
     /// The `common` register block itself
-    regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::common::RegisterBlock>,
+    regs: fpga::Device<ii_fpga_io_am1_s9::common::RegisterBlock>,
     /// Current midstate configuration
     midstate_count: MidstateCount,
     /// With which hashboard is this register block associated?
@@ -662,9 +730,8 @@ impl Common {
     }
 
     fn new(hashboard_idx: usize, midstate_count: MidstateCount) -> error::Result<Self> {
-        let uio = uio::Device::open(hashboard_idx, uio::Type::Common)?;
         Ok(Self {
-            regs: uio.map()?,
+            regs: uio::open_ip_core(hashboard_idx, uio::Type::Common)?,
             midstate_count,
             hashboard_idx,
         })
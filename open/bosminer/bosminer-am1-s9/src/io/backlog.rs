@@ -0,0 +1,151 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Adaptive sizing of the work backlog buffered in the FPGA work TX FIFO
+
+use std::time::{Duration, Instant};
+
+/// Keeps track of the rate at which the hash chain consumes work and determines how many work
+/// items have to be buffered in the FIFO to hide latency of the work generation. Work buffered
+/// above this depth is wasted when a new job arrives so the depth is kept as small as possible.
+#[derive(Debug, Clone)]
+pub struct Backlog {
+    /// Maximal number of work items which fit into the FIFO
+    max_depth: usize,
+    /// Current number of work items which should be buffered
+    depth: usize,
+    /// Start of the current measurement period
+    period_start: Instant,
+    /// Number of work items sent in the current measurement period
+    sent: usize,
+    /// The FIFO has been drained during the current measurement period
+    starved: bool,
+}
+
+impl Backlog {
+    /// Time of hashing covered by the buffered work
+    pub const LATENCY: Duration = Duration::from_millis(100);
+    /// Period of measurement of the consumption rate
+    pub const MEASUREMENT_PERIOD: Duration = Duration::from_secs(1);
+    /// Minimal number of buffered work items
+    pub const MIN_DEPTH: usize = 2;
+
+    /// Create new backlog which starts with the full FIFO until the first measurement is done
+    pub fn new(max_depth: usize, now: Instant) -> Self {
+        assert!(
+            max_depth >= Self::MIN_DEPTH,
+            "BUG: FIFO has no room for minimal backlog"
+        );
+        Self {
+            max_depth,
+            depth: max_depth,
+            period_start: now,
+            sent: 0,
+            starved: false,
+        }
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Account work sent to the FIFO. The `starved` flag denotes that the FIFO has been found
+    /// empty even though the work was generated without delay.
+    pub fn account_sent(&mut self, starved: bool) {
+        self.sent += 1;
+        self.starved |= starved;
+    }
+
+    /// Recompute the depth at the end of each measurement period. Returns the new depth when it
+    /// has been changed.
+    pub fn update(&mut self, now: Instant) -> Option<usize> {
+        let elapsed = now.saturating_duration_since(self.period_start);
+        if elapsed < Self::MEASUREMENT_PERIOD {
+            return None;
+        }
+        let rate = self.sent as f64 / elapsed.as_secs_f64();
+        let mut depth = (rate * Self::LATENCY.as_secs_f64()).ceil() as usize;
+        if self.starved {
+            // the chips have been waiting for work so do not decrease the backlog and grow it
+            // quickly instead
+            depth = depth.max(self.depth * 2);
+        }
+        let depth = depth.max(Self::MIN_DEPTH).min(self.max_depth);
+
+        self.period_start = now;
+        self.sent = 0;
+        self.starved = false;
+        if depth != self.depth {
+            self.depth = depth;
+            Some(depth)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backlog() {
+        let mut now = Instant::now();
+        let mut backlog = Backlog::new(50, now);
+        assert_eq!(backlog.depth(), 50);
+
+        // no update before the end of the measurement period
+        for _ in 0..20 {
+            backlog.account_sent(false);
+        }
+        assert_eq!(backlog.update(now), None);
+
+        // 200 works per second are consumed which requires 20 works for 100ms
+        for _ in 20..200 {
+            backlog.account_sent(false);
+        }
+        now += Backlog::MEASUREMENT_PERIOD;
+        assert_eq!(backlog.update(now), Some(20));
+        assert_eq!(backlog.depth(), 20);
+
+        // the same rate does not change the depth
+        for _ in 0..200 {
+            backlog.account_sent(false);
+        }
+        now += Backlog::MEASUREMENT_PERIOD;
+        assert_eq!(backlog.update(now), None);
+
+        // starvation doubles the depth
+        backlog.account_sent(true);
+        now += Backlog::MEASUREMENT_PERIOD;
+        assert_eq!(backlog.update(now), Some(40));
+        // and it is limited by the FIFO size
+        backlog.account_sent(true);
+        now += Backlog::MEASUREMENT_PERIOD;
+        assert_eq!(backlog.update(now), Some(50));
+
+        // idle chain drops to the minimal depth
+        now += Backlog::MEASUREMENT_PERIOD;
+        assert_eq!(backlog.update(now), Some(Backlog::MIN_DEPTH));
+    }
+}
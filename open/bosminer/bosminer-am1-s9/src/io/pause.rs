@@ -0,0 +1,127 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Pausing of the work TX task while chip registers are accessed for diagnostics
+
+use futures::lock::{Mutex, MutexGuard};
+use ii_async_compat::futures;
+
+use ii_async_compat::tokio;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Work TX task checks the pause flag before each work is sent to the FIFO, which is just an
+/// atomic access. The mutex is only taken by the work TX task when the sending is paused and it
+/// waits for the end of the pause.
+///
+/// Chips keep computing the work they already have, only no new work is sent to them.
+#[derive(Debug, Default)]
+pub struct WorkTxPause {
+    /// Work must not be sent
+    paused: AtomicBool,
+    /// Work TX task is between checking `paused` and sending the work
+    sending: AtomicBool,
+    /// Held for the whole duration of the pause
+    lock: Mutex<()>,
+}
+
+/// The work sending is resumed when the guard is dropped
+pub struct WorkTxPauseGuard<'a> {
+    pause: &'a WorkTxPause,
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl Drop for WorkTxPauseGuard<'_> {
+    fn drop(&mut self) {
+        self.pause.paused.store(false, Ordering::SeqCst);
+    }
+}
+
+impl WorkTxPause {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stop sending of work and wait until the work being sent right now is written to the FIFO
+    pub async fn pause(&self) -> WorkTxPauseGuard<'_> {
+        let lock = self.lock.lock().await;
+        self.paused.store(true, Ordering::SeqCst);
+        while self.sending.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+        WorkTxPauseGuard {
+            pause: self,
+            _lock: lock,
+        }
+    }
+
+    /// Run `send` unless sending is paused, otherwise wait for the end of the pause first
+    pub async fn send<T, F: FnOnce() -> T>(&self, send: F) -> T {
+        loop {
+            self.sending.store(true, Ordering::SeqCst);
+            if !self.paused.load(Ordering::SeqCst) {
+                break;
+            }
+            self.sending.store(false, Ordering::SeqCst);
+            // The lock is held by the pausing side until the pause is over
+            drop(self.lock.lock().await);
+        }
+        let result = send();
+        self.sending.store(false, Ordering::SeqCst);
+        result
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::delay_for;
+
+    #[tokio::test]
+    async fn test_work_tx_pause() {
+        let pause = Arc::new(WorkTxPause::new());
+        assert_eq!(pause.send(|| 1).await, 1);
+
+        let guard = pause.pause().await;
+        assert!(pause.is_paused());
+        let sent = Arc::new(AtomicBool::new(false));
+        let task = {
+            let pause = pause.clone();
+            let sent = sent.clone();
+            tokio::spawn(async move { pause.send(|| sent.store(true, Ordering::SeqCst)).await })
+        };
+        delay_for(Duration::from_millis(10)).await;
+        assert!(!sent.load(Ordering::SeqCst));
+
+        drop(guard);
+        assert!(!pause.is_paused());
+        task.await.expect("BUG: send task failed");
+        assert!(sent.load(Ordering::SeqCst));
+    }
+}
@@ -23,6 +23,7 @@
 //! Simple wrapper around UIO device
 
 use crate::error::{self, ErrorKind};
+use bosminer::backend::fpga;
 use failure::ResultExt;
 use uio_async;
 
@@ -47,6 +48,20 @@ impl Type {
             &Type::Command => "cmd-rx",
         }
     }
+
+    /// Name of UIO device of this type for given hashboard
+    ///
+    /// * `hashboard_idx` - one-based hashboard index (same as connector number:
+    ///   connector J8 means `hashboard_idx=8`)
+    fn device_name(&self, hashboard_idx: usize) -> String {
+        assert!(hashboard_idx > 0);
+        format!("chain{}-{}", hashboard_idx, self.as_str())
+    }
+}
+
+/// Open IP core of given type for given hashboard and map its register block
+pub fn open_ip_core<T>(hashboard_idx: usize, uio_type: Type) -> error::Result<fpga::Device<T>> {
+    Ok(fpga::Device::open(uio_type.device_name(hashboard_idx))?)
 }
 
 impl Device {
@@ -56,8 +71,7 @@ impl Device {
     ///   connector J8 means `hashboard_idx=8`)
     /// * `uio_type` - type of uio device, determines what IO block to map
     pub fn open(hashboard_idx: usize, uio_type: Type) -> error::Result<Self> {
-        assert!(hashboard_idx > 0);
-        let uio_name = format!("chain{}-{}", hashboard_idx, uio_type.as_str());
+        let uio_name = uio_type.device_name(hashboard_idx);
         let uio = uio_async::UioDevice::open_by_name(&uio_name).with_context(|_| {
             ErrorKind::UioDevice(uio_name.clone(), "cannot open uio device".to_string())
         })?;
@@ -23,7 +23,9 @@
 
 mod async_i2c;
 pub mod bm1387;
+pub mod bm1391;
 mod cgminer;
+pub mod chip;
 pub mod command;
 pub mod config;
 pub mod counters;
@@ -34,11 +36,14 @@ pub mod halt;
 pub mod hooks;
 pub mod i2c;
 pub mod io;
+mod metrics;
 pub mod monitor;
 pub mod null_work;
 pub mod power;
+pub mod psu;
 pub mod registry;
 pub mod sensor;
+pub mod tuner;
 pub mod utils;
 
 #[cfg(test)]
@@ -47,21 +52,27 @@ pub mod test;
 use ii_logging::macros::*;
 
 use bosminer::async_trait;
-use bosminer::hal::{self, BackendConfig as _};
+use bosminer::client;
+use bosminer::hal::{self, nonce_space, BackendConfig as _};
 use bosminer::node;
 use bosminer::stats;
+use bosminer::sync;
+use bosminer::translation_proxy;
 use bosminer::work;
 
 use bosminer_macros::WorkSolverNode;
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
 use std::time::{Duration, Instant};
 
 use error::ErrorKind;
 use failure::ResultExt;
 
-use futures::channel::mpsc;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{join, select, Either, FutureExt};
 use futures::lock::{Mutex, MutexGuard};
 use futures::stream::StreamExt;
 use ii_async_compat::futures;
@@ -69,25 +80,25 @@ use ii_async_compat::futures;
 use bm1387::{ChipAddress, MidstateCount};
 use command::Interface;
 
-use packed_struct::PackedStruct;
-
 use embedded_hal::digital::v2::InputPin;
 use embedded_hal::digital::v2::OutputPin;
 
-use ii_bitcoin::MeetsTarget;
-
 use ii_async_compat::tokio;
+use tokio::signal;
 use tokio::sync::watch;
 use tokio::time::delay_for;
 
-/// Timing constants
-const INACTIVATE_FROM_CHAIN_DELAY: Duration = Duration::from_millis(100);
 /// Base delay quantum during hashboard initialization
 const INIT_DELAY: Duration = Duration::from_secs(1);
 /// Time to wait between successive hashboard initialization attempts
 const ENUM_RETRY_DELAY: Duration = Duration::from_secs(10);
 /// How many times to retry the enumeration
 const ENUM_RETRY_COUNT: usize = 10;
+/// How often to check temperature and nonces of hashchain for thermal cutoff and watchdog
+const CHAIN_CHECK_PERIOD: Duration = Duration::from_secs(5);
+
+/// Number of hashboard connectors on the control board
+pub const MAX_HASHBOARDS: usize = 8;
 
 /// Maximum number of chips is limitted by the fact that there is only 8-bit address field and
 /// addresses to the chips need to be assigned with step of 4 (e.g. 0, 4, 8, etc.)
@@ -103,15 +114,34 @@ const INIT_CHIP_BAUD_RATE: usize = 115740;
 /// Exact desired target baud rate when hashing at full speed (matches the divisor, too)
 const TARGET_CHIP_BAUD_RATE: usize = 1562500;
 
+/// Fraction of the time required to search the whole nonce space after which the work is replaced
+/// by the next one
+const WORK_DELAY_FUDGE: f64 = 0.9;
+/// Hash chain which returns fewer nonces than this fraction of the nonces expected for the sent
+/// work has not searched all of it (e.g. the work has been dropped)
+const MIN_NONCE_COVERAGE: f64 = 0.85;
+
 /// Address of chip with connected temp sensor
 const TEMP_CHIP: ChipAddress = ChipAddress::One(61);
 
+/// I2C interface with power supply (the same one as for voltage controllers of hashboards)
+const PSU_I2C_INTERFACE: usize = 0;
+
 /// Timeout for completion of haschain halt
 const HALT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Time given to solutions found before shutdown to be submitted to pools
+const SOLUTION_DRAIN_PERIOD: Duration = Duration::from_secs(2);
+
+/// Timeout for closing of pool connections on shutdown
+const POOL_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Core address space size (it should be 114, but the addresses are non-consecutive)
 const CORE_ADR_SPACE_SIZE: usize = 128;
 
+/// Number of hardware solutions buffered between the solution RX FIFO and their processing
+const HW_SOLUTION_CHANNEL_CAPACITY: usize = 1024;
+
 /// Power type alias
 /// TODO: Implement it as a proper type (not just alias)
 pub type Power = usize;
@@ -179,6 +209,8 @@ impl ResetPin {
 pub struct HashChain {
     /// Number of chips that have been detected
     chip_count: usize,
+    /// Control of the chips specific to their generation
+    chip_driver: Arc<dyn chip::Driver>,
     /// Eliminates the need to query the IP core about the current number of configured midstates
     midstate_count: MidstateCount,
     /// ASIC difficulty
@@ -194,6 +226,8 @@ pub struct HashChain {
     pub common_io: io::Common,
     work_rx_io: Mutex<Option<io::WorkRx>>,
     work_tx_io: Mutex<Option<io::WorkTx>>,
+    /// Allows to stop sending work to the chips (e.g. during register diagnostics)
+    work_tx_pause: Arc<io::WorkTxPause>,
     monitor_tx: mpsc::UnboundedSender<monitor::Message>,
     /// Do not send open-core work if this is true (some tests that test chip initialization may
     /// want to do this).
@@ -210,6 +244,12 @@ pub struct HashChain {
     halt_receiver: halt::Receiver,
     /// Current hashchain settings
     frequency: Mutex<FrequencySettings>,
+    /// Accounting of nonce space searched by chips
+    pub nonce_coverage: Arc<Mutex<nonce_space::Coverage>>,
+    /// Number of work items currently allowed to be buffered in work TX FIFO
+    pub work_backlog: Arc<AtomicUsize>,
+    /// Optional reduction of frequency when the hashchain is getting hot
+    thermal_throttle: Option<monitor::ThermalThrottleConfig>,
 }
 
 impl HashChain {
@@ -249,6 +289,7 @@ impl HashChain {
 
         Ok(Self {
             chip_count: 0,
+            chip_driver: Arc::new(bm1387::Driver),
             midstate_count,
             asic_difficulty,
             asic_target: ii_bitcoin::Target::from_pool_difficulty(asic_difficulty),
@@ -259,6 +300,7 @@ impl HashChain {
             command_context: command::Context::new(command_io),
             work_rx_io: Mutex::new(Some(work_rx_io)),
             work_tx_io: Mutex::new(Some(work_tx_io)),
+            work_tx_pause: Arc::new(io::WorkTxPause::new()),
             monitor_tx,
             disable_init_work: false,
             temperature_sender: Mutex::new(Some(temperature_sender)),
@@ -270,6 +312,15 @@ impl HashChain {
             halt_sender,
             halt_receiver,
             frequency: Mutex::new(FrequencySettings::from_frequency(0)),
+            nonce_coverage: Arc::new(Mutex::new(nonce_space::Coverage::new(
+                *bm1387::NONCE_PARTITION,
+                0,
+                asic_difficulty,
+                WORK_DELAY_FUDGE,
+                MIN_NONCE_COVERAGE,
+            ))),
+            work_backlog: Arc::new(AtomicUsize::new(0)),
+            thermal_throttle: None,
         })
     }
 
@@ -331,6 +382,14 @@ impl HashChain {
         Ok(())
     }
 
+    /// Turns off the hashboard after it has been stopped
+    async fn power_down(&self) -> error::Result<()> {
+        info!("Hashboard {}: powering down", self.hashboard_idx);
+        self.common_io.disable_ip_core();
+        self.reset_pin.clone().enter_reset()?;
+        self.voltage_ctrl.disable_voltage().await
+    }
+
     /// Leaves reset mode
     fn exit_reset(&mut self) -> error::Result<()> {
         self.reset_pin.exit_reset()?;
@@ -340,26 +399,13 @@ impl HashChain {
 
     /// Configures difficulty globally on all chips within the hashchain
     async fn set_asic_diff(&mut self, difficulty: usize) -> error::Result<()> {
-        let tm_reg = bm1387::TicketMaskReg::new(difficulty as u32)?;
-        trace!(
-            "Setting ticket mask register for difficulty {}, value {:#010x?}",
-            difficulty,
-            tm_reg
-        );
-        self.command_context
-            .write_register_readback(ChipAddress::All, &tm_reg)
-            .await?;
-        Ok(())
+        self.chip_driver
+            .set_ticket_mask(&self.command_context, difficulty)
+            .await
     }
 
-    /// Reset hashboard and try to enumerate the chips.
-    /// If not enough chips were found and `accept_less_chips` is not specified,
-    /// treat it as error.
-    async fn reset_and_enumerate_and_init(
-        &mut self,
-        accept_less_chips: bool,
-        initial_frequency: &FrequencySettings,
-    ) -> error::Result<()> {
+    /// Reset hashboard and enumerate the chips
+    async fn reset_and_enumerate(&mut self) -> error::Result<()> {
         // Reset hashboard, toggle voltage
         info!("Resetting hash board");
         self.enter_reset()?;
@@ -379,6 +425,26 @@ impl HashChain {
         self.command_context.set_chip_count(self.chip_count).await;
         self.counter.lock().await.set_chip_count(self.chip_count);
         self.frequency.lock().await.set_chip_count(self.chip_count);
+        *self.nonce_coverage.lock().await = nonce_space::Coverage::new(
+            *bm1387::NONCE_PARTITION,
+            self.chip_count,
+            self.asic_difficulty,
+            WORK_DELAY_FUDGE,
+            MIN_NONCE_COVERAGE,
+        );
+
+        Ok(())
+    }
+
+    /// Reset hashboard and try to enumerate the chips.
+    /// If not enough chips were found and `accept_less_chips` is not specified,
+    /// treat it as error.
+    async fn reset_and_enumerate_and_init(
+        &mut self,
+        accept_less_chips: bool,
+        initial_frequency: &FrequencySettings,
+    ) -> error::Result<()> {
+        self.reset_and_enumerate().await?;
 
         // If we don't have full number of chips and we do not want incomplete chain, then raise
         // an error
@@ -455,60 +521,40 @@ impl HashChain {
         Ok(work_registry)
     }
 
-    /// Detects the number of chips on the hashing chain and assigns an address to each chip
-    async fn enumerate_chips(&mut self) -> error::Result<()> {
-        // Enumerate all chips (broadcast read address register request)
-        let responses = self
-            .command_context
-            .read_register::<bm1387::GetAddressReg>(ChipAddress::All)
+    /// Powers up the hashboard only to find out how many chips respond and powers it down again.
+    /// The hashchain cannot be started afterwards.
+    async fn probe_chip_count(&mut self) -> error::Result<usize> {
+        let result = self.power_up_and_enumerate().await;
+        let power_down_result = self.power_down().await;
+        // stop heart beat of the voltage controller
+        self.halt_sender.clone().send_halt().await;
+
+        result?;
+        power_down_result?;
+        Ok(self.chip_count)
+    }
+
+    async fn power_up_and_enumerate(&mut self) -> error::Result<()> {
+        self.voltage_ctrl
+            .clone()
+            .init(self.halt_receiver.clone())
             .await?;
+        self.ip_core_init().await?;
+        self.reset_and_enumerate().await
+    }
 
+    /// Detects generation and number of chips on the hashing chain and assigns an address to
+    /// each chip
+    async fn enumerate_chips(&mut self) -> error::Result<()> {
         // Reset chip count (we might get called multiple times)
         self.chip_count = 0;
-        // Check if are responses meaningful
-        for (address, addr_reg) in responses.iter().enumerate() {
-            if addr_reg.chip_rev != bm1387::CHIP_REV_BM1387 {
-                Err(ErrorKind::ChipEnumeration(format!(
-                    "unexpected revision of chip {} (expected: {:#x?} received: {:#x?})",
-                    address,
-                    bm1387::CHIP_REV_BM1387,
-                    addr_reg.chip_rev,
-                )))?
-            }
-            self.chip_count += 1;
-        }
-        if self.chip_count >= MAX_CHIPS_ON_CHAIN {
-            Err(ErrorKind::ChipEnumeration(format!(
-                "detected {} chips, expected less than {} chips on one chain. Possibly a hardware issue?",
-                self.chip_count,
-                MAX_CHIPS_ON_CHAIN,
-            )))?
-        }
-        if self.chip_count == 0 {
-            Err(ErrorKind::ChipEnumeration(
-                "no chips detected on the current chain".to_string(),
-            ))?
-        }
-
-        // Set all chips to be offline before address assignment. This is important so that each
-        // chip after initially accepting the address will pass on further addresses down the chain
-        let inactivate_from_chain_cmd = bm1387::InactivateFromChainCmd::new().pack();
-        // make sure all chips receive inactivation request
-        for _ in 0..3 {
-            self.command_context
-                .send_raw_command(inactivate_from_chain_cmd.to_vec(), false)
-                .await;
-            delay_for(INACTIVATE_FROM_CHAIN_DELAY).await;
-        }
-
-        // Assign address to each chip
-        for i in 0..self.chip_count {
-            let cmd = bm1387::SetChipAddressCmd::new(ChipAddress::One(i));
-            self.command_context
-                .send_raw_command(cmd.pack().to_vec(), false)
-                .await;
-        }
-
+        self.chip_driver = chip::detect(&self.command_context).await?;
+        info!(
+            "chain {}: detected chip generation {:?}",
+            self.hashboard_idx,
+            self.chip_driver.chip_rev()
+        );
+        self.chip_count = self.chip_driver.enumerate(&self.command_context).await?;
         Ok(())
     }
 
@@ -516,23 +562,19 @@ impl HashChain {
     ///
     /// WARNING: you have to take care of `set_work_time` yourself
     async fn set_chip_pll(&self, chip_addr: ChipAddress, freq: usize) -> error::Result<()> {
-        // convert frequency to PLL setting register
-        let pll = bm1387::PllFrequency::lookup_freq(freq)?;
+        let actual_freq = self
+            .chip_driver
+            .set_pll(&self.command_context, chip_addr, freq)
+            .await?;
 
         info!(
             "chain {}: setting frequency {} MHz on {:?} (error {} MHz)",
             self.hashboard_idx,
             freq / 1_000_000,
             chip_addr,
-            ((freq as f64) - (pll.frequency as f64)).abs() / 1_000_000.0,
+            ((freq as f64) - (actual_freq as f64)).abs() / 1_000_000.0,
         );
 
-        // NOTE: When PLL register is read back, it is or-ed with 0x8000_0000, not sure why.
-        //  Avoid reading it back to prevent disappointment.
-        self.command_context
-            .write_register(chip_addr, &pll.reg)
-            .await?;
-
         Ok(())
     }
 
@@ -551,8 +593,8 @@ impl HashChain {
         } else {
             // Update chips one-by-one
             for i in 0..self.chip_count {
-                let new_freq = self.frequency.lock().await.chip[i];
-                if new_freq != frequency.chip[i] {
+                let new_freq = frequency.chip[i];
+                if new_freq != self.frequency.lock().await.chip[i] {
                     self.set_chip_pll(ChipAddress::One(i), new_freq).await?;
                 }
             }
@@ -588,24 +630,9 @@ impl HashChain {
         not_set_baud: bool,
         gate_block: bool,
     ) -> error::Result<usize> {
-        let (baud_clock_div, actual_baud_rate) = calc_baud_clock_div(
-            baud_rate,
-            CHIP_OSC_CLK_HZ,
-            bm1387::CHIP_OSC_CLK_BASE_BAUD_DIV,
-        )?;
-        info!(
-            "Setting Hash chain baud rate @ requested: {}, actual: {}, divisor {:#04x}",
-            baud_rate, actual_baud_rate, baud_clock_div
-        );
-        // Each chip is always configured with inverted clock
-        let ctl_reg =
-            bm1387::MiscCtrlReg::new(not_set_baud, true, baud_clock_div, gate_block, true)?;
-        // Do not read back the MiscCtrl register when setting baud rate: it will result
-        // in serial speed mismatch and nothing being read.
-        self.command_context
-            .write_register(ChipAddress::All, &ctl_reg)
-            .await?;
-        Ok(actual_baud_rate)
+        self.chip_driver
+            .set_baud_rate(&self.command_context, baud_rate, not_set_baud, gate_block)
+            .await
     }
 
     /// This method only changes the communication speed of the FPGA IP core with the chips.
@@ -628,18 +655,60 @@ impl HashChain {
         self.chip_count
     }
 
+    /// Read back all known registers of chip(s) for diagnostics
+    pub async fn read_registers(
+        &self,
+        chip_address: ChipAddress,
+    ) -> error::Result<Vec<chip::RegisterValue>> {
+        let _pause = self.work_tx_pause.pause().await;
+        self.chip_driver
+            .read_registers(&self.command_context, chip_address)
+            .await
+    }
+
+    /// Read raw value of register `reg_num` of chip(s) for diagnostics.
+    /// No work is sent to the chain while the register is being accessed.
+    pub async fn read_raw_register(
+        &self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+    ) -> error::Result<Vec<u32>> {
+        let _pause = self.work_tx_pause.pause().await;
+        self.command_context
+            .read_raw_register(chip_address, reg_num)
+            .await
+    }
+
+    /// Write raw value to register `reg_num` of chip(s) for diagnostics.
+    /// No work is sent to the chain while the register is being accessed.
+    pub async fn write_raw_register(
+        &self,
+        chip_address: ChipAddress,
+        reg_num: u8,
+        value: u32,
+    ) -> error::Result<()> {
+        let _pause = self.work_tx_pause.pause().await;
+        warn!(
+            "Hashboard {}: diagnostic write of register {:#04x} of chip {:?}: {:#010x}",
+            self.hashboard_idx, reg_num, chip_address, value
+        );
+        self.command_context
+            .write_raw_register(chip_address, reg_num, value)
+            .await
+    }
+
     /// Initialize cores by sending open-core work with correct nbits to each core
     async fn send_init_work(&mut self, work_registry: Arc<Mutex<registry::WorkRegistry>>) {
         // Each core gets one work
-        const NUM_WORK: usize = bm1387::NUM_CORES_ON_CHIP;
+        let num_work = self.chip_driver.core_count();
         trace!(
             "Sending out {} pieces of dummy work to initialize chips",
-            NUM_WORK
+            num_work
         );
         let midstate_count = self.midstate_count.to_count();
         let mut work_tx_io = self.work_tx_io.lock().await;
         let tx_fifo = work_tx_io.as_mut().expect("tx fifo missing");
-        for _ in 0..NUM_WORK {
+        for _ in 0..num_work {
             let work = &null_work::prepare_opencore(true, midstate_count);
             // store work to registry as "initial work" so that later we can properly ignore
             // solutions
@@ -653,34 +722,65 @@ impl HashChain {
     /// registry (to pair with `Assignment` later) and sends it out to hw.
     /// It makes sure that TX fifo is empty before requesting work from
     /// generator.
+    /// The number of work items buffered in TX fifo is adapted to the rate at
+    /// which the hash chain consumes work (see `io::Backlog`).
     /// It exits when generator returns `None`.
     async fn work_tx_task(
         work_registry: Arc<Mutex<registry::WorkRegistry>>,
         mut tx_fifo: io::WorkTx,
         mut work_generator: work::Generator,
+        work_tx_pause: Arc<io::WorkTxPause>,
+        nonce_coverage: Arc<Mutex<nonce_space::Coverage>>,
+        work_backlog: Arc<AtomicUsize>,
     ) {
+        let mut backlog = io::Backlog::new(tx_fifo.max_backlog(), Instant::now());
+        tx_fifo.set_backlog(backlog.depth());
+        work_backlog.store(backlog.depth(), Ordering::Relaxed);
         loop {
             tx_fifo.wait_for_room().await.expect("wait for tx room");
+            let generate_start = Instant::now();
             let work = work_generator.generate().await;
             match work {
                 None => return,
                 Some(work) => {
+                    // empty fifo means that the chips have been waiting for work unless the
+                    // generator itself has been waiting for a new job
+                    let now = Instant::now();
+                    let starved = tx_fifo.is_empty()
+                        && now.saturating_duration_since(generate_start) < io::Backlog::LATENCY;
                     // assign `work_id` to `work`
                     let work_id = work_registry.lock().await.store_work(work.clone(), false);
                     // send work is synchronous
-                    tx_fifo.send_work(&work, work_id).expect("send work");
+                    work_tx_pause
+                        .send(|| tx_fifo.send_work(&work, work_id))
+                        .await
+                        .expect("send work");
+                    // The chips are expected to return nonces for the whole sent work
+                    if let Some(ratio) = nonce_coverage.lock().await.work_sent(work.midstates.len())
+                    {
+                        if ratio < MIN_NONCE_COVERAGE {
+                            warn!(
+                                "Hash chain returned only {:.1}% of expected nonces",
+                                ratio * 100.0
+                            );
+                        }
+                    }
+
+                    backlog.account_sent(starved);
+                    if let Some(depth) = backlog.update(now) {
+                        trace!("Work backlog changed to {} items", depth);
+                        tx_fifo.set_backlog(depth);
+                        work_backlog.store(depth, Ordering::Relaxed);
+                    }
                 }
             }
         }
     }
 
-    /// This task receives solutions from hardware, looks up `Assignment` in
-    /// registry (under `work_id` got from FPGA), pairs them together and
-    /// sends them back to frontend (via `solution_sender`).
-    /// If solution is duplicated, it gets dropped (and errors stats incremented).
-    /// It prints warnings when solution doesn't hit ASIC target.
-    /// TODO: this task is not very platform dependent, maybe move it somewhere else?
-    /// TODO: figure out when and how to stop this task
+    /// This task receives solutions from hardware and passes them to the processing task through
+    /// a lock-free channel. The FIFO is thus drained even when the processing waits for the work
+    /// registry that is shared with the work TX task.
+    /// The processing task stops when this task is dropped.
     async fn solution_rx_task(
         self: Arc<Self>,
         work_registry: Arc<Mutex<registry::WorkRegistry>>,
@@ -688,11 +788,40 @@ impl HashChain {
         solution_sender: work::SolutionSender,
         counter: Arc<Mutex<counters::HashChain>>,
     ) {
-        // solution receiving/filtering part
+        let (mut hw_solution_sender, hw_solution_receiver) =
+            sync::spsc::channel(HW_SOLUTION_CHANNEL_CAPACITY);
+        tokio::spawn(self.solution_process_task(
+            work_registry,
+            hw_solution_receiver,
+            solution_sender,
+            counter,
+        ));
         loop {
             let (rx_fifo_out, hw_solution) =
                 rx_fifo.recv_solution().await.expect("recv solution failed");
             rx_fifo = rx_fifo_out;
+            if hw_solution_sender.send(hw_solution).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// This task looks up `Assignment` in registry (under `work_id` got from FPGA) for each
+    /// hardware solution, pairs them together and sends them back to frontend (via
+    /// `solution_sender`).
+    /// If solution is duplicated (the same nonce for the same midstate), it gets dropped (and errors
+    /// stats incremented).
+    /// It prints warnings when solution doesn't hit ASIC target.
+    /// TODO: this task is not very platform dependent, maybe move it somewhere else?
+    async fn solution_process_task(
+        self: Arc<Self>,
+        work_registry: Arc<Mutex<registry::WorkRegistry>>,
+        mut hw_solution_receiver: sync::spsc::Receiver<io::Solution>,
+        solution_sender: work::SolutionSender,
+        counter: Arc<Mutex<counters::HashChain>>,
+    ) {
+        // solution receiving/filtering part
+        while let Some(hw_solution) = hw_solution_receiver.recv().await {
             let work_id = hw_solution.hardware_id;
             let solution = Solution::from_hw_solution(&hw_solution, self.asic_target);
             let mut work_registry = work_registry.lock().await;
@@ -704,18 +833,20 @@ impl HashChain {
                     if work_item.initial_work {
                         continue;
                     }
-                    let core_addr = bm1387::CoreAddress::new(solution.nonce);
+                    let nonce = solution.nonce;
+                    let core_addr = bm1387::CoreAddress::new(nonce);
                     let status = work_item.insert_solution(solution);
 
                     // work item detected a new unique solution, we will push it for further processing
                     if let Some(unique_solution) = status.unique_solution {
                         if !status.duplicate {
                             let hash = unique_solution.hash();
-                            if !hash.meets(unique_solution.backend_target()) {
+                            if unique_solution.is_hardware_error() {
                                 info!("Solution from hashchain not hitting ASIC target; {}", hash);
                                 counter.lock().await.add_error(core_addr);
                             } else {
                                 counter.lock().await.add_valid(core_addr);
+                                self.nonce_coverage.lock().await.nonce_found(nonce);
                             }
                             solution_sender.send(unique_solution);
                         }
@@ -766,6 +897,60 @@ impl HashChain {
         Ok(sensor)
     }
 
+    /// Apply decision of thermal throttle to hashchain frequency and voltage
+    ///
+    /// * `nominal` - frequency and voltage before throttling has started
+    async fn apply_thermal_throttle(
+        &self,
+        thermal_throttle: &mut monitor::ThermalThrottle,
+        nominal: &mut Option<(FrequencySettings, power::Voltage)>,
+        temp: sensor::Temperature,
+    ) {
+        match thermal_throttle.update(monitor::ChainTemperature::from_s9_sensor(temp)) {
+            monitor::ThrottleDecision::Keep => {}
+            monitor::ThrottleDecision::Insufficient => warn!(
+                "Hashchain {}: thermal throttling is insufficient at minimal frequency",
+                self.hashboard_idx
+            ),
+            monitor::ThrottleDecision::SetRatio(ratio) => {
+                if nominal.is_none() {
+                    nominal.replace((self.get_frequency().await, self.get_voltage().await));
+                }
+                let (nominal_frequency, nominal_voltage) =
+                    nominal.as_ref().expect("BUG: missing nominal frequency");
+                let frequency = nominal_frequency.scale(ratio);
+                let voltage = thermal_throttle.voltage(*nominal_voltage);
+                info!(
+                    "Hashchain {}: thermal throttle sets frequency to {} ({:.0}%) at {:.2} V",
+                    self.hashboard_idx,
+                    frequency,
+                    ratio * 100.0,
+                    voltage.as_volts()
+                );
+                // Chips have to be slowed down before the voltage is lowered and the voltage
+                // has to be raised before they are sped up again
+                let lowering = frequency.total() < self.get_frequency().await.total();
+                if !lowering {
+                    if let Err(e) = self.voltage_ctrl.set_voltage(voltage).await {
+                        error!("Thermal throttle failed to set voltage: {}", e);
+                    }
+                }
+                if let Err(e) = self.set_pll(&frequency).await {
+                    error!("Thermal throttle failed to set frequency: {}", e);
+                }
+                if lowering {
+                    if let Err(e) = self.voltage_ctrl.set_voltage(voltage).await {
+                        error!("Thermal throttle failed to set voltage: {}", e);
+                    }
+                }
+                if thermal_throttle.ratio() >= 1.0 {
+                    // Throttling is over and user may change the frequency again
+                    nominal.take();
+                }
+            }
+        }
+    }
+
     /// Monitor watchdog task.
     /// This task sends periodically ping to monitor task. It also tries to read temperature.
     async fn monitor_watchdog_temp_task(self: Arc<Self>) {
@@ -804,6 +989,9 @@ impl HashChain {
             error::Result::Ok(sensor) => Some(sensor),
         };
 
+        let mut thermal_throttle = self.thermal_throttle.map(monitor::ThermalThrottle::new);
+        let mut nominal = None;
+
         // "Watchdog" loop that pings monitor every some seconds
         loop {
             // If we have temperature sensor, try to read it
@@ -830,6 +1018,11 @@ impl HashChain {
                 sensor::INVALID_TEMPERATURE_READING
             };
 
+            if let Some(thermal_throttle) = thermal_throttle.as_mut() {
+                self.apply_thermal_throttle(thermal_throttle, &mut nominal, temp.clone())
+                    .await;
+            }
+
             // Broadcast
             temperature_sender
                 .broadcast(Some(temp.clone()))
@@ -887,6 +1080,9 @@ impl HashChain {
                 work_registry.clone(),
                 tx_fifo,
                 work_generator,
+                self.work_tx_pause.clone(),
+                self.nonce_coverage.clone(),
+                self.work_backlog.clone(),
             ));
 
         // spawn rx task
@@ -936,6 +1132,11 @@ impl HashChain {
             .await
             .expect("BUG: no voltage on hashchain")
     }
+
+    /// Voltage controller of the hashboard for runtime voltage scaling
+    pub fn voltage_controller(&self) -> Arc<dyn power::VoltageController> {
+        self.voltage_ctrl.clone()
+    }
 }
 
 impl fmt::Debug for HashChain {
@@ -984,6 +1185,17 @@ impl FrequencySettings {
         *self.chip.iter().max().expect("BUG: no chips on chain")
     }
 
+    /// Build frequency settings with all chip frequencies scaled by `ratio`
+    pub fn scale(&self, ratio: f32) -> Self {
+        Self {
+            chip: self
+                .chip
+                .iter()
+                .map(|&frequency| (frequency as f32 * ratio) as usize)
+                .collect(),
+        }
+    }
+
     pub fn avg(&self) -> usize {
         assert!(self.chip.len() > 0, "BUG: no chips on chain");
         let sum: u64 = self.chip.iter().map(|frequency| *frequency as u64).sum();
@@ -1131,6 +1343,143 @@ impl RunningChain {
         }
     }
 
+    /// Hash chain which is running under the same start as this one or `None` when it has
+    /// been stopped in the meantime
+    pub async fn hash_chain(&self) -> Option<Arc<HashChain>> {
+        let inner = self.manager.inner.lock().await;
+        if inner.start_count != self.start_id {
+            return None;
+        }
+        inner.hash_chain.clone()
+    }
+
+    /// Tune and supervise the running hashchain until the stop is requested. The hashchain is
+    /// stopped whenever it reaches the cutoff temperature and started again with the configured
+    /// frequency and voltage once it has cooled down. It is also restarted when the watchdog
+    /// detects that it has stopped returning nonces. Tuning starts over after each restart.
+    pub async fn control(mut self, config: config::ResolvedChainConfig, mut stop: ControlStop) {
+        let hashboard_idx = self.manager.hashboard_idx;
+        loop {
+            let restart_delay = match self.tune_and_supervise(&config, &mut stop).await {
+                Some(restart_delay) => restart_delay,
+                None => return,
+            };
+
+            let asic_difficulty = self.asic_difficulty;
+            let stopped_chain = self.stop().await;
+            if !stop.delay_for(restart_delay).await {
+                info!("Hashchain {}: restart cancelled", hashboard_idx);
+                return;
+            }
+
+            info!("Hashchain {}: restarting", hashboard_idx);
+            self = match stopped_chain
+                .start(&config.frequency, config.voltage, asic_difficulty)
+                .await
+            {
+                Ok(chain) => chain,
+                Err((_, e)) => {
+                    error!("Hashchain {}: restart failed: {}", hashboard_idx, e);
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Run tuning alongside the supervision so that the hashchain is protected while it is
+    /// being tuned. Returns the delay after which the hashchain has to be restarted or `None`
+    /// when the control is finished.
+    async fn tune_and_supervise(
+        &self,
+        config: &config::ResolvedChainConfig,
+        stop: &mut ControlStop,
+    ) -> Option<Duration> {
+        if config.thermal_cutoff.is_none() && config.watchdog_timeout.is_none() {
+            if let Some(tuner_config) = config.tuner.clone() {
+                tuner::Tuner::run(tuner_config, self, stop).await;
+            }
+            return None;
+        }
+
+        // Tuning is stopped by the supervision before the hashchain is restarted
+        let (tuner_stop_tx, tuner_stop_rx) = watch::channel(false);
+        let mut tuner_stop = ControlStop(tuner_stop_rx);
+        let tuning = async move {
+            if let Some(tuner_config) = config.tuner.clone() {
+                tuner::Tuner::run(tuner_config, self, &mut tuner_stop).await;
+            }
+        };
+        let supervision = async move {
+            let restart_delay = self
+                .supervise(
+                    config.thermal_cutoff.as_ref(),
+                    config.watchdog_timeout,
+                    stop,
+                )
+                .await;
+            let _ = tuner_stop_tx.broadcast(true);
+            restart_delay
+        };
+        join(tuning, supervision).await.1
+    }
+
+    /// Check the running hashchain until it reaches the cutoff temperature or the watchdog
+    /// detects that it has stopped returning nonces. Returns the delay after which the
+    /// hashchain has to be restarted or `None` when the supervision is finished.
+    async fn supervise(
+        &self,
+        thermal_cutoff: Option<&monitor::ThermalCutoffConfig>,
+        watchdog_timeout: Option<Duration>,
+        stop: &mut ControlStop,
+    ) -> Option<Duration> {
+        let hashboard_idx = self.manager.hashboard_idx;
+        let mut watchdog =
+            watchdog_timeout.map(|timeout| monitor::ChainWatchdog::new(timeout, Instant::now()));
+        loop {
+            if !stop.delay_for(CHAIN_CHECK_PERIOD).await {
+                return None;
+            }
+            let hash_chain = self.hash_chain().await?;
+
+            if let Some(config) = thermal_cutoff {
+                if let Some(temp) = hash_chain.current_temperature() {
+                    let temp = monitor::ChainTemperature::from_s9_sensor(temp);
+                    if config.is_overheated(temp) {
+                        warn!(
+                            "Hashchain {}: temperature {:?} reached cutoff, stopping for {} s",
+                            hashboard_idx,
+                            temp,
+                            config.cooldown.as_secs()
+                        );
+                        return Some(config.cooldown);
+                    }
+                }
+            }
+            if let Some(watchdog) = watchdog.as_mut() {
+                let counter = hash_chain.snapshot_counter().await;
+                let work = *self
+                    .manager
+                    .work_solver_stats
+                    .generated_work
+                    .take_snapshot();
+                if watchdog.update(counter.valid + counter.errors, work, Instant::now()) {
+                    error!(
+                        "Hashchain {}: no nonces returned for {} s (valid {}, errors {}, \
+                         frequency {}, voltage {:.2} V, temperature {:?}), restarting",
+                        hashboard_idx,
+                        watchdog.timeout().as_secs(),
+                        counter.valid,
+                        counter.errors,
+                        hash_chain.get_frequency().await,
+                        hash_chain.get_voltage().await.as_volts(),
+                        hash_chain.current_temperature()
+                    );
+                    return Some(Duration::from_secs(0));
+                }
+            }
+        }
+    }
+
     /// TODO: for the love of god use macros or something
     pub async fn get_frequency(&self) -> FrequencySettings {
         let inner = self.manager.inner.lock().await;
@@ -1287,6 +1636,46 @@ pub struct ManagerInner {
     pub hash_chain: Option<Arc<HashChain>>,
     /// Each (attempted) hashchain start increments this counter by 1
     pub start_count: usize,
+    /// Number of chips detected when the hashchain was started the last time
+    pub chip_count: Option<usize>,
+}
+
+/// Request to stop tuning and supervision of a hashchain. The task checks it between steps so
+/// that it never leaves the hashchain in the middle of start or settings change.
+#[derive(Clone)]
+pub struct ControlStop(watch::Receiver<bool>);
+
+impl ControlStop {
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Sleep for `duration` unless the stop is requested in the meantime.
+    /// Returns `false` when the sleep has been interrupted.
+    pub async fn delay_for(&mut self, duration: Duration) -> bool {
+        let receiver = &mut self.0;
+        let stop = async move {
+            loop {
+                match receiver.recv().await {
+                    Some(false) => continue,
+                    // dropped sender is also a request to stop
+                    Some(true) | None => break,
+                }
+            }
+        };
+        match select(delay_for(duration).boxed(), stop.boxed()).await {
+            Either::Left(_) => true,
+            Either::Right(_) => false,
+        }
+    }
+}
+
+/// Tuning and supervision task of a running hashchain
+struct ChainControl {
+    /// Request to stop the task
+    stop_tx: watch::Sender<bool>,
+    /// Closed when the task has finished and released the ownership of the hashchain
+    done_rx: oneshot::Receiver<()>,
 }
 
 /// Hashchain manager that can start and stop instances of hashchain
@@ -1310,9 +1699,54 @@ pub struct Manager {
     owned_by: StdMutex<Option<&'static str>>,
     pub inner: Mutex<ManagerInner>,
     pub chain_config: config::ResolvedChainConfig,
+    /// Settings of the active performance profile used for hashchain start and its tuning.
+    /// Power target of the tuner is shared by all enabled hashboards.
+    active_config: StdMutex<config::ResolvedChainConfig>,
+    /// All configured performance profiles resolved for this hashchain
+    profiles: BTreeMap<String, config::ResolvedChainConfig>,
+    control: Mutex<Option<ChainControl>>,
+    /// Power supply shared by all hashboards which is able to measure its input power
+    psu: Option<Arc<psu::Psu>>,
+    /// Hashboards currently running from the power supply, shared by all managers
+    psu_load: Arc<psu::Load>,
 }
 
 impl Manager {
+    /// Name of the current owner of the hashchain
+    pub fn owner(&self) -> Option<&'static str> {
+        *self.owned_by.lock().expect("BUG: failed to lock mutex")
+    }
+
+    /// Name of the active performance profile
+    pub fn active_profile(&self) -> Option<String> {
+        self.active_config
+            .lock()
+            .expect("BUG: failed to lock mutex")
+            .profile
+            .clone()
+    }
+
+    /// Names of all performance profiles available for this hashchain
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Measured power consumption of the hashboard in watts. When its voltage controller is not
+    /// able to measure it, the input power of the power supply is split evenly among the running
+    /// hashboards. Returns `None` when there's no power meter.
+    pub async fn measured_power(
+        &self,
+        controller: &dyn power::VoltageController,
+    ) -> error::Result<Option<f64>> {
+        if let Some(power) = controller.power().await? {
+            return Ok(Some(power));
+        }
+        match self.psu.as_ref() {
+            Some(psu) => Ok(self.psu_load.hashboard_power(&psu.read().await?)),
+            None => Ok(None),
+        }
+    }
+
     /// Acquire stopped or running chain
     pub async fn acquire(
         self: Arc<Self>,
@@ -1370,6 +1804,7 @@ impl Manager {
             self.monitor_tx.clone(),
         )
         .expect("BUG: hashchain instantiation failed");
+        hash_chain.thermal_throttle = self.chain_config.thermal_throttle;
 
         // initialize it
         let work_registry = match hash_chain
@@ -1401,11 +1836,50 @@ impl Manager {
             .await;
 
         // remember we started
+        inner.chip_count = Some(hash_chain.chip_count);
         inner.hash_chain.replace(hash_chain);
+        self.psu_load.attach();
 
         Ok(())
     }
 
+    /// Find out how many chips respond on the hashboard before the hashchain is started for the
+    /// first time. The number of chips is updated on each start of the hashchain.
+    pub async fn probe_chip_count(&self) -> error::Result<usize> {
+        // lock inner so that the hashchain cannot be started in the meantime
+        let mut inner = self.inner.lock().await;
+        if inner.hash_chain.is_some() {
+            Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                "cannot probe running hashchain".to_string(),
+            ))?
+        }
+
+        // hashboard is powered during the probe so let the monitor know about it
+        self.monitor_tx
+            .unbounded_send(monitor::Message::On)
+            .expect("BUG: send failed");
+        let result = match HashChain::new(
+            self.reset_pin.clone(),
+            self.plug_pin.clone(),
+            self.voltage_ctrl_backend.clone(),
+            self.hashboard_idx,
+            self.midstate_count,
+            config::DEFAULT_ASIC_DIFFICULTY,
+            self.monitor_tx.clone(),
+        ) {
+            Ok(mut hash_chain) => hash_chain.probe_chip_count().await,
+            Err(e) => Err(e),
+        };
+        self.monitor_tx
+            .unbounded_send(monitor::Message::Off)
+            .expect("BUG: send failed");
+
+        let chip_count = result?;
+        inner.chip_count = Some(chip_count);
+        Ok(chip_count)
+    }
+
     /// TODO: this function is private and should be called only from `RunningChain`
     async fn stop_chain(&self, its_ok_if_its_missing: bool) {
         // lock inner to guarantee atomicity of hashchain stop
@@ -1420,6 +1894,7 @@ impl Manager {
 
         // stop everything
         hash_chain.halt_sender.clone().send_halt().await;
+        self.psu_load.detach();
 
         // tell monitor we are done
         self.monitor_tx
@@ -1430,6 +1905,152 @@ impl Manager {
     async fn termination_handler(self: Arc<Self>) {
         self.stop_chain(true).await;
     }
+
+    /// Start hashchain with initial settings from configuration and spawn its tuning and
+    /// supervision. They keep ownership of the hashchain until they finish or until
+    /// `stop_controlled` is called.
+    pub async fn start_controlled(self: Arc<Self>, owner_name: &'static str) -> error::Result<()> {
+        let chain = match self.clone().acquire(owner_name).await {
+            Ok(ChainStatus::Stopped(chain)) => chain,
+            Ok(ChainStatus::Running(_)) => Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                "already running".to_string(),
+            ))?,
+            Err(owner) => Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                format!("controlled by {}", owner),
+            ))?,
+        };
+        let active_config = self
+            .active_config
+            .lock()
+            .expect("BUG: failed to lock mutex")
+            .clone();
+        let chain = chain
+            .start(
+                &active_config.frequency,
+                active_config.voltage,
+                config::DEFAULT_ASIC_DIFFICULTY,
+            )
+            .await
+            .map_err(|(_, e)| e)?;
+
+        self.spawn_control(chain, active_config).await;
+        Ok(())
+    }
+
+    /// Spawn tuning and supervision of running hashchain with settings from `active_config`
+    async fn spawn_control(&self, chain: RunningChain, active_config: config::ResolvedChainConfig) {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let stop = ControlStop(stop_rx);
+        let control = chain.control(active_config, stop);
+
+        let (done_tx, done_rx) = oneshot::channel::<()>();
+        self.control
+            .lock()
+            .await
+            .replace(ChainControl { stop_tx, done_rx });
+        tokio::spawn(async move {
+            // the control is never cancelled in the middle of an operation, it finishes on its
+            // own once it notices the stop request and drops the running chain
+            control.await;
+            drop(done_tx);
+        });
+    }
+
+    /// Switch to performance profile `profile_name` without restart of the hashchain. Tuning is
+    /// restarted with the new settings when the hashchain is running, otherwise the profile is
+    /// used for the next start.
+    pub async fn set_profile(
+        self: Arc<Self>,
+        profile_name: &str,
+        owner_name: &'static str,
+    ) -> error::Result<()> {
+        let profile_config = self.profiles.get(profile_name).cloned().ok_or_else(|| {
+            ErrorKind::Hashboard(
+                self.hashboard_idx,
+                format!("unknown profile '{}'", profile_name),
+            )
+        })?;
+
+        self.halt_control().await;
+        let status = match self.clone().acquire(owner_name).await {
+            Ok(status) => status,
+            Err(owner) => Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                format!("controlled by {}", owner),
+            ))?,
+        };
+        *self
+            .active_config
+            .lock()
+            .expect("BUG: failed to lock mutex") = profile_config.clone();
+        info!(
+            "Hashboard {}: switching to profile '{}'",
+            self.hashboard_idx, profile_name
+        );
+
+        match status {
+            ChainStatus::Running(chain) => {
+                // The chips must never be undervolted: raise the voltage before the frequency
+                // when switching to a more aggressive profile and lower it only after the
+                // frequency when switching to a less aggressive one
+                let result = if profile_config.voltage < chain.get_voltage().await {
+                    match chain.set_frequency(&profile_config.frequency).await {
+                        Ok(_) => chain.set_voltage(profile_config.voltage).await,
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    match chain.set_voltage(profile_config.voltage).await {
+                        Ok(_) => chain.set_frequency(&profile_config.frequency).await,
+                        Err(e) => Err(e),
+                    }
+                };
+                // keep the hashchain under control even if the settings couldn't be applied
+                self.spawn_control(chain, profile_config).await;
+                result
+            }
+            ChainStatus::Stopped(_) => Ok(()),
+        }
+    }
+
+    /// Terminate tuning and supervision of the hashchain and wait until they release its
+    /// ownership
+    async fn halt_control(&self) {
+        let control = self.control.lock().await.take();
+        if let Some(control) = control {
+            // the task may have already finished on its own
+            let _ = control.stop_tx.broadcast(true);
+            let _ = control.done_rx.await;
+        }
+    }
+
+    async fn control_termination_handler(self: Arc<Self>) {
+        self.halt_control().await;
+    }
+
+    /// Stop tuning and supervision of the hashchain, stop mining on it and power it down.
+    /// The hashchain can be started again with `start_controlled`.
+    pub async fn stop_controlled(self: Arc<Self>, owner_name: &'static str) -> error::Result<()> {
+        self.halt_control().await;
+        let chain = match self.clone().acquire(owner_name).await {
+            Ok(ChainStatus::Running(chain)) => chain,
+            Ok(ChainStatus::Stopped(_)) => Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                "not running".to_string(),
+            ))?,
+            Err(owner) => Err(ErrorKind::Hashboard(
+                self.hashboard_idx,
+                format!("controlled by {}", owner),
+            ))?,
+        };
+        let hash_chain = chain
+            .hash_chain()
+            .await
+            .expect("BUG: hashchain is not running");
+        chain.stop().await;
+        hash_chain.power_down().await
+    }
 }
 
 #[async_trait]
@@ -1443,7 +2064,8 @@ impl node::WorkSolver for Manager {
         match inner.hash_chain.as_ref() {
             Some(hash_chain) => {
                 let freq_sum = hash_chain.frequency.lock().await.total();
-                Some(((freq_sum as u128) * (bm1387::NUM_CORES_ON_CHIP as u128)).into())
+                let core_count = hash_chain.chip_driver.core_count();
+                Some(((freq_sum as u128) * (core_count as u128)).into())
             }
             None => None,
         }
@@ -1524,8 +2146,7 @@ impl Backend {
     /// Enumerate present hashboards by querying the plug pin
     pub fn detect_hashboards(gpio_mgr: &gpio::ControlPinManager) -> error::Result<Vec<usize>> {
         let mut detected = vec![];
-        // TODO: configure this range somewhere
-        for hashboard_idx in 1..=8 {
+        for hashboard_idx in 1..=MAX_HASHBOARDS {
             let plug_pin = PlugPin::open(gpio_mgr, hashboard_idx)?;
             if plug_pin.hashboard_present()? {
                 detected.push(hashboard_idx);
@@ -1540,6 +2161,90 @@ impl Backend {
         halt_sender.send_halt().await;
     }
 
+    /// Pool termination handler called when app is shutdown after the hashchains are stopped.
+    /// Let the in-flight solutions be submitted and then close all pool connections.
+    async fn pool_termination_handler(client_manager: client::Manager) {
+        delay_for(SOLUTION_DRAIN_PERIOD).await;
+        info!("Disconnecting from pools");
+        client_manager.stop_all(POOL_STOP_TIMEOUT).await;
+    }
+
+    /// Reload pool configuration from configuration file on every `SIGHUP` without touching
+    /// the hash chains. The signal is the only trigger, reloading is not exposed through the
+    /// cgminer API which has no access to the configuration file.
+    async fn reload_handler(
+        config_path: String,
+        cli_pools: bool,
+        client_manager: client::Manager,
+        backend_info: Option<hal::BackendInfo>,
+    ) {
+        let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("Cannot handle SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            if cli_pools {
+                warn!(
+                    "Pools are set on command line, ignoring pools located at \"{}\"",
+                    config_path
+                );
+                continue;
+            }
+            info!("Reloading pool configuration from \"{}\"", config_path);
+            let backend_config =
+                match config::FormatWrapper::<config::Backend>::parse_body(&config_path) {
+                    Ok(backend_config) => backend_config,
+                    Err(e) => {
+                        error!("Cannot load configuration file: {}", e);
+                        continue;
+                    }
+                };
+            // Configuration without any pool would stop mining so keep the current pools
+            if !backend_config.has_pools() {
+                error!("No pools specified, keeping the current ones");
+                continue;
+            }
+            if let Err(e) = client_manager
+                .reload_config(
+                    backend_config.groups,
+                    backend_info.as_ref(),
+                    config::DEFAULT_POOL_ENABLED,
+                )
+                .await
+            {
+                error!("Cannot reload pool configuration: {}", e);
+            }
+        }
+    }
+
+    /// Open power supply with PMBus interface and check that it responds. The miner runs
+    /// without power metering when the power supply is not present.
+    async fn open_psu() -> Option<Arc<psu::Psu>> {
+        let psu = match psu::Psu::open(PSU_I2C_INTERFACE, psu::DEFAULT_ADDRESS) {
+            Ok(psu) => psu,
+            Err(e) => {
+                warn!("Cannot open PSU: {}", e);
+                return None;
+            }
+        };
+        match psu.read().await {
+            Ok(readout) => {
+                info!("PSU input: {}", readout);
+                Some(Arc::new(psu))
+            }
+            Err(e) => {
+                warn!(
+                    "PSU does not respond over PMBus, power metering disabled: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
     /// Start miner
     /// TODO: maybe think about having a `Result` error value here?
     async fn start_miner(
@@ -1547,6 +2252,7 @@ impl Backend {
         enabled_chains: Vec<usize>,
         work_hub: work::SolverBuilder<Backend>,
         backend_config: config::Backend,
+        psu: Option<Arc<psu::Psu>>,
         app_halt_receiver: halt::Receiver,
         app_halt_sender: Arc<halt::Sender>,
     ) -> (Vec<Arc<Manager>>, Arc<monitor::Monitor>) {
@@ -1582,7 +2288,24 @@ impl Backend {
         .await;
         hooks.monitor_started(monitor.clone()).await;
 
+        // Per-chain configuration of empty connectors is ignored
+        for hashboard_idx in backend_config.configured_hash_chains() {
+            if !enabled_chains.contains(&hashboard_idx) {
+                warn!(
+                    "Hashboard {} is configured but it is not present, ignoring its configuration",
+                    hashboard_idx
+                );
+            }
+        }
+
+        // power budget of tuners is shared by all enabled hashboards
+        let enabled_count = enabled_chains
+            .iter()
+            .filter(|hashboard_idx| backend_config.resolve_chain_config(**hashboard_idx).enabled)
+            .count();
+
         let voltage_ctrl_backend = Arc::new(power::I2cBackend::new(0));
+        let psu_load = Arc::new(psu::Load::default());
         let mut managers = Vec::new();
         info!(
             "Initializing miner, enabled_chains={:?}, midstate_count={}",
@@ -1597,6 +2320,24 @@ impl Backend {
             let chain_config = backend_config.resolve_chain_config(hashboard_idx);
 
             let status_receiver = monitor.status_receiver.clone();
+            let resolve_profile = |profile_name: Option<&str>| {
+                let mut config =
+                    backend_config.resolve_chain_config_for_profile(hashboard_idx, profile_name);
+                config.tuner = config
+                    .tuner
+                    .take()
+                    .map(|config| config.share_power_target(enabled_count));
+                config
+            };
+            let active_config = resolve_profile(backend_config.active_profile());
+            let profiles = backend_config
+                .profile_names()
+                .into_iter()
+                .map(|profile_name| {
+                    let config = resolve_profile(Some(profile_name.as_str()));
+                    (profile_name, config)
+                })
+                .collect();
 
             // build hashchain_node for statistics and static parameters
             let manager = work_hub
@@ -1622,23 +2363,50 @@ impl Backend {
                         inner: Mutex::new(ManagerInner {
                             hash_chain: None,
                             start_count: 0,
+                            chip_count: None,
                         }),
                         chain_config,
+                        active_config: StdMutex::new(active_config),
+                        profiles,
+                        control: Mutex::new(None),
+                        psu: psu.clone(),
+                        psu_load: psu_load.clone(),
                     }
                 })
                 .await;
             managers.push(manager);
         }
 
+        // Probe all enabled hashboards at once so that the number of chips is known before
+        // the hashchains are started
+        let probes = managers
+            .iter()
+            .filter(|manager| manager.chain_config.enabled)
+            .map(
+                |manager| async move { (manager.hashboard_idx, manager.probe_chip_count().await) },
+            );
+        for (hashboard_idx, result) in futures::future::join_all(probes).await {
+            match result {
+                Ok(chip_count) => info!(
+                    "Hashboard {}: {} chips responding",
+                    hashboard_idx, chip_count
+                ),
+                Err(e) => warn!("Hashboard {}: chip probe failed: {}", hashboard_idx, e),
+            }
+        }
+
         // start everything
         for manager in managers.iter() {
-            let halt_receiver = halt_receiver.clone();
             let manager = manager.clone();
-
-            let initial_frequency = manager.chain_config.frequency.clone();
-            let initial_voltage = manager.chain_config.voltage;
             let hooks = hooks.clone();
 
+            // Tuning and supervision have to be terminated before the hashchain is stopped,
+            // otherwise they could start it again
+            halt_receiver
+                .register_client("hashchain control".into())
+                .await
+                .spawn_halt_handler(Manager::control_termination_handler(manager.clone()));
+
             // Register handler to stop hashchain when miner is stopped
             halt_receiver
                 .register_client("hashchain".into())
@@ -1650,15 +2418,7 @@ impl Backend {
             if hooks.can_start_chain(manager.clone()).await {
                 tokio::spawn(async move {
                     manager
-                        .acquire("main")
-                        .await
-                        .expect("BUG: failed to acquire hashchain")
-                        .expect_stopped()
-                        .start(
-                            &initial_frequency,
-                            initial_voltage,
-                            config::DEFAULT_ASIC_DIFFICULTY,
-                        )
+                        .start_controlled("main")
                         .await
                         .expect("BUG: failed to start hashchain");
                 });
@@ -1693,20 +2453,50 @@ impl hal::Backend for Backend {
             .expect("BUG: missing client manager");
         let group_configs = backend_config.groups.take();
         let backend_info = backend_config.info();
+        let config_path = backend_config.config_path.take();
+        let cli_pools = backend_config.cli_pools;
+        let replay_session = backend_config.replay_session.take();
+        let api_hardware_control = backend_config.api_hardware_control();
+        let translation_proxy_config = backend_config.translation_proxy();
+        let api_hooks = match hooks.as_ref() {
+            Some(hooks) => hooks.clone(),
+            None => Arc::new(hooks::NoHooks),
+        };
 
         let backend = work_hub.to_node().clone();
         let gpio_mgr = gpio::ControlPinManager::new();
         let (app_halt_sender, app_halt_receiver) = halt::make_pair(HALT_TIMEOUT);
+        let psu = if backend_config.psu_pmbus() {
+            Self::open_psu().await
+        } else {
+            None
+        };
         let (managers, monitor) = Self::start_miner(
             &gpio_mgr,
             Self::detect_hashboards(&gpio_mgr).expect("failed detecting hashboards"),
             work_hub,
             backend_config,
-            app_halt_receiver,
+            psu.clone(),
+            app_halt_receiver.clone(),
             app_halt_sender.clone(),
         )
         .await;
 
+        // The translation proxy serves other miners only while this miner is running
+        if let Some(config) = translation_proxy_config {
+            app_halt_receiver
+                .register_client("translation proxy".into())
+                .await
+                .spawn(translation_proxy::run(config));
+        }
+
+        // Pools are disconnected as the last step of the shutdown when all hashchains are
+        // already stopped and their solutions have been submitted
+        app_halt_receiver
+            .register_client("pool termination".into())
+            .await
+            .spawn_halt_handler(Self::pool_termination_handler(client_manager.clone()));
+
         // On miner exit, halt the whole program
         app_halt_sender
             .add_exit_hook(async {
@@ -1715,23 +2505,48 @@ impl hal::Backend for Backend {
             })
             .await;
         // Hook `Ctrl-C`, `SIGTERM` and other termination methods
-        app_halt_sender.hook_termination_signals();
+        app_halt_sender.clone().hook_termination_signals();
 
         // Load initial pool configuration
-        client_manager
-            .load_config(
-                group_configs,
-                backend_info.as_ref(),
-                config::DEFAULT_POOL_ENABLED,
-            )
-            .await?;
+        match replay_session {
+            Some(path) => client_manager.load_session_replay(path).await?,
+            None => {
+                client_manager
+                    .load_config(
+                        group_configs,
+                        backend_info.as_ref(),
+                        config::DEFAULT_POOL_ENABLED,
+                    )
+                    .await?
+            }
+        }
+        if let Some(config_path) = config_path {
+            tokio::spawn(Self::reload_handler(
+                config_path,
+                cli_pools,
+                client_manager.clone(),
+                backend_info,
+            ));
+        }
         if let Some(hooks) = hooks {
             // Pass the client manager to hook for further processing
             hooks.clients_loaded(client_manager).await;
         }
 
         Ok(hal::FrontendConfig {
-            cgminer_custom_commands: cgminer::create_custom_commands(backend, managers, monitor),
+            metrics_collector: Some(Arc::new(metrics::Collector::new(
+                managers.clone(),
+                psu.clone(),
+            ))),
+            cgminer_custom_commands: cgminer::create_custom_commands(
+                backend,
+                managers,
+                monitor,
+                psu,
+                api_hardware_control,
+                api_hooks,
+            ),
+            halt: Some(app_halt_sender),
         })
     }
 
@@ -1822,8 +2637,8 @@ fn calc_baud_clock_div(
 ///
 ///   work_delay = 0.9 * n_midstates * 2^19 / freq
 fn calculate_work_delay_for_pll(n_midstates: usize, pll_frequency: usize) -> f64 {
-    let space_size_per_core: u64 = 1 << 19;
-    0.9 * (n_midstates as u64 * space_size_per_core) as f64 / pll_frequency as f64
+    let space_size_per_core = bm1387::NONCE_PARTITION.core_space_size();
+    WORK_DELAY_FUDGE * (n_midstates as u64 * space_size_per_core) as f64 / pll_frequency as f64
 }
 
 /// Helper method to convert seconds to FPGA ticks suitable to be written
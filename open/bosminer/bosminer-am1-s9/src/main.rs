@@ -29,8 +29,19 @@ use bosminer_config::{ClientDescriptor, ClientUserInfo, GroupConfig, PoolConfig}
 
 use ii_async_compat::tokio;
 
-#[tokio::main]
-async fn main() {
+const EXIT_SUCCESS: i32 = 0;
+/// Exit status reported when the miner cannot be set up
+const EXIT_FAILURE: i32 = 1;
+
+fn main() {
+    let mut runtime = tokio::runtime::Runtime::new().expect("BUG: cannot create runtime");
+    let exit_code = runtime.block_on(run());
+    // All tasks have to be dropped before exiting
+    drop(runtime);
+    std::process::exit(exit_code);
+}
+
+async fn run() -> i32 {
     let app = clap::App::new(bosminer::SIGNATURE)
         .version(bosminer::version::STRING.as_str())
         .arg(
@@ -40,6 +51,14 @@ async fn main() {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Set logging level (error, warning, info, debug or trace)")
+                .required(false)
+                .takes_value(true),
+        )
         .arg(
             clap::Arg::with_name("pool")
                 .short("p")
@@ -80,6 +99,31 @@ async fn main() {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("benchmark")
+                .long("benchmark")
+                .value_name("SECONDS")
+                .help(
+                    "Verify that hardware solves known test blocks and measure hashrate for \
+                     SECONDS (60 by default) without connecting to any pool",
+                )
+                .required(false)
+                .takes_value(true)
+                .min_values(0)
+                .conflicts_with_all(&["pool", "user"]),
+        )
+        .arg(
+            clap::Arg::with_name("replay-session")
+                .long("replay-session")
+                .value_name("FILE")
+                .help(
+                    "Mine jobs replayed from session recorded with pool option 'record_session' \
+                     instead of connecting to any pool",
+                )
+                .required(false)
+                .takes_value(true)
+                .conflicts_with_all(&["pool", "user", "benchmark"]),
+        )
         .subcommand(
             clap::SubCommand::with_name("config")
                 .about("Configuration backend API")
@@ -113,13 +157,27 @@ async fn main() {
         );
 
     let matches = app.get_matches();
-    let _log_guard =
-        ii_logging::setup_for_app(bosminer_am1_s9::config::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE);
 
     let config_path = matches
         .value_of("config")
         .unwrap_or(config::DEFAULT_CONFIG_PATH);
 
+    // Logger has to be set up before the rest of configuration is parsed so take just its
+    // section from configuration file and let the command line override it
+    let (logging_config, logging_error) =
+        match config::Logging::parse(config_path).logging_config(matches.value_of("log-level")) {
+            Ok(logging_config) => (logging_config, None),
+            Err(e) => (
+                ii_logging::LoggingConfig::for_app(config::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE),
+                Some(e),
+            ),
+        };
+    let _log_guard = ii_logging::setup(logging_config);
+    if let Some(e) = logging_error {
+        error!("Cannot set up logging: {}", e);
+        return EXIT_FAILURE;
+    }
+
     // Handle special 'config' sub-command available for configuration backend API
     if let Some(matches) = matches.subcommand_matches("config") {
         let config_handler = config::api::Handler::new(config_path);
@@ -130,25 +188,20 @@ async fn main() {
         } else if matches.is_present("save") {
             config_handler.handle_save::<config::Backend>();
         }
-        return;
+        return EXIT_SUCCESS;
     }
 
-    let mut backend_config: config::Backend = match config::FormatWrapper::parse(config_path) {
-        Err(config::FormatWrapperError::IncompatibleVersion(version, Some(v))) => {
-            warn!(
-                "Incompatible format version '{}', but continuing anyway",
-                version
-            );
-            v.body
-        }
+    let mut backend_config: config::Backend = match config::FormatWrapper::parse_body(config_path) {
         Err(e) => {
             error!("Cannot load configuration file \"{}\"", config_path);
             error!("Reason: {}", e);
-            return;
+            return EXIT_FAILURE;
         }
-        Ok(v) => v.body,
+        Ok(v) => v,
     };
 
+    backend_config.config_path = Some(config_path.to_string());
+
     // Add pools from command line
     if let Some(url) = matches.value_of("pool") {
         let user_info = matches
@@ -159,7 +212,7 @@ async fn main() {
         match ClientDescriptor::create(url, &user_info, true) {
             Err(e) => {
                 error!("Cannot set pool from command line: {}", e.to_string());
-                return;
+                return EXIT_FAILURE;
             }
             Ok(_) => {}
         };
@@ -170,6 +223,22 @@ async fn main() {
                 url: url.to_string(),
                 user: user_info.user.to_string(),
                 password: user_info.password.map(|v| v.to_string()),
+                parse_error_policy: None,
+                alternate_users: None,
+                submit_jitter_ms: None,
+                compression: None,
+                ntime_tolerance_s: None,
+                ntime_policy: None,
+                ntime_rolling_s: None,
+                reject_alerts: None,
+                reconnect_policy: None,
+                reconnect_allowlist: None,
+                suggested_difficulty: None,
+                tls_verify: None,
+                tls_ca_file: None,
+                tls_server_name: None,
+                proxy: None,
+                record_session: None,
             }]),
         };
 
@@ -178,10 +247,42 @@ async fn main() {
         }
 
         backend_config.groups = Some(vec![group_config]);
+        backend_config.cli_pools = true;
+    }
+
+    let benchmark_duration = if matches.is_present("benchmark") {
+        match matches
+            .value_of("benchmark")
+            .map(|value| value.parse::<u64>())
+        {
+            None => Some(bosminer::benchmark::DEFAULT_DURATION),
+            Some(Ok(value)) => Some(std::time::Duration::from_secs(value)),
+            Some(Err(e)) => {
+                error!("Cannot use benchmark duration: {}", e.to_string());
+                return EXIT_FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+    if benchmark_duration.is_some() {
+        // Benchmark runs without pools and it must not be affected by configuration reload
+        backend_config.groups = None;
+        backend_config.config_path = None;
+    }
+
+    if let Some(path) = matches.value_of("replay-session") {
+        // Replayed session must not be affected by pools from configuration even on reload
+        backend_config.groups = None;
+        backend_config.config_path = None;
+        backend_config.replay_session = Some(path.to_string());
     }
 
     // Check if there's enough pools
-    if !backend_config.has_pools() {
+    if benchmark_duration.is_none()
+        && backend_config.replay_session.is_none()
+        && !backend_config.has_pools()
+    {
         error!("No pools specified!");
         info!("Use cli arguments:");
         info!("    bosminer --pool <HOSTNAME:PORT> --user <USERNAME.WORKERNAME[:PASSWORD]>");
@@ -190,7 +291,7 @@ async fn main() {
             config_path
         );
         info!("    in [[group.pool]] section");
-        return;
+        return EXIT_FAILURE;
     }
 
     // Set just 1 midstate if user requested disabling asicboost
@@ -210,7 +311,7 @@ async fn main() {
                     value,
                     e.to_string()
                 );
-                return;
+                return EXIT_FAILURE;
             }
         };
         backend_config
@@ -230,7 +331,7 @@ async fn main() {
                     value,
                     e.to_string()
                 );
-                return;
+                return EXIT_FAILURE;
             }
         };
         backend_config
@@ -244,10 +345,24 @@ async fn main() {
 
     if let Err(e) = backend_config.fill_info::<config::Backend>() {
         error!("Cannot get backend information: {}", e.to_string());
-        return;
+        return EXIT_FAILURE;
     }
 
     ii_async_compat::setup_panic_handling();
+    if let Some(duration) = benchmark_duration {
+        // Hash chains are already halted when the benchmark returns
+        return match bosminer::benchmark::run::<bosminer_am1_s9::Backend>(backend_config, duration)
+            .await
+        {
+            Ok(report) if report.is_success() => EXIT_SUCCESS,
+            Ok(_) => EXIT_FAILURE,
+            Err(e) => {
+                error!("Benchmark failed: {}", e.to_string());
+                EXIT_FAILURE
+            }
+        };
+    }
     bosminer::main::<bosminer_am1_s9::Backend>(backend_config, bosminer::SIGNATURE.to_string())
         .await;
+    EXIT_SUCCESS
 }
@@ -0,0 +1,234 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Hashboard specific metrics (temperatures, chip error counters and nonce space coverage) and
+//! power supply measurements for the Prometheus exporter
+
+use bosminer::api::prometheus::{self, MetricType, Metrics};
+use bosminer::async_trait;
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::psu;
+use crate::sensor;
+
+use ii_logging::macros::*;
+
+pub struct Collector {
+    managers: Vec<Arc<crate::Manager>>,
+    psu: Option<Arc<psu::Psu>>,
+}
+
+impl Collector {
+    const TEMPERATURE: &'static str = "bosminer_hashboard_temperature_celsius";
+    const CHIP_ERRORS: &'static str = "bosminer_chip_errors_total";
+    const CHIP_NONCES: &'static str = "bosminer_chip_valid_nonces_total";
+    const ERROR_RATIO: &'static str = "bosminer_hashboard_error_ratio";
+    const NONCE_COVERAGE: &'static str = "bosminer_hashboard_nonce_coverage_ratio";
+    const INCOMPLETE_SEARCH: &'static str = "bosminer_hashboard_incomplete_search_total";
+    const IDLE_CORES: &'static str = "bosminer_hashboard_idle_cores";
+    const WORK_BACKLOG: &'static str = "bosminer_hashboard_work_backlog";
+    const PSU_INPUT_VOLTAGE: &'static str = "bosminer_psu_input_voltage_volts";
+    const PSU_INPUT_CURRENT: &'static str = "bosminer_psu_input_current_amperes";
+    const PSU_INPUT_POWER: &'static str = "bosminer_psu_input_power_watts";
+
+    pub fn new(managers: Vec<Arc<crate::Manager>>, psu: Option<Arc<psu::Psu>>) -> Self {
+        Self { managers, psu }
+    }
+}
+
+#[async_trait]
+impl prometheus::Collector for Collector {
+    async fn collect(&self, metrics: &mut Metrics) {
+        let mut temperatures = vec![];
+        let mut chip_errors = vec![];
+        let mut error_ratios = vec![];
+        let mut coverages = vec![];
+        let mut work_backlogs = vec![];
+        for manager in self.managers.iter() {
+            let hash_chain = match manager.inner.lock().await.hash_chain.as_ref() {
+                Some(hash_chain) => hash_chain.clone(),
+                None => continue,
+            };
+            let hashboard = manager.hashboard_idx.to_string();
+
+            if let Some(sensor::Temperature { local, remote }) = hash_chain.current_temperature() {
+                for (sensor, measurement) in &[("board", local), ("chip", remote)] {
+                    if let Some(temperature) = Option::<f32>::from(measurement.clone()) {
+                        temperatures.push((hashboard.clone(), *sensor, temperature));
+                    }
+                }
+            }
+            let counter = hash_chain.snapshot_counter().await;
+            for (chip, chip_counter) in counter.chip.iter().enumerate() {
+                chip_errors.push((
+                    hashboard.clone(),
+                    chip.to_string(),
+                    chip_counter.errors,
+                    chip_counter.valid / counter.asic_difficulty.max(1),
+                ));
+            }
+            error_ratios.push((hashboard.clone(), counter.error_ratio()));
+            work_backlogs.push((
+                hashboard.clone(),
+                hash_chain.work_backlog.load(Ordering::Relaxed),
+            ));
+            coverages.push((hashboard, hash_chain.nonce_coverage.lock().await.stats()));
+        }
+
+        metrics.family(
+            Self::TEMPERATURE,
+            MetricType::Gauge,
+            "Current temperature of the hashboard",
+        );
+        for (hashboard, sensor, temperature) in &temperatures {
+            metrics.sample(
+                Self::TEMPERATURE,
+                &[("hashboard", hashboard.as_str()), ("sensor", *sensor)],
+                *temperature,
+            );
+        }
+
+        metrics.family(
+            Self::CHIP_ERRORS,
+            MetricType::Counter,
+            "Number of hardware errors of the chip since the hashboard has been started",
+        );
+        for (hashboard, chip, errors, _) in &chip_errors {
+            metrics.sample(
+                Self::CHIP_ERRORS,
+                &[("hashboard", hashboard.as_str()), ("chip", chip.as_str())],
+                *errors as f64,
+            );
+        }
+
+        metrics.family(
+            Self::CHIP_NONCES,
+            MetricType::Counter,
+            "Number of valid nonces of the chip since the hashboard has been started",
+        );
+        for (hashboard, chip, _, nonces) in &chip_errors {
+            metrics.sample(
+                Self::CHIP_NONCES,
+                &[("hashboard", hashboard.as_str()), ("chip", chip.as_str())],
+                *nonces as f64,
+            );
+        }
+
+        metrics.family(
+            Self::ERROR_RATIO,
+            MetricType::Gauge,
+            "Ratio of hardware errors to all nonces returned by the hashboard",
+        );
+        for (hashboard, error_ratio) in &error_ratios {
+            metrics.sample(
+                Self::ERROR_RATIO,
+                &[("hashboard", hashboard.as_str())],
+                *error_ratio,
+            );
+        }
+
+        metrics.family(
+            Self::NONCE_COVERAGE,
+            MetricType::Gauge,
+            "Fraction of nonces expected for the sent work returned by the hashboard",
+        );
+        for (hashboard, coverage) in &coverages {
+            metrics.sample(
+                Self::NONCE_COVERAGE,
+                &[("hashboard", hashboard.as_str())],
+                coverage.ratio(),
+            );
+        }
+
+        metrics.family(
+            Self::INCOMPLETE_SEARCH,
+            MetricType::Counter,
+            "Number of accounting windows in which the hashboard returned too few nonces",
+        );
+        for (hashboard, coverage) in &coverages {
+            metrics.sample(
+                Self::INCOMPLETE_SEARCH,
+                &[("hashboard", hashboard.as_str())],
+                coverage.incomplete_count as f64,
+            );
+        }
+
+        metrics.family(
+            Self::IDLE_CORES,
+            MetricType::Gauge,
+            "Number of cores which have not returned any nonce",
+        );
+        for (hashboard, coverage) in &coverages {
+            metrics.sample(
+                Self::IDLE_CORES,
+                &[("hashboard", hashboard.as_str())],
+                coverage.idle_core_count as f64,
+            );
+        }
+
+        metrics.family(
+            Self::WORK_BACKLOG,
+            MetricType::Gauge,
+            "Number of work items the hashboard is allowed to buffer in its work TX FIFO",
+        );
+        for (hashboard, work_backlog) in &work_backlogs {
+            metrics.sample(
+                Self::WORK_BACKLOG,
+                &[("hashboard", hashboard.as_str())],
+                *work_backlog as f64,
+            );
+        }
+
+        let readout = match self.psu.as_ref() {
+            Some(psu) => match psu.read().await {
+                Ok(readout) => readout,
+                Err(e) => {
+                    warn!("Cannot read PSU: {}", e);
+                    return;
+                }
+            },
+            None => return,
+        };
+        for (name, help, value) in &[
+            (
+                Self::PSU_INPUT_VOLTAGE,
+                "Input voltage of the power supply",
+                readout.input_voltage,
+            ),
+            (
+                Self::PSU_INPUT_CURRENT,
+                "Input current of the power supply",
+                readout.input_current,
+            ),
+            (
+                Self::PSU_INPUT_POWER,
+                "Input power of the power supply",
+                readout.input_power,
+            ),
+        ] {
+            metrics.family(name, MetricType::Gauge, help);
+            metrics.sample(name, &[], *value);
+        }
+    }
+}
@@ -27,6 +27,7 @@ use ii_logging::macros::*;
 
 use crate::fan;
 use crate::halt;
+use crate::power;
 use crate::sensor::{self, Measurement};
 
 use std::sync::Arc;
@@ -85,7 +86,7 @@ impl ChainTemperature {
     /// remote sensors fail while mining and instead of signalizing error they return non-sensical
     /// numbers.
     /// TODO: Is returning "Unknown" when sensor fails OK?
-    fn from_s9_sensor(temp: sensor::Temperature) -> Self {
+    pub(crate) fn from_s9_sensor(temp: sensor::Temperature) -> Self {
         match temp.remote {
             // remote is chip temperature
             Measurement::Ok(t) => Self::Ok(t),
@@ -219,6 +220,8 @@ pub enum FanControlMode {
 #[derive(Debug, Clone)]
 pub struct FanControlConfig {
     pub mode: FanControlMode,
+    /// Lowest speed the PID can set once the miner has warmed up
+    pub min_speed: fan::Speed,
     /// Minimal number of fans - miner will refuse to work until at least
     /// this number of fans is spinning.
     pub min_fans: usize,
@@ -231,6 +234,150 @@ pub struct TempControlConfig {
     pub hot_temp: f32,
 }
 
+/// Thermal throttle configuration of one hashchain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalThrottleConfig {
+    /// Temperature at which the hashchain frequency starts to be reduced
+    pub throttle_temp: f32,
+}
+
+/// Output of one thermal throttle update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottleDecision {
+    /// Keep current frequency
+    Keep,
+    /// Set hashchain frequency to this ratio of its nominal frequency
+    SetRatio(f32),
+    /// Frequency is already at its minimum and the temperature is still too high. The miner has
+    /// to rely on `dangerous_temp` shutdown of the monitor.
+    Insufficient,
+}
+
+/// Progressively reduces frequency of a hot hashchain to shed heat while it continues to mine.
+/// The frequency is restored step by step when the temperature drops below the throttle
+/// temperature (with hysteresis).
+#[derive(Debug, Clone)]
+pub struct ThermalThrottle {
+    config: ThermalThrottleConfig,
+    /// Number of frequency reduction steps currently applied
+    level: usize,
+}
+
+impl ThermalThrottle {
+    /// Frequency reduction of one step (relative to the nominal frequency)
+    pub const STEP: f32 = 0.05;
+    /// Maximal number of steps (frequency is never reduced below 50% of nominal)
+    pub const MAX_LEVEL: usize = 10;
+    /// Temperature drop below `throttle_temp` needed to start restoring the frequency
+    pub const HYSTERESIS: f32 = 5.0;
+    /// Voltage reduction of one step in volts. Chips are stable at lower voltage when running
+    /// at reduced frequency and the lower voltage sheds even more heat.
+    pub const VOLTAGE_STEP: f32 = 0.03;
+
+    pub fn new(config: ThermalThrottleConfig) -> Self {
+        Self { config, level: 0 }
+    }
+
+    /// Ratio of nominal frequency that should be currently used
+    pub fn ratio(&self) -> f32 {
+        1.0 - self.level as f32 * Self::STEP
+    }
+
+    /// Voltage that should be currently used instead of `nominal` voltage
+    pub fn voltage(&self, nominal: power::Voltage) -> power::Voltage {
+        if self.level == 0 {
+            return nominal;
+        }
+        power::Voltage::from_volts(nominal.as_volts() - self.level as f32 * Self::VOLTAGE_STEP)
+            .unwrap_or(power::Voltage::MIN_VOLTAGE)
+    }
+
+    /// Update throttle with a new hashchain temperature and decide what to do with frequency
+    pub fn update(&mut self, temp: ChainTemperature) -> ThrottleDecision {
+        let input_temp = match temp {
+            ChainTemperature::Ok(input_temp) => input_temp,
+            // Missing temperature is handled by monitor
+            ChainTemperature::Unknown | ChainTemperature::Failed => return ThrottleDecision::Keep,
+        };
+        if input_temp >= self.config.throttle_temp {
+            if self.level >= Self::MAX_LEVEL {
+                return ThrottleDecision::Insufficient;
+            }
+            self.level += 1;
+        } else if input_temp < self.config.throttle_temp - Self::HYSTERESIS && self.level > 0 {
+            self.level -= 1;
+        } else {
+            return ThrottleDecision::Keep;
+        }
+        ThrottleDecision::SetRatio(self.ratio())
+    }
+}
+
+/// Thermal cutoff configuration of one hashchain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalCutoffConfig {
+    /// Temperature at which the hashchain is stopped
+    pub cutoff_temp: f32,
+    /// How long the stopped hashchain cools down before it is started again
+    pub cooldown: Duration,
+}
+
+impl ThermalCutoffConfig {
+    /// Check whether the hashchain has to be stopped to cool down
+    pub fn is_overheated(&self, temp: ChainTemperature) -> bool {
+        match temp {
+            ChainTemperature::Ok(input_temp) => input_temp >= self.cutoff_temp,
+            // Missing temperature is handled by monitor
+            ChainTemperature::Unknown | ChainTemperature::Failed => false,
+        }
+    }
+}
+
+/// Detects a hashchain which has stopped returning nonces even though it is supplied with work
+#[derive(Debug, Clone)]
+pub struct ChainWatchdog {
+    /// How long the hashchain may return no nonces
+    timeout: Duration,
+    /// Last time the hashchain returned a nonce (or when it has not been supplied with work)
+    last_progress: Instant,
+    /// Number of nonces (valid ones and errors) at the last update
+    nonces: usize,
+    /// Amount of generated work at the last update
+    work: u64,
+}
+
+impl ChainWatchdog {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_progress: now,
+            nonces: 0,
+            work: 0,
+        }
+    }
+
+    #[inline]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Update the watchdog with the total number of `nonces` returned by the hashchain and the
+    /// total amount of `work` sent to it. Returns `true` when the hashchain is stuck.
+    pub fn update(&mut self, nonces: usize, work: u64, now: Instant) -> bool {
+        // Hashchain without new work (e.g. no pool is available) is not expected to return
+        // any nonce
+        let progress = nonces != self.nonces || work == self.work;
+        self.nonces = nonces;
+        self.work = work;
+        if progress {
+            self.last_progress = now;
+            false
+        } else {
+            now.saturating_duration_since(self.last_progress) >= self.timeout
+        }
+    }
+}
+
 /// Overall configuration
 /// "Disabled" is represented as `None`
 #[derive(Debug, Clone)]
@@ -589,10 +736,16 @@ impl Monitor {
                 target_temp,
                 input_temp,
             } => {
+                let min_speed = inner
+                    .config
+                    .fan_config
+                    .as_ref()
+                    .expect("BUG: PID without fan config")
+                    .min_speed;
                 if inner.config.fans_on_while_warming_up && miner_warming_up {
-                    inner.pid.set_warm_up_limits();
+                    inner.pid.set_warm_up_limits(min_speed);
                 } else {
-                    inner.pid.set_normal_limits();
+                    inner.pid.set_normal_limits(min_speed);
                 }
                 inner.pid.set_target(target_temp.into());
                 let speed = inner.pid.update(input_temp.into());
@@ -745,7 +898,7 @@ mod test {
         );
         assert_variant!(
             send(ChainState::On(now), later, Message::Running(temp.clone())),
-            ChainState::Running{ .. }
+            ChainState::Running { .. }
         );
         assert_variant!(
             send(ChainState::On(now), later, Message::Off),
@@ -757,11 +910,7 @@ mod test {
             ChainState::Broken(_)
         );
         assert_variant!(
-            send(
-                running_state.clone(),
-                later,
-                Message::Running(temp.clone())
-            ),
+            send(running_state.clone(), later, Message::Running(temp.clone())),
             ChainState::Running { .. }
         );
         assert_variant!(
@@ -825,7 +974,7 @@ mod test {
         assert_variant!(tick(ChainState::On(now), short), ChainState::On(_));
         assert_variant!(
             tick(running_state.clone(), short),
-            ChainState::Running{..}
+            ChainState::Running { .. }
         );
 
         // different states have different update timeouts
@@ -891,6 +1040,118 @@ mod test {
         );
     }
 
+    /// Test that rising temperature reduces frequency before the monitor decides to shutdown
+    #[test]
+    fn test_thermal_throttle() {
+        let config = Config {
+            fan_config: None,
+            temp_config: Some(TempControlConfig {
+                dangerous_temp: 110.0,
+                hot_temp: 100.0,
+            }),
+            fans_on_while_warming_up: false,
+        };
+        let mut thermal_throttle = ThermalThrottle::new(ThermalThrottleConfig {
+            throttle_temp: 95.0,
+        });
+
+        let mut last_ratio = 1.0;
+        let mut shutdown = false;
+        // simulate rising temperature
+        for temp in (80..120).map(|t| ChainTemperature::Ok(t as f32)) {
+            if ControlDecision::decide(&config, 0, temp).decision == ControlDecision::Shutdown {
+                shutdown = true;
+                break;
+            }
+            match thermal_throttle.update(temp) {
+                ThrottleDecision::SetRatio(ratio) => {
+                    assert!(ratio < last_ratio);
+                    last_ratio = ratio;
+                }
+                ThrottleDecision::Keep => assert_eq!(last_ratio, 1.0),
+                ThrottleDecision::Insufficient => {
+                    assert_relative_eq!(last_ratio, 0.5);
+                }
+            }
+        }
+        assert!(shutdown);
+        // frequency has been reduced before shutdown
+        assert!(last_ratio < 1.0);
+
+        // temperature within hysteresis keeps the frequency
+        let ratio = thermal_throttle.ratio();
+        assert_eq!(
+            thermal_throttle.update(ChainTemperature::Ok(92.0)),
+            ThrottleDecision::Keep
+        );
+        // unknown temperature doesn't change anything
+        assert_eq!(
+            thermal_throttle.update(ChainTemperature::Unknown),
+            ThrottleDecision::Keep
+        );
+        // voltage is reduced together with frequency
+        let nominal_voltage = power::Voltage::from_volts(8.8).expect("BUG: invalid voltage");
+        let voltage = thermal_throttle.voltage(nominal_voltage);
+        assert!(voltage.as_volts() < nominal_voltage.as_volts());
+        assert!(voltage.as_volts() >= power::Voltage::MIN_VOLTAGE.as_volts());
+
+        // frequency is restored when the board cools down
+        match thermal_throttle.update(ChainTemperature::Ok(80.0)) {
+            ThrottleDecision::SetRatio(new_ratio) => assert!(new_ratio > ratio),
+            decision => panic!("unexpected decision {:?}", decision),
+        }
+        for _ in 0..ThermalThrottle::MAX_LEVEL {
+            thermal_throttle.update(ChainTemperature::Ok(80.0));
+        }
+        assert_relative_eq!(thermal_throttle.ratio(), 1.0);
+        assert!(thermal_throttle.voltage(nominal_voltage) == nominal_voltage);
+    }
+
+    #[test]
+    fn test_thermal_cutoff() {
+        let thermal_cutoff = ThermalCutoffConfig {
+            cutoff_temp: 100.0,
+            cooldown: Duration::from_secs(300),
+        };
+        assert!(!thermal_cutoff.is_overheated(ChainTemperature::Ok(99.9)));
+        assert!(thermal_cutoff.is_overheated(ChainTemperature::Ok(100.0)));
+        // missing temperature doesn't stop the hashchain
+        assert!(!thermal_cutoff.is_overheated(ChainTemperature::Unknown));
+        assert!(!thermal_cutoff.is_overheated(ChainTemperature::Failed));
+    }
+
+    #[test]
+    fn test_chain_watchdog() {
+        let timeout = Duration::from_secs(60);
+        let mut now = Instant::now();
+        let mut watchdog = ChainWatchdog::new(timeout, now);
+
+        // hashchain without any work is never stuck
+        now += 2 * timeout;
+        assert!(!watchdog.update(0, 0, now));
+
+        // work is sent and nonces are returned
+        now += timeout;
+        assert!(!watchdog.update(10, 100, now));
+        now += timeout;
+        assert!(!watchdog.update(20, 200, now));
+
+        // work is sent but no nonce is returned
+        now += timeout / 2;
+        assert!(!watchdog.update(20, 300, now));
+        now += timeout / 2;
+        assert!(watchdog.update(20, 400, now));
+
+        // work is not supplied anymore
+        now += timeout;
+        assert!(!watchdog.update(20, 400, now));
+        // and the timeout starts again when it is resumed
+        now += timeout / 2;
+        assert!(!watchdog.update(20, 500, now));
+        now += timeout / 2;
+        assert!(watchdog.update(20, 600, now));
+    }
+
     /// Test temperature decision tree (non-exhaustive test)
     #[test]
     fn test_decide() {
@@ -904,6 +1165,7 @@ mod test {
         let fan_speed = fan::Speed::new(50);
         let fan_config = FanControlConfig {
             mode: FanControlMode::FixedSpeed(fan_speed),
+            min_speed: fan::Speed::STOPPED,
             min_fans: 2,
         };
         let fans_off = fan::Speed::STOPPED;
@@ -911,6 +1173,7 @@ mod test {
             fans_on_while_warming_up: true,
             fan_config: Some(FanControlConfig {
                 mode: FanControlMode::FixedSpeed(fans_off),
+                min_speed: fan::Speed::STOPPED,
                 min_fans: 2,
             }),
             temp_config: None,
@@ -939,6 +1202,7 @@ mod test {
             fans_on_while_warming_up: true,
             fan_config: Some(FanControlConfig {
                 mode: FanControlMode::TargetTemperature(75.0),
+                min_speed: fan::Speed::new(1),
                 min_fans: 2,
             }),
             temp_config: Some(temp_config.clone()),
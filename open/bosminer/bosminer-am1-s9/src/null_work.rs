@@ -151,7 +151,11 @@ pub fn prepare(i: u64) -> work::Assignment {
         state: midstate_bytes.into(),
     };
 
-    work::Assignment::new(job, vec![mid], time)
+    work::AssignmentBuilder::new(job)
+        .midstate(mid)
+        .ntime(time)
+        .build()
+        .expect("BUG: inconsistent null work")
 }
 
 pub fn prepare_opencore(enable_core: bool, midstate_count: usize) -> work::Assignment {
@@ -164,5 +168,9 @@ pub fn prepare_opencore(enable_core: bool, midstate_count: usize) -> work::Assig
         state: [0u8; ii_bitcoin::SHA256_DIGEST_SIZE].into(),
     };
 
-    work::Assignment::new(job, vec![one_midstate; midstate_count], time)
+    work::AssignmentBuilder::new(job)
+        .midstates(vec![one_midstate; midstate_count])
+        .ntime(time)
+        .build()
+        .expect("BUG: inconsistent null work")
 }
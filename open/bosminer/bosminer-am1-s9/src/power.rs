@@ -38,6 +38,7 @@ use ii_async_compat::futures;
 use ii_async_compat::tokio;
 use tokio::time::delay_for;
 
+use async_trait::async_trait;
 use once_cell::sync::Lazy;
 
 /// Default initial voltage
@@ -751,6 +752,45 @@ impl Control {
     }
 }
 
+/// Hashboard voltage controller that can be driven at runtime (e.g. by the tuner) without
+/// knowledge of the specific hardware
+#[async_trait]
+pub trait VoltageController: Send + Sync {
+    /// Set output voltage of the hashboard
+    async fn set_voltage(&self, voltage: Voltage) -> error::Result<()>;
+    /// Last voltage which has been set
+    async fn voltage(&self) -> Option<Voltage>;
+    /// Measured power consumption of the hashboard in watts (`None` when the controller is not
+    /// able to measure it)
+    async fn power(&self) -> error::Result<Option<f64>>;
+    /// Enable or disable output voltage of all power domains of the hashboard
+    async fn set_enabled(&self, enabled: bool) -> error::Result<()>;
+}
+
+/// The S9 PIC controller drives a single power domain and has no current sense
+#[async_trait]
+impl VoltageController for Control {
+    async fn set_voltage(&self, voltage: Voltage) -> error::Result<()> {
+        Control::set_voltage(self, voltage).await
+    }
+
+    async fn voltage(&self) -> Option<Voltage> {
+        self.get_current_voltage().await
+    }
+
+    async fn power(&self) -> error::Result<Option<f64>> {
+        Ok(None)
+    }
+
+    async fn set_enabled(&self, enabled: bool) -> error::Result<()> {
+        if enabled {
+            self.enable_voltage().await
+        } else {
+            self.disable_voltage().await
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
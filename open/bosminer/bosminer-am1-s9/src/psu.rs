@@ -0,0 +1,202 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Driver for power supplies with PMBus interface (e.g. APW series) which are able to measure
+//! their input power. The readings are more precise than the estimation of power consumption
+//! from hashboard voltage and chip frequencies.
+
+use crate::async_i2c::AsyncI2cDev;
+use crate::error::{self, ErrorKind};
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Default PMBus address of power supply (7-bit)
+pub const DEFAULT_ADDRESS: u8 = 0x58;
+
+/// PMBus command codes of input readings
+const READ_VIN: u8 = 0x88;
+const READ_IIN: u8 = 0x89;
+const READ_PIN: u8 = 0x97;
+
+/// Decode value in PMBus LINEAR11 format. The upper 5 bits are exponent and the lower 11 bits
+/// are mantissa, both of them are two's complement signed numbers.
+fn decode_linear11(word: u16) -> f64 {
+    let exponent = (word as i16) >> 11;
+    let mantissa = ((word << 5) as i16) >> 5;
+    mantissa as f64 * 2f64.powi(exponent as i32)
+}
+
+/// Measured input of power supply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Readout {
+    /// Input voltage in volts
+    pub input_voltage: f64,
+    /// Input current in amperes
+    pub input_current: f64,
+    /// Input power in watts
+    pub input_power: f64,
+}
+
+impl fmt::Display for Readout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} V, {:.2} A, {:.0} W",
+            self.input_voltage, self.input_current, self.input_power
+        )
+    }
+}
+
+/// Number of hashboards currently powered by the power supply. It follows hashchain starts and
+/// stops (also those requested over the API) so the input power is shared only by the running
+/// hashboards.
+#[derive(Debug, Default)]
+pub struct Load {
+    running: AtomicUsize,
+}
+
+impl Load {
+    /// Account for a started hashchain
+    pub fn attach(&self) {
+        self.running.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Account for a stopped hashchain
+    pub fn detach(&self) {
+        let previous = self.running.fetch_sub(1, Ordering::Relaxed);
+        assert!(previous > 0, "BUG: no hashboard is attached to PSU");
+    }
+
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Part of the input power attributed to one running hashboard. The input power also covers
+    /// fans and the controller, this overhead is split among the hashboards as well, the same way
+    /// as the reference power of `tuner::PowerModel` includes it. Returns `None` when no hashboard
+    /// is running because the whole input power is the overhead then.
+    pub fn hashboard_power(&self, readout: &Readout) -> Option<f64> {
+        match self.running() {
+            0 => None,
+            running => Some(readout.input_power / running as f64),
+        }
+    }
+}
+
+pub struct Psu {
+    i2c: AsyncI2cDev,
+    address: u8,
+}
+
+impl Psu {
+    /// Open power supply on I2C interface `i2c_interface_num` at PMBus `address`
+    pub fn open(i2c_interface_num: usize, address: u8) -> error::Result<Self> {
+        Ok(Self {
+            i2c: AsyncI2cDev::open(format!("/dev/i2c-{}", i2c_interface_num))?,
+            address,
+        })
+    }
+
+    /// Read one of the values in LINEAR11 format. Input readings can never be negative so such
+    /// value means that the power supply is not present or it doesn't support the command.
+    async fn read_linear11(&self, command: u8) -> error::Result<f64> {
+        let bytes = self.i2c.write_read(self.address, vec![command], 2).await?;
+        // PMBus transmits the low byte first
+        let value = decode_linear11(u16::from_le_bytes([bytes[0], bytes[1]]));
+        if value < 0.0 {
+            Err(ErrorKind::Power(format!(
+                "PSU: invalid reading {} of command {:#04x}",
+                value, command
+            )))?
+        }
+        Ok(value)
+    }
+
+    pub async fn read(&self) -> error::Result<Readout> {
+        Ok(Readout {
+            input_voltage: self.read_linear11(READ_VIN).await?,
+            input_current: self.read_linear11(READ_IIN).await?,
+            input_power: self.read_linear11(READ_PIN).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_linear11() {
+        // exponent 0
+        assert_eq!(decode_linear11(0x0001), 1.0);
+        assert_eq!(decode_linear11(0x03ff), 1023.0);
+        // positive exponent: 0x21 * 2^2
+        assert_eq!(decode_linear11(0x1021), 132.0);
+        // negative exponent: 0x3a6 * 2^-2 (typical reading of input voltage)
+        assert_eq!(decode_linear11(0xf3a6), 233.5);
+        // negative mantissa
+        assert_eq!(decode_linear11(0x07ff), -1.0);
+        // bus without any device returns all ones
+        assert_eq!(decode_linear11(0xffff), -0.5);
+    }
+
+    #[test]
+    fn test_readout_display() {
+        let readout = Readout {
+            input_voltage: 233.5,
+            input_current: 6.125,
+            input_power: 1430.0,
+        };
+        assert_eq!(readout.to_string(), "233.5 V, 6.12 A, 1430 W");
+    }
+
+    #[test]
+    fn test_load_hashboard_power() {
+        let readout = Readout {
+            input_voltage: 230.0,
+            input_current: 6.0,
+            input_power: 1380.0,
+        };
+        let load = Load::default();
+        assert_eq!(load.hashboard_power(&readout), None);
+
+        for _ in 0..3 {
+            load.attach();
+        }
+        assert_eq!(load.running(), 3);
+        assert_eq!(load.hashboard_power(&readout), Some(460.0));
+
+        // hashboard stopped over the API
+        load.detach();
+        assert_eq!(load.hashboard_power(&readout), Some(690.0));
+        load.detach();
+        load.detach();
+        assert_eq!(load.hashboard_power(&readout), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_load_detach_fail() {
+        Load::default().detach();
+    }
+}
@@ -49,10 +49,20 @@ impl WorkRegistryItem {
             unique_solution: None,
         };
         // scan the current solutions and detect a duplicate
-        let matching_solution = self
-            .solutions
-            .iter()
-            .find(|solution| solution.nonce == new_solution.nonce);
+        // NOTE: the same nonce found for a different midstate is a distinct share because each
+        // midstate represents a different rolled version of the same base header. Work for jobs
+        // with narrow version mask repeats midstates so the versions are compared instead of
+        // the midstate indexes.
+        let version = |midstate_idx: usize| {
+            self.work
+                .midstates
+                .get(midstate_idx)
+                .map(|midstate| midstate.version)
+        };
+        let new_version = version(new_solution.midstate_idx);
+        let matching_solution = self.solutions.iter().find(|solution| {
+            solution.nonce == new_solution.nonce && version(solution.midstate_idx) == new_version
+        });
         if matching_solution.is_none() {
             // At this point, we know such solution has not been received yet. If it is valid (no
             // hardware error detected == meets the target), it can be appended to the solution list
@@ -212,6 +222,104 @@ mod test {
         assert_eq!(registry.store_work(work.clone(), false), 0);
     }
 
+    /// Test that solutions with the same nonce found for different rolled versions are not
+    /// treated as duplicates while the exact duplicates are detected
+    #[test]
+    fn test_insert_solution_version_rolling() {
+        let mut registry = WorkRegistry::new(4);
+        let mut work = null_work::prepare_opencore(true, 2);
+        work.midstates[1].version = 0x2000_0000;
+        let work_id = registry.store_work(work, false);
+        let work_item = registry
+            .find_work(work_id)
+            .as_mut()
+            .expect("work not found");
+
+        let solution = |midstate_idx| Solution {
+            nonce: 0x1234_5678,
+            midstate_idx,
+            solution_idx: 0,
+            target: Default::default(),
+        };
+
+        let status = work_item.insert_solution(solution(0));
+        assert!(!status.duplicate);
+        let first = status.unique_solution.expect("missing solution");
+
+        let status = work_item.insert_solution(solution(1));
+        assert!(!status.duplicate);
+        let second = status.unique_solution.expect("missing solution");
+
+        // both solutions are submitted each with its own version
+        assert_eq!(first.nonce(), second.nonce());
+        assert_eq!(first.version(), 0);
+        assert_eq!(second.version(), 0x2000_0000);
+
+        // exact duplicate is still detected
+        assert!(work_item.insert_solution(solution(1)).duplicate);
+    }
+
+    /// Test that the same nonce found for a repeated midstate is a duplicate
+    #[test]
+    fn test_insert_solution_repeated_midstate() {
+        let mut registry = WorkRegistry::new(4);
+        // both midstates have the same version as if the job did not allow version rolling
+        let work = null_work::prepare_opencore(true, 2);
+        assert_eq!(work.midstates[0].version, work.midstates[1].version);
+        let work_id = registry.store_work(work, false);
+        let work_item = registry
+            .find_work(work_id)
+            .as_mut()
+            .expect("work not found");
+
+        let solution = |midstate_idx| Solution {
+            nonce: 0x1234_5678,
+            midstate_idx,
+            solution_idx: 0,
+            target: Default::default(),
+        };
+        assert!(!work_item.insert_solution(solution(0)).duplicate);
+        assert!(work_item.insert_solution(solution(1)).duplicate);
+    }
+
+    /// Test that identical nonces found for two midstates are distinct shares which both pass
+    /// verification
+    #[test]
+    fn test_duplicate_nonce_across_midstates() {
+        use ii_bitcoin::MeetsTarget;
+
+        let mut registry = WorkRegistry::new(4);
+        let mut work = null_work::prepare_opencore(true, 2);
+        work.midstates[1].version = 0x2000_0000;
+        let work_id = registry.store_work(work, false);
+        let work_item = registry
+            .find_work(work_id)
+            .as_mut()
+            .expect("work not found");
+
+        // the easiest possible target is met by any hash
+        let target = ii_bitcoin::Target::from_hex(
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        )
+        .expect("BUG: invalid target");
+        let mut hashes = vec![];
+        for midstate_idx in 0..2 {
+            let status = work_item.insert_solution(Solution {
+                nonce: 0xdead_beef,
+                midstate_idx,
+                solution_idx: 0,
+                target,
+            });
+            assert!(!status.duplicate);
+            let solution = status.unique_solution.expect("missing solution");
+            let hash = *solution.hash();
+            assert!(hash.meets(solution.backend_target()));
+            hashes.push(hash);
+        }
+        // each share is verified with its own rolled version
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
     /// Test that `initial_work` flag propagates to `WorkRegistryItem`
     #[test]
     fn test_initial_work() {
@@ -53,7 +53,11 @@ fn prepare_test_work(midstate_count: usize) -> work::Assignment {
         version: 0,
         state: [0u8; 32].into(),
     };
-    work::Assignment::new(job, vec![one_midstate; midstate_count], time)
+    work::AssignmentBuilder::new(job)
+        .midstates(vec![one_midstate; midstate_count])
+        .ntime(time)
+        .build()
+        .expect("BUG: inconsistent test work")
 }
 
 /// Task that receives solutions from hardware and sends them to channel
@@ -0,0 +1,586 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Per-chip frequency autotuning
+//!
+//! The tuner periodically evaluates nonces returned by each chip. A chip that returns less nonces
+//! than expected at its frequency or produces too many hardware errors is slowed down by one step
+//! and the frequency becomes its upper limit. Stable chips are sped up until they reach their
+//! limit. Tuning of a chip is finished when it is stable at its limit.
+//!
+//! In power-target mode the frequency limit is derived from the power budget of the hashboard
+//! and the voltage is lowered as long as it allows the chips to hash faster within the budget.
+
+use ii_logging::macros::*;
+
+use crate::counters;
+use crate::power;
+use crate::{ControlStop, FrequencySettings, RunningChain};
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Frequency change of one tuning step in Hz
+    pub frequency_step: usize,
+    /// Lowest frequency the tuner can set in Hz
+    pub min_frequency: usize,
+    /// Highest frequency the tuner can set in Hz
+    pub max_frequency: usize,
+    /// Maximal tolerated ratio of hardware errors to all nonces returned by a chip
+    pub max_error_ratio: f64,
+    /// Minimal ratio of valid nonces to nonces expected at current frequency of a chip
+    pub min_return_ratio: f64,
+    /// Time of measurement before frequencies are adjusted
+    pub period: Duration,
+    /// Scale also voltage to maximize hashrate within power budget
+    pub power_target: Option<PowerTarget>,
+}
+
+impl Config {
+    /// Split power budget of the whole miner evenly among `hashboard_count` hashboards
+    pub fn share_power_target(mut self, hashboard_count: usize) -> Self {
+        if let Some(power_target) = self.power_target.as_mut() {
+            power_target.power /= hashboard_count.max(1) as f64;
+        }
+        self
+    }
+}
+
+/// Estimation of hashboard power consumption from its voltage and chip frequencies. There is no
+/// power meter on S9 so the dynamic power of chips (proportional to `V^2 * f`) is scaled from
+/// a reference measurement of the whole miner (including PSU losses) divided among hashboards.
+/// The model is calibrated by input power of the PSU when it can be read over PMBus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerModel {
+    /// Power of one hashboard in watts at reference voltage and frequency
+    pub reference_power: f64,
+    /// Reference voltage in volts
+    pub reference_voltage: f32,
+    /// Reference average frequency of chips in Hz
+    pub reference_frequency: usize,
+}
+
+impl PowerModel {
+    pub const S9: Self = Self {
+        reference_power: 440.0,
+        reference_voltage: 8.8,
+        reference_frequency: 650_000_000,
+    };
+
+    fn voltage_ratio(&self, voltage: power::Voltage) -> f64 {
+        let ratio = f64::from(voltage.as_volts() / self.reference_voltage);
+        ratio * ratio
+    }
+
+    /// Estimated power of hashboard in watts
+    pub fn estimate(&self, voltage: power::Voltage, frequency: &FrequencySettings) -> f64 {
+        let frequency_ratio = frequency.avg() as f64 / self.reference_frequency as f64;
+        self.reference_power * self.voltage_ratio(voltage) * frequency_ratio
+    }
+
+    /// Model with reference power corrected by `measured` power of hashboard running at `voltage`
+    /// and `frequency`
+    pub fn calibrate(
+        &self,
+        measured: f64,
+        voltage: power::Voltage,
+        frequency: &FrequencySettings,
+    ) -> Self {
+        Self {
+            reference_power: self.reference_power * measured / self.estimate(voltage, frequency),
+            ..*self
+        }
+    }
+
+    /// Highest average frequency of chips in Hz whose estimated power at `voltage` does not
+    /// exceed `power`
+    pub fn max_frequency(&self, voltage: power::Voltage, power: f64) -> usize {
+        let frequency_ratio = power / (self.reference_power * self.voltage_ratio(voltage));
+        (self.reference_frequency as f64 * frequency_ratio) as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerTarget {
+    /// Power budget of one hashboard in watts
+    pub power: f64,
+    /// Voltage change of one tuning step in volts
+    pub voltage_step: f32,
+    /// Lowest voltage the tuner can set in volts
+    pub min_voltage: f32,
+    pub model: PowerModel,
+}
+
+impl PowerTarget {
+    /// Frequency limit of chips at `voltage` within frequency range allowed by `config`
+    fn max_frequency(&self, voltage: power::Voltage, config: &Config) -> usize {
+        self.model
+            .max_frequency(voltage, self.power)
+            .max(config.min_frequency)
+            .min(config.max_frequency)
+    }
+}
+
+/// Tuning state of one chip
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Chip {
+    frequency: usize,
+    /// Highest frequency that has not been proven unstable yet
+    upper_limit: usize,
+    converged: bool,
+}
+
+impl Chip {
+    /// Number of valid nonces (in shares) that a chip with `core_count` cores computes at
+    /// `frequency` during `duration`
+    fn expected_valid(frequency: usize, core_count: usize, duration: Duration) -> f64 {
+        let hashrate = frequency as f64 * core_count as f64;
+        hashrate * duration.as_secs_f64() / (1u64 << 32) as f64
+    }
+
+    /// Check `valid` shares and hardware `errors` returned by the chip during one measurement
+    fn is_stable(
+        &self,
+        config: &Config,
+        valid: usize,
+        errors: usize,
+        asic_difficulty: usize,
+        core_count: usize,
+        duration: Duration,
+    ) -> bool {
+        let error_ratio = counters::error_ratio(valid, errors, asic_difficulty);
+        let return_ratio =
+            valid as f64 / Self::expected_valid(self.frequency, core_count, duration);
+        error_ratio <= config.max_error_ratio && return_ratio >= config.min_return_ratio
+    }
+
+    fn adjust(&mut self, config: &Config, stable: bool) {
+        if stable {
+            let frequency = self.frequency + config.frequency_step;
+            if frequency <= self.upper_limit {
+                self.frequency = frequency;
+            } else {
+                self.converged = true;
+            }
+        } else {
+            self.upper_limit = self
+                .frequency
+                .saturating_sub(config.frequency_step)
+                .max(config.min_frequency);
+            // chip which is unstable even at the lowest frequency cannot be tuned any further
+            self.converged = self.frequency == self.upper_limit;
+            self.frequency = self.upper_limit;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tuner {
+    config: Config,
+    /// Number of cores on one chip of the tuned hash chain
+    core_count: usize,
+    chips: Vec<Chip>,
+}
+
+impl Tuner {
+    pub fn new(config: Config, frequency: &FrequencySettings, core_count: usize) -> Self {
+        assert!(config.frequency_step > 0, "BUG: zero frequency step");
+        assert!(config.min_frequency <= config.max_frequency);
+        let chips = frequency
+            .chip
+            .iter()
+            .map(|&frequency| Chip {
+                frequency: frequency
+                    .max(config.min_frequency)
+                    .min(config.max_frequency),
+                upper_limit: config.max_frequency,
+                converged: false,
+            })
+            .collect();
+        Self {
+            config,
+            core_count,
+            chips,
+        }
+    }
+
+    pub fn frequency(&self) -> FrequencySettings {
+        FrequencySettings {
+            chip: self.chips.iter().map(|chip| chip.frequency).collect(),
+        }
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.chips.iter().all(|chip| chip.converged)
+    }
+
+    /// Evaluate counters measured at current frequencies between `previous` and `current`
+    /// snapshots and adjust frequencies of chips which have not converged yet
+    pub fn update(&mut self, previous: &counters::HashChain, current: &counters::HashChain) {
+        if previous.started != current.started {
+            // counters have been reset in the meantime
+            return;
+        }
+        let duration = current.duration() - previous.duration();
+        for (i, chip) in self.chips.iter_mut().enumerate() {
+            if chip.converged {
+                continue;
+            }
+            match (previous.chip.get(i), current.chip.get(i)) {
+                (Some(previous), Some(current_chip)) => {
+                    let stable = chip.is_stable(
+                        &self.config,
+                        current_chip.valid - previous.valid,
+                        current_chip.errors - previous.errors,
+                        current.asic_difficulty,
+                        self.core_count,
+                        duration,
+                    );
+                    chip.adjust(&self.config, stable);
+                }
+                // chip is not present on hash chain
+                _ => chip.converged = true,
+            }
+        }
+    }
+
+    /// Tune frequencies of all chips on running `chain` until all of them converge. Tuning is
+    /// suspended while the frequency is changed by someone else (e.g. thermal throttling).
+    /// Returns the final frequencies or `None` when tuning has been interrupted.
+    async fn tune(
+        mut self,
+        chain: &RunningChain,
+        stop: &mut ControlStop,
+    ) -> Option<FrequencySettings> {
+        let hashboard_idx = chain.manager.hashboard_idx;
+        let mut applied: Option<FrequencySettings> = None;
+        while !self.is_converged() {
+            let hash_chain = match chain.hash_chain().await {
+                Some(hash_chain) => hash_chain,
+                None => {
+                    info!("Hashchain {}: frequency tuning stopped", hashboard_idx);
+                    return None;
+                }
+            };
+            let current = hash_chain.get_frequency().await;
+            let suspended = applied
+                .as_ref()
+                .map(|applied| applied.chip != current.chip)
+                .unwrap_or(false);
+            if !suspended {
+                let frequency = self.frequency();
+                if frequency.chip != current.chip {
+                    info!("Hashchain {}: tuning chips to {}", hashboard_idx, frequency);
+                    if let Err(e) = hash_chain.set_pll(&frequency).await {
+                        error!(
+                            "Hashchain {}: frequency tuning failed: {}",
+                            hashboard_idx, e
+                        );
+                        return None;
+                    }
+                }
+                applied.replace(frequency);
+            }
+
+            let previous = hash_chain.snapshot_counter().await;
+            if !stop.delay_for(self.config.period).await {
+                info!("Hashchain {}: frequency tuning stopped", hashboard_idx);
+                return None;
+            }
+            let counter = hash_chain.snapshot_counter().await;
+
+            // measurement is valid only when nobody has changed the frequency in the meantime
+            let current = hash_chain.get_frequency().await;
+            match applied.as_ref() {
+                Some(applied) if applied.chip == current.chip => self.update(&previous, &counter),
+                _ => debug!(
+                    "Hashchain {}: frequency tuning suspended (frequency changed to {})",
+                    hashboard_idx, current
+                ),
+            }
+        }
+        Some(self.frequency())
+    }
+
+    /// Tune running `chain` according to `config` and keep the chain owned until it is finished
+    pub async fn run(config: Config, chain: &RunningChain, stop: &mut ControlStop) {
+        let hashboard_idx = chain.manager.hashboard_idx;
+        let hash_chain = match chain.hash_chain().await {
+            Some(hash_chain) => hash_chain,
+            None => return,
+        };
+        let frequency = match config.power_target.clone() {
+            None => {
+                info!("Hashchain {}: frequency tuning started", hashboard_idx);
+                Self::new(
+                    config,
+                    &hash_chain.get_frequency().await,
+                    hash_chain.chip_driver.core_count(),
+                )
+                .tune(chain, stop)
+                .await
+            }
+            Some(power_target) => {
+                info!(
+                    "Hashchain {}: tuning for power target {:.0} W started",
+                    hashboard_idx, power_target.power
+                );
+                Self::tune_power_target(config, power_target, chain, stop).await
+            }
+        };
+        if let Some(frequency) = frequency {
+            info!(
+                "Hashchain {}: frequency tuning finished at {}",
+                hashboard_idx, frequency
+            );
+        }
+    }
+
+    /// Lower the voltage step by step while the frequency at which chips are stable within the
+    /// power budget increases the hashrate. Finally the best voltage and frequencies are restored.
+    async fn tune_power_target(
+        config: Config,
+        mut power_target: PowerTarget,
+        chain: &RunningChain,
+        stop: &mut ControlStop,
+    ) -> Option<FrequencySettings> {
+        let hashboard_idx = chain.manager.hashboard_idx;
+        let controller = chain.hash_chain().await?.voltage_controller();
+        let mut voltage = controller.voltage().await?;
+        let mut best: Option<(power::Voltage, FrequencySettings)> = None;
+        loop {
+            let max_frequency = power_target.max_frequency(voltage, &config);
+            info!(
+                "Hashchain {}: tuning at {} with frequency limit {:.1} MHz",
+                hashboard_idx,
+                voltage,
+                max_frequency as f32 / 1_000_000.0
+            );
+            let hash_chain = chain.hash_chain().await?;
+            let tuner = Self::new(
+                Config {
+                    max_frequency,
+                    ..config.clone()
+                },
+                &hash_chain.get_frequency().await,
+                hash_chain.chip_driver.core_count(),
+            );
+            let frequency = tuner.tune(chain, stop).await?;
+            // correct the estimation when the power can be measured
+            match chain.manager.measured_power(&*controller).await {
+                Ok(Some(power)) => {
+                    power_target.model = power_target.model.calibrate(power, voltage, &frequency)
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Hashchain {}: cannot read power: {}", hashboard_idx, e),
+            }
+            let improved = best
+                .as_ref()
+                .map(|(_, best_frequency)| frequency.total() > best_frequency.total())
+                .unwrap_or(true);
+            if !improved {
+                break;
+            }
+            best = Some((voltage, frequency));
+
+            // lower voltage leaves more power for higher frequency
+            voltage =
+                match power::Voltage::from_volts(voltage.as_volts() - power_target.voltage_step) {
+                    Ok(next_voltage) if next_voltage.as_volts() >= power_target.min_voltage => {
+                        next_voltage
+                    }
+                    _ => break,
+                };
+            if let Err(e) = controller.set_voltage(voltage).await {
+                error!("Hashchain {}: voltage tuning failed: {}", hashboard_idx, e);
+                break;
+            }
+        }
+
+        let (voltage, frequency) = best?;
+        let hash_chain = chain.hash_chain().await?;
+        // raise voltage before frequency to keep chips stable
+        if let Err(e) = controller.set_voltage(voltage).await {
+            error!("Hashchain {}: voltage tuning failed: {}", hashboard_idx, e);
+            return None;
+        }
+        if let Err(e) = hash_chain.set_pll(&frequency).await {
+            error!(
+                "Hashchain {}: frequency tuning failed: {}",
+                hashboard_idx, e
+            );
+            return None;
+        }
+        info!(
+            "Hashchain {}: estimated power {:.0} W at {}",
+            hashboard_idx,
+            power_target.model.estimate(voltage, &frequency),
+            voltage
+        );
+        Some(frequency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bm1387;
+
+    const MHZ: usize = 1_000_000;
+    const ASIC_DIFFICULTY: usize = 64;
+
+    fn config() -> Config {
+        Config {
+            frequency_step: 10 * MHZ,
+            min_frequency: 600 * MHZ,
+            max_frequency: 700 * MHZ,
+            max_error_ratio: 0.05,
+            min_return_ratio: 0.8,
+            period: Duration::from_secs(600),
+            power_target: None,
+        }
+    }
+
+    /// Simulate one measurement where each chip returns given ratio of expected valid nonces and
+    /// number of hardware errors
+    fn measure(
+        tuner: &mut Tuner,
+        counter: &mut counters::HashChain,
+        results: &[(f64, usize)],
+    ) -> FrequencySettings {
+        let previous = counter.snapshot();
+        let period = tuner.config.period;
+        counter.stopped = Some(previous.stopped.expect("BUG: missing stop time") + period);
+        for ((chip, tuner_chip), &(ratio, errors)) in
+            counter.chip.iter_mut().zip(&tuner.chips).zip(results)
+        {
+            let expected_valid =
+                Chip::expected_valid(tuner_chip.frequency, tuner.core_count, period);
+            chip.valid += (expected_valid * ratio) as usize;
+            chip.errors += errors;
+        }
+        tuner.update(&previous, counter);
+        tuner.frequency()
+    }
+
+    #[test]
+    fn test_tuner() {
+        let mut tuner = Tuner::new(
+            config(),
+            &FrequencySettings {
+                chip: vec![650 * MHZ; 4],
+            },
+            bm1387::NUM_CORES_ON_CHIP,
+        );
+        let mut counter = counters::HashChain::new(3, ASIC_DIFFICULTY);
+
+        // stable chip is sped up, chips with errors or missing nonces are slowed down
+        let frequency = measure(&mut tuner, &mut counter, &[(1.0, 0), (1.0, 100), (0.5, 0)]);
+        assert_eq!(
+            frequency.chip,
+            vec![660 * MHZ, 640 * MHZ, 640 * MHZ, 650 * MHZ]
+        );
+        // chip missing on the hash chain is not tuned
+        assert!(tuner.chips[3].converged);
+        assert!(!tuner.is_converged());
+
+        // slowed down chips converge once they are stable at their limit
+        let frequency = measure(&mut tuner, &mut counter, &[(1.0, 0), (1.0, 0), (0.5, 0)]);
+        assert_eq!(
+            frequency.chip,
+            vec![670 * MHZ, 640 * MHZ, 630 * MHZ, 650 * MHZ]
+        );
+        assert!(tuner.chips[1].converged);
+        assert!(!tuner.chips[2].converged);
+
+        // measurement is discarded when counters are reset
+        let previous = counter.snapshot();
+        counter.reset();
+        tuner.update(&previous, &counter);
+        assert_eq!(tuner.frequency().chip, frequency.chip);
+    }
+
+    #[test]
+    fn test_tuner_limits() {
+        // initial frequency is clamped to the allowed range
+        let mut tuner = Tuner::new(
+            config(),
+            &FrequencySettings {
+                chip: vec![800 * MHZ, 500 * MHZ],
+            },
+            bm1387::NUM_CORES_ON_CHIP,
+        );
+        assert_eq!(tuner.frequency().chip, vec![700 * MHZ, 600 * MHZ]);
+        let mut counter = counters::HashChain::new(2, ASIC_DIFFICULTY);
+
+        // stable chip at the maximal frequency and unstable chip at the minimal one converge
+        let frequency = measure(&mut tuner, &mut counter, &[(1.0, 0), (0.0, 0)]);
+        assert_eq!(frequency.chip, vec![700 * MHZ, 600 * MHZ]);
+        assert!(tuner.is_converged());
+    }
+
+    #[test]
+    fn test_power_model() {
+        let model = PowerModel::S9;
+        let voltage = power::Voltage::from_volts(8.8).expect("BUG: invalid voltage");
+        let frequency = FrequencySettings::from_frequency(650 * MHZ);
+        let power = model.estimate(voltage, &frequency);
+        assert!((power - model.reference_power).abs() < 1.0);
+
+        // frequency limit is the inverse of power estimation
+        let max_frequency = model.max_frequency(voltage, power);
+        assert!((max_frequency as i64 - (650 * MHZ) as i64).abs() < 1000);
+        assert!(model.max_frequency(voltage, power / 2.0) < max_frequency);
+        // lower voltage allows higher frequency within the same power budget
+        let lower_voltage = power::Voltage::from_volts(8.4).expect("BUG: invalid voltage");
+        assert!(model.max_frequency(lower_voltage, power) > max_frequency);
+        // measured power corrects the estimation
+        let calibrated = model.calibrate(power * 1.1, voltage, &frequency);
+        assert!((calibrated.estimate(voltage, &frequency) - power * 1.1).abs() < 1e-6);
+
+        let config = Config {
+            power_target: Some(PowerTarget {
+                power: 1200.0,
+                voltage_step: 0.1,
+                min_voltage: 8.0,
+                model,
+            }),
+            ..config()
+        }
+        .share_power_target(3);
+        let power_target = config
+            .power_target
+            .clone()
+            .expect("BUG: missing power target");
+        assert_eq!(power_target.power, 400.0);
+        // frequency limit is kept within the frequency range of the tuner
+        assert_eq!(power_target.max_frequency(voltage, &config), 600 * MHZ);
+        let max_frequency = power_target.max_frequency(lower_voltage, &config);
+        assert!(max_frequency > 600 * MHZ && max_frequency < 700 * MHZ);
+        let power_target = PowerTarget {
+            power: 1000.0,
+            ..power_target
+        };
+        assert_eq!(power_target.max_frequency(voltage, &config), 700 * MHZ);
+    }
+}
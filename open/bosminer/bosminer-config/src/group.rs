@@ -59,6 +59,9 @@ pub struct Descriptor {
     #[serde(flatten)]
     #[serde(skip_serializing_if = "Option::is_none")]
     strategy: Option<LoadBalanceStrategy>,
+    /// Maximal number of pools connected at once while looking for a working one (failover)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_probing_pools: Option<usize>,
 }
 
 impl Descriptor {
@@ -74,6 +77,7 @@ impl Descriptor {
             name,
             private,
             strategy: strategy.into(),
+            max_probing_pools: None,
         }
     }
 
@@ -103,6 +107,7 @@ impl Default for Descriptor {
             name: Self::DEFAULT_NAME.to_string(),
             private: false,
             strategy: None,
+            max_probing_pools: None,
         }
     }
 }
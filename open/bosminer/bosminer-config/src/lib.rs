@@ -23,16 +23,27 @@
 mod client;
 mod error;
 mod group;
+mod pool;
 
 // Reexport inner structures
 pub use client::Descriptor as ClientDescriptor;
+pub use client::NtimePolicy as ClientNtimePolicy;
+pub use client::ParseErrorPolicy as ClientParseErrorPolicy;
 pub use client::Protocol as ClientProtocol;
+pub use client::ReconnectPolicy as ClientReconnectPolicy;
+pub use client::RejectAction as ClientRejectAction;
+pub use client::RejectAlert as ClientRejectAlert;
+pub use client::RejectReason as ClientRejectReason;
+pub use client::Socks5Proxy as ClientSocks5Proxy;
+pub use client::TlsOptions as ClientTlsOptions;
 pub use client::UserInfo as ClientUserInfo;
 pub use client::URL_JAVA_SCRIPT_REGEX as CLIENT_URL_JAVA_SCRIPT_REGEX;
 
 pub use group::Descriptor as GroupDescriptor;
 pub use group::LoadBalanceStrategy;
 
+pub use pool::PoolUrl;
+
 // reexport common crates
 pub use clap;
 pub use config;
@@ -48,6 +59,39 @@ pub struct PoolConfig {
     pub user: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_error_policy: Option<ClientParseErrorPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternate_users: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submit_jitter_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntime_tolerance_s: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntime_policy: Option<ClientNtimePolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntime_rolling_s: Option<u64>,
+    #[serde(rename = "reject_alert")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject_alerts: Option<Vec<ClientRejectAlert>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconnect_policy: Option<ClientReconnectPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconnect_allowlist: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_difficulty: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_verify: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ca_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_session: Option<String>,
 }
 
 // NOTE: `#[serde(deny_unknown_fields)]` cannot be used due to flatten descriptor but the error is
@@ -0,0 +1,268 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Software backend which searches nonces on host threads. It is slow but it allows running the
+//! whole mining pipeline on a development host without any mining hardware.
+
+use ii_logging::macros::*;
+
+pub mod config;
+
+use bosminer::async_trait;
+use bosminer::hal;
+use bosminer::node;
+use bosminer::stats;
+use bosminer::work;
+use bosminer_macros::WorkSolverNode;
+
+use ii_bitcoin::MeetsTarget;
+
+use futures::executor::block_on;
+use ii_async_compat::{futures, tokio};
+use tokio::task;
+
+use lazy_static::lazy_static;
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Difficulty of solutions reported by the solver (the same as for real ASICs)
+    pub static ref CPU_TARGET: ii_bitcoin::Target = Default::default();
+}
+
+/// Number of nonces searched between checks of elapsed time
+const NONCE_BATCH_SIZE: u32 = 1 << 14;
+
+/// Represents raw solution found by host thread
+#[derive(Debug)]
+pub struct Solution {
+    /// Actual nonce
+    nonce: u32,
+    /// Index of midstate the nonce has been found for
+    midstate_idx: usize,
+}
+
+impl Solution {
+    pub fn new(nonce: u32, midstate_idx: usize) -> Self {
+        Self {
+            nonce,
+            midstate_idx,
+        }
+    }
+}
+
+impl hal::BackendSolution for Solution {
+    #[inline]
+    fn nonce(&self) -> u32 {
+        self.nonce
+    }
+
+    #[inline]
+    fn midstate_idx(&self) -> usize {
+        self.midstate_idx
+    }
+
+    #[inline]
+    fn solution_idx(&self) -> usize {
+        0
+    }
+
+    #[inline]
+    fn target(&self) -> &ii_bitcoin::Target {
+        &CPU_TARGET
+    }
+}
+
+/// Return all nonces from `nonces` which meet the `CPU_TARGET` for given work and midstate
+pub fn search(work: &work::Assignment, midstate_idx: usize, nonces: Range<u32>) -> Vec<u32> {
+    let mut header = work.block_header(midstate_idx, 0);
+    nonces
+        .filter(|nonce| {
+            header.nonce = *nonce;
+            header.hash().meets(&CPU_TARGET)
+        })
+        .collect()
+}
+
+#[derive(Debug, WorkSolverNode)]
+pub struct Backend {
+    #[member_work_solver_stats]
+    work_solver_stats: stats::BasicWorkSolver,
+    /// Generator is shared by all host threads
+    work_generator: Mutex<work::Generator>,
+    solution_sender: work::SolutionSender,
+    settings: config::Solver,
+}
+
+impl Backend {
+    pub fn new(
+        work_generator: work::Generator,
+        solution_sender: work::SolutionSender,
+        settings: config::Solver,
+    ) -> Self {
+        Self {
+            work_solver_stats: Default::default(),
+            work_generator: Mutex::new(work_generator),
+            solution_sender,
+            settings,
+        }
+    }
+
+    /// Hashrate limit of one thread in hashes per second
+    fn thread_hashrate_limit(&self) -> Option<f64> {
+        self.settings
+            .hashrate_limit
+            .map(|hashrate| hashrate / self.settings.thread_count as f64)
+    }
+
+    /// Search the work for `config::WORK_TIME` and send all found solutions
+    fn solve(&self, work: work::Assignment) {
+        let hashrate_limit = self.thread_hashrate_limit();
+        let time_per_midstate = config::WORK_TIME / work.midstates.len() as u32;
+
+        for midstate_idx in 0..work.midstates.len() {
+            let start = Instant::now();
+            let mut nonce = 0;
+            while start.elapsed() < time_per_midstate && nonce < u32::max_value() {
+                let batch_end = nonce.saturating_add(NONCE_BATCH_SIZE);
+                for solution_nonce in search(&work, midstate_idx, nonce..batch_end) {
+                    self.solution_sender.send(work::Solution::new(
+                        work.clone(),
+                        Solution::new(solution_nonce, midstate_idx),
+                        None,
+                    ));
+                }
+                nonce = batch_end;
+
+                // Slow down to keep the configured hashrate
+                if let Some(hashrate_limit) = hashrate_limit {
+                    let expected = Duration::from_secs_f64(nonce as f64 / hashrate_limit);
+                    if let Some(remaining) = expected.checked_sub(start.elapsed()) {
+                        thread::sleep(remaining);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Solve generated work until the generator is shut down
+    fn run(&self, thread_idx: usize) {
+        info!("CPU: thread {} is ready to solve the work!", thread_idx);
+        loop {
+            let work = block_on(
+                self.work_generator
+                    .lock()
+                    .expect("cannot lock work generator")
+                    .generate(),
+            );
+            match work {
+                Some(work) => self.solve(work),
+                None => break,
+            }
+        }
+        info!("CPU: thread {} has been stopped", thread_idx);
+    }
+
+    fn enable(self: Arc<Self>) {
+        for thread_idx in 0..self.settings.thread_count {
+            let backend = self.clone();
+            // Hashing is blocking operation so it must not block the regular threadpool
+            task::spawn_blocking(move || backend.run(thread_idx));
+        }
+    }
+}
+
+#[async_trait]
+impl node::WorkSolver for Backend {
+    async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
+        self.settings
+            .hashrate_limit
+            .map(|hashrate| ii_bitcoin::HashesUnit::Hashes(hashrate as u128))
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CPU")
+    }
+}
+
+#[async_trait]
+impl hal::Backend for Backend {
+    type Type = Self;
+    type Config = config::Backend;
+
+    const DEFAULT_HASHRATE_INTERVAL: Duration = config::DEFAULT_HASHRATE_INTERVAL;
+    const JOB_TIMEOUT: Duration = config::JOB_TIMEOUT;
+
+    fn create(backend_config: &mut config::Backend) -> hal::WorkNode<Self> {
+        let settings = backend_config.solver;
+        node::WorkSolverType::WorkSolver(Box::new(move |work_generator, solution_sender| {
+            Self::new(work_generator, solution_sender, settings)
+        }))
+    }
+
+    async fn init_work_hub(
+        _backend_config: config::Backend,
+        _work_hub: work::SolverBuilder<Self::Type>,
+    ) -> bosminer::Result<hal::FrontendConfig> {
+        panic!("BUG: called `init_work_hub`");
+    }
+
+    async fn init_work_solver(
+        config: config::Backend,
+        work_solver: Arc<Self>,
+    ) -> bosminer::Result<hal::FrontendConfig> {
+        // TODO: remove it after `node::WorkSolver` trait will be extended with `enable` method
+        work_solver.enable();
+
+        // Create initial client configuration
+        config.init_client().await;
+
+        Ok(hal::FrontendConfig {
+            cgminer_custom_commands: None,
+            metrics_collector: None,
+            halt: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bosminer::test_utils;
+
+    #[test]
+    fn test_search() {
+        for test_block in test_utils::TEST_BLOCKS.iter() {
+            let work: work::Assignment = test_block.into();
+            let nonces = test_block.nonce.saturating_sub(NONCE_BATCH_SIZE)
+                ..test_block.nonce.saturating_add(NONCE_BATCH_SIZE);
+            assert!(search(&work, 0, nonces).contains(&test_block.nonce));
+        }
+    }
+}
@@ -190,6 +190,8 @@ impl hal::Backend for Backend {
 
         Ok(hal::FrontendConfig {
             cgminer_custom_commands: None,
+            metrics_collector: None,
+            halt: None,
         })
     }
 }
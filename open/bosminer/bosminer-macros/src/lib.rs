@@ -215,6 +215,10 @@ fn impl_derive_mining_stats(ast: &DeriveInput, derive_name: &str) -> proc_macro2
         member_accepted,
         member_rejected,
         member_stale,
+        member_solution_age,
+        member_submission_latency,
+        member_reject_reasons,
+        member_reconnections,
         member_valid_network_diff,
         member_valid_job_diff,
         member_valid_backend_diff,
@@ -243,6 +247,10 @@ fn impl_derive_client_stats(
     let accepted = find_member(&fields, "member_accepted");
     let rejected = find_member(&fields, "member_rejected");
     let stale = find_member(&fields, "member_stale");
+    let solution_age = find_member(&fields, "member_solution_age");
+    let submission_latency = find_member(&fields, "member_submission_latency");
+    let reject_reasons = find_member(&fields, "member_reject_reasons");
+    let reconnections = find_member(&fields, "member_reconnections");
 
     stream.extend(quote! {
         impl#generics stats::Client for #name#generics {
@@ -275,6 +283,26 @@ fn impl_derive_client_stats(
             fn stale(&self) -> &stats::Meter {
                 &self.#stale
             }
+
+            #[inline]
+            fn solution_age(&self) -> &stats::AgeHistogram {
+                &self.#solution_age
+            }
+
+            #[inline]
+            fn submission_latency(&self) -> &stats::SubmissionLatency {
+                &self.#submission_latency
+            }
+
+            #[inline]
+            fn reject_reasons(&self) -> &stats::RejectReasons {
+                &self.#reject_reasons
+            }
+
+            #[inline]
+            fn reconnections(&self) -> &stats::CounterUsize {
+                &self.#reconnections
+            }
         }
     });
     stream
@@ -287,6 +315,7 @@ fn impl_derive_client_stats(
         member_start_time,
         member_last_work_time,
         member_generated_work,
+        member_midstates,
         member_last_share,
         member_best_share,
         member_valid_network_diff,
@@ -313,6 +342,7 @@ fn impl_derive_work_solver_stats(
     let fields = get_fields(&ast, derive_name);
     let last_work_time = find_member(&fields, "member_last_work_time");
     let generated_work = find_member(&fields, "member_generated_work");
+    let midstates = find_member(&fields, "member_midstates");
 
     stream.extend(quote! {
         impl#generics stats::WorkSolver for #name#generics {
@@ -325,6 +355,11 @@ fn impl_derive_work_solver_stats(
             fn generated_work(&self) -> &stats::CounterU64 {
                 &self.#generated_work
             }
+
+            #[inline]
+            fn midstates(&self) -> &stats::RateMeter {
+                &self.#midstates
+            }
         }
     });
     stream
@@ -21,13 +21,31 @@
 // contact us at opensource@braiins.com.
 
 mod cgminer;
+pub mod prometheus;
 
 use crate::hal;
 use crate::hub;
 
+use ii_async_compat::tokio;
+
+use std::net::SocketAddr;
 use std::sync::Arc;
 
-pub async fn run(core: Arc<hub::Core>, config: hal::FrontendConfig, signature: String) {
+pub async fn run(
+    core: Arc<hub::Core>,
+    config: hal::FrontendConfig,
+    metrics_listen_addr: Option<SocketAddr>,
+    signature: String,
+) {
+    // the metrics are published only when the address is configured
+    if let Some(metrics_listen_addr) = metrics_listen_addr {
+        tokio::spawn(prometheus::run(
+            core.clone(),
+            metrics_listen_addr,
+            config.metrics_collector,
+        ));
+    }
+
     let addr = "0.0.0.0:4028".parse().unwrap();
     cgminer::run(core, addr, config.cgminer_custom_commands, signature).await;
 }
@@ -23,6 +23,8 @@
 //! This module implements CGMiner compatible API server to control BOSminer and to extract
 //! statistics from it.
 
+use ii_logging::macros::*;
+
 use crate::client;
 use crate::error;
 use crate::hub;
@@ -110,7 +112,7 @@ impl Handler {
 
         let last_diff = last_job
             .as_ref()
-            .map(|job| job.target().get_difficulty() as f64)
+            .map(|job| job.difficulty() as f64)
             .unwrap_or(0.0);
         let current_block_version = last_job.map(|job| job.version()).unwrap_or_default();
 
@@ -210,12 +212,8 @@ impl Handler {
         let total_mega_hashes = valid_job_diff.shares.into_mega_hashes().into_f64();
         let backend_valid_solutions = valid_backend_diff.solutions;
         let backend_error_solutions = error_backend_diff.solutions;
-        let backend_all_solutions = backend_error_solutions + backend_valid_solutions;
-        let backend_error_ratio = if backend_all_solutions != 0 {
-            backend_error_solutions as f64 / backend_all_solutions as f64 * 100.0
-        } else {
-            0.0
-        };
+        let backend_error_ratio =
+            stats::hardware_error_ratio(backend_valid_solutions, backend_error_solutions) * 100.0;
 
         response::Asc {
             idx: idx as i32,
@@ -440,12 +438,8 @@ impl command::Handler for Handler {
         let network_valid_solutions = valid_network_diff.solutions;
         let backend_valid_solutions = valid_backend_diff.solutions;
         let backend_error_solutions = error_backend_diff.solutions;
-        let backend_all_solutions = backend_error_solutions + backend_valid_solutions;
-        let backend_error_ratio = if backend_all_solutions != 0 {
-            backend_error_solutions as f64 / backend_all_solutions as f64
-        } else {
-            0.0
-        } * 100.0;
+        let backend_error_ratio =
+            stats::hardware_error_ratio(backend_valid_solutions, backend_error_solutions) * 100.0;
         let work_utility = valid_backend_diff.shares.to_sharerate(elapsed) * 60.0;
 
         let mut pools_valid_jobs: u64 = 0;
@@ -776,6 +770,56 @@ impl command::Handler for Handler {
             user: "".to_string(),
         })
     }
+
+    async fn handle_zero(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::Zero> {
+        let parameter = parameter
+            .expect("BUG: missing ZERO parameter")
+            .as_str()
+            .expect("BUG: invalid ZERO parameter type");
+        let invalid_parameter = || response::ErrorCode::InvalidZeroParameter(parameter.to_string());
+
+        let mut args = parameter.splitn(2, ii_cgminer_api::PARAMETER_DELIMITER);
+        let which = args.next().expect("BUG: missing ZERO counter group").trim();
+        let summary = args
+            .next()
+            .expect("BUG: missing ZERO summary flag")
+            .trim()
+            .parse::<bool>()
+            .map_err(|_| invalid_parameter())?;
+
+        let groups = if which.eq_ignore_ascii_case("all") {
+            stats::CounterGroup::ALL.to_vec()
+        } else {
+            vec![which
+                .parse::<stats::CounterGroup>()
+                .map_err(|_| invalid_parameter())?]
+        };
+
+        if summary {
+            let valid_job_diff = self
+                .core
+                .frontend
+                .mining_stats()
+                .valid_job_diff()
+                .take_snapshot()
+                .await;
+            info!(
+                "CGMiner API: zeroing {} stats (valid solutions: {}, shares: {})",
+                which,
+                valid_job_diff.solutions,
+                valid_job_diff.shares.value()
+            );
+        }
+        self.core.reset_counters(&groups).await;
+
+        Ok(response::Zero {
+            which: which.to_string(),
+            summary,
+        })
+    }
 }
 
 pub async fn run(
@@ -0,0 +1,640 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! This module implements HTTP server publishing mining statistics on `/metrics` endpoint in
+//! Prometheus text exposition format. Backends can extend the published metrics with hardware
+//! specific ones (temperatures, chip errors) by providing their own `Collector`.
+
+use ii_logging::macros::*;
+
+use crate::hub;
+use crate::node::{Stats as _, WorkSolverStats as _};
+use crate::stats;
+
+use async_trait::async_trait;
+use ii_async_compat::tokio;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+
+use std::fmt::{self, Write as _};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time;
+
+use stats::TIME_MEAN_INTERVAL_15M as INTERVAL_15M;
+use stats::TIME_MEAN_INTERVAL_1M as INTERVAL_1M;
+use stats::TIME_MEAN_INTERVAL_24H as INTERVAL_24H;
+use stats::TIME_MEAN_INTERVAL_5M as INTERVAL_5M;
+use stats::TIME_MEAN_INTERVAL_5S as INTERVAL_5S;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+const MAX_REQUEST_SIZE: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl fmt::Display for MetricType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Counter => write!(f, "counter"),
+            Self::Gauge => write!(f, "gauge"),
+            Self::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+/// Metrics serialized in Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    buffer: String,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Start new metric family. All samples of the family have to follow immediately.
+    pub fn family(&mut self, name: &str, metric_type: MetricType, help: &str) {
+        writeln!(self.buffer, "# HELP {} {}", name, help).expect("BUG: cannot write metric");
+        writeln!(self.buffer, "# TYPE {} {}", name, metric_type).expect("BUG: cannot write metric");
+    }
+
+    /// Append one sample of the current metric family. Infinite values are written in the form
+    /// required by the exposition format.
+    pub fn sample<T: Into<f64>>(&mut self, name: &str, labels: &[(&str, &str)], value: T) {
+        self.buffer.push_str(name);
+        if !labels.is_empty() {
+            self.buffer.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.buffer.push(',');
+                }
+                write!(self.buffer, "{}=\"", label).expect("BUG: cannot write metric");
+                for c in value.chars() {
+                    match c {
+                        '\\' => self.buffer.push_str("\\\\"),
+                        '"' => self.buffer.push_str("\\\""),
+                        '\n' => self.buffer.push_str("\\n"),
+                        _ => self.buffer.push(c),
+                    }
+                }
+                self.buffer.push('"');
+            }
+            self.buffer.push('}');
+        }
+        let value = value.into();
+        if value.is_infinite() {
+            let sign = if value > 0.0 { '+' } else { '-' };
+            writeln!(self.buffer, " {}Inf", sign)
+        } else {
+            writeln!(self.buffer, " {}", value)
+        }
+        .expect("BUG: cannot write metric");
+    }
+
+    /// Append all samples of the current histogram family. Buckets are cumulative and bounded
+    /// by seconds.
+    pub fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        histogram: &stats::AgeHistogram,
+    ) {
+        let bucket_name = format!("{}_bucket", name);
+        let mut count = 0;
+        for (i, bucket) in histogram.take_snapshot().iter().enumerate() {
+            count += bucket;
+            let upper_bound = match histogram.bounds().get(i) {
+                Some(bound) => bound.as_secs_f64().to_string(),
+                None => "+Inf".to_string(),
+            };
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", upper_bound.as_str()));
+            self.sample(&bucket_name, &bucket_labels, count as f64);
+        }
+        self.sample(
+            &format!("{}_sum", name),
+            labels,
+            histogram.sum().as_secs_f64(),
+        );
+        self.sample(&format!("{}_count", name), labels, count as f64);
+    }
+
+    pub fn into_string(self) -> String {
+        self.buffer
+    }
+}
+
+/// Source of backend specific metrics
+#[async_trait]
+pub trait Collector: Send + Sync {
+    async fn collect(&self, metrics: &mut Metrics);
+}
+
+fn hashrate_intervals() -> [(&'static str, time::Duration); 5] {
+    [
+        ("5s", *INTERVAL_5S),
+        ("1m", *INTERVAL_1M),
+        ("5m", *INTERVAL_5M),
+        ("15m", *INTERVAL_15M),
+        ("24h", *INTERVAL_24H),
+    ]
+}
+
+/// Collect general metrics of the whole miner, all work solvers and all clients
+async fn collect_core(core: &hub::Core, metrics: &mut Metrics) {
+    let now = time::Instant::now();
+    let mining_stats = core.frontend.mining_stats();
+    let work_solvers = core.get_work_solvers().await;
+
+    const HASHRATE: &str = "bosminer_hashrate";
+    metrics.family(
+        HASHRATE,
+        MetricType::Gauge,
+        "Hashrate in hashes per second measured from valid backend solutions",
+    );
+    let valid_backend_diff = mining_stats.valid_backend_diff().take_snapshot().await;
+    for (interval_name, interval) in hashrate_intervals().iter() {
+        let hashrate = valid_backend_diff
+            .to_kilo_hashes(*interval, now)
+            .into_hashes();
+        metrics.sample(
+            HASHRATE,
+            &[("interval", *interval_name)],
+            hashrate.into_f64(),
+        );
+    }
+
+    // counters are published from session values which are not affected by reset
+    const FOUND_BLOCKS: &str = "bosminer_found_blocks_total";
+    metrics.family(
+        FOUND_BLOCKS,
+        MetricType::Counter,
+        "Number of solutions meeting network target",
+    );
+    let valid_network_diff = mining_stats.valid_network_diff().take_snapshot().await;
+    metrics.sample(
+        FOUND_BLOCKS,
+        &[],
+        valid_network_diff.session_solutions as f64,
+    );
+
+    const UPTIME: &str = "bosminer_uptime_seconds";
+    metrics.family(UPTIME, MetricType::Gauge, "Time since the start of mining");
+    metrics.sample(
+        UPTIME,
+        &[],
+        now.duration_since(*mining_stats.start_time()).as_secs_f64(),
+    );
+
+    const BEST_SHARE: &str = "bosminer_best_share_difficulty";
+    metrics.family(
+        BEST_SHARE,
+        MetricType::Gauge,
+        "Difficulty of the best share found since the start of mining",
+    );
+    let best_share = mining_stats.best_share().take_session_snapshot();
+    metrics.sample(BEST_SHARE, &[], best_share.map_or(0, |inner| *inner) as f64);
+
+    const SOLUTION_QUEUE_DEPTH: &str = "bosminer_solution_queue_depth";
+    metrics.family(
+        SOLUTION_QUEUE_DEPTH,
+        MetricType::Gauge,
+        "Number of solutions found by backends which are waiting for submission to clients",
+    );
+    metrics.sample(
+        SOLUTION_QUEUE_DEPTH,
+        &[],
+        core.solution_queue_depth() as f64,
+    );
+
+    let midstates = core
+        .frontend
+        .work_solver_stats()
+        .midstates()
+        .take_snapshot()
+        .await;
+    const MIDSTATES: &str = "bosminer_midstates_total";
+    metrics.family(
+        MIDSTATES,
+        MetricType::Counter,
+        "Number of midstates computed for generated work",
+    );
+    metrics.sample(MIDSTATES, &[], midstates.total as f64);
+
+    const MIDSTATE_RATE: &str = "bosminer_midstate_rate";
+    metrics.family(
+        MIDSTATE_RATE,
+        MetricType::Gauge,
+        "Midstates computed per second for generated work",
+    );
+    for (interval_name, interval) in hashrate_intervals().iter() {
+        metrics.sample(
+            MIDSTATE_RATE,
+            &[("interval", *interval_name)],
+            midstates.to_rate(*interval, now),
+        );
+    }
+
+    const SOLVER_HASHRATE: &str = "bosminer_work_solver_hashrate";
+    metrics.family(
+        SOLVER_HASHRATE,
+        MetricType::Gauge,
+        "Hashrate of individual work solver in hashes per second",
+    );
+    for work_solver in &work_solvers {
+        let solver = work_solver.to_string();
+        let valid_backend_diff = work_solver
+            .mining_stats()
+            .valid_backend_diff()
+            .take_snapshot()
+            .await;
+        for (interval_name, interval) in hashrate_intervals().iter() {
+            let hashrate = valid_backend_diff
+                .to_kilo_hashes(*interval, now)
+                .into_hashes();
+            metrics.sample(
+                SOLVER_HASHRATE,
+                &[("solver", solver.as_str()), ("interval", *interval_name)],
+                hashrate.into_f64(),
+            );
+        }
+    }
+
+    const HW_ERRORS: &str = "bosminer_hardware_errors_total";
+    metrics.family(
+        HW_ERRORS,
+        MetricType::Counter,
+        "Number of invalid solutions reported by work solver",
+    );
+    for work_solver in &work_solvers {
+        let error_backend_diff = work_solver
+            .mining_stats()
+            .error_backend_diff()
+            .take_snapshot()
+            .await;
+        metrics.sample(
+            HW_ERRORS,
+            &[("solver", work_solver.to_string().as_str())],
+            error_backend_diff.session_solutions as f64,
+        );
+    }
+
+    let mut clients = vec![];
+    for group in core.get_client_manager().get_groups().await {
+        clients.extend(group.get_clients().await.into_iter());
+    }
+
+    const POOL_SHARES: &str = "bosminer_pool_shares_total";
+    metrics.family(
+        POOL_SHARES,
+        MetricType::Counter,
+        "Number of shares submitted to the pool by the result of submission",
+    );
+    let mut pool_difficulty = vec![];
+    let mut pool_hashrate = vec![];
+    let mut pool_reject_ratio = vec![];
+    let mut pool_reject_reasons = vec![];
+    let mut pool_reconnections = vec![];
+    let mut pool_submission_latency = vec![];
+    for client in &clients {
+        let pool = client.descriptor().await.get_full_url();
+        let client_stats = client.stats();
+        let accepted = client_stats.accepted().take_snapshot().await;
+        let rejected = client_stats.rejected().take_snapshot().await;
+        pool_reconnections.push((pool.clone(), *client_stats.reconnections().take_snapshot()));
+        let submission_latency = client_stats.submission_latency();
+        for (stage, histogram) in &[
+            ("total", &submission_latency.total),
+            ("server", &submission_latency.server),
+        ] {
+            for quantile in stats::SUBMISSION_LATENCY_QUANTILES.iter() {
+                if let Some(latency) = histogram.quantile(*quantile, now) {
+                    pool_submission_latency.push((
+                        pool.clone(),
+                        *stage,
+                        quantile.to_string(),
+                        latency,
+                    ));
+                }
+            }
+        }
+        for (interval_name, interval) in hashrate_intervals().iter() {
+            let hashrate = accepted.to_kilo_hashes(*interval, now).into_hashes();
+            pool_hashrate.push((pool.clone(), *interval_name, hashrate.into_f64()));
+            let reject_ratio = stats::reject_ratio(&*accepted, &*rejected, *interval, now);
+            pool_reject_ratio.push((pool.clone(), *interval_name, reject_ratio));
+        }
+        for (reason, snapshot) in client_stats.reject_reasons().take_snapshot().await {
+            pool_reject_reasons.push((
+                pool.clone(),
+                reason.to_string(),
+                snapshot.session_solutions,
+            ));
+        }
+        for (status, meter) in &[
+            ("accepted", client_stats.accepted()),
+            ("rejected", client_stats.rejected()),
+            ("stale", client_stats.stale()),
+        ] {
+            let snapshot = meter.take_snapshot().await;
+            metrics.sample(
+                POOL_SHARES,
+                &[("pool", pool.as_str()), ("status", *status)],
+                snapshot.session_solutions as f64,
+            );
+            pool_difficulty.push((pool.clone(), *status, snapshot.session_shares.as_f64()));
+        }
+    }
+
+    const POOL_DIFFICULTY: &str = "bosminer_pool_difficulty_total";
+    metrics.family(
+        POOL_DIFFICULTY,
+        MetricType::Counter,
+        "Sum of difficulties of shares submitted to the pool by the result of submission",
+    );
+    for (pool, status, difficulty) in &pool_difficulty {
+        metrics.sample(
+            POOL_DIFFICULTY,
+            &[("pool", pool.as_str()), ("status", *status)],
+            *difficulty,
+        );
+    }
+
+    const POOL_HASHRATE: &str = "bosminer_pool_hashrate";
+    metrics.family(
+        POOL_HASHRATE,
+        MetricType::Gauge,
+        "Effective hashrate in hashes per second measured from shares accepted by the pool",
+    );
+    for (pool, interval_name, hashrate) in &pool_hashrate {
+        metrics.sample(
+            POOL_HASHRATE,
+            &[("pool", pool.as_str()), ("interval", *interval_name)],
+            *hashrate,
+        );
+    }
+
+    const POOL_REJECT_RATIO: &str = "bosminer_pool_reject_ratio";
+    metrics.family(
+        POOL_REJECT_RATIO,
+        MetricType::Gauge,
+        "Ratio of difficulty of shares rejected by the pool to all shares responded by the pool",
+    );
+    for (pool, interval_name, reject_ratio) in &pool_reject_ratio {
+        metrics.sample(
+            POOL_REJECT_RATIO,
+            &[("pool", pool.as_str()), ("interval", *interval_name)],
+            *reject_ratio,
+        );
+    }
+
+    const POOL_REJECTED_SHARES: &str = "bosminer_pool_rejected_shares_total";
+    metrics.family(
+        POOL_REJECTED_SHARES,
+        MetricType::Counter,
+        "Number of shares rejected by the pool by the classified reason of rejection",
+    );
+    for (pool, reason, count) in &pool_reject_reasons {
+        metrics.sample(
+            POOL_REJECTED_SHARES,
+            &[("pool", pool.as_str()), ("reason", reason.as_str())],
+            *count as f64,
+        );
+    }
+
+    const POOL_RECONNECTIONS: &str = "bosminer_pool_reconnections_total";
+    metrics.family(
+        POOL_RECONNECTIONS,
+        MetricType::Counter,
+        "Number of reconnection attempts after connection to the pool has failed",
+    );
+    for (pool, reconnections) in &pool_reconnections {
+        metrics.sample(
+            POOL_RECONNECTIONS,
+            &[("pool", pool.as_str())],
+            *reconnections as f64,
+        );
+    }
+
+    const POOL_SOLUTION_AGE: &str = "bosminer_pool_solution_age_seconds";
+    metrics.family(
+        POOL_SOLUTION_AGE,
+        MetricType::Histogram,
+        "Time since the job has been received until its solution has been submitted to the pool",
+    );
+    for client in &clients {
+        let pool = client.descriptor().await.get_full_url();
+        metrics.histogram(
+            POOL_SOLUTION_AGE,
+            &[("pool", pool.as_str())],
+            client.stats().solution_age(),
+        );
+    }
+
+    const POOL_SUBMISSION_LATENCY: &str = "bosminer_pool_submission_latency_seconds";
+    metrics.family(
+        POOL_SUBMISSION_LATENCY,
+        MetricType::Gauge,
+        &format!(
+            "Quantiles of share submission latency measured since the solution has been found \
+             (total) or since the share has been sent to the pool (server) in the last {} minutes",
+            stats::SUBMISSION_LATENCY_WINDOW.as_secs() / 60
+        ),
+    );
+    for (pool, stage, quantile, latency) in &pool_submission_latency {
+        metrics.sample(
+            POOL_SUBMISSION_LATENCY,
+            &[
+                ("pool", pool.as_str()),
+                ("stage", *stage),
+                ("quantile", quantile.as_str()),
+            ],
+            *latency,
+        );
+    }
+}
+
+/// Extract path from the request line of HTTP GET request
+fn parse_request_path(request: &[u8]) -> Option<&str> {
+    let request_line = std::str::from_utf8(request).ok()?.lines().next()?;
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("GET"), Some(path), Some(version)) if version.starts_with("HTTP/") => Some(path),
+        _ => None,
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n")
+        && request.len() < MAX_REQUEST_SIZE
+    {
+        let size = stream.read(&mut buffer).await?;
+        if size == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..size]);
+    }
+    Ok(request)
+}
+
+async fn handle_connection(
+    core: Arc<hub::Core>,
+    collector: Option<Arc<dyn Collector>>,
+    mut stream: TcpStream,
+) -> std::io::Result<()> {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(request) => request?,
+        // Do not wait for slow clients
+        Err(_) => return Ok(()),
+    };
+
+    let (status, body) = match parse_request_path(&request) {
+        Some(METRICS_PATH) => {
+            let mut metrics = Metrics::new();
+            collect_core(&core, &mut metrics).await;
+            if let Some(collector) = collector {
+                collector.collect(&mut metrics).await;
+            }
+            ("200 OK", metrics.into_string())
+        }
+        Some(_) => ("404 Not Found", "Not Found\n".to_string()),
+        None => ("400 Bad Request", "Bad Request\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+pub async fn run(
+    core: Arc<hub::Core>,
+    listen_addr: SocketAddr,
+    collector: Option<Arc<dyn Collector>>,
+) {
+    let mut listener = match TcpListener::bind(listen_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Prometheus: cannot listen on {}: {}", listen_addr, e);
+            return;
+        }
+    };
+    info!("Prometheus: metrics are published on {}", listen_addr);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let core = core.clone();
+                let collector = collector.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(core, collector, stream).await {
+                        debug!("Prometheus: connection error: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("Prometheus: cannot accept connection: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics_format() {
+        let mut metrics = Metrics::new();
+        metrics.family("bosminer_test", MetricType::Counter, "Test metric");
+        metrics.sample("bosminer_test", &[], 1u32);
+        metrics.sample(
+            "bosminer_test",
+            &[
+                ("pool", "stratum+tcp://\"user\"@pool"),
+                ("status", "accepted"),
+            ],
+            2.5,
+        );
+        metrics.sample("bosminer_test", &[("quantile", "0.99")], f64::INFINITY);
+
+        assert_eq!(
+            metrics.into_string(),
+            "# HELP bosminer_test Test metric\n\
+             # TYPE bosminer_test counter\n\
+             bosminer_test 1\n\
+             bosminer_test{pool=\"stratum+tcp://\\\"user\\\"@pool\",status=\"accepted\"} 2.5\n\
+             bosminer_test{quantile=\"0.99\"} +Inf\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_format() {
+        let histogram = stats::AgeHistogram::new(&[
+            time::Duration::from_millis(500),
+            time::Duration::from_secs(2),
+        ]);
+        for &age in &[100, 500, 1500, 3000] {
+            histogram.account_solution(time::Duration::from_millis(age));
+        }
+
+        let mut metrics = Metrics::new();
+        metrics.family("bosminer_test", MetricType::Histogram, "Test histogram");
+        metrics.histogram("bosminer_test", &[("pool", "pool")], &histogram);
+
+        assert_eq!(
+            metrics.into_string(),
+            "# HELP bosminer_test Test histogram\n\
+             # TYPE bosminer_test histogram\n\
+             bosminer_test_bucket{pool=\"pool\",le=\"0.5\"} 2\n\
+             bosminer_test_bucket{pool=\"pool\",le=\"2\"} 3\n\
+             bosminer_test_bucket{pool=\"pool\",le=\"+Inf\"} 4\n\
+             bosminer_test_sum{pool=\"pool\"} 5.1\n\
+             bosminer_test_count{pool=\"pool\"} 4\n"
+        );
+    }
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(
+            parse_request_path(b"GET /metrics HTTP/1.1\r\nHost: miner\r\n\r\n"),
+            Some(METRICS_PATH)
+        );
+        assert_eq!(parse_request_path(b"GET / HTTP/1.0\r\n\r\n"), Some("/"));
+        assert_eq!(parse_request_path(b"POST /metrics HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(parse_request_path(b"garbage"), None);
+    }
+}
@@ -22,6 +22,8 @@
 
 //! This module contains dynamically built backend hierarchy
 
+pub mod fpga;
+
 use crate::node::{self, WorkSolverType};
 
 use async_trait::async_trait;
@@ -0,0 +1,186 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Generic access to IP cores in the FPGA of the control board.
+//!
+//! Every IP core is exposed by the kernel as a UIO device with one memory-mapped register block
+//! and one interrupt. This module provides:
+//!   * `Device`, which maps the register block and implements waiting for events via interrupt
+//!   * `RxFifo` and `TxFifo` traits, which implement interrupt driven transfers of single words
+//!     and whole buffers on top of a few IP core specific register accesses
+//!
+//! Supporting a new bitstream thus only requires the register definitions and implementation
+//! of the FIFO traits for its IP cores.
+
+use crate::error::{self, ErrorKind};
+
+use failure::ResultExt;
+
+use async_trait::async_trait;
+
+use std::ops;
+use std::time::Duration;
+
+use uio_async;
+
+/// Register block of an IP core of type `T` together with its interrupt
+pub struct Device<T> {
+    regs: uio_async::UioTypedMapping<T>,
+    uio: uio_async::UioDevice,
+    /// Name of the UIO device used in error messages
+    name: String,
+}
+
+impl<T> Device<T> {
+    /// Open UIO device called `name` and map its register block
+    pub fn open(name: String) -> error::Result<Self> {
+        let uio = uio_async::UioDevice::open_by_name(&name).with_context(|_| {
+            ErrorKind::UioDevice(name.clone(), "cannot open uio device".to_string())
+        })?;
+        let regs = uio
+            .map_mapping(0)
+            .with_context(|_| {
+                ErrorKind::UioDevice(name.clone(), "cannot map uio device".to_string())
+            })?
+            .into_typed();
+        Ok(Self { regs, uio, name })
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Wait for interrupt until `cond` is satisfied.
+    /// Performs blocking wait with optional timeout and returns `Ok(None)` on timeout.
+    pub fn irq_wait_cond<F>(&self, cond: F, timeout: Option<Duration>) -> error::Result<Option<()>>
+    where
+        F: Fn() -> bool,
+    {
+        Ok(self.uio.irq_wait_cond(cond, timeout).with_context(|_| {
+            ErrorKind::UioDevice(self.name.clone(), "cannot wait for interrupt".to_string())
+        })?)
+    }
+
+    /// Wait for interrupt until `cond` is satisfied.
+    /// Async variant.
+    pub async fn async_irq_wait_cond<F>(&self, cond: F) -> error::Result<()>
+    where
+        F: Fn() -> bool,
+    {
+        Ok(self.uio.async_irq_wait_cond(cond).await.with_context(|_| {
+            ErrorKind::UioDevice(self.name.clone(), "cannot wait for interrupt".to_string())
+        })?)
+    }
+}
+
+impl<T> ops::Deref for Device<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.regs
+    }
+}
+
+/// FIFO transferring words from the FPGA to the CPU
+#[async_trait]
+pub trait RxFifo: Sync {
+    /// Register block of the IP core which provides the FIFO
+    type Regs;
+
+    fn device(&self) -> &Device<Self::Regs>;
+
+    fn is_empty(&self) -> bool;
+
+    /// Read one word from the FIFO without checking that there is any
+    fn read_unchecked(&self) -> u32;
+
+    /// Try to read from the FIFO.
+    /// Performs blocking read with timeout. Uses IRQ.
+    fn read(&self, timeout: Option<Duration>) -> error::Result<Option<u32>> {
+        let got_irq = self.device().irq_wait_cond(|| !self.is_empty(), timeout)?;
+        Ok(got_irq.map(|_| self.read_unchecked()))
+    }
+
+    /// Try to read from the FIFO.
+    /// Async variant. Uses IRQ.
+    async fn async_read(&self) -> error::Result<u32> {
+        self.device()
+            .async_irq_wait_cond(|| !self.is_empty())
+            .await?;
+        Ok(self.read_unchecked())
+    }
+
+    /// Fill the whole `buf` with words read from the FIFO.
+    /// Async variant. Uses IRQ.
+    async fn async_read_buffer(&self, buf: &mut [u32]) -> error::Result<()> {
+        for word in buf.iter_mut() {
+            *word = self.async_read().await?;
+        }
+        Ok(())
+    }
+}
+
+/// FIFO transferring words from the CPU to the FPGA
+#[async_trait]
+pub trait TxFifo: Sync {
+    /// Register block of the IP core which provides the FIFO
+    type Regs;
+
+    fn device(&self) -> &Device<Self::Regs>;
+
+    fn is_full(&self) -> bool;
+
+    /// Check that there is room for `count` words in the FIFO
+    fn has_room(&self, count: usize) -> bool;
+
+    /// Write one word to the FIFO without checking that there is room for it
+    fn write_unchecked(&self, word: u32);
+
+    /// Try to write to the FIFO.
+    /// Performs blocking write without timeout. Uses IRQ.
+    fn write(&self, word: u32) -> error::Result<()> {
+        self.device().irq_wait_cond(|| !self.is_full(), None)?;
+        self.write_unchecked(word);
+        Ok(())
+    }
+
+    /// Write all words of `buf` to the FIFO at once.
+    /// Performs blocking wait without timeout until the whole buffer fits into the FIFO and then
+    /// writes it without checking the FIFO state. Uses IRQ.
+    fn write_buffer(&self, buf: &[u32]) -> error::Result<()> {
+        self.device()
+            .irq_wait_cond(|| self.has_room(buf.len()), None)?;
+        for word in buf.iter() {
+            self.write_unchecked(*word);
+        }
+        Ok(())
+    }
+
+    /// Wait until `count` words fit into the FIFO.
+    /// Async variant. Uses IRQ.
+    async fn async_wait_for_room(&self, count: usize) -> error::Result<()> {
+        self.device()
+            .async_irq_wait_cond(|| self.has_room(count))
+            .await
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Benchmark mode is a hardware self-test which runs without any pool connection. At first the
+//! backend mines all known test blocks and it is verified that the expected nonces are found.
+//! Then the backend mines work derived from the test blocks for a given time and the effective
+//! hashrate is computed from the valid solutions.
+
+use ii_logging::macros::*;
+
+use ii_bitcoin::MeetsTarget;
+
+use crate::client;
+use crate::hal::{self, BackendConfig as _};
+use crate::test_utils::{self, block_mining};
+use crate::work;
+
+use futures::channel::mpsc;
+use futures::lock::Mutex;
+use futures::stream::StreamExt;
+use ii_async_compat::futures;
+
+use ii_async_compat::tokio;
+use tokio::time::delay_for;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default time of hashrate measurement
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(60);
+
+/// Engine generating work from test blocks over and over. Each work contains one midstate
+/// with the original block version (which has a known solution) and synthetic midstates with
+/// other versions.
+#[derive(Debug)]
+struct CyclicEngine {
+    midstate_count: usize,
+    next_idx: AtomicUsize,
+    terminated: AtomicBool,
+}
+
+impl CyclicEngine {
+    fn new(midstate_count: usize) -> Self {
+        Self {
+            midstate_count,
+            next_idx: AtomicUsize::new(0),
+            terminated: AtomicBool::new(false),
+        }
+    }
+}
+
+impl work::Engine for CyclicEngine {
+    fn terminate(&self) {
+        self.terminated.store(true, Ordering::Relaxed);
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.terminated.load(Ordering::Relaxed)
+    }
+
+    fn next_work(&self) -> work::LoopState<work::Assignment> {
+        if self.is_exhausted() {
+            return work::LoopState::Exhausted;
+        }
+        let idx = self.next_idx.fetch_add(1, Ordering::Relaxed);
+        let block_count = test_utils::TEST_BLOCKS.len();
+        let problem = block_mining::Problem::new(
+            (&test_utils::TEST_BLOCKS[idx % block_count]).into(),
+            (idx / block_count) % self.midstate_count,
+        );
+        work::LoopState::Continue(problem.into_work(self.midstate_count))
+    }
+}
+
+/// Accumulates solutions found during hashrate measurement
+#[derive(Debug, Default)]
+struct Meter {
+    shares: ii_bitcoin::Shares,
+    errors: u64,
+}
+
+impl Meter {
+    fn account_solution(&mut self, solution: &work::Solution) {
+        if solution.hash().meets(solution.backend_target()) {
+            self.shares.account_solution(solution.backend_target());
+        } else {
+            self.errors += 1;
+        }
+    }
+}
+
+/// Result of the benchmark
+#[derive(Debug)]
+pub struct Report {
+    /// All test blocks have been solved
+    pub test_blocks_solved: bool,
+    /// Shares computed during the measurement
+    pub shares: ii_bitcoin::Shares,
+    /// Number of solutions not meeting the backend target
+    pub errors: u64,
+    pub duration: Duration,
+}
+
+impl Report {
+    pub fn hashrate(&self) -> ii_bitcoin::HashesUnit {
+        self.shares.into_hashrate(self.duration)
+    }
+
+    /// Hardware passes the benchmark when it solves all test blocks and computes something
+    pub fn is_success(&self) -> bool {
+        self.test_blocks_solved && self.shares.value() > 0
+    }
+}
+
+/// Route solutions to test block registry while the test blocks are verified and then to meter
+async fn collect_solutions(
+    mut solution_queue_rx: mpsc::UnboundedReceiver<work::Solution>,
+    registry: Arc<Mutex<block_mining::Registry>>,
+    meter: Arc<Mutex<Meter>>,
+    verifying: Arc<AtomicBool>,
+) {
+    while let Some(solution) = solution_queue_rx.next().await {
+        if verifying.load(Ordering::Relaxed) {
+            trace!(
+                "benchmark: test block solution nonce={:08x} ms={}",
+                solution.nonce(),
+                solution.midstate_idx()
+            );
+            registry.lock().await.add_solution(solution.into());
+        } else {
+            meter.lock().await.account_solution(&solution);
+        }
+    }
+}
+
+/// Run the benchmark with `backend_config` and measure hashrate for `duration`
+pub async fn run<T: hal::Backend>(
+    mut backend_config: T::Config,
+    duration: Duration,
+) -> crate::Result<Report> {
+    let midstate_count = backend_config.midstate_count();
+    // There are no pools but the backend may require the manager
+    backend_config.set_client_manager(client::Manager::new(backend_config.work_strategy(), None));
+
+    let (engine_sender, solution_queue_rx, mut reschedule_receiver, work_solver_builder) =
+        block_mining::build_solvers();
+    let registry = Arc::new(Mutex::new(block_mining::Registry::new()));
+    let meter = Arc::new(Mutex::new(Meter::default()));
+    let verifying = Arc::new(AtomicBool::new(true));
+
+    let frontend_config =
+        block_mining::start_backend::<T>(backend_config, work_solver_builder).await?;
+    tokio::spawn(collect_solutions(
+        solution_queue_rx,
+        registry.clone(),
+        meter.clone(),
+        verifying.clone(),
+    ));
+
+    info!(
+        "Benchmark: mining {} test blocks in {} midstate(s)",
+        test_utils::TEST_BLOCKS.len(),
+        midstate_count
+    );
+    let test_blocks_solved = block_mining::mine_test_blocks(
+        &engine_sender,
+        &mut reschedule_receiver,
+        &registry,
+        midstate_count,
+        T::JOB_TIMEOUT,
+    )
+    .await;
+    if test_blocks_solved {
+        info!("Benchmark: all test blocks solved");
+    } else {
+        error!("Benchmark: some test blocks have not been solved");
+    }
+
+    info!("Benchmark: measuring hashrate for {}s", duration.as_secs());
+    verifying.store(false, Ordering::Relaxed);
+    *meter.lock().await = Default::default();
+    let started = Instant::now();
+    engine_sender.broadcast_engine(Arc::new(CyclicEngine::new(midstate_count)));
+    delay_for(duration).await;
+    let meter = meter.lock().await;
+    engine_sender.invalidate();
+    // Stop the hardware before reporting so that the caller can exit right away
+    if let Some(halt) = frontend_config.halt {
+        halt.halt().await;
+    }
+
+    let report = Report {
+        test_blocks_solved,
+        shares: meter.shares,
+        errors: meter.errors,
+        duration: started.elapsed(),
+    };
+    info!(
+        "Benchmark: hashrate {}/s, {} hardware error(s)",
+        report.hashrate().into_pretty_hashes(),
+        report.errors
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_utils::simulator;
+
+    #[tokio::test]
+    async fn test_benchmark() {
+        let report = run::<simulator::Backend>(Default::default(), Duration::from_secs(1))
+            .await
+            .expect("BUG: benchmark failed");
+        assert!(report.is_success());
+        assert_eq!(report.errors, 0);
+    }
+}
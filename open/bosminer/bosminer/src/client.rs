@@ -23,10 +23,15 @@
 //! This module contains common functionality related to mining protocol client and allows
 //! executing a specific type of mining protocol client instance.
 
+mod backoff;
 mod scheduler;
+pub(crate) mod transport;
 
 // Sub-modules with client implementation
 pub mod drain;
+pub mod session;
+pub mod solo;
+pub mod source;
 pub mod stratum_v2;
 pub mod stratum_v2_channels;
 
@@ -38,21 +43,27 @@ use crate::stats;
 use crate::sync::event;
 use crate::work;
 
+use ii_logging::macros::*;
+
 // Scheduler re-exports
 pub use scheduler::JobExecutor;
 
 use bosminer_config::{
-    ClientDescriptor, ClientProtocol, ClientUserInfo, GroupConfig, GroupDescriptor,
-    LoadBalanceStrategy,
+    ClientDescriptor, ClientProtocol, ClientSocks5Proxy, ClientUserInfo, GroupConfig,
+    GroupDescriptor, LoadBalanceStrategy, PoolConfig,
 };
 
 use futures::channel::mpsc;
 use futures::lock::Mutex;
-use ii_async_compat::futures;
+use ii_async_compat::{futures, tokio};
+
+use tokio::time::delay_for;
 
+use std::path::Path;
 use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time;
 
 #[derive(Debug)]
 pub struct Handle {
@@ -62,6 +73,8 @@ pub struct Handle {
     enabled: AtomicBool,
     engine_sender: Arc<work::EngineSender>,
     solution_sender: mpsc::UnboundedSender<work::Solution>,
+    difficulty_ramp: job::DifficultyRampConfig,
+    session_recorder: job::SessionRecorderConfig,
 }
 
 impl Handle {
@@ -80,6 +93,8 @@ impl Handle {
         let engine_sender = Arc::new(work::EngineSender::new(None));
 
         let job_solver = job::Solver::new(engine_sender.clone(), solution_receiver);
+        let difficulty_ramp = job_solver.job_sender.difficulty_ramp();
+        let session_recorder = job_solver.job_sender.session_recorder();
         let node: Arc<dyn node::Client> = match &descriptor.protocol {
             ClientProtocol::Drain => {
                 assert!(
@@ -88,29 +103,43 @@ impl Handle {
                 );
                 Arc::new(drain::Client::new(descriptor.get_full_url(), job_solver))
             }
-            ClientProtocol::StratumV1 => {
+            ClientProtocol::StratumV1 | ClientProtocol::StratumV1Tls => {
                 assert!(
                     channel.is_none(),
                     "BUG: protocol 'Stratum V1' does not support channel"
                 );
-                Arc::new(stratum_v2_channels::StratumClient::new(
-                    stratum_v2_channels::ConnectionDetails::from_descriptor(&descriptor),
+                // Stratum V1 is served by the channel source through the V2->V1 translation
+                Arc::new(source::Client::new(
+                    Box::new(stratum_v2_channels::StratumSource::new(
+                        stratum_v2_channels::ConnectionDetails::from_descriptor(&descriptor),
+                    )),
+                    job_solver,
+                ))
+            }
+            ClientProtocol::StratumV2(_) | ClientProtocol::StratumV2Insecure => {
+                Arc::new(source::Client::new(
+                    Box::new(stratum_v2::StratumSource::new(
+                        stratum_v2::ConnectionDetails::from_descriptor(&descriptor),
+                        backend_info,
+                        channel,
+                    )),
+                    job_solver,
+                ))
+            }
+            ClientProtocol::Solo(_) => {
+                assert!(
+                    channel.is_none(),
+                    "BUG: protocol 'Solo' does not support channel"
+                );
+                Arc::new(source::Client::new(
+                    Box::new(solo::Source::new(solo::ConnectionDetails::from_descriptor(
+                        &descriptor,
+                    ))),
                     job_solver,
                 ))
             }
-            ClientProtocol::StratumV2(_) => Arc::new(stratum_v2::StratumClient::new(
-                stratum_v2::ConnectionDetails::from_descriptor(&descriptor),
-                backend_info,
-                job_solver,
-                channel,
-            )),
-            ClientProtocol::StratumV2Insecure => Arc::new(stratum_v2::StratumClient::new(
-                stratum_v2::ConnectionDetails::from_descriptor(&descriptor),
-                backend_info,
-                job_solver,
-                channel,
-            )),
         };
+        Self::start_session_recording(&descriptor, &session_recorder);
 
         Self {
             descriptor: Arc::new(Mutex::new(descriptor)),
@@ -118,6 +147,58 @@ impl Handle {
             enabled: AtomicBool::new(false),
             engine_sender,
             solution_sender,
+            difficulty_ramp,
+            session_recorder,
+        }
+    }
+
+    /// Start recording of the session when it is requested by the client configuration
+    fn start_session_recording(
+        descriptor: &ClientDescriptor,
+        session_recorder: &job::SessionRecorderConfig,
+    ) {
+        if let Some(path) = descriptor.record_session.as_ref() {
+            match session::Recorder::create(path) {
+                Ok(recorder) => {
+                    info!(
+                        "Recording session of {} to '{}'",
+                        descriptor.get_url(true, true, false),
+                        path
+                    );
+                    *session_recorder
+                        .lock()
+                        .expect("cannot lock session recorder") = Some(recorder);
+                }
+                Err(e) => warn!(
+                    "Cannot record session of {} to '{}': {}",
+                    descriptor.get_url(true, true, false),
+                    path,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Create client obtaining jobs from custom `source` instead of the protocol specified in the
+    /// `descriptor` which is then used only for identification of the client
+    pub fn with_source(descriptor: ClientDescriptor, source: Box<dyn source::JobSource>) -> Self {
+        let (solution_sender, solution_receiver) = mpsc::unbounded();
+        // Initially register new client without ability to send work
+        let engine_sender = Arc::new(work::EngineSender::new(None));
+
+        let job_solver = job::Solver::new(engine_sender.clone(), solution_receiver);
+        let difficulty_ramp = job_solver.job_sender.difficulty_ramp();
+        let session_recorder = job_solver.job_sender.session_recorder();
+        let node: Arc<dyn node::Client> = Arc::new(source::Client::new(source, job_solver));
+
+        Self {
+            descriptor: Arc::new(Mutex::new(descriptor)),
+            node,
+            enabled: AtomicBool::new(false),
+            engine_sender,
+            solution_sender,
+            difficulty_ramp,
+            session_recorder,
         }
     }
 
@@ -126,12 +207,26 @@ impl Handle {
         self.descriptor.lock().await.clone()
     }
 
-    pub async fn change_descriptor(&self, descriptor: ClientDescriptor) {
+    /// Change descriptor of the client. Returns `false` when the settings have changed and the
+    /// running client cannot apply them.
+    pub async fn change_descriptor(&self, descriptor: ClientDescriptor) -> bool {
         // NOTE: Keep descriptor locked to synchronize descriptor changes
         let mut current_descriptor = self.descriptor.lock().await;
 
-        self.node.change_connection_details(&descriptor);
+        // Enabling and disabling of the client is handled by its handle
+        let settings_changed = ClientDescriptor {
+            enabled: current_descriptor.enabled,
+            ..descriptor.clone()
+        } != *current_descriptor;
+        // The session record is opened only when the client is created
+        if descriptor.record_session != current_descriptor.record_session {
+            return false;
+        }
+        if settings_changed && !self.node.change_connection_details(&descriptor) {
+            return false;
+        }
         *current_descriptor = descriptor;
+        true
     }
 
     pub fn replace_engine_generator(
@@ -142,6 +237,22 @@ impl Handle {
             .replace_engine_generator(engine_generator)
     }
 
+    /// Set optional difficulty ramp requested from the pool after client (re)connection
+    pub fn set_difficulty_ramp(&self, difficulty_ramp: Option<job::DifficultyRamp>) {
+        *self
+            .difficulty_ramp
+            .lock()
+            .expect("cannot lock difficulty ramp") = difficulty_ramp;
+    }
+
+    /// Start recording of jobs and submitted solutions of this client or stop it with `None`
+    pub fn set_session_recorder(&self, session_recorder: Option<session::Recorder>) {
+        *self
+            .session_recorder
+            .lock()
+            .expect("cannot lock session recorder") = session_recorder;
+    }
+
     /// Tests if solution should be delivered to this client
     /// NOTE: This comparison uses trait method `node::Info::get_unique_ptr` to unify dynamic
     /// objects to point to the same pointer otherwise direct comparison of self with other is never
@@ -179,6 +290,17 @@ impl Handle {
         self.node.status().status()
     }
 
+    /// Check if the client has been asked to stop and it has not finished yet
+    #[inline]
+    fn is_stopping(&self) -> bool {
+        match self.status() {
+            crate::sync::Status::Stopping
+            | crate::sync::Status::Declining
+            | crate::sync::Status::Failing => true,
+            _ => false,
+        }
+    }
+
     #[inline]
     fn start(&self) {
         if self.node.status().initiate_starting() {
@@ -269,27 +391,56 @@ impl PartialEq for Handle {
 
 #[derive(Debug)]
 pub struct Group {
-    pub descriptor: GroupDescriptor,
+    /// Load balance settings of the group can be changed by configuration reload
+    descriptor: StdMutex<GroupDescriptor>,
     scheduler_client_handles: Mutex<Vec<scheduler::ClientHandle>>,
     event_sender: event::Sender,
-    /// All clients in the group must support the same amount of midstates
-    midstate_count: usize,
+    /// All clients in the group must generate the same shape of work
+    work_strategy: work::engine::Strategy,
+    /// Optional difficulty ramp applied to all clients in the group
+    difficulty_ramp: Option<job::DifficultyRamp>,
 }
 
 impl Group {
     fn new(
         descriptor: GroupDescriptor,
         event_sender: event::Sender,
-        midstate_count: usize,
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
     ) -> Self {
         Self {
-            descriptor,
+            descriptor: StdMutex::new(descriptor),
             scheduler_client_handles: Mutex::new(vec![]),
             event_sender,
-            midstate_count,
+            work_strategy,
+            difficulty_ramp,
         }
     }
 
+    #[inline]
+    pub fn descriptor(&self) -> GroupDescriptor {
+        self.descriptor
+            .lock()
+            .expect("BUG: cannot lock group descriptor")
+            .clone()
+    }
+
+    #[inline]
+    pub fn name(&self) -> String {
+        self.descriptor
+            .lock()
+            .expect("BUG: cannot lock group descriptor")
+            .name
+            .clone()
+    }
+
+    fn set_descriptor(&self, descriptor: GroupDescriptor) {
+        *self
+            .descriptor
+            .lock()
+            .expect("BUG: cannot lock group descriptor") = descriptor;
+    }
+
     #[inline]
     pub async fn len(&self) -> usize {
         self.scheduler_client_handles.lock().await.len()
@@ -311,10 +462,8 @@ impl Group {
     }
 
     pub async fn push_client(&self, client_handle: Handle) -> Arc<Handle> {
-        let midstate_count = self.midstate_count;
-        let _ = client_handle.replace_engine_generator(Box::new(move |job| {
-            Arc::new(work::engine::VersionRolling::new(job, midstate_count))
-        }));
+        let _ = client_handle.replace_engine_generator(self.work_strategy.engine_generator());
+        client_handle.set_difficulty_ramp(self.difficulty_ramp);
         let _ = client_handle.try_disable();
         client_handle.set_event_sender(self.event_sender.clone());
 
@@ -394,6 +543,67 @@ impl Group {
         Ok(client_handle)
     }
 
+    /// Tests if both descriptors describe connection to the same pool with the same credentials
+    fn same_pool(a: &ClientDescriptor, b: &ClientDescriptor) -> bool {
+        a.get_full_url() == b.get_full_url() && a.password == b.password
+    }
+
+    /// Replace clients in the group with the ones described by `descriptors` in given order.
+    /// Clients already connected to the same pool are kept running and only their descriptor is
+    /// updated so the change doesn't interrupt mining on them.
+    async fn reload_clients(
+        &self,
+        descriptors: Vec<ClientDescriptor>,
+        backend_info: Option<&hal::BackendInfo>,
+    ) {
+        let len = descriptors.len();
+        for (index, descriptor) in descriptors.into_iter().enumerate() {
+            let clients = self.get_clients().await;
+            // Clients before `index` have been already reloaded
+            let mut position = None;
+            for (client_index, client) in clients.iter().enumerate().skip(index) {
+                if Self::same_pool(&client.descriptor().await, &descriptor) {
+                    position = Some(client_index);
+                    break;
+                }
+            }
+
+            let position = match position {
+                Some(client_index) => {
+                    let client = &clients[client_index];
+                    let enabled = descriptor.enabled;
+                    if client.change_descriptor(descriptor.clone()).await {
+                        if enabled {
+                            let _ = client.try_enable();
+                        } else {
+                            let _ = client.try_disable();
+                        }
+                        client_index
+                    } else {
+                        // Reconnect with new settings which the running client cannot apply
+                        self.remove_client_at(client_index)
+                            .await
+                            .expect("BUG: reloaded client is missing in the group");
+                        self.push_client(Handle::new(descriptor, backend_info.cloned(), None))
+                            .await;
+                        clients.len() - 1
+                    }
+                }
+                None => {
+                    self.push_client(Handle::new(descriptor, backend_info.cloned(), None))
+                        .await;
+                    clients.len()
+                }
+            };
+            self.move_client_to(position, index)
+                .await
+                .expect("BUG: reloaded client is missing in the group");
+        }
+
+        // Remove all remaining clients which are not present in the new configuration
+        while self.remove_client_at(len).await.is_ok() {}
+    }
+
     async fn find_client(&self, solution: &work::Solution) -> Option<Arc<Handle>> {
         self.scheduler_client_handles
             .lock()
@@ -448,17 +658,22 @@ impl GroupRegistry {
         self.list.iter_mut()
     }
 
-    /// Creates a new group that handles clients connected to pools that support `midstate_count`
-    /// of midstates.
+    /// Creates a new group that handles clients connected to pools that generate work according to
+    /// `work_strategy`.
     /// TODO: once this functionality is available through the API, we should review arbitrary
     ///  recalculation of quotas
     pub fn create_group(
         &mut self,
         descriptor: GroupDescriptor,
-        midstate_count: usize,
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
     ) -> Result<Arc<Group>, error::Client> {
         match descriptor.strategy() {
             LoadBalanceStrategy::Quota(quota) => {
+                // Zero quota would result in undefined share ratio of the group
+                if quota == 0 {
+                    Err(error::Client::ZeroQuota)?;
+                }
                 self.total_quota += quota;
             }
             LoadBalanceStrategy::FixedShareRatio(fixed_share_ratio) => {
@@ -472,16 +687,28 @@ impl GroupRegistry {
             }
         }
 
+        let group_handle = self.push_group(descriptor, work_strategy, difficulty_ramp);
+        self.recalculate_quotas(true);
+
+        Ok(group_handle)
+    }
+
+    /// Register a new group without recalculating share ratio of all groups
+    fn push_group(
+        &mut self,
+        descriptor: GroupDescriptor,
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
+    ) -> Arc<Group> {
         let group_handle = Arc::new(Group::new(
             descriptor,
             self.event_monitor.publish(),
-            midstate_count,
+            work_strategy,
+            difficulty_ramp,
         ));
         let scheduler_group_handle = scheduler::GroupHandle::new(group_handle.clone());
         self.list.push(scheduler_group_handle);
-        self.recalculate_quotas(true);
-
-        Ok(group_handle)
+        group_handle
     }
 
     pub fn get_groups(&self) -> Vec<Arc<Group>> {
@@ -498,6 +725,95 @@ impl GroupRegistry {
             .map(|scheduler_group_handle| scheduler_group_handle.group_handle.clone())
     }
 
+    /// Change descriptors of existing groups and create missing ones according to `descriptors`
+    /// which are matched with existing public groups by name. Share ratio of the resulting set of
+    /// all groups is validated at once so either all groups are changed or none of them.
+    /// Returns groups in the order of `descriptors`.
+    pub fn reload_groups(
+        &mut self,
+        descriptors: Vec<GroupDescriptor>,
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
+    ) -> Result<Vec<Arc<Group>>, error::Client> {
+        let existing_groups: Vec<_> = descriptors
+            .iter()
+            .map(|descriptor| {
+                self.list
+                    .iter()
+                    .find(|scheduler_group_handle| {
+                        !scheduler_group_handle.is_private()
+                            && scheduler_group_handle.group_handle.name() == descriptor.name
+                    })
+                    .map(|scheduler_group_handle| scheduler_group_handle.group_handle.clone())
+            })
+            .collect();
+
+        // Groups which are not reloaded keep their current strategy
+        let kept_strategies = self
+            .list
+            .iter()
+            .filter(|scheduler_group_handle| {
+                !existing_groups
+                    .iter()
+                    .flatten()
+                    .any(|group| Arc::ptr_eq(group, &scheduler_group_handle.group_handle))
+            })
+            .map(|scheduler_group_handle| {
+                scheduler_group_handle.group_handle.descriptor().strategy()
+            });
+        let strategies: Vec<_> = kept_strategies
+            .chain(descriptors.iter().map(GroupDescriptor::strategy))
+            .collect();
+        let mut total_quota = 0;
+        let mut fixed_share_ratio_count = 0;
+        let mut total_fixed_share_ratio = 0.0;
+        for strategy in strategies.iter() {
+            match *strategy {
+                LoadBalanceStrategy::Quota(quota) => {
+                    // Zero quota would result in undefined share ratio of the group
+                    if quota == 0 {
+                        Err(error::Client::ZeroQuota)?;
+                    }
+                    total_quota += quota;
+                }
+                LoadBalanceStrategy::FixedShareRatio(fixed_share_ratio) => {
+                    fixed_share_ratio_count += 1;
+                    total_fixed_share_ratio += fixed_share_ratio;
+                }
+            }
+        }
+        if fixed_share_ratio_count > 0 && fixed_share_ratio_count >= strategies.len() {
+            Err(error::Client::OnlyFixedShareRatio)?;
+        } else if total_fixed_share_ratio >= 1.0 {
+            Err(error::Client::FixedShareRatioOverflow)?;
+        }
+
+        self.total_quota = total_quota;
+        self.fixed_share_ratio_count = fixed_share_ratio_count;
+        self.total_fixed_share_ratio = total_fixed_share_ratio;
+        let mut groups = vec![];
+        for (descriptor, group) in descriptors.into_iter().zip(existing_groups) {
+            let group = match group {
+                Some(group) => {
+                    for scheduler_group_handle in self.list.iter_mut() {
+                        if Arc::ptr_eq(&scheduler_group_handle.group_handle, &group) {
+                            scheduler_group_handle.share_ratio =
+                                descriptor.get_fixed_share_ratio().unwrap_or_default();
+                        }
+                    }
+                    group.set_descriptor(descriptor);
+                    group
+                }
+                None => self.push_group(descriptor, work_strategy, difficulty_ramp),
+            };
+            groups.push(group);
+        }
+        if !self.is_empty() {
+            self.recalculate_quotas(true);
+        }
+        Ok(groups)
+    }
+
     /// Find client which given solution is associated with
     async fn find_client(&self, solution: &work::Solution) -> Option<Arc<Handle>> {
         for scheduler_group_handle in &self.list {
@@ -547,16 +863,24 @@ impl GroupRegistry {
 pub struct Manager {
     group_registry: Arc<Mutex<GroupRegistry>>,
     event_monitor: event::Monitor,
-    midstate_count: usize,
+    work_strategy: work::engine::Strategy,
+    difficulty_ramp: Option<job::DifficultyRamp>,
 }
 
 impl Manager {
-    pub fn new(midstate_count: usize) -> Self {
+    /// Period of checking whether all clients have been stopped
+    const STOP_POLL_PERIOD: time::Duration = time::Duration::from_millis(100);
+
+    pub fn new(
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
+    ) -> Self {
         let event_monitor = event::Monitor::new();
         Self {
             group_registry: Arc::new(Mutex::new(GroupRegistry::new(event_monitor.clone()))),
             event_monitor,
-            midstate_count,
+            work_strategy,
+            difficulty_ramp,
         }
     }
 
@@ -574,15 +898,8 @@ impl Manager {
                 let group = self.create_group(group_config.descriptor).await?;
                 if let Some(pool_configs) = group_config.pools {
                     for pool_config in pool_configs {
-                        let descriptor = ClientDescriptor::create(
-                            pool_config.url.as_str(),
-                            &ClientUserInfo::new(
-                                pool_config.user.as_str(),
-                                pool_config.password.as_deref(),
-                            ),
-                            pool_config.enabled.unwrap_or(default_pool_enabled),
-                        )
-                        .map_err(|e| e.to_string())?;
+                        let descriptor =
+                            Self::create_client_descriptor(&pool_config, default_pool_enabled)?;
                         let client_handle = Handle::new(descriptor, backend_info.cloned(), None);
                         group.push_client(client_handle).await;
                     }
@@ -592,6 +909,156 @@ impl Manager {
         Ok(())
     }
 
+    /// Mine jobs replayed from the session recorded at `path` instead of connecting to any pool
+    pub async fn load_session_replay<P: AsRef<Path>>(&self, path: P) -> error::Result<()> {
+        let replay = session::Replay::open(path)?;
+        // The descriptor only identifies the client, the jobs are provided by the replay
+        let descriptor = ClientDescriptor::create(
+            format!("{}://replay", ClientProtocol::SCHEME_DRAIN).as_str(),
+            &ClientUserInfo::new("replay", None),
+            true,
+        )
+        .map_err(|e| e.to_string())?;
+        let group = self.create_group(Default::default()).await?;
+        group
+            .push_client(Handle::with_source(descriptor, Box::new(replay)))
+            .await;
+        Ok(())
+    }
+
+    /// Build client descriptor with all optional settings from pool configuration
+    fn create_client_descriptor(
+        pool_config: &PoolConfig,
+        default_pool_enabled: bool,
+    ) -> error::Result<ClientDescriptor> {
+        let mut descriptor = ClientDescriptor::create(
+            pool_config.url.as_str(),
+            &ClientUserInfo::new(pool_config.user.as_str(), pool_config.password.as_deref()),
+            pool_config.enabled.unwrap_or(default_pool_enabled),
+        )
+        .map_err(|e| e.to_string())?;
+        if let Some(parse_error_policy) = pool_config.parse_error_policy {
+            descriptor.parse_error_policy = parse_error_policy;
+        }
+        if let Some(alternate_users) = pool_config.alternate_users.as_ref() {
+            descriptor.alternate_users = alternate_users.clone();
+        }
+        if let Some(submit_jitter_ms) = pool_config.submit_jitter_ms {
+            descriptor.submit_jitter = time::Duration::from_millis(submit_jitter_ms);
+        }
+        if let Some(compression) = pool_config.compression {
+            descriptor.compression = compression;
+        }
+        if let Some(ntime_tolerance_s) = pool_config.ntime_tolerance_s {
+            descriptor.ntime_tolerance = Some(time::Duration::from_secs(ntime_tolerance_s));
+        }
+        if let Some(ntime_policy) = pool_config.ntime_policy {
+            descriptor.ntime_policy = ntime_policy;
+        }
+        if let Some(ntime_rolling_s) = pool_config.ntime_rolling_s {
+            descriptor.ntime_rolling = Some(time::Duration::from_secs(ntime_rolling_s));
+        }
+        if let Some(reject_alerts) = pool_config.reject_alerts.as_ref() {
+            for alert in reject_alerts {
+                if !(0.0..=1.0).contains(&alert.max_ratio) {
+                    return Err(format!(
+                        "ratio {} of reject alert '{}' is out of range 0-1",
+                        alert.max_ratio, alert.reason
+                    )
+                    .into());
+                }
+            }
+            descriptor.reject_alerts = reject_alerts.clone();
+        }
+        if let Some(reconnect_policy) = pool_config.reconnect_policy {
+            descriptor.reconnect_policy = reconnect_policy;
+        }
+        if let Some(reconnect_allowlist) = pool_config.reconnect_allowlist.as_ref() {
+            descriptor.reconnect_allowlist = reconnect_allowlist.clone();
+        }
+        if let Some(suggested_difficulty) = pool_config.suggested_difficulty {
+            if suggested_difficulty == 0 {
+                return Err("suggested difficulty must be greater than zero".into());
+            }
+            descriptor.suggested_difficulty = Some(suggested_difficulty);
+        }
+        if let Some(tls_verify) = pool_config.tls_verify {
+            descriptor.tls.verify = tls_verify;
+        }
+        if let Some(tls_ca_file) = pool_config.tls_ca_file.as_ref() {
+            descriptor.tls.ca_file = Some(tls_ca_file.clone());
+        }
+        if let Some(tls_server_name) = pool_config.tls_server_name.as_ref() {
+            descriptor.tls.server_name = Some(tls_server_name.clone());
+        }
+        if let Some(proxy) = pool_config.proxy.as_ref() {
+            descriptor.proxy =
+                Some(ClientSocks5Proxy::parse(proxy.as_str()).map_err(|e| e.to_string())?);
+        }
+        if let Some(record_session) = pool_config.record_session.as_ref() {
+            if record_session.is_empty() {
+                return Err("path of the session record must not be empty".into());
+            }
+            descriptor.record_session = Some(record_session.clone());
+        }
+        Ok(descriptor)
+    }
+
+    /// Apply new pool configuration without restarting the miner. Groups are matched by name and
+    /// clients by pool URL with credentials so unchanged pools keep their connection. Groups which
+    /// are missing in the new configuration are only emptied because removing them would change
+    /// share ratio of all other groups.
+    pub async fn reload_config<T>(
+        &self,
+        group_configs: T,
+        backend_info: Option<&hal::BackendInfo>,
+        default_pool_enabled: bool,
+    ) -> error::Result<()>
+    where
+        T: Into<Option<Vec<GroupConfig>>>,
+    {
+        // Check the whole configuration before any change is made
+        let mut new_groups = vec![];
+        for group_config in group_configs.into().unwrap_or_default() {
+            let mut descriptors = vec![];
+            for pool_config in group_config.pools.iter().flatten() {
+                descriptors.push(Self::create_client_descriptor(
+                    pool_config,
+                    default_pool_enabled,
+                )?);
+            }
+            new_groups.push((group_config.descriptor, descriptors));
+        }
+
+        let (group_descriptors, client_descriptors): (Vec<_>, Vec<_>) =
+            new_groups.into_iter().unzip();
+
+        let groups = self.get_groups().await;
+        let reloaded_groups = self.group_registry.lock().await.reload_groups(
+            group_descriptors,
+            self.work_strategy,
+            self.difficulty_ramp,
+        )?;
+        for group in groups.iter() {
+            if !reloaded_groups
+                .iter()
+                .any(|reloaded_group| Arc::ptr_eq(reloaded_group, group))
+            {
+                info!("Removing all pools from group '{}'", group.name());
+                group.reload_clients(vec![], backend_info).await;
+            }
+        }
+        for (group, client_descriptors) in reloaded_groups.into_iter().zip(client_descriptors) {
+            info!(
+                "Reloading group '{}' with {} pool(s)",
+                group.name(),
+                client_descriptors.len()
+            );
+            group.reload_clients(client_descriptors, backend_info).await;
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn subscribe_to_clients_status_changes(&self) -> event::Receiver {
         self.event_monitor.subscribe()
@@ -602,10 +1069,11 @@ impl Manager {
         &self,
         descriptor: GroupDescriptor,
     ) -> Result<Arc<Group>, error::Client> {
-        self.group_registry
-            .lock()
-            .await
-            .create_group(descriptor, self.midstate_count)
+        self.group_registry.lock().await.create_group(
+            descriptor,
+            self.work_strategy,
+            self.difficulty_ramp,
+        )
     }
 
     pub async fn create_or_get_default_group(&self) -> Arc<Group> {
@@ -613,7 +1081,7 @@ impl Manager {
         match group_registry.get_group(GroupDescriptor::DEFAULT_INDEX) {
             Some(group) => group,
             None => group_registry
-                .create_group(Default::default(), self.midstate_count)
+                .create_group(Default::default(), self.work_strategy, self.difficulty_ramp)
                 .expect("BUG: cannot create default group"),
         }
     }
@@ -630,4 +1098,275 @@ impl Manager {
     pub async fn get_groups(&self) -> Vec<Arc<Group>> {
         self.group_registry.lock().await.get_groups()
     }
+
+    /// Disable all clients to close their connections and wait until they are stopped or the
+    /// `timeout` expires
+    pub async fn stop_all(&self, timeout: time::Duration) {
+        let mut clients = vec![];
+        for group in self.get_groups().await {
+            clients.extend(group.get_clients().await);
+        }
+        for client in clients.iter() {
+            let _ = client.try_disable();
+        }
+
+        let deadline = time::Instant::now() + timeout;
+        while clients.iter().any(|client| client.is_stopping()) {
+            if time::Instant::now() >= deadline {
+                warn!("Some clients have not been stopped in {:?}", timeout);
+                break;
+            }
+            delay_for(Self::STOP_POLL_PERIOD).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool_config(url: &str, user: &str, submit_jitter_ms: Option<u64>) -> PoolConfig {
+        PoolConfig {
+            enabled: Some(false),
+            url: url.to_string(),
+            user: user.to_string(),
+            password: None,
+            parse_error_policy: None,
+            alternate_users: None,
+            submit_jitter_ms,
+            compression: None,
+            ntime_tolerance_s: None,
+            ntime_policy: None,
+            ntime_rolling_s: None,
+            reject_alerts: None,
+            reconnect_policy: None,
+            reconnect_allowlist: None,
+            suggested_difficulty: None,
+            tls_verify: None,
+            tls_ca_file: None,
+            tls_server_name: None,
+            proxy: None,
+            record_session: None,
+        }
+    }
+
+    fn group_config(name: &str, quota: usize, pools: Vec<PoolConfig>) -> GroupConfig {
+        GroupConfig {
+            descriptor: GroupDescriptor::new(
+                name.to_string(),
+                false,
+                LoadBalanceStrategy::Quota(quota),
+            ),
+            pools: Some(pools),
+        }
+    }
+
+    async fn client_users(group: &Group) -> Vec<String> {
+        let mut users = vec![];
+        for client in group.get_clients().await {
+            users.push(client.descriptor().await.user);
+        }
+        users
+    }
+
+    #[tokio::test]
+    async fn test_reload_config() {
+        let manager = Manager::new(work::engine::Strategy::SingleMidstate, None);
+        manager
+            .load_config(
+                vec![group_config(
+                    "main",
+                    1,
+                    vec![
+                        pool_config("stratum+tcp://a.example.com:3333", "a", None),
+                        pool_config("stratum+tcp://b.example.com:3333", "b", None),
+                        pool_config("stratum+tcp://c.example.com:3333", "c", None),
+                    ],
+                )],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot load configuration");
+        let group = manager
+            .get_groups()
+            .await
+            .pop()
+            .expect("BUG: missing group");
+        let clients = group.get_clients().await;
+
+        // reorder pools, remove one of them, change settings of another one and add a new one
+        manager
+            .reload_config(
+                vec![
+                    group_config(
+                        "main",
+                        3,
+                        vec![
+                            pool_config("stratum+tcp://c.example.com:3333", "c", None),
+                            pool_config("stratum+tcp://d.example.com:3333", "d", None),
+                            pool_config("stratum+tcp://a.example.com:3333", "a", Some(100)),
+                        ],
+                    ),
+                    group_config(
+                        "backup",
+                        1,
+                        vec![pool_config("stratum+tcp://e.example.com:3333", "e", None)],
+                    ),
+                ],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot reload configuration");
+
+        let groups = manager.get_groups().await;
+        assert_eq!(groups.len(), 2);
+        assert!(Arc::ptr_eq(&groups[0], &group));
+        assert_eq!(group.descriptor().get_quota(), Some(3));
+        assert_eq!(client_users(&group).await, vec!["c", "d", "a"]);
+        assert_eq!(client_users(&groups[1]).await, vec!["e"]);
+
+        let reloaded_clients = group.get_clients().await;
+        // unchanged pool keeps its client
+        assert!(Arc::ptr_eq(&reloaded_clients[0], &clients[2]));
+        // Stratum V1 client cannot apply new settings so it is replaced
+        assert!(!Arc::ptr_eq(&reloaded_clients[2], &clients[0]));
+        assert_eq!(
+            reloaded_clients[2].descriptor().await.submit_jitter,
+            time::Duration::from_millis(100)
+        );
+
+        // group missing in the configuration is only emptied
+        manager
+            .reload_config(
+                vec![group_config(
+                    "main",
+                    3,
+                    vec![pool_config("stratum+tcp://c.example.com:3333", "c", None)],
+                )],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot reload configuration");
+        let groups = manager.get_groups().await;
+        assert_eq!(groups.len(), 2);
+        assert!(groups[1].is_empty().await);
+        assert!(Arc::ptr_eq(
+            &group.get_clients().await[0],
+            &reloaded_clients[0]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reload_invalid_config() {
+        let manager = Manager::new(work::engine::Strategy::SingleMidstate, None);
+        manager
+            .load_config(
+                vec![group_config(
+                    "main",
+                    1,
+                    vec![pool_config("stratum+tcp://a.example.com:3333", "a", None)],
+                )],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot load configuration");
+        let group = manager
+            .get_groups()
+            .await
+            .pop()
+            .expect("BUG: missing group");
+
+        // invalid pool is detected before any change is made
+        assert!(manager
+            .reload_config(
+                vec![group_config(
+                    "main",
+                    2,
+                    vec![
+                        pool_config("stratum+tcp://b.example.com:3333", "b", None),
+                        pool_config("invalid://c.example.com:3333", "c", None),
+                    ],
+                )],
+                None,
+                false,
+            )
+            .await
+            .is_err());
+        assert_eq!(group.descriptor().get_quota(), Some(1));
+        assert_eq!(client_users(&group).await, vec!["a"]);
+
+        // the only group with quota cannot be switched to fixed share ratio
+        let mut config = group_config("main", 1, vec![]);
+        config.descriptor = GroupDescriptor::new(
+            "main".to_string(),
+            false,
+            LoadBalanceStrategy::FixedShareRatio(0.5),
+        );
+        assert!(manager
+            .reload_config(vec![config], None, false)
+            .await
+            .is_err());
+        assert_eq!(group.descriptor().get_quota(), Some(1));
+
+        // invalid group is detected before any other group is changed
+        assert!(manager
+            .reload_config(
+                vec![
+                    group_config("main", 2, vec![]),
+                    group_config("backup", 0, vec![])
+                ],
+                None,
+                false,
+            )
+            .await
+            .is_err());
+        assert_eq!(group.descriptor().get_quota(), Some(1));
+        assert_eq!(manager.get_groups().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reload_swapped_strategies() {
+        let fixed_share_ratio_config = |name: &str| {
+            let mut config = group_config(name, 1, vec![]);
+            config.descriptor = GroupDescriptor::new(
+                name.to_string(),
+                false,
+                LoadBalanceStrategy::FixedShareRatio(0.5),
+            );
+            config
+        };
+        let manager = Manager::new(work::engine::Strategy::SingleMidstate, None);
+        manager
+            .load_config(
+                vec![
+                    group_config("main", 1, vec![]),
+                    fixed_share_ratio_config("backup"),
+                ],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot load configuration");
+
+        // the configuration is valid as a whole although it cannot be applied group by group
+        manager
+            .reload_config(
+                vec![
+                    fixed_share_ratio_config("main"),
+                    group_config("backup", 1, vec![]),
+                ],
+                None,
+                false,
+            )
+            .await
+            .expect("BUG: cannot reload configuration");
+        let groups = manager.get_groups().await;
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].descriptor().get_fixed_share_ratio(), Some(0.5));
+        assert_eq!(groups[1].descriptor().get_quota(), Some(1));
+    }
 }
@@ -0,0 +1,144 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Exponential backoff of reconnection attempts to remote server
+
+use rand::Rng;
+
+use std::time;
+
+/// Delay before the first reconnection attempt
+pub const DEFAULT_INITIAL_DELAY: time::Duration = time::Duration::from_secs(1);
+/// Cap of the delay between reconnection attempts
+pub const DEFAULT_MAX_DELAY: time::Duration = time::Duration::from_secs(300);
+
+/// Computes delays between consecutive reconnection attempts. The delay is doubled after each
+/// failed attempt up to the cap and it is randomized to the interval `[delay / 2, delay)` so that
+/// many miners disconnected at the same time do not reconnect at once.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial_delay: time::Duration,
+    max_delay: time::Duration,
+    /// Number of attempts since the last successful connection
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(initial_delay: time::Duration, max_delay: time::Duration) -> Self {
+        assert!(initial_delay <= max_delay);
+        Self {
+            initial_delay,
+            max_delay,
+            attempts: 0,
+        }
+    }
+
+    /// Number of attempts since the last successful connection
+    #[inline]
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Delay without jitter before the next attempt
+    fn base_delay(&self) -> time::Duration {
+        // avoid overflow of the multiplier, the cap is reached much earlier anyway
+        let multiplier = 1u32 << self.attempts.min(16);
+        self.initial_delay
+            .checked_mul(multiplier)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Delay before the next attempt where `jitter` is from interval `[0, 1)`
+    fn next_delay_with_jitter(&mut self, jitter: f64) -> time::Duration {
+        let delay = self.base_delay();
+        self.attempts = self.attempts.saturating_add(1);
+        delay.mul_f64(1.0 - jitter / 2.0)
+    }
+
+    /// Delay before the next attempt
+    pub fn next_delay(&mut self) -> time::Duration {
+        let jitter = rand::thread_rng().gen_range(0.0, 1.0);
+        self.next_delay_with_jitter(jitter)
+    }
+
+    /// Start again from the initial delay after successful connection
+    #[inline]
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_DELAY, DEFAULT_MAX_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_exponential() {
+        let mut backoff = Backoff::new(time::Duration::from_secs(1), time::Duration::from_secs(10));
+        let delays: Vec<_> = (0..6)
+            .map(|_| backoff.next_delay_with_jitter(0.0).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.attempts(), 6);
+
+        // the delay is capped even after many attempts
+        for _ in 0..100 {
+            backoff.next_delay_with_jitter(0.0);
+        }
+        assert_eq!(
+            backoff.next_delay_with_jitter(0.0),
+            time::Duration::from_secs(10)
+        );
+
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(
+            backoff.next_delay_with_jitter(0.0),
+            time::Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let max_delay = time::Duration::from_secs(300);
+        let mut backoff = Backoff::new(time::Duration::from_secs(4), max_delay);
+        assert_eq!(
+            backoff.next_delay_with_jitter(0.5),
+            time::Duration::from_secs(3)
+        );
+
+        for _ in 0..100 {
+            let base_delay = backoff.base_delay();
+            let delay = backoff.next_delay();
+            assert!(delay <= base_delay);
+            assert!(delay >= base_delay / 2);
+            assert!(delay <= max_delay);
+        }
+    }
+}
@@ -44,7 +44,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use std::time;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Job {
     client: Weak<Client>,
     difficulty: Difficulty,
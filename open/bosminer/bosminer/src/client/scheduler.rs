@@ -20,10 +20,15 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use ii_logging::macros::*;
+
 use crate::client;
-use crate::sync::event;
+use crate::stats;
+use crate::sync::{self, event};
 use crate::work;
 
+use bosminer_config::{ClientRejectAction, ClientRejectReason};
+
 use futures::channel::mpsc;
 use futures::lock::{Mutex, MutexGuard};
 use ii_async_compat::{futures, FutureExt};
@@ -31,6 +36,59 @@ use ii_async_compat::{futures, FutureExt};
 use std::sync::Arc;
 use std::time;
 
+/// Maximal ratio of shares rejected within last 5 minutes before the client is bypassed and the
+/// next client in the group is used instead
+const MAX_REJECT_RATIO: f64 = 0.05;
+/// Minimal number of rejected solutions required for the decision so that a few rejects just after
+/// connection cannot cause failover
+const MIN_REJECTED_SOLUTIONS: u64 = 10;
+/// How long a client rejecting shares is bypassed before it is tried again
+const REJECT_PENALTY: time::Duration = time::Duration::from_secs(300);
+
+/// Failover policy which leaves clients rejecting too many shares
+#[derive(Debug, Clone, Default)]
+struct RejectGuard {
+    /// Number of rejected solutions at the moment the client has been (re)admitted
+    baseline: u64,
+    /// The client is bypassed until this time
+    penalized_until: Option<time::Instant>,
+    /// Reasons whose configured threshold is currently exceeded
+    alerted_reasons: Vec<ClientRejectReason>,
+}
+
+impl RejectGuard {
+    /// Update state of the alert for `reason` and return `true` when it has changed
+    fn update_alert(&mut self, reason: ClientRejectReason, exceeded: bool) -> bool {
+        let position = self.alerted_reasons.iter().position(|r| *r == reason);
+        match (position, exceeded) {
+            (None, true) => self.alerted_reasons.push(reason),
+            (Some(i), false) => {
+                self.alerted_reasons.swap_remove(i);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns `true` when the client should be bypassed. The decision is made when the rejected
+    /// shares `exceeded` some threshold.
+    fn check(&mut self, exceeded: bool, rejected_solutions: u64, now: time::Instant) -> bool {
+        if let Some(penalized_until) = self.penalized_until {
+            if now < penalized_until {
+                return true;
+            }
+            // Give the client another chance and take into account only new rejects
+            self.penalized_until = None;
+            self.baseline = rejected_solutions;
+        }
+        if rejected_solutions.saturating_sub(self.baseline) >= MIN_REJECTED_SOLUTIONS && exceeded {
+            self.penalized_until = Some(now + REJECT_PENALTY);
+            return true;
+        }
+        false
+    }
+}
+
 /// This struct cannot be shared and it is possible to use mutable references. However, the
 /// client handle is shared object with interior mutability scheduler::ClientHandle. It solves
 /// many synchronization problems.
@@ -38,12 +96,14 @@ use std::time;
 pub struct ClientHandle {
     pub client_handle: Arc<client::Handle>,
     last_generated_work: u64,
+    reject_guard: RejectGuard,
 }
 
 impl ClientHandle {
     pub fn new(client_handle: Arc<client::Handle>) -> Self {
         Self {
             last_generated_work: Self::get_generated_work(&client_handle),
+            reject_guard: Default::default(),
             client_handle,
         }
     }
@@ -74,6 +134,57 @@ impl ClientHandle {
         }
     }
 
+    /// Check if the remote server rejects too many shares and the client should be bypassed
+    async fn is_rejecting_shares(&mut self) -> bool {
+        let now = time::Instant::now();
+        let client_stats = self.client_handle.stats();
+        let accepted = client_stats.accepted().take_snapshot().await;
+        let rejected = client_stats.rejected().take_snapshot().await;
+        let interval = *stats::TIME_MEAN_INTERVAL_5M;
+        let mut exceeded =
+            stats::reject_ratio(&*accepted, &*rejected, interval, now) > MAX_REJECT_RATIO;
+
+        let descriptor = self.client_handle.descriptor().await;
+        for alert in &descriptor.reject_alerts {
+            let reason_rejected = client_stats
+                .reject_reasons()
+                .get(alert.reason)
+                .take_snapshot()
+                .await;
+            let ratio = stats::reject_reason_ratio(
+                &*accepted,
+                &*rejected,
+                &*reason_rejected,
+                interval,
+                now,
+            );
+            let alert_exceeded = ratio > alert.max_ratio;
+            if self.reject_guard.update_alert(alert.reason, alert_exceeded) {
+                if alert_exceeded {
+                    warn!(
+                        "Scheduler: {} rejects {:.1}% of shares as '{}' (threshold {:.1}%)",
+                        descriptor.get_full_url(),
+                        ratio * 100.0,
+                        alert.reason,
+                        alert.max_ratio * 100.0
+                    );
+                } else {
+                    info!(
+                        "Scheduler: {} rejects shares as '{}' below the threshold again",
+                        descriptor.get_full_url(),
+                        alert.reason
+                    );
+                }
+            }
+            if alert_exceeded && alert.action == ClientRejectAction::Switch {
+                exceeded = true;
+            }
+        }
+
+        self.reject_guard
+            .check(exceeded, rejected.session_solutions, now)
+    }
+
     fn get_generated_work(client_handle: &Arc<client::Handle>) -> u64 {
         *client_handle
             .node
@@ -118,7 +229,7 @@ impl GroupHandle {
             active_client: None,
             generated_work: 0,
             share_ratio: group_handle
-                .descriptor
+                .descriptor()
                 .get_fixed_share_ratio()
                 .unwrap_or_default(),
             group_handle,
@@ -127,25 +238,29 @@ impl GroupHandle {
 
     #[inline]
     pub fn is_private(&self) -> bool {
-        self.group_handle.descriptor.private
+        self.group_handle.descriptor().private
     }
 
     #[inline]
     pub fn has_fixed_share_ratio(&self) -> bool {
         self.group_handle
-            .descriptor
+            .descriptor()
             .get_fixed_share_ratio()
             .is_some()
     }
 
     #[inline]
     pub fn get_quota(&self) -> Option<usize> {
-        self.group_handle.descriptor.get_quota()
+        self.group_handle.descriptor().get_quota()
     }
 
     async fn update_status(&mut self) {
         let mut scheduler_client_handles = self.group_handle.scheduler_client_handles.lock().await;
         let mut generated_work_delta = 0;
+        // enabled clients with higher priority than the active one
+        let mut candidates = Vec::new();
+        // running client used only when there is no other client available
+        let mut fallback_client = None;
 
         self.active_client = None;
         for scheduler_client_handle in scheduler_client_handles.iter_mut() {
@@ -153,9 +268,17 @@ impl GroupHandle {
             match self.active_client {
                 None => {
                     if scheduler_client_handle.is_running() {
-                        self.active_client = Some(scheduler_client_handle.client_handle.clone());
-                    } else {
-                        let _ = scheduler_client_handle.try_start();
+                        if scheduler_client_handle.is_rejecting_shares().await {
+                            // Keep the client connected and try the next one
+                            fallback_client.get_or_insert_with(|| {
+                                scheduler_client_handle.client_handle.clone()
+                            });
+                        } else {
+                            self.active_client =
+                                Some(scheduler_client_handle.client_handle.clone());
+                        }
+                    } else if scheduler_client_handle.client_handle.is_enabled() {
+                        candidates.push(scheduler_client_handle.client_handle.clone());
                     }
                 }
                 Some(_) => {
@@ -163,6 +286,18 @@ impl GroupHandle {
                 }
             }
         }
+        if self.active_client.is_none() {
+            self.active_client = fallback_client;
+        }
+
+        let statuses: Vec<_> = candidates
+            .iter()
+            .map(|client_handle| client_handle.status())
+            .collect();
+        let max_probing_pools = self.group_handle.descriptor().max_probing_pools;
+        for i in select_probed_clients(&statuses, max_probing_pools) {
+            candidates[i].start();
+        }
 
         self.generated_work += generated_work_delta;
     }
@@ -173,6 +308,49 @@ impl GroupHandle {
     }
 }
 
+/// Select clients which should be connected (probed) from `statuses` of candidates sorted by
+/// priority so that no more than `max_probing_pools` connection attempts run concurrently. Clients
+/// which have not been tried yet take precedence over the failed ones so that every pool is
+/// eventually probed. Returns indexes of the clients to be started.
+fn select_probed_clients(
+    statuses: &[sync::Status],
+    max_probing_pools: Option<usize>,
+) -> Vec<usize> {
+    let is_probing = |status: &sync::Status| match status {
+        sync::Status::Starting
+        | sync::Status::Retrying
+        | sync::Status::Restarting
+        | sync::Status::Recovering => true,
+        _ => false,
+    };
+    let is_untried = |status: &sync::Status| match status {
+        sync::Status::Created | sync::Status::Stopped => true,
+        _ => false,
+    };
+
+    let mut free_slots = match max_probing_pools {
+        Some(max_probing_pools) => {
+            max_probing_pools.saturating_sub(statuses.iter().filter(|s| is_probing(s)).count())
+        }
+        None => statuses.len(),
+    };
+    let untried = statuses.iter().enumerate().filter(|(_, s)| is_untried(s));
+    let failed = statuses
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !is_untried(s) && !is_probing(s));
+
+    let mut selected = Vec::new();
+    for (i, _) in untried.chain(failed) {
+        if free_slots == 0 {
+            break;
+        }
+        selected.push(i);
+        free_slots -= 1;
+    }
+    selected
+}
+
 enum ActiveClient {
     None(Arc<work::EngineSender>),
     Some(Arc<client::Handle>),
@@ -244,6 +422,7 @@ impl JobDispatcher {
         match next_client.into() {
             Some(next_client) => {
                 if self.active_client != next_client {
+                    info!("Scheduler: switching to client {}", next_client.node);
                     next_client
                         .engine_sender
                         .swap_sender(self.active_client.get_engine_sender());
@@ -396,3 +575,85 @@ impl JobExecutor {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reject_guard() {
+        let start = time::Instant::now();
+        let mut guard = RejectGuard::default();
+
+        // exceeded threshold is ignored until there are enough rejected solutions
+        assert!(!guard.check(true, MIN_REJECTED_SOLUTIONS - 1, start));
+        assert!(!guard.check(false, MIN_REJECTED_SOLUTIONS, start));
+        assert!(guard.check(true, MIN_REJECTED_SOLUTIONS, start));
+
+        // the client is bypassed for the whole penalty even if the rejects stop
+        let now = start + REJECT_PENALTY / 2;
+        assert!(guard.check(false, MIN_REJECTED_SOLUTIONS, now));
+
+        // after the penalty only new rejects are taken into account
+        let now = start + REJECT_PENALTY;
+        assert!(!guard.check(true, MIN_REJECTED_SOLUTIONS, now));
+        assert!(!guard.check(true, 2 * MIN_REJECTED_SOLUTIONS - 1, now));
+        assert!(guard.check(true, 2 * MIN_REJECTED_SOLUTIONS, now));
+    }
+
+    #[test]
+    fn test_reject_alert() {
+        let mut guard = RejectGuard::default();
+
+        // only changes of the alert state are reported
+        assert!(!guard.update_alert(ClientRejectReason::Stale, false));
+        assert!(guard.update_alert(ClientRejectReason::Stale, true));
+        assert!(!guard.update_alert(ClientRejectReason::Stale, true));
+        assert!(guard.update_alert(ClientRejectReason::Duplicate, true));
+        assert!(guard.update_alert(ClientRejectReason::Stale, false));
+        assert!(!guard.update_alert(ClientRejectReason::Stale, false));
+        assert_eq!(guard.alerted_reasons, vec![ClientRejectReason::Duplicate]);
+    }
+
+    /// Simulate connection attempts to many pools which all fail and check the number of
+    /// concurrently probed pools
+    #[test]
+    fn test_max_probing_pools() {
+        const POOL_COUNT: usize = 16;
+        const MAX_PROBING_POOLS: usize = 3;
+
+        let mut statuses = vec![sync::Status::Created; POOL_COUNT];
+        let mut probed = vec![false; POOL_COUNT];
+        for _ in 0..POOL_COUNT {
+            for i in select_probed_clients(&statuses, Some(MAX_PROBING_POOLS)) {
+                statuses[i] = match statuses[i] {
+                    sync::Status::Failed => sync::Status::Retrying,
+                    _ => sync::Status::Starting,
+                };
+                probed[i] = true;
+            }
+            let probing_pools = statuses
+                .iter()
+                .filter(|status| match status {
+                    sync::Status::Starting | sync::Status::Retrying => true,
+                    _ => false,
+                })
+                .count();
+            assert!(probing_pools <= MAX_PROBING_POOLS);
+
+            // the pool with the highest priority fails first
+            if let Some(status) = statuses.iter_mut().find(|status| match status {
+                sync::Status::Starting | sync::Status::Retrying => true,
+                _ => false,
+            }) {
+                *status = sync::Status::Failed;
+            }
+        }
+        // all pools have been probed eventually
+        assert!(probed.iter().all(|&probed| probed));
+
+        // without the limit all candidates are started at once
+        let statuses = vec![sync::Status::Created; POOL_COUNT];
+        assert_eq!(select_probed_clients(&statuses, None).len(), POOL_COUNT);
+    }
+}
@@ -0,0 +1,729 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Recording of mining sessions and their deterministic replay. The recorder stores all raw
+//! protocol frames exchanged with a pool, the jobs built from them and all submitted solutions as
+//! JSON lines together with the time elapsed since the beginning of the session. The file can be
+//! fed back to the miner with the `Replay` job source which reproduces the original timing of
+//! the jobs offline.
+
+use ii_logging::macros::*;
+
+use super::source::{self, JobSource};
+use crate::error;
+use crate::job;
+use crate::node;
+use crate::work;
+
+use ii_bitcoin::{FromHex, HashTrait as _};
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use futures::task::{Context, Poll};
+use futures::{ready, Sink, Stream};
+use ii_async_compat::{futures, tokio};
+use ii_stratum::{v1, v2};
+use tokio::time::delay_until;
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Weak};
+use std::thread;
+use std::time;
+
+/// Job as it is stored in the session record
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobRecord {
+    pub version: u32,
+    pub version_mask: u32,
+    pub previous_hash: String,
+    pub merkle_root: String,
+    pub time: u32,
+    pub max_time: u32,
+    pub bits: u32,
+    pub target: String,
+    pub block_height: Option<u32>,
+}
+
+impl From<&dyn job::Bitcoin> for JobRecord {
+    fn from(job: &dyn job::Bitcoin) -> Self {
+        Self {
+            version: job.version(),
+            version_mask: job.version_mask(),
+            previous_hash: job.previous_hash().to_string(),
+            merkle_root: job.merkle_root().to_string(),
+            time: job.time(),
+            max_time: job.max_time(),
+            bits: job.bits(),
+            target: job.target().to_string(),
+            block_height: job.block_height(),
+        }
+    }
+}
+
+/// Solution as it is stored in the session record
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SolutionRecord {
+    pub merkle_root: String,
+    pub version: u32,
+    pub time: u32,
+    pub nonce: u32,
+    pub hash: String,
+}
+
+impl From<&work::Solution> for SolutionRecord {
+    fn from(solution: &work::Solution) -> Self {
+        Self {
+            merkle_root: ii_bitcoin::DHash::from_slice(&solution.get_block_header().merkle_root)
+                .expect("BUG: incorrect size of merkle root")
+                .to_string(),
+            version: solution.version(),
+            time: solution.time(),
+            nonce: solution.nonce(),
+            hash: solution.hash().to_string(),
+        }
+    }
+}
+
+/// Direction of a raw protocol frame as seen from the miner
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Frame received from the pool
+    Rx,
+    /// Frame sent to the pool
+    Tx,
+}
+
+/// Stratum V1 frame as it has been received from or sent to the pool
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct V1FrameRecord {
+    pub direction: Direction,
+    /// JSON message without the line delimiter
+    pub payload: String,
+}
+
+/// Stratum V2 frame as it has been received from or sent to the pool
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct V2FrameRecord {
+    pub direction: Direction,
+    pub is_channel_message: bool,
+    pub extension_type: u16,
+    pub msg_type: u8,
+    /// Hex encoded payload of the frame
+    pub payload: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Job(JobRecord),
+    Solution(SolutionRecord),
+    V1Frame(V1FrameRecord),
+    V2Frame(V2FrameRecord),
+}
+
+/// One line of the session record
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Time elapsed since the beginning of the session in milliseconds
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Protocol frame which can be stored in the session record
+pub trait RecordedFrame {
+    fn to_event(&self, direction: Direction) -> ii_stratum::error::Result<Event>;
+}
+
+impl RecordedFrame for v1::Frame {
+    fn to_event(&self, direction: Direction) -> ii_stratum::error::Result<Event> {
+        let payload = self.payload().to_bytes_mut()?;
+        Ok(Event::V1Frame(V1FrameRecord {
+            direction,
+            payload: String::from_utf8_lossy(&payload).trim_end().to_string(),
+        }))
+    }
+}
+
+impl RecordedFrame for v2::Frame {
+    fn to_event(&self, direction: Direction) -> ii_stratum::error::Result<Event> {
+        let payload = self.payload().to_bytes_mut()?;
+        Ok(Event::V2Frame(V2FrameRecord {
+            direction,
+            is_channel_message: self.header.is_channel_message,
+            extension_type: self.header.extension_type,
+            msg_type: self.header.msg_type,
+            payload: hex::encode(&payload),
+        }))
+    }
+}
+
+/// Writer of session records. The records are written by a dedicated thread so that slow
+/// storage never blocks processing of jobs and solutions. When the writer cannot keep up, the
+/// records are dropped.
+pub struct Recorder {
+    sender: mpsc::SyncSender<Record>,
+    writer_thread: thread::JoinHandle<()>,
+    start_time: time::Instant,
+}
+
+impl Recorder {
+    /// Maximal number of records waiting for the writer thread
+    const QUEUE_SIZE: usize = 1024;
+
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(Self::QUEUE_SIZE);
+        let writer_thread = thread::Builder::new()
+            .name("session-recorder".to_string())
+            .spawn(move || Self::write_records(writer, receiver))
+            .expect("BUG: cannot spawn session recorder thread");
+        Self {
+            sender,
+            writer_thread,
+            start_time: time::Instant::now(),
+        }
+    }
+
+    /// Record the session to a new file at `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::create(path)?;
+        Ok(Self::new(Box::new(io::LineWriter::new(file))))
+    }
+
+    fn write_records(mut writer: Box<dyn Write + Send>, receiver: mpsc::Receiver<Record>) {
+        for record in receiver {
+            if let Err(e) = json::to_writer(&mut writer, &record)
+                .map_err(io::Error::from)
+                .and_then(|_| writer.write_all(b"\n"))
+            {
+                warn!("Cannot record session: {}", e);
+            }
+        }
+        if let Err(e) = writer.flush() {
+            warn!("Cannot flush session record: {}", e);
+        }
+    }
+
+    /// Stop the recording and wait until all queued records are written
+    pub fn finish(self) {
+        drop(self.sender);
+        if self.writer_thread.join().is_err() {
+            warn!("Session recorder thread has panicked");
+        }
+    }
+
+    fn record(&self, event: Event) {
+        let record = Record {
+            elapsed_ms: self.start_time.elapsed().as_millis() as u64,
+            event,
+        };
+        match self.sender.try_send(record) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(_)) => {
+                warn!("Session recorder cannot keep up, dropping record")
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                warn!("Session recorder has terminated, dropping record")
+            }
+        }
+    }
+
+    pub fn record_job(&self, job: &dyn job::Bitcoin) {
+        self.record(Event::Job(job.into()));
+    }
+
+    pub fn record_solution(&self, solution: &work::Solution) {
+        self.record(Event::Solution(solution.into()));
+    }
+
+    pub fn record_frame<F: RecordedFrame>(&self, direction: Direction, frame: &F) {
+        match frame.to_event(direction) {
+            Ok(event) => self.record(event),
+            Err(e) => warn!("Cannot record frame: {}", e),
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("start_time", &self.start_time)
+            .finish()
+    }
+}
+
+/// Connection which records all protocol frames passing through it when the session recording
+/// is enabled
+#[derive(Debug)]
+pub struct RecordingConnection<T> {
+    inner: T,
+    session_recorder: job::SessionRecorderConfig,
+}
+
+impl<T> RecordingConnection<T> {
+    pub fn new(inner: T, session_recorder: job::SessionRecorderConfig) -> Self {
+        Self {
+            inner,
+            session_recorder,
+        }
+    }
+
+    fn record<F: RecordedFrame>(&self, direction: Direction, frame: &F) {
+        job::record_session(&self.session_recorder, |recorder| {
+            recorder.record_frame(direction, frame)
+        });
+    }
+}
+
+impl<T, F, E> Stream for RecordingConnection<T>
+where
+    T: Stream<Item = Result<F, E>> + Unpin,
+    F: RecordedFrame,
+{
+    type Item = Result<F, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(Pin::new(&mut self.inner).poll_next(cx));
+        if let Some(Ok(frame)) = &item {
+            self.record(Direction::Rx, frame);
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<T, F> Sink<F> for RecordingConnection<T>
+where
+    T: Sink<F> + Unpin,
+    F: RecordedFrame,
+{
+    type Error = T::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), T::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: F) -> Result<(), T::Error> {
+        self.record(Direction::Tx, &frame);
+        Pin::new(&mut self.inner).start_send(frame)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), T::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), T::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Job replayed from the session record
+#[derive(Debug, Clone)]
+pub struct Job {
+    origin: Weak<dyn node::Client>,
+    version: u32,
+    version_mask: u32,
+    previous_hash: ii_bitcoin::DHash,
+    merkle_root: ii_bitcoin::DHash,
+    time: u32,
+    max_time: u32,
+    bits: u32,
+    target: ii_bitcoin::Target,
+    block_height: Option<u32>,
+}
+
+impl Job {
+    fn new(origin: Weak<dyn node::Client>, record: &JobRecord) -> error::Result<Self> {
+        if record.version_mask & !ii_bitcoin::BIP320_VERSION_MASK != 0 {
+            Err(format!(
+                "invalid version mask {:#010x} in session record",
+                record.version_mask
+            ))?;
+        }
+        if record.max_time < record.time {
+            Err(format!(
+                "max time {} precedes time {} in session record",
+                record.max_time, record.time
+            ))?;
+        }
+        Ok(Self {
+            origin,
+            version: record.version,
+            version_mask: record.version_mask,
+            previous_hash: ii_bitcoin::DHash::from_hex(&record.previous_hash)
+                .map_err(|e| format!("invalid previous hash in session record: {}", e))?,
+            merkle_root: ii_bitcoin::DHash::from_hex(&record.merkle_root)
+                .map_err(|e| format!("invalid merkle root in session record: {}", e))?,
+            time: record.time,
+            max_time: record.max_time,
+            bits: record.bits,
+            target: ii_bitcoin::Target::from_hex(&record.target)
+                .map_err(|e| format!("invalid target in session record: {}", e))?,
+            block_height: record.block_height,
+        })
+    }
+}
+
+impl job::Bitcoin for Job {
+    fn origin(&self) -> Weak<dyn node::Client> {
+        self.origin.clone()
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn version_mask(&self) -> u32 {
+        self.version_mask
+    }
+
+    fn previous_hash(&self) -> &ii_bitcoin::DHash {
+        &self.previous_hash
+    }
+
+    fn merkle_root(&self) -> &ii_bitcoin::DHash {
+        &self.merkle_root
+    }
+
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn max_time(&self) -> u32 {
+        self.max_time
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn target(&self) -> ii_bitcoin::Target {
+        self.target
+    }
+
+    fn is_valid(&self) -> bool {
+        true
+    }
+
+    fn block_height(&self) -> Option<u32> {
+        self.block_height
+    }
+}
+
+/// Current position in the replayed session
+#[derive(Debug)]
+struct ReplayState {
+    start_time: time::Instant,
+    /// Index of the next record
+    position: usize,
+}
+
+/// Job source which replays recorded session with the original timing of jobs. The solutions are
+/// only logged and compared with the recorded ones (the hash is the same for the same nonce).
+#[derive(Debug)]
+pub struct Replay {
+    path: PathBuf,
+    records: Vec<Record>,
+    state: Mutex<ReplayState>,
+}
+
+impl Replay {
+    /// Read session record from `reader`. The `path` is used only for identification.
+    pub fn from_reader<R: BufRead>(path: PathBuf, reader: R) -> error::Result<Self> {
+        let mut records = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record = json::from_str(&line)
+                .map_err(|e| format!("invalid session record on line {}: {}", i + 1, e))?;
+            records.push(record);
+        }
+
+        Ok(Self {
+            path,
+            records,
+            state: Mutex::new(ReplayState {
+                start_time: time::Instant::now(),
+                position: 0,
+            }),
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> error::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = fs::File::open(&path)?;
+        Self::from_reader(path, io::BufReader::new(file))
+    }
+
+    /// Recorded solution of a replayed job with the same nonce
+    fn recorded_solution(&self, solution: &SolutionRecord) -> Option<&SolutionRecord> {
+        self.records.iter().find_map(|record| match &record.event {
+            Event::Solution(recorded)
+                if recorded.merkle_root == solution.merkle_root
+                    && recorded.nonce == solution.nonce =>
+            {
+                Some(recorded)
+            }
+            _ => None,
+        })
+    }
+}
+
+#[async_trait]
+impl JobSource for Replay {
+    async fn connect(&self, _client: &Arc<source::Client>) -> error::Result<()> {
+        // start the session from the beginning
+        *self.state.lock().await = ReplayState {
+            start_time: time::Instant::now(),
+            position: 0,
+        };
+        Ok(())
+    }
+
+    async fn next_job(
+        &self,
+        client: &Arc<source::Client>,
+    ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+        let mut state = self.state.lock().await;
+        while let Some(record) = self.records.get(state.position) {
+            if let Event::Job(job_record) = &record.event {
+                let job = Job::new(client.origin(), job_record)?;
+                let deadline = state.start_time + time::Duration::from_millis(record.elapsed_ms);
+                // the position is moved only after the whole delay so the job is not lost
+                // when the future is dropped in the meantime
+                delay_until(deadline.into()).await;
+                state.position += 1;
+                return Ok(Some(Arc::new(job)));
+            }
+            state.position += 1;
+        }
+        Ok(None)
+    }
+
+    async fn submit_solution(
+        &self,
+        _client: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<source::Submission> {
+        let solution = SolutionRecord::from(&solution);
+        match self.recorded_solution(&solution) {
+            Some(recorded) if recorded == &solution => {
+                info!(
+                    "Replay: solution with nonce {:#010x} matches record",
+                    solution.nonce
+                )
+            }
+            Some(recorded) => warn!(
+                "Replay: solution {:?} differs from recorded {:?}",
+                solution, recorded
+            ),
+            None => info!("Replay: new solution {:?}", solution),
+        }
+        Ok(source::Submission::Accepted)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay://{}", self.path.display())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use futures::{SinkExt as _, StreamExt as _};
+    use std::sync::Mutex as StdMutex;
+
+    /// Writer which allows reading of recorded data after the recorder is dropped
+    #[derive(Debug, Clone, Default)]
+    struct SharedBuffer(Arc<StdMutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("cannot lock buffer").write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Client driven by an empty replay which is used as the origin of replayed jobs
+    fn create_client() -> Arc<source::Client> {
+        let (_solution_sender, solution_receiver) = futures::channel::mpsc::unbounded();
+        let solver = job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver);
+        let replay = Replay::from_reader("empty".into(), &b""[..]).expect("BUG: empty replay");
+        Arc::new(source::Client::new(Box::new(replay), solver))
+    }
+
+    #[tokio::test]
+    async fn test_session_replay() {
+        let buffer = SharedBuffer::default();
+        let recorder = Recorder::new(Box::new(buffer.clone()));
+        for block in test_utils::TEST_BLOCKS.iter() {
+            recorder.record_job(block);
+        }
+        recorder.finish();
+
+        let data = buffer.0.lock().expect("cannot lock buffer").clone();
+        let replay =
+            Replay::from_reader("test".into(), &data[..]).expect("BUG: cannot parse session");
+        assert_eq!(replay.records.len(), test_utils::TEST_BLOCKS.len());
+        assert!(replay
+            .records
+            .windows(2)
+            .all(|r| r[0].elapsed_ms <= r[1].elapsed_ms));
+
+        let client = create_client();
+        replay
+            .connect(&client)
+            .await
+            .expect("BUG: cannot connect replay");
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let job = replay
+                .next_job(&client)
+                .await
+                .expect("BUG: cannot replay job")
+                .expect("BUG: missing job");
+            assert_eq!(job.version(), block.version);
+            assert_eq!(job.previous_hash(), &block.previous_hash);
+            assert_eq!(job.merkle_root(), &block.merkle_root);
+            assert_eq!(job.time(), block.time);
+            assert_eq!(job.bits(), block.bits);
+            assert_eq!(job.target(), block.target);
+            assert_eq!(
+                job.block_header().hash(),
+                job::Bitcoin::block_header(block).hash()
+            );
+        }
+        assert!(replay
+            .next_job(&client)
+            .await
+            .expect("BUG: cannot replay job")
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_job_record() {
+        let block: &dyn job::Bitcoin = &test_utils::TEST_BLOCKS[0];
+        let origin = block.origin();
+        let record = JobRecord::from(block);
+        assert!(Job::new(origin.clone(), &record).is_ok());
+
+        // replayed job would make the work engine generate inconsistent work
+        let mut invalid_record = record.clone();
+        invalid_record.version_mask = 0xffff_ffff;
+        assert!(Job::new(origin.clone(), &invalid_record).is_err());
+
+        let mut invalid_record = record;
+        invalid_record.max_time = invalid_record.time - 1;
+        assert!(Job::new(origin, &invalid_record).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_frame_recording() {
+        use ii_async_compat::bytes::BytesMut;
+
+        const V1_PAYLOAD: &str =
+            "{\"id\":null,\"method\":\"mining.set_difficulty\",\"params\":[8]}";
+        let buffer = SharedBuffer::default();
+        let session_recorder: job::SessionRecorderConfig =
+            Arc::new(StdMutex::new(Some(Recorder::new(Box::new(buffer.clone())))));
+
+        let v1_frame = v1::Frame::from_serialized_payload(BytesMut::from(
+            format!("{}\n", V1_PAYLOAD).as_bytes(),
+        ));
+        let mut v1_connection = RecordingConnection::new(
+            futures::stream::iter(vec![Ok::<_, ()>(v1_frame)]),
+            session_recorder.clone(),
+        );
+        while let Some(frame) = v1_connection.next().await {
+            assert!(frame.is_ok());
+        }
+
+        let (v2_sender, _v2_receiver) = futures::channel::mpsc::channel(1);
+        let mut v2_connection = RecordingConnection::new(v2_sender, session_recorder.clone());
+        let v2_frame =
+            v2::Frame::from_serialized_payload(true, 0, 0x1f, BytesMut::from(&[1u8, 2][..]));
+        v2_connection
+            .send(v2_frame)
+            .await
+            .expect("BUG: cannot send frame");
+
+        session_recorder
+            .lock()
+            .expect("cannot lock session recorder")
+            .take()
+            .expect("BUG: missing recorder")
+            .finish();
+        let data = buffer.0.lock().expect("cannot lock buffer").clone();
+        let replay =
+            Replay::from_reader("test".into(), &data[..]).expect("BUG: cannot parse session");
+        let events: Vec<_> = replay.records.iter().map(|r| r.event.clone()).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::V1Frame(V1FrameRecord {
+                    direction: Direction::Rx,
+                    payload: V1_PAYLOAD.to_string(),
+                }),
+                Event::V2Frame(V2FrameRecord {
+                    direction: Direction::Tx,
+                    is_channel_message: true,
+                    extension_type: 0,
+                    msg_type: 0x1f,
+                    payload: "0102".to_string(),
+                }),
+            ]
+        );
+
+        // frames are kept in the record for analysis but only jobs are replayed
+        let client = create_client();
+        replay
+            .connect(&client)
+            .await
+            .expect("BUG: cannot connect replay");
+        assert!(replay
+            .next_job(&client)
+            .await
+            .expect("BUG: cannot replay job")
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_record() {
+        let data = b"{\"elapsed_ms\":0,\"type\":\"unknown\"}\n";
+        assert!(Replay::from_reader("test".into(), &data[..]).is_err());
+    }
+}
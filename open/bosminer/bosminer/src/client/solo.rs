@@ -0,0 +1,732 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Solo mining client that obtains block templates from a Bitcoin node over JSON-RPC
+//! (`getblocktemplate`) and submits found blocks directly to the node (`submitblock`)
+
+use ii_logging::macros::*;
+
+use super::source;
+
+use crate::error;
+use crate::job;
+use crate::node;
+use crate::work;
+
+use bosminer_config::{ClientDescriptor, ClientProtocol, ClientRejectReason};
+
+use ii_bitcoin::FromHex;
+
+use async_trait::async_trait;
+use failure::ResultExt;
+use futures::lock::Mutex;
+use ii_async_compat::prelude::*;
+use tokio::net::TcpStream;
+use tokio::time::delay_for;
+
+use serde::Deserialize;
+use serde_json as json;
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time;
+
+/// Tag inserted into the coinbase script of the found blocks
+const COINBASE_TAG: &[u8] = b"/BOSminer/";
+
+/// Subset of `getblocktemplate` result (BIP22) needed for building jobs
+#[derive(Deserialize, Clone, Debug)]
+struct BlockTemplate {
+    version: u32,
+    #[serde(rename = "previousblockhash")]
+    previous_block_hash: String,
+    transactions: Vec<TemplateTransaction>,
+    #[serde(rename = "coinbasevalue")]
+    coinbase_value: u64,
+    bits: String,
+    #[serde(rename = "curtime")]
+    current_time: u32,
+    height: u32,
+    #[serde(default)]
+    default_witness_commitment: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct TemplateTransaction {
+    data: String,
+    txid: String,
+}
+
+/// Compute merkle root from coinbase transaction ID and IDs of all other transactions
+fn merkle_root(coinbase_txid: ii_bitcoin::DHash, txids: &[ii_bitcoin::DHash]) -> ii_bitcoin::DHash {
+    ii_bitcoin::merkle::root(coinbase_txid, &ii_bitcoin::merkle::coinbase_branch(txids))
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    origin: Weak<dyn node::Client>,
+    template: Arc<BlockTemplate>,
+    coinbase: Arc<ii_bitcoin::coinbase::Coinbase>,
+    previous_hash: ii_bitcoin::DHash,
+    merkle_root: ii_bitcoin::DHash,
+    bits: u32,
+    target: ii_bitcoin::Target,
+    valid: Arc<AtomicBool>,
+}
+
+impl Job {
+    fn new(
+        origin: Weak<dyn node::Client>,
+        template: Arc<BlockTemplate>,
+        extranonce: &[u8],
+        payout_script: &[u8],
+        valid: Arc<AtomicBool>,
+    ) -> error::Result<Self> {
+        let previous_hash = ii_bitcoin::DHash::from_hex(&template.previous_block_hash)
+            .context("invalid previous block hash in block template")?;
+        let bits =
+            u32::from_str_radix(&template.bits, 16).context("invalid bits in block template")?;
+        let target = ii_bitcoin::Target::from_compact(bits)?;
+        let witness_commitment = match &template.default_witness_commitment {
+            Some(commitment) => {
+                Some(hex::decode(commitment).context("invalid witness commitment")?)
+            }
+            None => None,
+        };
+        let txids = template
+            .transactions
+            .iter()
+            .map(|transaction| ii_bitcoin::DHash::from_hex(&transaction.txid))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid transaction ID in block template")?;
+
+        let mut coinbase_builder = ii_bitcoin::coinbase::Builder::new(
+            template.height,
+            template.coinbase_value,
+            payout_script,
+        )
+        .extranonce_size(extranonce.len())
+        .tag(COINBASE_TAG);
+        if let Some(commitment) = &witness_commitment {
+            coinbase_builder = coinbase_builder.witness_commitment(commitment);
+        }
+        let coinbase = coinbase_builder
+            .build()
+            .context("cannot build coinbase transaction")?
+            .coinbase(extranonce);
+        let merkle_root = merkle_root(coinbase.txid(), &txids);
+
+        Ok(Self {
+            origin,
+            template,
+            coinbase: Arc::new(coinbase),
+            previous_hash,
+            merkle_root,
+            bits,
+            target,
+            valid,
+        })
+    }
+
+    /// Serialize full block with the header of the found solution
+    fn serialize_block(&self, header: &[u8]) -> error::Result<Vec<u8>> {
+        let mut block = header.to_vec();
+        ii_bitcoin::coinbase::push_var_int(&mut block, 1 + self.template.transactions.len());
+        block.extend_from_slice(self.coinbase.block_serialization());
+        for transaction in &self.template.transactions {
+            block.extend(hex::decode(&transaction.data).context("invalid transaction data")?);
+        }
+        Ok(block)
+    }
+}
+
+impl job::Bitcoin for Job {
+    fn origin(&self) -> Weak<dyn node::Client> {
+        self.origin.clone()
+    }
+
+    fn version(&self) -> u32 {
+        self.template.version
+    }
+
+    fn version_mask(&self) -> u32 {
+        ii_bitcoin::BIP320_VERSION_MASK
+    }
+
+    fn previous_hash(&self) -> &ii_bitcoin::DHash {
+        &self.previous_hash
+    }
+
+    fn merkle_root(&self) -> &ii_bitcoin::DHash {
+        &self.merkle_root
+    }
+
+    fn time(&self) -> u32 {
+        self.template.current_time
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn target(&self) -> ii_bitcoin::Target {
+        self.target
+    }
+
+    fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::Relaxed)
+    }
+
+    fn block_height(&self) -> Option<u32> {
+        Some(self.template.height)
+    }
+
+    fn coinbase(&self) -> Option<Vec<u8>> {
+        Some(self.coinbase.block_serialization().to_vec())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse {
+    #[serde(default)]
+    result: json::Value,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+/// Minimal JSON-RPC client of Bitcoin node using HTTP connection per request
+#[derive(Debug)]
+struct RpcClient {
+    address: String,
+    authorization: String,
+}
+
+impl RpcClient {
+    const TIMEOUT: time::Duration = time::Duration::from_secs(10);
+    const HEADER_DELIMITER: &'static [u8] = b"\r\n\r\n";
+
+    fn new(connection_details: &ConnectionDetails) -> Self {
+        let credentials = format!(
+            "{}:{}",
+            connection_details.user,
+            connection_details
+                .password
+                .as_ref()
+                .map_or("", String::as_str)
+        );
+        Self {
+            address: connection_details.get_host_and_port(),
+            authorization: base64::encode(&credentials),
+        }
+    }
+
+    async fn exchange(&self, request: String) -> error::Result<Vec<u8>> {
+        let mut stream = TcpStream::connect(self.address.as_str()).await?;
+        let header = format!(
+            "POST / HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Authorization: Basic {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            self.address,
+            self.authorization,
+            request.len()
+        );
+        stream.write_all(header.as_bytes()).await?;
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(response)
+    }
+
+    /// Extract result from HTTP response with JSON-RPC body
+    fn parse_response(response: &[u8]) -> error::Result<json::Value> {
+        let body_start = response
+            .windows(Self::HEADER_DELIMITER.len())
+            .position(|window| window == Self::HEADER_DELIMITER)
+            .ok_or("malformed HTTP response")?
+            + Self::HEADER_DELIMITER.len();
+        let status_line = String::from_utf8_lossy(&response[..body_start])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        // Bitcoin node reports RPC errors with JSON body even for unsuccessful HTTP status
+        let response: RpcResponse = json::from_slice(&response[body_start..])
+            .with_context(|_| format!("unexpected JSON-RPC response ({})", status_line))?;
+        match response.error {
+            Some(error) => Err(format!("JSON-RPC error {}: {}", error.code, error.message))?,
+            None => Ok(response.result),
+        }
+    }
+
+    async fn call(&self, method: &str, params: json::Value) -> error::Result<json::Value> {
+        let request = json::json!({
+            "jsonrpc": "1.0",
+            "id": method,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let response = tokio::time::timeout(Self::TIMEOUT, self.exchange(request))
+            .await
+            .map_err(|_| format!("JSON-RPC '{}' timeout", method))??;
+        Self::parse_response(&response)
+    }
+
+    async fn get_block_template(&self) -> error::Result<BlockTemplate> {
+        let template = self
+            .call("getblocktemplate", json::json!([{ "rules": ["segwit"] }]))
+            .await?;
+        Ok(json::from_value(template).context("invalid block template")?)
+    }
+
+    /// Obtain output script for the payout address from the node
+    async fn get_payout_script(&self, address: &str) -> error::Result<Vec<u8>> {
+        let info = self.call("validateaddress", json::json!([address])).await?;
+        if !info["isvalid"].as_bool().unwrap_or(false) {
+            Err(format!("invalid payout address '{}'", address))?;
+        }
+        let script = info["scriptPubKey"]
+            .as_str()
+            .ok_or("missing script of payout address")?;
+        Ok(hex::decode(script).context("invalid script of payout address")?)
+    }
+
+    /// Submit found block and return reason of rejection when the node refuses it
+    async fn submit_block(&self, block: &[u8]) -> error::Result<Option<String>> {
+        let result = self
+            .call("submitblock", json::json!([hex::encode(block)]))
+            .await?;
+        Ok(result.as_str().map(|reason| reason.to_string()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionDetails {
+    pub user: String,
+    pub password: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub payout_address: String,
+}
+
+impl ConnectionDetails {
+    pub fn from_descriptor(descriptor: &ClientDescriptor) -> Self {
+        let payout_address = match &descriptor.protocol {
+            ClientProtocol::Solo(payout_address) => payout_address.clone(),
+            _ => panic!("BUG: solo client supports only solo protocol!"),
+        };
+        Self {
+            user: descriptor.user.clone(),
+            password: descriptor.password.clone(),
+            host: descriptor.host.clone(),
+            port: descriptor.port(),
+            payout_address,
+        }
+    }
+
+    fn get_host_and_port(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Current block template together with the extranonce used for the last job built from it
+struct TemplateState {
+    payout_script: Vec<u8>,
+    previous_block_hash: Option<String>,
+    valid: Arc<AtomicBool>,
+    extranonce: job::Extranonce2,
+    last_job_time: time::Instant,
+}
+
+/// Job source of solo mining which builds jobs from block templates of the node
+#[derive(Debug)]
+pub struct Source {
+    connection_details: ConnectionDetails,
+    rpc: RpcClient,
+    /// State of the connected session
+    state: Mutex<Option<TemplateState>>,
+}
+
+impl Source {
+    /// Interval of polling the node for a new block
+    const POLL_INTERVAL: time::Duration = time::Duration::from_secs(5);
+    /// Interval of refreshing the job with new transactions and time when no block has been found
+    const JOB_REFRESH_INTERVAL: time::Duration = time::Duration::from_secs(30);
+    /// Size of extranonce in the coinbase script
+    const EXTRANONCE_SIZE: usize = job::Extranonce2::MAX_SIZE;
+    /// How many times submission of a found block is attempted when the node cannot be reached
+    const SUBMIT_ATTEMPTS: usize = 3;
+    /// Delay between attempts to submit a found block
+    const SUBMIT_RETRY_DELAY: time::Duration = time::Duration::from_secs(1);
+
+    pub fn new(connection_details: ConnectionDetails) -> Self {
+        Self {
+            rpc: RpcClient::new(&connection_details),
+            connection_details,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Build a new job from fetched block template when there is a new block in the network
+    /// or the current job is too old
+    fn update_template(
+        origin: Weak<dyn node::Client>,
+        state: &mut TemplateState,
+        template: BlockTemplate,
+    ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+        let new_block = state.previous_block_hash.as_ref() != Some(&template.previous_block_hash);
+        if !new_block && state.last_job_time.elapsed() < Self::JOB_REFRESH_INTERVAL {
+            return Ok(None);
+        }
+        if new_block {
+            // Work on previous block is no longer useful
+            state.valid.store(false, Ordering::Relaxed);
+            state.valid = Arc::new(AtomicBool::new(true));
+            state.previous_block_hash = Some(template.previous_block_hash.clone());
+            info!(
+                "Solo: mining block at height {} with {} transactions",
+                template.height,
+                template.transactions.len()
+            );
+        }
+        let extranonce = state
+            .extranonce
+            .roll()
+            .ok_or("extranonce space has been exhausted")?;
+        state.last_job_time = time::Instant::now();
+
+        Ok(Some(Arc::new(Job::new(
+            origin,
+            Arc::new(template),
+            &extranonce,
+            &state.payout_script,
+            state.valid.clone(),
+        )?)))
+    }
+
+    /// Submit found block to the node. The submission is retried when the node cannot be
+    /// reached and any failure is reported as rejection so that mining continues.
+    async fn submit_block(&self, solution: &work::Solution) -> source::Submission {
+        let job: &Job = solution.job();
+        let block = match job.serialize_block(&solution.get_block_header().into_bytes()) {
+            Ok(block) => block,
+            Err(e) => {
+                error!("Solo: cannot serialize block {}: {}", solution.hash(), e);
+                return source::Submission::Rejected {
+                    reason: ClientRejectReason::Other,
+                    message: e.to_string(),
+                };
+            }
+        };
+
+        let mut attempt = 1;
+        let result = loop {
+            match self.rpc.submit_block(&block).await {
+                Err(e) if attempt < Self::SUBMIT_ATTEMPTS => {
+                    warn!(
+                        "Solo: submitting block {} failed (attempt {}/{}): {}",
+                        solution.hash(),
+                        attempt,
+                        Self::SUBMIT_ATTEMPTS,
+                        e
+                    );
+                    attempt += 1;
+                    delay_for(Self::SUBMIT_RETRY_DELAY).await;
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Ok(None) => {
+                info!(
+                    "Solo: block {} at height {} has been accepted",
+                    solution.hash(),
+                    job.template.height
+                );
+                source::Submission::Accepted
+            }
+            Ok(Some(reason)) => {
+                warn!(
+                    "Solo: block {} has been rejected: {}",
+                    solution.hash(),
+                    reason
+                );
+                source::Submission::Rejected {
+                    reason: ClientRejectReason::classify(&reason),
+                    message: reason,
+                }
+            }
+            Err(e) => {
+                error!("Solo: cannot submit block {}: {}", solution.hash(), e);
+                // Keep the whole block in the log so that it can be submitted manually
+                error!(
+                    "Solo: block {} data: {}",
+                    solution.hash(),
+                    hex::encode(&block)
+                );
+                source::Submission::Rejected {
+                    reason: ClientRejectReason::Other,
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl source::JobSource for Source {
+    async fn connect(&self, _client: &Arc<source::Client>) -> error::Result<()> {
+        let payout_script = self
+            .rpc
+            .get_payout_script(&self.connection_details.payout_address)
+            .await?;
+        *self.state.lock().await = Some(TemplateState {
+            payout_script,
+            previous_block_hash: None,
+            valid: Arc::new(AtomicBool::new(true)),
+            extranonce: job::Extranonce2::new(Self::EXTRANONCE_SIZE)?,
+            last_job_time: time::Instant::now(),
+        });
+        Ok(())
+    }
+
+    async fn next_job(
+        &self,
+        client: &Arc<source::Client>,
+    ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+        const NOT_CONNECTED: &str = "Solo: source is not connected";
+        loop {
+            // The first template of the session is fetched immediately
+            let polled = self
+                .state
+                .lock()
+                .await
+                .as_ref()
+                .ok_or(NOT_CONNECTED)?
+                .previous_block_hash
+                .is_some();
+            // The state is not locked while waiting for the node so that the session can be
+            // disconnected in the meantime
+            if polled {
+                delay_for(Self::POLL_INTERVAL).await;
+            }
+            let template = self.rpc.get_block_template().await?;
+
+            let mut state = self.state.lock().await;
+            let state = state.as_mut().ok_or(NOT_CONNECTED)?;
+            if let Some(job) = Self::update_template(client.origin(), state, template)? {
+                return Ok(Some(job));
+            }
+        }
+    }
+
+    async fn submit_solution(
+        &self,
+        _client: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<source::Submission> {
+        Ok(self.submit_block(&solution).await)
+    }
+
+    async fn disconnect(&self, _client: &Arc<source::Client>) {
+        if let Some(state) = self.state.lock().await.take() {
+            // Jobs of the terminated session must not be solved anymore
+            state.valid.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}@{}",
+            ClientProtocol::SCHEME_SOLO,
+            self.connection_details.user,
+            self.connection_details.get_host_and_port()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::job::Bitcoin as _;
+    use crate::test_utils;
+
+    use ii_bitcoin::HashTrait as _;
+
+    const PAYOUT_SCRIPT: &[u8] = &[0x51];
+    const EXTRANONCE: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn build_template(transaction_data: &[&[u8]]) -> BlockTemplate {
+        BlockTemplate {
+            version: 0x2000_0000,
+            previous_block_hash: ii_bitcoin::DHash::hash(b"previous block").to_string(),
+            transactions: transaction_data
+                .iter()
+                .map(|data| TemplateTransaction {
+                    data: hex::encode(data),
+                    txid: ii_bitcoin::DHash::hash(data).to_string(),
+                })
+                .collect(),
+            coinbase_value: 625_000_000,
+            bits: "1d00ffff".to_string(),
+            current_time: 1_577_836_800,
+            height: 610_000,
+            default_witness_commitment: None,
+        }
+    }
+
+    fn build_job(template: BlockTemplate) -> error::Result<Job> {
+        Job::new(
+            Arc::downgrade(&(test_utils::TEST_CLIENT.clone() as Arc<dyn node::Client>)),
+            Arc::new(template),
+            EXTRANONCE,
+            PAYOUT_SCRIPT,
+            Arc::new(AtomicBool::new(true)),
+        )
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let coinbase = ii_bitcoin::coinbase::Builder::new(100, 50, &[0x51])
+            .extranonce_size(8)
+            .tag(COINBASE_TAG)
+            .build()
+            .expect("BUG: cannot build coinbase")
+            .coinbase(&1u64.to_le_bytes());
+        // single transaction is the merkle root itself
+        assert_eq!(merkle_root(coinbase.txid(), &[]), coinbase.txid());
+
+        let txid = ii_bitcoin::DHash::hash(b"transaction");
+        let expected = ii_bitcoin::DHash::hash(&[&coinbase.txid()[..], &txid[..]].concat());
+        assert_eq!(merkle_root(coinbase.txid(), &[txid]), expected);
+        // odd number of transactions duplicates the last one
+        let expected = ii_bitcoin::DHash::hash(
+            &[
+                &expected[..],
+                &ii_bitcoin::DHash::hash(&[&txid[..], &txid[..]].concat())[..],
+            ]
+            .concat(),
+        );
+        assert_eq!(merkle_root(coinbase.txid(), &[txid, txid]), expected);
+    }
+
+    #[test]
+    fn test_rpc_response() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            {\"result\":null,\"error\":null,\"id\":\"submitblock\"}";
+        assert_eq!(
+            RpcClient::parse_response(response).expect("valid response"),
+            json::Value::Null
+        );
+
+        let response = b"HTTP/1.1 500 Internal Server Error\r\n\r\n\
+            {\"result\":null,\"error\":{\"code\":-8,\"message\":\"Block decode failed\"}}";
+        assert!(RpcClient::parse_response(response).is_err());
+        assert!(RpcClient::parse_response(b"HTTP/1.1 401 Unauthorized\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_job() {
+        let transaction: &[u8] = b"transaction";
+        let template = build_template(&[transaction]);
+        let job = build_job(template.clone()).expect("BUG: cannot build job");
+
+        let coinbase = ii_bitcoin::coinbase::Builder::new(
+            template.height,
+            template.coinbase_value,
+            PAYOUT_SCRIPT,
+        )
+        .extranonce_size(EXTRANONCE.len())
+        .tag(COINBASE_TAG)
+        .build()
+        .expect("BUG: cannot build coinbase")
+        .coinbase(EXTRANONCE);
+        assert_eq!(job.version(), template.version);
+        assert_eq!(job.time(), template.current_time);
+        assert_eq!(job.bits(), 0x1d00ffff);
+        assert!(job.target() == ii_bitcoin::Target::from_compact(0x1d00ffff).unwrap());
+        assert_eq!(job.block_height(), Some(template.height));
+        assert_eq!(
+            *job.previous_hash(),
+            ii_bitcoin::DHash::hash(b"previous block")
+        );
+        assert_eq!(
+            *job.merkle_root(),
+            merkle_root(coinbase.txid(), &[ii_bitcoin::DHash::hash(transaction)])
+        );
+        assert_eq!(
+            job.coinbase(),
+            Some(coinbase.block_serialization().to_vec())
+        );
+        assert!(job.is_valid());
+        job.valid.store(false, Ordering::Relaxed);
+        assert!(!job.is_valid());
+
+        let mut template = build_template(&[]);
+        template.bits = "invalid".to_string();
+        assert!(build_job(template).is_err());
+        let mut template = build_template(&[transaction]);
+        template.transactions[0].txid = "invalid".to_string();
+        assert!(build_job(template).is_err());
+    }
+
+    #[test]
+    fn test_serialize_block() {
+        let transactions: &[&[u8]] = &[b"first transaction", b"second transaction"];
+        let job = build_job(build_template(transactions)).expect("BUG: cannot build job");
+        let header = [0xaa; 80];
+
+        let block = job
+            .serialize_block(&header)
+            .expect("BUG: cannot serialize block");
+        let mut expected = header.to_vec();
+        // transaction count includes the coinbase
+        expected.push(3);
+        expected.extend_from_slice(job.coinbase.block_serialization());
+        for transaction in transactions {
+            expected.extend_from_slice(transaction);
+        }
+        assert_eq!(block, expected);
+
+        let mut template = build_template(transactions);
+        template.transactions[1].data = "invalid".to_string();
+        let job = build_job(template).expect("BUG: cannot build job");
+        assert!(job.serialize_block(&header).is_err());
+    }
+}
@@ -0,0 +1,514 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Generic client which obtains jobs from any `JobSource`. Mining protocols as well as other means
+//! of obtaining jobs are implemented as sources so that a new protocol can be added without
+//! touching the work engine and the job/solution plumbing which is shared by all of them:
+//! - Stratum V1: `stratum_v2_channels::StratumSource` which drives the V1 connection through the
+//!   V2->V1 translation (there is no separate V1 source)
+//! - Stratum V2: `stratum_v2::StratumSource`
+//! - solo mining: `solo::Source`
+//! - replay of recorded sessions: `session::Replay`
+
+use ii_logging::macros::*;
+
+use crate::error;
+use crate::job;
+use crate::node;
+use crate::stats;
+use crate::sync;
+use crate::work;
+
+use bosminer_config::{ClientDescriptor, ClientRejectReason};
+use bosminer_macros::ClientNode;
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::future;
+use futures::lock::Mutex;
+use ii_async_compat::prelude::*;
+use ii_async_compat::select;
+
+use std::fmt;
+use std::sync::{Arc, Weak};
+use std::time;
+
+/// Outcome of solution submission reported by `JobSource::submit_solution`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submission {
+    Accepted,
+    /// The solution has been refused for `reason` explained by the `message` from remote server
+    Rejected {
+        reason: ClientRejectReason,
+        message: String,
+    },
+    /// The result is not known yet and the source accounts it itself once it is (e.g. when the
+    /// pool responds asynchronously)
+    Pending,
+}
+
+/// Stream of jobs together with sink of their solutions decoupled from a specific protocol
+#[async_trait]
+pub trait JobSource: fmt::Debug + fmt::Display + Send + Sync {
+    /// Prepare the source for a new mining session (e.g. connect to remote server). It is called
+    /// whenever the client is (re)started and the client is running only when it succeeds.
+    async fn connect(&self, _client: &Arc<Client>) -> error::Result<()> {
+        Ok(())
+    }
+
+    /// Wait for the next job. All jobs created by the source have to return `client.origin()`
+    /// from `job::Bitcoin::origin`. `None` is returned when the source has been exhausted.
+    /// The method runs concurrently with `submit_solution` and it is cancelled only when the
+    /// mining session is terminated.
+    async fn next_job(&self, client: &Arc<Client>) -> error::Result<Option<Arc<dyn job::Bitcoin>>>;
+
+    /// Submit solution of a job previously returned by `next_job`. An error terminates the
+    /// mining session and the client is restarted.
+    async fn submit_solution(
+        &self,
+        client: &Arc<Client>,
+        solution: work::Solution,
+    ) -> error::Result<Submission>;
+
+    /// Release all resources of the mining session after it has been terminated (stopped or
+    /// failed)
+    async fn disconnect(&self, _client: &Arc<Client>) {}
+
+    /// Apply changed settings from `descriptor` to the source. Returns `false` when the source
+    /// cannot apply them and the client has to be replaced by a new one.
+    fn change_connection_details(&self, _descriptor: &ClientDescriptor) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, ClientNode)]
+pub struct Client {
+    #[member_status]
+    status: sync::StatusMonitor,
+    #[member_client_stats]
+    stats: stats::BasicClient,
+    source: Box<dyn JobSource>,
+    stop_sender: mpsc::Sender<()>,
+    stop_receiver: Mutex<mpsc::Receiver<()>>,
+    last_job: Mutex<Option<Arc<dyn job::Bitcoin>>>,
+    job_sender: Mutex<job::Sender>,
+    solution_receiver: Mutex<job::SolutionReceiver>,
+}
+
+impl Client {
+    pub fn new(source: Box<dyn JobSource>, solver: job::Solver) -> Self {
+        let (stop_sender, stop_receiver) = mpsc::channel(1);
+        Self {
+            status: Default::default(),
+            stats: Default::default(),
+            source,
+            stop_sender,
+            stop_receiver: Mutex::new(stop_receiver),
+            last_job: Mutex::new(None),
+            job_sender: Mutex::new(solver.job_sender),
+            solution_receiver: Mutex::new(solver.solution_receiver),
+        }
+    }
+
+    /// Origin which has to be assigned to all jobs of the source
+    pub fn origin(self: &Arc<Self>) -> Weak<dyn node::Client> {
+        Arc::downgrade(&(self.clone() as Arc<dyn node::Client>))
+    }
+
+    /// Statistics of the client which are accounted by the source (e.g. results of pending
+    /// submissions)
+    #[inline]
+    pub fn stats(&self) -> &stats::BasicClient {
+        &self.stats
+    }
+
+    /// The client is being restarted after the previous mining session has failed
+    #[inline]
+    pub fn is_retrying(&self) -> bool {
+        self.status.status() == sync::Status::Retrying
+    }
+
+    /// Returns shared recorder of the session (e.g. for recording of raw protocol frames)
+    pub async fn session_recorder(&self) -> job::SessionRecorderConfig {
+        self.job_sender.lock().await.session_recorder()
+    }
+
+    /// Returns difficulty ramp to be requested from the pool once the mining session is opened
+    pub async fn difficulty_ramp(&self) -> Option<job::DifficultyRamp> {
+        *self
+            .job_sender
+            .lock()
+            .await
+            .difficulty_ramp()
+            .lock()
+            .expect("cannot lock difficulty ramp")
+    }
+
+    async fn process_job(&self, job: Arc<dyn job::Bitcoin>) {
+        self.last_job.lock().await.replace(job.clone());
+        self.job_sender.lock().await.send(job);
+    }
+
+    async fn process_solution(self: &Arc<Self>, solution: work::Solution) -> error::Result<()> {
+        let submitted = time::Instant::now();
+        let submission = self.source.submit_solution(self, solution.clone()).await?;
+        let now = time::Instant::now();
+        let job_target = *solution.job_target();
+        match submission {
+            Submission::Accepted => {
+                self.stats
+                    .submission_latency
+                    .account_solution(&solution, submitted, now);
+                self.stats.accepted.account_solution(&job_target, now).await;
+            }
+            Submission::Rejected { reason, message } => {
+                warn!(
+                    "Source '{}' rejected solution with nonce={:08x}: {}",
+                    self.source,
+                    solution.nonce(),
+                    message
+                );
+                self.stats
+                    .submission_latency
+                    .account_solution(&solution, submitted, now);
+                self.stats.rejected.account_solution(&job_target, now).await;
+                self.stats
+                    .reject_reasons
+                    .account_solution(reason, &job_target, now)
+                    .await;
+            }
+            Submission::Pending => {}
+        }
+        Ok(())
+    }
+
+    async fn job_loop(self: &Arc<Self>) -> error::Result<()> {
+        while !self.status.is_shutting_down() {
+            match self.source.next_job(self).await? {
+                Some(job) => self.process_job(job).await,
+                None => {
+                    // keep submitting solutions of the last job until the client is stopped
+                    info!("Source '{}' has no more jobs", self.source);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn solution_loop(self: &Arc<Self>) -> error::Result<()> {
+        let mut solution_receiver = self.solution_receiver.lock().await;
+        while !self.status.is_shutting_down() {
+            match solution_receiver.receive().await {
+                Some(solution) => self.process_solution(solution).await?,
+                None => Err("Standard application shutdown")?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self: Arc<Self>) {
+        if let Err(e) = self.source.connect(&self).await {
+            warn!("Source '{}' cannot connect: {}", self.source, e);
+            self.status.initiate_failing();
+            return;
+        }
+        if self.status.initiate_running() {
+            // Jobs and solutions are processed concurrently so that neither of the source
+            // methods is cancelled in the middle of the session
+            if let Err(e) = future::try_join(self.job_loop(), self.solution_loop()).await {
+                warn!("Source '{}' failed: {}", self.source, e);
+                self.status.initiate_failing();
+            }
+        }
+    }
+
+    async fn main_task(self: Arc<Self>) {
+        // TODO: Count as a discarded solution?
+        // Flush all obsolete solutions from previous run
+        self.solution_receiver.lock().await.flush();
+
+        loop {
+            let mut stop_receiver = self.stop_receiver.lock().await;
+            select! {
+                _ = self.clone().run().fuse() => {}
+                _ = stop_receiver.next() => {}
+            }
+
+            // Invalidate current job to stop working on it
+            self.job_sender.lock().await.invalidate();
+            self.source.disconnect(&self).await;
+            // Flush all unprocessed solutions to empty buffer
+            // TODO: Count as a discarded solution?
+            self.solution_receiver.lock().await.flush();
+
+            if self.status.can_stop() {
+                // NOTE: it is not safe to add here any code!
+                // The reason is that at this point the main task can be executed in parallel again
+                break;
+            }
+            // Restarting
+        }
+    }
+}
+
+#[async_trait]
+impl node::Client for Client {
+    fn start(self: Arc<Self>) {
+        tokio::spawn(self.clone().main_task());
+    }
+
+    fn stop(&self) {
+        if let Err(e) = self.stop_sender.clone().try_send(()) {
+            assert!(
+                e.is_full(),
+                "BUG: Unexpected error in stop sender: {}",
+                e.to_string()
+            );
+        }
+    }
+
+    async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
+        self.last_job.lock().await.clone()
+    }
+
+    fn change_connection_details(&self, descriptor: &ClientDescriptor) -> bool {
+        self.source.change_connection_details(descriptor)
+    }
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Job of the test block assigned to the tested client
+    #[derive(Debug, Clone)]
+    struct TestJob {
+        origin: Weak<dyn node::Client>,
+        block: test_utils::TestBlock,
+    }
+
+    impl job::Bitcoin for TestJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.origin.clone()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.block.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Counters {
+        connected: AtomicUsize,
+        disconnected: AtomicUsize,
+        submitted: AtomicUsize,
+    }
+
+    /// Source which provides one job per session and optionally reports exhaustion afterwards
+    #[derive(Debug)]
+    struct TestSource {
+        counters: Arc<Counters>,
+        exhausted: bool,
+        job_sent: Mutex<bool>,
+    }
+
+    impl TestSource {
+        fn new(exhausted: bool) -> (Self, Arc<Counters>) {
+            let counters = Arc::new(Counters::default());
+            (
+                Self {
+                    counters: counters.clone(),
+                    exhausted,
+                    job_sent: Mutex::new(false),
+                },
+                counters,
+            )
+        }
+    }
+
+    #[async_trait]
+    impl JobSource for TestSource {
+        async fn connect(&self, _client: &Arc<Client>) -> error::Result<()> {
+            self.counters.connected.fetch_add(1, Ordering::Relaxed);
+            *self.job_sent.lock().await = false;
+            Ok(())
+        }
+
+        async fn next_job(
+            &self,
+            client: &Arc<Client>,
+        ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+            let mut job_sent = self.job_sent.lock().await;
+            if !*job_sent {
+                *job_sent = true;
+                return Ok(Some(Arc::new(TestJob {
+                    origin: client.origin(),
+                    block: test_utils::TEST_BLOCKS[0],
+                })));
+            }
+            if !self.exhausted {
+                future::pending::<()>().await;
+            }
+            Ok(None)
+        }
+
+        async fn submit_solution(
+            &self,
+            _client: &Arc<Client>,
+            _solution: work::Solution,
+        ) -> error::Result<Submission> {
+            self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+            Ok(Submission::Accepted)
+        }
+
+        async fn disconnect(&self, _client: &Arc<Client>) {
+            self.counters.disconnected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl fmt::Display for TestSource {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "test")
+        }
+    }
+
+    fn create_client(source: TestSource) -> (Arc<Client>, mpsc::UnboundedSender<work::Solution>) {
+        let (solution_sender, solution_receiver) = mpsc::unbounded();
+        let solver = job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver);
+        (
+            Arc::new(Client::new(Box::new(source), solver)),
+            solution_sender,
+        )
+    }
+
+    fn start_client(client: &Arc<Client>) {
+        assert!(client.status.initiate_starting());
+        node::Client::start(client.clone());
+    }
+
+    fn stop_client(client: &Arc<Client>) {
+        assert!(client.status.initiate_stopping());
+        node::Client::stop(client.as_ref());
+    }
+
+    async fn wait_for(condition: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+        panic!("BUG: condition has not been met in time");
+    }
+
+    #[tokio::test]
+    async fn test_client_restart() {
+        let (source, counters) = TestSource::new(false);
+        let (client, _solution_sender) = create_client(source);
+
+        start_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Running).await;
+        let job = node::Client::get_last_job(client.as_ref())
+            .await
+            .expect("BUG: missing job");
+        assert!(Weak::ptr_eq(&job.origin(), &client.origin()));
+
+        stop_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Stopped).await;
+        assert_eq!(counters.disconnected.load(Ordering::Relaxed), 1);
+
+        // the source is connected again for the new session
+        start_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Running).await;
+        assert_eq!(counters.connected.load(Ordering::Relaxed), 2);
+
+        stop_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Stopped).await;
+        assert_eq!(counters.disconnected.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_exhausted() {
+        let (source, counters) = TestSource::new(true);
+        let (client, solution_sender) = create_client(source);
+
+        start_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Running).await;
+        let job = node::Client::get_last_job(client.as_ref())
+            .await
+            .expect("BUG: missing job");
+        assert_eq!(
+            job.block_header().hash(),
+            job::Bitcoin::block_header(&test_utils::TEST_BLOCKS[0]).hash()
+        );
+
+        // solutions are still submitted when the source has no more jobs
+        solution_sender
+            .unbounded_send((&test_utils::TEST_BLOCKS[0]).into())
+            .expect("BUG: cannot send solution");
+        wait_for(|| counters.submitted.load(Ordering::Relaxed) == 1).await;
+        assert_eq!(client.status.status(), sync::Status::Running);
+        assert_eq!(counters.connected.load(Ordering::Relaxed), 1);
+        assert_eq!(client.stats.accepted.take_snapshot().await.solutions, 1);
+
+        stop_client(&client);
+        wait_for(|| client.status.status() == sync::Status::Stopped).await;
+    }
+}
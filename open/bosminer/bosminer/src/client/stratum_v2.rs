@@ -25,20 +25,26 @@ pub mod telemetry;
 
 use ii_logging::macros::*;
 
+use super::backoff::Backoff;
+use super::session;
+use super::source;
+use super::transport;
+
 use crate::error;
 use crate::hal;
 use crate::job;
 use crate::node;
-use crate::stats;
-use crate::sync;
+use crate::stats::UnixTime as _;
 use crate::work;
 
 use failure::ResultExt;
 
-use ii_bitcoin::HashTrait;
+use ii_bitcoin::{HashTrait, MeetsTarget};
 
-use bosminer_config::{ClientDescriptor, ClientProtocol};
-use bosminer_macros::ClientNode;
+use bosminer_config::{
+    ClientDescriptor, ClientNtimePolicy, ClientParseErrorPolicy, ClientProtocol,
+    ClientReconnectPolicy, ClientRejectReason, ClientSocks5Proxy,
+};
 
 use async_trait::async_trait;
 use futures::channel::mpsc;
@@ -46,18 +52,20 @@ use futures::lock::Mutex;
 use ii_async_compat::prelude::*;
 use ii_async_compat::select;
 
+use rand::Rng;
+
 use std::collections::VecDeque;
 use std::fmt;
-use std::str::FromStr;
 use std::sync::Mutex as StdMutex;
+use std::sync::MutexGuard as StdMutexGuard;
 use std::sync::{Arc, Weak};
 use std::time;
 
 use ii_stratum::v2::messages::{
     NewMiningJob, OpenStandardMiningChannel, OpenStandardMiningChannelError,
-    OpenStandardMiningChannelSuccess, SetNewPrevHash, SetTarget, SetupConnection,
+    OpenStandardMiningChannelSuccess, Reconnect, SetNewPrevHash, SetTarget, SetupConnection,
     SetupConnectionError, SetupConnectionSuccess, SubmitSharesError, SubmitSharesStandard,
-    SubmitSharesSuccess,
+    SubmitSharesSuccess, UpdateChannel,
 };
 use ii_stratum::v2::types::*;
 use ii_stratum::v2::{
@@ -79,6 +87,16 @@ pub struct ConnectionDetails {
     pub user: String,
     pub host: String,
     pub port: u16,
+    pub parse_error_policy: ClientParseErrorPolicy,
+    pub alternate_users: Vec<String>,
+    pub submit_jitter: time::Duration,
+    pub compression: bool,
+    pub ntime_tolerance: Option<time::Duration>,
+    pub ntime_policy: ClientNtimePolicy,
+    pub ntime_rolling: Option<time::Duration>,
+    pub reconnect_policy: ClientReconnectPolicy,
+    pub reconnect_allowlist: Vec<String>,
+    pub proxy: Option<ClientSocks5Proxy>,
 }
 
 impl ConnectionDetails {
@@ -88,17 +106,29 @@ impl ConnectionDetails {
             user: descriptor.user.clone(),
             host: descriptor.host.clone(),
             port: descriptor.port(),
+            parse_error_policy: descriptor.parse_error_policy,
+            alternate_users: descriptor.alternate_users.clone(),
+            submit_jitter: descriptor.submit_jitter,
+            compression: descriptor.compression,
+            ntime_tolerance: descriptor.ntime_tolerance,
+            ntime_policy: descriptor.ntime_policy,
+            ntime_rolling: descriptor.ntime_rolling,
+            reconnect_policy: descriptor.reconnect_policy,
+            reconnect_allowlist: descriptor.reconnect_allowlist.clone(),
+            proxy: descriptor.proxy.clone(),
         }
     }
 
     fn get_host_and_port(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+        transport::host_and_port(&self.host, self.port)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct StratumJob {
-    client: Weak<StratumClient>,
+    origin: Weak<dyn node::Client>,
+    /// Previous block hash of the last `SetNewPrevHash` shared with the client
+    current_prev_hash: Arc<StdMutex<Option<ii_bitcoin::DHash>>>,
     id: u32,
     channel_id: u32,
     version: u32,
@@ -107,17 +137,28 @@ pub struct StratumJob {
     time: u32,
     bits: u32,
     target: ii_bitcoin::Target,
+    /// Maximal rolled ntime allowed by the pool
+    max_time: u32,
+    /// Time when the job has been received
+    received: time::Instant,
 }
 
 impl StratumJob {
     pub fn new(
-        client: Arc<StratumClient>,
+        client: &StratumClient,
+        origin: Weak<dyn node::Client>,
         job_msg: &NewMiningJob,
         prevhash_msg: &SetNewPrevHash,
         target: ii_bitcoin::Target,
     ) -> Self {
+        let ntime_rolling = client
+            .connection_details()
+            .ntime_rolling
+            .map(|ntime_rolling| ntime_rolling.as_secs().min(std::u32::MAX.into()) as u32)
+            .unwrap_or(work::engine::DEFAULT_NTIME_ROLLING_LIMIT);
         Self {
-            client: Arc::downgrade(&client),
+            origin,
+            current_prev_hash: client.current_prev_hash.clone(),
             id: job_msg.job_id,
             channel_id: job_msg.channel_id,
             version: job_msg.version,
@@ -128,13 +169,15 @@ impl StratumJob {
             time: prevhash_msg.min_ntime,
             bits: prevhash_msg.nbits,
             target,
+            max_time: prevhash_msg.min_ntime.saturating_add(ntime_rolling),
+            received: time::Instant::now(),
         }
     }
 }
 
 impl job::Bitcoin for StratumJob {
     fn origin(&self) -> Weak<dyn node::Client> {
-        self.client.clone()
+        self.origin.clone()
     }
 
     fn version(&self) -> u32 {
@@ -157,6 +200,10 @@ impl job::Bitcoin for StratumJob {
         self.time
     }
 
+    fn max_time(&self) -> u32 {
+        self.max_time
+    }
+
     fn bits(&self) -> u32 {
         self.bits
     }
@@ -166,54 +213,516 @@ impl job::Bitcoin for StratumJob {
     }
 
     fn is_valid(&self) -> bool {
-        // TODO: currently there is no easy way to detect the job is valid -> we have to check
-        //  its presence in the registry. The inequality below was possible in the previous
-        //  iteration of the protocol
-        // self.block_height >= self.current_block_height.load(Ordering::Relaxed)
-        true
+        // All jobs built on top of previous block are invalidated by a new prevhash
+        *self
+            .current_prev_hash
+            .lock()
+            .expect("BUG: cannot lock prev hash")
+            == Some(self.prev_hash)
     }
 }
 
-/// Queue that contains pairs of solution and its assigned sequence number. It is our responsibility
-/// to keep the sequence number monotonic so that we as a stratum V2 client can easily process bulk
-/// acknowledgements. The sequence number type has been selected as u32 to match
+/// Queue that contains solutions with their assigned sequence number and time of submission. It is
+/// our responsibility to keep the sequence number monotonic so that we as a stratum V2 client can
+/// easily process bulk acknowledgements. The sequence number type has been selected as u32 to match
 /// up with the protocol.
-type SolutionQueue = Mutex<VecDeque<(work::Solution, u32)>>;
+type SolutionQueue = Mutex<VecDeque<(work::Solution, u32, time::Instant)>>;
+
+/// Detects a server that keeps sending mining jobs but never sends any `SetNewPrevHash`. No complete
+/// job can be assembled from such messages and the miner would silently idle.
+#[derive(Debug, Default)]
+pub(crate) struct MissingPrevHashAlarm {
+    /// Time of the first job received without a known prevhash
+    first_job_time: Option<time::Instant>,
+    /// Number of jobs received without a known prevhash
+    job_count: usize,
+    /// ID of the last job received without a known prevhash
+    last_job_id: Option<u32>,
+}
+
+impl MissingPrevHashAlarm {
+    /// Maximal time to wait for the first prevhash since the first job has been received
+    pub const TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+    /// Record a mining job that has been received while no prevhash is known
+    pub fn job_without_prevhash(&mut self, job_msg: &NewMiningJob, now: time::Instant) {
+        self.first_job_time.get_or_insert(now);
+        self.job_count += 1;
+        self.last_job_id = Some(job_msg.job_id);
+    }
+
+    /// Any prevhash allows to assemble complete jobs so the alarm is disarmed
+    pub fn prevhash_received(&mut self) {
+        self.first_job_time = None;
+        self.job_count = 0;
+        self.last_job_id = None;
+    }
+
+    /// Fails when jobs have been received for longer than `TIMEOUT` without any prevhash
+    pub fn check(&self, now: time::Instant) -> error::Result<()> {
+        match self.first_job_time {
+            Some(first_job_time)
+                if now.saturating_duration_since(first_job_time) >= Self::TIMEOUT =>
+            {
+                let msg = format!(
+                    "The remote stratum server sent {} job(s) (last job ID {}) but no prevhash \
+                     within {}s, no complete job can be assembled",
+                    self.job_count,
+                    self.last_job_id.unwrap_or_default(),
+                    Self::TIMEOUT.as_secs()
+                );
+                error!("Stratum: {}", msg);
+                Err(msg.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Applies configured `ClientParseErrorPolicy` to messages that cannot be parsed
+#[derive(Debug)]
+pub(crate) struct ParseErrorHandler {
+    policy: ClientParseErrorPolicy,
+    /// Number of messages that could not be parsed
+    error_count: u64,
+}
+
+impl ParseErrorHandler {
+    pub fn new(policy: ClientParseErrorPolicy) -> Self {
+        Self {
+            policy,
+            error_count: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn error_count(&self) -> u64 {
+        self.error_count
+    }
+
+    /// Returns the error back when the connection is to be dropped
+    pub fn handle(&mut self, error: error::Error) -> error::Result<()> {
+        self.error_count += 1;
+        match self.policy {
+            ClientParseErrorPolicy::Skip => {
+                warn!(
+                    "Stratum: skipping message that cannot be parsed ({} in total): {}",
+                    self.error_count, error
+                );
+                Ok(())
+            }
+            ClientParseErrorPolicy::Disconnect => {
+                error!(
+                    "Stratum: disconnecting due to message that cannot be parsed: {}",
+                    error
+                );
+                Err(error)
+            }
+        }
+    }
+}
+
+/// Applies configured `ClientNtimePolicy` to jobs whose ntime is too far from the local clock
+#[derive(Debug)]
+pub(crate) struct NtimeValidator {
+    tolerance: Option<time::Duration>,
+    policy: ClientNtimePolicy,
+}
+
+impl NtimeValidator {
+    pub fn new(tolerance: Option<time::Duration>, policy: ClientNtimePolicy) -> Self {
+        Self { tolerance, policy }
+    }
+
+    /// Returns ntime which should be used for the job or `None` when the job is to be rejected.
+    /// The `ntime` is the minimal time of the job and `max_ntime` is the maximal time allowed by
+    /// the pool. All times are Unix times in seconds.
+    pub fn validate(&self, ntime: u32, max_ntime: u32, now: u32) -> Option<u32> {
+        let tolerance = match self.tolerance {
+            Some(tolerance) => tolerance.as_secs().min(u32::MAX as u64) as u32,
+            None => return Some(ntime),
+        };
+        let min_local_ntime = now.saturating_sub(tolerance);
+        let max_local_ntime = now.saturating_add(tolerance);
+        if ntime >= min_local_ntime && ntime <= max_local_ntime {
+            return Some(ntime);
+        }
+
+        let skew = ntime as i64 - now as i64;
+        match self.policy {
+            ClientNtimePolicy::Accept => {
+                warn!(
+                    "Stratum: job ntime is {}s off the local clock (tolerance {}s)",
+                    skew, tolerance
+                );
+                Some(ntime)
+            }
+            ClientNtimePolicy::Clamp => {
+                // the pool does not accept shares with ntime out of the job range
+                let clamped_ntime = min_local_ntime.min(max_ntime).max(ntime);
+                warn!(
+                    "Stratum: job ntime is {}s off the local clock, adjusting it to {}",
+                    skew, clamped_ntime
+                );
+                Some(clamped_ntime)
+            }
+            ClientNtimePolicy::Reject => {
+                warn!(
+                    "Stratum: rejecting job with ntime {}s off the local clock",
+                    skew
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Keeps track of users rejected by the pool and of failed connection attempts. It provides the
+/// user for the next connection attempt and delays the attempts after failure.
+#[derive(Debug)]
+pub(crate) struct CredentialRotation {
+    users: Vec<String>,
+    current: usize,
+    /// The pool has rejected authorization of the current user during the last attempt
+    auth_rejected: bool,
+    /// Delay of reconnection attempts after failure
+    backoff: Backoff,
+}
+
+impl CredentialRotation {
+    pub fn new(user: String, alternate_users: Vec<String>) -> Self {
+        let mut users = vec![user];
+        users.extend(alternate_users);
+        Self {
+            users,
+            current: 0,
+            auth_rejected: false,
+            backoff: Default::default(),
+        }
+    }
+
+    /// Current user or `None` when all users have been rejected already
+    pub fn user(&self) -> Option<String> {
+        self.users.get(self.current).cloned()
+    }
+
+    /// Mark current user as rejected and return the next one to be tried
+    pub fn reject(&mut self) -> Option<String> {
+        self.current = (self.current + 1).min(self.users.len());
+        self.user()
+    }
+
+    /// Start a new connection attempt and return the user to be authorized with the pool
+    pub fn start_attempt(&mut self) -> error::Result<String> {
+        self.auth_rejected = false;
+        // All users have been rejected so there is no point in connecting to the pool
+        self.user()
+            .ok_or_else(|| "All configured users have been rejected by the pool".into())
+    }
+
+    /// The pool has refused to authorize the current user
+    pub fn set_auth_rejected(&mut self) {
+        self.auth_rejected = true;
+    }
+
+    /// Finish failed connection attempt to `host_and_port`. Switch to the next user when the
+    /// pool has rejected the current one and return `true` in such case.
+    pub fn attempt_failed(&mut self, host_and_port: &str) -> bool {
+        if !self.auth_rejected {
+            return false;
+        }
+        self.auth_rejected = false;
+        let rejected_user = self.user().unwrap_or_default();
+        match self.reject() {
+            Some(user) => warn!(
+                "Stratum: {} rejected user '{}', trying alternate user '{}'",
+                host_and_port, rejected_user, user
+            ),
+            None => error!(
+                "Stratum: {} rejected all configured users, giving up until the pool is \
+                 reconfigured",
+                host_and_port
+            ),
+        }
+        true
+    }
+
+    /// Reset the reconnection delay after mining session has been successfully initialized
+    pub fn attempt_succeeded(&mut self) {
+        self.backoff.reset();
+    }
+
+    /// Wait before the next connection attempt to `host_and_port` when the previous one has
+    /// failed. The delay grows exponentially with the number of failed attempts. Redirection
+    /// requested by the server is followed immediately.
+    pub async fn wait_before_reconnect(
+        credentials: &StdMutex<Self>,
+        redirect: &StdMutex<ServerRedirect>,
+        node: &source::Client,
+        host_and_port: &str,
+    ) {
+        if redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .take_pending()
+        {
+            return;
+        }
+        if !node.is_retrying() {
+            return;
+        }
+        let (delay, attempts) = {
+            let mut credentials = credentials.lock().expect("BUG: cannot lock credentials");
+            (
+                credentials.backoff.next_delay(),
+                credentials.backoff.attempts(),
+            )
+        };
+        node.stats().reconnections.inc();
+        info!(
+            "Stratum: reconnecting to {} in {:.1}s (attempt {})",
+            host_and_port,
+            delay.as_secs_f64(),
+            attempts
+        );
+        tokio::time::delay_for(delay).await;
+    }
+}
+
+/// Keeps track of the host the server asked the client to reconnect to. The redirection lasts
+/// until the connection to the new host fails or the pool is reconfigured.
+#[derive(Debug)]
+pub(crate) struct ServerRedirect {
+    /// Host from the pool configuration which the policy is evaluated against
+    configured_host: String,
+    policy: ClientReconnectPolicy,
+    allowlist: Vec<String>,
+    target: Option<(String, u16)>,
+    /// The current connection should be closed to follow the redirection
+    pending: bool,
+}
+
+impl ServerRedirect {
+    pub fn new(
+        configured_host: String,
+        policy: ClientReconnectPolicy,
+        allowlist: Vec<String>,
+    ) -> Self {
+        Self {
+            configured_host,
+            policy,
+            allowlist,
+            target: None,
+            pending: false,
+        }
+    }
+
+    /// Host and port the client should connect to instead of the configured ones
+    pub fn target(&self) -> Option<(String, u16)> {
+        self.target.clone()
+    }
+
+    /// Validate reconnect request of the server currently connected at `current_host` and
+    /// `current_port`. Empty `new_host` or zero `new_port` keep the current value. Returns
+    /// address of the new host on success or the refused one.
+    pub fn request(
+        &mut self,
+        current_host: &str,
+        current_port: u16,
+        new_host: &str,
+        new_port: u16,
+    ) -> std::result::Result<String, String> {
+        let host = if new_host.is_empty() {
+            current_host
+        } else {
+            transport::unbracket_host(new_host)
+        };
+        let port = if new_port == 0 {
+            current_port
+        } else {
+            new_port
+        };
+        let host_and_port = transport::host_and_port(host, port);
+        if !self
+            .policy
+            .is_allowed(&self.configured_host, host, &self.allowlist)
+        {
+            return Err(host_and_port);
+        }
+        self.target = Some((host.to_string(), port));
+        self.pending = true;
+        Ok(host_and_port)
+    }
+
+    /// Check if the client should reconnect to follow the redirection
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Acknowledge the reconnection and return whether it has been requested by the server
+    pub fn take_pending(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+
+    /// Drop the redirection and return it when it has been in effect
+    pub fn reset(&mut self) -> Option<(String, u16)> {
+        self.pending = false;
+        self.target.take()
+    }
+}
+
+/// Randomized delay of share submission which spreads bursts of shares from many rigs mining the
+/// same job
+#[derive(Debug)]
+pub(crate) struct SubmitJitter {
+    /// Exclusive upper bound of the delay, zero disables the jitter
+    max_delay: time::Duration,
+}
+
+impl SubmitJitter {
+    pub fn new(max_delay: time::Duration) -> Self {
+        Self { max_delay }
+    }
+
+    /// Delay before submission of a solution. Blocks are never delayed.
+    pub fn delay(&self, is_block: bool) -> time::Duration {
+        let max_delay = self.max_delay.as_micros() as u64;
+        if is_block || max_delay == 0 {
+            return time::Duration::from_secs(0);
+        }
+        time::Duration::from_micros(rand::thread_rng().gen_range(0, max_delay))
+    }
+}
+
+/// Difficulty ramp of the mining channel. The pool is asked for lower difficulty right after the
+/// channel has been opened and the request is then raised back to the difficulty assigned by the
+/// pool. The pool confirms each accepted request with `SetTarget`.
+#[derive(Debug)]
+pub(crate) struct ChannelDifficultyRamp {
+    channel_id: u32,
+    session: job::DifficultyRampSession,
+}
+
+impl ChannelDifficultyRamp {
+    pub fn new(
+        channel_id: u32,
+        ramp: job::DifficultyRamp,
+        pool_target: ii_bitcoin::Target,
+    ) -> Self {
+        Self {
+            channel_id,
+            session: job::DifficultyRampSession::new(ramp, pool_target, time::Instant::now()),
+        }
+    }
+
+    /// Channel update requesting the target of the ramp at `now` or `None` when the requested
+    /// target has not changed
+    pub fn update(&mut self, now: time::Instant) -> Option<UpdateChannel> {
+        let channel_id = self.channel_id;
+        self.session.next_target(now).map(|target| {
+            info!(
+                "Stratum: requesting channel target diff={} along the difficulty ramp",
+                target.get_difficulty()
+            );
+            UpdateChannel {
+                channel_id,
+                nominal_hashrate: 1e9,
+                max_target: target.into(),
+            }
+        })
+    }
+
+    /// Time remaining to the next update of the channel or `None` when the ramp has finished
+    pub fn next_update(&self, now: time::Instant) -> Option<time::Duration> {
+        self.session.next_update(now)
+    }
+
+    /// Wait for the next update of the channel. It never completes when there is no update.
+    pub async fn wait_for_update(delay: Option<time::Duration>) {
+        match delay {
+            Some(delay) => tokio::time::delay_for(delay).await,
+            None => futures::future::pending().await,
+        }
+    }
+}
 
 /// Helper task for `StratumClient` that implements Stratum V2 visitor which processes incoming
 /// messages from remote server.
 struct StratumEventHandler {
     client: Arc<StratumClient>,
+    /// Client driving the source which accounts the results of submitted shares
+    node: Weak<source::Client>,
+    /// Job built from the last processed message which is to be returned by `next_job`
+    new_job: Option<Arc<dyn job::Bitcoin>>,
     all_jobs: HashMap<u32, NewMiningJob>,
     current_prevhash_msg: Option<SetNewPrevHash>,
+    /// Message of the job which is being solved
+    current_job_msg: Option<NewMiningJob>,
     /// Mining target for the next job that is to be solved
     current_target: ii_bitcoin::Target,
+    missing_prevhash_alarm: MissingPrevHashAlarm,
+    parse_error_handler: ParseErrorHandler,
+    ntime_validator: NtimeValidator,
 }
 
 impl StratumEventHandler {
-    pub fn new(client: Arc<StratumClient>, current_target: ii_bitcoin::Target) -> Self {
+    pub fn new(
+        client: Arc<StratumClient>,
+        node: Weak<source::Client>,
+        current_target: ii_bitcoin::Target,
+    ) -> Self {
+        let connection_details = client.connection_details();
+        let parse_error_policy = connection_details.parse_error_policy;
+        let ntime_validator = NtimeValidator::new(
+            connection_details.ntime_tolerance,
+            connection_details.ntime_policy,
+        );
+        client.set_channel_target(current_target);
         Self {
             client,
+            node,
+            new_job: None,
             all_jobs: Default::default(),
             current_prevhash_msg: None,
+            current_job_msg: None,
             current_target,
+            missing_prevhash_alarm: Default::default(),
+            parse_error_handler: ParseErrorHandler::new(parse_error_policy),
+            ntime_validator,
         }
     }
 
-    /// Convert new mining job message into StratumJob and send it down the line for solving.
+    /// Fails when the server keeps sending jobs without any prevhash
+    fn check_missing_prevhash(&self) -> error::Result<()> {
+        self.missing_prevhash_alarm.check(time::Instant::now())
+    }
+
+    /// Convert new mining job message into StratumJob which is passed down the line for solving.
     ///
     /// * `job_msg` - job message used as a base for the StratumJob
     async fn update_job(&mut self, job_msg: &NewMiningJob) {
-        let job = Arc::new(StratumJob::new(
-            self.client.clone(),
+        self.current_job_msg = Some(job_msg.clone());
+        let mut job = StratumJob::new(
+            &self.client,
+            self.node.clone(),
             job_msg,
             self.current_prevhash_msg
                 .as_ref()
                 .expect("TODO: no prevhash"),
             self.current_target,
-        ));
-        self.client.update_last_job(job.clone()).await;
-        self.client.job_sender.lock().await.send(job);
+        );
+        let now = time::SystemTime::now().get_unix_time().unwrap_or(job.time);
+        match self.ntime_validator.validate(job.time, job.max_time, now) {
+            Some(ntime) => job.time = ntime,
+            None => {
+                if let Some(node) = self.node.upgrade() {
+                    node.stats().invalid_jobs.inc();
+                }
+                return;
+            }
+        }
+        self.new_job = Some(Arc::new(job));
     }
 
     fn update_target(&mut self, value: Uint256Bytes) {
@@ -224,18 +733,27 @@ impl StratumEventHandler {
             new_target.get_difficulty()
         );
         self.current_target = new_target;
+        self.client.set_channel_target(new_target);
     }
 
     async fn process_accepted_shares(&self, success_msg: &SubmitSharesSuccess) {
+        let node = match self.node.upgrade() {
+            Some(node) => node,
+            None => return,
+        };
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some((solution, seq_num, submitted)) =
+            self.client.solutions.lock().await.pop_front()
+        {
+            node.stats()
+                .submission_latency
+                .account_solution(&solution, submitted, now);
             info!(
                 "Stratum: accepted solution #{} with nonce={:08x}",
                 seq_num,
                 solution.nonce()
             );
-            self.client
-                .client_stats
+            node.stats()
                 .accepted
                 .account_solution(&solution.job_target(), now)
                 .await;
@@ -251,19 +769,41 @@ impl StratumEventHandler {
     }
 
     async fn process_rejected_shares(&self, error_msg: &SubmitSharesError) {
+        let node = match self.node.upgrade() {
+            Some(node) => node,
+            None => return,
+        };
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some((solution, seq_num, submitted)) =
+            self.client.solutions.lock().await.pop_front()
+        {
+            node.stats()
+                .submission_latency
+                .account_solution(&solution, submitted, now);
             if error_msg.seq_num == seq_num {
-                info!(
-                    "Stratum: rejected solution #{} with nonce={:08x}!",
+                let current_prev_hash = self.current_prevhash_msg.as_ref().map(|prevhash_msg| {
+                    ii_bitcoin::DHash::from_slice(prevhash_msg.prev_hash.as_ref())
+                        .expect("BUG: Stratum: incorrect size of prev hash")
+                });
+                warn!(
+                    "Stratum: rejected solution #{} with nonce={:08x} and code '{}': {}",
                     seq_num,
-                    solution.nonce()
+                    solution.nonce(),
+                    error_msg.code.to_string(),
+                    job::RejectDiagnosis::diagnose(&solution, current_prev_hash.as_ref())
                 );
-                self.client
-                    .client_stats
+                node.stats()
                     .rejected
                     .account_solution(&solution.job_target(), now)
                     .await;
+                node.stats()
+                    .reject_reasons
+                    .account_solution(
+                        ClientRejectReason::classify(&error_msg.code.to_string()),
+                        &solution.job_target(),
+                        now,
+                    )
+                    .await;
                 // the rejected solution has been found
                 return;
             } else {
@@ -274,8 +814,7 @@ impl StratumEventHandler {
                     seq_num,
                     solution.nonce()
                 );
-                self.client
-                    .client_stats
+                node.stats()
                     .accepted
                     .account_solution(&solution.job_target(), now)
                     .await;
@@ -319,13 +858,22 @@ impl Handler for StratumEventHandler {
         //  send the new prevhash ahead of this job. This scenario is still yet to be investigated
         //  as it should prevented typically on the V2->V1->upstream translation proxies. These
         //  proxies should guarantee that no such case like a job without a prevhash would exist.
-        if !job_msg.future_job && self.current_prevhash_msg.is_some() {
+        if self.current_prevhash_msg.is_none() {
+            self.missing_prevhash_alarm
+                .job_without_prevhash(job_msg, time::Instant::now());
+        } else if !job_msg.future_job {
             self.update_job(job_msg).await;
         }
     }
 
     async fn visit_set_new_prev_hash(&mut self, _header: &Header, prevhash_msg: &SetNewPrevHash) {
         self.current_prevhash_msg.replace(prevhash_msg.clone());
+        self.missing_prevhash_alarm.prevhash_received();
+        // Solutions of jobs built on the previous block are stale from now on
+        self.client.set_current_prev_hash(Some(
+            ii_bitcoin::DHash::from_slice(prevhash_msg.prev_hash.as_ref())
+                .expect("BUG: Stratum: incorrect size of prev hash"),
+        ));
 
         // find the future job with ID referenced in prevhash_msg
         let (_, mut future_job_msg) = self
@@ -334,7 +882,7 @@ impl Handler for StratumEventHandler {
             .expect("TODO: requested job ID not found");
 
         // remove all other jobs (they are now invalid)
-        self.all_jobs.retain(|_, _| true);
+        self.all_jobs.clear();
         // turn the job into an immediate job
         future_job_msg.future_job = false;
         // reinsert the job
@@ -346,7 +894,16 @@ impl Handler for StratumEventHandler {
     }
 
     async fn visit_set_target(&mut self, _header: &Header, target_msg: &SetTarget) {
+        let previous_target = self.current_target;
         self.update_target(target_msg.max_target);
+        // The job carries its target so the current job is restarted when the difficulty has
+        // been lowered (e.g. along the difficulty ramp). Otherwise the shares meeting the new
+        // target would not be submitted until the next job arrives.
+        if self.current_target > previous_target {
+            if let Some(job_msg) = self.current_job_msg.clone() {
+                self.update_job(&job_msg).await;
+            }
+        }
     }
 
     async fn visit_submit_shares_success(
@@ -360,6 +917,11 @@ impl Handler for StratumEventHandler {
     async fn visit_submit_shares_error(&mut self, _header: &Header, error_msg: &SubmitSharesError) {
         self.process_rejected_shares(error_msg).await;
     }
+
+    async fn visit_reconnect(&mut self, _header: &Header, reconnect_msg: &Reconnect) {
+        self.client
+            .request_redirect(reconnect_msg.new_host.as_ref(), reconnect_msg.new_port);
+    }
 }
 
 trait FrameSink:
@@ -400,30 +962,29 @@ impl<T> FrameStream for T where
 {
 }
 
-struct StratumSolutionHandler<S> {
+/// Part of `StratumSolutionHandler` which sends shares to the server. It is shared with the
+/// tasks submitting delayed shares so that the sequence numbers are sent in order.
+struct StratumSubmitter<S> {
     client: Arc<StratumClient>,
     connection_tx: Arc<Mutex<S>>,
     seq_num: u32,
 }
 
-impl<S, E> StratumSolutionHandler<S>
+impl<S, E> StratumSubmitter<S>
 where
     E: Into<error::Error>,
     // TODO use S: FrameSink once the trait is adjusted to deal with payload specific error
     S: Sink<<Framing as ii_wire::Framing>::Tx, Error = E>
         + std::marker::Unpin
         + std::fmt::Debug
+        + Send
         + 'static,
 {
-    fn new(client: Arc<StratumClient>, connection_tx: Arc<Mutex<S>>) -> Self {
-        Self {
-            client,
-            connection_tx,
-            seq_num: 0,
-        }
-    }
-
-    async fn process_solution(&mut self, solution: work::Solution) -> error::Result<()> {
+    async fn submit(
+        &mut self,
+        node: &source::Client,
+        solution: work::Solution,
+    ) -> error::Result<()> {
         let job: &StratumJob = solution.job();
 
         let seq_num = self.seq_num;
@@ -437,12 +998,15 @@ where
             ntime: solution.time(),
             version: solution.version(),
         };
+        node.stats()
+            .solution_age
+            .account_solution(time::Instant::now().saturating_duration_since(job.received));
         // store solution with sequence number for future server acknowledge
         self.client
             .solutions
             .lock()
             .await
-            .push_back((solution, seq_num));
+            .push_back((solution, seq_num, time::Instant::now()));
         // send solutions back to the stratum server
         StratumClient::send_msg(&self.connection_tx, share_msg)
             .await
@@ -452,8 +1016,97 @@ where
     }
 }
 
+struct StratumSolutionHandler<S> {
+    client: Arc<StratumClient>,
+    submitter: Arc<Mutex<StratumSubmitter<S>>>,
+    submit_jitter: SubmitJitter,
+}
+
+impl<S, E> StratumSolutionHandler<S>
+where
+    E: Into<error::Error>,
+    // TODO use S: FrameSink once the trait is adjusted to deal with payload specific error
+    S: Sink<<Framing as ii_wire::Framing>::Tx, Error = E>
+        + std::marker::Unpin
+        + std::fmt::Debug
+        + Send
+        + 'static,
+{
+    fn new(client: Arc<StratumClient>, connection_tx: Arc<Mutex<S>>) -> Self {
+        let submit_jitter = SubmitJitter::new(client.connection_details().submit_jitter);
+        Self {
+            submitter: Arc::new(Mutex::new(StratumSubmitter {
+                client: client.clone(),
+                connection_tx,
+                seq_num: 0,
+            })),
+            client,
+            submit_jitter,
+        }
+    }
+
+    async fn process_solution(
+        &mut self,
+        node: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<()> {
+        let is_block = solution.hash().meets(&solution.network_target());
+        // The server may have raised the difficulty of the channel after the job has been
+        // received and it would reject the share
+        let channel_target = self.client.channel_target();
+        if !is_block && !solution.hash().meets(&channel_target) {
+            info!(
+                "Stratum: dropping solution with nonce={:08x} below channel target diff={}",
+                solution.nonce(),
+                channel_target.get_difficulty()
+            );
+            // The share has been found by the local hardware but it is not usable anymore so
+            // it is accounted as a local hardware error of the client
+            node.stats()
+                .error_backend_diff
+                .account_solution(solution.backend_target(), time::Instant::now())
+                .await;
+            return Ok(());
+        }
+        let delay = self.submit_jitter.delay(is_block);
+        if delay == time::Duration::from_secs(0) {
+            return self.submitter.lock().await.submit(node, solution).await;
+        }
+
+        // Delayed solution is submitted by a separate task so that it does not hold back the
+        // following ones
+        let submitter = Arc::downgrade(&self.submitter);
+        let node = node.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(delay).await;
+            let is_valid = job::Bitcoin::is_valid(solution.job::<StratumJob>());
+            match submitter.upgrade() {
+                // The mining session is still running
+                Some(submitter) if is_valid => {
+                    if let Err(e) = submitter.lock().await.submit(&node, solution).await {
+                        warn!("Stratum: cannot submit delayed solution: {}", e);
+                    }
+                }
+                _ => {
+                    node.stats()
+                        .stale
+                        .account_solution(solution.job_target(), time::Instant::now())
+                        .await;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
 struct StratumConnectionHandler {
     client: Arc<StratumClient>,
+    /// Flags requested in `SetupConnection`
+    setup_flags: u32,
+    /// Compression of the connection codec enabled once it has been negotiated
+    compression: v2::CompressionSwitch,
+    /// Channel opened by the pool
+    channel_id: u32,
     init_target: ii_bitcoin::Target,
     status: Option<error::Result<()>>,
 }
@@ -462,6 +1115,9 @@ impl StratumConnectionHandler {
     pub fn new(client: Arc<StratumClient>) -> Self {
         Self {
             client,
+            setup_flags: 0,
+            compression: Default::default(),
+            channel_id: 0,
             init_target: Default::default(),
             status: None,
         }
@@ -477,11 +1133,17 @@ impl StratumConnectionHandler {
         S: FrameSink,
     {
         let connection_details = self.client.connection_details();
+        // NOTE: the codec starts decoding compressed frames once the pool confirms the request
+        self.setup_flags = if connection_details.compression {
+            v2::compression::SETUP_CONNECTION_FLAG_COMPRESSION
+        } else {
+            0
+        };
         let setup_msg = SetupConnection {
             protocol: 0,
             max_version: 2,
             min_version: 2,
-            flags: 0,
+            flags: self.setup_flags,
             endpoint_host: Str0_255::from_string(connection_details.host.clone()),
             endpoint_port: connection_details.port,
             device: self.client.backend_info.clone().unwrap_or_default().into(),
@@ -515,9 +1177,9 @@ impl StratumConnectionHandler {
             req_id: 10, // TODO? come up with request ID sequencing
             user: self
                 .client
-                .connection_details()
-                .user
-                .clone()
+                .credentials()
+                .user()
+                .expect("BUG: connecting without user")
                 .try_into()
                 .expect("BUG: cannot convert 'OpenStandardMiningChannel::user'"),
             nominal_hashrate: 1e9,
@@ -543,10 +1205,14 @@ impl StratumConnectionHandler {
 
     async fn connect(&self) -> error::Result<v2::Framed> {
         let connection_details = self.client.connection_details();
-        let addr = ii_wire::Address::from_str(connection_details.get_host_and_port().as_str())?;
-        let mut client = ii_wire::Client::new(addr);
-        // Attempt only once to connect (as the stratum client is being managed externally)
-        let connection = client.next().await?;
+        let connection = match connection_details.proxy.as_ref() {
+            // the host name is resolved by the proxy
+            Some(proxy) => {
+                transport::connect_socks5(proxy, &connection_details.host, connection_details.port)
+                    .await?
+            }
+            None => transport::connect(&connection_details.host, connection_details.port).await?,
+        };
 
         // TODO this will be replaced by a 'connector' that will be set when building stratum
         // client instance
@@ -571,7 +1237,7 @@ impl StratumConnectionHandler {
 
     /// Starts mining session and provides the initial target negotiated by the upstream endpoint
     async fn init_mining_session<R, S>(
-        mut self,
+        &mut self,
         connection_rx: &mut R,
         connection_tx: Arc<Mutex<S>>,
     ) -> error::Result<ii_bitcoin::Target>
@@ -595,8 +1261,16 @@ impl Handler for StratumConnectionHandler {
     async fn visit_setup_connection_success(
         &mut self,
         _header: &Header,
-        _success_msg: &SetupConnectionSuccess,
+        success_msg: &SetupConnectionSuccess,
     ) {
+        if self.setup_flags & v2::compression::SETUP_CONNECTION_FLAG_COMPRESSION != 0 {
+            if v2::compression::is_negotiated(self.setup_flags, success_msg.flags) {
+                info!("V2: compression of job messages has been negotiated");
+                self.compression.set(true);
+            } else {
+                info!("V2: compression is not supported by the pool, continuing without it");
+            }
+        }
         self.status = Ok(()).into();
     }
 
@@ -614,6 +1288,7 @@ impl Handler for StratumConnectionHandler {
         _header: &Header,
         success_msg: &OpenStandardMiningChannelSuccess,
     ) {
+        self.channel_id = success_msg.channel_id;
         self.init_target = success_msg.target.into();
         self.status = Ok(()).into();
     }
@@ -623,6 +1298,7 @@ impl Handler for StratumConnectionHandler {
         _header: &Header,
         error_msg: &OpenStandardMiningChannelError,
     ) {
+        self.client.credentials().set_auth_rejected();
         self.status =
             Err(format!("Open channel error: {}", error_msg.code.to_string()).into()).into();
     }
@@ -649,27 +1325,25 @@ pub type ExtensionChannelFromStratumReceiver = mpsc::Receiver<ExtensionChannelMs
 /// Sender for Stratum --> Remote direction (stratum client end)
 pub type ExtensionChannelFromStratumSender = mpsc::Sender<ExtensionChannelMsg>;
 
-#[derive(Debug, ClientNode)]
+/// State of the Stratum V2 protocol shared by the job source with the helper handlers
+#[derive(Debug)]
 pub struct StratumClient {
     connection_details: Arc<StdMutex<ConnectionDetails>>,
     backend_info: Option<hal::BackendInfo>,
-    #[member_status]
-    status: sync::StatusMonitor,
-    #[member_client_stats]
-    client_stats: stats::BasicClient,
-    stop_sender: mpsc::Sender<()>,
-    stop_receiver: Mutex<mpsc::Receiver<()>>,
-    // Last job has to be weak reference to prevent circular reference (the `StratumJob` keeps
-    // reference to `StratumClient`)
-    last_job: Mutex<Option<Arc<StratumJob>>>,
     solutions: SolutionQueue,
-    job_sender: Mutex<job::Sender>,
-    solution_receiver: Mutex<job::SolutionReceiver>,
     /// Frames received from this channel will be forwarded to the network connection
     extension_channel_receiver: Mutex<ExtensionChannelToStratumReceiver>,
     /// Frames intended for the specified extension will be forwarded into this channel (wrapped
     /// into ExtensionChannelMsg
     extension_channel_sender: Mutex<ExtensionChannelFromStratumSender>,
+    /// Users tried when the pool rejects authorization
+    credentials: StdMutex<CredentialRotation>,
+    /// Current target of the mining channel which applies to all submitted shares
+    channel_target: StdMutex<ii_bitcoin::Target>,
+    /// Previous block hash of the last `SetNewPrevHash` which all valid jobs build on
+    current_prev_hash: Arc<StdMutex<Option<ii_bitcoin::DHash>>>,
+    /// Host requested by the server with reconnect message
+    redirect: StdMutex<ServerRedirect>,
 }
 
 impl StratumClient {
@@ -716,14 +1390,11 @@ impl StratumClient {
     pub fn new(
         connection_details: ConnectionDetails,
         backend_info: Option<hal::BackendInfo>,
-        solver: job::Solver,
         channel: Option<(
             ExtensionChannelToStratumReceiver,
             ExtensionChannelFromStratumSender,
         )>,
     ) -> Self {
-        let (stop_sender, stop_receiver) = mpsc::channel(1);
-
         // Extract the both channel endpoints that connect the client with the stratum extension
         // or populate it with dummy endpoints. That way we can handle the endpoints uniformly
         // regardless whether they are configured or not (see `main_loop()`)
@@ -735,32 +1406,124 @@ impl StratumClient {
             );
             Self::start_dummy_extension_task(connection_details.clone())
         });
+        let credentials = CredentialRotation::new(
+            connection_details.user.clone(),
+            connection_details.alternate_users.clone(),
+        );
+        let redirect = ServerRedirect::new(
+            connection_details.host.clone(),
+            connection_details.reconnect_policy,
+            connection_details.reconnect_allowlist.clone(),
+        );
 
         Self {
             connection_details: Arc::new(StdMutex::new(connection_details)),
             backend_info,
-            status: Default::default(),
-            client_stats: Default::default(),
-            stop_sender: stop_sender,
-            stop_receiver: Mutex::new(stop_receiver),
-            last_job: Mutex::new(None),
             solutions: Mutex::new(VecDeque::new()),
-            job_sender: Mutex::new(solver.job_sender),
-            solution_receiver: Mutex::new(solver.solution_receiver),
             extension_channel_receiver: Mutex::new(extension_channel_receiver),
             extension_channel_sender: Mutex::new(extension_channel_sender),
+            credentials: StdMutex::new(credentials),
+            channel_target: StdMutex::new(Default::default()),
+            current_prev_hash: Arc::new(StdMutex::new(None)),
+            redirect: StdMutex::new(redirect),
         }
     }
 
+    /// Connection details with host and port replaced by the redirection requested by the server
     fn connection_details(&self) -> ConnectionDetails {
-        self.connection_details
+        let mut connection_details = self
+            .connection_details
             .lock()
             .expect("BUG: cannot lock connection details")
-            .clone()
+            .clone();
+        if let Some((host, port)) = self
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .target()
+        {
+            connection_details.host = host;
+            connection_details.port = port;
+        }
+        connection_details
     }
 
-    async fn update_last_job(&self, job: Arc<StratumJob>) {
-        self.last_job.lock().await.replace(job);
+    /// Follow request of the server to reconnect to another host when the reconnect policy of the
+    /// pool allows it. The current user is kept for the new connection.
+    fn request_redirect(&self, new_host: &str, new_port: u16) {
+        let connection_details = self.connection_details();
+        let result = self
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .request(
+                &connection_details.host,
+                connection_details.port,
+                new_host,
+                new_port,
+            );
+        match result {
+            Ok(host_and_port) => info!(
+                "Stratum: {} requested reconnection to {}",
+                connection_details.get_host_and_port(),
+                host_and_port
+            ),
+            Err(host_and_port) => warn!(
+                "Stratum: {} requested reconnection to {} refused by reconnect policy '{}'",
+                connection_details.get_host_and_port(),
+                host_and_port,
+                connection_details.reconnect_policy
+            ),
+        }
+    }
+
+    fn is_redirect_pending(&self) -> bool {
+        self.redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .is_pending()
+    }
+
+    /// Return to the configured host after connection to the redirected one has failed
+    fn reset_redirect(&self) {
+        if let Some((host, port)) = self
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .reset()
+        {
+            warn!(
+                "Stratum: connection to redirected host {} failed, returning to configured host",
+                transport::host_and_port(&host, port)
+            );
+        }
+    }
+
+    fn credentials(&self) -> StdMutexGuard<'_, CredentialRotation> {
+        self.credentials
+            .lock()
+            .expect("BUG: cannot lock credentials")
+    }
+
+    fn channel_target(&self) -> ii_bitcoin::Target {
+        *self
+            .channel_target
+            .lock()
+            .expect("BUG: cannot lock channel target")
+    }
+
+    fn set_channel_target(&self, target: ii_bitcoin::Target) {
+        *self
+            .channel_target
+            .lock()
+            .expect("BUG: cannot lock channel target") = target;
+    }
+
+    fn set_current_prev_hash(&self, prev_hash: Option<ii_bitcoin::DHash>) {
+        *self
+            .current_prev_hash
+            .lock()
+            .expect("BUG: cannot lock prev hash") = prev_hash;
     }
 
     /// Send a message down a specified Tx Sink
@@ -797,10 +1560,10 @@ impl StratumClient {
         event_handler: &mut StratumEventHandler,
     ) -> error::Result<()> {
         match frame.header.extension_type {
-            extensions::BASE => {
-                let event_msg = build_message_from_frame(frame)?;
-                event_msg.accept(event_handler).await;
-            }
+            extensions::BASE => match build_message_from_frame(frame) {
+                Ok(event_msg) => event_msg.accept(event_handler).await,
+                Err(e) => event_handler.parse_error_handler.handle(e.into())?,
+            },
             // pass any other extension down the line
             _ => {
                 info!(
@@ -824,212 +1587,271 @@ impl StratumClient {
         }
         Ok(())
     }
+}
 
-    async fn main_loop<R, S>(
-        self: Arc<Self>,
-        mut connection_rx: R,
-        connection_tx: Arc<Mutex<S>>,
-        mut event_handler: StratumEventHandler,
-    ) -> error::Result<()>
-    where
-        R: FrameStream,
-        S: FrameSink,
-    {
-        let mut solution_receiver = self.solution_receiver.lock().await;
-        let mut extension_channel_rx = self.extension_channel_receiver.lock().await;
-        let mut solution_handler = StratumSolutionHandler::new(self.clone(), connection_tx.clone());
+/// Connection of the mining session with recording of raw frames
+type Connection = session::RecordingConnection<v2::Framed>;
+type ConnectionTx = futures::stream::SplitSink<Connection, <Framing as ii_wire::Framing>::Tx>;
+type ConnectionRx = futures::stream::SplitStream<Connection>;
+
+/// Receiving half of the mining session which processes messages from the remote server
+struct JobReceiver {
+    connection_rx: ConnectionRx,
+    /// Sending half shared with the solution handler for forwarding of extension frames
+    connection_tx: Arc<Mutex<ConnectionTx>>,
+    event_handler: StratumEventHandler,
+    difficulty_ramp: Option<ChannelDifficultyRamp>,
+}
 
-        // Notify the extension user that we are ready to start forwarding its protocol, use a
-        // separate block, so that the lock is dropped immediately after the start notification
-        // is sent
-        {
-            self.extension_channel_sender
-                .lock()
-                .await
-                .try_send(ExtensionChannelMsg::Start)
-                .map_err(|e| {
-                    info!("Stratum extension channel start error: {:?}", e);
-                })
-                .expect("BUG: stratum extension channel not available for start");
-        }
-        while !self.status.is_shutting_down() {
-            select! {
-                frame = connection_rx.next().timeout(Self::EVENT_TIMEOUT).fuse() => {
-                    match frame {
-                        Ok(Some(frame)) => self.handle_frame(frame?, &mut event_handler).await?,
-                        Ok(None) | Err(_) => {
-                            Err("The remote stratum server was disconnected prematurely")?;
-                        }
-                    }
-                }
-                // Forward extension protocol frames onto the network
-                frame = extension_channel_rx.next().fuse() => {
-                    connection_tx.lock().await
-                        .send(frame.expect("BUG: extension channel must not shutdown!"))
-                        .await?;
-                }
-                solution = solution_receiver.receive().fuse() => {
-                    match solution {
-                        Some(solution) => solution_handler.process_solution(solution).await?,
-                        None => {
-                            // TODO: initiate Destroying and remove error
-                            Err("Standard application shutdown")?;
-                        }
-                    }
-                }
-            }
-        }
-        Ok(())
-    }
+/// Stratum V2 job source
+#[derive(Debug)]
+pub struct StratumSource {
+    client: Arc<StratumClient>,
+    job_receiver: Mutex<Option<JobReceiver>>,
+    solution_handler: Mutex<Option<StratumSolutionHandler<ConnectionTx>>>,
+}
 
-    async fn run_job_solver<R, S>(
-        self: Arc<Self>,
-        connection_rx: R,
-        connection_tx: Arc<Mutex<S>>,
-        init_target: ii_bitcoin::Target,
-    ) where
-        R: FrameStream,
-        S: FrameSink,
-    {
-        let event_handler = StratumEventHandler::new(self.clone(), init_target);
-        // TODO consider changing main_loop to accept Arc<Self> and build the solution_handler
-        //  along with solution handler communication channels inside of the main_loop.
-        let client = self.clone();
-        if let Err(_) = client
-            .main_loop(connection_rx, connection_tx, event_handler)
-            .await
-        {
-            self.status.initiate_failing();
+impl StratumSource {
+    pub fn new(
+        connection_details: ConnectionDetails,
+        backend_info: Option<hal::BackendInfo>,
+        channel: Option<(
+            ExtensionChannelToStratumReceiver,
+            ExtensionChannelFromStratumSender,
+        )>,
+    ) -> Self {
+        Self {
+            client: Arc::new(StratumClient::new(
+                connection_details,
+                backend_info,
+                channel,
+            )),
+            job_receiver: Mutex::new(None),
+            solution_handler: Mutex::new(None),
         }
     }
+}
 
-    async fn run(self: Arc<Self>) {
-        let connection_handler = StratumConnectionHandler::new(self.clone());
-        let connection_details = connection_handler.client.connection_details();
+#[async_trait]
+impl source::JobSource for StratumSource {
+    async fn connect(&self, node: &Arc<source::Client>) -> error::Result<()> {
+        let connection_details = self.client.connection_details();
         let host_and_port = connection_details.get_host_and_port();
-        let user = connection_details.user.clone();
+        CredentialRotation::wait_before_reconnect(
+            &self.client.credentials,
+            &self.client.redirect,
+            node,
+            &host_and_port,
+        )
+        .await;
+        let user = self.client.credentials().start_attempt()?;
+        let mut connection_handler = StratumConnectionHandler::new(self.client.clone());
 
-        match connection_handler
+        let framed_connection = match connection_handler
             .connect()
-            .timeout(Self::CONNECTION_TIMEOUT)
+            .timeout(StratumClient::CONNECTION_TIMEOUT)
             .await
             .map_err(|_| error::ErrorKind::General("Connection timeout".to_string()).into())
         {
-            Ok(Ok(framed_connection)) => {
-                let (framed_sink, mut framed_stream) = framed_connection.split();
-                let framed_sink = Arc::new(Mutex::new(framed_sink));
-                match connection_handler
-                    .init_mining_session(&mut framed_stream, framed_sink.clone())
-                    .timeout(Self::CONNECTION_TIMEOUT)
-                    .await
-                    .map_err(|_| {
-                        error::ErrorKind::General("Init mining session timeout".to_string()).into()
-                    }) {
-                    Ok(Ok(init_target)) => {
-                        if self.status.initiate_running() {
-                            self.clone()
-                                .run_job_solver(framed_stream, framed_sink, init_target)
-                                .await;
-                        }
-                    }
-                    Ok(Err(e)) | Err(e) => {
-                        info!(
-                            "Failed to negotiation initial V2 target: at {}, user={} ({:?}",
-                            host_and_port, user, e
-                        );
-                        // TODO consolidate this, so that we have exactly 1 place where we
-                        //  initiate failing
-                        self.status.initiate_failing();
-                    }
-                }
-            }
+            Ok(Ok(framed_connection)) => framed_connection,
             Ok(Err(e)) | Err(e) => {
                 info!(
                     "Failed to connect to {}, user={} {:?}",
                     host_and_port, user, e
                 );
-                self.status.initiate_failing()
+                self.client.reset_redirect();
+                return Err(e);
             }
-        }
+        };
+        connection_handler.compression = framed_connection.codec().compression_switch();
+        let framed_connection =
+            session::RecordingConnection::new(framed_connection, node.session_recorder().await);
+        let (framed_sink, mut framed_stream) = framed_connection.split();
+        let framed_sink = Arc::new(Mutex::new(framed_sink));
+        let init_target = match connection_handler
+            .init_mining_session(&mut framed_stream, framed_sink.clone())
+            .timeout(StratumClient::CONNECTION_TIMEOUT)
+            .await
+            .map_err(|_| {
+                error::ErrorKind::General("Init mining session timeout".to_string()).into()
+            }) {
+            Ok(Ok(init_target)) => init_target,
+            Ok(Err(e)) | Err(e) => {
+                info!(
+                    "Failed to negotiation initial V2 target: at {}, user={} ({:?}",
+                    host_and_port, user, e
+                );
+                if !self.client.credentials().attempt_failed(&host_and_port) {
+                    self.client.reset_redirect();
+                }
+                return Err(e);
+            }
+        };
+        self.client.credentials().attempt_succeeded();
+
+        let channel_id = connection_handler.channel_id;
+        *self.job_receiver.lock().await = Some(JobReceiver {
+            connection_rx: framed_stream,
+            connection_tx: framed_sink.clone(),
+            event_handler: StratumEventHandler::new(
+                self.client.clone(),
+                Arc::downgrade(node),
+                init_target,
+            ),
+            difficulty_ramp: node
+                .difficulty_ramp()
+                .await
+                .map(|ramp| ChannelDifficultyRamp::new(channel_id, ramp, init_target)),
+        });
+        *self.solution_handler.lock().await = Some(StratumSolutionHandler::new(
+            self.client.clone(),
+            framed_sink,
+        ));
+
+        // Notify the extension user that we are ready to start forwarding its protocol
+        self.client
+            .extension_channel_sender
+            .lock()
+            .await
+            .try_send(ExtensionChannelMsg::Start)
+            .map_err(|e| {
+                info!("Stratum extension channel start error: {:?}", e);
+            })
+            .expect("BUG: stratum extension channel not available for start");
+        Ok(())
     }
 
-    async fn main_task(self: Arc<Self>) {
-        // TODO: Count as a discarded solution?
-        // Flush all obsolete solutions from previous run
-        self.solution_receiver.lock().await.flush();
+    async fn next_job(
+        &self,
+        _node: &Arc<source::Client>,
+    ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+        let mut job_receiver = self.job_receiver.lock().await;
+        let job_receiver = job_receiver.as_mut().ok_or("Stratum: no mining session")?;
+        // NOTE: `self` cannot be used inside of `select!` in the async trait method
+        let client = &self.client;
+        let mut extension_channel_rx = client.extension_channel_receiver.lock().await;
 
         loop {
-            let mut stop_receiver = self.stop_receiver.lock().await;
-            select! {
-                _ = self.clone().run().fuse() => {}
-                _ = stop_receiver.next() => {}
-            }
-
-            // Notify the other end that uses the extension channel that it should restart its
-            // operation
-            // TODO Note that this error is triggered also when there is not extension channel.
-            //  It needs to be reworked once we eliminate the need for a dummy extension channel
-            //  pair
-
-            if let Err(e) = self
-                .extension_channel_sender
-                .lock()
-                .await
-                .try_send(ExtensionChannelMsg::Stop)
+            // Ask the pool for the next difficulty along the ramp when it is due
+            let now = time::Instant::now();
+            if let Some(update) = job_receiver
+                .difficulty_ramp
+                .as_mut()
+                .and_then(|difficulty_ramp| difficulty_ramp.update(now))
             {
-                info!(
-                    "Cannot send stop notification into the extension channel: {:?}",
-                    e
-                );
+                StratumClient::send_msg(&job_receiver.connection_tx, update)
+                    .await
+                    .context("Cannot send stratum channel update")?;
             }
-            // Invalidate current job to stop working on it
-            self.job_sender.lock().await.invalidate();
-            // Flush all unprocessed solutions to empty buffer
-            // TODO: Count as a discarded solution?
-            self.solution_receiver.lock().await.flush();
-            self.solutions.lock().await.clear();
-
-            if self.status.can_stop() {
-                // NOTE: it is not safe to add here any code!
-                // The reason is that at this point the main task can be executed in parallel again
-                break;
+            let ramp_delay = job_receiver
+                .difficulty_ramp
+                .as_ref()
+                .and_then(|difficulty_ramp| difficulty_ramp.next_update(now));
+
+            select! {
+                frame = job_receiver.connection_rx.next().timeout(StratumClient::EVENT_TIMEOUT).fuse() => {
+                    match frame {
+                        Ok(Some(frame)) => {
+                            client.handle_frame(frame?, &mut job_receiver.event_handler).await?;
+                            job_receiver.event_handler.check_missing_prevhash()?;
+                            if client.is_redirect_pending() {
+                                Err("Reconnection requested by the remote stratum server")?;
+                            }
+                            if let Some(job) = job_receiver.event_handler.new_job.take() {
+                                return Ok(Some(job));
+                            }
+                        }
+                        Ok(None) | Err(_) => {
+                            Err("The remote stratum server was disconnected prematurely")?;
+                        }
+                    }
+                }
+                // Forward extension protocol frames onto the network
+                frame = extension_channel_rx.next().fuse() => {
+                    job_receiver.connection_tx.lock().await
+                        .send(frame.expect("BUG: extension channel must not shutdown!"))
+                        .await?;
+                }
+                _ = ChannelDifficultyRamp::wait_for_update(ramp_delay).fuse() => {}
             }
-            // Restarting
         }
     }
-}
-
-#[async_trait]
-impl node::Client for StratumClient {
-    fn start(self: Arc<Self>) {
-        tokio::spawn(self.clone().main_task());
-    }
 
-    fn stop(&self) {
-        if let Err(e) = self.stop_sender.clone().try_send(()) {
-            assert!(
-                e.is_full(),
-                "BUG: Unexpected error in stop sender: {}",
-                e.to_string()
-            );
-        }
+    async fn submit_solution(
+        &self,
+        node: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<source::Submission> {
+        self.solution_handler
+            .lock()
+            .await
+            .as_mut()
+            .ok_or("Stratum: no mining session")?
+            .process_solution(node, solution)
+            .await?;
+        // the response of the server is accounted by the event handler
+        Ok(source::Submission::Pending)
     }
 
-    async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
-        self.last_job
+    async fn disconnect(&self, _node: &Arc<source::Client>) {
+        self.job_receiver.lock().await.take();
+        self.solution_handler.lock().await.take();
+
+        // Notify the other end that uses the extension channel that it should restart its
+        // operation
+        // TODO Note that this error is triggered also when there is not extension channel.
+        //  It needs to be reworked once we eliminate the need for a dummy extension channel
+        //  pair
+        if let Err(e) = self
+            .client
+            .extension_channel_sender
             .lock()
             .await
-            .as_ref()
-            .map(|job| job.clone() as Arc<dyn job::Bitcoin>)
+            .try_send(ExtensionChannelMsg::Stop)
+        {
+            info!(
+                "Cannot send stop notification into the extension channel: {:?}",
+                e
+            );
+        }
+        self.client.set_current_prev_hash(None);
+        // TODO: Count as a discarded solution?
+        self.client.solutions.lock().await.clear();
     }
 
-    /// Build new connection details from the specified `descriptor`
-    fn change_connection_details(&self, descriptor: &bosminer_config::ClientDescriptor) {
+    /// Build new connection details from the specified `descriptor`. They are used for the next
+    /// connection to the pool.
+    fn change_connection_details(&self, descriptor: &bosminer_config::ClientDescriptor) -> bool {
         *self
+            .client
             .connection_details
             .lock()
             .expect("BUG: cannot lock connection details") =
             ConnectionDetails::from_descriptor(descriptor);
+        // New configuration gives previously rejected users another chance
+        *self
+            .client
+            .credentials
+            .lock()
+            .expect("BUG: cannot lock credentials") =
+            CredentialRotation::new(descriptor.user.clone(), descriptor.alternate_users.clone());
+        // Redirection of the previous configuration is no longer valid
+        *self
+            .client
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect") = ServerRedirect::new(
+            descriptor.host.clone(),
+            descriptor.reconnect_policy,
+            descriptor.reconnect_allowlist.clone(),
+        );
+        true
+    }
+}
+
+impl fmt::Display for StratumSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.client)
     }
 }
 
@@ -1043,3 +1865,353 @@ impl fmt::Display for StratumClient {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::Bitcoin as _;
+    use crate::test_utils;
+
+    use bosminer_config::ClientUserInfo;
+    use ii_stratum::v2::messages::MessageType;
+
+    #[test]
+    fn test_missing_prevhash_alarm() {
+        let mut alarm = MissingPrevHashAlarm::default();
+        let start = time::Instant::now();
+        assert!(alarm.check(start).is_ok());
+
+        // feed the alarm only with mining jobs and no prevhash
+        for job_id in 0..10 {
+            let job_msg = NewMiningJob {
+                channel_id: 0,
+                job_id,
+                future_job: job_id % 2 == 0,
+                version: 0x20000000,
+                merkle_root: Uint256Bytes([job_id as u8; 32]),
+            };
+            let now = start + time::Duration::from_secs(job_id as u64);
+            alarm.job_without_prevhash(&job_msg, now);
+            assert!(alarm.check(now).is_ok());
+        }
+        assert!(alarm
+            .check(start + MissingPrevHashAlarm::TIMEOUT - time::Duration::from_secs(1))
+            .is_ok());
+        let err = alarm
+            .check(start + MissingPrevHashAlarm::TIMEOUT)
+            .expect_err("BUG: alarm has not been raised");
+        assert!(err.to_string().contains("10 job(s) (last job ID 9)"));
+
+        // the alarm is disarmed as soon as any prevhash arrives
+        alarm.prevhash_received();
+        assert!(alarm
+            .check(start + 2 * MissingPrevHashAlarm::TIMEOUT)
+            .is_ok());
+    }
+
+    /// Build frame that claims to be a mining job but its payload cannot be deserialized
+    fn build_malformed_frame() -> v2::Frame {
+        v2::Frame::from_serialized_payload(
+            true,
+            extensions::BASE,
+            MessageType::NewMiningJob as u8,
+            (&[0xffu8; 3][..]).into(),
+        )
+    }
+
+    #[test]
+    fn test_parse_error_policy_skip() {
+        let mut handler = ParseErrorHandler::new(ClientParseErrorPolicy::Skip);
+        for i in 1..=3 {
+            let e = build_message_from_frame(build_malformed_frame())
+                .err()
+                .expect("BUG: malformed frame has been parsed");
+            assert!(handler.handle(e.into()).is_ok());
+            assert_eq!(handler.error_count(), i);
+        }
+    }
+
+    #[test]
+    fn test_parse_error_policy_disconnect() {
+        let mut handler = ParseErrorHandler::new(ClientParseErrorPolicy::Disconnect);
+        let e = build_message_from_frame(build_malformed_frame())
+            .err()
+            .expect("BUG: malformed frame has been parsed");
+        assert!(handler.handle(e.into()).is_err());
+        assert_eq!(handler.error_count(), 1);
+    }
+
+    #[test]
+    fn test_submit_jitter() {
+        let max_delay = time::Duration::from_millis(100);
+        let jitter = SubmitJitter::new(max_delay);
+
+        let delays: Vec<_> = (0..100).map(|_| jitter.delay(false)).collect();
+        assert!(delays.iter().all(|&delay| delay < max_delay));
+        // the delays have to be spread within the bound
+        assert!(delays.iter().any(|&delay| delay != delays[0]));
+
+        // blocks are submitted immediately
+        assert_eq!(jitter.delay(true), time::Duration::from_secs(0));
+        // zero bound disables the jitter
+        let jitter = SubmitJitter::new(time::Duration::from_secs(0));
+        assert_eq!(jitter.delay(false), time::Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_ntime_validator() {
+        const NOW: u32 = 1_600_000_000;
+        const TOLERANCE: u32 = 60;
+        const ROLLING: u32 = 7200;
+        let future_ntime = NOW + 3600;
+        let tolerance = Some(time::Duration::from_secs(TOLERANCE as u64));
+
+        // without configured tolerance the job is always used as it is
+        let validator = NtimeValidator::new(None, ClientNtimePolicy::Reject);
+        assert_eq!(
+            validator.validate(future_ntime, future_ntime + ROLLING, NOW),
+            Some(future_ntime)
+        );
+
+        for &policy in &[
+            ClientNtimePolicy::Accept,
+            ClientNtimePolicy::Clamp,
+            ClientNtimePolicy::Reject,
+        ] {
+            let validator = NtimeValidator::new(tolerance, policy);
+            // ntime within the tolerance is not affected by any policy
+            assert_eq!(
+                validator.validate(NOW + TOLERANCE, NOW + TOLERANCE + ROLLING, NOW),
+                Some(NOW + TOLERANCE)
+            );
+            assert_eq!(
+                validator.validate(NOW - TOLERANCE, NOW - TOLERANCE + ROLLING, NOW),
+                Some(NOW - TOLERANCE)
+            );
+
+            let expected = match policy {
+                ClientNtimePolicy::Accept => Some(future_ntime),
+                // ntime cannot be lowered below the minimal time of the job
+                ClientNtimePolicy::Clamp => Some(future_ntime),
+                ClientNtimePolicy::Reject => None,
+            };
+            assert_eq!(
+                validator.validate(future_ntime, future_ntime + ROLLING, NOW),
+                expected
+            );
+        }
+
+        // ntime in the past is clamped to the lower bound of the local clock
+        let validator = NtimeValidator::new(tolerance, ClientNtimePolicy::Clamp);
+        assert_eq!(
+            validator.validate(NOW - 3600, NOW - 3600 + ROLLING, NOW),
+            Some(NOW - TOLERANCE)
+        );
+        // but never beyond the maximal time of the job
+        assert_eq!(
+            validator.validate(NOW - 3600, NOW - 3000, NOW),
+            Some(NOW - 3000)
+        );
+    }
+
+    #[test]
+    fn test_credential_rotation() {
+        let mut credentials = CredentialRotation::new("bad".to_string(), vec!["good".to_string()]);
+        assert_eq!(credentials.user(), Some("bad".to_string()));
+
+        // the first user is rejected so the alternate one is tried
+        assert_eq!(credentials.reject(), Some("good".to_string()));
+        // successful authorization keeps the alternate user
+        assert_eq!(credentials.user(), Some("good".to_string()));
+
+        // no more users are available after the last one is rejected
+        assert_eq!(credentials.reject(), None);
+        assert_eq!(credentials.reject(), None);
+        assert_eq!(credentials.user(), None);
+    }
+
+    #[test]
+    fn test_server_redirect() {
+        let mut redirect = ServerRedirect::new(
+            "eu.example.com".to_string(),
+            ClientReconnectPolicy::SameDomain,
+            vec![],
+        );
+        assert_eq!(redirect.target(), None);
+
+        // hosts outside of the pool domain are refused
+        assert_eq!(
+            redirect.request("eu.example.com", 3336, "evil.com", 3336),
+            Err("evil.com:3336".to_string())
+        );
+        assert!(!redirect.is_pending());
+        assert_eq!(redirect.target(), None);
+
+        // empty host keeps the current one
+        assert_eq!(
+            redirect.request("eu.example.com", 3336, "", 3337),
+            Ok("eu.example.com:3337".to_string())
+        );
+        // zero port keeps the current one
+        assert_eq!(
+            redirect.request("eu.example.com", 3337, "us.example.com", 0),
+            Ok("us.example.com:3337".to_string())
+        );
+        assert_eq!(
+            redirect.target(),
+            Some(("us.example.com".to_string(), 3337))
+        );
+        assert!(redirect.is_pending());
+        assert!(redirect.take_pending());
+        assert!(!redirect.take_pending());
+
+        // IPv6 address of the new host is accepted with or without brackets
+        let mut redirect = ServerRedirect::new(
+            "2001:db8::1".to_string(),
+            ClientReconnectPolicy::Allowlist,
+            vec!["2001:db8::2".to_string()],
+        );
+        assert_eq!(
+            redirect.request("2001:db8::1", 3336, "[2001:db8::2]", 0),
+            Ok("[2001:db8::2]:3336".to_string())
+        );
+        assert_eq!(redirect.target(), Some(("2001:db8::2".to_string(), 3336)));
+
+        // the policy is evaluated against the configured host and not the redirected one
+        let mut redirect =
+            ServerRedirect::new("10.0.0.1".to_string(), ClientReconnectPolicy::Deny, vec![]);
+        assert!(redirect.request("10.0.0.1", 3336, "10.0.0.1", 3337).is_ok());
+        assert_eq!(redirect.reset(), Some(("10.0.0.1".to_string(), 3337)));
+        assert!(!redirect.is_pending());
+        assert_eq!(redirect.reset(), None);
+    }
+
+    /// Job of the test block whose network difficulty is raised so that the solution of the job
+    /// is only a share and not a block
+    #[derive(Debug, Clone)]
+    struct ShareJob {
+        block: test_utils::TestBlock,
+    }
+
+    impl job::Bitcoin for ShareJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.block.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.block.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn network_target(&self) -> ii_bitcoin::Target {
+            ii_bitcoin::Target::from_pool_difficulty(usize::MAX)
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug)]
+    struct ShareSolution {
+        nonce: u32,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for ShareSolution {
+        fn nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+    }
+
+    fn build_share(block: &test_utils::TestBlock) -> work::Solution {
+        let work = work::AssignmentBuilder::new(Arc::new(ShareJob { block: *block }))
+            .midstate(work::Midstate {
+                version: block.version(),
+                state: block.midstate,
+            })
+            .build()
+            .expect("BUG: inconsistent test block work");
+        let solution = ShareSolution {
+            nonce: block.nonce,
+            target: Default::default(),
+        };
+        work::Solution::new(work, solution, None)
+    }
+
+    #[tokio::test]
+    async fn test_drop_solution_below_channel_target() {
+        const POOL_DIFFICULTY: usize = 1 << 40;
+
+        let descriptor = ClientDescriptor::create(
+            "stratum2+tcp+insecure://127.0.0.1:3336",
+            &ClientUserInfo::new("user", None),
+            true,
+        )
+        .expect("BUG: cannot create client descriptor");
+        let source =
+            StratumSource::new(ConnectionDetails::from_descriptor(&descriptor), None, None);
+        let client = source.client.clone();
+        let (_solution_sender, solution_receiver) = mpsc::unbounded();
+        let solver = job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver);
+        let node = Arc::new(source::Client::new(Box::new(source), solver));
+
+        let (connection_tx, mut connection_rx) = mpsc::unbounded::<v2::Frame>();
+        let mut handler =
+            StratumSolutionHandler::new(client.clone(), Arc::new(Mutex::new(connection_tx)));
+
+        // the pool has raised the difficulty of the channel after the job has been sent
+        let block = &test_utils::TEST_BLOCKS[0];
+        let channel_target = ii_bitcoin::Target::from_pool_difficulty(POOL_DIFFICULTY);
+        assert!(!block.hash.meets(&channel_target));
+        client.set_channel_target(channel_target);
+
+        let solution = build_share(block);
+        assert!(!solution.is_hardware_error());
+        handler
+            .process_solution(&node, solution)
+            .await
+            .expect("BUG: cannot process solution");
+
+        // the share is not submitted and it is accounted as a local hardware error
+        assert!(connection_rx.try_next().is_err());
+        let stats = node.stats();
+        assert_eq!(stats.error_backend_diff.take_snapshot().await.solutions, 1);
+        assert_eq!(stats.stale.take_snapshot().await.solutions, 0);
+    }
+}
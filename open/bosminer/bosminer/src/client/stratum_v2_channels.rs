@@ -24,19 +24,28 @@
 
 use ii_logging::macros::*;
 
+use super::session;
+use super::source;
+use super::stratum_v2::{
+    ChannelDifficultyRamp, CredentialRotation, MissingPrevHashAlarm, NtimeValidator,
+    ParseErrorHandler, ServerRedirect, SubmitJitter,
+};
+use super::transport::{self, BoxedStream};
+
 use crate::error;
 use crate::job;
 use crate::node;
-use crate::stats;
-use crate::sync;
+use crate::stats::UnixTime as _;
 use crate::work;
 
 use failure::ResultExt;
 
-use ii_bitcoin::HashTrait;
+use ii_bitcoin::{HashTrait, MeetsTarget};
 
-use bosminer_config::{ClientDescriptor, ClientProtocol};
-use bosminer_macros::ClientNode;
+use bosminer_config::{
+    ClientDescriptor, ClientNtimePolicy, ClientParseErrorPolicy, ClientProtocol,
+    ClientReconnectPolicy, ClientRejectReason, ClientSocks5Proxy, ClientTlsOptions,
+};
 
 use async_trait::async_trait;
 use futures::channel::mpsc;
@@ -46,14 +55,16 @@ use ii_async_compat::select;
 
 use std::collections::VecDeque;
 use std::fmt;
-use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex as StdMutex;
+use std::sync::MutexGuard as StdMutexGuard;
 use std::sync::{Arc, Weak};
 use std::time;
 
 use ii_stratum::v2::framing::{Framing, Header};
 use ii_stratum::v2::messages::{
     NewMiningJob, OpenStandardMiningChannel, OpenStandardMiningChannelError,
-    OpenStandardMiningChannelSuccess, SetNewPrevHash, SetTarget, SetupConnection,
+    OpenStandardMiningChannelSuccess, Reconnect, SetNewPrevHash, SetTarget, SetupConnection,
     SetupConnectionError, SetupConnectionSuccess, SubmitSharesError, SubmitSharesStandard,
     SubmitSharesSuccess,
 };
@@ -62,19 +73,34 @@ use ii_stratum::v2::types::*;
 use ii_stratum::v2::{build_message_from_frame, Handler};
 use ii_stratum::{v1, v2};
 use ii_stratum_proxy::translation::{V2ToV1Translation, V2ToV1TranslationOptions};
-use ii_wire::Connection;
 
 use std::collections::HashMap;
 
 // TODO: move it to the stratum crate
 const VERSION_MASK: u32 = 0x1fffe000;
 
+/// Upstream V1 connection over any transport
+type V1Framed = session::RecordingConnection<
+    tokio_util::codec::Framed<BoxedStream, <v1::Framing as ii_wire::Framing>::Codec>,
+>;
+
 #[derive(Debug)]
 pub struct ConnectionDetails {
     pub user: String,
     pub host: String,
     pub port: u16,
     pub fragment: Option<String>,
+    pub alternate_users: Vec<String>,
+    pub submit_jitter: time::Duration,
+    pub reconnect_policy: ClientReconnectPolicy,
+    pub reconnect_allowlist: Vec<String>,
+    pub suggested_difficulty: Option<u64>,
+    pub parse_error_policy: ClientParseErrorPolicy,
+    pub ntime_tolerance: Option<time::Duration>,
+    pub ntime_policy: ClientNtimePolicy,
+    /// TLS session is established over the connection when present
+    pub tls: Option<ClientTlsOptions>,
+    pub proxy: Option<ClientSocks5Proxy>,
 }
 
 impl ConnectionDetails {
@@ -84,13 +110,22 @@ impl ConnectionDetails {
             host: descriptor.host.clone(),
             port: descriptor.port(),
             fragment: descriptor.fragment.clone(),
+            alternate_users: descriptor.alternate_users.clone(),
+            submit_jitter: descriptor.submit_jitter,
+            reconnect_policy: descriptor.reconnect_policy,
+            reconnect_allowlist: descriptor.reconnect_allowlist.clone(),
+            suggested_difficulty: descriptor.suggested_difficulty,
+            parse_error_policy: descriptor.parse_error_policy,
+            ntime_tolerance: descriptor.ntime_tolerance,
+            ntime_policy: descriptor.ntime_policy,
+            tls: match descriptor.protocol {
+                ClientProtocol::StratumV1Tls => Some(descriptor.tls.clone()),
+                _ => None,
+            },
+            proxy: descriptor.proxy.clone(),
         }
     }
 
-    fn get_host_and_port(&self) -> String {
-        format!("{}:{}", self.host, self.port)
-    }
-
     fn try_enable_xnsub(&self) -> bool {
         self.host.find(".nicehash.com").is_some()
             || self
@@ -99,30 +134,55 @@ impl ConnectionDetails {
                 .and_then(|fragment| fragment.find("xnsub"))
                 .is_some()
     }
+
+    /// Pools that expect `ntime` and `nonce` in block header byte order are marked with URL
+    /// fragment e.g.: `stratum+tcp://pool.example.com:3333#submit-le`
+    fn submit_byte_order(&self) -> v1::messages::SubmitByteOrder {
+        if self
+            .fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("submit-le"))
+            .is_some()
+        {
+            v1::messages::SubmitByteOrder::LittleEndian
+        } else {
+            v1::messages::SubmitByteOrder::BigEndian
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct StratumJob {
-    client: Weak<StratumClient>,
+    origin: Weak<dyn node::Client>,
+    /// Current generation of jobs shared with the client
+    job_generation: Arc<AtomicU64>,
     id: u32,
     channel_id: u32,
     version: u32,
     prev_hash: ii_bitcoin::DHash,
     merkle_root: ii_bitcoin::DHash,
     time: u32,
+    /// Maximal rolled ntime, it is not affected by adjustment of `time`
+    max_time: u32,
     bits: u32,
     target: ii_bitcoin::Target,
+    /// Time when the job has been received
+    received: time::Instant,
+    /// Generation of jobs the job belongs to (see `StratumClient::job_generation`)
+    generation: u64,
 }
 
 impl StratumJob {
     pub fn new(
-        client: Arc<StratumClient>,
+        client: &StratumClient,
+        origin: Weak<dyn node::Client>,
         job_msg: &NewMiningJob,
         prevhash_msg: &SetNewPrevHash,
         target: ii_bitcoin::Target,
     ) -> Self {
         Self {
-            client: Arc::downgrade(&client),
+            origin,
+            job_generation: client.job_generation.clone(),
             id: job_msg.job_id,
             channel_id: job_msg.channel_id,
             version: job_msg.version,
@@ -131,15 +191,20 @@ impl StratumJob {
             merkle_root: ii_bitcoin::DHash::from_slice(job_msg.merkle_root.as_ref())
                 .expect("BUG: Stratum: incorrect size of merkle root"),
             time: prevhash_msg.min_ntime,
+            max_time: prevhash_msg
+                .min_ntime
+                .saturating_add(work::engine::DEFAULT_NTIME_ROLLING_LIMIT),
             bits: prevhash_msg.nbits,
             target,
+            received: time::Instant::now(),
+            generation: client.job_generation(),
         }
     }
 }
 
 impl job::Bitcoin for StratumJob {
     fn origin(&self) -> Weak<dyn node::Client> {
-        self.client.clone()
+        self.origin.clone()
     }
 
     fn version(&self) -> u32 {
@@ -162,6 +227,10 @@ impl job::Bitcoin for StratumJob {
         self.time
     }
 
+    fn max_time(&self) -> u32 {
+        self.max_time
+    }
+
     fn bits(&self) -> u32 {
         self.bits
     }
@@ -171,54 +240,90 @@ impl job::Bitcoin for StratumJob {
     }
 
     fn is_valid(&self) -> bool {
-        // TODO: currently there is no easy way to detect the job is valid -> we have to check
-        //  its presence in the registry. The inequality below was possible in the previous
-        //  iteration of the protocol
-        // self.block_height >= self.current_block_height.load(Ordering::Relaxed)
-        true
+        // All older jobs are invalidated by a new prevhash which the translation also sends for
+        // `mining.notify` with `clean_jobs` flag
+        self.job_generation.load(Ordering::Relaxed) == self.generation
     }
 }
 
-/// Queue that contains pairs of solution and its assigned sequence number. It is our responsibility
-/// to keep the sequence number monotonic so that we as a stratum V2 client can easily process bulk
-/// acknowledgements. The sequence number type has been selected as u32 to match
+/// Queue that contains solutions with their assigned sequence number and time of submission. It is
+/// our responsibility to keep the sequence number monotonic so that we as a stratum V2 client can
+/// easily process bulk acknowledgements. The sequence number type has been selected as u32 to match
 /// up with the protocol.
-type SolutionQueue = Mutex<VecDeque<(work::Solution, u32)>>;
+type SolutionQueue = Mutex<VecDeque<(work::Solution, u32, time::Instant)>>;
 
 /// Helper task for `StratumClient` that implements Stratum V2 visitor which processes incoming
 /// messages from remote server.
 struct StratumEventHandler {
     client: Arc<StratumClient>,
+    /// Client driving the source which accounts the results of submitted shares
+    node: Weak<source::Client>,
+    /// Job built from the last processed message which is to be returned by `next_job`
+    new_job: Option<Arc<dyn job::Bitcoin>>,
     all_jobs: HashMap<u32, NewMiningJob>,
     current_prevhash_msg: Option<SetNewPrevHash>,
+    /// Message of the job which is being solved
+    current_job_msg: Option<NewMiningJob>,
     /// Mining target for the next job that is to be solved
     current_target: ii_bitcoin::Target,
+    missing_prevhash_alarm: MissingPrevHashAlarm,
+    ntime_validator: NtimeValidator,
 }
 
 impl StratumEventHandler {
-    pub fn new(client: Arc<StratumClient>, current_target: ii_bitcoin::Target) -> Self {
+    pub fn new(
+        client: Arc<StratumClient>,
+        node: Weak<source::Client>,
+        current_target: ii_bitcoin::Target,
+    ) -> Self {
+        let ntime_validator = NtimeValidator::new(
+            client.connection_details.ntime_tolerance,
+            client.connection_details.ntime_policy,
+        );
+        client.set_channel_target(current_target);
         Self {
             client,
+            node,
+            new_job: None,
             all_jobs: Default::default(),
             current_prevhash_msg: None,
+            current_job_msg: None,
             current_target,
+            missing_prevhash_alarm: Default::default(),
+            ntime_validator,
         }
     }
 
-    /// Convert new mining job message into StratumJob and send it down the line for solving.
+    /// Fails when the server keeps sending jobs without any prevhash
+    fn check_missing_prevhash(&self) -> error::Result<()> {
+        self.missing_prevhash_alarm.check(time::Instant::now())
+    }
+
+    /// Convert new mining job message into StratumJob which is passed down the line for solving.
     ///
     /// * `job_msg` - job message used as a base for the StratumJob
     async fn update_job(&mut self, job_msg: &NewMiningJob) {
-        let job = Arc::new(StratumJob::new(
-            self.client.clone(),
+        self.current_job_msg = Some(job_msg.clone());
+        let mut job = StratumJob::new(
+            &self.client,
+            self.node.clone(),
             job_msg,
             self.current_prevhash_msg
                 .as_ref()
                 .expect("TODO: no prevhash"),
             self.current_target,
-        ));
-        self.client.update_last_job(job.clone()).await;
-        self.client.job_sender.lock().await.send(job);
+        );
+        let now = time::SystemTime::now().get_unix_time().unwrap_or(job.time);
+        match self.ntime_validator.validate(job.time, job.max_time, now) {
+            Some(ntime) => job.time = ntime,
+            None => {
+                if let Some(node) = self.node.upgrade() {
+                    node.stats().invalid_jobs.inc();
+                }
+                return;
+            }
+        }
+        self.new_job = Some(Arc::new(job));
     }
 
     fn update_target(&mut self, value: Uint256Bytes) {
@@ -229,18 +334,27 @@ impl StratumEventHandler {
             new_target.get_difficulty()
         );
         self.current_target = new_target;
+        self.client.set_channel_target(new_target);
     }
 
     async fn process_accepted_shares(&self, success_msg: &SubmitSharesSuccess) {
+        let node = match self.node.upgrade() {
+            Some(node) => node,
+            None => return,
+        };
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some((solution, seq_num, submitted)) =
+            self.client.solutions.lock().await.pop_front()
+        {
+            node.stats()
+                .submission_latency
+                .account_solution(&solution, submitted, now);
             info!(
                 "Stratum: accepted solution #{} with nonce={:08x}",
                 seq_num,
                 solution.nonce()
             );
-            self.client
-                .client_stats
+            node.stats()
                 .accepted
                 .account_solution(&solution.job_target(), now)
                 .await;
@@ -256,19 +370,41 @@ impl StratumEventHandler {
     }
 
     async fn process_rejected_shares(&self, error_msg: &SubmitSharesError) {
+        let node = match self.node.upgrade() {
+            Some(node) => node,
+            None => return,
+        };
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some((solution, seq_num, submitted)) =
+            self.client.solutions.lock().await.pop_front()
+        {
+            node.stats()
+                .submission_latency
+                .account_solution(&solution, submitted, now);
             if error_msg.seq_num == seq_num {
-                info!(
-                    "Stratum: rejected solution #{} with nonce={:08x}!",
+                let current_prev_hash = self.current_prevhash_msg.as_ref().map(|prevhash_msg| {
+                    ii_bitcoin::DHash::from_slice(prevhash_msg.prev_hash.as_ref())
+                        .expect("BUG: Stratum: incorrect size of prev hash")
+                });
+                warn!(
+                    "Stratum: rejected solution #{} with nonce={:08x} and code '{}': {}",
                     seq_num,
-                    solution.nonce()
+                    solution.nonce(),
+                    error_msg.code.to_string(),
+                    job::RejectDiagnosis::diagnose(&solution, current_prev_hash.as_ref())
                 );
-                self.client
-                    .client_stats
+                node.stats()
                     .rejected
                     .account_solution(&solution.job_target(), now)
                     .await;
+                node.stats()
+                    .reject_reasons
+                    .account_solution(
+                        ClientRejectReason::classify(&error_msg.code.to_string()),
+                        &solution.job_target(),
+                        now,
+                    )
+                    .await;
                 // the rejected solution has been found
                 return;
             } else {
@@ -279,8 +415,7 @@ impl StratumEventHandler {
                     seq_num,
                     solution.nonce()
                 );
-                self.client
-                    .client_stats
+                node.stats()
                     .accepted
                     .account_solution(&solution.job_target(), now)
                     .await;
@@ -324,13 +459,19 @@ impl Handler for StratumEventHandler {
         // a `NewMiningJob` being acted on immediately, which results in `no prevhash error`. This
         // should be dealt with in proxy, but let's put the `current_prevhash_msg` existence check
         // here anyway.
-        if !job_msg.future_job && self.current_prevhash_msg.is_some() {
+        if self.current_prevhash_msg.is_none() {
+            self.missing_prevhash_alarm
+                .job_without_prevhash(job_msg, time::Instant::now());
+        } else if !job_msg.future_job {
             self.update_job(job_msg).await;
         }
     }
 
     async fn visit_set_new_prev_hash(&mut self, _header: &Header, prevhash_msg: &SetNewPrevHash) {
         self.current_prevhash_msg.replace(prevhash_msg.clone());
+        self.missing_prevhash_alarm.prevhash_received();
+        // Solutions of all previous jobs are stale from now on
+        self.client.invalidate_jobs();
 
         // find the future job with ID referenced in prevhash_msg
         let (_, mut future_job_msg) = self
@@ -339,7 +480,7 @@ impl Handler for StratumEventHandler {
             .expect("TODO: requested job ID not found");
 
         // remove all other jobs (they are now invalid)
-        self.all_jobs.retain(|_, _| true);
+        self.all_jobs.clear();
         // turn the job into an immediate job
         future_job_msg.future_job = false;
         // reinsert the job
@@ -351,7 +492,14 @@ impl Handler for StratumEventHandler {
     }
 
     async fn visit_set_target(&mut self, _header: &Header, target_msg: &SetTarget) {
+        let previous_target = self.current_target;
         self.update_target(target_msg.max_target);
+        // Restart the current job when the difficulty has been lowered (see the V2 client)
+        if self.current_target > previous_target {
+            if let Some(job_msg) = self.current_job_msg.clone() {
+                self.update_job(&job_msg).await;
+            }
+        }
     }
 
     async fn visit_submit_shares_success(
@@ -365,6 +513,11 @@ impl Handler for StratumEventHandler {
     async fn visit_submit_shares_error(&mut self, _header: &Header, error_msg: &SubmitSharesError) {
         self.process_rejected_shares(error_msg).await;
     }
+
+    async fn visit_reconnect(&mut self, _header: &Header, reconnect_msg: &Reconnect) {
+        self.client
+            .request_redirect(reconnect_msg.new_host.as_ref(), reconnect_msg.new_port);
+    }
 }
 
 trait FrameSink:
@@ -396,28 +549,23 @@ impl<T> FrameStream for T where
 {
 }
 
-struct StratumSolutionHandler<S> {
+/// Part of `StratumSolutionHandler` which sends shares to the server. It is shared with the
+/// tasks submitting delayed shares so that the sequence numbers are sent in order.
+struct StratumSubmitter<S> {
     client: Arc<StratumClient>,
     connection_tx: S,
     seq_num: u32,
 }
 
-impl<S> StratumSolutionHandler<S>
+impl<S> StratumSubmitter<S>
 where
-    S: FrameSink,
-    //    S: Sink<<Framing as ii_wire::Framing>::Tx, Error = E> + std::marker::Unpin + std::fmt::Debug
-    //    + 'static,
-    //    E: failure::Fail + std::marker::Unpin + 'static
+    S: FrameSink + Send,
 {
-    fn new(client: Arc<StratumClient>, connection_tx: S) -> Self {
-        Self {
-            client,
-            connection_tx,
-            seq_num: 0,
-        }
-    }
-
-    async fn process_solution(&mut self, solution: work::Solution) -> error::Result<()> {
+    async fn submit(
+        &mut self,
+        node: &source::Client,
+        solution: work::Solution,
+    ) -> error::Result<()> {
         let job: &StratumJob = solution.job();
 
         let seq_num = self.seq_num;
@@ -431,12 +579,15 @@ where
             ntime: solution.time(),
             version: solution.version(),
         };
+        node.stats()
+            .solution_age
+            .account_solution(time::Instant::now().saturating_duration_since(job.received));
         // store solution with sequence number for future server acknowledge
         self.client
             .solutions
             .lock()
             .await
-            .push_back((solution, seq_num));
+            .push_back((solution, seq_num, time::Instant::now()));
         // send solutions back to the stratum server
         StratumClient::send_msg(&mut self.connection_tx, share_msg)
             .await
@@ -446,8 +597,87 @@ where
     }
 }
 
+struct StratumSolutionHandler<S> {
+    client: Arc<StratumClient>,
+    submitter: Arc<Mutex<StratumSubmitter<S>>>,
+    submit_jitter: SubmitJitter,
+}
+
+impl<S> StratumSolutionHandler<S>
+where
+    S: FrameSink + Send,
+{
+    fn new(client: Arc<StratumClient>, connection_tx: S) -> Self {
+        let submit_jitter = SubmitJitter::new(client.connection_details.submit_jitter);
+        Self {
+            submitter: Arc::new(Mutex::new(StratumSubmitter {
+                client: client.clone(),
+                connection_tx,
+                seq_num: 0,
+            })),
+            client,
+            submit_jitter,
+        }
+    }
+
+    async fn process_solution(
+        &mut self,
+        node: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<()> {
+        let is_block = solution.hash().meets(&solution.network_target());
+        // The server may have raised the difficulty of the channel after the job has been
+        // received and it would reject the share
+        let channel_target = self.client.channel_target();
+        if !is_block && !solution.hash().meets(&channel_target) {
+            info!(
+                "Stratum: dropping solution with nonce={:08x} below channel target diff={}",
+                solution.nonce(),
+                channel_target.get_difficulty()
+            );
+            // The share has been found by the local hardware but it is not usable anymore so
+            // it is accounted as a local hardware error of the client
+            node.stats()
+                .error_backend_diff
+                .account_solution(solution.backend_target(), time::Instant::now())
+                .await;
+            return Ok(());
+        }
+        let delay = self.submit_jitter.delay(is_block);
+        if delay == time::Duration::from_secs(0) {
+            return self.submitter.lock().await.submit(node, solution).await;
+        }
+
+        // Delayed solution is submitted by a separate task so that it does not hold back the
+        // following ones
+        let submitter = Arc::downgrade(&self.submitter);
+        let node = node.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(delay).await;
+            let is_valid = job::Bitcoin::is_valid(solution.job::<StratumJob>());
+            match submitter.upgrade() {
+                // The mining session is still running
+                Some(submitter) if is_valid => {
+                    if let Err(e) = submitter.lock().await.submit(&node, solution).await {
+                        warn!("Stratum: cannot submit delayed solution: {}", e);
+                    }
+                }
+                _ => {
+                    node.stats()
+                        .stale
+                        .account_solution(solution.job_target(), time::Instant::now())
+                        .await;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
 struct StratumConnectionHandler {
     client: Arc<StratumClient>,
+    /// Channel opened by the pool
+    channel_id: u32,
     init_target: ii_bitcoin::Target,
     status: Option<error::Result<()>>,
 }
@@ -456,6 +686,7 @@ impl StratumConnectionHandler {
     pub fn new(client: Arc<StratumClient>) -> Self {
         Self {
             client,
+            channel_id: 0,
             init_target: Default::default(),
             status: None,
         }
@@ -522,9 +753,9 @@ impl StratumConnectionHandler {
             req_id: 10,
             user: self
                 .client
-                .connection_details
-                .user
-                .clone()
+                .credentials()
+                .user()
+                .expect("BUG: connecting without user")
                 .try_into()
                 .expect("BUG: cannot convert 'OpenStandardMiningChannel::user'"),
             nominal_hashrate: 1e9,
@@ -549,28 +780,33 @@ impl StratumConnectionHandler {
             .unwrap_or(Err("Unexpected response for stratum open channel".into()))
     }
 
-    async fn connect(self) -> error::Result<v1::Framed> {
-        let socket_addr = self
-            .client
-            .connection_details
-            .get_host_and_port()
-            .to_socket_addrs()
-            .context("Invalid server address")?
-            // TODO: this is not correct as it always only attempts to ever connect to the first
-            //  IP address from the resolved set
-            .next()
-            .ok_or("Cannot resolve any IP address")?;
+    async fn connect(
+        &self,
+        session_recorder: job::SessionRecorderConfig,
+    ) -> error::Result<V1Framed> {
+        let (host, port) = self.client.server_address();
+        let connection = match self.client.connection_details.proxy.as_ref() {
+            // the host name is resolved by the proxy
+            Some(proxy) => transport::connect_socks5(proxy, &host, port).await?,
+            None => transport::connect(&host, port)
+                .await
+                .context("Cannot connect to stratum server")?,
+        };
 
-        let connection = Connection::<v1::Framing>::connect(&socket_addr)
-            .await
-            .context("Cannot connect to stratum server")?;
+        let mut stream: BoxedStream = Box::new(connection);
+        if let Some(tls) = self.client.connection_details.tls.as_ref() {
+            stream = transport::connect_tls(stream, &host, tls).await?;
+        }
 
-        Ok(connection.into_inner())
+        Ok(V1Framed::new(
+            tokio_util::codec::Framed::new(stream, Default::default()),
+            session_recorder,
+        ))
     }
 
     /// Starts mining session and provides the initial target negotiated by the upstream endpoint
     async fn init_mining_session<R, S>(
-        mut self,
+        &mut self,
         connection_rx: &mut R,
         connection_tx: &mut S,
     ) -> error::Result<ii_bitcoin::Target>
@@ -613,6 +849,7 @@ impl Handler for StratumConnectionHandler {
         _header: &Header,
         success_msg: &OpenStandardMiningChannelSuccess,
     ) {
+        self.channel_id = success_msg.channel_id;
         self.init_target = success_msg.target.into();
         self.status = Ok(()).into();
     }
@@ -622,26 +859,25 @@ impl Handler for StratumConnectionHandler {
         _header: &Header,
         error_msg: &OpenStandardMiningChannelError,
     ) {
+        self.client.credentials().set_auth_rejected();
         self.status =
             Err(format!("Open channel error: {}", error_msg.code.to_string()).into()).into();
     }
 }
 
-#[derive(Debug, ClientNode)]
+/// State of the Stratum V1 protocol shared by the job source with the helper handlers
+#[derive(Debug)]
 pub struct StratumClient {
     connection_details: ConnectionDetails,
-    #[member_status]
-    status: sync::StatusMonitor,
-    #[member_client_stats]
-    client_stats: stats::BasicClient,
-    stop_sender: mpsc::Sender<()>,
-    stop_receiver: Mutex<mpsc::Receiver<()>>,
-    // Last job has to be week reference to prevent circular reference (the `StratumJob` keeps
-    // reference to `StratumClient`)
-    last_job: Mutex<Option<Weak<StratumJob>>>,
     solutions: SolutionQueue,
-    job_sender: Mutex<job::Sender>,
-    solution_receiver: Mutex<job::SolutionReceiver>,
+    /// Users tried when the pool rejects authorization
+    credentials: StdMutex<CredentialRotation>,
+    /// Host requested by the server with reconnect message
+    redirect: StdMutex<ServerRedirect>,
+    /// Current target of the mining channel which applies to all submitted shares
+    channel_target: StdMutex<ii_bitcoin::Target>,
+    /// Incremented whenever all current jobs become invalid (new prevhash or disconnection)
+    job_generation: Arc<AtomicU64>,
 }
 
 impl StratumClient {
@@ -649,154 +885,132 @@ impl StratumClient {
     const EVENT_TIMEOUT: time::Duration = time::Duration::from_secs(60);
     const SEND_TIMEOUT: time::Duration = time::Duration::from_secs(2);
 
-    pub fn new(connection_details: ConnectionDetails, solver: job::Solver) -> Self {
-        let (stop_sender, stop_receiver) = mpsc::channel(1);
+    pub fn new(connection_details: ConnectionDetails) -> Self {
+        let credentials = CredentialRotation::new(
+            connection_details.user.clone(),
+            connection_details.alternate_users.clone(),
+        );
+        let redirect = ServerRedirect::new(
+            connection_details.host.clone(),
+            connection_details.reconnect_policy,
+            connection_details.reconnect_allowlist.clone(),
+        );
         Self {
             connection_details,
-            status: Default::default(),
-            client_stats: Default::default(),
-            stop_sender: stop_sender,
-            stop_receiver: Mutex::new(stop_receiver),
-            last_job: Mutex::new(None),
             solutions: Mutex::new(VecDeque::new()),
-            job_sender: Mutex::new(solver.job_sender),
-            solution_receiver: Mutex::new(solver.solution_receiver),
+            credentials: StdMutex::new(credentials),
+            redirect: StdMutex::new(redirect),
+            channel_target: StdMutex::new(Default::default()),
+            job_generation: Arc::new(AtomicU64::new(0)),
         }
     }
 
-    async fn update_last_job(&self, job: Arc<StratumJob>) {
-        self.last_job.lock().await.replace(Arc::downgrade(&job));
+    fn job_generation(&self) -> u64 {
+        self.job_generation.load(Ordering::Relaxed)
     }
 
-    /// Send a message down a specified Tx Sink
-    async fn send_msg<M, S>(connection_tx: &mut S, message: M) -> error::Result<()>
-    where
-        M: TryInto<<Framing as ii_wire::Framing>::Tx, Error = <Framing as ii_wire::Framing>::Error>,
-        S: FrameSink,
-    {
-        let frame = message.try_into()?;
-        match connection_tx.send(frame).timeout(Self::SEND_TIMEOUT).await {
-            Ok(Ok(_)) => Ok(()),
-            Ok(Err(e)) => Err(e.into()),
-            Err(_) => Err("Cannot send message due to timeout")?,
-        }
+    fn invalidate_jobs(&self) {
+        self.job_generation.fetch_add(1, Ordering::Relaxed);
     }
 
-    async fn main_loop<R, S>(
-        &self,
-        mut connection_rx: R,
-        event_handler: &mut StratumEventHandler,
-        mut solution_handler: StratumSolutionHandler<S>,
-    ) -> error::Result<()>
-    where
-        R: FrameStream,
-        S: FrameSink,
-    {
-        let mut solution_receiver = self.solution_receiver.lock().await;
+    fn channel_target(&self) -> ii_bitcoin::Target {
+        *self
+            .channel_target
+            .lock()
+            .expect("BUG: cannot lock channel target")
+    }
 
-        while !self.status.is_shutting_down() {
-            select! {
-                frame = connection_rx.next().timeout(Self::EVENT_TIMEOUT).fuse() => {
-                    match frame {
-                        Ok(Some(frame)) => {
-                            let event_msg = build_message_from_frame(frame)?;
-                            event_msg.accept(event_handler).await;
-                        }
-                        Ok(None) | Err(_) => {
-                            Err("The remote stratum server was disconnected prematurely")?;
-                        }
-                    }
-                },
-                solution = solution_receiver.receive().fuse() => {
-                    match solution {
-                        Some(solution) => solution_handler.process_solution(solution).await?,
-                        None => {
-                            // TODO: initiate Destroying and remove error
-                            Err("Standard application shutdown")?;
-                        }
-                    }
-                },
-            }
+    fn set_channel_target(&self, target: ii_bitcoin::Target) {
+        *self
+            .channel_target
+            .lock()
+            .expect("BUG: cannot lock channel target") = target;
+    }
+
+    /// Configured host and port or the ones requested by the server
+    fn server_address(&self) -> (String, u16) {
+        match self
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .target()
+        {
+            Some(target) => target,
+            None => (
+                self.connection_details.host.clone(),
+                self.connection_details.port,
+            ),
         }
-        Ok(())
     }
 
-    async fn run_job_solver<R, S>(self: Arc<Self>, mut connection_rx: R, mut connection_tx: S)
-    where
-        R: FrameStream,
-        S: FrameSink,
-    {
-        // Initial target should be the result of properly initiated mining session
-        let mining_session_result = StratumConnectionHandler::new(self.clone())
-            .init_mining_session(&mut connection_rx, &mut connection_tx)
-            .timeout(Self::CONNECTION_TIMEOUT)
-            .await;
-        match mining_session_result {
-            Ok(Ok(init_target)) => {
-                let mut event_handler = StratumEventHandler::new(self.clone(), init_target);
-                let solution_handler = StratumSolutionHandler::new(self.clone(), connection_tx);
-                if let Err(_) = self
-                    .main_loop(connection_rx, &mut event_handler, solution_handler)
-                    .await
-                {
-                    self.status.initiate_failing();
-                }
-            }
-            Ok(Err(_)) | Err(_) => self.status.initiate_failing(),
+    /// Address of the configured host or the one requested by the server
+    fn host_and_port(&self) -> String {
+        let (host, port) = self.server_address();
+        transport::host_and_port(&host, port)
+    }
+
+    /// Follow request of the server to reconnect to another host when the reconnect policy of the
+    /// pool allows it. The current user is kept for the new connection.
+    fn request_redirect(&self, new_host: &str, new_port: u16) {
+        let current_host_and_port = self.host_and_port();
+        let mut redirect = self.redirect.lock().expect("BUG: cannot lock redirect");
+        let (current_host, current_port) = redirect.target().unwrap_or_else(|| {
+            (
+                self.connection_details.host.clone(),
+                self.connection_details.port,
+            )
+        });
+        match redirect.request(&current_host, current_port, new_host, new_port) {
+            Ok(host_and_port) => info!(
+                "Stratum: {} requested reconnection to {}",
+                current_host_and_port, host_and_port
+            ),
+            Err(host_and_port) => warn!(
+                "Stratum: {} requested reconnection to {} refused by reconnect policy '{}'",
+                current_host_and_port, host_and_port, self.connection_details.reconnect_policy
+            ),
         }
     }
 
-    async fn run(self: Arc<Self>) {
-        match StratumConnectionHandler::new(self.clone())
-            .connect()
-            .timeout(Self::CONNECTION_TIMEOUT)
-            .await
+    fn is_redirect_pending(&self) -> bool {
+        self.redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .is_pending()
+    }
+
+    /// Return to the configured host after connection to the redirected one has failed
+    fn reset_redirect(&self) {
+        if let Some((host, port)) = self
+            .redirect
+            .lock()
+            .expect("BUG: cannot lock redirect")
+            .reset()
         {
-            Ok(Ok(v1_framed_connection)) => {
-                if self.status.initiate_running() {
-                    let options = V2ToV1TranslationOptions {
-                        try_enable_xnsub: self.connection_details.try_enable_xnsub(),
-                    };
-                    let (translation_handler, v2_translation_rx, v2_translation_tx) =
-                        TranslationHandler::new(v1_framed_connection, options);
-                    tokio::spawn(async move {
-                        let status = translation_handler.run().await;
-                        info!("V2->V1 translation terminated: {:?}", status);
-                    });
-                    self.clone()
-                        .run_job_solver(v2_translation_rx, v2_translation_tx)
-                        .await;
-                }
-            }
-            Ok(Err(_)) | Err(_) => self.status.initiate_failing(),
+            warn!(
+                "Stratum: connection to redirected host {} failed, returning to configured host",
+                transport::host_and_port(&host, port)
+            );
         }
     }
 
-    async fn main_task(self: Arc<Self>) {
-        // TODO: Count as a discarded solution?
-        // Flush all obsolete solutions from previous run
-        self.solution_receiver.lock().await.flush();
-
-        loop {
-            let mut stop_receiver = self.stop_receiver.lock().await;
-            select! {
-                _ = self.clone().run().fuse() => {}
-                _ = stop_receiver.next() => {}
-            }
+    fn credentials(&self) -> StdMutexGuard<'_, CredentialRotation> {
+        self.credentials
+            .lock()
+            .expect("BUG: cannot lock credentials")
+    }
 
-            // Invalidate current job to stop working on it
-            self.job_sender.lock().await.invalidate();
-            // Flush all unprocessed solutions to empty buffer
-            // TODO: Count as a discarded solution?
-            self.solution_receiver.lock().await.flush();
-            self.solutions.lock().await.clear();
-
-            if self.status.can_stop() {
-                // NOTE: it is not safe to add here any code!
-                // The reason is that at this point the main task can be executed in parallel again
-                break;
-            }
-            // Restarting
+    /// Send a message down a specified Tx Sink
+    async fn send_msg<M, S>(connection_tx: &mut S, message: M) -> error::Result<()>
+    where
+        M: TryInto<<Framing as ii_wire::Framing>::Tx, Error = <Framing as ii_wire::Framing>::Error>,
+        S: FrameSink,
+    {
+        let frame = message.try_into()?;
+        match connection_tx.send(frame).timeout(Self::SEND_TIMEOUT).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err("Cannot send message due to timeout")?,
         }
     }
 }
@@ -809,11 +1023,13 @@ struct TranslationHandler {
     /// Actual protocol translator
     translation: V2ToV1Translation,
     /// Upstream V1 connection
-    v1_conn: v1::Framed,
+    v1_conn: V1Framed,
     /// Receiver for V1 frames from the translator that will be sent out via V1 connection
     v1_translation_rx: mpsc::Receiver<v1::Frame>,
     /// V2 Frames from the client that we use for feeding the translator
     v2_client_rx: mpsc::Receiver<v2::Frame>,
+    /// Decides what to do with upstream V1 messages that cannot be parsed
+    parse_error_handler: ParseErrorHandler,
 }
 
 impl TranslationHandler {
@@ -821,8 +1037,9 @@ impl TranslationHandler {
 
     /// Builds the new translation handler and provides Tx/Rx communication ends
     fn new(
-        v1_conn: v1::Framed,
+        v1_conn: V1Framed,
         options: V2ToV1TranslationOptions,
+        parse_error_policy: ClientParseErrorPolicy,
     ) -> (Self, mpsc::Receiver<v2::Frame>, mpsc::Sender<v2::Frame>) {
         let (v1_translation_tx, v1_translation_rx) =
             mpsc::channel(Self::MAX_TRANSLATION_CHANNEL_SIZE);
@@ -838,6 +1055,7 @@ impl TranslationHandler {
                 v1_conn,
                 v1_translation_rx,
                 v2_client_rx,
+                parse_error_handler: ParseErrorHandler::new(parse_error_policy),
             },
             v2_translation_rx,
             v2_client_tx,
@@ -861,8 +1079,12 @@ impl TranslationHandler {
                 v1_frame = self.v1_conn.next().timeout(StratumClient::EVENT_TIMEOUT).fuse() => {
                     match v1_frame {
                         Ok(Some(v1_frame)) => {
-                            let v1_msg = v1::build_message_from_frame(v1_frame?)?;
-                            v1_msg.accept(&mut self.translation).await;
+                            // Framing errors still terminate the connection, only messages
+                            // that cannot be parsed are subject to the configured policy
+                            match v1::build_message_from_frame(v1_frame?) {
+                                Ok(v1_msg) => v1_msg.accept(&mut self.translation).await,
+                                Err(e) => self.parse_error_handler.handle(e.into())?,
+                            }
                         }
                         Ok(None) | Err(_) => {
                             Err("Upstream V1 stratum connection dropped terminating translation")?;
@@ -906,28 +1128,211 @@ impl TranslationHandler {
     }
 }
 
+/// Receiving half of the mining session which processes messages translated from V1
+struct JobReceiver {
+    connection_rx: mpsc::Receiver<v2::Frame>,
+    /// Sending half shared with the solution handler for updates of the channel
+    connection_tx: mpsc::Sender<v2::Frame>,
+    event_handler: StratumEventHandler,
+    difficulty_ramp: Option<ChannelDifficultyRamp>,
+}
+
+/// Stratum V1 job source which talks V2 to the pool through the V2->V1 translation
+#[derive(Debug)]
+pub struct StratumSource {
+    client: Arc<StratumClient>,
+    job_receiver: Mutex<Option<JobReceiver>>,
+    solution_handler: Mutex<Option<StratumSolutionHandler<mpsc::Sender<v2::Frame>>>>,
+}
+
+impl StratumSource {
+    pub fn new(connection_details: ConnectionDetails) -> Self {
+        Self {
+            client: Arc::new(StratumClient::new(connection_details)),
+            job_receiver: Mutex::new(None),
+            solution_handler: Mutex::new(None),
+        }
+    }
+
+    /// Initial target should be the result of properly initiated mining session
+    async fn init_mining_session(
+        &self,
+        node: &Arc<source::Client>,
+        mut connection_rx: mpsc::Receiver<v2::Frame>,
+        mut connection_tx: mpsc::Sender<v2::Frame>,
+    ) -> error::Result<()> {
+        let mut connection_handler = StratumConnectionHandler::new(self.client.clone());
+        let mining_session_result = connection_handler
+            .init_mining_session(&mut connection_rx, &mut connection_tx)
+            .timeout(StratumClient::CONNECTION_TIMEOUT)
+            .await
+            .map_err(|_| {
+                error::ErrorKind::General("Init mining session timeout".to_string()).into()
+            });
+        match mining_session_result {
+            Ok(Ok(init_target)) => {
+                self.client.credentials().attempt_succeeded();
+                let channel_id = connection_handler.channel_id;
+                *self.job_receiver.lock().await = Some(JobReceiver {
+                    connection_rx,
+                    connection_tx: connection_tx.clone(),
+                    event_handler: StratumEventHandler::new(
+                        self.client.clone(),
+                        Arc::downgrade(node),
+                        init_target,
+                    ),
+                    difficulty_ramp: node
+                        .difficulty_ramp()
+                        .await
+                        .map(|ramp| ChannelDifficultyRamp::new(channel_id, ramp, init_target)),
+                });
+                *self.solution_handler.lock().await = Some(StratumSolutionHandler::new(
+                    self.client.clone(),
+                    connection_tx,
+                ));
+                Ok(())
+            }
+            Ok(Err(e)) | Err(e) => {
+                if !self
+                    .client
+                    .credentials()
+                    .attempt_failed(&self.client.host_and_port())
+                {
+                    self.client.reset_redirect();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
 #[async_trait]
-impl node::Client for StratumClient {
-    fn start(self: Arc<Self>) {
-        tokio::spawn(self.clone().main_task());
+impl source::JobSource for StratumSource {
+    async fn connect(&self, node: &Arc<source::Client>) -> error::Result<()> {
+        CredentialRotation::wait_before_reconnect(
+            &self.client.credentials,
+            &self.client.redirect,
+            node,
+            &self.client.host_and_port(),
+        )
+        .await;
+        self.client.credentials().start_attempt()?;
+        let v1_framed_connection = match StratumConnectionHandler::new(self.client.clone())
+            .connect(node.session_recorder().await)
+            .timeout(StratumClient::CONNECTION_TIMEOUT)
+            .await
+            .map_err(|_| error::ErrorKind::General("Connection timeout".to_string()).into())
+        {
+            Ok(Ok(v1_framed_connection)) => v1_framed_connection,
+            Ok(Err(e)) | Err(e) => {
+                self.client.reset_redirect();
+                return Err(e);
+            }
+        };
+
+        let connection_details = &self.client.connection_details;
+        let options = V2ToV1TranslationOptions {
+            try_enable_xnsub: connection_details.try_enable_xnsub(),
+            submit_byte_order: connection_details.submit_byte_order(),
+            suggested_difficulty: connection_details
+                .suggested_difficulty
+                .map(|difficulty| difficulty as f64),
+            ..Default::default()
+        };
+        let (translation_handler, v2_translation_rx, v2_translation_tx) = TranslationHandler::new(
+            v1_framed_connection,
+            options,
+            connection_details.parse_error_policy,
+        );
+        // The translation is terminated once the mining session drops its channels
+        tokio::spawn(async move {
+            let status = translation_handler.run().await;
+            info!("V2->V1 translation terminated: {:?}", status);
+        });
+        self.init_mining_session(node, v2_translation_rx, v2_translation_tx)
+            .await
     }
 
-    fn stop(&self) {
-        if let Err(e) = self.stop_sender.clone().try_send(()) {
-            assert!(
-                e.is_full(),
-                "BUG: Unexpected error in stop sender: {}",
-                e.to_string()
-            );
+    async fn next_job(
+        &self,
+        _node: &Arc<source::Client>,
+    ) -> error::Result<Option<Arc<dyn job::Bitcoin>>> {
+        let mut job_receiver = self.job_receiver.lock().await;
+        let job_receiver = job_receiver.as_mut().ok_or("Stratum: no mining session")?;
+
+        // NOTE: `self` cannot be used inside of `select!` in the async trait method
+        let client = &self.client;
+
+        loop {
+            // Ask the pool for the next difficulty along the ramp when it is due
+            let now = time::Instant::now();
+            if let Some(update) = job_receiver
+                .difficulty_ramp
+                .as_mut()
+                .and_then(|difficulty_ramp| difficulty_ramp.update(now))
+            {
+                StratumClient::send_msg(&mut job_receiver.connection_tx, update)
+                    .await
+                    .context("Cannot send stratum channel update")?;
+            }
+            let ramp_delay = job_receiver
+                .difficulty_ramp
+                .as_ref()
+                .and_then(|difficulty_ramp| difficulty_ramp.next_update(now));
+
+            select! {
+                frame = job_receiver.connection_rx.next().timeout(StratumClient::EVENT_TIMEOUT).fuse() => {
+                    match frame {
+                        Ok(Some(frame)) => {
+                            let event_msg = build_message_from_frame(frame)?;
+                            event_msg.accept(&mut job_receiver.event_handler).await;
+                            job_receiver.event_handler.check_missing_prevhash()?;
+                            if client.is_redirect_pending() {
+                                Err("Reconnection requested by the remote stratum server")?;
+                            }
+                            if let Some(job) = job_receiver.event_handler.new_job.take() {
+                                return Ok(Some(job));
+                            }
+                        }
+                        Ok(None) | Err(_) => {
+                            Err("The remote stratum server was disconnected prematurely")?;
+                        }
+                    }
+                }
+                _ = ChannelDifficultyRamp::wait_for_update(ramp_delay).fuse() => {}
+            }
         }
     }
 
-    async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
-        self.last_job
+    async fn submit_solution(
+        &self,
+        node: &Arc<source::Client>,
+        solution: work::Solution,
+    ) -> error::Result<source::Submission> {
+        self.solution_handler
             .lock()
             .await
-            .as_ref()
-            .and_then(|job| job.upgrade().map(|job| job as Arc<dyn job::Bitcoin>))
+            .as_mut()
+            .ok_or("Stratum: no mining session")?
+            .process_solution(node, solution)
+            .await?;
+        // the response of the server is accounted by the event handler
+        Ok(source::Submission::Pending)
+    }
+
+    async fn disconnect(&self, _node: &Arc<source::Client>) {
+        // Dropping of the channels terminates the translation
+        self.job_receiver.lock().await.take();
+        self.solution_handler.lock().await.take();
+        self.client.invalidate_jobs();
+        // TODO: Count as a discarded solution?
+        self.client.solutions.lock().await.clear();
+    }
+}
+
+impl fmt::Display for StratumSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.client)
     }
 }
 
@@ -936,7 +1341,11 @@ impl fmt::Display for StratumClient {
         write!(
             f,
             "{}://{}@{}",
-            ClientProtocol::SCHEME_STRATUM_V1,
+            if self.connection_details.tls.is_some() {
+                ClientProtocol::SCHEME_STRATUM_V1_TLS
+            } else {
+                ClientProtocol::SCHEME_STRATUM_V1
+            },
             self.connection_details.host,
             self.connection_details.user
         )
@@ -0,0 +1,602 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Transport layer of connections to remote servers
+
+use crate::error;
+
+use bosminer_config::{ClientSocks5Proxy, ClientTlsOptions};
+
+use failure::ResultExt;
+
+use futures::stream::FuturesUnordered;
+use ii_async_compat::prelude::*;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time;
+
+/// Byte stream of a connection to remote server regardless of the underlying transport
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+pub type BoxedStream = Box<dyn Stream>;
+
+/// Timeout of connection attempt to single address of the server
+pub const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+/// Delay before next connection attempt is started while the previous one is still in progress
+/// (recommended value from RFC 8305)
+pub const CONNECTION_ATTEMPT_DELAY: time::Duration = time::Duration::from_millis(250);
+
+/// Strip brackets enclosing IPv6 address of `host` in URLs
+pub fn unbracket_host(host: &str) -> &str {
+    if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    }
+}
+
+/// Format `host` and `port` as an address used in URLs and logs where IPv6 address of `host` has
+/// to be enclosed in brackets
+pub fn host_and_port(host: &str, port: u16) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    }
+}
+
+/// Sort addresses so that address families alternate starting with IPv6 while the order given
+/// by the resolver is kept within each family
+fn interleave_addresses(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+
+    let mut result = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (first, second) => result.extend(first.into_iter().chain(second)),
+        }
+    }
+    result
+}
+
+async fn connect_with_timeout(
+    address: SocketAddr,
+    timeout: time::Duration,
+) -> error::Result<TcpStream> {
+    match tokio::time::timeout(timeout, TcpStream::connect(&address)).await {
+        Ok(result) => Ok(result.context(format!("cannot connect to {}", address))?),
+        Err(_) => Err(format!("connection to {} timed out", address))?,
+    }
+}
+
+/// Try to connect to `addresses` in given order. The next attempt is started when the previous
+/// one fails or when it does not succeed in `CONNECTION_ATTEMPT_DELAY` so that a dead address
+/// does not stall the connection. The first established connection is returned.
+async fn connect_any(
+    addresses: Vec<SocketAddr>,
+    timeout: time::Duration,
+) -> error::Result<TcpStream> {
+    let mut addresses = addresses.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if let Some(address) = addresses.next() {
+            attempts.push(connect_with_timeout(address, timeout));
+        }
+        let result = if addresses.len() > 0 {
+            match tokio::time::timeout(CONNECTION_ATTEMPT_DELAY, attempts.next()).await {
+                Ok(result) => result,
+                // start attempt to the next address while keeping the pending ones
+                Err(_) => continue,
+            }
+        } else {
+            attempts.next().await
+        };
+        match result {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(e)) => last_error = Some(e),
+            // all attempts failed
+            None => break,
+        }
+    }
+    Err(last_error.unwrap_or_else(|| "no address to connect to".into()))
+}
+
+/// Resolve `host` to all its IPv6 and IPv4 addresses and connect to them in the manner of
+/// "Happy Eyeballs" (RFC 8305)
+pub async fn connect(host: &str, port: u16) -> error::Result<TcpStream> {
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .context(format!("cannot resolve '{}'", host))?
+        .collect();
+    connect_any(interleave_addresses(addresses), CONNECT_TIMEOUT).await
+}
+
+/// SOCKS protocol constants (RFC 1928) and user name/password authentication (RFC 1929)
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS5_AUTH_PASSWORD_VERSION: u8 = 0x01;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+fn socks5_reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Negotiate connection to `host:port` with SOCKS5 proxy over already connected `stream`. Host
+/// names are sent to the proxy unresolved so that they are resolved on the proxy side.
+async fn socks5_handshake<S>(
+    stream: &mut S,
+    proxy: &ClientSocks5Proxy,
+    host: &str,
+    port: u16,
+) -> error::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = vec![SOCKS5_VERSION];
+    match proxy.user {
+        Some(_) => request.extend_from_slice(&[2, SOCKS5_AUTH_NONE, SOCKS5_AUTH_PASSWORD]),
+        None => request.extend_from_slice(&[1, SOCKS5_AUTH_NONE]),
+    }
+    stream
+        .write_all(&request)
+        .await
+        .context("cannot send SOCKS5 greeting")?;
+
+    let mut reply = [0u8; 2];
+    stream
+        .read_exact(&mut reply)
+        .await
+        .context("cannot receive SOCKS5 authentication method")?;
+    if reply[0] != SOCKS5_VERSION {
+        Err(format!("unexpected SOCKS version {} of proxy", reply[0]))?;
+    }
+    match (reply[1], proxy.user.as_ref()) {
+        (SOCKS5_AUTH_NONE, _) => {}
+        (SOCKS5_AUTH_PASSWORD, Some(user)) => {
+            let password = proxy.password.as_deref().unwrap_or("");
+            if user.len() > u8::max_value() as usize {
+                Err(format!("too long user name '{}' for SOCKS5", user))?;
+            }
+            if password.len() > u8::max_value() as usize {
+                Err("too long password for SOCKS5")?;
+            }
+            let mut request = vec![SOCKS5_AUTH_PASSWORD_VERSION, user.len() as u8];
+            request.extend_from_slice(user.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream
+                .write_all(&request)
+                .await
+                .context("cannot send SOCKS5 credentials")?;
+
+            stream
+                .read_exact(&mut reply)
+                .await
+                .context("cannot receive SOCKS5 authentication status")?;
+            if reply[1] != SOCKS5_REPLY_SUCCEEDED {
+                Err("SOCKS5 proxy rejected credentials")?;
+            }
+        }
+        _ => Err("SOCKS5 proxy requires unsupported authentication method")?,
+    }
+
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0x00];
+    let host = unbracket_host(host);
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(address)) => {
+            request.push(SOCKS5_ATYP_IPV4);
+            request.extend_from_slice(&address.octets());
+        }
+        Ok(IpAddr::V6(address)) => {
+            request.push(SOCKS5_ATYP_IPV6);
+            request.extend_from_slice(&address.octets());
+        }
+        Err(_) => {
+            if host.len() > u8::max_value() as usize {
+                Err(format!("too long host name '{}' for SOCKS5", host))?;
+            }
+            request.push(SOCKS5_ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream
+        .write_all(&request)
+        .await
+        .context("cannot send SOCKS5 connect request")?;
+
+    let mut reply = [0u8; 4];
+    stream
+        .read_exact(&mut reply)
+        .await
+        .context("cannot receive SOCKS5 connect reply")?;
+    if reply[0] != SOCKS5_VERSION {
+        Err(format!("unexpected SOCKS version {} of proxy", reply[0]))?;
+    }
+    if reply[1] != SOCKS5_REPLY_SUCCEEDED {
+        Err(format!(
+            "SOCKS5 proxy cannot connect to {}: {}",
+            host_and_port(host, port),
+            socks5_reply_message(reply[1])
+        ))?;
+    }
+
+    // the bound address is not used but it has to be consumed
+    let address_len = match reply[3] {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => stream
+            .read_u8()
+            .await
+            .context("cannot receive SOCKS5 bound address")? as usize,
+        atyp => Err(format!("unexpected SOCKS5 address type {}", atyp))?,
+    };
+    let mut bound_address = vec![0u8; address_len + 2];
+    stream
+        .read_exact(&mut bound_address)
+        .await
+        .context("cannot receive SOCKS5 bound address")?;
+
+    Ok(())
+}
+
+/// Connect to `host:port` through SOCKS5 `proxy`. The negotiation with the proxy is limited by
+/// `CONNECT_TIMEOUT` the same way as the connection itself.
+pub async fn connect_socks5(
+    proxy: &ClientSocks5Proxy,
+    host: &str,
+    port: u16,
+) -> error::Result<TcpStream> {
+    let mut stream = connect(unbracket_host(&proxy.host), proxy.port)
+        .await
+        .context(format!("cannot connect to proxy {}", proxy))?;
+    match tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        socks5_handshake(&mut stream, proxy, host, port),
+    )
+    .await
+    {
+        Ok(result) => result?,
+        Err(_) => Err(format!("negotiation with proxy {} timed out", proxy))?,
+    }
+    Ok(stream)
+}
+
+/// Verifier that accepts any certificate presented by the server
+struct NoVerification;
+
+impl rustls::ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        _presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// Name sent in SNI and verified in the certificate
+fn server_name<'a>(host: &'a str, options: &'a ClientTlsOptions) -> &'a str {
+    options.server_name.as_deref().unwrap_or(host)
+}
+
+fn client_config(options: &ClientTlsOptions) -> error::Result<rustls::ClientConfig> {
+    let mut config = rustls::ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+    if let Some(ca_file) = options.ca_file.as_ref() {
+        let file = File::open(ca_file).context(format!("cannot open CA file '{}'", ca_file))?;
+        let (valid_count, _) = config
+            .root_store
+            .add_pem_file(&mut BufReader::new(file))
+            .map_err(|_| format!("cannot parse CA file '{}'", ca_file))?;
+        if valid_count == 0 {
+            Err(format!("no valid certificate in CA file '{}'", ca_file))?;
+        }
+    }
+    if !options.verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerification));
+    }
+    Ok(config)
+}
+
+/// Establish TLS session with `host` over already connected `stream`
+pub async fn connect_tls(
+    stream: BoxedStream,
+    host: &str,
+    options: &ClientTlsOptions,
+) -> error::Result<BoxedStream> {
+    let config = client_config(options)?;
+    let name = server_name(host, options);
+    // IP addresses are not supported by the certificate verification
+    let dns_name = webpki::DNSNameRef::try_from_ascii_str(name)
+        .map_err(|_| format!("invalid TLS server name '{}'", name))?;
+
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(dns_name, stream)
+        .await
+        .context(format!("TLS handshake with '{}' failed", name))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tls_options() {
+        let mut options = ClientTlsOptions::default();
+        assert!(options.verify);
+        assert_eq!(
+            server_name("pool.example.com", &options),
+            "pool.example.com"
+        );
+        assert!(client_config(&options).is_ok());
+
+        options.server_name = Some("stratum.example.com".to_string());
+        assert_eq!(server_name("10.0.0.1", &options), "stratum.example.com");
+
+        options.ca_file = Some("/nonexistent/ca.pem".to_string());
+        assert!(client_config(&options).is_err());
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        assert_eq!(
+            host_and_port("pool.example.com", 3333),
+            "pool.example.com:3333"
+        );
+        assert_eq!(host_and_port("10.0.0.1", 3333), "10.0.0.1:3333");
+        assert_eq!(host_and_port("2001:db8::1", 3333), "[2001:db8::1]:3333");
+        // brackets are not doubled
+        assert_eq!(host_and_port("[2001:db8::1]", 3333), "[2001:db8::1]:3333");
+
+        assert_eq!(unbracket_host("[2001:db8::1]"), "2001:db8::1");
+        assert_eq!(unbracket_host("2001:db8::1"), "2001:db8::1");
+        assert_eq!(unbracket_host("pool.example.com"), "pool.example.com");
+    }
+
+    #[test]
+    fn test_interleave_addresses() {
+        let addresses: Vec<SocketAddr> = [
+            "10.0.0.1:3333",
+            "10.0.0.2:3333",
+            "10.0.0.3:3333",
+            "[2001:db8::1]:3333",
+            "[2001:db8::2]:3333",
+        ]
+        .iter()
+        .map(|address| address.parse().expect("BUG: invalid address"))
+        .collect();
+        let expected: Vec<SocketAddr> = [
+            "[2001:db8::1]:3333",
+            "10.0.0.1:3333",
+            "[2001:db8::2]:3333",
+            "10.0.0.2:3333",
+            "10.0.0.3:3333",
+        ]
+        .iter()
+        .map(|address| address.parse().expect("BUG: invalid address"))
+        .collect();
+        assert_eq!(interleave_addresses(addresses), expected);
+        assert!(interleave_addresses(vec![]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connect_any() {
+        let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("BUG: cannot bind listener");
+        let address = listener.local_addr().expect("BUG: no local address");
+        // port of dropped listener is closed so the connection is refused
+        let closed_address = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("BUG: cannot bind listener")
+            .local_addr()
+            .expect("BUG: no local address");
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        let stream = connect_any(vec![closed_address, address], CONNECT_TIMEOUT)
+            .await
+            .expect("cannot connect to the second address");
+        assert_eq!(stream.peer_addr().expect("BUG: no peer address"), address);
+
+        assert!(connect_any(vec![closed_address], CONNECT_TIMEOUT)
+            .await
+            .is_err());
+        assert!(connect_any(vec![], CONNECT_TIMEOUT).await.is_err());
+    }
+
+    /// Run SOCKS5 handshake against mock proxy that answers each expected request with the
+    /// corresponding reply
+    async fn mock_socks5_handshake(
+        proxy: &ClientSocks5Proxy,
+        requests: Vec<Vec<u8>>,
+        replies: Vec<Vec<u8>>,
+    ) -> error::Result<()> {
+        let (mut stream, mut proxy_stream) =
+            tokio::net::UnixStream::pair().expect("BUG: cannot create stream pair");
+        tokio::spawn(async move {
+            for (expected_request, reply) in requests.into_iter().zip(replies) {
+                let mut request = vec![0u8; expected_request.len()];
+                proxy_stream
+                    .read_exact(&mut request)
+                    .await
+                    .expect("BUG: cannot read request");
+                assert_eq!(request, expected_request);
+                proxy_stream
+                    .write_all(&reply)
+                    .await
+                    .expect("BUG: cannot write reply");
+            }
+        });
+        socks5_handshake(&mut stream, proxy, "pool.example.com", 3333).await
+    }
+
+    #[tokio::test]
+    async fn test_socks5_handshake() {
+        let mut proxy = ClientSocks5Proxy {
+            host: "127.0.0.1".to_string(),
+            port: 9050,
+            user: None,
+            password: None,
+        };
+        // host name is sent to the proxy unresolved
+        let connect_request = b"\x05\x01\x00\x03\x10pool.example.com\x0d\x05".to_vec();
+        let connect_reply = vec![5, 0, 0, 1, 10, 0, 0, 1, 0x0d, 0x05];
+
+        mock_socks5_handshake(
+            &proxy,
+            vec![vec![5, 1, 0], connect_request.clone()],
+            vec![vec![5, 0], connect_reply.clone()],
+        )
+        .await
+        .expect("SOCKS5 handshake failed");
+
+        // proxy cannot reach the pool
+        assert!(mock_socks5_handshake(
+            &proxy,
+            vec![vec![5, 1, 0], connect_request.clone()],
+            vec![vec![5, 0], vec![5, 5, 0, 1, 0, 0, 0, 0, 0, 0]],
+        )
+        .await
+        .is_err());
+
+        // authentication with user name and password
+        proxy.user = Some("miner".to_string());
+        proxy.password = Some("secret".to_string());
+        mock_socks5_handshake(
+            &proxy,
+            vec![
+                vec![5, 2, 0, 2],
+                b"\x01\x05miner\x06secret".to_vec(),
+                connect_request.clone(),
+            ],
+            vec![vec![5, 2], vec![1, 0], connect_reply],
+        )
+        .await
+        .expect("SOCKS5 handshake with authentication failed");
+
+        // rejected credentials
+        assert!(mock_socks5_handshake(
+            &proxy,
+            vec![vec![5, 2, 0, 2], b"\x01\x05miner\x06secret".to_vec()],
+            vec![vec![5, 2], vec![1, 1]],
+        )
+        .await
+        .is_err());
+
+        // credentials which do not fit into the authentication request
+        proxy.password = Some("x".repeat(256));
+        assert!(
+            mock_socks5_handshake(&proxy, vec![vec![5, 2, 0, 2]], vec![vec![5, 2]])
+                .await
+                .is_err()
+        );
+        proxy.user = Some("x".repeat(256));
+        proxy.password = None;
+        assert!(
+            mock_socks5_handshake(&proxy, vec![vec![5, 2, 0, 2]], vec![vec![5, 2]])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_socks5() {
+        let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("BUG: cannot bind listener");
+        let proxy = ClientSocks5Proxy {
+            host: "127.0.0.1".to_string(),
+            port: listener.local_addr().expect("BUG: no local address").port(),
+            user: None,
+            password: None,
+        };
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("BUG: cannot accept");
+            let mut request = [0u8; 3];
+            stream
+                .read_exact(&mut request)
+                .await
+                .expect("BUG: cannot read greeting");
+            stream
+                .write_all(&[5, 0])
+                .await
+                .expect("BUG: cannot write method");
+            let mut request = [0u8; 10];
+            stream
+                .read_exact(&mut request)
+                .await
+                .expect("BUG: cannot read connect request");
+            assert_eq!(request, [5, 1, 0, 1, 10, 0, 0, 1, 0x0d, 0x05]);
+            stream
+                .write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x0d, 0x05])
+                .await
+                .expect("BUG: cannot write connect reply");
+
+            // second proxy connection is accepted but never negotiated
+            let (_stream, _) = listener.accept().await.expect("BUG: cannot accept");
+            tokio::time::delay_for(2 * CONNECT_TIMEOUT).await;
+        });
+
+        connect_socks5(&proxy, "10.0.0.1", 3333)
+            .await
+            .expect("cannot connect through proxy");
+        assert!(connect_socks5(&proxy, "10.0.0.1", 3333).await.is_err());
+    }
+}
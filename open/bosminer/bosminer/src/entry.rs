@@ -27,6 +27,7 @@ use crate::api;
 use crate::backend;
 use crate::hal::{self, BackendConfig as _};
 use crate::hub;
+use crate::job;
 use crate::stats;
 
 use ii_async_compat::tokio;
@@ -37,10 +38,30 @@ pub async fn main<T: hal::Backend>(backend_config: T::Config, signature: String)
     let backend_registry = Arc::new(backend::Registry::new());
     // Get frontend specific settings from backend config
     let backend_info = backend_config.info();
+    let metrics_listen_addr = backend_config.metrics_listen_addr();
+    let header_exporter = match backend_config.header_export() {
+        Some(config) => Some(
+            job::HeaderExporter::create(&config)
+                .await
+                .expect("Cannot open block header export file"),
+        ),
+        None => None,
+    };
+    let block_archive = match backend_config.block_archive() {
+        Some(config) => Some(
+            stats::BlockArchive::create(&config)
+                .await
+                .expect("Cannot open found block archive file"),
+        ),
+        None => None,
+    };
 
     // Initialize hub core which manages all resources
     let core = Arc::new(hub::Core::new(
-        backend_config.midstate_count(),
+        backend_config.work_strategy(),
+        backend_config.difficulty_ramp(),
+        header_exporter,
+        block_archive,
         &backend_registry,
         backend_info.clone(),
     ));
@@ -59,5 +80,5 @@ pub async fn main<T: hal::Backend>(backend_config: T::Config, signature: String)
     ));
 
     // the bosminer is controlled with API which also controls when the miner will end
-    api::run(core, frontend_config, signature).await;
+    api::run(core, frontend_config, metrics_listen_addr, signature).await;
 }
@@ -23,8 +23,10 @@
 //! The bosminer errors
 
 mod client;
+mod work;
 
 pub use client::ErrorKind as Client;
+pub use work::ErrorKind as Work;
 
 use ii_async_compat::prelude::*;
 
@@ -51,6 +53,10 @@ pub enum ErrorKind {
     #[fail(display = "Backend error: {}", _0)]
     Backend(String),
 
+    /// Error tied to a particular UIO device
+    #[fail(display = "UIO device {}: {}", _0, _1)]
+    UioDevice(String, String),
+
     /// Error generated by backend for selected target
     #[fail(display = "Stratum error: {}", _0)]
     Stratum(String),
@@ -58,6 +64,10 @@ pub enum ErrorKind {
     /// Error related to clients
     #[fail(display = "Client error: {}", _0)]
     Client(Client),
+
+    /// Error related to generated work
+    #[fail(display = "Work error: {}", _0)]
+    Work(Work),
 }
 
 /// Implement Fail trait instead of use Derive to get more control over custom type.
@@ -124,6 +134,12 @@ impl From<Client> for Error {
     }
 }
 
+impl From<Work> for Error {
+    fn from(work: Work) -> Self {
+        ErrorKind::Work(work).into()
+    }
+}
+
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Self {
         Self { inner }
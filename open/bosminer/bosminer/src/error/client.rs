@@ -32,4 +32,6 @@ pub enum ErrorKind {
     OnlyFixedShareRatio,
     #[fail(display = "total fixed share ratio is greater than or equal to 1.0")]
     FixedShareRatioOverflow,
+    #[fail(display = "client group quota must be greater than zero")]
+    ZeroQuota,
 }
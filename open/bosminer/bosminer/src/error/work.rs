@@ -0,0 +1,47 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+use failure::Fail;
+
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "work has to contain at least one midstate")]
+    MissingMidstate,
+    #[fail(
+        display = "midstate version {:#010x} differs from job version {:#010x} outside of mask {:#010x}",
+        version, job_version, version_mask
+    )]
+    InconsistentVersion {
+        version: u32,
+        job_version: u32,
+        version_mask: u32,
+    },
+    #[fail(
+        display = "ntime {} is out of job range {}..={}",
+        ntime, min_time, max_time
+    )]
+    NtimeOutOfRange {
+        ntime: u32,
+        min_time: u32,
+        max_time: u32,
+    },
+}
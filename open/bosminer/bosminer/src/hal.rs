@@ -20,9 +20,14 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+pub mod nonce_space;
+
+use crate::api;
 use crate::client;
 use crate::error;
+use crate::job;
 use crate::node;
+use crate::stats;
 use crate::work;
 
 use ii_cgminer_api::command;
@@ -30,6 +35,7 @@ use ii_stratum::v2::types::DeviceInfo;
 
 use std::convert::TryInto;
 use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -48,6 +54,35 @@ pub trait BackendSolution: Debug + Send + Sync {
     fn target(&self) -> &ii_bitcoin::Target;
 }
 
+/// Allocates disjoint ranges of the index space (rolled version and ntime) of a single job to the
+/// work requested by all backends. Every index must be allocated exactly once before the
+/// allocator is exhausted.
+pub trait NonceAllocator: Debug + Send + Sync {
+    /// Return next range of indexes <a, b) with the size given by the step size of the allocator.
+    /// The last range of the space is returned as `LoopState::Break` so that the decision is made
+    /// atomically with the allocation. `LoopState::Exhausted` is returned when the whole space
+    /// has been allocated.
+    fn next(&self) -> work::LoopState<(u32, u32)>;
+    /// Stop allocation of any other range
+    fn terminate(&self);
+}
+
+impl NonceAllocator for Box<dyn NonceAllocator> {
+    #[inline]
+    fn next(&self) -> work::LoopState<(u32, u32)> {
+        self.as_ref().next()
+    }
+
+    #[inline]
+    fn terminate(&self) {
+        self.as_ref().terminate()
+    }
+}
+
+/// Creates `NonceAllocator` for each new job from the size of the index space (the first
+/// argument) and the step size (the second argument)
+pub type NonceAllocatorBuilder = Arc<dyn Fn(u32, u32) -> Box<dyn NonceAllocator> + Send + Sync>;
+
 /// Enum returned from `Backend::create` is intended for choosing type of backend root node (work
 /// hub or work solver) and also for providing closure responsible for creating this node.
 pub type WorkNode<T> = node::WorkSolverType<
@@ -104,8 +139,29 @@ impl From<BackendInfo> for DeviceInfo {
 pub trait BackendConfig: Debug + Send + Sync {
     /// Number of midstates that backend is able to solve at once
     fn midstate_count(&self) -> usize;
+    /// Work generation strategy for the backend. It is selected automatically from the
+    /// `midstate_count` unless overridden.
+    fn work_strategy(&self) -> work::engine::Strategy {
+        work::engine::Strategy::from_midstate_count(self.midstate_count())
+    }
     /// Pass client manager to backend to get access to its functionality
     fn set_client_manager(&mut self, _client_manager: client::Manager) {}
+    /// Optional difficulty ramp requested from the pool by newly connected clients
+    fn difficulty_ramp(&self) -> Option<job::DifficultyRamp> {
+        None
+    }
+    /// Optional export of found block headers for external verification
+    fn header_export(&self) -> Option<job::HeaderExportConfig> {
+        None
+    }
+    /// Optional persistent archive of found blocks
+    fn block_archive(&self) -> Option<stats::BlockArchiveConfig> {
+        None
+    }
+    /// Optional address of HTTP endpoint publishing Prometheus metrics
+    fn metrics_listen_addr(&self) -> Option<SocketAddr> {
+        None
+    }
     /// Optional information about backend
     fn info(&self) -> Option<BackendInfo> {
         None
@@ -114,6 +170,17 @@ pub trait BackendConfig: Debug + Send + Sync {
 
 pub struct FrontendConfig {
     pub cgminer_custom_commands: Option<command::Map>,
+    /// Backend specific metrics published together with the general ones
+    pub metrics_collector: Option<Arc<dyn api::prometheus::Collector>>,
+    /// Allows frontend to stop the backend when it is not needed anymore
+    pub halt: Option<Arc<dyn Halt>>,
+}
+
+/// Stopping of the backend initiated by frontend
+#[async_trait]
+pub trait Halt: Send + Sync {
+    /// Stop all work solvers and release the hardware without terminating the program
+    async fn halt(&self);
 }
 
 /// Minimal interface for running compatible backend with BOSminer crate
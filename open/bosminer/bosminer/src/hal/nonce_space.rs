@@ -0,0 +1,394 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Partitioning of the 32-bit nonce space among chips of a hash chain and accounting of how much
+//! of the space has actually been searched.
+//!
+//! Hashing chips typically divide the nonce space statically by their address and by index of
+//! the core so that each core searches its own subrange. The chips do not report any progress so
+//! the coverage is measured from the nonces they return: every core is expected to return one
+//! nonce per `2^32 * difficulty` hashes of the sent work. Work which has never reached the chips
+//! or which has been replaced too early shows up as missing nonces.
+
+/// Number of nonces the whole hash chain is expected to return before the coverage of the
+/// accounting window is evaluated. It keeps the statistical error of the ratio around 3%.
+pub const WINDOW_NONCES: f64 = 1024.0;
+
+/// Expected number of hashes per one nonce returned at difficulty 1
+const HASHES_PER_DIFFICULTY_1: f64 = (1u64 << 32) as f64;
+
+/// Bit field of a nonce
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BitField {
+    shift: u32,
+    bits: u32,
+}
+
+impl BitField {
+    #[inline]
+    fn mask(&self) -> u32 {
+        ((1u64 << self.bits) - 1) as u32
+    }
+
+    #[inline]
+    fn get(&self, nonce: u32) -> usize {
+        ((nonce >> self.shift) & self.mask()) as usize
+    }
+
+    #[inline]
+    fn overlaps(&self, other: &Self) -> bool {
+        (self.mask() << self.shift) & (other.mask() << other.shift) != 0
+    }
+}
+
+/// Partition of the nonce space where index of chip and index of core which searches a nonce are
+/// encoded as bit fields in the nonce itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Partition {
+    chip: BitField,
+    core: BitField,
+    /// Number of cores actually present on each chip. Subranges of missing cores are never
+    /// searched.
+    core_count: usize,
+}
+
+impl Partition {
+    /// Create partition where chip index occupies `chip_bits` starting at `chip_shift` and
+    /// index of one of `core_count` cores occupies `core_bits` starting at `core_shift`
+    pub fn new(
+        chip_shift: u32,
+        chip_bits: u32,
+        core_shift: u32,
+        core_bits: u32,
+        core_count: usize,
+    ) -> Self {
+        assert!(chip_bits > 0 && chip_shift + chip_bits <= 32);
+        assert!(core_shift + core_bits <= 32);
+        assert!(core_count > 0 && core_count <= 1 << core_bits);
+        let partition = Self {
+            chip: BitField {
+                shift: chip_shift,
+                bits: chip_bits,
+            },
+            core: BitField {
+                shift: core_shift,
+                bits: core_bits,
+            },
+            core_count,
+        };
+        assert!(
+            !partition.chip.overlaps(&partition.core),
+            "BUG: chip and core fields overlap"
+        );
+        partition
+    }
+
+    /// Maximal number of chips the space is divided among
+    #[inline]
+    pub fn chip_count(&self) -> usize {
+        1 << self.chip.bits
+    }
+
+    /// Number of cores which search the subrange of each chip
+    #[inline]
+    pub fn core_count(&self) -> usize {
+        self.core_count
+    }
+
+    /// Index of chip which searches `nonce`
+    #[inline]
+    pub fn chip(&self, nonce: u32) -> usize {
+        self.chip.get(nonce)
+    }
+
+    /// Index of core (within its chip) which searches `nonce`
+    #[inline]
+    pub fn core(&self, nonce: u32) -> usize {
+        self.core.get(nonce)
+    }
+
+    /// Number of nonces in the subrange of one chip
+    #[inline]
+    pub fn chip_space_size(&self) -> u64 {
+        1 << (32 - self.chip.bits)
+    }
+
+    /// Number of nonces searched by one core
+    #[inline]
+    pub fn core_space_size(&self) -> u64 {
+        1 << (32 - self.chip.bits - self.core.bits)
+    }
+
+    /// Fraction of the chip subrange covered by its present cores
+    #[inline]
+    pub fn searched_ratio(&self) -> f64 {
+        self.core_count as f64 / (1u64 << self.core.bits) as f64
+    }
+}
+
+/// Statistics of nonce space coverage
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoverageStats {
+    /// Number of evaluated accounting windows
+    pub window_count: u64,
+    /// Number of windows in which the hash chain returned fewer nonces than required
+    pub incomplete_count: u64,
+    /// Fraction of the expected nonces returned by each chip since the start
+    pub chip_ratio: Vec<f64>,
+    /// Number of cores which have not returned any nonce since the start
+    pub idle_core_count: usize,
+}
+
+impl CoverageStats {
+    /// Fraction of the expected nonces returned by the whole hash chain
+    pub fn ratio(&self) -> f64 {
+        if self.chip_ratio.is_empty() {
+            return 0.0;
+        }
+        self.chip_ratio.iter().sum::<f64>() / self.chip_ratio.len() as f64
+    }
+}
+
+/// Accounting of nonce space searched by chips of one hash chain. It compares the nonces
+/// returned by every core with the number of nonces expected for the work sent to the chips.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    partition: Partition,
+    /// Expected number of hashes per one returned nonce
+    hashes_per_nonce: f64,
+    /// Fraction of each core subrange searched before the work is replaced by the next one
+    search_ratio: f64,
+    /// Window with at least this fraction of the expected nonces is considered complete
+    complete_ratio: f64,
+    /// Number of hashes each core is expected to compute for all sent work
+    core_hashes: f64,
+    /// Number of nonces returned by each core of each chip
+    core_nonces: Vec<Vec<u64>>,
+    /// Number of hashes each core is expected to compute for work sent within current window
+    window_hashes: f64,
+    /// Number of nonces returned by the hash chain within current window
+    window_nonces: u64,
+    window_count: u64,
+    incomplete_count: u64,
+}
+
+impl Coverage {
+    /// * `chip_count` - number of chips present on hash chain
+    /// * `difficulty` - difficulty of nonces returned by chips
+    /// * `search_ratio` - fraction of each core subrange searched before the work is replaced
+    ///   (backends may intentionally send work a bit faster)
+    /// * `complete_ratio` - window with at least this fraction of the expected nonces is
+    ///   considered complete
+    pub fn new(
+        partition: Partition,
+        chip_count: usize,
+        difficulty: usize,
+        search_ratio: f64,
+        complete_ratio: f64,
+    ) -> Self {
+        assert!(chip_count <= partition.chip_count());
+        Self {
+            partition,
+            hashes_per_nonce: difficulty.max(1) as f64 * HASHES_PER_DIFFICULTY_1,
+            search_ratio,
+            complete_ratio,
+            core_hashes: 0.0,
+            core_nonces: vec![vec![0; partition.core_count()]; chip_count],
+            window_hashes: 0.0,
+            window_nonces: 0,
+            window_count: 0,
+            incomplete_count: 0,
+        }
+    }
+
+    /// Number of nonces the whole hash chain is expected to return for `core_hashes`
+    #[inline]
+    fn expected_nonces(&self, core_hashes: f64) -> f64 {
+        (core_hashes * (self.core_nonces.len() * self.partition.core_count()) as f64)
+            / self.hashes_per_nonce
+    }
+
+    /// Account valid `nonce` returned by chips. Nonces which do not belong to any present core
+    /// are ignored and `false` is returned.
+    pub fn nonce_found(&mut self, nonce: u32) -> bool {
+        let chip = self.partition.chip(nonce);
+        let core = self.partition.core(nonce);
+        match self
+            .core_nonces
+            .get_mut(chip)
+            .and_then(|cores| cores.get_mut(core))
+        {
+            Some(count) => {
+                *count += 1;
+                self.window_nonces += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Account work with `midstate_count` midstates sent to chips. Once the chips are expected
+    /// to return `WINDOW_NONCES` nonces for the sent work, fraction of the expected nonces which
+    /// have been returned within the window is returned.
+    pub fn work_sent(&mut self, midstate_count: usize) -> Option<f64> {
+        let hashes =
+            (midstate_count as u64 * self.partition.core_space_size()) as f64 * self.search_ratio;
+        self.core_hashes += hashes;
+        self.window_hashes += hashes;
+
+        let expected = self.expected_nonces(self.window_hashes);
+        if expected < WINDOW_NONCES {
+            return None;
+        }
+        let ratio = self.window_nonces as f64 / expected;
+        self.window_count += 1;
+        if ratio < self.complete_ratio {
+            self.incomplete_count += 1;
+        }
+        self.window_hashes = 0.0;
+        self.window_nonces = 0;
+        Some(ratio)
+    }
+
+    pub fn stats(&self) -> CoverageStats {
+        let chip_expected =
+            self.core_hashes * self.partition.core_count() as f64 / self.hashes_per_nonce;
+        CoverageStats {
+            window_count: self.window_count,
+            incomplete_count: self.incomplete_count,
+            chip_ratio: self
+                .core_nonces
+                .iter()
+                .map(|cores| {
+                    if chip_expected == 0.0 {
+                        0.0
+                    } else {
+                        cores.iter().sum::<u64>() as f64 / chip_expected
+                    }
+                })
+                .collect(),
+            idle_core_count: self
+                .core_nonces
+                .iter()
+                .flatten()
+                .filter(|&&count| count == 0)
+                .count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Partition of BM1387 chips
+    fn partition() -> Partition {
+        Partition::new(2, 6, 24, 7, 114)
+    }
+
+    fn make_nonce(chip: u32, core: u32) -> u32 {
+        (core << 24) | (chip << 2)
+    }
+
+    #[test]
+    fn test_partition() {
+        let partition = partition();
+        assert_eq!(partition.chip_count(), 64);
+        assert_eq!(partition.core_count(), 114);
+        assert_eq!(partition.chip_space_size(), 1 << 26);
+        assert_eq!(partition.core_space_size(), 1 << 19);
+        assert_eq!(partition.searched_ratio(), 114.0 / 128.0);
+
+        assert_eq!(partition.chip(0x1234_5678), 0x1e);
+        assert_eq!(partition.core(0x1234_5678), 0x12);
+        assert_eq!(partition.chip(0xffff_ffff), 63);
+        assert_eq!(partition.core(0xffff_ffff), 127);
+
+        // every chip gets the same share of the space (chip field lies within the lowest 16 bits)
+        let mut chip_nonces = vec![0u64; partition.chip_count()];
+        for nonce in 0..(1u32 << 16) {
+            chip_nonces[partition.chip(nonce)] += 1;
+        }
+        assert!(chip_nonces.iter().all(|&count| count == 1 << 10));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_partition_overlap() {
+        Partition::new(2, 6, 7, 7, 114);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_partition_core_count() {
+        Partition::new(2, 6, 24, 7, 129);
+    }
+
+    /// Send work until the window is evaluated and return number of sent work and the ratio
+    fn close_window(coverage: &mut Coverage) -> (usize, f64) {
+        for count in 1.. {
+            if let Some(ratio) = coverage.work_sent(256) {
+                return (count, ratio);
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn test_coverage() {
+        // one work searched by two chips yields 2 * 114 * 2^27 / 2^32 = 7.125 nonces so the
+        // window is evaluated after 144 work items with 1026 expected nonces
+        let mut coverage = Coverage::new(partition(), 2, 1, 1.0, 0.85);
+        assert_eq!(coverage.stats().ratio(), 0.0);
+
+        // nonces of missing chips and cores are ignored
+        assert!(!coverage.nonce_found(make_nonce(2, 0)));
+        assert!(!coverage.nonce_found(make_nonce(0, 114)));
+
+        // all cores of the first chip and all except one of the second one return nonces
+        for i in 0..1026 {
+            assert!(coverage.nonce_found(make_nonce(i % 2, (i / 2) % 113)));
+        }
+        assert!(coverage.nonce_found(make_nonce(0, 113)));
+        assert_eq!(close_window(&mut coverage), (144, 1027.0 / 1026.0));
+
+        // dropped work of the second chip
+        for i in 0..513 {
+            coverage.nonce_found(make_nonce(0, i % 114));
+        }
+        assert_eq!(close_window(&mut coverage), (144, 0.5));
+
+        let stats = coverage.stats();
+        assert_eq!(stats.window_count, 2);
+        assert_eq!(stats.incomplete_count, 1);
+        assert_eq!(stats.chip_ratio, vec![1027.0 / 1026.0, 513.0 / 1026.0]);
+        assert_eq!(stats.idle_core_count, 1);
+        assert!((stats.ratio() - 1540.0 / 2052.0).abs() < 1e-9);
+
+        // work replaced before its nonce space is searched is expected to yield fewer nonces
+        let mut coverage = Coverage::new(partition(), 2, 1, 0.5, 0.85);
+        for i in 0..1026 {
+            coverage.nonce_found(make_nonce(i % 2, 0));
+        }
+        assert_eq!(close_window(&mut coverage), (288, 1.0));
+    }
+}
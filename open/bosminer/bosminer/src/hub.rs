@@ -29,7 +29,10 @@ use crate::backend;
 use crate::client;
 use crate::error;
 use crate::hal::{self, BackendConfig};
+use crate::job;
 use crate::node;
+use crate::stats;
+use crate::sync::event;
 use crate::work;
 
 use futures::channel::mpsc;
@@ -38,6 +41,7 @@ use futures::stream::StreamExt;
 use ii_async_compat::{futures, tokio};
 
 use std::sync::{Arc, Weak};
+use std::time;
 
 /// Handle external events. Currently it is used only wor handling exhausted work from work engine.
 /// It usually signals some serious problem in backend.
@@ -54,29 +58,53 @@ impl work::ExhaustedHandler for EventHandler {
 struct SolutionRouter {
     job_executor: Arc<client::JobExecutor>,
     solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
+    solution_queue_depth: Arc<work::SolutionQueueDepth>,
+    /// Optional export of found block headers for external verification
+    header_exporter: Option<job::HeaderExporter>,
+    /// Optional archive of found blocks
+    block_archive: Option<stats::BlockArchive>,
 }
 
 impl SolutionRouter {
     fn new(
         job_executor: Arc<client::JobExecutor>,
         solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
+        solution_queue_depth: Arc<work::SolutionQueueDepth>,
+        header_exporter: Option<job::HeaderExporter>,
+        block_archive: Option<stats::BlockArchive>,
     ) -> Self {
         Self {
             job_executor,
             solution_receiver,
+            solution_queue_depth,
+            header_exporter,
+            block_archive,
         }
     }
 
     async fn run(mut self) {
         while let Some(solution) = self.solution_receiver.next().await {
+            self.solution_queue_depth.dec();
+            let found_time = time::SystemTime::now();
             // NOTE: all solutions targeting to removed clients are discarded
             if let Some(solution_sender) = self.job_executor.get_solution_sender(&solution).await {
                 solution_sender
-                    .unbounded_send(solution)
+                    .unbounded_send(solution.clone())
                     .expect("solution queue send failed");
             } else {
                 warn!("Hub: solution has been discarded because client does not exist anymore");
             }
+            // The solution is submitted first so that it is not delayed by slow storage
+            if let Some(header_exporter) = self.header_exporter.as_mut() {
+                if let Err(e) = header_exporter.export(&solution).await {
+                    error!("Hub: cannot export block header: {}", e);
+                }
+            }
+            if let Some(block_archive) = self.block_archive.as_mut() {
+                if let Err(e) = block_archive.archive(&solution, found_time).await {
+                    error!("Hub: cannot archive found block: {}", e);
+                }
+            }
         }
     }
 }
@@ -88,16 +116,23 @@ pub struct Core {
     pub frontend: Arc<crate::Frontend>,
     job_executor: Arc<client::JobExecutor>,
     engine_receiver: work::EngineReceiver,
-    solution_sender: mpsc::UnboundedSender<work::Solution>,
+    solution_sender: work::SolutionSender,
+    /// Number of solutions waiting for routing to clients
+    solution_queue_depth: Arc<work::SolutionQueueDepth>,
     solution_router: Mutex<Option<SolutionRouter>>,
     /// Registry of clients that are able to supply new jobs for mining
     client_manager: client::Manager,
+    /// Notifies subscribers whenever statistics counters have been reset
+    counter_reset_monitor: event::Monitor,
 }
 
 /// Concentrates handles to all nodes associated with mining (backends, clients, work solvers)
 impl Core {
     pub fn new(
-        midstate_count: usize,
+        work_strategy: work::engine::Strategy,
+        difficulty_ramp: Option<job::DifficultyRamp>,
+        header_exporter: Option<job::HeaderExporter>,
+        block_archive: Option<stats::BlockArchive>,
         backend_registry: &Arc<backend::Registry>,
         backend_info: Option<hal::BackendInfo>,
     ) -> Self {
@@ -105,8 +140,9 @@ impl Core {
 
         let (engine_sender, engine_receiver) = work::engine_channel(EventHandler);
         let (solution_sender, solution_receiver) = mpsc::unbounded();
+        let solution_queue_depth = Arc::new(work::SolutionQueueDepth::default());
 
-        let client_manager = client::Manager::new(midstate_count);
+        let client_manager = client::Manager::new(work_strategy, difficulty_ramp);
         let job_executor = Arc::new(client::JobExecutor::new(
             frontend.clone(),
             engine_sender,
@@ -119,9 +155,20 @@ impl Core {
             frontend,
             job_executor: job_executor.clone(),
             engine_receiver,
-            solution_sender,
-            solution_router: Mutex::new(Some(SolutionRouter::new(job_executor, solution_receiver))),
+            solution_sender: work::SolutionSender::new(
+                solution_sender,
+                solution_queue_depth.clone(),
+            ),
+            solution_queue_depth: solution_queue_depth.clone(),
+            solution_router: Mutex::new(Some(SolutionRouter::new(
+                job_executor,
+                solution_receiver,
+                solution_queue_depth,
+                header_exporter,
+                block_archive,
+            ))),
             client_manager,
+            counter_reset_monitor: event::Monitor::new(),
         }
     }
 
@@ -201,6 +248,47 @@ impl Core {
         &self.client_manager
     }
 
+    /// Atomically reset selected counter groups in all nodes (frontend, clients, work hubs and
+    /// work solvers) while the mining continues
+    pub async fn reset_counters(&self, groups: &[stats::CounterGroup]) {
+        use node::Stats as _;
+
+        let mut nodes: Vec<Arc<dyn node::WorkSolver>> = self.get_work_hubs().await;
+        nodes.extend(self.get_work_solvers().await);
+        let mut clients = vec![];
+        for group in self.client_manager.get_groups().await {
+            clients.extend(group.get_clients().await);
+        }
+
+        let mut reset = stats::CounterReset::default();
+        for &group in groups {
+            reset.add_mining(self.frontend.mining_stats(), group);
+            for node in &nodes {
+                reset.add_mining(node.mining_stats(), group);
+            }
+            for client in &clients {
+                reset.add_client(client.stats(), group);
+            }
+        }
+        reset.reset().await;
+        for group in groups {
+            info!("Hub: {} counters have been reset", group);
+        }
+        self.counter_reset_monitor.publish().notify();
+    }
+
+    /// Number of solutions found by backends which have not been routed to clients yet
+    pub fn solution_queue_depth(&self) -> usize {
+        self.solution_queue_depth.get()
+    }
+
+    /// Subscribe to notifications about reset of statistics counters. Consumers which keep their
+    /// own state derived from the counters (e.g. rates computed from previous values) should start
+    /// over after the reset.
+    pub fn subscribe_to_counter_resets(&self) -> event::Receiver {
+        self.counter_reset_monitor.subscribe()
+    }
+
     pub async fn run(self: Arc<Self>) {
         let solution_router = self
             .solution_router
@@ -238,7 +326,7 @@ pub mod test {
                 frontend,
                 Arc::new(backend::Registry::new()),
                 engine_receiver,
-                solution_sender,
+                work::SolutionSender::new(solution_sender, Default::default()),
             ),
         )
     }
@@ -290,4 +378,88 @@ pub mod test {
         drop(job_solver);
         assert!(work_generator.generate().await.is_some());
     }
+
+    /// Verify that midstates computed for generated work are accounted in the work solver
+    #[tokio::test]
+    async fn test_midstates_stats() {
+        use crate::node::WorkSolverStats as _;
+
+        let (job_solver, work_solver_builder) = build_solvers();
+        let work_solver = Arc::new(test_utils::TestWorkSolver::new());
+
+        let mut work_generator = None;
+        work_solver_builder
+            .create_work_solver(|local_work_generator, _| {
+                work_generator = Some(local_work_generator);
+                work_solver.clone()
+            })
+            .await;
+        let mut work_generator = work_generator.unwrap();
+
+        let mut expected_midstates = 0;
+        for block in test_utils::TEST_BLOCKS.iter() {
+            job_solver.job_sender.send(Arc::new(*block));
+            let work = work_generator.generate().await.unwrap();
+            expected_midstates += work.midstates.len() as u64;
+
+            let midstates = work_solver
+                .work_solver_stats()
+                .midstates()
+                .take_snapshot()
+                .await;
+            assert_eq!(midstates.total, expected_midstates);
+        }
+    }
+
+    fn build_core(backend_registry: &Arc<backend::Registry>) -> Core {
+        Core::new(
+            work::engine::Strategy::SingleMidstate,
+            None,
+            None,
+            None,
+            backend_registry,
+            None,
+        )
+    }
+
+    /// Verify that the solution queue depth follows solutions sent by backends until they are
+    /// processed by the solution router
+    #[tokio::test]
+    async fn test_solution_queue_depth() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = build_core(&backend_registry);
+
+        assert_eq!(core.solution_queue_depth(), 0);
+        for block in test_utils::TEST_BLOCKS.iter().take(2) {
+            core.solution_sender.send(block.into());
+        }
+        assert_eq!(core.solution_queue_depth(), 2);
+
+        let solution_router = core.solution_router.lock().await.take().unwrap();
+        tokio::spawn(solution_router.run());
+        tokio::time::timeout(time::Duration::from_secs(1), async {
+            while core.solution_queue_depth() > 0 {
+                tokio::time::delay_for(time::Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("BUG: solutions have not been processed");
+    }
+
+    /// Verify that subscribers are notified about reset of statistics counters
+    #[tokio::test]
+    async fn test_counter_reset_event() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = build_core(&backend_registry);
+        let mut counter_resets = core.subscribe_to_counter_resets();
+
+        core.reset_counters(&stats::CounterGroup::ALL).await;
+        tokio::time::timeout(
+            time::Duration::from_secs(1),
+            counter_resets.wait_for_event(),
+        )
+        .await
+        .expect("BUG: missing counter reset event")
+        .expect("BUG: counter reset monitor closed");
+    }
 }
@@ -24,26 +24,50 @@ use ii_logging::macros::*;
 
 use ii_bitcoin::{HashTrait as _, MeetsTarget};
 
+use crate::client::session;
+use crate::error;
 use crate::job;
 use crate::node;
+use crate::record_file::RecordFile;
 use crate::stats::{self, DiffTargetType};
 use crate::work;
 
 use futures::channel::mpsc;
 use futures::stream::StreamExt;
-use ii_async_compat::futures;
+use ii_async_compat::{futures, tokio};
+use tokio::io::AsyncWrite;
 
+use std::collections::{HashSet, VecDeque};
 use std::convert::TryInto;
 use std::fmt::Debug;
+use std::io;
 use std::mem;
-use std::sync::{Arc, Weak};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time;
 
 use downcast_rs::{impl_downcast, Downcast};
 
+/// Helper trait that allows cloning of `Bitcoin` trait objects without knowing the concrete job
+/// type. It is automatically implemented for every job that implements `Clone`.
+pub trait BitcoinClone {
+    /// Create an owned snapshot of the job
+    fn clone_box(&self) -> Box<dyn Bitcoin>;
+}
+
+impl<T> BitcoinClone for T
+where
+    T: Bitcoin + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn Bitcoin> {
+        Box::new(self.clone())
+    }
+}
+
 /// Represents interface for Bitcoin job with access to block header from which the new work will be
 /// generated. The trait is bound to Downcast which enables connect work solution with original job
 /// and hide protocol specific details.
-pub trait Bitcoin: Debug + Downcast + Send + Sync {
+pub trait Bitcoin: Debug + Downcast + BitcoinClone + Send + Sync {
     /// Information about origin where the job has been created
     fn origin(&self) -> Weak<dyn node::Client>;
     /// Original version field that reflects the current network consensus
@@ -56,17 +80,54 @@ pub trait Bitcoin: Debug + Downcast + Send + Sync {
     fn merkle_root(&self) -> &ii_bitcoin::DHash;
     /// Current block timestamp as seconds since 1970-01-01T00:00 UTC
     fn time(&self) -> u32;
-    /// Maximal timestamp for current block as seconds since 1970-01-01T00:00 UTC
+    /// Maximal timestamp for current block as seconds since 1970-01-01T00:00 UTC which bounds
+    /// rolling of ntime in work engines
     fn max_time(&self) -> u32 {
         self.time()
+            .saturating_add(work::engine::DEFAULT_NTIME_ROLLING_LIMIT)
     }
     /// Current network target in compact format (network difficulty)
     /// https://en.bitcoin.it/wiki/Difficulty
     fn bits(&self) -> u32;
     /// Current pool/protocol target used for solution checking
     fn target(&self) -> ii_bitcoin::Target;
+    /// Difficulty of the current pool/protocol target
+    fn difficulty(&self) -> usize {
+        self.target().get_difficulty()
+    }
+    /// Network target decoded from `bits`
+    /// NOTE: it is expected that job has been checked in `Sender` and its `bits` are correct
+    fn network_target(&self) -> ii_bitcoin::Target {
+        ii_bitcoin::Target::from_compact(self.bits()).expect("BUG: job has incorrect nbits")
+    }
+    /// Network difficulty decoded from `bits`
+    fn network_difficulty(&self) -> f64 {
+        self.network_target().get_float_difficulty()
+    }
     /// Checks if job is still valid for mining
     fn is_valid(&self) -> bool;
+    /// Height of the block being mined when it is known to the job origin
+    fn block_height(&self) -> Option<u32> {
+        None
+    }
+    /// Serialized coinbase transaction when it is known to the job origin (it is not available
+    /// e.g. for standard channels of Stratum V2)
+    fn coinbase(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Block header assembled from the job fields with zero nonce. Rolled fields are replaced
+    /// by the caller.
+    fn block_header(&self) -> ii_bitcoin::BlockHeader {
+        ii_bitcoin::BlockHeader {
+            version: self.version(),
+            previous_hash: self.previous_hash().into_inner(),
+            merkle_root: self.merkle_root().into_inner(),
+            time: self.time(),
+            bits: self.bits(),
+            nonce: 0,
+        }
+    }
 
     /// Extract least-significant word of merkle root that goes to chunk2 of SHA256
     /// The word is interpreted as a little endian number.
@@ -82,6 +143,12 @@ pub trait Bitcoin: Debug + Downcast + Send + Sync {
 }
 impl_downcast!(Bitcoin);
 
+impl Clone for Box<dyn Bitcoin> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
 /// Compound object for job submission and solution reception intended to be passed to
 /// protocol handler
 pub struct Solver {
@@ -94,22 +161,170 @@ impl Solver {
         engine_sender: Arc<work::EngineSender>,
         solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
     ) -> Self {
+        let session_recorder: SessionRecorderConfig = Arc::new(StdMutex::new(None));
+        Self {
+            job_sender: Sender {
+                session_recorder: session_recorder.clone(),
+                ..Sender::new(engine_sender)
+            },
+            solution_receiver: SolutionReceiver {
+                session_recorder,
+                ..SolutionReceiver::new(solution_receiver)
+            },
+        }
+    }
+}
+
+/// Optional ramp of the difficulty requested from the pool for newly connected backends. The
+/// client asks the pool for `start_difficulty` after the mining session has been opened and raises
+/// the request linearly toward the difficulty assigned by the pool over the `period`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifficultyRamp {
+    start_difficulty: usize,
+    period: time::Duration,
+}
+
+impl DifficultyRamp {
+    pub fn new(start_difficulty: usize, period: time::Duration) -> Self {
+        assert!(start_difficulty > 0, "BUG: zero ramp start difficulty");
         Self {
-            job_sender: Sender::new(engine_sender),
-            solution_receiver: SolutionReceiver::new(solution_receiver),
+            start_difficulty,
+            period,
+        }
+    }
+
+    /// Difficulty requested from the pool after `elapsed` time from the beginning of the ramp
+    pub fn difficulty(&self, job_difficulty: usize, elapsed: time::Duration) -> usize {
+        if elapsed >= self.period || self.start_difficulty >= job_difficulty {
+            return job_difficulty;
+        }
+        let progress = elapsed.as_secs_f64() / self.period.as_secs_f64();
+        let difficulty = self.start_difficulty as f64
+            + (job_difficulty - self.start_difficulty) as f64 * progress;
+        // the request must never be stricter than the pool difficulty
+        (difficulty as usize).min(job_difficulty)
+    }
+
+    /// Target requested from the pool after `elapsed` time from the beginning of the ramp
+    pub fn target(
+        &self,
+        job_target: &ii_bitcoin::Target,
+        elapsed: time::Duration,
+    ) -> ii_bitcoin::Target {
+        let job_difficulty = job_target.get_difficulty();
+        let difficulty = self.difficulty(job_difficulty, elapsed);
+        if difficulty >= job_difficulty {
+            *job_target
+        } else {
+            ii_bitcoin::Target::from_pool_difficulty(difficulty)
         }
     }
 }
 
+/// Shared configuration of difficulty ramp which can be changed after the client is created
+pub type DifficultyRampConfig = Arc<StdMutex<Option<DifficultyRamp>>>;
+
+/// Difficulty ramp of one mining session. The requested difficulty is raised in a fixed number of
+/// steps so that the pool is not asked for a new difficulty with every message.
+#[derive(Debug, Clone)]
+pub struct DifficultyRampSession {
+    ramp: DifficultyRamp,
+    /// Target assigned by the pool when the session has been opened
+    pool_target: ii_bitcoin::Target,
+    start: time::Instant,
+    /// Target which has been requested from the pool most recently
+    requested_target: ii_bitcoin::Target,
+}
+
+impl DifficultyRampSession {
+    /// Number of steps of the ramp
+    const STEPS: u32 = 10;
+
+    pub fn new(ramp: DifficultyRamp, pool_target: ii_bitcoin::Target, now: time::Instant) -> Self {
+        Self {
+            ramp,
+            pool_target,
+            start: now,
+            requested_target: pool_target,
+        }
+    }
+
+    /// Step of the ramp reached at `now`
+    fn step(&self, now: time::Instant) -> u32 {
+        let period = self.ramp.period.as_secs_f64();
+        if period == 0.0 {
+            return Self::STEPS;
+        }
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        ((elapsed / period * Self::STEPS as f64) as u32).min(Self::STEPS)
+    }
+
+    /// Returns target to be requested from the pool at `now` or `None` when the previously
+    /// requested target is still valid
+    pub fn next_target(&mut self, now: time::Instant) -> Option<ii_bitcoin::Target> {
+        let elapsed = self.ramp.period * self.step(now) / Self::STEPS;
+        let target = self.ramp.target(&self.pool_target, elapsed);
+        if target == self.requested_target {
+            return None;
+        }
+        self.requested_target = target;
+        Some(target)
+    }
+
+    /// Time remaining to the next step of the ramp or `None` when the ramp has finished
+    pub fn next_update(&self, now: time::Instant) -> Option<time::Duration> {
+        let step = self.step(now);
+        if step >= Self::STEPS {
+            return None;
+        }
+        let next_step = self.start + self.ramp.period * (step + 1) / Self::STEPS;
+        Some(next_step.saturating_duration_since(now))
+    }
+}
+
+/// Optional recorder of the pool session used by both job sender and solution receiver
+pub type SessionRecorderConfig = Arc<StdMutex<Option<session::Recorder>>>;
+
+/// Record an event of the pool session when the recording is enabled. The recorder only queues
+/// the event for its writer thread so the lock is never held during any I/O.
+pub(crate) fn record_session<F: FnOnce(&session::Recorder)>(
+    session_recorder: &SessionRecorderConfig,
+    f: F,
+) {
+    if let Some(recorder) = session_recorder
+        .lock()
+        .expect("cannot lock session recorder")
+        .as_ref()
+    {
+        f(recorder);
+    }
+}
+
 /// This is the entrypoint for new jobs and updates into processing.
 /// Typically the mining protocol handler will inject new jobs through it
 pub struct Sender {
     engine_sender: Arc<work::EngineSender>,
+    session_recorder: SessionRecorderConfig,
+    difficulty_ramp: DifficultyRampConfig,
 }
 
 impl Sender {
     pub fn new(engine_sender: Arc<work::EngineSender>) -> Self {
-        Self { engine_sender }
+        Self {
+            engine_sender,
+            session_recorder: Arc::new(StdMutex::new(None)),
+            difficulty_ramp: Arc::new(StdMutex::new(None)),
+        }
+    }
+
+    /// Returns shared recorder of the pool session
+    pub fn session_recorder(&self) -> SessionRecorderConfig {
+        self.session_recorder.clone()
+    }
+
+    /// Returns shared configuration of difficulty ramp requested from the pool
+    pub fn difficulty_ramp(&self) -> DifficultyRampConfig {
+        self.difficulty_ramp.clone()
     }
 
     /// Check if the job has valid attributes
@@ -142,6 +357,9 @@ impl Sender {
         // send only jobs with correct data
         if let Some(origin) = origin {
             origin.client_stats().valid_jobs().inc();
+            record_session(&self.session_recorder, |recorder| {
+                recorder.record_job(job.as_ref())
+            });
             info!("--- broadcasting new job ---");
             self.engine_sender.broadcast_job(job);
         } else {
@@ -156,16 +374,113 @@ impl Sender {
     }
 }
 
+/// Counter of extranonce2 space with the size specified by the pool. Each value is handed out
+/// only once: the counter does not wrap around and reports exhaustion instead, so two jobs with
+/// the same coinbase prefix are never built from the same extranonce2.
+#[derive(Debug, Clone)]
+pub struct Extranonce2 {
+    size: usize,
+    /// Next value to be used or `None` when the whole space has been exhausted
+    next: Option<u64>,
+}
+
+impl Extranonce2 {
+    /// Maximal supported size of extranonce2 in bytes
+    pub const MAX_SIZE: usize = mem::size_of::<u64>();
+
+    pub fn new(size: usize) -> error::Result<Self> {
+        if size == 0 || size > Self::MAX_SIZE {
+            Err(error::ErrorKind::General(format!(
+                "unsupported extranonce2 size {} (expected 1 to {} bytes)",
+                size,
+                Self::MAX_SIZE
+            )))?
+        }
+        Ok(Self {
+            size,
+            next: Some(0),
+        })
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Maximal value which fits into the extranonce2 space
+    #[inline]
+    pub fn max_value(&self) -> u64 {
+        u64::max_value() >> (8 * (Self::MAX_SIZE - self.size))
+    }
+
+    /// Return the next unused extranonce2 serialized as little-endian bytes of the configured
+    /// size or `None` when the space has been exhausted
+    pub fn roll(&mut self) -> Option<Vec<u8>> {
+        let value = self.next?;
+        self.next = if value < self.max_value() {
+            Some(value + 1)
+        } else {
+            None
+        };
+        Some(value.to_le_bytes()[..self.size].to_vec())
+    }
+
+    /// Start over the extranonce2 space. It is only safe when the coinbase prefix has changed
+    /// (e.g. new connection with a different extranonce1).
+    pub fn reset(&mut self) {
+        self.next = Some(0);
+    }
+}
+
+/// Bounded set of recently submitted shares. The hash of the block header identifies the job
+/// together with nonce, ntime and version of the solution.
+#[derive(Debug, Default)]
+struct SubmittedShares {
+    hashes: HashSet<ii_bitcoin::DHash>,
+    /// Order of insertion used for forgetting the oldest shares
+    order: VecDeque<ii_bitcoin::DHash>,
+}
+
+impl SubmittedShares {
+    /// Maximal number of remembered shares
+    const CAPACITY: usize = 1024;
+
+    fn contains(&self, hash: &ii_bitcoin::DHash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Remember a share with block header `hash`. Returns `false` when the share is already
+    /// present.
+    fn insert(&mut self, hash: ii_bitcoin::DHash) -> bool {
+        if !self.hashes.insert(hash) {
+            return false;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > Self::CAPACITY {
+            let oldest = self.order.pop_front().expect("BUG: no submitted shares");
+            self.hashes.remove(&oldest);
+        }
+        true
+    }
+}
+
 /// Receives `work::Solution` via a channel and filters only solutions that meet the client/pool
 /// specified target
 #[derive(Debug)]
 pub struct SolutionReceiver {
     solution_channel: mpsc::UnboundedReceiver<work::Solution>,
+    /// Shares passed for submission which are used to drop duplicate solutions
+    submitted_shares: SubmittedShares,
+    session_recorder: SessionRecorderConfig,
 }
 
 impl SolutionReceiver {
     pub fn new(solution_channel: mpsc::UnboundedReceiver<work::Solution>) -> Self {
-        Self { solution_channel }
+        Self {
+            solution_channel,
+            submitted_shares: Default::default(),
+            session_recorder: Arc::new(StdMutex::new(None)),
+        }
     }
 
     fn trace_share(solution: &work::Solution, target: &ii_bitcoin::Target) {
@@ -191,41 +506,430 @@ impl SolutionReceiver {
             let path = solution.path();
             let time = solution.timestamp();
             let hash = solution.hash();
-            let job_target = solution.job_target();
+            if self.submitted_shares.contains(hash) {
+                warn!(
+                    "Dropping duplicate solution with nonce={:08x} ntime={:08x} version={:08x}",
+                    solution.nonce(),
+                    solution.time(),
+                    solution.version()
+                );
+                // the duplicate has been already accounted and submitted
+                continue;
+            }
+            let job_target = *solution.job_target();
 
             // compare block hash for given solution with all targets
             // TODO: create tests for solution validation with all difficulty variants
-            assert!(&solution.network_target() <= job_target);
-            if hash.meets(&solution.network_target()) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Network)
-                    .await;
-            } else if hash.meets(&job_target) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Job).await;
-            } else if hash.meets(solution.backend_target()) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Backend)
-                    .await;
-                // skip submitting the solution as we've only met backend difficulty
-                continue;
-            } else {
-                stats::account_error_backend_diff(&path, &solution.backend_target(), time).await;
-                // skip submitting the solution as this is a backend error
-                continue;
+            assert!(solution.network_target() <= job_target);
+            match solution.verify(&job_target) {
+                work::Verdict::ValidShare => {
+                    let met_diff_target_type = if hash.meets(&solution.network_target()) {
+                        DiffTargetType::Network
+                    } else {
+                        DiffTargetType::Job
+                    };
+                    stats::account_valid_solution(&path, &solution, time, met_diff_target_type)
+                        .await;
+                }
+                work::Verdict::BelowShareTarget => {
+                    stats::account_valid_solution(&path, &solution, time, DiffTargetType::Backend)
+                        .await;
+                    // skip submitting the solution as we've only met backend difficulty
+                    continue;
+                }
+                work::Verdict::HardwareError => {
+                    stats::account_error_backend_diff(&path, &solution.backend_target(), time)
+                        .await;
+                    // skip submitting the solution as this is a backend error
+                    continue;
+                }
             }
 
+            if !solution.has_valid_version() {
+                warn!(
+                    "Solution with version {:#010x} is outside of job version mask {:#010x}",
+                    solution.version(),
+                    solution.version_mask()
+                );
+                // skip submitting the solution as the server would reject it anyway
+                continue;
+            }
             if solution.has_valid_job() {
-                // TODO: Account solution to Discard meter
                 Self::trace_share(&solution, &job_target);
+                self.submitted_shares.insert(*solution.hash());
+                record_session(&self.session_recorder, |recorder| {
+                    recorder.record_solution(&solution)
+                });
                 return Some(solution);
             }
+            // late solution of a job that has been invalidated in the meantime (e.g. by a new
+            // block) would be rejected by the server
+            if let Some(origin) = solution.origin().upgrade() {
+                origin
+                    .client_stats()
+                    .stale()
+                    .account_solution(solution.job_target(), time)
+                    .await;
+            }
         }
         None
     }
 
     /// Empty all buffered solutions without blocking. This is to prevent the client from submitting
-    /// already stale solutions
+    /// already stale solutions.
     /// TODO: We should review this regularly as there may be extensions in the mining protocol that
     /// may allow resume a mining session
     pub fn flush(&mut self) {
         while let Ok(Some(_)) = self.solution_channel.try_next() {}
     }
 }
+
+/// Local explanation of a share rejected by the remote server. The block header of the solution is
+/// recomputed and checked against the share target and the current state of the job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectDiagnosis {
+    /// The hash of the recomputed header does not meet the share target
+    TargetNotMet {
+        hash: ii_bitcoin::DHash,
+        target: ii_bitcoin::Target,
+    },
+    /// The job has been invalidated or the server has already moved to another block
+    StaleJob { previous_hash: ii_bitcoin::DHash },
+    /// The share is valid from the local point of view
+    Unknown { hash: ii_bitcoin::DHash },
+}
+
+impl RejectDiagnosis {
+    /// Diagnose rejected `solution` with respect to `current_previous_hash` which is the hash of
+    /// the previous block the remote server currently builds on (if known)
+    pub fn diagnose(
+        solution: &work::Solution,
+        current_previous_hash: Option<&ii_bitcoin::DHash>,
+    ) -> Self {
+        let header = solution.get_block_header();
+        // do not use the cached hash to detect any inconsistency between header and solution
+        let hash = header.hash();
+        let target = *solution.job_target();
+        if !hash.meets(&target) {
+            return Self::TargetNotMet { hash, target };
+        }
+
+        let previous_hash = ii_bitcoin::DHash::from_slice(&header.previous_hash)
+            .expect("BUG: incorrect size of previous hash");
+        let is_stale = match current_previous_hash {
+            Some(current_previous_hash) => *current_previous_hash != previous_hash,
+            None => false,
+        };
+        if is_stale || !solution.has_valid_job() {
+            return Self::StaleJob { previous_hash };
+        }
+        Self::Unknown { hash }
+    }
+}
+
+impl std::fmt::Display for RejectDiagnosis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TargetNotMet { hash, target } => {
+                write!(f, "hash 0x{:x} does not meet target 0x{:x}", hash, target)
+            }
+            Self::StaleJob { previous_hash } => write!(
+                f,
+                "job stale (built on previous block 0x{:x})",
+                previous_hash
+            ),
+            Self::Unknown { hash } => write!(
+                f,
+                "hash 0x{:x} meets target and job is fresh (rejected for a server side reason)",
+                hash
+            ),
+        }
+    }
+}
+
+/// Configuration of raw block header export for external verification of found shares
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderExportConfig {
+    /// File where the headers are appended
+    pub path: PathBuf,
+    /// Export only solutions that meet the network target to limit the volume
+    pub blocks_only: bool,
+}
+
+/// Writes full 80-byte block headers of found shares as hexadecimal strings (one per line) so that
+/// an external tool can independently verify them against the blockchain
+pub struct HeaderExporter<W = tokio::fs::File> {
+    output: RecordFile<W>,
+    blocks_only: bool,
+}
+
+impl HeaderExporter {
+    /// Opens the export file specified by `config` in append mode
+    pub async fn create(config: &HeaderExportConfig) -> io::Result<Self> {
+        Ok(Self {
+            output: RecordFile::open(&config.path).await?,
+            blocks_only: config.blocks_only,
+        })
+    }
+}
+
+impl<W: AsyncWrite + Unpin> HeaderExporter<W> {
+    pub fn new(output: W, blocks_only: bool) -> Self {
+        Self {
+            output: RecordFile::new(output),
+            blocks_only,
+        }
+    }
+
+    /// Export the solution when it meets job target (or network target when only blocks are
+    /// exported). Returns true when the solution header has been written.
+    pub async fn export(&mut self, solution: &work::Solution) -> io::Result<bool> {
+        let target = if self.blocks_only {
+            solution.network_target()
+        } else {
+            *solution.job_target()
+        };
+        if !solution.hash().meets(&target) {
+            return Ok(false);
+        }
+        self.output
+            .append(hex::encode(&solution.get_block_header().into_bytes()[..]))
+            .await?;
+        Ok(true)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.into_inner()
+    }
+}
+
+impl<W> Debug for HeaderExporter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeaderExporter")
+            .field("blocks_only", &self.blocks_only)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{self, TestBlockBuilder as _};
+
+    use ii_bitcoin::HashTrait;
+
+    #[test]
+    fn test_clone_box() {
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let job: Arc<dyn Bitcoin> = Arc::new(*block);
+            let cloned_job = job.clone_box();
+
+            assert_eq!(job.version(), cloned_job.version());
+            assert_eq!(job.version_mask(), cloned_job.version_mask());
+            assert_eq!(job.previous_hash(), cloned_job.previous_hash());
+            assert_eq!(job.merkle_root(), cloned_job.merkle_root());
+            assert_eq!(job.time(), cloned_job.time());
+            assert_eq!(job.bits(), cloned_job.bits());
+            assert_eq!(job.target(), cloned_job.target());
+
+            // the snapshot must keep its concrete type
+            let cloned_block = cloned_job
+                .downcast_ref::<test_utils::TestBlock>()
+                .expect("BUG: cannot downcast cloned job");
+            assert_eq!(block.hash, cloned_block.hash);
+        }
+    }
+
+    #[test]
+    fn test_job_targets() {
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let job: Arc<dyn Bitcoin> =
+                Arc::new(block.change_target(ii_bitcoin::Target::from_pool_difficulty(64)));
+            assert_eq!(job.difficulty(), 64);
+            assert_eq!(job.network_target(), block.target);
+            assert_eq!(
+                job.network_difficulty(),
+                block.target.get_float_difficulty()
+            );
+            assert!(job.network_difficulty() > job.difficulty() as f64);
+        }
+    }
+
+    #[test]
+    fn test_block_header() {
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let job: Arc<dyn Bitcoin> = Arc::new(*block);
+            let header = ii_bitcoin::BlockHeader {
+                nonce: block.nonce,
+                ..job.block_header()
+            };
+
+            assert_eq!(&header.into_bytes()[..], &block.header_bytes[..]);
+            assert_eq!(header.hash(), block.hash);
+        }
+    }
+
+    #[test]
+    fn test_extranonce2() {
+        assert!(Extranonce2::new(0).is_err());
+        assert!(Extranonce2::new(Extranonce2::MAX_SIZE + 1).is_err());
+        assert_eq!(
+            Extranonce2::new(Extranonce2::MAX_SIZE)
+                .expect("BUG: cannot create extranonce2")
+                .max_value(),
+            u64::max_value()
+        );
+
+        let mut extranonce2 = Extranonce2::new(1).expect("BUG: cannot create extranonce2");
+        assert_eq!(extranonce2.max_value(), 0xff);
+        assert_eq!(extranonce2.roll(), Some(vec![0x00]));
+        assert_eq!(extranonce2.roll(), Some(vec![0x01]));
+        for _ in 2..=0xff {
+            assert!(extranonce2.roll().is_some());
+        }
+        // the space is exhausted and no value is reused
+        assert_eq!(extranonce2.roll(), None);
+        assert_eq!(extranonce2.roll(), None);
+
+        extranonce2.reset();
+        assert_eq!(extranonce2.roll(), Some(vec![0x00]));
+
+        let mut extranonce2 = Extranonce2::new(4).expect("BUG: cannot create extranonce2");
+        extranonce2.roll();
+        assert_eq!(extranonce2.roll(), Some(vec![0x01, 0x00, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn test_submitted_shares() {
+        let mut submitted_shares = SubmittedShares::default();
+        let hashes: Vec<_> = (0..=SubmittedShares::CAPACITY)
+            .map(|i| ii_bitcoin::DHash::hash(&i.to_le_bytes()))
+            .collect();
+
+        assert!(submitted_shares.insert(hashes[0]));
+        assert!(submitted_shares.contains(&hashes[0]));
+        // duplicate is detected
+        assert!(!submitted_shares.insert(hashes[0]));
+
+        // the oldest share is forgotten when the capacity is exceeded
+        for hash in &hashes[1..] {
+            assert!(submitted_shares.insert(*hash));
+        }
+        assert!(!submitted_shares.contains(&hashes[0]));
+        assert!(submitted_shares.contains(&hashes[1]));
+        assert_eq!(submitted_shares.order.len(), SubmittedShares::CAPACITY);
+    }
+
+    #[test]
+    fn test_reject_diagnosis() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let previous_hash = block.previous_hash;
+
+        // the share meets only a trivial target while the job requires the maximal difficulty
+        let mut low_difficulty_block = block;
+        low_difficulty_block.nonce ^= 1;
+        let low_difficulty_block = low_difficulty_block
+            .change_target(ii_bitcoin::Target::from_pool_difficulty(std::usize::MAX));
+        let solution: work::Solution = low_difficulty_block.into();
+        let diagnosis = RejectDiagnosis::diagnose(&solution, Some(&previous_hash));
+        match diagnosis {
+            RejectDiagnosis::TargetNotMet { hash, target } => {
+                assert_eq!(&hash, solution.hash());
+                assert_eq!(&target, solution.job_target());
+            }
+            _ => panic!("unexpected reject diagnosis: {:?}", diagnosis),
+        }
+        assert!(diagnosis.to_string().contains("does not meet target"));
+
+        // valid share built on a previous block the server does not mine on anymore
+        let solution: work::Solution = block.into();
+        let other_previous_hash = test_utils::TEST_BLOCKS[1].previous_hash;
+        assert_eq!(
+            RejectDiagnosis::diagnose(&solution, Some(&other_previous_hash)),
+            RejectDiagnosis::StaleJob { previous_hash }
+        );
+
+        // nothing wrong can be found locally
+        assert_eq!(
+            RejectDiagnosis::diagnose(&solution, Some(&previous_hash)),
+            RejectDiagnosis::Unknown {
+                hash: *solution.hash()
+            }
+        );
+    }
+
+    #[test]
+    fn test_difficulty_ramp() {
+        const POOL_DIFFICULTY: usize = 1024;
+        const RAMP_PERIOD: time::Duration = time::Duration::from_secs(60);
+
+        let pool_target = ii_bitcoin::Target::from_pool_difficulty(POOL_DIFFICULTY);
+        let start = time::Instant::now();
+        let mut ramp_session =
+            DifficultyRampSession::new(DifficultyRamp::new(8, RAMP_PERIOD), pool_target, start);
+
+        // requested difficulty has to increase over the ramp window
+        let mut last_difficulty = 0;
+        for secs in (0..=RAMP_PERIOD.as_secs()).step_by(10) {
+            let now = start + time::Duration::from_secs(secs);
+            let difficulty = ramp_session
+                .next_target(now)
+                .expect("BUG: missing ramp target")
+                .get_difficulty();
+            assert!(difficulty > last_difficulty);
+            assert!(difficulty <= POOL_DIFFICULTY);
+            last_difficulty = difficulty;
+            // the same target is not requested twice
+            assert_eq!(ramp_session.next_target(now), None);
+        }
+        assert_eq!(last_difficulty, POOL_DIFFICULTY);
+        assert_eq!(ramp_session.next_update(start + RAMP_PERIOD), None);
+        assert_eq!(ramp_session.next_target(start + 2 * RAMP_PERIOD), None);
+
+        // the target changes only in steps of the ramp
+        let mut ramp_session =
+            DifficultyRampSession::new(DifficultyRamp::new(8, RAMP_PERIOD), pool_target, start);
+        assert_eq!(
+            ramp_session
+                .next_target(start)
+                .map(|target| target.get_difficulty()),
+            Some(8)
+        );
+        let now = start + time::Duration::from_secs(1);
+        assert_eq!(ramp_session.next_target(now), None);
+        assert_eq!(
+            ramp_session.next_update(now),
+            Some(time::Duration::from_secs(5))
+        );
+
+        // pool difficulty below the start of the ramp is kept
+        let mut ramp_session = DifficultyRampSession::new(
+            DifficultyRamp::new(POOL_DIFFICULTY * 2, RAMP_PERIOD),
+            pool_target,
+            start,
+        );
+        assert_eq!(ramp_session.next_target(start), None);
+    }
+
+    #[tokio::test]
+    async fn test_header_export() {
+        let mut exporter = HeaderExporter::new(Vec::new(), false);
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let solution: work::Solution = block.into();
+            assert!(exporter
+                .export(&solution)
+                .await
+                .expect("cannot export header"));
+        }
+
+        let output = String::from_utf8(exporter.into_inner()).expect("invalid export output");
+        let headers: Vec<_> = output.lines().collect();
+        assert_eq!(headers.len(), test_utils::TEST_BLOCKS.len());
+        for (header, block) in headers.iter().zip(test_utils::TEST_BLOCKS.iter()) {
+            let header_bytes = hex::decode(header).expect("invalid header hex");
+            assert_eq!(&header_bytes[..], &block.header_bytes[..]);
+            // the exported header must be verifiable by its hash
+            assert_eq!(ii_bitcoin::DHash::hash(&header_bytes), block.hash);
+        }
+    }
+}
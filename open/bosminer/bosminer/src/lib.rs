@@ -24,8 +24,9 @@
 // the default recursion limit if more complex statements are used
 #![recursion_limit = "256"]
 
-mod api;
+pub mod api;
 pub mod backend;
+pub mod benchmark;
 pub mod client;
 pub mod config;
 pub mod entry;
@@ -34,8 +35,10 @@ pub mod hal;
 pub mod hub;
 pub mod job;
 pub mod node;
+pub mod record_file;
 pub mod stats;
 pub mod sync;
+pub mod translation_proxy;
 pub mod version;
 pub mod work;
 
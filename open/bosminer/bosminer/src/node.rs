@@ -58,8 +58,11 @@ pub trait Client: Info + ClientStats {
     fn stop(&self);
     /// Return latest received job
     async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>>;
-    /// FIXME: Do not allow dynamic descriptor changes
-    fn change_connection_details(&self, _descriptor: &bosminer_config::ClientDescriptor) {}
+    /// Apply changed settings from `descriptor` to the client. Returns `false` when the client
+    /// cannot apply them and has to be replaced by a new one.
+    fn change_connection_details(&self, _descriptor: &bosminer_config::ClientDescriptor) -> bool {
+        false
+    }
 }
 
 pub trait ClientStats: Stats {
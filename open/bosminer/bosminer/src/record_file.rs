@@ -0,0 +1,103 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Append-only files with one text record per line
+
+use ii_async_compat::tokio;
+use tokio::fs;
+use tokio::io::{AsyncWrite, AsyncWriteExt as _};
+
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::path::Path;
+
+/// File where records are appended one per line. The records are written asynchronously so the
+/// executor is never blocked by slow storage and every record is flushed so that it survives
+/// a crash of the miner.
+pub struct RecordFile<W = fs::File> {
+    output: W,
+}
+
+impl RecordFile {
+    /// Open file at `path` in append mode, it is created when it does not exist
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> RecordFile<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+
+    pub async fn append<T: Display>(&mut self, record: T) -> io::Result<()> {
+        let line = format!("{}\n", record);
+        self.output.write_all(line.as_bytes()).await?;
+        self.output.flush().await
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W> Debug for RecordFile<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordFile").finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    #[tokio::test]
+    async fn test_record_file() {
+        let path = env::temp_dir().join(format!("bosminer-records-{}.log", process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        for record in &["first", "second"] {
+            // every open continues at the end of the file
+            let mut record_file = RecordFile::open(&path)
+                .await
+                .expect("BUG: cannot open record file");
+            record_file
+                .append(record)
+                .await
+                .expect("BUG: cannot append record");
+        }
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("BUG: cannot read record file"),
+            "first\nsecond\n"
+        );
+
+        std::fs::remove_file(&path).expect("BUG: cannot remove record file");
+    }
+}
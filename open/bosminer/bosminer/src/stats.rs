@@ -23,19 +23,28 @@
 use ii_logging::macros::*;
 
 use crate::node;
+use crate::record_file::RecordFile;
 use crate::stats;
 use crate::work;
 
+use bosminer_config::ClientRejectReason;
 use bosminer_macros::{ClientStats, MiningStats, WorkSolverStats};
 
+use ii_bitcoin::MeetsTarget;
 use ii_stats::WindowedTimeMean;
 
 use futures::lock::Mutex;
 use ii_async_compat::{futures, tokio};
+use tokio::io::AsyncWrite;
 use tokio::time::delay_for;
 
-use std::fmt::Debug;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex as StdMutex;
 use std::time;
 
 use once_cell::sync::Lazy;
@@ -50,6 +59,10 @@ pub static TIME_MEAN_INTERVAL_15M: Lazy<time::Duration> =
 pub static TIME_MEAN_INTERVAL_24H: Lazy<time::Duration> =
     Lazy::new(|| time::Duration::from_secs(24 * 60 * 60));
 
+/// Default floor for time windows of hashrate measurement. Estimates over shorter windows are too
+/// noisy to be useful.
+pub const MIN_TIME_MEAN_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
 static DEFAULT_TIME_MEAN_INTERVALS: Lazy<Vec<time::Duration>> = Lazy::new(|| {
     vec![
         *TIME_MEAN_INTERVAL_5S,
@@ -60,6 +73,47 @@ static DEFAULT_TIME_MEAN_INTERVALS: Lazy<Vec<time::Duration>> = Lazy::new(|| {
     ]
 });
 
+/// Upper bounds of buckets used for the histogram of solution ages
+static DEFAULT_SOLUTION_AGE_BOUNDS: Lazy<Vec<time::Duration>> = Lazy::new(|| {
+    vec![
+        time::Duration::from_millis(100),
+        time::Duration::from_millis(250),
+        time::Duration::from_millis(500),
+        time::Duration::from_secs(1),
+        time::Duration::from_secs(2),
+        time::Duration::from_secs(5),
+        time::Duration::from_secs(10),
+        time::Duration::from_secs(30),
+    ]
+});
+
+static DEFAULT_SUBMISSION_LATENCY_BOUNDS: Lazy<Vec<time::Duration>> = Lazy::new(|| {
+    vec![
+        time::Duration::from_millis(10),
+        time::Duration::from_millis(25),
+        time::Duration::from_millis(50),
+        time::Duration::from_millis(100),
+        time::Duration::from_millis(250),
+        time::Duration::from_millis(500),
+        time::Duration::from_secs(1),
+        time::Duration::from_millis(2500),
+        time::Duration::from_secs(5),
+        time::Duration::from_secs(10),
+        time::Duration::from_secs(30),
+    ]
+});
+
+/// Percentiles of submission latency exposed to operators
+pub const SUBMISSION_LATENCY_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// Submission latency is measured only within this rolling window so that a current latency
+/// spike is not hidden by the whole uptime
+pub const SUBMISSION_LATENCY_WINDOW: time::Duration = time::Duration::from_secs(15 * 60);
+
+/// Number of slots the window of latency histogram is split into. The oldest slot expires as
+/// a whole.
+const LATENCY_WINDOW_SLOTS: u32 = 15;
+
 /// Auxiliary structure for adding time to snapshots
 pub struct Snapshot<T> {
     pub snapshot_time: time::Instant,
@@ -90,12 +144,29 @@ pub struct MeterSnapshot {
     pub solutions: u64,
     /// All shares measured from the beginning of the mining
     pub shares: ii_bitcoin::Shares,
+    /// Number of solutions measured in the whole mining session (not affected by reset)
+    pub session_solutions: u64,
+    /// All shares measured in the whole mining session (not affected by reset)
+    pub session_shares: ii_bitcoin::Shares,
     /// Approximate arithmetic mean of hashes within given time intervals (in kH/time)
     time_means: Vec<WindowedTimeMean>,
+    /// Floor of time intervals, any shorter interval is clamped to this value
+    min_interval: time::Duration,
 }
 
 impl MeterSnapshot {
+    /// Start measuring again from zero while keeping the configured time intervals
+    /// The session counters are kept intact.
+    fn reset(&mut self) {
+        self.solutions = 0;
+        self.shares = Default::default();
+        for time_mean in &mut self.time_means {
+            *time_mean = WindowedTimeMean::new(time_mean.interval());
+        }
+    }
+
     fn get_time_mean(&self, interval: time::Duration) -> &WindowedTimeMean {
+        let interval = interval.max(self.min_interval);
         self.time_means
             .iter()
             .find(|time_mean| time_mean.interval() == interval)
@@ -154,15 +225,32 @@ pub struct Meter {
 }
 
 impl Meter {
-    pub fn new(intervals: &Vec<time::Duration>) -> Self {
+    pub fn new(intervals: &[time::Duration]) -> Self {
+        Self::with_min_interval(intervals, MIN_TIME_MEAN_INTERVAL)
+    }
+
+    /// Create meter with custom floor of time intervals. Any shorter interval is clamped to
+    /// `min_interval`.
+    pub fn with_min_interval(intervals: &[time::Duration], min_interval: time::Duration) -> Self {
         Self {
             inner: Mutex::new(MeterSnapshot {
                 solutions: 0,
                 shares: Default::default(),
+                session_solutions: 0,
+                session_shares: Default::default(),
                 time_means: intervals
                     .iter()
-                    .map(|&interval| WindowedTimeMean::new(interval))
+                    .map(|&interval| {
+                        if interval < min_interval {
+                            warn!(
+                                "Hash rate window {:?} is too small, using {:?} instead",
+                                interval, min_interval
+                            );
+                        }
+                        WindowedTimeMean::new(interval.max(min_interval))
+                    })
                     .collect(),
+                min_interval,
             }),
         }
     }
@@ -180,6 +268,8 @@ impl Meter {
         // TODO: what to do when number overflows
         meter.solutions += 1;
         meter.shares.account_solution(target);
+        meter.session_solutions += 1;
+        meter.session_shares.account_solution(target);
         for time_mean in &mut meter.time_means {
             time_mean.insert(kilo_hashes, time);
         }
@@ -234,15 +324,19 @@ impl Default for LastShare {
     }
 }
 
+/// The best share difficulty (computed from the hash of a solution)
 #[derive(Debug)]
 pub struct BestShare {
     inner: AtomicUsize,
+    /// The best share in the whole mining session which is not affected by reset
+    session: AtomicUsize,
 }
 
 impl BestShare {
     const INVALID_DIFFICULTY: usize = 0;
-    pub fn take_snapshot(&self) -> Option<Snapshot<usize>> {
-        let difficulty = self.inner.load(Ordering::Relaxed);
+
+    fn snapshot(value: &AtomicUsize) -> Option<Snapshot<usize>> {
+        let difficulty = value.load(Ordering::Relaxed);
         if difficulty == Self::INVALID_DIFFICULTY {
             None
         } else {
@@ -250,14 +344,11 @@ impl BestShare {
         }
     }
 
-    pub(crate) fn account_solution(&self, target: &ii_bitcoin::Target) {
-        let new_diff = target.get_difficulty();
-        let mut old_diff = self.inner.load(Ordering::Relaxed);
+    fn update(value: &AtomicUsize, new_diff: usize) {
+        let mut old_diff = value.load(Ordering::Relaxed);
 
         while old_diff < new_diff {
-            let prev_diff = self
-                .inner
-                .compare_and_swap(old_diff, new_diff, Ordering::Relaxed);
+            let prev_diff = value.compare_and_swap(old_diff, new_diff, Ordering::Relaxed);
             if old_diff == prev_diff {
                 break;
             } else {
@@ -265,16 +356,347 @@ impl BestShare {
             }
         }
     }
+
+    pub fn take_snapshot(&self) -> Option<Snapshot<usize>> {
+        Self::snapshot(&self.inner)
+    }
+
+    /// The best share since the start of mining regardless of counter reset
+    pub fn take_session_snapshot(&self) -> Option<Snapshot<usize>> {
+        Self::snapshot(&self.session)
+    }
+
+    pub(crate) fn account_solution(&self, target: &ii_bitcoin::Target) {
+        let new_diff = target.get_difficulty();
+        Self::update(&self.inner, new_diff);
+        Self::update(&self.session, new_diff);
+    }
+
+    pub(crate) fn reset(&self) {
+        self.inner
+            .store(Self::INVALID_DIFFICULTY, Ordering::Relaxed);
+    }
 }
 
 impl Default for BestShare {
     fn default() -> Self {
         Self {
             inner: AtomicUsize::new(Self::INVALID_DIFFICULTY),
+            session: AtomicUsize::new(Self::INVALID_DIFFICULTY),
+        }
+    }
+}
+
+/// Histogram of solution ages (time elapsed since the job has been received) at the moment of
+/// submission. It allows to correlate staleness of solutions with the reject rate.
+#[derive(Debug)]
+pub struct AgeHistogram {
+    /// Inclusive upper bounds of all buckets except the last one, which is unbounded
+    bounds: Vec<time::Duration>,
+    buckets: Vec<AtomicU64>,
+    /// Sum of all accounted ages in microseconds
+    sum: AtomicU64,
+}
+
+impl AgeHistogram {
+    pub fn new(bounds: &[time::Duration]) -> Self {
+        assert!(
+            bounds.windows(2).all(|pair| pair[0] < pair[1]),
+            "BUG: histogram bounds are not sorted"
+        );
+        Self {
+            bounds: bounds.to_vec(),
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn bounds(&self) -> &[time::Duration] {
+        &self.bounds
+    }
+
+    pub(crate) fn account_solution(&self, age: time::Duration) {
+        let i = self
+            .bounds
+            .iter()
+            .position(|&bound| age <= bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.sum
+            .fetch_add(age.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns sum of ages of all accounted solutions
+    pub fn sum(&self) -> time::Duration {
+        time::Duration::from_micros(self.sum.load(Ordering::Relaxed))
+    }
+
+    /// Returns number of solutions in each bucket
+    pub fn take_snapshot(&self) -> Snapshot<Vec<u64>> {
+        Snapshot::new(
+            self.buckets
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+        )
+    }
+}
+
+impl Default for AgeHistogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_SOLUTION_AGE_BOUNDS.as_ref())
+    }
+}
+
+/// Histogram of latencies accounted only within the last `window` so that its quantiles
+/// follow the current latency. The window is split into slots of the same length and the
+/// buckets of the oldest slot are dropped at once when it leaves the window.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    /// Inclusive upper bounds of all buckets except the last one, which is unbounded
+    bounds: Vec<time::Duration>,
+    window: time::Duration,
+    slot_interval: time::Duration,
+    /// Start time of each slot in the window with its buckets (the newest slot is the last one)
+    slots: StdMutex<VecDeque<(time::Instant, Vec<u64>)>>,
+}
+
+impl LatencyHistogram {
+    pub fn new(bounds: &[time::Duration], window: time::Duration) -> Self {
+        assert!(
+            bounds.windows(2).all(|pair| pair[0] < pair[1]),
+            "BUG: histogram bounds are not sorted"
+        );
+        Self {
+            bounds: bounds.to_vec(),
+            window,
+            slot_interval: window / LATENCY_WINDOW_SLOTS,
+            slots: StdMutex::new(VecDeque::new()),
+        }
+    }
+
+    #[inline]
+    pub fn window(&self) -> time::Duration {
+        self.window
+    }
+
+    /// Drop slots that started before the window
+    fn expire(&self, slots: &mut VecDeque<(time::Instant, Vec<u64>)>, now: time::Instant) {
+        while let Some((start, _)) = slots.front() {
+            if now.saturating_duration_since(*start) < self.window {
+                break;
+            }
+            slots.pop_front();
+        }
+    }
+
+    pub(crate) fn account(&self, latency: time::Duration, now: time::Instant) {
+        let i = self
+            .bounds
+            .iter()
+            .position(|&bound| latency <= bound)
+            .unwrap_or(self.bounds.len());
+
+        let mut slots = self.slots.lock().expect("BUG: cannot lock histogram");
+        self.expire(&mut slots, now);
+        let is_current = match slots.back() {
+            Some((start, _)) => now.saturating_duration_since(*start) < self.slot_interval,
+            None => false,
+        };
+        if !is_current {
+            slots.push_back((now, vec![0; self.bounds.len() + 1]));
+        }
+        slots.back_mut().expect("BUG: missing slot").1[i] += 1;
+    }
+
+    /// Returns number of latencies in each bucket within the window
+    pub fn take_snapshot(&self, now: time::Instant) -> Snapshot<Vec<u64>> {
+        let mut slots = self.slots.lock().expect("BUG: cannot lock histogram");
+        self.expire(&mut slots, now);
+        let mut buckets = vec![0; self.bounds.len() + 1];
+        for (_, slot) in slots.iter() {
+            for (bucket, count) in buckets.iter_mut().zip(slot) {
+                *bucket += count;
+            }
+        }
+        Snapshot::new(buckets)
+    }
+
+    /// Returns upper bound in seconds of the bucket containing `quantile` (from interval
+    /// `[0, 1]`) of latencies within the window or `None` when there's none. Quantiles falling
+    /// into the last unbounded bucket are reported as infinity.
+    pub fn quantile(&self, quantile: f64, now: time::Instant) -> Option<f64> {
+        let buckets = self.take_snapshot(now);
+        let total: u64 = buckets.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((quantile * total as f64).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (i, count) in buckets.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                return Some(
+                    self.bounds
+                        .get(i)
+                        .map_or(f64::INFINITY, time::Duration::as_secs_f64),
+                );
+            }
+        }
+        Some(f64::INFINITY)
+    }
+}
+
+/// Latency of share submission measured when the server acknowledges the share (regardless
+/// whether it has been accepted or rejected). The total latency is split so that the time spent
+/// by waiting for the server can be distinguished from local processing delays (share filtering,
+/// submit jitter, queueing) which are the difference between both histograms. Only shares
+/// acknowledged within `SUBMISSION_LATENCY_WINDOW` are taken into account.
+#[derive(Debug)]
+pub struct SubmissionLatency {
+    /// Time elapsed since the arrival of the solution to the solver
+    pub total: LatencyHistogram,
+    /// Time elapsed since the share has been sent to the server
+    pub server: LatencyHistogram,
+}
+
+impl SubmissionLatency {
+    pub(crate) fn account_solution(
+        &self,
+        solution: &work::Solution,
+        submitted: time::Instant,
+        acknowledged: time::Instant,
+    ) {
+        self.total.account(
+            acknowledged.saturating_duration_since(solution.timestamp()),
+            acknowledged,
+        );
+        self.server.account(
+            acknowledged.saturating_duration_since(submitted),
+            acknowledged,
+        );
+    }
+}
+
+impl Default for SubmissionLatency {
+    fn default() -> Self {
+        Self {
+            total: LatencyHistogram::new(
+                DEFAULT_SUBMISSION_LATENCY_BOUNDS.as_ref(),
+                SUBMISSION_LATENCY_WINDOW,
+            ),
+            server: LatencyHistogram::new(
+                DEFAULT_SUBMISSION_LATENCY_BOUNDS.as_ref(),
+                SUBMISSION_LATENCY_WINDOW,
+            ),
+        }
+    }
+}
+
+/// Shares rejected by remote server split by the reason reported by the server
+#[derive(Debug)]
+pub struct RejectReasons {
+    meters: Vec<(ClientRejectReason, Meter)>,
+}
+
+impl RejectReasons {
+    /// Get meter of shares rejected for given `reason`
+    pub fn get(&self, reason: ClientRejectReason) -> &Meter {
+        self.meters
+            .iter()
+            .find(|(meter_reason, _)| *meter_reason == reason)
+            .map(|(_, meter)| meter)
+            .expect("BUG: missing meter for reject reason")
+    }
+
+    pub(crate) async fn account_solution(
+        &self,
+        reason: ClientRejectReason,
+        target: &ii_bitcoin::Target,
+        time: time::Instant,
+    ) {
+        self.get(reason).account_solution(target, time).await;
+    }
+
+    pub async fn take_snapshot(&self) -> Vec<(ClientRejectReason, Snapshot<MeterSnapshot>)> {
+        let mut snapshots = Vec::with_capacity(self.meters.len());
+        for (reason, meter) in &self.meters {
+            snapshots.push((*reason, meter.take_snapshot().await));
+        }
+        snapshots
+    }
+}
+
+impl Default for RejectReasons {
+    fn default() -> Self {
+        Self {
+            meters: ClientRejectReason::ALL
+                .iter()
+                .map(|reason| (*reason, Default::default()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateMeterSnapshot {
+    /// Total number of accounted events
+    pub total: u64,
+    /// Approximate arithmetic mean of events within given time intervals (in events/s)
+    time_means: Vec<WindowedTimeMean>,
+}
+
+impl RateMeterSnapshot {
+    /// Number of events per second within given time interval
+    pub fn to_rate(&self, interval: time::Duration, now: time::Instant) -> f64 {
+        self.time_means
+            .iter()
+            .find(|time_mean| time_mean.interval() == interval)
+            .expect("cannot find given time interval")
+            .measure(now)
+    }
+}
+
+/// Measures number of events per second (e.g. midstates computed for generated work)
+#[derive(Debug)]
+pub struct RateMeter {
+    inner: Mutex<RateMeterSnapshot>,
+}
+
+impl RateMeter {
+    pub fn new(intervals: &[time::Duration]) -> Self {
+        Self {
+            inner: Mutex::new(RateMeterSnapshot {
+                total: 0,
+                time_means: intervals
+                    .iter()
+                    .map(|&interval| WindowedTimeMean::new(interval))
+                    .collect(),
+            }),
+        }
+    }
+
+    pub async fn take_snapshot(&self) -> Snapshot<RateMeterSnapshot> {
+        Snapshot::new(self.inner.lock().await.clone())
+    }
+
+    pub(crate) async fn account(&self, amount: u64, time: time::Instant) {
+        let mut meter = self.inner.lock().await;
+
+        meter.total += amount;
+        for time_mean in &mut meter.time_means {
+            time_mean.insert(amount as f64, time);
         }
     }
 }
 
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIME_MEAN_INTERVALS.as_ref())
+    }
+}
+
 pub trait AtomicCounter: Debug {
     /// The underlying type
     type Type: Default;
@@ -405,6 +827,114 @@ impl UnixTime for time::SystemTime {
     }
 }
 
+/// Archival record of a solution which meets the network target (found block)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundBlock {
+    /// Unix time when the block has been found
+    pub timestamp: u32,
+    /// Height of the block when it is provided by the job
+    pub height: Option<u32>,
+    /// Full 80-byte block header
+    pub header: Vec<u8>,
+    /// Serialized coinbase transaction when it is provided by the job
+    pub coinbase: Option<Vec<u8>>,
+    /// Client (pool) which has provided the job
+    pub pool: String,
+    /// Last node in the solution path (usually the hash chain which has solved the work)
+    pub worker: String,
+}
+
+impl FoundBlock {
+    pub fn new(solution: &work::Solution, time: time::SystemTime) -> Self {
+        let path = solution.path();
+        let node_name = |node: Option<&node::DynInfo>| {
+            node.map(|node| node.to_string())
+                .unwrap_or_else(|| "?".to_string())
+        };
+
+        Self {
+            timestamp: time.get_unix_time().unwrap_or_default(),
+            height: solution.block_height(),
+            header: solution.get_block_header().into_bytes().to_vec(),
+            coinbase: solution.coinbase(),
+            pool: node_name(path.first()),
+            worker: node_name(path.last()),
+        }
+    }
+}
+
+impl fmt::Display for FoundBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timestamp={} height=", self.timestamp)?;
+        match self.height {
+            Some(height) => write!(f, "{}", height)?,
+            None => write!(f, "-")?,
+        }
+        write!(f, " header={} coinbase=", hex::encode(&self.header))?;
+        match &self.coinbase {
+            Some(coinbase) => write!(f, "{}", hex::encode(coinbase))?,
+            None => write!(f, "-")?,
+        }
+        write!(f, " pool={:?} worker={:?}", self.pool, self.worker)
+    }
+}
+
+/// Configuration of persistent archive of found blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockArchiveConfig {
+    /// File where the records are appended
+    pub path: PathBuf,
+}
+
+/// Appends `FoundBlock` records (one per line) to a durable log so that every block find can be
+/// later proved
+pub struct BlockArchive<W = tokio::fs::File> {
+    output: RecordFile<W>,
+}
+
+impl BlockArchive {
+    /// Opens the archive file specified by `config` in append mode
+    pub async fn create(config: &BlockArchiveConfig) -> io::Result<Self> {
+        Ok(Self {
+            output: RecordFile::open(&config.path).await?,
+        })
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BlockArchive<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output: RecordFile::new(output),
+        }
+    }
+
+    /// Archive the solution when it meets network target. Returns the record when the solution
+    /// is a found block.
+    pub async fn archive(
+        &mut self,
+        solution: &work::Solution,
+        time: time::SystemTime,
+    ) -> io::Result<Option<FoundBlock>> {
+        if !solution.hash().meets(&solution.network_target()) {
+            return Ok(None);
+        }
+        let found_block = FoundBlock::new(solution, time);
+        info!("Found block: {}", found_block);
+        self.output.append(&found_block).await?;
+        Ok(Some(found_block))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.into_inner()
+    }
+}
+
+impl<W> Debug for BlockArchive<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockArchive").finish()
+    }
+}
+
 pub trait Mining: Send + Sync {
     /// The time all statistics are measured from
     fn start_time(&self) -> &time::Instant;
@@ -434,6 +964,14 @@ pub trait Client: Mining {
     fn rejected(&self) -> &Meter;
     /// Valid shares rejected by remote server or discarded due to some error
     fn stale(&self) -> &Meter;
+    /// Age of solutions at the moment of submission
+    fn solution_age(&self) -> &AgeHistogram;
+    /// Latency of share submission until the server acknowledgment
+    fn submission_latency(&self) -> &SubmissionLatency;
+    /// Reasons of shares rejected by remote server
+    fn reject_reasons(&self) -> &RejectReasons;
+    /// Number of reconnection attempts after connection to remote server has failed
+    fn reconnections(&self) -> &CounterUsize;
 }
 
 pub trait WorkSolver: Mining {
@@ -441,6 +979,8 @@ pub trait WorkSolver: Mining {
     fn last_work_time(&self) -> &Timestamp;
     /// Number of work generated from jobs by rolling or with extra nonce
     fn generated_work(&self) -> &CounterU64;
+    /// Midstates computed for the generated work. It reflects CPU cost of work generation.
+    fn midstates(&self) -> &RateMeter;
 }
 
 #[derive(Debug, MiningStats)]
@@ -501,6 +1041,14 @@ pub struct BasicClient {
     pub rejected: stats::Meter,
     #[member_stale]
     pub stale: stats::Meter,
+    #[member_solution_age]
+    pub solution_age: AgeHistogram,
+    #[member_submission_latency]
+    pub submission_latency: SubmissionLatency,
+    #[member_reject_reasons]
+    pub reject_reasons: RejectReasons,
+    #[member_reconnections]
+    pub reconnections: CounterUsize,
     #[member_valid_network_diff]
     pub valid_network_diff: Meter,
     #[member_valid_job_diff]
@@ -523,6 +1071,10 @@ impl BasicClient {
             accepted: Meter::new(&intervals),
             rejected: Meter::new(&intervals),
             stale: Default::default(),
+            solution_age: Default::default(),
+            submission_latency: Default::default(),
+            reject_reasons: Default::default(),
+            reconnections: Default::default(),
             valid_network_diff: Meter::new(&intervals),
             valid_job_diff: Meter::new(&intervals),
             valid_backend_diff: Meter::new(&intervals),
@@ -545,6 +1097,8 @@ pub struct BasicWorkSolver {
     pub last_work_time: Timestamp,
     #[member_generated_work]
     pub generated_work: CounterU64,
+    #[member_midstates]
+    pub midstates: RateMeter,
     #[member_last_share]
     pub last_share: LastShare,
     #[member_best_share]
@@ -567,6 +1121,7 @@ impl BasicWorkSolver {
             best_share: Default::default(),
             last_work_time: Default::default(),
             generated_work: Default::default(),
+            midstates: RateMeter::new(&intervals),
             valid_network_diff: Meter::new(&intervals),
             valid_job_diff: Meter::new(&intervals),
             valid_backend_diff: Meter::new(&intervals),
@@ -581,6 +1136,121 @@ impl Default for BasicWorkSolver {
     }
 }
 
+/// Group of counters which can be reset at runtime without restarting the miner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterGroup {
+    /// Valid shares on all difficulty levels together with accepted and rejected shares
+    Shares,
+    /// Only the best share
+    BestShare,
+    /// Invalid work reported by backends (HW errors)
+    HwErrors,
+    /// Valid shares rejected or discarded (lost shares)
+    Stale,
+}
+
+impl CounterGroup {
+    pub const ALL: [Self; 4] = [Self::Shares, Self::BestShare, Self::HwErrors, Self::Stale];
+}
+
+impl fmt::Display for CounterGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shares => write!(f, "shares"),
+            Self::BestShare => write!(f, "bestshare"),
+            Self::HwErrors => write!(f, "hwerrors"),
+            Self::Stale => write!(f, "stale"),
+        }
+    }
+}
+
+impl FromStr for CounterGroup {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|group| group.to_string().eq_ignore_ascii_case(value))
+            .copied()
+            .ok_or_else(|| format!("unknown counter group '{}'", value))
+    }
+}
+
+/// Counters selected for reset. All of them are locked before the first one is zeroed so any
+/// solution accounted concurrently to a single counter is counted either before or after the reset
+/// in all the selected counters.
+#[derive(Default)]
+pub struct CounterReset<'a> {
+    meters: Vec<&'a Meter>,
+    last_shares: Vec<&'a LastShare>,
+    best_shares: Vec<&'a BestShare>,
+}
+
+impl<'a> CounterReset<'a> {
+    /// Select all general mining statistics that belong to the `group`
+    pub fn add_mining<T: Mining + ?Sized>(&mut self, mining_stats: &'a T, group: CounterGroup) {
+        match group {
+            CounterGroup::Shares => {
+                self.meters.push(mining_stats.valid_network_diff());
+                self.meters.push(mining_stats.valid_job_diff());
+                self.meters.push(mining_stats.valid_backend_diff());
+                self.last_shares.push(mining_stats.last_share());
+                self.best_shares.push(mining_stats.best_share());
+            }
+            CounterGroup::BestShare => self.best_shares.push(mining_stats.best_share()),
+            CounterGroup::HwErrors => self.meters.push(mining_stats.error_backend_diff()),
+            CounterGroup::Stale => {}
+        }
+    }
+
+    /// Select all client statistics (including the general mining ones) that belong to the
+    /// `group`
+    pub fn add_client<T: Client + ?Sized>(&mut self, client_stats: &'a T, group: CounterGroup) {
+        self.add_mining(client_stats, group);
+        match group {
+            CounterGroup::Shares => {
+                self.meters.push(client_stats.accepted());
+                self.meters.push(client_stats.rejected());
+            }
+            CounterGroup::Stale => self.meters.push(client_stats.stale()),
+            CounterGroup::BestShare | CounterGroup::HwErrors => {}
+        }
+    }
+
+    /// Reset all selected counters at once
+    pub async fn reset(mut self) {
+        // the locks are always taken in the same order (given by their address) so concurrent
+        // resets cannot deadlock and no lock is taken twice
+        self.meters
+            .sort_by_key(|meter| &meter.inner as *const _ as usize);
+        self.meters
+            .dedup_by_key(|meter| &meter.inner as *const _ as usize);
+        self.last_shares
+            .sort_by_key(|last_share| &last_share.inner as *const _ as usize);
+        self.last_shares
+            .dedup_by_key(|last_share| &last_share.inner as *const _ as usize);
+
+        let mut meters = Vec::with_capacity(self.meters.len());
+        for meter in &self.meters {
+            meters.push(meter.inner.lock().await);
+        }
+        let mut last_shares = Vec::with_capacity(self.last_shares.len());
+        for last_share in &self.last_shares {
+            last_shares.push(last_share.inner.lock().await);
+        }
+
+        for meter in &mut meters {
+            meter.reset();
+        }
+        for last_share in &mut last_shares {
+            last_share.take();
+        }
+        for best_share in &self.best_shares {
+            best_share.reset();
+        }
+    }
+}
+
 /// Generate share accounting function for a particular difficulty level
 /// The function traverses all nodes in the path and accounts the solution in the field specific
 /// to the difficulty level given by `solution_target`
@@ -606,6 +1276,47 @@ account_impl!(account_valid_job_diff, valid_job_diff);
 account_impl!(account_valid_backend_diff, valid_backend_diff);
 account_impl!(account_error_backend_diff, error_backend_diff);
 
+/// Ratio of hardware `errors` to all nonces returned by the backend (`valid` nonces and `errors`)
+pub fn hardware_error_ratio(valid: u64, errors: u64) -> f64 {
+    let nonces = valid + errors;
+    if nonces != 0 {
+        errors as f64 / nonces as f64
+    } else {
+        0.0
+    }
+}
+
+/// Ratio of rejected shares to all shares responded by remote server within time `interval`.
+/// The shares are weighted by their difficulty.
+#[inline]
+pub fn reject_ratio(
+    accepted: &MeterSnapshot,
+    rejected: &MeterSnapshot,
+    interval: time::Duration,
+    now: time::Instant,
+) -> f64 {
+    reject_reason_ratio(accepted, rejected, rejected, interval, now)
+}
+
+/// Ratio of shares rejected for some reason (`reason_rejected` is subset of `rejected`) to all
+/// shares responded by remote server within time `interval`
+pub fn reject_reason_ratio(
+    accepted: &MeterSnapshot,
+    rejected: &MeterSnapshot,
+    reason_rejected: &MeterSnapshot,
+    interval: time::Duration,
+    now: time::Instant,
+) -> f64 {
+    let accepted = accepted.to_kilo_hashes(interval, now).into_f64();
+    let rejected = rejected.to_kilo_hashes(interval, now).into_f64();
+    let reason_rejected = reason_rejected.to_kilo_hashes(interval, now).into_f64();
+    if accepted + rejected > 0.0 {
+        reason_rejected / (accepted + rejected)
+    } else {
+        0.0
+    }
+}
+
 /// Describes which difficulty target a particular solution has met.
 /// It also determines in which statistics a particular solution should be accounted.
 #[derive(Debug, PartialEq)]
@@ -639,13 +1350,15 @@ pub async fn account_valid_solution(
         }
         // use only job difficulty for accounting the last share even if a hash of the solution
         // meets higher difficulties
+        // the best share is the actual difficulty of the solution hash
+        let share_target = ii_bitcoin::Target::from(*solution.hash());
         for node in path {
             let mining_stats = node.mining_stats();
             mining_stats
                 .last_share()
                 .account_solution(target, time::SystemTime::now())
                 .await;
-            mining_stats.best_share().account_solution(target);
+            mining_stats.best_share().account_solution(&share_target);
         }
     }
 }
@@ -669,3 +1382,344 @@ pub async fn mining_task(node: node::DynInfo, interval: time::Duration) {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_solution_age_histogram() {
+        let histogram = AgeHistogram::new(&vec![
+            time::Duration::from_millis(100),
+            time::Duration::from_secs(1),
+            time::Duration::from_secs(10),
+        ]);
+        assert_eq!(*histogram.take_snapshot(), vec![0, 0, 0, 0]);
+
+        // synthetic solution ages in milliseconds
+        for &age in &[0, 50, 100, 101, 999, 1000, 5000, 10001, 60000] {
+            histogram.account_solution(time::Duration::from_millis(age));
+        }
+        assert_eq!(*histogram.take_snapshot(), vec![3, 3, 1, 2]);
+        assert_eq!(histogram.sum(), time::Duration::from_millis(77251));
+    }
+
+    #[test]
+    fn test_latency_histogram_quantile() {
+        let histogram = LatencyHistogram::new(
+            &vec![
+                time::Duration::from_millis(10),
+                time::Duration::from_millis(100),
+                time::Duration::from_secs(1),
+            ],
+            time::Duration::from_secs(60),
+        );
+        let now = time::Instant::now();
+        assert_eq!(histogram.quantile(0.5, now), None);
+
+        // 90 fast, 8 slow and 2 latencies above the largest bound
+        for _ in 0..90 {
+            histogram.account(time::Duration::from_millis(5), now);
+        }
+        for _ in 0..8 {
+            histogram.account(time::Duration::from_millis(500), now);
+        }
+        for _ in 0..2 {
+            histogram.account(time::Duration::from_secs(5), now);
+        }
+        assert_eq!(*histogram.take_snapshot(now), vec![90, 0, 8, 2]);
+        assert_eq!(histogram.quantile(0.0, now), Some(0.01));
+        assert_eq!(histogram.quantile(0.5, now), Some(0.01));
+        assert_eq!(histogram.quantile(0.9, now), Some(0.01));
+        assert_eq!(histogram.quantile(0.95, now), Some(1.0));
+        // latencies above the largest bound are not under-reported
+        assert_eq!(histogram.quantile(0.99, now), Some(f64::INFINITY));
+        assert_eq!(histogram.quantile(1.0, now), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_latency_histogram_window() {
+        let histogram = LatencyHistogram::new(
+            &vec![
+                time::Duration::from_millis(100),
+                time::Duration::from_secs(1),
+            ],
+            time::Duration::from_secs(60),
+        );
+        assert_eq!(histogram.window(), time::Duration::from_secs(60));
+        let start = time::Instant::now();
+        let at = |secs| start + time::Duration::from_secs(secs);
+
+        // long period of low latency followed by recent spike
+        for secs in 0..30 {
+            histogram.account(time::Duration::from_millis(50), at(secs));
+        }
+        for secs in 50..55 {
+            histogram.account(time::Duration::from_millis(500), at(secs));
+        }
+        assert_eq!(*histogram.take_snapshot(at(55)), vec![30, 5, 0]);
+        assert_eq!(histogram.quantile(0.95, at(55)), Some(1.0));
+
+        // slots with low latency leave the window one by one
+        assert_eq!(*histogram.take_snapshot(at(62)), vec![26, 5, 0]);
+        assert_eq!(*histogram.take_snapshot(at(90)), vec![0, 5, 0]);
+        assert_eq!(histogram.quantile(0.5, at(90)), Some(1.0));
+        assert_eq!(*histogram.take_snapshot(at(115)), vec![0, 0, 0]);
+        assert_eq!(histogram.quantile(0.5, at(115)), None);
+    }
+
+    #[test]
+    fn test_submission_latency() {
+        let latency = SubmissionLatency::default();
+        let solution: work::Solution = (&test_utils::TEST_BLOCKS[0]).into();
+        let submitted = solution.timestamp() + time::Duration::from_millis(200);
+        let acknowledged = submitted + time::Duration::from_millis(20);
+        latency.account_solution(&solution, submitted, acknowledged);
+
+        assert_eq!(latency.total.quantile(0.5, acknowledged), Some(0.25));
+        assert_eq!(latency.server.quantile(0.5, acknowledged), Some(0.025));
+
+        // latencies leave the window
+        let later = acknowledged + SUBMISSION_LATENCY_WINDOW;
+        assert_eq!(latency.total.quantile(0.5, later), None);
+        assert_eq!(latency.server.quantile(0.5, later), None);
+    }
+
+    #[tokio::test]
+    async fn test_block_archive() {
+        let mut block_archive = BlockArchive::new(Vec::new());
+        let found_time = time::UNIX_EPOCH + time::Duration::from_secs(1_500_000_000);
+
+        let mut expected_records = Vec::new();
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let solution: work::Solution = block.into();
+            let found_block = block_archive
+                .archive(&solution, found_time)
+                .await
+                .expect("BUG: cannot write to archive")
+                .expect("BUG: block has not been archived");
+
+            assert_eq!(found_block.timestamp, 1_500_000_000);
+            assert_eq!(
+                &found_block.header[..],
+                &solution.get_block_header().into_bytes()[..]
+            );
+            assert_eq!(found_block.pool, test_utils::TEST_CLIENT.to_string());
+            assert!(!found_block.worker.is_empty());
+            expected_records.push(found_block.to_string());
+        }
+
+        // a share which does not meet network target is not a block
+        let mut not_block = test_utils::TEST_BLOCKS[0];
+        not_block.nonce ^= 1;
+        let share: work::Solution = not_block.into();
+        let mut share_archive = BlockArchive::new(Vec::new());
+        share_archive
+            .archive(&share, found_time)
+            .await
+            .expect("BUG: cannot write to archive");
+        assert!(share_archive.into_inner().is_empty());
+
+        let output = String::from_utf8(block_archive.into_inner()).expect("BUG: invalid UTF-8");
+        let records: Vec<_> = output.lines().collect();
+        assert_eq!(records, expected_records);
+        for record in records {
+            for field in &[
+                "timestamp=",
+                "height=",
+                "header=",
+                "coinbase=",
+                "pool=",
+                "worker=",
+            ] {
+                assert!(
+                    record.contains(field),
+                    "missing '{}' in '{}'",
+                    field,
+                    record
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_hardware_error_ratio() {
+        assert_eq!(hardware_error_ratio(0, 0), 0.0);
+        assert_eq!(hardware_error_ratio(3, 0), 0.0);
+        assert_eq!(hardware_error_ratio(3, 1), 0.25);
+        assert_eq!(hardware_error_ratio(0, 2), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_reject_ratio() {
+        let accepted = Meter::default();
+        let rejected = Meter::default();
+        let target = Default::default();
+        let interval = *TIME_MEAN_INTERVAL_5M;
+        assert_eq!(
+            reject_ratio(
+                &*accepted.take_snapshot().await,
+                &*rejected.take_snapshot().await,
+                interval,
+                time::Instant::now()
+            ),
+            0.0
+        );
+
+        let now = time::Instant::now();
+        for _ in 0..19 {
+            accepted.account_solution(&target, now).await;
+        }
+        rejected.account_solution(&target, now).await;
+        let ratio = reject_ratio(
+            &*accepted.take_snapshot().await,
+            &*rejected.take_snapshot().await,
+            interval,
+            time::Instant::now(),
+        );
+        assert!((ratio - 0.05).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_reject_reasons() {
+        let reject_reasons = RejectReasons::default();
+        let target = Default::default();
+        let now = time::Instant::now();
+        reject_reasons
+            .account_solution(ClientRejectReason::Stale, &target, now)
+            .await;
+        reject_reasons
+            .account_solution(ClientRejectReason::LowDifficulty, &target, now)
+            .await;
+        reject_reasons
+            .account_solution(ClientRejectReason::Stale, &target, now)
+            .await;
+
+        let snapshot = reject_reasons.take_snapshot().await;
+        assert_eq!(snapshot.len(), ClientRejectReason::ALL.len());
+        for (reason, meter) in snapshot {
+            let expected_solutions = match reason {
+                ClientRejectReason::Stale => 2,
+                ClientRejectReason::LowDifficulty => 1,
+                _ => 0,
+            };
+            assert_eq!(meter.session_solutions, expected_solutions);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_share_counters() {
+        let client_stats = BasicClient::default();
+        let target = ii_bitcoin::Target::from_pool_difficulty(1024);
+        async fn account_shares(
+            client_stats: &BasicClient,
+            target: &ii_bitcoin::Target,
+            count: usize,
+        ) {
+            for _ in 0..count {
+                let now = time::Instant::now();
+                client_stats
+                    .valid_job_diff()
+                    .account_solution(target, now)
+                    .await;
+                client_stats.accepted().account_solution(target, now).await;
+                client_stats.best_share().account_solution(target);
+            }
+        }
+
+        account_shares(&client_stats, &target, 5).await;
+        client_stats
+            .stale()
+            .account_solution(&target, time::Instant::now())
+            .await;
+        assert_eq!(client_stats.accepted().take_snapshot().await.solutions, 5);
+
+        let mut reset = CounterReset::default();
+        reset.add_client(&client_stats, CounterGroup::Shares);
+        // counters selected more than once are locked only once
+        reset.add_mining(&client_stats, CounterGroup::Shares);
+        reset.reset().await;
+        assert_eq!(
+            client_stats
+                .valid_job_diff()
+                .take_snapshot()
+                .await
+                .solutions,
+            0
+        );
+        assert_eq!(client_stats.accepted().take_snapshot().await.solutions, 0);
+        assert!(client_stats.best_share().take_snapshot().is_none());
+        // session counters are not affected by reset
+        assert_eq!(
+            client_stats
+                .accepted()
+                .take_snapshot()
+                .await
+                .session_solutions,
+            5
+        );
+        assert_eq!(
+            client_stats
+                .best_share()
+                .take_session_snapshot()
+                .map(|inner| *inner),
+            Some(1024)
+        );
+        // other counter groups are not affected
+        assert_eq!(client_stats.stale().take_snapshot().await.solutions, 1);
+
+        // mining continues and the counters increment from zero
+        account_shares(&client_stats, &target, 3).await;
+        let accepted = client_stats.accepted().take_snapshot().await;
+        assert_eq!(accepted.solutions, 3);
+        assert_eq!(accepted.session_solutions, 8);
+        assert_eq!(accepted.shares, {
+            let mut shares = ii_bitcoin::Shares::default();
+            for _ in 0..3 {
+                shares.account_solution(&target);
+            }
+            shares
+        });
+        assert_eq!(
+            client_stats
+                .valid_job_diff()
+                .take_snapshot()
+                .await
+                .solutions,
+            3
+        );
+
+        assert_eq!("HwErrors".parse(), Ok(CounterGroup::HwErrors));
+        assert!("unknown".parse::<CounterGroup>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_meter_min_interval() {
+        let too_small_interval = time::Duration::from_millis(100);
+        let meter = Meter::new(&vec![too_small_interval]);
+        let target = ii_bitcoin::Target::from_pool_difficulty(1024);
+        for _ in 0..10 {
+            meter.account_solution(&target, time::Instant::now()).await;
+        }
+
+        let snapshot = meter.take_snapshot().await;
+        assert_eq!(snapshot.time_means[0].interval(), MIN_TIME_MEAN_INTERVAL);
+        let expected = ii_bitcoin::Shares::new(&target)
+            .into_kilo_hashes()
+            .into_f64()
+            * 10.0
+            / MIN_TIME_MEAN_INTERVAL.as_secs_f64();
+        // the whole clamped window is still in progress so the estimate does not fluctuate
+        for _ in 0..3 {
+            let hashes = snapshot
+                .to_kilo_hashes(too_small_interval, time::Instant::now())
+                .into_f64();
+            assert!((hashes - expected).abs() < 1e-6 * expected);
+        }
+        assert_eq!(
+            snapshot.to_kilo_hashes(too_small_interval, time::Instant::now()),
+            snapshot.to_kilo_hashes(MIN_TIME_MEAN_INTERVAL, time::Instant::now())
+        );
+    }
+}
@@ -21,6 +21,7 @@
 // contact us at opensource@braiins.com.
 
 pub mod event;
+pub mod spsc;
 
 use std::fmt;
 use std::sync::atomic::Ordering;
@@ -0,0 +1,299 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Bounded lock-free channel for a single producer and a single consumer. It is intended for hot
+//! paths (e.g. solutions received from one hash chain) where the producer must not be stalled by
+//! a lock held on the consumer side.
+
+use ii_async_compat::futures;
+
+use futures::future::poll_fn;
+use futures::task::{AtomicWaker, Context, Poll};
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Inner<T> {
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Index of the next item to be received (only written by the receiver)
+    head: AtomicUsize,
+    /// Index of the next item to be sent (only written by the sender)
+    tail: AtomicUsize,
+    /// Waker of the receiver waiting for a new item
+    rx_waker: AtomicWaker,
+    /// Waker of the sender waiting for a free slot
+    tx_waker: AtomicWaker,
+    tx_closed: AtomicBool,
+    rx_closed: AtomicBool,
+}
+
+// The buffer slots are accessed exclusively: the sender writes only free slots and the receiver
+// reads only occupied slots. Ownership is handed over by release/acquire of the indexes.
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[inline]
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.buffer[index % self.capacity()].get()
+    }
+
+    fn try_push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.capacity() {
+            return Err(value);
+        }
+        unsafe {
+            self.slot(tail).write(MaybeUninit::new(value));
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { self.slot(head).read().assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        self.tx_waker.wake();
+        Some(value)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+/// Create a new channel which buffers at most `capacity` items
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "BUG: zero capacity of SPSC channel");
+    let inner = Arc::new(Inner {
+        buffer: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        rx_waker: AtomicWaker::new(),
+        tx_waker: AtomicWaker::new(),
+        tx_closed: AtomicBool::new(false),
+        rx_closed: AtomicBool::new(false),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// Sending half of the channel. It cannot be cloned to guarantee a single producer.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send `value` without waiting. The value is returned back when the channel is full or the
+    /// receiver has been dropped.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        if self.inner.rx_closed.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        self.inner.try_push(value)
+    }
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<bool> {
+        let ready = || {
+            if self.inner.rx_closed.load(Ordering::Acquire) {
+                Some(false)
+            } else if self.inner.len() < self.inner.capacity() {
+                Some(true)
+            } else {
+                None
+            }
+        };
+        if let Some(ready) = ready() {
+            return Poll::Ready(ready);
+        }
+        self.inner.tx_waker.register(cx.waker());
+        // check again to avoid missing a wake up between the check and the registration
+        match ready() {
+            Some(ready) => Poll::Ready(ready),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Wait for a free slot and send `value`. The value is returned back when the receiver has
+    /// been dropped.
+    pub async fn send(&mut self, value: T) -> Result<(), T> {
+        if poll_fn(|cx| self.poll_ready(cx)).await {
+            self.inner.try_push(value)
+        } else {
+            Err(value)
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.tx_closed.store(true, Ordering::Release);
+        self.inner.rx_waker.wake();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "spsc::Sender ({}/{})",
+            self.inner.len(),
+            self.inner.capacity()
+        )
+    }
+}
+
+/// Receiving half of the channel
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive a value without waiting
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.inner.try_pop()
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(value) = self.inner.try_pop() {
+            return Poll::Ready(Some(value));
+        }
+        self.inner.rx_waker.register(cx.waker());
+        // the sender has to be checked before the buffer otherwise the last values could be lost
+        let closed = self.inner.tx_closed.load(Ordering::Acquire);
+        match self.inner.try_pop() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Wait for a value. Returns `None` when the sender has been dropped and all values have
+    /// been received.
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.rx_closed.store(true, Ordering::Release);
+        self.inner.tx_waker.wake();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "spsc::Receiver ({}/{})",
+            self.inner.len(),
+            self.inner.capacity()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_spsc_try() {
+        let (mut sender, mut receiver) = channel(2);
+        assert_eq!(receiver.try_recv(), None);
+        assert_eq!(sender.try_send(1), Ok(()));
+        assert_eq!(sender.try_send(2), Ok(()));
+        // the channel is full
+        assert_eq!(sender.try_send(3), Err(3));
+        assert_eq!(receiver.try_recv(), Some(1));
+        assert_eq!(sender.try_send(3), Ok(()));
+        assert_eq!(receiver.try_recv(), Some(2));
+        assert_eq!(receiver.try_recv(), Some(3));
+        assert_eq!(receiver.try_recv(), None);
+
+        drop(receiver);
+        assert_eq!(sender.try_send(4), Err(4));
+    }
+
+    #[test]
+    fn test_spsc_close() {
+        let (mut sender, mut receiver) = channel(4);
+        sender.try_send(1).expect("BUG: cannot send");
+        drop(sender);
+        // values sent before closing are still received
+        assert_eq!(block_on(receiver.recv()), Some(1));
+        assert_eq!(block_on(receiver.recv()), None);
+    }
+
+    #[test]
+    fn test_spsc_threads() {
+        const COUNT: usize = 100_000;
+
+        let (mut sender, mut receiver) = channel(16);
+        let producer = std::thread::spawn(move || {
+            block_on(async {
+                for i in 0..COUNT {
+                    sender.send(Arc::new(i)).await.expect("BUG: cannot send");
+                }
+            })
+        });
+        block_on(async {
+            for i in 0..COUNT {
+                assert_eq!(receiver.recv().await.as_deref(), Some(&i));
+            }
+            assert_eq!(receiver.recv().await, None);
+        });
+        producer.join().expect("BUG: producer panicked");
+    }
+}
@@ -21,6 +21,8 @@
 // contact us at opensource@braiins.com.
 
 pub mod block_mining;
+pub mod pool;
+pub mod simulator;
 
 use crate::hal;
 use crate::job::{self, Bitcoin as _};
@@ -29,7 +31,7 @@ use crate::stats;
 use crate::sync;
 use crate::work;
 
-pub use ii_bitcoin::{TestBlock, TEST_BLOCKS};
+pub use ii_bitcoin::{TestBlock, TestBlockGenerator, TEST_BLOCKS};
 
 use bosminer_macros::{ClientNode, MiningNode, WorkSolverNode};
 
@@ -224,7 +226,11 @@ impl From<&TestBlock> for work::Assignment {
             state: job.midstate,
         };
 
-        Self::new(job, vec![mid], time)
+        work::AssignmentBuilder::new(job)
+            .midstate(mid)
+            .ntime(time)
+            .build()
+            .expect("BUG: inconsistent test block work")
     }
 }
 
@@ -402,6 +408,8 @@ mod test {
     use futures::executor::block_on;
     use ii_async_compat::futures;
 
+    use ii_bitcoin::MeetsTarget;
+
     fn get_engine(work_receiver: &mut work::EngineReceiver) -> Arc<dyn work::Engine> {
         block_on(work_receiver.get_engine()).expect("cannot get test work engine")
     }
@@ -414,6 +422,18 @@ mod test {
         work
     }
 
+    #[test]
+    fn test_random_block_solution() {
+        for block in TestBlockGenerator::new(0).take(32) {
+            cmp_block_with_work(&block, (&block).into());
+
+            // header reconstructed from the solution has to meet the block target
+            let solution = work::Solution::from(&block);
+            assert_eq!(*solution.hash(), block.hash);
+            assert!(solution.hash().meets(&block.target));
+        }
+    }
+
     #[test]
     fn test_work_receiver() {
         let mut work_receiver = create_test_work_receiver();
@@ -27,8 +27,6 @@
 
 use ii_logging::macros::*;
 
-use ii_bitcoin::HashTrait;
-
 use crate::backend;
 use crate::hal::{self, BackendConfig as _};
 use crate::job::Bitcoin;
@@ -72,13 +70,13 @@ impl work::ExhaustedHandler for ExhaustedWorkHandler {
 /// solution in a particular midstate.
 /// The `model_solution` is a "template" after which this work is modeled.
 #[derive(Clone)]
-struct Problem {
+pub(crate) struct Problem {
     model_solution: work::Solution,
     target_midstate: usize,
 }
 
 impl Problem {
-    fn new(model_solution: work::Solution, target_midstate: usize) -> Self {
+    pub fn new(model_solution: work::Solution, target_midstate: usize) -> Self {
         Self {
             model_solution,
             target_midstate,
@@ -90,18 +88,14 @@ impl Problem {
     /// The in-soluble midstates (other than the one specified in the problem)
     /// are created from the original solution by increasing/decreasing the version
     /// slightly. There's no guarantee these blocks have no solution.
-    fn into_work(self, midstate_count: usize) -> work::Assignment {
+    pub fn into_work(self, midstate_count: usize) -> work::Assignment {
         let job: &test_utils::TestBlock = self.model_solution.job();
         let time = job.time();
         let correct_version = job.version();
         let mut midstates = Vec::with_capacity(midstate_count);
 
         // prepare block chunk1 with all invariants
-        let mut block_chunk1 = ii_bitcoin::BlockHeader {
-            previous_hash: job.previous_hash().into_inner(),
-            merkle_root: job.merkle_root().into_inner(),
-            ..Default::default()
-        };
+        let mut block_chunk1 = job.block_header();
 
         // generate all midstates from given range of indexes
         for index in 0..midstate_count {
@@ -129,7 +123,7 @@ impl std::fmt::Debug for Problem {
 
 /// `Solution` represents a valid solution from hardware in a given index.
 #[derive(Clone)]
-struct Solution {
+pub(crate) struct Solution {
     solution: work::Solution,
     midstate_idx: usize,
 }
@@ -202,12 +196,12 @@ impl SolutionState {
 
 /// Registry holds problems and pairs them with solutions
 #[derive(Clone, Debug)]
-struct Registry {
+pub(crate) struct Registry {
     map: HashMap<SolutionKey, SolutionState>,
 }
 
 impl Registry {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             map: HashMap::new(),
         }
@@ -226,7 +220,7 @@ impl Registry {
     }
 
     /// Adds solution to registry.
-    fn add_solution(&mut self, solution: Solution) {
+    pub fn add_solution(&mut self, solution: Solution) {
         match self
             .map
             .get_mut(&SolutionKey::from_solution(solution.clone()))
@@ -238,7 +232,7 @@ impl Registry {
 
     /// Checks if all problems in registry were solved.
     /// Prints the ones that were not solved.
-    fn check_everything_solved(&self, print_missing_solutions: bool) -> bool {
+    pub fn check_everything_solved(&self, print_missing_solutions: bool) -> bool {
         let mut everything_solved = true;
         for (_solution_key, solution_state) in self.map.iter() {
             if !solution_state.solved {
@@ -257,7 +251,7 @@ impl Registry {
 /// - add channel to `engine_sender` that will notify us of engine being exhausted
 /// - make a channel to get solutions back
 /// - build a solver and connect everything to it
-fn build_solvers() -> (
+pub(crate) fn build_solvers() -> (
     work::EngineSender,
     mpsc::UnboundedReceiver<work::Solution>,
     mpsc::UnboundedReceiver<work::DynEngine>,
@@ -280,7 +274,7 @@ fn build_solvers() -> (
             Arc::new(crate::Frontend::new()),
             Arc::new(backend::IgnoreHierarchy),
             engine_receiver,
-            solution_queue_tx,
+            work::SolutionSender::new(solution_queue_tx, Default::default()),
         ),
     )
 }
@@ -302,37 +296,33 @@ async fn collect_solutions(
     }
 }
 
-pub async fn run<T: hal::Backend>(mut backend_config: T::Config) {
-    // this is a small miner core: we generate work, collect solutions, and we pair them together
-    // we expect all (generated) problems to be solved
-    // ii_async_compat::run_main_exits(async move {
-    // read config
-    let midstate_count = backend_config.midstate_count();
-
-    // Create solver and channels to send/receive work
-    let (engine_sender, solution_queue_rx, mut reschedule_receiver, work_solver_builder) =
-        build_solvers();
-
-    // create problem registry
-    let registry = Arc::new(Mutex::new(Registry::new()));
-
-    // start HW backend for selected target
+/// Start HW backend with `work_solver_builder` created by `build_solvers`
+pub(crate) async fn start_backend<T: hal::Backend>(
+    mut backend_config: T::Config,
+    work_solver_builder: work::SolverBuilder<crate::Frontend>,
+) -> crate::Result<hal::FrontendConfig> {
     match T::create(&mut backend_config) {
         node::WorkSolverType::WorkHub(create) => {
             let work_hub = work_solver_builder.create_work_hub(create).await;
-            T::init_work_hub(backend_config, work_hub).await.unwrap();
+            T::init_work_hub(backend_config, work_hub).await
         }
         node::WorkSolverType::WorkSolver(create) => {
             let work_solver = work_solver_builder.create_work_solver(create).await;
-            T::init_work_solver(backend_config, work_solver)
-                .await
-                .unwrap();
+            T::init_work_solver(backend_config, work_solver).await
         }
     }
+}
 
-    // start task to collect solutions and put them to registry
-    tokio::spawn(collect_solutions(solution_queue_rx, registry.clone()));
-
+/// Send work for all test blocks in all possible midstates to the backend and wait until the
+/// solutions are put to `registry` by solution collector. Returns whether all blocks were
+/// solved before `timeout` elapsed.
+pub(crate) async fn mine_test_blocks(
+    engine_sender: &work::EngineSender,
+    reschedule_receiver: &mut mpsc::UnboundedReceiver<work::DynEngine>,
+    registry: &Arc<Mutex<Registry>>,
+    midstate_count: usize,
+    timeout: Duration,
+) -> bool {
     // TODO: first work sent to miner is for some reason ignored
     // workaround: send two works
     engine_sender.broadcast_engine(Arc::new(test_utils::OneWorkEngine::new(
@@ -360,7 +350,7 @@ pub async fn run<T: hal::Backend>(mut backend_config: T::Config) {
 
     // wait for hw to finish computation
     let timeout_started = Instant::now();
-    while timeout_started.elapsed() < T::JOB_TIMEOUT {
+    while timeout_started.elapsed() < timeout {
         delay_for(Duration::from_secs(1)).await;
 
         if registry.lock().await.check_everything_solved(false) {
@@ -369,9 +359,40 @@ pub async fn run<T: hal::Backend>(mut backend_config: T::Config) {
     }
 
     // go through registry and check if everything was solved
-    let registry = registry.lock().await;
-    assert!(registry.check_everything_solved(true));
-    // });
+    registry.lock().await.check_everything_solved(true)
+}
+
+pub async fn run<T: hal::Backend>(backend_config: T::Config) {
+    // this is a small miner core: we generate work, collect solutions, and we pair them together
+    // we expect all (generated) problems to be solved
+    // read config
+    let midstate_count = backend_config.midstate_count();
+
+    // Create solver and channels to send/receive work
+    let (engine_sender, solution_queue_rx, mut reschedule_receiver, work_solver_builder) =
+        build_solvers();
+
+    // create problem registry
+    let registry = Arc::new(Mutex::new(Registry::new()));
+
+    // start HW backend for selected target
+    start_backend::<T>(backend_config, work_solver_builder)
+        .await
+        .unwrap();
+
+    // start task to collect solutions and put them to registry
+    tokio::spawn(collect_solutions(solution_queue_rx, registry.clone()));
+
+    assert!(
+        mine_test_blocks(
+            &engine_sender,
+            &mut reschedule_receiver,
+            &registry,
+            midstate_count,
+            T::JOB_TIMEOUT,
+        )
+        .await
+    );
 }
 
 #[test]
@@ -0,0 +1,540 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Mock Stratum pool listening on the loopback interface. Every accepted connection is served by
+//! its own script of actions (sending jobs, changing difficulty, disconnecting) which is started
+//! once the client is authorized. All users and submitted shares are recorded so that client,
+//! failover and reconnection logic can be tested deterministically without any real pool.
+//!
+//! The pool speaks either Stratum V1 or insecure (non-noise) Stratum V2.
+
+mod v1;
+mod v2;
+
+use ii_logging::macros::*;
+
+use crate::test_utils::TestBlock;
+
+use futures::channel::oneshot;
+use futures::lock::Mutex;
+use ii_async_compat::prelude::*;
+use ii_async_compat::select;
+use tokio::time::delay_for;
+
+use async_trait::async_trait;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Protocol served by the pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    V1,
+    V2,
+}
+
+/// Scripted step performed by the pool after the client has been authorized
+#[derive(Clone)]
+pub enum Action {
+    /// Send V2 mining job built from the test block followed by its prevhash (V2 only)
+    Job(&'static TestBlock),
+    /// Send V1 `mining.notify` (V1 only)
+    Notify(ii_stratum::v1::messages::Notify),
+    /// Change target of the channel (V1 uses equivalent `mining.set_difficulty`)
+    SetTarget(ii_bitcoin::Target),
+    /// Accept (`true`) or reject all following shares
+    AcceptShares(bool),
+    /// Wait for given time
+    Wait(Duration),
+    /// Wait until the total number of shares received over all connections reaches the value
+    WaitForShares(usize),
+    /// Close the connection
+    Disconnect,
+}
+
+/// Script of one connection
+#[derive(Clone, Default)]
+pub struct Script {
+    actions: Vec<Action>,
+    /// Users which are not authorized
+    rejected_users: Vec<String>,
+    initial_target: ii_bitcoin::Target,
+}
+
+impl Script {
+    pub fn new(actions: Vec<Action>) -> Self {
+        Self {
+            actions,
+            ..Default::default()
+        }
+    }
+
+    pub fn reject_users(mut self, users: Vec<String>) -> Self {
+        self.rejected_users = users;
+        self
+    }
+
+    /// Target (difficulty) sent to the client as part of the connection handshake
+    pub fn initial_target(mut self, target: ii_bitcoin::Target) -> Self {
+        self.initial_target = target;
+        self
+    }
+}
+
+/// Share submitted by client
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submission {
+    /// Index of the connection (starting from 0) the share has been received on
+    pub connection_idx: usize,
+    pub job_id: String,
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+    pub accepted: bool,
+}
+
+/// Everything the pool has observed
+#[derive(Debug, Default)]
+struct Record {
+    /// Users which tried to authorize in order of arrival
+    users: Vec<String>,
+    submissions: Vec<Submission>,
+    connection_count: usize,
+}
+
+/// State of one connection shared by the script and by the request processing
+struct State {
+    idx: usize,
+    accept_shares: AtomicBool,
+    rejected_users: Vec<String>,
+    initial_target: ii_bitcoin::Target,
+    record: Arc<Mutex<Record>>,
+}
+
+impl State {
+    /// Record the user and return whether it is authorized
+    async fn authorize(&self, user: String) -> bool {
+        let authorized = !self.rejected_users.contains(&user);
+        self.record.lock().await.users.push(user);
+        authorized
+    }
+
+    /// Record the share and return whether it is accepted
+    async fn submit(&self, job_id: String, nonce: u32, ntime: u32, version: u32) -> bool {
+        let accepted = self.accept_shares.load(Ordering::Relaxed);
+        self.record.lock().await.submissions.push(Submission {
+            connection_idx: self.idx,
+            job_id,
+            nonce,
+            ntime,
+            version,
+            accepted,
+        });
+        accepted
+    }
+}
+
+/// Protocol specific part of the connection
+#[async_trait]
+trait Session: Send + Sync {
+    /// Send message(s) corresponding to protocol specific action
+    async fn send_action(&self, action: Action) -> ii_stratum::error::Result<()>;
+}
+
+/// Perform all script actions. Returns `false` when the connection should be closed.
+async fn run_script<S: Session>(session: &S, state: &State, actions: Vec<Action>) -> bool {
+    for action in actions {
+        match action {
+            Action::AcceptShares(accept) => state.accept_shares.store(accept, Ordering::Relaxed),
+            Action::Wait(duration) => delay_for(duration).await,
+            Action::WaitForShares(count) => {
+                while state.record.lock().await.submissions.len() < count {
+                    delay_for(Duration::from_millis(10)).await;
+                }
+            }
+            Action::Disconnect => return false,
+            action => {
+                if let Err(e) = session.send_action(action).await {
+                    warn!("Mock pool: cannot send message: {}", e);
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Process client `requests` and run the script once the client gets authorized. The
+/// connection is closed as soon as the script says so even when the client is still active.
+async fn serve<S, F>(
+    session: &S,
+    state: &State,
+    actions: Vec<Action>,
+    authorized: oneshot::Receiver<()>,
+    requests: F,
+) where
+    S: Session,
+    F: Future<Output = ()>,
+{
+    let script = async move {
+        match authorized.await {
+            Ok(()) => run_script(session, state, actions).await,
+            // Client has not been authorized, keep processing its requests
+            Err(_) => true,
+        }
+    }
+    .fuse();
+    let requests = requests.fuse();
+    futures::pin_mut!(script, requests);
+
+    select! {
+        keep_open = script => {
+            if keep_open {
+                requests.await;
+            }
+        }
+        _ = requests => {}
+    }
+}
+
+/// Handle to a running mock pool
+#[derive(Clone)]
+pub struct MockPool {
+    address: SocketAddr,
+    record: Arc<Mutex<Record>>,
+}
+
+impl MockPool {
+    /// Start the pool on a random port of the loopback interface. The n-th accepted connection
+    /// is served with the n-th script, connections beyond the scripts are closed immediately.
+    pub fn start(protocol: Protocol, scripts: Vec<Script>) -> Self {
+        let mut server = ii_wire::Server::bind("127.0.0.1:0").expect("BUG: cannot bind mock pool");
+        let address = server.local_addr().expect("BUG: missing mock pool address");
+        let record = Arc::new(Mutex::new(Record::default()));
+
+        let pool = Self {
+            address,
+            record: record.clone(),
+        };
+        tokio::spawn(async move {
+            let mut scripts: VecDeque<_> = scripts.into();
+            while let Some(stream) = server.next().await {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Mock pool: cannot accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let idx = {
+                    let mut record = record.lock().await;
+                    record.connection_count += 1;
+                    record.connection_count - 1
+                };
+                let script = match scripts.pop_front() {
+                    Some(script) => script,
+                    None => {
+                        info!("Mock pool: no script for connection #{}", idx);
+                        continue;
+                    }
+                };
+                let state = State {
+                    idx,
+                    accept_shares: AtomicBool::new(true),
+                    rejected_users: script.rejected_users,
+                    initial_target: script.initial_target,
+                    record: record.clone(),
+                };
+                match protocol {
+                    Protocol::V1 => tokio::spawn(v1::handle(stream, state, script.actions)),
+                    Protocol::V2 => tokio::spawn(v2::handle(stream, state, script.actions)),
+                };
+            }
+        });
+        pool
+    }
+
+    pub fn host(&self) -> String {
+        self.address.ip().to_string()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// Number of accepted connections
+    pub async fn connection_count(&self) -> usize {
+        self.record.lock().await.connection_count
+    }
+
+    /// Users which tried to authorize
+    pub async fn users(&self) -> Vec<String> {
+        self.record.lock().await.users.clone()
+    }
+
+    /// All shares received so far
+    pub async fn submissions(&self) -> Vec<Submission> {
+        self.record.lock().await.submissions.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_utils::TEST_BLOCKS;
+
+    use ii_stratum::test_utils::{v1 as v1_utils, v2 as v2_utils};
+    use ii_stratum::v1::{self, messages as v1_messages, rpc};
+    use ii_stratum::v2::messages::MessageType;
+    use ii_stratum::v2::{self, messages as v2_messages, types::Uint256Bytes};
+    use ii_wire::Connection;
+
+    use ii_bitcoin::HashTrait;
+
+    use std::convert::{TryFrom, TryInto};
+
+    async fn v2_send<M>(connection: &mut Connection<v2::Framing>, message: M)
+    where
+        M: TryInto<v2::Frame, Error = ii_stratum::error::Error>,
+    {
+        connection
+            .send(message.try_into().expect("BUG: cannot convert to frame"))
+            .await
+            .expect("BUG: cannot send message");
+    }
+
+    async fn v2_receive_frame(connection: &mut Connection<v2::Framing>) -> v2::Frame {
+        connection
+            .next()
+            .await
+            .expect("BUG: connection closed")
+            .expect("BUG: cannot receive frame")
+    }
+
+    /// Receive message of `msg_type`
+    async fn v2_receive<M>(connection: &mut Connection<v2::Framing>, msg_type: MessageType) -> M
+    where
+        M: TryFrom<v2::Frame, Error = ii_stratum::error::Error>,
+    {
+        let frame = v2_receive_frame(connection).await;
+        assert_eq!(frame.header.msg_type, msg_type as u8);
+        M::try_from(frame).expect("BUG: cannot parse message")
+    }
+
+    /// Open channel for `user` and return whether it has been accepted
+    async fn v2_open_channel(connection: &mut Connection<v2::Framing>, user: &str) -> bool {
+        v2_send(connection, v2_utils::build_setup_connection()).await;
+        let _: v2_messages::SetupConnectionSuccess =
+            v2_receive(connection, MessageType::SetupConnectionSuccess).await;
+
+        v2_send(
+            connection,
+            v2_messages::OpenStandardMiningChannel {
+                req_id: 1,
+                user: user.try_into().expect("BUG: cannot convert user"),
+                nominal_hashrate: 1e9,
+                max_target: ii_bitcoin::Target::default().into(),
+            },
+        )
+        .await;
+        let frame = v2_receive_frame(connection).await;
+        frame.header.msg_type == MessageType::OpenStandardMiningChannelSuccess as u8
+    }
+
+    /// Receive job and submit its solution
+    async fn v2_solve_job(connection: &mut Connection<v2::Framing>, block: &TestBlock) {
+        let job: v2_messages::NewMiningJob =
+            v2_receive(connection, MessageType::NewMiningJob).await;
+        assert_eq!(
+            job.merkle_root,
+            Uint256Bytes(block.merkle_root.into_inner())
+        );
+        let prev_hash: v2_messages::SetNewPrevHash =
+            v2_receive(connection, MessageType::SetNewPrevHash).await;
+        assert_eq!(prev_hash.job_id, job.job_id);
+        assert_eq!(prev_hash.min_ntime, block.time);
+
+        v2_send(
+            connection,
+            v2_messages::SubmitSharesStandard {
+                channel_id: super::v2::CHANNEL_ID,
+                seq_num: 0,
+                job_id: job.job_id,
+                nonce: block.nonce,
+                ntime: block.time,
+                version: block.version,
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_v2_pool() {
+        let block = &TEST_BLOCKS[0];
+        let pool = MockPool::start(
+            Protocol::V2,
+            vec![
+                Script::new(vec![
+                    Action::Job(block),
+                    Action::WaitForShares(1),
+                    Action::Disconnect,
+                ]),
+                Script::new(vec![Action::AcceptShares(false), Action::Job(block)]),
+                Script::default().reject_users(vec!["bad".to_string()]),
+            ],
+        );
+
+        // The first share is accepted and then the pool disconnects
+        let mut connection = Connection::<v2::Framing>::connect(pool.address)
+            .await
+            .expect("BUG: cannot connect to mock pool");
+        assert!(v2_open_channel(&mut connection, "user").await);
+        v2_solve_job(&mut connection, block).await;
+        while connection.next().await.is_some() {}
+
+        // The second share is rejected
+        let mut connection = Connection::<v2::Framing>::connect(pool.address)
+            .await
+            .expect("BUG: cannot connect to mock pool");
+        assert!(v2_open_channel(&mut connection, "user").await);
+        v2_solve_job(&mut connection, block).await;
+        let _: v2_messages::SubmitSharesError =
+            v2_receive(&mut connection, MessageType::SubmitSharesError).await;
+
+        let mut connection = Connection::<v2::Framing>::connect(pool.address)
+            .await
+            .expect("BUG: cannot connect to mock pool");
+        assert!(!v2_open_channel(&mut connection, "bad").await);
+
+        assert_eq!(pool.connection_count().await, 3);
+        assert_eq!(pool.users().await, vec!["user", "user", "bad"]);
+        let submissions = pool.submissions().await;
+        assert_eq!(submissions.len(), 2);
+        for (idx, submission) in submissions.iter().enumerate() {
+            assert_eq!(submission.connection_idx, idx);
+            assert_eq!(submission.nonce, block.nonce);
+            assert_eq!(submission.accepted, idx == 0);
+        }
+    }
+
+    async fn v1_send(connection: &mut Connection<v1::Framing>, id: u32, message: rpc::Rpc) {
+        let message = match message {
+            rpc::Rpc::Request(request) => rpc::Rpc::from(rpc::Request {
+                id: Some(id),
+                payload: request.payload,
+            }),
+            response => response,
+        };
+        connection
+            .send(message.try_into().expect("BUG: cannot convert to frame"))
+            .await
+            .expect("BUG: cannot send message");
+    }
+
+    async fn v1_receive(connection: &mut Connection<v1::Framing>) -> rpc::Rpc {
+        let frame = connection
+            .next()
+            .await
+            .expect("BUG: connection closed")
+            .expect("BUG: cannot receive frame");
+        rpc::Rpc::try_from(frame).expect("BUG: cannot parse frame")
+    }
+
+    async fn v1_receive_result(connection: &mut Connection<v1::Framing>) -> rpc::StratumResult {
+        match v1_receive(connection).await {
+            rpc::Rpc::Response(response) => response.payload.result.expect("BUG: error response"),
+            rpc::Rpc::Request(request) => panic!("BUG: unexpected request {:?}", request),
+        }
+    }
+
+    async fn v1_receive_request(connection: &mut Connection<v1::Framing>) -> rpc::Request {
+        match v1_receive(connection).await {
+            rpc::Rpc::Request(request) => request,
+            rpc::Rpc::Response(response) => panic!("BUG: unexpected response {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v1_pool() {
+        let notify = v1_utils::build_mining_notify();
+        let pool = MockPool::start(
+            Protocol::V1,
+            vec![Script::new(vec![Action::Notify(notify.clone())])],
+        );
+        let mut connection = Connection::<v1::Framing>::connect(pool.address)
+            .await
+            .expect("BUG: cannot connect to mock pool");
+
+        v1_send(
+            &mut connection,
+            1,
+            v1_utils::build_subscribe_request_frame(),
+        )
+        .await;
+        let result = v1_receive_result(&mut connection).await;
+        v1_messages::SubscribeResult::try_from(&result).expect("BUG: unexpected result");
+        let request = v1_receive_request(&mut connection).await;
+        let difficulty = v1_messages::SetDifficulty::try_from(request).expect("BUG: no difficulty");
+        assert_eq!(difficulty.value(), 1.0);
+
+        v1_send(
+            &mut connection,
+            2,
+            v1_utils::build_authorize_request_message(),
+        )
+        .await;
+        let result = v1_receive_result(&mut connection).await;
+        assert!(v1_messages::BooleanResult::try_from(&result).unwrap().0);
+        let request = v1_receive_request(&mut connection).await;
+        assert_eq!(v1_messages::Notify::try_from(request).unwrap(), notify);
+
+        v1_send(
+            &mut connection,
+            3,
+            v1_utils::build_mining_submit_request_message(),
+        )
+        .await;
+        let result = v1_receive_result(&mut connection).await;
+        assert!(v1_messages::BooleanResult::try_from(&result).unwrap().0);
+
+        let submit = v1_utils::build_mining_submit();
+        assert_eq!(
+            pool.users().await,
+            vec![v1_utils::build_authorize().name().clone()]
+        );
+        assert_eq!(
+            pool.submissions().await,
+            vec![Submission {
+                connection_idx: 0,
+                job_id: notify.job_id().to_string(),
+                nonce: submit.nonce(),
+                ntime: submit.time(),
+                version: submit.version().unwrap_or(0),
+                accepted: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Stratum V1 part of the mock pool
+
+use ii_logging::macros::*;
+
+use super::{Action, State};
+
+use futures::channel::oneshot;
+use futures::lock::Mutex;
+use futures::stream::{SplitSink, SplitStream};
+use ii_async_compat::prelude::*;
+use tokio::net::TcpStream;
+
+use async_trait::async_trait;
+
+use ii_stratum::v1::{
+    self,
+    messages::{
+        Authorize, BooleanResult, ConfigureResult, SetDifficulty, Submit, SubscribeResult,
+        Subscription, SuggestDifficulty,
+    },
+    rpc::{Method, Request, RequestPayload, Response, ResponsePayload, Rpc, StratumError},
+    ExtraNonce1, HexBytes,
+};
+
+use std::convert::{TryFrom, TryInto};
+
+/// Extra nonce 1 assigned to every client
+const EXTRA_NONCE_1: &str = "00000000";
+const EXTRA_NONCE_2_SIZE: usize = 4;
+
+/// Stratum error code of rejected share
+const LOW_DIFFICULTY_SHARE: i32 = 23;
+/// Stratum error code of unsupported request
+const OTHER_UNKNOWN: i32 = 20;
+
+struct Session {
+    sink: Mutex<SplitSink<v1::Framed, v1::Frame>>,
+}
+
+impl Session {
+    async fn send(&self, rpc: Rpc) -> ii_stratum::error::Result<()> {
+        let frame = rpc.try_into()?;
+        self.sink.lock().await.send(frame).await
+    }
+
+    async fn send_result<T>(&self, id: u32, result: T) -> ii_stratum::error::Result<()>
+    where
+        T: TryInto<ResponsePayload, Error = ii_stratum::error::Error>,
+    {
+        self.send(Rpc::from(Response {
+            id,
+            payload: result.try_into()?,
+        }))
+        .await
+    }
+
+    async fn send_error(&self, id: u32, code: i32, msg: &str) -> ii_stratum::error::Result<()> {
+        self.send(Rpc::from(Response {
+            id,
+            payload: ResponsePayload {
+                result: None,
+                error: Some(StratumError(code, msg.to_string(), None)),
+            },
+        }))
+        .await
+    }
+
+    async fn send_notification<T>(&self, notification: T) -> ii_stratum::error::Result<()>
+    where
+        T: TryInto<RequestPayload, Error = ii_stratum::error::Error>,
+    {
+        self.send(Rpc::from(Request {
+            id: None,
+            payload: notification.try_into()?,
+        }))
+        .await
+    }
+
+    async fn handle_request(
+        &self,
+        state: &State,
+        request: Request,
+        authorized: &mut Option<oneshot::Sender<()>>,
+    ) -> ii_stratum::error::Result<()> {
+        let id = match request.id {
+            Some(id) => id,
+            None => {
+                debug!("Mock pool: ignoring notification {:?}", request);
+                return Ok(());
+            }
+        };
+        match &request.payload.method {
+            Method::Configure => {
+                let result = serde_json::json!({
+                    "version-rolling": true,
+                    "version-rolling.mask": format!("{:08x}", ii_stratum::BIP320_N_VERSION_MASK),
+                });
+                self.send_result(id, ConfigureResult(result)).await
+            }
+            Method::Subscribe => {
+                let result = SubscribeResult(
+                    vec![
+                        Subscription("mining.set_difficulty".to_string(), "1".to_string()),
+                        Subscription("mining.notify".to_string(), "1".to_string()),
+                    ],
+                    ExtraNonce1(HexBytes::try_from(EXTRA_NONCE_1)?),
+                    EXTRA_NONCE_2_SIZE,
+                );
+                self.send_result(id, result).await?;
+                // Client needs the difficulty before it can start mining
+                self.send_notification(SetDifficulty([
+                    state.initial_target.get_float_difficulty() as f32
+                ]))
+                .await
+            }
+            Method::ExtranonceSubscribe => self.send_result(id, BooleanResult(true)).await,
+            // Suggested difficulty is always accepted
+            Method::SuggestDifficulty => {
+                let request = SuggestDifficulty::try_from(request)?;
+                self.send_result(id, BooleanResult(true)).await?;
+                self.send_notification(SetDifficulty([request.value() as f32]))
+                    .await
+            }
+            Method::Authorize => {
+                let request = Authorize::try_from(request)?;
+                let result = state.authorize(request.name().clone()).await;
+                self.send_result(id, BooleanResult(result)).await?;
+                if result {
+                    if let Some(authorized) = authorized.take() {
+                        let _ = authorized.send(());
+                    }
+                }
+                Ok(())
+            }
+            Method::Submit => {
+                let share = Submit::try_from(request)?;
+                let accepted = state
+                    .submit(
+                        share.job_id().clone(),
+                        share.nonce(),
+                        share.time(),
+                        share.version().unwrap_or(0),
+                    )
+                    .await;
+                if accepted {
+                    self.send_result(id, BooleanResult(true)).await
+                } else {
+                    self.send_error(id, LOW_DIFFICULTY_SHARE, "Low difficulty share")
+                        .await
+                }
+            }
+            method => {
+                debug!("Mock pool: unsupported method {:?}", method);
+                self.send_error(id, OTHER_UNKNOWN, "Unsupported request")
+                    .await
+            }
+        }
+    }
+
+    /// Respond to all client requests until the client disconnects
+    async fn process_requests(
+        &self,
+        state: &State,
+        mut stream: SplitStream<v1::Framed>,
+        authorized: oneshot::Sender<()>,
+    ) {
+        let mut authorized = Some(authorized);
+        while let Some(frame) = stream.next().await {
+            let rpc = match frame.and_then(Rpc::try_from) {
+                Ok(rpc) => rpc,
+                Err(e) => {
+                    warn!("Mock pool: cannot receive request: {}", e);
+                    return;
+                }
+            };
+            let request = match rpc {
+                Rpc::Request(request) => request,
+                Rpc::Response(response) => {
+                    debug!("Mock pool: ignoring response {:?}", response);
+                    continue;
+                }
+            };
+            if let Err(e) = self.handle_request(state, request, &mut authorized).await {
+                warn!("Mock pool: cannot handle request: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl super::Session for Session {
+    async fn send_action(&self, action: Action) -> ii_stratum::error::Result<()> {
+        match action {
+            Action::Notify(notify) => self.send_notification(notify).await,
+            Action::SetTarget(target) => {
+                self.send_notification(SetDifficulty([target.get_float_difficulty() as f32]))
+                    .await
+            }
+            Action::Job(_) => panic!("BUG: V1 mock pool cannot send V2 job"),
+            _ => panic!("BUG: action is not protocol specific"),
+        }
+    }
+}
+
+pub(super) async fn handle(stream: TcpStream, state: State, actions: Vec<Action>) {
+    let framed = ii_wire::Connection::<v1::Framing>::new(stream).into_inner();
+    let (sink, stream) = framed.split();
+    let session = Session {
+        sink: Mutex::new(sink),
+    };
+    let (authorized_tx, authorized_rx) = oneshot::channel();
+
+    super::serve(
+        &session,
+        &state,
+        actions,
+        authorized_rx,
+        session.process_requests(&state, stream, authorized_tx),
+    )
+    .await;
+}
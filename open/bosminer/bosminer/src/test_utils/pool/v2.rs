@@ -0,0 +1,275 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Stratum V2 part of the mock pool
+
+use ii_logging::macros::*;
+
+use super::{Action, State};
+
+use futures::channel::oneshot;
+use futures::lock::Mutex;
+use futures::stream::{SplitSink, SplitStream};
+use ii_async_compat::prelude::*;
+use tokio::net::TcpStream;
+
+use async_trait::async_trait;
+
+use ii_bitcoin::HashTrait;
+use ii_stratum::v2::framing::Header;
+use ii_stratum::v2::messages::{
+    NewMiningJob, OpenStandardMiningChannel, OpenStandardMiningChannelError,
+    OpenStandardMiningChannelSuccess, SetNewPrevHash, SetTarget, SetupConnection,
+    SetupConnectionSuccess, SubmitSharesError, SubmitSharesStandard, SubmitSharesSuccess,
+    UpdateChannel,
+};
+use ii_stratum::v2::types::*;
+use ii_stratum::v2::{self, build_message_from_frame, Handler};
+
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The only channel opened by the pool
+pub const CHANNEL_ID: u32 = 1;
+
+/// Request received from the client
+enum Request {
+    SetupConnection,
+    OpenChannel(OpenStandardMiningChannel),
+    SubmitShares(SubmitSharesStandard),
+    UpdateChannel(UpdateChannel),
+}
+
+/// Stores the received request
+#[derive(Default)]
+struct RequestHandler {
+    request: Option<Request>,
+}
+
+#[async_trait]
+impl Handler for RequestHandler {
+    async fn visit_setup_connection(&mut self, _header: &Header, _payload: &SetupConnection) {
+        self.request = Some(Request::SetupConnection);
+    }
+
+    async fn visit_open_standard_mining_channel(
+        &mut self,
+        _header: &Header,
+        payload: &OpenStandardMiningChannel,
+    ) {
+        self.request = Some(Request::OpenChannel(payload.clone()));
+    }
+
+    async fn visit_submit_shares_standard(
+        &mut self,
+        _header: &Header,
+        payload: &SubmitSharesStandard,
+    ) {
+        self.request = Some(Request::SubmitShares(payload.clone()));
+    }
+
+    async fn visit_update_channel(&mut self, _header: &Header, payload: &UpdateChannel) {
+        self.request = Some(Request::UpdateChannel(payload.clone()));
+    }
+}
+
+struct Session {
+    sink: Mutex<SplitSink<v2::Framed, v2::Frame>>,
+    last_job_id: AtomicU32,
+}
+
+impl Session {
+    async fn send<M>(&self, message: M) -> ii_stratum::error::Result<()>
+    where
+        M: TryInto<v2::Frame, Error = ii_stratum::error::Error>,
+    {
+        let frame = message.try_into()?;
+        self.sink.lock().await.send(frame).await
+    }
+
+    /// Respond to all client requests until the client disconnects
+    async fn process_requests(
+        &self,
+        state: &State,
+        mut stream: SplitStream<v2::Framed>,
+        authorized: oneshot::Sender<()>,
+    ) {
+        let mut authorized = Some(authorized);
+        while let Some(request) = receive(&mut stream).await {
+            let result = match request {
+                Request::SetupConnection => {
+                    self.send(SetupConnectionSuccess {
+                        used_version: 2,
+                        flags: 0,
+                    })
+                    .await
+                }
+                Request::OpenChannel(request) => {
+                    if state.authorize(request.user.to_string()).await {
+                        let result = self
+                            .send(OpenStandardMiningChannelSuccess {
+                                req_id: request.req_id,
+                                channel_id: CHANNEL_ID,
+                                target: state.initial_target.into(),
+                                extranonce_prefix: Default::default(),
+                                group_channel_id: 0,
+                            })
+                            .await;
+                        if let Some(authorized) = authorized.take() {
+                            let _ = authorized.send(());
+                        }
+                        result
+                    } else {
+                        self.send(OpenStandardMiningChannelError {
+                            req_id: request.req_id,
+                            code: "unknown-user"
+                                .try_into()
+                                .expect("BUG: cannot convert error code"),
+                        })
+                        .await
+                    }
+                }
+                Request::SubmitShares(share) => {
+                    let accepted = state
+                        .submit(
+                            share.job_id.to_string(),
+                            share.nonce,
+                            share.ntime,
+                            share.version,
+                        )
+                        .await;
+                    if accepted {
+                        self.send(SubmitSharesSuccess {
+                            channel_id: CHANNEL_ID,
+                            last_seq_num: share.seq_num,
+                            new_submits_accepted_count: 1,
+                            new_shares_sum: 1,
+                        })
+                        .await
+                    } else {
+                        self.send(SubmitSharesError {
+                            channel_id: CHANNEL_ID,
+                            seq_num: share.seq_num,
+                            code: "invalid-share"
+                                .try_into()
+                                .expect("BUG: cannot convert error code"),
+                        })
+                        .await
+                    }
+                }
+                // Requested target is always accepted
+                Request::UpdateChannel(request) => {
+                    self.send(SetTarget {
+                        channel_id: CHANNEL_ID,
+                        max_target: request.max_target,
+                    })
+                    .await
+                }
+            };
+            if let Err(e) = result {
+                warn!("Mock pool: cannot send response: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl super::Session for Session {
+    async fn send_action(&self, action: Action) -> ii_stratum::error::Result<()> {
+        match action {
+            Action::Job(block) => {
+                let job_id = self.last_job_id.fetch_add(1, Ordering::Relaxed) + 1;
+                self.send(NewMiningJob {
+                    channel_id: CHANNEL_ID,
+                    job_id,
+                    future_job: true,
+                    version: block.version,
+                    merkle_root: Uint256Bytes(block.merkle_root.into_inner()),
+                })
+                .await?;
+                self.send(SetNewPrevHash {
+                    channel_id: CHANNEL_ID,
+                    job_id,
+                    prev_hash: Uint256Bytes(block.previous_hash.into_inner()),
+                    min_ntime: block.time,
+                    nbits: block.bits,
+                })
+                .await
+            }
+            Action::SetTarget(target) => {
+                self.send(SetTarget {
+                    channel_id: CHANNEL_ID,
+                    max_target: target.into(),
+                })
+                .await
+            }
+            Action::Notify(_) => panic!("BUG: V2 mock pool cannot send V1 job"),
+            _ => panic!("BUG: action is not protocol specific"),
+        }
+    }
+}
+
+/// Receive next request, `None` means that the client has disconnected
+async fn receive(stream: &mut SplitStream<v2::Framed>) -> Option<Request> {
+    loop {
+        let frame = match stream.next().await? {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Mock pool: cannot receive frame: {}", e);
+                return None;
+            }
+        };
+        let message = match build_message_from_frame(frame) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Mock pool: cannot parse frame: {}", e);
+                return None;
+            }
+        };
+        let mut handler = RequestHandler::default();
+        message.accept(&mut handler).await;
+        match handler.request {
+            Some(request) => return Some(request),
+            None => debug!("Mock pool: ignoring unexpected message"),
+        }
+    }
+}
+
+pub(super) async fn handle(stream: TcpStream, state: State, actions: Vec<Action>) {
+    let framed = ii_wire::Connection::<v2::Framing>::new(stream).into_inner();
+    let (sink, stream) = framed.split();
+    let session = Session {
+        sink: Mutex::new(sink),
+        last_job_id: AtomicU32::new(0),
+    };
+    let (authorized_tx, authorized_rx) = oneshot::channel();
+
+    super::serve(
+        &session,
+        &state,
+        actions,
+        authorized_rx,
+        session.process_requests(&state, stream, authorized_tx),
+    )
+    .await;
+}
@@ -0,0 +1,565 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Deterministic simulator of mining hardware. It does not compute any hash but it solves work
+//! generated from `TestBlock` jobs (where the correct nonce is known) according to a script. Jobs
+//! of other types (e.g. received from `test_utils::pool::MockPool`) are solved when their header
+//! matches one of `TEST_BLOCKS`. This allows reproducible tests of the whole frontend (work
+//! generation, solution accounting and submission) without any mining hardware.
+
+use ii_logging::macros::*;
+
+use crate::hal;
+use crate::node;
+use crate::stats;
+use crate::test_utils::{TestBlock, TEST_BLOCKS};
+use crate::work;
+
+use bosminer_macros::WorkSolverNode;
+
+use futures::lock::Mutex;
+use ii_async_compat::{futures, tokio};
+use tokio::time::delay_for;
+
+use async_trait::async_trait;
+
+use ii_bitcoin::HashTrait as _;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default number of midstates to exercise work generation with version rolling
+pub const DEFAULT_MIDSTATE_COUNT: usize = 4;
+
+/// Result of solving one piece of work
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Report the correct nonce
+    Solution,
+    /// Report incorrect nonce which has to be accounted as a hardware error
+    HardwareError,
+    /// Do not report anything for the work
+    Nothing,
+}
+
+/// One step of the script: wait for `delay` after the work is received and report `outcome`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub delay: Duration,
+    pub outcome: Outcome,
+}
+
+impl Step {
+    pub fn new(delay: Duration, outcome: Outcome) -> Self {
+        Self { delay, outcome }
+    }
+}
+
+/// Sequence of steps applied to consecutive work
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    steps: Vec<Step>,
+    /// Start from the beginning when all steps are applied
+    repeat: bool,
+}
+
+impl Script {
+    pub fn new(steps: Vec<Step>, repeat: bool) -> Self {
+        Self { steps, repeat }
+    }
+
+    /// Immediately solve all work
+    pub fn solve_all() -> Self {
+        Self::new(
+            vec![Step::new(Duration::from_secs(0), Outcome::Solution)],
+            true,
+        )
+    }
+
+    /// Solve `length` pieces of work, one every `interval`, where each solution is a hardware
+    /// error with probability `hw_error_ratio`. The script is always the same for given `seed`.
+    pub fn random(seed: u64, length: usize, interval: Duration, hw_error_ratio: f64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let steps = (0..length)
+            .map(|_| {
+                let outcome = if rng.gen_bool(hw_error_ratio) {
+                    Outcome::HardwareError
+                } else {
+                    Outcome::Solution
+                };
+                Step::new(interval, outcome)
+            })
+            .collect();
+        Self::new(steps, false)
+    }
+
+    #[inline]
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    fn into_steps(self) -> Box<dyn Iterator<Item = Step> + Send> {
+        if self.repeat {
+            Box::new(self.steps.into_iter().cycle())
+        } else {
+            Box::new(self.steps.into_iter())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub midstate_count: usize,
+    pub script: Script,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            midstate_count: DEFAULT_MIDSTATE_COUNT,
+            script: Script::solve_all(),
+        }
+    }
+}
+
+impl hal::BackendConfig for Config {
+    #[inline]
+    fn midstate_count(&self) -> usize {
+        self.midstate_count
+    }
+}
+
+/// Represents raw solution reported by the simulator. Solutions are reported at the job (pool)
+/// target of the work as if the difficulty of the hardware followed the pool.
+#[derive(Debug)]
+struct Solution {
+    nonce: u32,
+    midstate_idx: usize,
+    target: ii_bitcoin::Target,
+}
+
+impl Solution {
+    fn new(nonce: u32, midstate_idx: usize, target: ii_bitcoin::Target) -> Self {
+        Self {
+            nonce,
+            midstate_idx,
+            target,
+        }
+    }
+}
+
+impl hal::BackendSolution for Solution {
+    #[inline]
+    fn nonce(&self) -> u32 {
+        self.nonce
+    }
+
+    #[inline]
+    fn midstate_idx(&self) -> usize {
+        self.midstate_idx
+    }
+
+    #[inline]
+    fn solution_idx(&self) -> usize {
+        0
+    }
+
+    #[inline]
+    fn target(&self) -> &ii_bitcoin::Target {
+        &self.target
+    }
+}
+
+#[derive(Debug, WorkSolverNode)]
+pub struct Backend {
+    #[member_work_solver_stats]
+    work_solver_stats: stats::BasicWorkSolver,
+    work_generator: Mutex<Option<work::Generator>>,
+    solution_sender: work::SolutionSender,
+    script: Mutex<Option<Script>>,
+}
+
+impl Backend {
+    pub fn new(
+        work_generator: work::Generator,
+        solution_sender: work::SolutionSender,
+        script: Script,
+    ) -> Self {
+        Self {
+            work_solver_stats: Default::default(),
+            work_generator: Mutex::new(Some(work_generator)),
+            solution_sender,
+            script: Mutex::new(Some(script)),
+        }
+    }
+
+    /// Find test block with the same header as the work regardless of the type of its job
+    fn find_test_block(work: &work::Assignment) -> Option<TestBlock> {
+        if let Some(test_block) = work.job::<TestBlock>() {
+            return Some(*test_block);
+        }
+        let header = work.block_header(0, 0);
+        TEST_BLOCKS
+            .iter()
+            .find(|test_block| {
+                test_block.previous_hash.into_inner() == header.previous_hash
+                    && test_block.merkle_root.into_inner() == header.merkle_root
+                    && test_block.time == work.ntime
+            })
+            .copied()
+    }
+
+    /// Apply the script step to the work
+    fn solve(&self, work: work::Assignment, outcome: Outcome) {
+        let test_block = match Self::find_test_block(&work) {
+            Some(test_block) => test_block,
+            None => {
+                warn!("Simulator: cannot solve work which is not generated from test block");
+                return;
+            }
+        };
+        let target = *work.job_target();
+        // Only the midstate with original version of the block has a solution
+        let midstate_idx = work
+            .midstates
+            .iter()
+            .position(|midstate| midstate.version == test_block.version);
+
+        let solution = match (outcome, midstate_idx) {
+            (Outcome::Solution, Some(midstate_idx)) => {
+                Solution::new(test_block.nonce, midstate_idx, target)
+            }
+            (Outcome::HardwareError, midstate_idx) => {
+                Solution::new(test_block.nonce ^ 1, midstate_idx.unwrap_or(0), target)
+            }
+            (Outcome::Solution, None) | (Outcome::Nothing, _) => return,
+        };
+        self.solution_sender
+            .send(work::Solution::new(work, solution, None));
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut work_generator = self
+            .work_generator
+            .lock()
+            .await
+            .take()
+            .expect("BUG: missing work generator");
+        let script = self
+            .script
+            .lock()
+            .await
+            .take()
+            .expect("BUG: missing script");
+
+        for step in script.into_steps() {
+            let work = match work_generator.generate().await {
+                Some(work) => work,
+                None => return,
+            };
+            delay_for(step.delay).await;
+            self.solve(work, step.outcome);
+        }
+        info!("Simulator: all steps of the script have been done");
+    }
+}
+
+#[async_trait]
+impl node::WorkSolver for Backend {
+    async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
+        None
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Simulator")
+    }
+}
+
+#[async_trait]
+impl hal::Backend for Backend {
+    type Type = Self;
+    type Config = Config;
+
+    const DEFAULT_HASHRATE_INTERVAL: Duration = Duration::from_secs(60);
+    const JOB_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn create(backend_config: &mut Config) -> hal::WorkNode<Self> {
+        let script = backend_config.script.clone();
+        node::WorkSolverType::WorkSolver(Box::new(move |work_generator, solution_sender| {
+            Self::new(work_generator, solution_sender, script)
+        }))
+    }
+
+    async fn init_work_hub(
+        _backend_config: Config,
+        _work_hub: work::SolverBuilder<Self::Type>,
+    ) -> crate::Result<hal::FrontendConfig> {
+        panic!("BUG: called `init_work_hub`");
+    }
+
+    async fn init_work_solver(
+        _backend_config: Config,
+        work_solver: Arc<Self>,
+    ) -> crate::Result<hal::FrontendConfig> {
+        tokio::spawn(work_solver.run());
+
+        Ok(hal::FrontendConfig {
+            cgminer_custom_commands: None,
+            metrics_collector: None,
+            halt: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::backend;
+    use crate::client;
+    use crate::hub;
+    use crate::job;
+    use crate::test_utils::block_mining;
+    use crate::test_utils::pool::{self, MockPool};
+
+    use bosminer_config::{ClientDescriptor, ClientUserInfo};
+
+    use ii_bitcoin::MeetsTarget;
+
+    #[test]
+    fn test_random_script() {
+        let interval = Duration::from_millis(10);
+        let script = Script::random(42, 100, interval, 0.2);
+        assert_eq!(script.steps().len(), 100);
+        // The same seed always gives the same script
+        assert_eq!(script, Script::random(42, 100, interval, 0.2));
+        assert_ne!(script, Script::random(43, 100, interval, 0.2));
+
+        assert!(Script::random(42, 100, interval, 0.0)
+            .steps()
+            .iter()
+            .all(|step| step.outcome == Outcome::Solution));
+        assert!(Script::random(42, 100, interval, 1.0)
+            .steps()
+            .iter()
+            .all(|step| step.outcome == Outcome::HardwareError));
+    }
+
+    #[tokio::test]
+    async fn test_block_mining() {
+        block_mining::run::<Backend>(Default::default()).await;
+    }
+
+    /// Whole frontend with the simulator backend mining for one mock pool client
+    struct PoolMining {
+        /// The core keeps only weak reference to the registry
+        _backend_registry: Arc<backend::Registry>,
+        core: Arc<hub::Core>,
+        client: Arc<client::Handle>,
+    }
+
+    impl PoolMining {
+        async fn start(
+            descriptor: ClientDescriptor,
+            difficulty_ramp: Option<job::DifficultyRamp>,
+        ) -> Self {
+            let backend_config = Config::default();
+            let backend_registry = Arc::new(backend::Registry::new());
+            let core = Arc::new(hub::Core::new(
+                hal::BackendConfig::work_strategy(&backend_config),
+                difficulty_ramp,
+                None,
+                None,
+                &backend_registry,
+                None,
+            ));
+            core.build_backend::<Backend>(backend_config)
+                .await
+                .expect("BUG: cannot build simulator backend");
+            tokio::spawn(core.clone().run());
+
+            let client = core
+                .get_client_manager()
+                .create_or_get_default_group()
+                .await
+                .push_client(client::Handle::new(descriptor, None, None))
+                .await;
+            Self {
+                _backend_registry: backend_registry,
+                core,
+                client,
+            }
+        }
+
+        /// Solutions of the simulator which met the target of the backend
+        async fn valid_backend_diff(&self) -> stats::Snapshot<stats::MeterSnapshot> {
+            let work_solver = self
+                .core
+                .get_work_solvers()
+                .await
+                .pop()
+                .expect("BUG: missing simulator");
+            work_solver
+                .mining_stats()
+                .valid_backend_diff()
+                .take_snapshot()
+                .await
+        }
+    }
+
+    /// Descriptor of client which mines on the `pool` as `user`
+    fn pool_descriptor(pool: &MockPool) -> ClientDescriptor {
+        ClientDescriptor::create(
+            format!("stratum2+tcp+insecure://{}:{}", pool.host(), pool.port()).as_str(),
+            &ClientUserInfo::new("user", None),
+            true,
+        )
+        .expect("BUG: cannot create client descriptor")
+    }
+
+    /// Wait until the pool receives the first share
+    async fn wait_for_submissions(pool: &MockPool) -> Vec<pool::Submission> {
+        let mut submissions = vec![];
+        for _ in 0..100 {
+            submissions = pool.submissions().await;
+            if !submissions.is_empty() {
+                break;
+            }
+            delay_for(Duration::from_millis(50)).await;
+        }
+        submissions
+    }
+
+    /// Mine job of the mock pool with the whole frontend and check that the share is submitted
+    /// and accounted at the pool target
+    #[tokio::test]
+    async fn test_pool_mining() {
+        const POOL_DIFFICULTY: usize = 1024;
+
+        let block = &TEST_BLOCKS[0];
+        let pool_target = ii_bitcoin::Target::from_pool_difficulty(POOL_DIFFICULTY);
+        let pool = MockPool::start(
+            pool::Protocol::V2,
+            vec![pool::Script::new(vec![pool::Action::Job(block)]).initial_target(pool_target)],
+        );
+        let mining = PoolMining::start(pool_descriptor(&pool), None).await;
+
+        let submissions = wait_for_submissions(&pool).await;
+        let submission = submissions
+            .first()
+            .expect("BUG: no share has been submitted");
+        assert_eq!(submission.nonce, block.nonce);
+        assert_eq!(submission.version, block.version);
+        assert!(submission.accepted);
+
+        // solutions of the simulator follow the pool target
+        let valid_backend_diff = mining.valid_backend_diff().await;
+        assert!(valid_backend_diff.solutions > 0);
+        assert_eq!(
+            valid_backend_diff.shares.value(),
+            valid_backend_diff.solutions * POOL_DIFFICULTY as u64
+        );
+        mining
+            .client
+            .try_disable()
+            .expect("BUG: client is not enabled");
+    }
+
+    /// Share below the difficulty assigned by the pool is submitted once the difficulty ramp has
+    /// lowered the difficulty of the channel
+    #[tokio::test]
+    async fn test_difficulty_ramp_mining() {
+        // The hash of the test block meets any difficulty up to the network one so the pool
+        // difficulty has to be even higher
+        const POOL_DIFFICULTY: usize = 1 << 40;
+        const RAMP_START_DIFFICULTY: usize = 1;
+
+        let block = &TEST_BLOCKS[0];
+        let pool_target = ii_bitcoin::Target::from_pool_difficulty(POOL_DIFFICULTY);
+        assert!(!block.hash.meets(&pool_target));
+        // The job is sent after the pool has answered the request of the ramp
+        let pool = MockPool::start(
+            pool::Protocol::V2,
+            vec![pool::Script::new(vec![
+                pool::Action::Wait(Duration::from_millis(200)),
+                pool::Action::Job(block),
+            ])
+            .initial_target(pool_target)],
+        );
+        let difficulty_ramp =
+            job::DifficultyRamp::new(RAMP_START_DIFFICULTY, Duration::from_secs(600));
+        let mining = PoolMining::start(pool_descriptor(&pool), Some(difficulty_ramp)).await;
+
+        let submissions = wait_for_submissions(&pool).await;
+        let submission = submissions
+            .first()
+            .expect("BUG: no share has been submitted along the difficulty ramp");
+        assert_eq!(submission.nonce, block.nonce);
+        assert!(submission.accepted);
+
+        // solutions of the simulator follow the difficulty requested by the ramp
+        let valid_backend_diff = mining.valid_backend_diff().await;
+        assert!(valid_backend_diff.solutions > 0);
+        assert_eq!(
+            valid_backend_diff.shares.value(),
+            valid_backend_diff.solutions * RAMP_START_DIFFICULTY as u64
+        );
+        mining
+            .client
+            .try_disable()
+            .expect("BUG: client is not enabled");
+    }
+
+    /// Mining continues with the alternate user when the pool rejects the configured one
+    #[tokio::test]
+    async fn test_alternate_user_mining() {
+        let block = &TEST_BLOCKS[0];
+        let pool = MockPool::start(
+            pool::Protocol::V2,
+            vec![
+                pool::Script::default().reject_users(vec!["user".to_string()]),
+                pool::Script::new(vec![pool::Action::Job(block)]),
+            ],
+        );
+        let mut descriptor = pool_descriptor(&pool);
+        descriptor.alternate_users = vec!["alternate".to_string()];
+        let mining = PoolMining::start(descriptor, None).await;
+
+        let submissions = wait_for_submissions(&pool).await;
+        let submission = submissions
+            .first()
+            .expect("BUG: no share has been submitted by the alternate user");
+        assert_eq!(submission.connection_idx, 1);
+        assert!(submission.accepted);
+        assert_eq!(pool.users().await, vec!["user", "alternate"]);
+        mining
+            .client
+            .try_disable()
+            .expect("BUG: client is not enabled");
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Built-in Stratum V1->V2 translation proxy. Legacy Stratum V1 miners connect to the listening
+//! port and each of them is served by its own extended channel opened at the upstream Stratum V2
+//! server.
+
+use ii_logging::macros::*;
+
+use crate::client::transport;
+use crate::error;
+
+use bosminer_config::{ClientDescriptor, ClientProtocol, ClientUserInfo};
+
+use failure::ResultExt;
+
+use futures::channel::mpsc;
+use futures::stream::FuturesUnordered;
+use ii_async_compat::prelude::*;
+use ii_async_compat::{select, tokio};
+
+use ii_stratum::{v1, v2};
+use ii_stratum_proxy::v1_translation::{V1ToV2Translation, V1ToV2TranslationOptions};
+use ii_wire::{Address, Connection, Server};
+
+use std::time;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Address to listen on for incoming Stratum V1 connections
+    pub listen_addr: Address,
+    /// Upstream Stratum V2 server with the user all downstream miners are mining for
+    pub upstream: ClientDescriptor,
+}
+
+impl Config {
+    /// Build configuration from listen address in `host:port` format and upstream Stratum V2 URL
+    pub fn parse(listen_addr: &str, upstream_url: &str, user: &str) -> Result<Self, String> {
+        let listen_addr = listen_addr
+            .parse()
+            .map_err(|_| format!("invalid address '{}' (expected 'host:port')", listen_addr))?;
+        let upstream = ClientDescriptor::create(upstream_url, &ClientUserInfo::parse(user), true)
+            .map_err(|e| format!("invalid upstream '{}': {}", upstream_url, e))?;
+        match upstream.protocol {
+            ClientProtocol::StratumV2(_) | ClientProtocol::StratumV2Insecure => {}
+            _ => Err(format!(
+                "upstream '{}' is not a Stratum V2 server",
+                upstream_url
+            ))?,
+        }
+        Ok(Self {
+            listen_addr,
+            upstream,
+        })
+    }
+}
+
+/// Message pump between one downstream V1 miner, its translation and the upstream V2 server
+struct ConnectionHandler {
+    translation: V1ToV2Translation,
+    v1_conn: v1::Framed,
+    v2_conn: v2::Framed,
+    v1_translation_rx: mpsc::Receiver<v1::Frame>,
+    v2_translation_rx: mpsc::Receiver<v2::Frame>,
+}
+
+impl ConnectionHandler {
+    const MAX_TRANSLATION_CHANNEL_SIZE: usize = 10;
+    const EVENT_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+
+    async fn connect_upstream(upstream: &ClientDescriptor) -> error::Result<v2::Framed> {
+        let connection = match upstream.proxy.as_ref() {
+            // the host name is resolved by the proxy
+            Some(proxy) => {
+                transport::connect_socks5(proxy, &upstream.host, upstream.port()).await?
+            }
+            None => transport::connect(&upstream.host, upstream.port()).await?,
+        };
+
+        Ok(match &upstream.protocol {
+            ClientProtocol::StratumV2(upstream_authority_public_key) => {
+                v2::noise::Initiator::new(upstream_authority_public_key.clone().into_inner())
+                    .connect(connection)
+                    .await?
+            }
+            ClientProtocol::StratumV2Insecure => {
+                Connection::<v2::Framing>::new(connection).into_inner()
+            }
+            _ => panic!("BUG: translation proxy supports only stratum V2 upstream!"),
+        })
+    }
+
+    async fn new(v1_conn: v1::Framed, upstream: &ClientDescriptor) -> error::Result<Self> {
+        let v2_conn = Self::connect_upstream(upstream).await?;
+        let (v1_translation_tx, v1_translation_rx) =
+            mpsc::channel(Self::MAX_TRANSLATION_CHANNEL_SIZE);
+        let (v2_translation_tx, v2_translation_rx) =
+            mpsc::channel(Self::MAX_TRANSLATION_CHANNEL_SIZE);
+        let options = V1ToV2TranslationOptions::new(
+            upstream.user.clone(),
+            upstream.host.clone(),
+            upstream.port(),
+        );
+        let mut translation = V1ToV2Translation::new(v1_translation_tx, v2_translation_tx, options);
+        translation
+            .start()
+            .context("Cannot set up upstream connection")?;
+
+        Ok(Self {
+            translation,
+            v1_conn,
+            v2_conn,
+            v1_translation_rx,
+            v2_translation_rx,
+        })
+    }
+
+    /// Runs the translation until any side of the connection terminates
+    async fn run(mut self) -> error::Result<()> {
+        while !self.translation.is_failed() {
+            select! {
+                v1_frame = self.v1_conn.next().timeout(Self::EVENT_TIMEOUT).fuse() => {
+                    match v1_frame {
+                        Ok(Some(v1_frame)) => {
+                            let v1_msg = v1::build_message_from_frame(v1_frame?)?;
+                            v1_msg.accept(&mut self.translation).await;
+                        }
+                        Ok(None) | Err(_) => Err("Downstream V1 connection dropped")?,
+                    }
+                },
+                v2_frame = self.v2_conn.next().timeout(Self::EVENT_TIMEOUT).fuse() => {
+                    match v2_frame {
+                        Ok(Some(v2_frame)) => {
+                            let v2_msg = v2::build_message_from_frame(v2_frame?)?;
+                            v2_msg.accept(&mut self.translation).await;
+                        }
+                        Ok(None) | Err(_) => Err("Upstream V2 connection dropped")?,
+                    }
+                },
+                v1_frame = self.v1_translation_rx.next().fuse() => {
+                    match v1_frame {
+                        Some(v1_frame) => self
+                            .v1_conn
+                            .send(v1_frame)
+                            .timeout(Self::EVENT_TIMEOUT)
+                            .await
+                            .map_err(|_| "V1 send timeout")??,
+                        None => Err("V1 translation component terminated")?,
+                    }
+                },
+                v2_frame = self.v2_translation_rx.next().fuse() => {
+                    match v2_frame {
+                        Some(v2_frame) => self
+                            .v2_conn
+                            .send(v2_frame)
+                            .timeout(Self::EVENT_TIMEOUT)
+                            .await
+                            .map_err(|_| "V2 send timeout")??,
+                        None => Err("V2 translation component terminated")?,
+                    }
+                },
+            }
+        }
+        // Pending responses telling the miner about the failure are flushed before closing
+        while let Ok(Some(v1_frame)) = self.v1_translation_rx.try_next() {
+            self.v1_conn
+                .send(v1_frame)
+                .timeout(Self::EVENT_TIMEOUT)
+                .await
+                .map_err(|_| "V1 send timeout")??;
+        }
+        Err("Upstream refused to open channel".into())
+    }
+}
+
+async fn handle_connection(
+    stream: std::io::Result<tokio::net::TcpStream>,
+    upstream: ClientDescriptor,
+) {
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!("Translation proxy: cannot accept connection: {}", e);
+            return;
+        }
+    };
+    let peer_addr = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown peer".to_string());
+    info!("Translation proxy: accepted connection from {}", peer_addr);
+    let v1_conn = Connection::<v1::Framing>::new(stream).into_inner();
+    let result = match ConnectionHandler::new(v1_conn, &upstream).await {
+        Ok(handler) => handler.run().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        info!(
+            "Translation proxy: connection from {} closed: {}",
+            peer_addr, e
+        );
+    }
+}
+
+/// Run the translation proxy until the returned future is dropped. All downstream connections are
+/// driven by the same future so they are closed together with it. Errors are only logged so that
+/// the proxy never affects the mining.
+pub async fn run(config: Config) {
+    let mut server = match Server::bind(&config.listen_addr) {
+        Ok(server) => server,
+        Err(e) => {
+            error!(
+                "Translation proxy: cannot listen on {}: {}",
+                config.listen_addr, e
+            );
+            return;
+        }
+    };
+    info!(
+        "Translation proxy: listening on {} for Stratum V1 miners, upstream {}",
+        config.listen_addr,
+        config.upstream.get_url(true, true, false)
+    );
+
+    let mut connections = FuturesUnordered::new();
+    loop {
+        select! {
+            stream = server.next().fuse() => match stream {
+                Some(stream) => connections.push(handle_connection(stream, config.upstream.clone())),
+                None => break,
+            },
+            _ = connections.select_next_some() => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_parse() {
+        let config = Config::parse(
+            "0.0.0.0:3333",
+            "stratum2+tcp+insecure://v2.pool.example.com",
+            "user.worker",
+        )
+        .expect("BUG: invalid config");
+        assert_eq!(config.listen_addr, Address("0.0.0.0".to_string(), 3333));
+        assert_eq!(config.upstream.host, "v2.pool.example.com");
+        assert_eq!(config.upstream.port(), 3336);
+        assert_eq!(config.upstream.user, "user.worker");
+
+        assert!(
+            Config::parse(":3333", "stratum2+tcp+insecure://pool.example.com", "user").is_err()
+        );
+        assert!(Config::parse("0.0.0.0:3333", "stratum+tcp://pool.example.com", "user").is_err());
+        // Secure upstream requires authority public key
+        assert!(Config::parse("0.0.0.0:3333", "stratum2+tcp://pool.example.com", "user").is_err());
+    }
+}
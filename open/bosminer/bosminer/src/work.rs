@@ -26,13 +26,14 @@
 pub mod engine;
 mod solver;
 
+use crate::error;
 use crate::hal;
 use crate::job;
 use crate::node;
 
-use ii_bitcoin::HashTrait as _;
+use ii_bitcoin::MeetsTarget;
 
-pub use solver::{Generator, SolutionSender, SolverBuilder};
+pub use solver::{Generator, SolutionQueueDepth, SolutionSender, SolverBuilder};
 
 use ii_async_compat::prelude::*;
 use tokio::sync::watch;
@@ -87,23 +88,34 @@ pub struct Midstate {
 /// Starting with merkle_root_tail the data goes to chunk2 of SHA256.
 #[derive(Clone, Debug)]
 pub struct Assignment {
-    /// Unique path describing internal hierarchy of backend solvers
-    pub path: node::Path,
+    /// Unique path describing internal hierarchy of backend solvers. The path is shared by all
+    /// work generated for the same work solver.
+    pub path: node::SharedPath,
     /// Bitcoin job shared with initial network protocol and work solution
     job: Arc<dyn job::Bitcoin>,
     /// Multiple midstates can be generated for each work
     pub midstates: Vec<Midstate>,
     /// nTime value for current work
     pub ntime: u32,
+    /// Job (pool) target in effect when the work has been generated. Solutions of this work are
+    /// accounted at this target even when the pool changes difficulty in the meantime
+    job_target: ii_bitcoin::Target,
+    /// Number of midstates reused from previously generated work instead of computing them
+    reused_midstates: usize,
 }
 
 impl Assignment {
-    pub fn new(job: Arc<dyn job::Bitcoin>, midstates: Vec<Midstate>, ntime: u32) -> Self {
+    /// Create work without any validation. It is intended only for tests which need
+    /// inconsistent work, `AssignmentBuilder` should be used otherwise.
+    pub(crate) fn new(job: Arc<dyn job::Bitcoin>, midstates: Vec<Midstate>, ntime: u32) -> Self {
+        let job_target = job.target();
         Self {
-            path: vec![],
+            path: Default::default(),
             job,
             midstates,
             ntime,
+            job_target,
+            reused_midstates: 0,
         }
     }
 
@@ -113,6 +125,12 @@ impl Assignment {
         self.job.origin()
     }
 
+    /// Return original job when it has the expected type
+    #[inline]
+    pub fn job<T: job::Bitcoin>(&self) -> Option<&T> {
+        self.job.downcast_ref::<T>()
+    }
+
     /// Return merkle root tail
     #[inline]
     pub fn merkle_root_tail(&self) -> u32 {
@@ -125,11 +143,128 @@ impl Assignment {
         self.job.bits()
     }
 
+    /// Return job (pool) target stamped at the time of work generation
+    #[inline]
+    pub fn job_target(&self) -> &ii_bitcoin::Target {
+        &self.job_target
+    }
+
     /// Return number of generated work associated within this work assignment
     #[inline]
     pub fn generated_work_amount(&self) -> usize {
         self.midstates.len()
     }
+
+    /// Return number of midstates which have been computed for this work assignment
+    #[inline]
+    pub fn computed_midstates(&self) -> usize {
+        self.midstates.len() - self.reused_midstates
+    }
+
+    /// Build full block header for selected midstate and nonce (used by software solvers which do
+    /// not work with midstates directly)
+    pub fn block_header(&self, midstate_idx: usize, nonce: u32) -> ii_bitcoin::BlockHeader {
+        ii_bitcoin::BlockHeader {
+            version: self.midstates[midstate_idx].version,
+            time: self.ntime,
+            nonce,
+            ..self.job.block_header()
+        }
+    }
+}
+
+/// Builder of work assignment which checks that the work is consistent with its job
+#[derive(Clone, Debug)]
+pub struct AssignmentBuilder {
+    job: Arc<dyn job::Bitcoin>,
+    midstates: Vec<Midstate>,
+    ntime: u32,
+    reused_midstates: usize,
+}
+
+impl AssignmentBuilder {
+    /// Start building work for `job` with ntime set to the job time
+    pub fn new(job: Arc<dyn job::Bitcoin>) -> Self {
+        let ntime = job.time();
+        Self {
+            job,
+            midstates: vec![],
+            ntime,
+            reused_midstates: 0,
+        }
+    }
+
+    pub fn midstate(mut self, midstate: Midstate) -> Self {
+        self.midstates.push(midstate);
+        self
+    }
+
+    pub fn midstates<I: IntoIterator<Item = Midstate>>(mut self, midstates: I) -> Self {
+        self.midstates.extend(midstates);
+        self
+    }
+
+    /// Mark `count` of the midstates as reused from previously generated work
+    pub fn reused_midstates(mut self, count: usize) -> Self {
+        self.reused_midstates = count;
+        self
+    }
+
+    /// Use rolled `ntime` which has to be within the job range up to `max_time`
+    pub fn ntime(mut self, ntime: u32) -> Self {
+        self.ntime = ntime;
+        self
+    }
+
+    /// Check all invariants and create the work
+    pub fn build(self) -> Result<Assignment, error::Work> {
+        if self.midstates.is_empty() {
+            return Err(error::Work::MissingMidstate);
+        }
+
+        let job_version = self.job.version();
+        let version_mask = self.job.version_mask();
+        if let Some(midstate) = self
+            .midstates
+            .iter()
+            .find(|midstate| (midstate.version ^ job_version) & !version_mask != 0)
+        {
+            return Err(error::Work::InconsistentVersion {
+                version: midstate.version,
+                job_version,
+                version_mask,
+            });
+        }
+
+        let (min_time, max_time) = (self.job.time(), self.job.max_time());
+        if self.ntime < min_time || self.ntime > max_time {
+            return Err(error::Work::NtimeOutOfRange {
+                ntime: self.ntime,
+                min_time,
+                max_time,
+            });
+        }
+
+        assert!(
+            self.reused_midstates <= self.midstates.len(),
+            "BUG: more reused midstates than midstates"
+        );
+        Ok(Assignment {
+            reused_midstates: self.reused_midstates,
+            ..Assignment::new(self.job, self.midstates, self.ntime)
+        })
+    }
+}
+
+/// Result of software verification of a nonce returned by the backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The hash meets the share target and the solution can be submitted
+    ValidShare,
+    /// The nonce is correct (the hash meets the backend target) but it is below the share target
+    BelowShareTarget,
+    /// The hash does not meet even the backend target so the chip computed it incorrectly
+    HardwareError,
 }
 
 /// Container with mining work and a corresponding solution received at a particular time
@@ -144,8 +279,6 @@ pub struct Solution {
     solution: Arc<dyn hal::BackendSolution>,
     /// Lazy evaluated double hash of this solution
     hash: OnceCell<ii_bitcoin::DHash>,
-    /// Lazy evaluated job target to ensure that the value is stable for this solution
-    job_target: OnceCell<ii_bitcoin::Target>,
     /// Lazy evaluated backend target to ensure that the value is stable for this solution
     backend_target: OnceCell<ii_bitcoin::Target>,
 }
@@ -162,7 +295,6 @@ impl Solution {
             solution: Arc::new(solution),
             hash: OnceCell::new(),
             backend_target: OnceCell::new(),
-            job_target: OnceCell::new(),
         }
     }
 
@@ -200,16 +332,39 @@ impl Solution {
         self.work.midstates[i].version
     }
 
+    /// Bits of the version field which are allowed to be rolled for the job
+    #[inline]
+    pub fn version_mask(&self) -> u32 {
+        self.work.job.version_mask()
+    }
+
+    /// Height of the block being mined when it is known
+    #[inline]
+    pub fn block_height(&self) -> Option<u32> {
+        self.work.job.block_height()
+    }
+
+    /// Serialized coinbase transaction of the block being mined when it is known
+    #[inline]
+    pub fn coinbase(&self) -> Option<Vec<u8>> {
+        self.work.job.coinbase()
+    }
+
     #[inline]
     pub fn network_target(&self) -> ii_bitcoin::Target {
-        // NOTE: it is expected that job has been checked in client and is correct
-        ii_bitcoin::Target::from_compact(self.work.job.bits())
-            .expect("BUG: job has incorrect nbits")
+        self.work.job.network_target()
     }
 
+    /// Return job (pool) target in effect when the work of this solution has been generated
     #[inline]
     pub fn job_target(&self) -> &ii_bitcoin::Target {
-        self.job_target.get_or_init(|| self.work.job.target())
+        self.work.job_target()
+    }
+
+    /// Return pool difficulty in effect when the work of this solution has been generated
+    #[inline]
+    pub fn job_difficulty(&self) -> usize {
+        self.job_target().get_difficulty()
     }
 
     #[inline]
@@ -230,23 +385,45 @@ impl Solution {
 
     /// Converts mining work solution to Bitcoin block header structure which is packable
     pub fn get_block_header(&self) -> ii_bitcoin::BlockHeader {
-        let job = &self.work.job;
-
         ii_bitcoin::BlockHeader {
             version: self.version(),
-            previous_hash: job.previous_hash().into_inner(),
-            merkle_root: job.merkle_root().into_inner(),
             time: self.time(),
-            bits: job.bits(),
             nonce: self.nonce(),
+            ..self.work.job.block_header()
+        }
+    }
+
+    /// Recompute the hash of this solution and classify it with respect to the `share_target`
+    /// and the backend target
+    pub fn verify(&self, share_target: &ii_bitcoin::Target) -> Verdict {
+        let hash = self.hash();
+        if hash.meets(share_target) {
+            Verdict::ValidShare
+        } else if hash.meets(self.backend_target()) {
+            Verdict::BelowShareTarget
+        } else {
+            Verdict::HardwareError
         }
     }
 
+    /// Check if the hash of this solution does not meet the backend target
+    #[inline]
+    pub fn is_hardware_error(&self) -> bool {
+        !self.hash().meets(self.backend_target())
+    }
+
     #[inline]
     pub fn has_valid_job(&self) -> bool {
         self.work.job.is_valid()
     }
 
+    /// Check that the rolled version differs from the job version only in bits allowed by the
+    /// negotiated version mask (BIP320)
+    #[inline]
+    pub fn has_valid_version(&self) -> bool {
+        (self.version() ^ self.work.job.version()) & !self.version_mask() == 0
+    }
+
     /// Return the whole unique path starting from job origin and ending in backend.
     pub fn path(&self) -> node::Path {
         // Arc does not support dynamic casting to trait bounds so there must be used another Arc
@@ -261,7 +438,7 @@ impl Solution {
                 .collect()
         } else {
             // Origin has been removed and no one will receive any solution
-            self.work.path.clone()
+            self.work.path.as_ref().clone()
         }
     }
 }
@@ -479,6 +656,7 @@ impl EngineReceiver {
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::test_utils::{TestBlock, TEST_BLOCKS};
 
     #[test]
     fn test_block_double_hash() {
@@ -494,4 +672,229 @@ pub mod test {
             assert_eq!(&block.hash, hash);
         }
     }
+
+    #[test]
+    fn test_assignment_builder() {
+        let block = TEST_BLOCKS[0];
+        let midstate = |version| Midstate {
+            version,
+            state: block.midstate,
+        };
+        let builder = AssignmentBuilder::new(Arc::new(block));
+
+        let work = builder
+            .clone()
+            .midstate(midstate(block.version))
+            .build()
+            .expect("BUG: inconsistent work");
+        assert_eq!(work.ntime, block.time);
+        assert_eq!(work.midstates.len(), 1);
+
+        assert_eq!(
+            builder.clone().build().unwrap_err(),
+            error::Work::MissingMidstate
+        );
+        // test block does not allow any version rolling
+        assert_eq!(
+            builder
+                .clone()
+                .midstates(vec![
+                    midstate(block.version),
+                    midstate(block.version ^ 0x2000)
+                ])
+                .build()
+                .unwrap_err(),
+            error::Work::InconsistentVersion {
+                version: block.version ^ 0x2000,
+                job_version: block.version,
+                version_mask: 0,
+            }
+        );
+
+        let max_time = block.time + engine::DEFAULT_NTIME_ROLLING_LIMIT;
+        let work = builder
+            .clone()
+            .midstate(midstate(block.version))
+            .ntime(max_time)
+            .build()
+            .expect("BUG: inconsistent work");
+        assert_eq!(work.ntime, max_time);
+        for &ntime in &[block.time - 1, max_time + 1] {
+            assert_eq!(
+                builder
+                    .clone()
+                    .midstate(midstate(block.version))
+                    .ntime(ntime)
+                    .build()
+                    .unwrap_err(),
+                error::Work::NtimeOutOfRange {
+                    ntime,
+                    min_time: block.time,
+                    max_time,
+                }
+            );
+        }
+    }
+
+    /// Job with pool target which can be changed after the job has been created
+    #[derive(Debug, Clone)]
+    struct VariableTargetJob {
+        block: TestBlock,
+        target: Arc<StdMutex<ii_bitcoin::Target>>,
+    }
+
+    impl job::Bitcoin for VariableTargetJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.block.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.block.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            *self.target.lock().expect("cannot lock target")
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug)]
+    struct NonceSolution {
+        nonce: u32,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for NonceSolution {
+        fn nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+    }
+
+    #[test]
+    fn test_solution_job_difficulty() {
+        let block = TEST_BLOCKS[0];
+        let target = Arc::new(StdMutex::new(ii_bitcoin::Target::from_pool_difficulty(64)));
+        let job = Arc::new(VariableTargetJob {
+            block,
+            target: target.clone(),
+        });
+        let work = AssignmentBuilder::new(job)
+            .midstate(Midstate {
+                version: block.version,
+                state: block.midstate,
+            })
+            .build()
+            .expect("BUG: inconsistent work");
+
+        // work is generated at difficulty 64
+        assert_eq!(work.job_target().get_difficulty(), 64);
+        // pool changes the difficulty while the work is being solved
+        *target.lock().expect("cannot lock target") = ii_bitcoin::Target::from_pool_difficulty(256);
+        let solution = Solution::new(
+            work,
+            NonceSolution {
+                nonce: block.nonce,
+                target: Default::default(),
+            },
+            None,
+        );
+        // and once more before the solution is submitted
+        *target.lock().expect("cannot lock target") =
+            ii_bitcoin::Target::from_pool_difficulty(1024);
+
+        assert_eq!(solution.job_difficulty(), 64);
+        assert_eq!(
+            solution.job_target(),
+            &ii_bitcoin::Target::from_pool_difficulty(64)
+        );
+    }
+
+    #[test]
+    fn test_solution_version() {
+        let block = TEST_BLOCKS[0];
+        let new_solution = |version| {
+            let work = Assignment::new(
+                Arc::new(block),
+                vec![Midstate {
+                    version,
+                    state: block.midstate,
+                }],
+                block.time,
+            );
+            Solution::new(
+                work,
+                NonceSolution {
+                    nonce: block.nonce,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        // test block does not allow any version rolling
+        assert!(new_solution(block.version).has_valid_version());
+        assert!(!new_solution(block.version ^ 0x2000).has_valid_version());
+    }
+
+    #[test]
+    fn test_solution_verify() {
+        let block = TEST_BLOCKS[0];
+        let new_solution = |nonce| {
+            Solution::new(
+                (&block).into(),
+                NonceSolution {
+                    nonce,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        let solution = new_solution(block.nonce);
+        assert_eq!(solution.verify(&block.target), Verdict::ValidShare);
+        // zero share target cannot be met by any hash
+        let share_target = ii_bitcoin::Target::from_compact(0).expect("BUG: invalid target");
+        assert_eq!(solution.verify(&share_target), Verdict::BelowShareTarget);
+        assert!(!solution.is_hardware_error());
+
+        // wrong nonce does not meet the backend target with difficulty 1
+        let solution = new_solution(block.nonce ^ 1);
+        assert_eq!(solution.verify(&block.target), Verdict::HardwareError);
+        assert!(solution.is_hardware_error());
+    }
 }
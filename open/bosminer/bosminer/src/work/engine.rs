@@ -22,11 +22,13 @@
 
 //! Provides work engines that are capable for converting Jobs to actual work suitable for mining
 //! backend processing
+use ii_logging::macros::*;
+
 use super::*;
 use crate::job;
 
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
 
 #[derive(Debug)]
 pub struct ExhaustedWork;
@@ -47,15 +49,53 @@ impl Engine for ExhaustedWork {
 /// The maximal index represent the range which is excluded so it must be incremented by 1.
 const BIP320_UPPER_BOUND_EXCLUSIVE_INDEX: u32 = ii_bitcoin::BIP320_VERSION_MAX + 1;
 /// Once we exhaust the version we roll, we have to roll ntime.
-/// The current limit gives us support for miners with speed up to 2.4 PH/s
+/// The default limit gives us support for miners with speed up to 2.4 PH/s
 /// hash_space * roll_ntime_seconds / new_stratum_job_every_sec = 2**(32 + 16) * 256 / 30 = 2.4e15
-const ROLL_NTIME_SECONDS: u32 = 256;
+pub const DEFAULT_NTIME_ROLLING_LIMIT: u32 = 255;
+/// Maximal offset of rolled ntime which keeps the whole index space of a job in `u32`
+pub const MAX_NTIME_ROLLING_LIMIT: u32 = std::u16::MAX as u32 - 1;
+/// Upper bound of midstates cached by one engine. The versions are traversed in the same order
+/// for every ntime value so the cache covers the whole BIP320 version space to reuse all
+/// midstates of jobs with full version mask.
+pub const MAX_MIDSTATE_CACHE_SIZE: usize = BIP320_UPPER_BOUND_EXCLUSIVE_INDEX as usize;
+
+/// Version bits of the `job` which are rolled. Bits outside of BIP320 range are never touched
+/// even if the pool allows them.
+fn rolling_mask(job: &Arc<dyn job::Bitcoin>) -> u32 {
+    job.version_mask() & ii_bitcoin::BIP320_VERSION_MASK
+}
+
+/// Number of distinct versions and ntime values which can be used for work generated from the
+/// `job`. Jobs without version mask are expanded only by rolling ntime up to `max_time`.
+fn rolling_space(job: &Arc<dyn job::Bitcoin>) -> (u32, u32) {
+    let version_count = 1 << rolling_mask(job).count_ones();
+    let ntime_limit = job
+        .max_time()
+        .saturating_sub(job.time())
+        .min(MAX_NTIME_ROLLING_LIMIT);
+    (version_count, ntime_limit + 1)
+}
+
+/// Number of distinct midstates in each work generated from the `job`. Multiple midstates
+/// require rolling of version bits so a job with narrow `version_mask` provides fewer versions
+/// than `midstate_count` (a single one when the job does not allow any rolling). Both counts are
+/// powers of two so the result always divides the version space.
+fn distinct_midstate_count(job: &Arc<dyn job::Bitcoin>, midstate_count: usize) -> usize {
+    let (version_count, _) = rolling_space(job);
+    midstate_count.min(version_count as usize)
+}
+
+/// Size of the whole index space of a job that is allocated to generated work
+fn rolling_space_size(job: &Arc<dyn job::Bitcoin>) -> u32 {
+    let (version_count, ntime_count) = rolling_space(job);
+    version_count * ntime_count
+}
 
 /// Primitive for atomic range counter
 /// This structure can be freely shared among parallel processes and each range is returned only to
 /// one competing process. The structure returns ranges until maximal allowed index is reached.
 #[derive(Debug, Clone)]
-struct AtomicRange {
+pub struct AtomicRange {
     /// Maximal index value which cannot be exceeded
     max_index: u32,
     /// Size of step between each range
@@ -128,104 +168,249 @@ impl AtomicRange {
     }
 }
 
+/// Default allocation splits the space into contiguous ranges handed out in ascending order to
+/// whichever backend requests work first
+impl hal::NonceAllocator for AtomicRange {
+    #[inline]
+    fn next(&self) -> LoopState<(u32, u32)> {
+        match AtomicRange::next(self) {
+            None => LoopState::Exhausted,
+            // the range ending at `next` is the last one when no other range fits after it
+            Some((current, next)) if self.is_exhausted(next) => LoopState::Break((current, next)),
+            Some(range) => LoopState::Continue(range),
+        }
+    }
+
+    #[inline]
+    fn terminate(&self) {
+        AtomicRange::terminate(self)
+    }
+}
+
+/// Strategy of work generation for a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Each work contains only one midstate and the backend searches the full nonce range for it.
+    /// It is suitable for backends that cannot use multiple midstates per chip (older ASICs).
+    SingleMidstate,
+    /// Each work contains multiple midstates with rolled version (as per BIP320)
+    MultiMidstate(usize),
+}
+
+impl Strategy {
+    /// Select strategy automatically from the number of midstates that backend is able to solve
+    /// at once
+    pub fn from_midstate_count(midstate_count: usize) -> Self {
+        assert!(midstate_count > 0, "BUG: backend without midstates");
+        // Every work has to take the same number of versions from the BIP320 rolling space
+        assert_eq!(
+            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX % (midstate_count as u32),
+            0,
+            "BUG: unsupported number of midstates"
+        );
+        if midstate_count == 1 {
+            Self::SingleMidstate
+        } else {
+            Self::MultiMidstate(midstate_count)
+        }
+    }
+
+    /// Number of midstates in each generated work
+    pub fn midstate_count(&self) -> usize {
+        match *self {
+            Self::SingleMidstate => 1,
+            Self::MultiMidstate(midstate_count) => midstate_count,
+        }
+    }
+
+    /// Create work engine for the `job` that generates work shaped according to this strategy.
+    /// The work always contains the number of midstates the backend is configured for even when
+    /// the job does not provide enough versions (see `VersionRolling`).
+    pub fn create_engine(&self, job: Arc<dyn job::Bitcoin>) -> DynEngine {
+        Arc::new(VersionRolling::new(job, self.midstate_count()))
+    }
+
+    /// Same as `create_engine` but the index space of the job is allocated by a custom allocator
+    /// created with `allocator_builder`
+    pub fn create_engine_with_allocator(
+        &self,
+        job: Arc<dyn job::Bitcoin>,
+        allocator_builder: &hal::NonceAllocatorBuilder,
+    ) -> DynEngine {
+        let midstate_count = self.midstate_count();
+        let allocator = allocator_builder(
+            rolling_space_size(&job),
+            distinct_midstate_count(&job, midstate_count) as u32,
+        );
+        Arc::new(VersionRolling::with_allocator(
+            job,
+            midstate_count,
+            allocator,
+        ))
+    }
+
+    /// Engine generator that can be used by `EngineSender`
+    pub fn engine_generator(self) -> EngineGenerator {
+        Box::new(move |job| self.create_engine(job))
+    }
+
+    /// Engine generator with custom allocation of the index space which can be injected to
+    /// clients with `client::Handle::replace_engine_generator`
+    pub fn engine_generator_with_allocator(
+        self,
+        allocator_builder: hal::NonceAllocatorBuilder,
+    ) -> EngineGenerator {
+        Box::new(move |job| self.create_engine_with_allocator(job, &allocator_builder))
+    }
+}
+
 /// Version rolling implements WorkEngine trait and represents a shared source of work for mining
 /// backends. Each instance takes care of atomically allocating version field ranges until the
 /// range is full exhausted. After version has been rolled over, ntime is incremented and version
-/// resetted to 0. The limit of `ntime` range is determined by `ROLL_NTIME_SECONDS`.
+/// resetted to 0. The limit of `ntime` range is determined by `max_time` of the job which reflects
+/// bounds allowed by the pool. Jobs which do not allow version rolling are expanded only by ntime.
+/// When the job provides fewer versions than `midstate_count` the distinct midstates are repeated
+/// to fill the work because backends cannot change the number of midstates at runtime.
 ///
 /// TODO: Rolling ntime together with version IS A HACK. This needs to be fixed properly by raising
 /// `ntime` in sync with real-time clock.
 #[derive(Debug, Clone)]
-pub struct VersionRolling {
+pub struct VersionRolling<A = AtomicRange> {
     job: Arc<dyn job::Bitcoin>,
     /// Number of midstates that each generated work covers
     midstate_count: usize,
+    /// Number of distinct versions in each generated work. It is lower than `midstate_count`
+    /// when the job does not allow rolling of enough version bits.
+    distinct_midstate_count: usize,
     /// Current range of the rolled part of the version (before BIP320 shift)
     /// We keep current version in lower 16 bits and `ntime_offset`
-    /// in upper 8 bits. When version overflows, the ntime_offset gets
+    /// in upper bits. When version overflows, the ntime_offset gets
     /// automatically incremented.
-    curr_range: AtomicRange,
-    /// Base Bitcoin block header version with BIP320 bits cleared
+    curr_range: A,
+    /// Number of distinct versions rolled before ntime is incremented
+    version_count: u32,
+    /// Number of distinct ntime values (the first one is the original job time)
+    ntime_count: u32,
+    /// Version bits which are rolled
+    rolling_mask: u32,
+    /// Base Bitcoin block header version with rolled bits cleared
     base_version: u32,
+    /// Midstates computed for the first ntime value reused after ntime is rolled. There is no
+    /// cache when the job does not allow rolling of ntime.
+    midstate_cache: Option<Arc<StdMutex<ii_bitcoin::MidstateCache>>>,
+    /// Set when the last range has been allocated or the engine has been terminated
+    exhausted: Arc<AtomicBool>,
 }
 
 impl VersionRolling {
     pub fn new(job: Arc<dyn job::Bitcoin>, midstate_count: usize) -> Self {
-        let base_version = job.version() & !ii_bitcoin::BIP320_VERSION_MASK;
-        // we have to be sure we have no "leftover" midstates when we roll
-        assert_eq!(
-            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX % (midstate_count as u32),
-            0
+        let curr_range = AtomicRange::new(
+            0,
+            rolling_space_size(&job),
+            distinct_midstate_count(&job, midstate_count) as u32,
         );
+        Self::with_allocator(job, midstate_count, curr_range)
+    }
+}
+
+impl<A: hal::NonceAllocator> VersionRolling<A> {
+    /// Create version rolling engine with a custom allocation of the index space. The allocator
+    /// has to return ranges of `distinct_midstate_count` indexes.
+    pub fn with_allocator(
+        job: Arc<dyn job::Bitcoin>,
+        midstate_count: usize,
+        curr_range: A,
+    ) -> Self {
+        let (version_count, ntime_count) = rolling_space(&job);
+        let rolling_mask = rolling_mask(&job);
+        let base_version = job.version() & !rolling_mask;
+        let distinct_midstate_count = distinct_midstate_count(&job, midstate_count);
+        // we have to be sure we have no "leftover" midstates when we roll
+        assert_eq!(version_count % (distinct_midstate_count as u32), 0);
+        // midstates are reused only when ntime is rolled
+        let midstate_cache = if ntime_count > 1 {
+            let capacity = (version_count as usize).min(MAX_MIDSTATE_CACHE_SIZE);
+            Some(Arc::new(StdMutex::new(ii_bitcoin::MidstateCache::new(
+                capacity,
+            ))))
+        } else {
+            None
+        };
         Self {
             job,
             midstate_count,
-            curr_range: AtomicRange::new(
-                0,
-                BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * ROLL_NTIME_SECONDS,
-                midstate_count as u32,
-            ),
+            distinct_midstate_count,
+            curr_range,
+            version_count,
+            ntime_count,
+            rolling_mask,
             base_version,
+            midstate_cache,
+            exhausted: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    /// Convert the allocated index to a block version as per BIP320
+    /// Convert the allocated index to a block version as per BIP320. Bits of the index are
+    /// deposited into the rolled bits of the version from the least significant one.
     #[inline]
     fn get_block_version(&self, index: u32) -> u32 {
-        let version = index % BIP320_UPPER_BOUND_EXCLUSIVE_INDEX;
-        assert!(version <= ii_bitcoin::BIP320_VERSION_MAX);
-        self.base_version | (version << ii_bitcoin::BIP320_VERSION_SHIFT)
+        let mut version = index % self.version_count;
+        if self.rolling_mask == ii_bitcoin::BIP320_VERSION_MASK {
+            return self.base_version | (version << ii_bitcoin::BIP320_VERSION_SHIFT);
+        }
+        let mut rolled_bits = 0;
+        let mut mask = self.rolling_mask;
+        while mask != 0 {
+            let lowest_bit = mask & mask.wrapping_neg();
+            if version & 1 != 0 {
+                rolled_bits |= lowest_bit;
+            }
+            version >>= 1;
+            mask &= mask - 1;
+        }
+        self.base_version | rolled_bits
     }
 
     /// Convert the allocated index to a ntime offset
     #[inline]
     fn get_ntime_offset(&self, index: u32) -> u32 {
-        let ntime_offset = index / BIP320_UPPER_BOUND_EXCLUSIVE_INDEX;
-        assert!(ntime_offset < ROLL_NTIME_SECONDS);
+        let ntime_offset = index / self.version_count;
+        assert!(ntime_offset < self.ntime_count);
         ntime_offset
     }
 }
 
-impl Engine for VersionRolling {
+impl<A: hal::NonceAllocator> Engine for VersionRolling<A> {
     fn terminate(&self) {
         self.curr_range.terminate();
+        self.exhausted.store(true, Ordering::Relaxed);
     }
 
     fn is_exhausted(&self) -> bool {
-        self.curr_range.is_exhausted(None)
+        self.exhausted.load(Ordering::Relaxed)
     }
 
     fn next_work(&self) -> LoopState<Assignment> {
         // determine next range of indexes from version space
-        let (current, next) = match self.curr_range.next() {
+        let range = self.curr_range.next();
+        let (current, next) = match &range {
             // return immediately when the space is exhausted
-            None => return LoopState::Exhausted,
+            LoopState::Exhausted => {
+                self.exhausted.store(true, Ordering::Relaxed);
+                return LoopState::Exhausted;
+            }
             // use range of indexes for generation of midstates
-            Some(range) => range,
+            LoopState::Break(range) => {
+                self.exhausted.store(true, Ordering::Relaxed);
+                *range
+            }
+            LoopState::Continue(range) => *range,
         };
 
-        // check if given range is the same as number of midstates
-        assert_eq!(self.midstate_count, (next - current) as usize);
+        // check if given range is the same as number of distinct midstates
+        assert_eq!(self.distinct_midstate_count, (next - current) as usize);
         let mut midstates = Vec::with_capacity(self.midstate_count);
 
-        // prepare block chunk1 with all invariants
-        let mut block_chunk1 = ii_bitcoin::BlockHeader {
-            previous_hash: self.job.previous_hash().into_inner(),
-            merkle_root: self.job.merkle_root().into_inner(),
-            ..Default::default()
-        };
-
-        // generate all midstates from given range of indexes
-        for index in current..next {
-            // use index for generation compatible header version
-            let version = self.get_block_version(index);
-            block_chunk1.version = version;
-            midstates.push(Midstate {
-                version,
-                state: block_chunk1.midstate(),
-            })
-        }
-
         // Once we exhaust version-rolling-space, we start rolling ntime.
         // We can be sure ntime offset is common for all blocks, because `midstate_count`
         // divides the size of range we roll.
@@ -233,14 +418,68 @@ impl Engine for VersionRolling {
         let ntime_offset = self.get_ntime_offset(current);
         assert_eq!(ntime_offset, self.get_ntime_offset(next - 1));
 
-        let work = Assignment::new(self.job.clone(), midstates, self.job.time() + ntime_offset);
-        if self.curr_range.is_exhausted(next) {
-            // when the whole version space has been exhausted then mark the generated work as
-            // a last one (the next call of this method will return 'Exhausted')
-            LoopState::Break(work)
-        } else {
-            LoopState::Continue(work)
+        // prepare block chunk1 with all invariants
+        let mut block_chunk1 = self.job.block_header();
+
+        // midstates do not depend on ntime so the ones computed for the first ntime value are
+        // looked up once ntime has been rolled
+        let mut cached_states = vec![None; self.distinct_midstate_count];
+        if let Some(midstate_cache) = self.midstate_cache.as_ref().filter(|_| ntime_offset > 0) {
+            let mut midstate_cache = midstate_cache
+                .lock()
+                .expect("BUG: cannot lock midstate cache");
+            for (index, cached_state) in (current..next).zip(cached_states.iter_mut()) {
+                block_chunk1.version = self.get_block_version(index);
+                *cached_state = midstate_cache.get(&block_chunk1);
+            }
+        }
+
+        // generate all missing midstates from given range of indexes
+        let mut computed_midstates = Vec::new();
+        for (index, cached_state) in (current..next).zip(cached_states) {
+            // use index for generation compatible header version
+            let version = self.get_block_version(index);
+            block_chunk1.version = version;
+            let state = cached_state.unwrap_or_else(|| {
+                let state = block_chunk1.midstate();
+                computed_midstates.push((block_chunk1, state));
+                state
+            });
+            midstates.push(Midstate { version, state })
+        }
+        if let Some(midstate_cache) = self.midstate_cache.as_ref() {
+            if !computed_midstates.is_empty() {
+                let mut midstate_cache = midstate_cache
+                    .lock()
+                    .expect("BUG: cannot lock midstate cache");
+                for (header, state) in computed_midstates.iter() {
+                    midstate_cache.insert(header, *state);
+                }
+            }
         }
+        // repeat the distinct midstates when the job does not allow rolling of enough versions
+        for i in self.distinct_midstate_count..self.midstate_count {
+            let midstate = midstates[i % self.distinct_midstate_count].clone();
+            midstates.push(midstate);
+        }
+
+        let work = match AssignmentBuilder::new(self.job.clone())
+            .midstates(midstates)
+            .reused_midstates(self.midstate_count - computed_midstates.len())
+            .ntime(self.job.time() + ntime_offset)
+            .build()
+        {
+            Ok(work) => work,
+            Err(e) => {
+                // Mining of an inconsistent job would only produce invalid shares
+                error!("Engine cannot generate work for job: {}", e);
+                self.terminate();
+                return LoopState::Exhausted;
+            }
+        };
+        // when the whole version space has been exhausted then the generated work is marked as
+        // a last one (the next call of this method will return 'Exhausted')
+        range.map(|_| work)
     }
 }
 
@@ -256,6 +495,19 @@ pub mod test {
             assert_eq!(range.next(), Some((i, i + step)));
         }
         assert_eq!(range.next(), None);
+
+        // the allocator reports the last range together with its allocation
+        let range = AtomicRange::new(start, stop, step);
+        let mut last_range = None;
+        loop {
+            match hal::NonceAllocator::next(&range) {
+                LoopState::Exhausted => break,
+                LoopState::Break(range) => last_range = Some(range),
+                LoopState::Continue(_) => assert!(last_range.is_none()),
+            }
+        }
+        let start = stop - (stop - start) % step - step;
+        assert_eq!(last_range, Some((start, start + step)));
     }
 
     #[test]
@@ -267,8 +519,8 @@ pub mod test {
             1,
         );
         compare_range(
-            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * ROLL_NTIME_SECONDS - 1,
-            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * ROLL_NTIME_SECONDS,
+            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * (DEFAULT_NTIME_ROLLING_LIMIT + 1) - 1,
+            BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * (DEFAULT_NTIME_ROLLING_LIMIT + 1),
             1,
         );
         compare_range(std::u32::MAX - 1, std::u32::MAX, 1);
@@ -295,6 +547,412 @@ pub mod test {
         compare_range(5, 9, 4);
     }
 
+    /// Test block which allows rolling of BIP320 version bits
+    #[derive(Debug, Clone)]
+    struct RollingTestBlock(test_utils::TestBlock);
+
+    impl job::Bitcoin for RollingTestBlock {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.0.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.0.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            ii_bitcoin::BIP320_VERSION_MASK
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.0.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.0.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.0.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.0.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.0.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            self.0.is_valid()
+        }
+    }
+
+    /// Test block which does not allow version rolling and limits rolling of ntime
+    #[derive(Debug, Clone)]
+    struct NtimeLimitedTestBlock {
+        block: test_utils::TestBlock,
+        ntime_limit: u32,
+    }
+
+    impl job::Bitcoin for NtimeLimitedTestBlock {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.block.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            0
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn max_time(&self) -> u32 {
+            self.block.time() + self.ntime_limit
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            self.block.is_valid()
+        }
+    }
+
+    /// Test block which allows rolling only of some version bits
+    #[derive(Debug, Clone)]
+    struct NarrowRollingTestBlock {
+        block: test_utils::TestBlock,
+        version_mask: u32,
+    }
+
+    impl job::Bitcoin for NarrowRollingTestBlock {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.block.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.version_mask
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            self.block.is_valid()
+        }
+    }
+
+    /// Check shape of the work generated by the strategy
+    fn check_strategy_work(strategy: Strategy, expected_midstate_count: usize) {
+        let random_blocks = test_utils::TestBlockGenerator::new(0).take(16);
+        for block in test_utils::TEST_BLOCKS.iter().cloned().chain(random_blocks) {
+            let job = Arc::new(block);
+            let engine = strategy.create_engine(Arc::new(RollingTestBlock(block)));
+
+            let work = engine.next_work().unwrap();
+            assert_eq!(work.midstates.len(), expected_midstate_count);
+            assert_eq!(work.ntime, job.time());
+            for (i, midstate) in work.midstates.iter().enumerate() {
+                assert_eq!(midstate.version, get_block_version(&job, i as u32));
+            }
+            // the first midstate corresponds to the original block
+            assert_eq!(block.midstate, work.midstates[0].state);
+
+            // the next work continues with following versions
+            let work = engine.next_work().unwrap();
+            assert_eq!(
+                work.midstates[0].version,
+                get_block_version(&job, expected_midstate_count as u32)
+            );
+        }
+    }
+
+    #[test]
+    fn test_strategy() {
+        assert_eq!(Strategy::from_midstate_count(1), Strategy::SingleMidstate);
+        assert_eq!(Strategy::from_midstate_count(4), Strategy::MultiMidstate(4));
+
+        check_strategy_work(Strategy::SingleMidstate, 1);
+        check_strategy_work(Strategy::from_midstate_count(2), 2);
+        check_strategy_work(Strategy::from_midstate_count(4), 4);
+        check_strategy_work(Strategy::from_midstate_count(8), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported number of midstates")]
+    fn test_strategy_unsupported_midstate_count() {
+        Strategy::from_midstate_count(3);
+    }
+
+    #[test]
+    fn test_strategy_zero_version_mask() {
+        // test blocks do not allow version rolling
+        let job = Arc::new(test_utils::TEST_BLOCKS[0]);
+        assert_eq!(job.version_mask(), 0);
+
+        for &midstate_count in &[1, 2, 4, 8] {
+            let strategy = Strategy::from_midstate_count(midstate_count);
+            let engine = strategy.create_engine(job.clone());
+            let work = engine.next_work().unwrap();
+            // the backend always gets the number of midstates it is configured for
+            assert_eq!(work.midstates.len(), midstate_count);
+            for midstate in work.midstates.iter() {
+                assert_eq!(midstate.version, job.version());
+                assert_eq!(midstate.state, job.midstate);
+            }
+            assert_eq!(work.computed_midstates(), 1);
+            // the repeated midstates do not consume the index space
+            assert_eq!(engine.next_work().unwrap().ntime, job.time() + 1);
+        }
+    }
+
+    #[test]
+    fn test_narrow_version_mask() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let base_version = block.version & !0x6000;
+
+        // only two bits are rolled, the bit outside of BIP320 range is ignored
+        let job = Arc::new(NarrowRollingTestBlock {
+            block,
+            version_mask: 0x8000_6000,
+        });
+        let engine = Strategy::from_midstate_count(4).create_engine(job.clone());
+        let work = engine.next_work().unwrap();
+        let versions: Vec<_> = work.midstates.iter().map(|m| m.version).collect();
+        assert_eq!(
+            versions,
+            vec![
+                base_version,
+                base_version | 0x2000,
+                base_version | 0x4000,
+                base_version | 0x6000,
+            ]
+        );
+        assert_eq!(work.ntime, block.time);
+        // the version space is exhausted so ntime is rolled
+        assert_eq!(engine.next_work().unwrap().ntime, block.time + 1);
+
+        // distinct midstates are repeated when the mask is too narrow
+        let job = Arc::new(NarrowRollingTestBlock {
+            block,
+            version_mask: 0x0001_0000,
+        });
+        let work = Strategy::from_midstate_count(4)
+            .create_engine(job)
+            .next_work()
+            .unwrap();
+        let versions: Vec<_> = work.midstates.iter().map(|m| m.version).collect();
+        let base_version = block.version & !0x0001_0000;
+        assert_eq!(
+            versions,
+            vec![
+                base_version,
+                base_version | 0x0001_0000,
+                base_version,
+                base_version | 0x0001_0000,
+            ]
+        );
+    }
+
+    /// Collect ntime of all work generated by the engine until it is exhausted
+    fn collect_ntime(engine: &dyn Engine, version: u32) -> Vec<u32> {
+        let mut ntimes = vec![];
+        loop {
+            let (work, last) = match engine.next_work() {
+                LoopState::Continue(work) => (work, false),
+                LoopState::Break(work) => (work, true),
+                LoopState::Exhausted => panic!("expected work"),
+            };
+            assert_eq!(work.midstates.len(), 1);
+            assert_eq!(work.midstates[0].version, version);
+            ntimes.push(work.ntime);
+            if last {
+                break;
+            }
+        }
+        assert!(engine.is_exhausted());
+        ntimes
+    }
+
+    #[test]
+    fn test_ntime_rolling_without_version_mask() {
+        // job without version mask is expanded only by ntime within default limit
+        let job = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let engine = Strategy::SingleMidstate.create_engine(job.clone());
+        let expected: Vec<_> = (0..=DEFAULT_NTIME_ROLLING_LIMIT)
+            .map(|ntime_index| get_ntime(&job, ntime_index))
+            .collect();
+        assert_eq!(collect_ntime(engine.as_ref(), job.version()), expected);
+    }
+
+    #[test]
+    fn test_ntime_rolling_limit() {
+        let block = test_utils::TEST_BLOCKS[0];
+        for &ntime_limit in &[0, 1, 7] {
+            let job = Arc::new(NtimeLimitedTestBlock { block, ntime_limit });
+            let engine = VersionRolling::new(job, 1);
+            let expected: Vec<_> = (0..=ntime_limit)
+                .map(|ntime_index| block.time + ntime_index)
+                .collect();
+            assert_eq!(collect_ntime(&engine, block.version), expected);
+        }
+
+        // time bounds allowed by the pool are capped to fit the index space
+        let job: Arc<dyn job::Bitcoin> = Arc::new(NtimeLimitedTestBlock {
+            block,
+            ntime_limit: std::u32::MAX - block.time,
+        });
+        assert_eq!(rolling_space(&job), (1, MAX_NTIME_ROLLING_LIMIT + 1));
+    }
+
+    /// Allocator which hands out ranges in descending order
+    #[derive(Debug)]
+    struct DescendingRange {
+        step_size: u32,
+        /// Exclusive end of the next range
+        curr_index: AtomicU32,
+    }
+
+    impl DescendingRange {
+        fn new(space_size: u32, step_size: u32) -> Self {
+            Self {
+                step_size,
+                curr_index: AtomicU32::new(space_size - space_size % step_size),
+            }
+        }
+    }
+
+    impl hal::NonceAllocator for DescendingRange {
+        fn next(&self) -> LoopState<(u32, u32)> {
+            loop {
+                let current = self.curr_index.load(Ordering::Relaxed);
+                let next = match current.checked_sub(self.step_size) {
+                    Some(next) => next,
+                    None => return LoopState::Exhausted,
+                };
+                if self
+                    .curr_index
+                    .compare_and_swap(current, next, Ordering::Relaxed)
+                    == current
+                {
+                    return if next < self.step_size {
+                        LoopState::Break((next, current))
+                    } else {
+                        LoopState::Continue((next, current))
+                    };
+                }
+            }
+        }
+
+        fn terminate(&self) {
+            self.curr_index.store(0, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_custom_nonce_allocator() {
+        // the whole space has to be covered exactly once
+        const SPACE_SIZE: u32 = 64;
+        for &step_size in &[1, 2, 4] {
+            let allocator = DescendingRange::new(SPACE_SIZE, step_size);
+            let mut covered = vec![false; SPACE_SIZE as usize];
+            loop {
+                let range = hal::NonceAllocator::next(&allocator);
+                let last = match range {
+                    LoopState::Exhausted => panic!("BUG: the last range has not been reported"),
+                    LoopState::Break(_) => true,
+                    LoopState::Continue(_) => false,
+                };
+                let (start, stop) = range.unwrap();
+                assert_eq!(stop - start, step_size);
+                for index in start..stop {
+                    assert!(!covered[index as usize], "index {} allocated twice", index);
+                    covered[index as usize] = true;
+                }
+                if last {
+                    break;
+                }
+            }
+            assert!(match hal::NonceAllocator::next(&allocator) {
+                LoopState::Exhausted => true,
+                _ => false,
+            });
+            assert!(covered.iter().all(|&covered| covered));
+        }
+
+        // the engine has to generate work from ranges provided by the custom allocator
+        let created = Arc::new(AtomicU32::new(0));
+        let allocator_builder: hal::NonceAllocatorBuilder = {
+            let created = created.clone();
+            Arc::new(move |space_size, step_size| {
+                created.fetch_add(1, Ordering::Relaxed);
+                Box::new(DescendingRange::new(space_size, step_size))
+            })
+        };
+        let strategy = Strategy::from_midstate_count(4);
+        let block = test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(block);
+        let engine = strategy
+            .create_engine_with_allocator(Arc::new(RollingTestBlock(block)), &allocator_builder);
+        assert_eq!(created.load(Ordering::Relaxed), 1);
+
+        let work = engine.next_work().unwrap();
+        assert_eq!(work.midstates.len(), 4);
+        assert_eq!(work.ntime, get_ntime(&job, DEFAULT_NTIME_ROLLING_LIMIT));
+        for (i, midstate) in work.midstates.iter().enumerate() {
+            let version_index = BIP320_UPPER_BOUND_EXCLUSIVE_INDEX - 4 + i as u32;
+            assert_eq!(midstate.version, get_block_version(&job, version_index));
+        }
+
+        engine.terminate();
+        assert!(engine.is_exhausted());
+        assert!(match engine.next_work() {
+            LoopState::Exhausted => true,
+            _ => false,
+        });
+    }
+
     #[test]
     fn test_block_midstate() {
         for block in test_utils::TEST_BLOCKS.iter() {
@@ -306,6 +964,89 @@ pub mod test {
         }
     }
 
+    /// Generate `count` work items with the same ntime value and check that their midstates
+    /// are taken from the cache when the ntime has been rolled
+    fn check_cached_work(engine: &VersionRolling, ntime: u32, count: usize) -> Vec<Assignment> {
+        let works: Vec<_> = (0..count).map(|_| engine.next_work().unwrap()).collect();
+        for work in works.iter() {
+            assert_eq!(work.ntime, ntime);
+            if ntime == engine.job.time() {
+                assert_eq!(work.computed_midstates(), engine.distinct_midstate_count);
+            } else {
+                assert_eq!(work.computed_midstates(), 0);
+            }
+        }
+        works
+    }
+
+    fn compare_midstates(first_works: &[Assignment], rolled_works: &[Assignment]) {
+        for (first, rolled) in first_works.iter().zip(rolled_works.iter()) {
+            for (first, rolled) in first.midstates.iter().zip(rolled.midstates.iter()) {
+                assert_eq!(first.version, rolled.version);
+                assert_eq!(first.state, rolled.state);
+            }
+        }
+    }
+
+    /// Capacity, size, hits and misses of the midstate cache of `engine`
+    fn midstate_cache_stats(engine: &VersionRolling) -> (usize, usize, u64, u64) {
+        let midstate_cache = engine
+            .midstate_cache
+            .as_ref()
+            .expect("BUG: missing cache")
+            .lock()
+            .expect("BUG: cannot lock midstate cache");
+        (
+            midstate_cache.capacity(),
+            midstate_cache.len(),
+            midstate_cache.hits(),
+            midstate_cache.misses(),
+        )
+    }
+
+    #[test]
+    fn test_midstate_cache_ntime_roll() {
+        let block = test_utils::TEST_BLOCKS[0];
+        // 16 rolled versions are covered by 4 work items
+        let engine = VersionRolling::new(
+            Arc::new(NarrowRollingTestBlock {
+                block,
+                version_mask: 0x0001_e000,
+            }),
+            4,
+        );
+        let first_works = check_cached_work(&engine, block.time, 4);
+        let rolled_works = check_cached_work(&engine, block.time + 1, 4);
+        compare_midstates(&first_works, &rolled_works);
+        assert_eq!(midstate_cache_stats(&engine), (16, 16, 16, 0));
+
+        // midstates of the whole BIP320 space are cached as well
+        let engine = VersionRolling::new(Arc::new(RollingTestBlock(block)), 4);
+        let first_works = check_cached_work(&engine, block.time, 4);
+        // roll ntime and generate work for the same versions again
+        engine
+            .curr_range
+            .curr_index
+            .store(make_compound_index(1, 0), Ordering::Relaxed);
+        let rolled_works = check_cached_work(&engine, block.time + 1, 4);
+        compare_midstates(&first_works, &rolled_works);
+        assert_eq!(
+            midstate_cache_stats(&engine),
+            (MAX_MIDSTATE_CACHE_SIZE, 16, 16, 0)
+        );
+
+        // nothing is cached when ntime cannot be rolled
+        let engine = VersionRolling::new(
+            Arc::new(NtimeLimitedTestBlock {
+                block,
+                ntime_limit: 0,
+            }),
+            4,
+        );
+        engine.next_work().unwrap();
+        assert!(engine.midstate_cache.is_none());
+    }
+
     fn get_block_version(job: &Arc<test_utils::TestBlock>, version_index: u32) -> u32 {
         job.version() | (version_index << ii_bitcoin::BIP320_VERSION_SHIFT)
     }
@@ -315,7 +1056,7 @@ pub mod test {
     }
 
     fn make_compound_index(ntime_index: u32, version_index: u32) -> u32 {
-        assert!(ntime_index < ROLL_NTIME_SECONDS);
+        assert!(ntime_index <= DEFAULT_NTIME_ROLLING_LIMIT);
         assert!(version_index <= ii_bitcoin::BIP320_VERSION_MAX);
         ntime_index * BIP320_UPPER_BOUND_EXCLUSIVE_INDEX + version_index
     }
@@ -324,7 +1065,7 @@ pub mod test {
     fn test_ntime_increment() {
         // use first test block for job
         let job = Arc::new(test_utils::TEST_BLOCKS[0]);
-        let engine = VersionRolling::new(job.clone(), 1);
+        let engine = VersionRolling::new(Arc::new(RollingTestBlock(*job)), 1);
 
         // position ourselves to end of first version range
         const START_VERSION_INDEX: u32 = ii_bitcoin::BIP320_VERSION_MAX;
@@ -361,12 +1102,12 @@ pub mod test {
     fn test_exhausted_work() {
         // use first test block for job
         let job = Arc::new(test_utils::TEST_BLOCKS[0]);
-        let engine = VersionRolling::new(job.clone(), 1);
+        let engine = VersionRolling::new(Arc::new(RollingTestBlock(*job)), 1);
 
         // modify current version counter to decrease the search space
         // adn test only boundary values
         const START_VERSION_INDEX: u32 = ii_bitcoin::BIP320_VERSION_MAX - 1;
-        const START_NTIME_INDEX: u32 = ROLL_NTIME_SECONDS - 1;
+        const START_NTIME_INDEX: u32 = DEFAULT_NTIME_ROLLING_LIMIT;
         engine.curr_range.curr_index.store(
             make_compound_index(START_NTIME_INDEX, START_VERSION_INDEX),
             Ordering::Relaxed,
@@ -28,6 +28,7 @@ use futures::channel::mpsc;
 use futures::lock::Mutex;
 use ii_async_compat::futures;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use std::time;
 
@@ -63,13 +64,13 @@ where
         base_work_solver: Arc<T>,
         hierarchy_builder: Arc<dyn backend::HierarchyBuilder>,
         engine_receiver: EngineReceiver,
-        solution_sender: mpsc::UnboundedSender<Solution>,
+        solution_sender: SolutionSender,
     ) -> Self {
         Self {
             node: NodeType::Base(base_work_solver),
             path: vec![],
             engine_receiver,
-            solution_sender: SolutionSender(solution_sender),
+            solution_sender,
             hierarchy_builder,
         }
     }
@@ -172,6 +173,9 @@ pub struct Generator {
     work_solver: Arc<Mutex<Option<Weak<dyn node::WorkSolver>>>>,
     /// Source of trait objects that implement `WorkEngine` interface
     engine_receiver: EngineReceiver,
+    /// Path shared by generated work. Only weak reference is kept to prevent circular
+    /// dependency with the work solver and the path is rebuilt when all work has been dropped.
+    shared_path: Weak<node::Path>,
 }
 
 impl Generator {
@@ -184,9 +188,30 @@ impl Generator {
             path,
             work_solver,
             engine_receiver,
+            shared_path: Weak::new(),
         }
     }
 
+    /// Return path of all work solvers ending with the work solver associated with this generator
+    fn shared_path(&mut self, work_solver: &Arc<dyn node::WorkSolver>) -> node::SharedPath {
+        if let Some(shared_path) = self.shared_path.upgrade() {
+            return shared_path;
+        }
+        // Arc does not support dynamic casting to trait bounds so there must be used
+        // another Arc indirection with implemented `node::Info` trait.
+        // This blanket implementation can be found in the module `crate::node`:
+        // impl<T: ?Sized + Info> Info for Arc<T> {}
+        let shared_path: node::SharedPath = Arc::new(
+            self.path
+                .iter()
+                .chain(iter::once(work_solver))
+                .map(|node| Arc::new(node.clone()) as node::DynInfo)
+                .collect(),
+        );
+        self.shared_path = Arc::downgrade(&shared_path);
+        shared_path
+    }
+
     /// Loops until new work is available or no more `WorkEngines` are supplied (signals
     /// Generator shutdown)
     pub async fn generate(&mut self) -> Option<Assignment> {
@@ -223,6 +248,7 @@ impl Generator {
             };
             // determine how much work has been generated for current work assignment
             let work_amount = work.generated_work_amount() as u64;
+            let computed_midstates = work.computed_midstates() as u64;
             // account generated work on the client side
             if let Some(origin) = work.origin().upgrade() {
                 origin.client_stats().generated_work().add(work_amount);
@@ -234,14 +260,16 @@ impl Generator {
 
             // account generated work in all work solvers in the path
             let now = time::SystemTime::now();
+            let instant = time::Instant::now();
+            work.path = self.shared_path(&work_solver);
             for node in self.path.iter().chain(iter::once(&work_solver)) {
                 let work_solver_stats = node.work_solver_stats();
-                // Arc does not support dynamic casting to trait bounds so there must be used
-                // another Arc indirection with implemented `node::Info` trait.
-                // This blanket implementation can be found in the module `crate::node`:
-                // impl<T: ?Sized + Info> Info for Arc<T> {}
-                work.path.push(Arc::new(node.clone()));
                 work_solver_stats.generated_work().add(work_amount);
+                // midstates reused from the cache do not cost anything
+                work_solver_stats
+                    .midstates()
+                    .account(computed_midstates, instant)
+                    .await;
                 work_solver_stats.last_work_time().touch(now).await;
             }
             return Some(work);
@@ -249,14 +277,52 @@ impl Generator {
     }
 }
 
+/// Number of solutions submitted by mining backends which are waiting in the solution queue.
+/// The receiving side of the queue is responsible for decrementing it.
+#[derive(Debug, Default)]
+pub struct SolutionQueueDepth(AtomicUsize);
+
+impl SolutionQueueDepth {
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// This struct is to be passed to the underlying mining backend. It allows submission of
 /// `work::Solution`
 #[derive(Debug, Clone)]
-pub struct SolutionSender(mpsc::UnboundedSender<Solution>);
+pub struct SolutionSender {
+    sender: mpsc::UnboundedSender<Solution>,
+    queue_depth: Arc<SolutionQueueDepth>,
+}
 
 impl SolutionSender {
+    pub fn new(
+        sender: mpsc::UnboundedSender<Solution>,
+        queue_depth: Arc<SolutionQueueDepth>,
+    ) -> Self {
+        Self {
+            sender,
+            queue_depth,
+        }
+    }
+
     pub fn send(&self, solution: Solution) {
-        self.0
+        // The depth is incremented first so that it cannot underflow when the solution is
+        // received immediately
+        self.queue_depth.inc();
+        self.sender
             .unbounded_send(solution)
             .expect("solution queue send failed");
     }
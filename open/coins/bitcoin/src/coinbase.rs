@@ -0,0 +1,442 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Builder of coinbase transactions for mining directly from block templates (solo mining and
+//! job negotiation). The coinbase script contains the block height as required by BIP34,
+//! a placeholder for extranonce and optional tags. Blocks with segwit transactions also need
+//! the witness commitment output and the witness reserved value in the coinbase input.
+
+use super::{DHash, HashTrait, SHA256_DIGEST_SIZE};
+
+use std::error;
+use std::fmt;
+
+/// Coinbase script has to be at least 2 bytes and at most 100 bytes long (consensus rule)
+pub const MIN_SCRIPT_SIG_SIZE: usize = 2;
+pub const MAX_SCRIPT_SIG_SIZE: usize = 100;
+
+/// Maximal size of data which can be pushed to the stack with a single opcode
+const MAX_DIRECT_PUSH_SIZE: usize = 0x4b;
+const OP_0: u8 = 0x00;
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_1: u8 = 0x51;
+
+/// Version of the coinbase transaction
+const TX_VERSION: u32 = 1;
+/// Size of transaction field `nLockTime` which terminates its serialization
+const LOCK_TIME_SIZE: usize = 4;
+/// Segwit marker and flag inserted after transaction version
+const SEGWIT_MARKER_AND_FLAG: [u8; 2] = [0x00, 0x01];
+
+/// Append variable length integer used by Bitcoin serialization
+pub fn push_var_int(buffer: &mut Vec<u8>, value: usize) {
+    match value {
+        0..=0xfc => buffer.push(value as u8),
+        0xfd..=0xffff => {
+            buffer.push(0xfd);
+            buffer.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            buffer.push(0xfe);
+            buffer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            buffer.push(0xff);
+            buffer.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+}
+
+/// Append script operation that pushes `data` to the stack
+fn push_script_data(script: &mut Vec<u8>, data: &[u8]) {
+    if data.len() > MAX_DIRECT_PUSH_SIZE {
+        script.push(OP_PUSHDATA1);
+    }
+    script.push(data.len() as u8);
+    script.extend_from_slice(data);
+}
+
+/// Append script operation that pushes the block height to the stack in the same form as
+/// required by BIP34
+pub fn push_script_height(script: &mut Vec<u8>, height: u32) {
+    match height {
+        0 => script.push(OP_0),
+        1..=16 => script.push(OP_1 + (height - 1) as u8),
+        _ => {
+            let mut bytes: Vec<u8> = height
+                .to_le_bytes()
+                .iter()
+                .cloned()
+                .rev()
+                .skip_while(|byte| *byte == 0)
+                .collect();
+            bytes.reverse();
+            // keep the number positive
+            if bytes.last().expect("BUG: missing height byte") & 0x80 != 0 {
+                bytes.push(0);
+            }
+            push_script_data(script, &bytes);
+        }
+    }
+}
+
+/// Errors detected when the coinbase is assembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The coinbase script does not meet consensus limits on its size
+    ScriptSigSize(usize),
+    /// Pushed data (extranonce or tag) is too large for the coinbase script
+    PushSize(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ScriptSigSize(size) => write!(
+                f,
+                "coinbase script size {} is out of range {}..={}",
+                size, MIN_SCRIPT_SIG_SIZE, MAX_SCRIPT_SIG_SIZE
+            ),
+            Error::PushSize(size) => write!(f, "coinbase script cannot push {} bytes", size),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Builder of coinbase transaction paying the whole block reward to a single output
+#[derive(Debug, Clone)]
+pub struct Builder {
+    height: u32,
+    value: u64,
+    payout_script: Vec<u8>,
+    extranonce_size: usize,
+    tags: Vec<Vec<u8>>,
+    witness_commitment: Option<Vec<u8>>,
+}
+
+impl Builder {
+    /// Coinbase of block at `height` paying `value` (subsidy with fees) to `payout_script`
+    pub fn new(height: u32, value: u64, payout_script: &[u8]) -> Self {
+        Self {
+            height,
+            value,
+            payout_script: payout_script.to_vec(),
+            extranonce_size: 0,
+            tags: vec![],
+            witness_commitment: None,
+        }
+    }
+
+    /// Reserve space for extranonce of `size` bytes in the coinbase script
+    pub fn extranonce_size(mut self, size: usize) -> Self {
+        self.extranonce_size = size;
+        self
+    }
+
+    /// Append `tag` (e.g. name of the miner) to the coinbase script after extranonce
+    pub fn tag(mut self, tag: &[u8]) -> Self {
+        self.tags.push(tag.to_vec());
+        self
+    }
+
+    /// Add output with witness `commitment` script (`default_witness_commitment` from
+    /// `getblocktemplate`). The coinbase is then serialized with witness reserved value.
+    pub fn witness_commitment(mut self, commitment: &[u8]) -> Self {
+        self.witness_commitment = Some(commitment.to_vec());
+        self
+    }
+
+    /// Assemble coinbase transaction with extranonce placeholder
+    pub fn build(&self) -> Result<Template, Error> {
+        if let Some(tag) = self
+            .tags
+            .iter()
+            .find(|tag| tag.len() > u8::MAX as usize)
+        {
+            return Err(Error::PushSize(tag.len()));
+        }
+        if self.extranonce_size > MAX_DIRECT_PUSH_SIZE {
+            return Err(Error::PushSize(self.extranonce_size));
+        }
+
+        let mut script_sig = Vec::new();
+        push_script_height(&mut script_sig, self.height);
+        script_sig.push(self.extranonce_size as u8);
+        let extranonce_offset = script_sig.len();
+        script_sig.resize(extranonce_offset + self.extranonce_size, 0);
+        for tag in &self.tags {
+            push_script_data(&mut script_sig, tag);
+        }
+        if script_sig.len() < MIN_SCRIPT_SIG_SIZE || script_sig.len() > MAX_SCRIPT_SIG_SIZE {
+            return Err(Error::ScriptSigSize(script_sig.len()));
+        }
+
+        let mut prefix = TX_VERSION.to_le_bytes().to_vec();
+        // single input spending null outpoint
+        push_var_int(&mut prefix, 1);
+        prefix.extend_from_slice(&[0u8; SHA256_DIGEST_SIZE]);
+        prefix.extend_from_slice(&u32::MAX.to_le_bytes());
+        push_var_int(&mut prefix, script_sig.len());
+        prefix.extend_from_slice(&script_sig[..extranonce_offset]);
+
+        let mut suffix = script_sig[extranonce_offset + self.extranonce_size..].to_vec();
+        // input sequence
+        suffix.extend_from_slice(&u32::MAX.to_le_bytes());
+        push_var_int(&mut suffix, 1 + self.witness_commitment.is_some() as usize);
+        suffix.extend_from_slice(&self.value.to_le_bytes());
+        push_var_int(&mut suffix, self.payout_script.len());
+        suffix.extend_from_slice(&self.payout_script);
+        if let Some(commitment) = &self.witness_commitment {
+            suffix.extend_from_slice(&0u64.to_le_bytes());
+            push_var_int(&mut suffix, commitment.len());
+            suffix.extend_from_slice(commitment);
+        }
+        // lock time
+        suffix.extend_from_slice(&0u32.to_le_bytes());
+
+        Ok(Template {
+            prefix,
+            suffix,
+            extranonce_size: self.extranonce_size,
+            witness: self.witness_commitment.is_some(),
+        })
+    }
+}
+
+/// Coinbase transaction without extranonce. The serialization without witness is split at the
+/// extranonce placeholder in the same way as in Stratum V1 (`coinb1` and `coinb2`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    extranonce_size: usize,
+    witness: bool,
+}
+
+impl Template {
+    /// Part of the serialization before extranonce
+    #[inline]
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Part of the serialization after extranonce
+    #[inline]
+    pub fn suffix(&self) -> &[u8] {
+        &self.suffix
+    }
+
+    #[inline]
+    pub fn extranonce_size(&self) -> usize {
+        self.extranonce_size
+    }
+
+    /// Fill in the `extranonce` which must have the reserved size
+    pub fn coinbase(&self, extranonce: &[u8]) -> Coinbase {
+        assert_eq!(
+            extranonce.len(),
+            self.extranonce_size,
+            "BUG: extranonce size"
+        );
+        let legacy = [&self.prefix[..], extranonce, &self.suffix[..]].concat();
+
+        let witness = if self.witness {
+            let (body, lock_time) = legacy.split_at(legacy.len() - LOCK_TIME_SIZE);
+            let (version, body) = body.split_at(std::mem::size_of::<u32>());
+            // coinbase input has a single witness item with the witness reserved value
+            let mut reserved_value = Vec::new();
+            push_var_int(&mut reserved_value, 1);
+            push_var_int(&mut reserved_value, SHA256_DIGEST_SIZE);
+            reserved_value.extend_from_slice(&[0u8; SHA256_DIGEST_SIZE]);
+            Some(
+                [
+                    version,
+                    &SEGWIT_MARKER_AND_FLAG,
+                    body,
+                    &reserved_value,
+                    lock_time,
+                ]
+                .concat(),
+            )
+        } else {
+            None
+        };
+
+        Coinbase { legacy, witness }
+    }
+}
+
+/// Serialized coinbase transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coinbase {
+    /// Serialization without witness used for calculation of transaction ID
+    legacy: Vec<u8>,
+    /// Full serialization used in the block (only when the block has witness commitment)
+    witness: Option<Vec<u8>>,
+}
+
+impl Coinbase {
+    /// Transaction ID which is the leaf of merkle tree
+    pub fn txid(&self) -> DHash {
+        DHash::hash(&self.legacy)
+    }
+
+    /// Serialization without witness
+    #[inline]
+    pub fn legacy_serialization(&self) -> &[u8] {
+        &self.legacy
+    }
+
+    /// Serialization included in the block
+    #[inline]
+    pub fn block_serialization(&self) -> &[u8] {
+        self.witness.as_ref().unwrap_or(&self.legacy)
+    }
+
+    #[inline]
+    pub fn has_witness(&self) -> bool {
+        self.witness.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_script_height() {
+        for (height, expected) in &[
+            (0u32, &[0x00u8][..]),
+            (1, &[0x51]),
+            (16, &[0x60]),
+            (17, &[0x01, 0x11]),
+            (128, &[0x02, 0x80, 0x00]),
+            (500_000, &[0x03, 0x20, 0xa1, 0x07]),
+        ] {
+            let mut script = Vec::new();
+            push_script_height(&mut script, *height);
+            assert_eq!(&script[..], *expected, "height {}", height);
+        }
+    }
+
+    #[test]
+    fn test_var_int() {
+        for (value, expected) in &[
+            (0usize, &[0x00u8][..]),
+            (0xfc, &[0xfc]),
+            (0xfd, &[0xfd, 0xfd, 0x00]),
+            (0x1_0000, &[0xfe, 0x00, 0x00, 0x01, 0x00]),
+        ] {
+            let mut buffer = Vec::new();
+            push_var_int(&mut buffer, *value);
+            assert_eq!(&buffer[..], *expected, "value {}", value);
+        }
+    }
+
+    #[test]
+    fn test_coinbase_builder() {
+        let payout_script = [0x51u8];
+        let template = Builder::new(500_000, 50, &payout_script)
+            .extranonce_size(8)
+            .tag(b"/BOSminer/")
+            .build()
+            .expect("BUG: cannot build coinbase");
+        assert_eq!(template.extranonce_size(), 8);
+
+        let extranonce = 1u64.to_le_bytes();
+        let coinbase = template.coinbase(&extranonce);
+        assert!(!coinbase.has_witness());
+        assert_eq!(
+            coinbase.legacy_serialization(),
+            &[template.prefix(), &extranonce[..], template.suffix()].concat()[..]
+        );
+        assert_eq!(
+            coinbase.block_serialization(),
+            coinbase.legacy_serialization()
+        );
+
+        // version, input count, null outpoint and script length
+        let script_offset = 4 + 1 + SHA256_DIGEST_SIZE + 4 + 1;
+        let legacy = coinbase.legacy_serialization();
+        let script_sig = &legacy[script_offset..script_offset + legacy[script_offset - 1] as usize];
+        assert_eq!(
+            script_sig,
+            &[
+                &[0x03, 0x20, 0xa1, 0x07, 0x08][..],
+                &extranonce[..],
+                &[0x0a],
+                b"/BOSminer/",
+            ]
+            .concat()[..]
+        );
+        // different extranonce changes the transaction ID
+        assert_ne!(coinbase.txid(), template.coinbase(&[0; 8]).txid());
+
+        let witness_commitment = [0x6au8; 38];
+        let template = Builder::new(500_000, 50, &payout_script)
+            .extranonce_size(8)
+            .witness_commitment(&witness_commitment)
+            .build()
+            .expect("BUG: cannot build coinbase");
+        let coinbase = template.coinbase(&extranonce);
+        let witness = coinbase.block_serialization();
+        // marker with flag and witness reserved value
+        assert_eq!(&witness[4..6], &SEGWIT_MARKER_AND_FLAG);
+        assert_eq!(
+            witness.len(),
+            coinbase.legacy_serialization().len() + 2 + 2 + SHA256_DIGEST_SIZE
+        );
+        // the witness does not change the transaction ID
+        assert_eq!(
+            coinbase.txid(),
+            DHash::hash(coinbase.legacy_serialization())
+        );
+        assert_eq!(&witness[witness.len() - 4..], &[0; 4]);
+    }
+
+    #[test]
+    fn test_coinbase_builder_limits() {
+        // even empty extranonce is pushed so the shortest script is still valid
+        let template = Builder::new(1, 50, &[])
+            .build()
+            .expect("BUG: cannot build coinbase");
+        assert_eq!(
+            &template.prefix()[template.prefix().len() - 3..],
+            &[0x02, 0x51, 0x00]
+        );
+        assert_eq!(
+            Builder::new(500_000, 50, &[]).tag(&[0; 97]).build(),
+            Err(Error::ScriptSigSize(4 + 1 + 2 + 97))
+        );
+        assert_eq!(
+            Builder::new(500_000, 50, &[]).extranonce_size(76).build(),
+            Err(Error::PushSize(76))
+        );
+        // long tags are pushed with `OP_PUSHDATA1`
+        let template = Builder::new(500_000, 50, &[])
+            .tag(&[0; 80])
+            .build()
+            .expect("BUG: cannot build coinbase");
+        assert_eq!(&template.suffix()[..2], &[OP_PUSHDATA1, 80]);
+    }
+}
@@ -20,10 +20,13 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+pub mod coinbase;
+pub mod merkle;
+mod sha256_hw;
 pub mod test_blocks;
 
 // reexport Bitcoin test structures
-pub use test_blocks::{TestBlock, TEST_BLOCKS};
+pub use test_blocks::{TestBlock, TestBlockGenerator, TEST_BLOCKS};
 
 use packed_struct::prelude::*;
 use packed_struct_codegen::PackedStruct;
@@ -32,6 +35,7 @@ use bitcoin_hashes::{sha256, HashEngine};
 // reexport Bitcoin hash to remove dependency on bitcoin_hashes in other modules
 pub use bitcoin_hashes::{hex::FromHex, sha256d::Hash as DHash, Hash as HashTrait};
 
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto;
 use std::fmt;
 use std::mem::size_of;
@@ -81,6 +85,26 @@ pub struct BlockHeader {
 }
 
 impl BlockHeader {
+    /// Parse Bitcoin block header from its binary representation
+    #[inline]
+    pub fn from_bytes(bytes: &[u8; BLOCK_HEADER_SIZE]) -> Self {
+        Self::unpack(bytes).expect("BUG: all block header fields can be unpacked")
+    }
+
+    /// Parse Bitcoin block header from a slice which must be exactly 80 bytes long
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, bitcoin_hashes::Error> {
+        let bytes: &[u8; BLOCK_HEADER_SIZE] = bytes
+            .try_into()
+            .map_err(|_| bitcoin_hashes::Error::InvalidLength(BLOCK_HEADER_SIZE, bytes.len()))?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// Parse Bitcoin block header from hexadecimal string of its binary representation
+    pub fn from_hex(s: &str) -> Result<Self, bitcoin_hashes::Error> {
+        let bytes: Vec<u8> = FromHex::from_hex(s)?;
+        Self::from_slice(&bytes)
+    }
+
     /// Get binary representation of Bitcoin block header
     #[inline]
     pub fn into_bytes(self) -> [u8; BLOCK_HEADER_SIZE] {
@@ -94,9 +118,18 @@ impl BlockHeader {
     }
 
     /// Compute SHA256 midstate from first chunk of block header
+    /// CPU SHA256 extensions are used when they are available.
     pub fn midstate(&self) -> Midstate {
+        let block_bytes = self.into_bytes();
+        let chunk = block_bytes[..BLOCK_HEADER_CHUNK1_SIZE]
+            .try_into()
+            .expect("BUG: block header chunk size");
+        if let Some(midstate) = sha256_hw::midstate(chunk) {
+            return midstate.into();
+        }
+
         let mut engine = sha256::Hash::engine();
-        engine.input(&self.into_bytes()[..BLOCK_HEADER_CHUNK1_SIZE]);
+        engine.input(chunk);
         engine.midstate().into()
     }
 }
@@ -155,6 +188,167 @@ midstate_hex_fmt_impl!(Debug);
 midstate_hex_fmt_impl!(Display);
 midstate_hex_fmt_impl!(LowerHex);
 
+/// Key of cached midstate which consists of all fields in the first chunk of block header.
+/// The merkle root identifies both the job and the extranonce2 used in its coinbase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct MidstateKey {
+    previous_hash: [u8; 32],
+    merkle_root: [u8; 32],
+    version: u32,
+}
+
+impl From<&BlockHeader> for MidstateKey {
+    fn from(header: &BlockHeader) -> Self {
+        Self {
+            previous_hash: header.previous_hash,
+            merkle_root: header.merkle_root,
+            version: header.version,
+        }
+    }
+}
+
+/// Bounded LRU cache of computed midstates. Work which differs from the previous one only by
+/// rolled ntime shares the first chunk of block header with it so its midstates can be reused.
+/// The recency order is kept lazily: every access pushes a new stamp to the queue and stale
+/// stamps are skipped during eviction.
+#[derive(Clone)]
+pub struct MidstateCache {
+    capacity: usize,
+    entries: HashMap<MidstateKey, (Midstate, u64)>,
+    /// Access stamps ordered from the least recently used
+    order: VecDeque<(MidstateKey, u64)>,
+    next_stamp: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl MidstateCache {
+    /// Create empty cache holding at most `capacity` midstates. The memory is allocated as the
+    /// midstates are inserted.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_stamp: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Record access to the entry with `key` and return its new stamp
+    fn touch(&mut self, key: MidstateKey) -> u64 {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.order.push_back((key, stamp));
+        stamp
+    }
+
+    /// Drop stale stamps when the queue grows much larger than the number of entries
+    fn compact(&mut self) {
+        if self.order.len() <= 2 * self.capacity {
+            return;
+        }
+        let entries = &self.entries;
+        self.order
+            .retain(|(key, stamp)| entries.get(key).map(|entry| entry.1) == Some(*stamp));
+    }
+
+    /// Remove the least recently used entry
+    fn evict(&mut self) {
+        while let Some((key, stamp)) = self.order.pop_front() {
+            if self.entries.get(&key).map(|entry| entry.1) == Some(stamp) {
+                self.entries.remove(&key);
+                return;
+            }
+        }
+    }
+
+    /// Return cached midstate of block header
+    pub fn get(&mut self, header: &BlockHeader) -> Option<Midstate> {
+        let key = MidstateKey::from(header);
+        match self.entries.get(&key) {
+            Some(&(midstate, _)) => {
+                self.hits += 1;
+                let stamp = self.touch(key);
+                self.entries.insert(key, (midstate, stamp));
+                self.compact();
+                Some(midstate)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Store computed midstate of block header and evict the least recently used one when the
+    /// cache is full
+    pub fn insert(&mut self, header: &BlockHeader, midstate: Midstate) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = MidstateKey::from(header);
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        let stamp = self.touch(key);
+        self.entries.insert(key, (midstate, stamp));
+        self.compact();
+    }
+
+    /// Return midstate of block header from the cache or compute it
+    pub fn midstate(&mut self, header: &BlockHeader) -> Midstate {
+        if let Some(midstate) = self.get(header) {
+            return midstate;
+        }
+        let midstate = header.midstate();
+        self.insert(header, midstate);
+        midstate
+    }
+
+    /// Maximal number of cached midstates
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of cached midstates
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of midstates served from the cache
+    #[inline]
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups of midstates which were not cached
+    #[inline]
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+impl fmt::Debug for MidstateCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // do not print all cached entries
+        f.debug_struct("MidstateCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries.len())
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
 /// Helper trait used by `MidstateWords` for reading little endian midstate word from slice created
 /// from original midstate bytes
 pub trait FromMidstateWord<T> {
@@ -239,10 +433,51 @@ impl Target {
     /// Create target from difficulty used by pools
     /// This implementation can produce different results than targets for network difficulty.
     pub fn from_pool_difficulty(difficulty: usize) -> Self {
-        // TODO: use floating point division to get the same result expected by pool
         Self(Self::difficulty_1_target() / difficulty)
     }
 
+    /// Create target from fractional difficulty sent by pools (e.g. in `mining.set_difficulty`)
+    /// The floating point number is decomposed to its mantissa and binary exponent and the target
+    /// is computed with 512bit integer division so the result is exact without any rounding
+    /// other than truncation. The target saturates to the maximal value for very low difficulties.
+    pub fn from_float_difficulty(difficulty: f64) -> Result<Self, &'static str> {
+        if !difficulty.is_finite() || difficulty <= 0.0 {
+            return Err("difficulty has to be a positive finite number");
+        }
+
+        // decompose IEEE 754 double precision number: difficulty = mantissa * 2^exponent
+        let bits = difficulty.to_bits();
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+        let mut mantissa = bits & ((1 << 52) - 1);
+        let exponent = if biased_exponent == 0 {
+            // subnormal number
+            -1074
+        } else {
+            mantissa |= 1 << 52;
+            biased_exponent - 1075
+        };
+
+        let mut numerator = Self::u256_into_u512(Self::difficulty_1_target());
+        let mut denominator = uint::U512::from(mantissa);
+        if exponent < 0 {
+            // difficulty 1 target has 224 bits and the result would not fit into 256 bits anyway
+            if -exponent > 512 - 224 {
+                return Ok(Self(uint::U256::max_value()));
+            }
+            numerator <<= -exponent as usize;
+        } else {
+            // mantissa has 53 bits and the result would be zero anyway
+            if exponent > 512 - 53 {
+                return Ok(Self(uint::U256::zero()));
+            }
+            denominator <<= exponent as usize;
+        }
+
+        Ok(Self(Self::u512_into_u256_saturating(
+            numerator / denominator,
+        )))
+    }
+
     /// Create target from its compact representation used by Bitcoin protocol
     pub fn from_compact(bits: u32) -> Result<Self, &'static str> {
         // this code is inspired by `rust-bitcoin` crate implementation
@@ -274,6 +509,13 @@ impl Target {
         (Self::difficulty_1_target() / self.0).low_u64() as usize
     }
 
+    /// Convert target to fractional pool difficulty
+    /// The share difficulty of a solution is obtained by converting its hash to the target first.
+    /// Zero target has infinite difficulty.
+    pub fn get_float_difficulty(&self) -> f64 {
+        Self::u256_into_f64(Self::difficulty_1_target()) / Self::u256_into_f64(self.0)
+    }
+
     /// Convert target to its compact representation used by Bitcoin protocol
     pub fn into_compact(self) -> u32 {
         // this code is inspired by `rust-bitcoin` crate implementation
@@ -298,6 +540,28 @@ impl Target {
         self.0
     }
 
+    fn u256_into_u512(value: uint::U256) -> uint::U512 {
+        let mut bytes = [0u8; 2 * SHA256_DIGEST_SIZE];
+        value.to_little_endian(&mut bytes[..SHA256_DIGEST_SIZE]);
+        uint::U512::from_little_endian(&bytes)
+    }
+
+    fn u512_into_u256_saturating(value: uint::U512) -> uint::U256 {
+        let mut bytes = [0u8; 2 * SHA256_DIGEST_SIZE];
+        value.to_little_endian(&mut bytes);
+        if bytes[SHA256_DIGEST_SIZE..].iter().any(|&byte| byte != 0) {
+            uint::U256::max_value()
+        } else {
+            uint::U256::from_little_endian(&bytes[..SHA256_DIGEST_SIZE])
+        }
+    }
+
+    /// Convert 256bit number to the nearest lower floating point number
+    fn u256_into_f64(value: uint::U256) -> f64 {
+        let shift = value.bits().saturating_sub(64);
+        (value >> shift).low_u64() as f64 * 2f64.powi(shift as i32)
+    }
+
     /// Auxiliary function to check if the target is greater or equal to some 256bit number
     #[inline]
     fn is_greater_or_equal(&self, other: &Target) -> bool {
@@ -644,6 +908,99 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_block_header_parse() {
+        // binary representation of block 171874 (the first test block)
+        const HEADER_HEX: &str = "01000000b3aec10cfb91d39d005f1a1e2a127a81e4af245fc0c4b6d0880400\
+                                  00000000007e6ebbf2035cab9376138a28ef231f055fc9d6753fdb0f8309f3\
+                                  e9a02fa722ce1426674f87320b1a000187a2";
+        let block = &TEST_BLOCKS[0];
+        let header = BlockHeader::from_hex(HEADER_HEX).expect("BUG: invalid header");
+        assert_eq!(header.version, block.version);
+        assert_eq!(header.previous_hash, block.previous_hash.into_inner());
+        assert_eq!(header.merkle_root, block.merkle_root.into_inner());
+        assert_eq!(header.time, block.time);
+        assert_eq!(header.bits, block.bits);
+        assert_eq!(header.nonce, block.nonce);
+        assert_eq!(header.hash(), block.hash);
+        assert_eq!(header.into_bytes().to_hex(), HEADER_HEX);
+
+        // the serialization has to be reversible
+        for block in TEST_BLOCKS.iter() {
+            let header = BlockHeader::from_bytes(&block.header_bytes);
+            assert_eq!(header.into_bytes()[..], block.header_bytes[..]);
+            assert_eq!(header.hash(), block.hash);
+        }
+
+        match BlockHeader::from_slice(&[0; BLOCK_HEADER_SIZE - 1]) {
+            Err(bitcoin_hashes::Error::InvalidLength(expected, length)) => {
+                assert_eq!(
+                    (expected, length),
+                    (BLOCK_HEADER_SIZE, BLOCK_HEADER_SIZE - 1)
+                )
+            }
+            _ => panic!("BUG: header with invalid length has been parsed"),
+        }
+        assert!(BlockHeader::from_hex("invalid").is_err());
+    }
+
+    #[test]
+    fn test_midstate_cache() {
+        let mut cache = MidstateCache::new(2);
+        let header = BlockHeader {
+            version: TEST_BLOCKS[0].version,
+            previous_hash: TEST_BLOCKS[0].previous_hash.into_inner(),
+            merkle_root: TEST_BLOCKS[0].merkle_root.into_inner(),
+            time: TEST_BLOCKS[0].time,
+            ..Default::default()
+        };
+
+        assert_eq!(cache.midstate(&header), TEST_BLOCKS[0].midstate);
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        // rolled ntime does not change the first chunk so the midstate is reused
+        let mut rolled_ntime = header;
+        rolled_ntime.time += 1;
+        assert_eq!(cache.get(&rolled_ntime), Some(TEST_BLOCKS[0].midstate));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // different version is a different entry
+        let mut rolled_version = header;
+        rolled_version.version ^= 1 << BIP320_VERSION_SHIFT;
+        assert_eq!(cache.get(&rolled_version), None);
+        cache.insert(&rolled_version, rolled_version.midstate());
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.len(), 2);
+
+        // refresh the first entry so that `rolled_version` is the least recently used one
+        cache.midstate(&header);
+        let mut other_merkle_root = header;
+        other_merkle_root.merkle_root[0] ^= 0xff;
+        assert_eq!(
+            cache.midstate(&other_merkle_root),
+            other_merkle_root.midstate()
+        );
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.get(&header), Some(TEST_BLOCKS[0].midstate));
+        assert_eq!(cache.get(&rolled_version), None);
+        assert_eq!((cache.hits(), cache.misses()), (3, 4));
+
+        // many hits do not grow the cache over its capacity
+        for _ in 0..100 {
+            cache.midstate(&header);
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.order.len() <= 2 * cache.capacity());
+
+        // zero capacity disables caching
+        let mut cache = MidstateCache::new(0);
+        cache.midstate(&header);
+        cache.midstate(&header);
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+        assert!(cache.is_empty());
+    }
+
     #[test]
     fn test_block_header_midstate() {
         for block in TEST_BLOCKS.iter() {
@@ -755,6 +1112,83 @@ pub mod test {
         assert_eq!(TARGET_1_STR, format!("{:x}", difficulty_1_target));
     }
 
+    #[test]
+    fn test_target_float_difficulty() {
+        // integer difficulties give the same result as the integer conversion
+        for &difficulty in [1usize, 512, 1638, 8192, 65536, 1_000_000].iter() {
+            let target = Target::from_float_difficulty(difficulty as f64).unwrap();
+            assert_eq!(target, Target::from_pool_difficulty(difficulty));
+            assert_eq!(target.get_difficulty(), difficulty);
+            // the target is truncated so the difficulty is only approximately the same
+            let error = target.get_float_difficulty() / difficulty as f64 - 1.0;
+            assert!(error >= 0.0 && error < 1e-12);
+        }
+
+        // fractional difficulties are exact powers of two
+        let difficulty_1_target = Target::default().into_inner();
+        assert_eq!(
+            Target::from_float_difficulty(0.5).unwrap().into_inner(),
+            difficulty_1_target << 1
+        );
+        assert_eq!(
+            Target::from_float_difficulty(1.0 / 1024.0)
+                .unwrap()
+                .into_inner(),
+            difficulty_1_target << 10
+        );
+        assert_eq!(
+            Target::from_float_difficulty(0.5)
+                .unwrap()
+                .get_float_difficulty(),
+            0.5
+        );
+        // 1.5 = 3 / 2
+        assert_eq!(
+            Target::from_float_difficulty(1.5).unwrap().into_inner(),
+            (difficulty_1_target << 1) / 3
+        );
+
+        // extreme values saturate
+        assert_eq!(
+            Target::from_float_difficulty(std::f64::MIN_POSITIVE)
+                .unwrap()
+                .into_inner(),
+            uint::U256::max_value()
+        );
+        assert_eq!(
+            Target::from_float_difficulty(std::f64::MAX)
+                .unwrap()
+                .into_inner(),
+            uint::U256::zero()
+        );
+        assert_eq!(
+            Target::from(uint::U256::zero()).get_float_difficulty(),
+            std::f64::INFINITY
+        );
+
+        // invalid difficulties
+        assert!(Target::from_float_difficulty(0.0).is_err());
+        assert!(Target::from_float_difficulty(-1.0).is_err());
+        assert!(Target::from_float_difficulty(std::f64::NAN).is_err());
+        assert!(Target::from_float_difficulty(std::f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_share_difficulty() {
+        for block in TEST_BLOCKS.iter() {
+            // the share difficulty of the block is at least the network difficulty
+            let network_target = Target::from_compact(block.bits).unwrap();
+            let share_difficulty = Target::from(block.hash).get_float_difficulty();
+            assert!(share_difficulty >= network_target.get_float_difficulty());
+
+            // and the block meets the target derived from its share difficulty (with a margin for
+            // rounding of the floating point number)
+            let share_target =
+                Target::from_float_difficulty(share_difficulty * (1.0 - 1e-12)).unwrap();
+            assert!(block.hash.meets(&share_target));
+        }
+    }
+
     #[test]
     fn test_target_compact() {
         for block in TEST_BLOCKS.iter() {
@@ -836,4 +1270,66 @@ pub mod test {
         assert!(Shares::default() < shares);
         assert!(shares > Shares::default());
     }
+
+    #[test]
+    fn test_block_from_header() {
+        for block in TEST_BLOCKS.iter() {
+            let rebuilt = TestBlock::from_header(BlockHeader {
+                version: block.version,
+                previous_hash: block.previous_hash.into_inner(),
+                merkle_root: block.merkle_root.into_inner(),
+                time: block.time,
+                bits: block.bits,
+                nonce: block.nonce,
+            });
+
+            assert_eq!(rebuilt.hash, block.hash);
+            assert_eq!(rebuilt.hash_str, block.hash_str);
+            assert_eq!(rebuilt.midstate, block.midstate);
+            assert_eq!(rebuilt.midstate_str, block.midstate_str);
+            assert_eq!(rebuilt.target, block.target);
+            assert_eq!(rebuilt.header_bytes[..], block.header_bytes[..]);
+            assert_eq!(rebuilt.icarus_bytes[..], block.icarus_bytes[..]);
+        }
+    }
+
+    #[test]
+    fn test_block_generator() {
+        let blocks: Vec<_> = TestBlockGenerator::new(1).take(16).collect();
+        let default_target =
+            Target::from(uint::U256::max_value() >> TestBlockGenerator::DEFAULT_ZERO_BITS);
+
+        for block in blocks.iter() {
+            let header = BlockHeader {
+                version: block.version,
+                previous_hash: block.previous_hash.into_inner(),
+                merkle_root: block.merkle_root.into_inner(),
+                time: block.time,
+                bits: block.bits,
+                nonce: block.nonce,
+            };
+            assert_eq!(header.hash(), block.hash);
+            assert_eq!(header.midstate(), block.midstate);
+            assert!(block.hash.meets(&block.target));
+            assert!(block.target <= default_target);
+            assert_eq!(block.version & BIP320_VERSION_MASK, 0);
+        }
+
+        // the same seed yields the same blocks
+        let hashes: Vec<_> = TestBlockGenerator::new(1)
+            .take(16)
+            .map(|block| block.hash)
+            .collect();
+        assert!(blocks.iter().map(|block| block.hash).eq(hashes.into_iter()));
+        // and the blocks differ from each other and from another seed
+        assert_ne!(blocks[0].hash, blocks[1].hash);
+        assert_ne!(TestBlockGenerator::new(2).next_block().hash, blocks[0].hash);
+
+        // custom target
+        let target = Target::from(uint::U256::max_value() >> 12);
+        for block in TestBlockGenerator::new(3).target(target).take(4) {
+            assert!(block.target <= target);
+            assert!(block.hash.meets(&target));
+        }
+    }
 }
@@ -0,0 +1,135 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Merkle tree of block transactions as it is needed by mining. The miner knows only the coinbase
+//! transaction and the merkle branch (hashes of sibling nodes on the path from the coinbase leaf
+//! to the root) which is provided e.g. by Stratum V1 `mining.notify`.
+
+use super::{DHash, HashTrait};
+
+use bitcoin_hashes::HashEngine;
+
+/// Hash of a parent node computed from its `left` and `right` child
+fn parent(left: &DHash, right: &DHash) -> DHash {
+    let mut engine = DHash::engine();
+    engine.input(&left[..]);
+    engine.input(&right[..]);
+    DHash::from_engine(engine)
+}
+
+/// Compute merkle root by folding the coinbase transaction hash with the merkle branch.
+/// The coinbase is always the leftmost leaf so every branch hash is appended from the right.
+pub fn root<'a, I>(coinbase_hash: DHash, branch: I) -> DHash
+where
+    I: IntoIterator<Item = &'a DHash>,
+{
+    branch
+        .into_iter()
+        .fold(coinbase_hash, |node, sibling| parent(&node, sibling))
+}
+
+/// Compute merkle branch of the coinbase transaction from hashes of all other transactions in
+/// the block (in block order). The branch does not depend on the coinbase so it can be computed
+/// once per block template and reused for every coinbase variant (e.g. different extranonce).
+pub fn coinbase_branch(txids: &[DHash]) -> Vec<DHash> {
+    let mut branch = Vec::new();
+    // the first node of each level depends on the coinbase and it is not known here
+    let mut level = txids.to_vec();
+    while !level.is_empty() {
+        branch.push(level[0]);
+        // the remaining nodes start at odd position (after the unknown node) so the last one
+        // has no sibling when their count is even and it is paired with itself
+        if level.len() & 1 == 0 {
+            level.push(*level.last().expect("BUG: empty merkle level"));
+        }
+        level = level[1..]
+            .chunks(2)
+            .map(|pair| parent(&pair[0], &pair[1]))
+            .collect();
+    }
+    branch
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FromHex;
+
+    /// Reference computation of merkle root from all transaction hashes
+    fn full_tree_root(txids: &[DHash]) -> DHash {
+        let mut level = txids.to_vec();
+        while level.len() > 1 {
+            if level.len() % 2 != 0 {
+                level.push(*level.last().expect("BUG: empty merkle level"));
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| parent(&pair[0], &pair[1]))
+                .collect();
+        }
+        level[0]
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let coinbase_hash = DHash::hash(b"coinbase");
+        // block with coinbase only
+        assert_eq!(root(coinbase_hash, &[]), coinbase_hash);
+        assert!(coinbase_branch(&[]).is_empty());
+
+        for count in 1..20u32 {
+            let txids: Vec<_> = (0..count).map(|i| DHash::hash(&i.to_le_bytes())).collect();
+            let branch = coinbase_branch(&txids);
+            // the branch length is the depth of the tree
+            let leaves = count as usize + 1;
+            assert!(1 << branch.len() >= leaves);
+            assert!(1 << (branch.len() - 1) < leaves);
+
+            let all_txids: Vec<_> = std::iter::once(coinbase_hash)
+                .chain(txids.iter().cloned())
+                .collect();
+            assert_eq!(root(coinbase_hash, &branch), full_tree_root(&all_txids));
+        }
+    }
+
+    #[test]
+    fn test_merkle_root_block() {
+        // block 100000 with four transactions
+        let txids: Vec<_> = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]
+        .iter()
+        .map(|txid| DHash::from_hex(txid).expect("BUG: invalid txid"))
+        .collect();
+        let merkle_root =
+            DHash::from_hex("f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766")
+                .expect("BUG: invalid merkle root");
+
+        let branch = coinbase_branch(&txids[1..]);
+        assert_eq!(branch.len(), 2);
+        assert_eq!(branch[0], txids[1]);
+        assert_eq!(root(txids[0], &branch), merkle_root);
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! SHA256 compression function accelerated by CPU extensions (x86 SHA extensions and ARMv8
+//! cryptographic extensions in both AArch64 and AArch32 state). It is used only for midstate
+//! computation where exactly one chunk is compressed. The availability of the extensions is
+//! detected at runtime and the caller falls back to the software implementation when they are not
+//! present.
+//!
+//! ARMv7 cores (e.g. Cortex-A9 of the S9 control board) do not implement the extensions so the
+//! 32-bit ARM binary uses them only on ARMv8 cores running in AArch32 state.
+
+use super::{BLOCK_HEADER_CHUNK1_SIZE, SHA256_DIGEST_SIZE};
+
+/// SHA256 initial hash value
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA256 round constants
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Chunk of SHA256 message
+type Chunk = [u8; BLOCK_HEADER_CHUNK1_SIZE];
+
+/// Check if the CPU provides SHA256 extensions
+#[inline]
+pub fn is_supported() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        is_x86_feature_detected!("sha") && is_x86_feature_detected!("sse4.1")
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        std::arch::is_aarch64_feature_detected!("sha2")
+    }
+    #[cfg(target_arch = "arm")]
+    {
+        *arch::IS_SUPPORTED
+    }
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "arm"
+    )))]
+    {
+        false
+    }
+}
+
+/// Compute SHA256 midstate of the first chunk in the same format as `sha256::HashEngine`
+/// (state words in big endian). Returns `None` when the CPU does not support SHA256 extensions.
+pub fn midstate(chunk: &Chunk) -> Option<[u8; SHA256_DIGEST_SIZE]> {
+    if !is_supported() {
+        return None;
+    }
+
+    let mut state = H;
+    // the support of required CPU features has been checked above
+    unsafe {
+        arch::compress(&mut state, chunk);
+    }
+
+    let mut bytes = [0u8; SHA256_DIGEST_SIZE];
+    for (word, word_bytes) in state.iter().zip(bytes.chunks_mut(4)) {
+        word_bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    Some(bytes)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    use super::{Chunk, K};
+
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Compute next four words of the message schedule from the previous sixteen words
+    #[inline]
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    unsafe fn schedule(w0: __m128i, w1: __m128i, w2: __m128i, w3: __m128i) -> __m128i {
+        let t1 = _mm_sha256msg1_epu32(w0, w1);
+        let t2 = _mm_alignr_epi8(w3, w2, 4);
+        _mm_sha256msg2_epu32(_mm_add_epi32(t1, t2), w3)
+    }
+
+    /// Compress one chunk with SHA extensions
+    /// The instructions work with the state reordered to ABEF and CDGH words.
+    #[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+    pub unsafe fn compress(state: &mut [u32; 8], chunk: &Chunk) {
+        // shuffle mask converting big endian message words to little endian
+        let mask = _mm_set_epi64x(
+            0x0c0d_0e0f_0809_0a0bu64 as i64,
+            0x0405_0607_0001_0203u64 as i64,
+        );
+
+        let state_ptr = state.as_mut_ptr() as *mut __m128i;
+        let dcba = _mm_loadu_si128(state_ptr);
+        let efgh = _mm_loadu_si128(state_ptr.add(1));
+        let cdab = _mm_shuffle_epi32(dcba, 0xb1);
+        let efgh = _mm_shuffle_epi32(efgh, 0x1b);
+        let mut abef = _mm_alignr_epi8(cdab, efgh, 8);
+        let mut cdgh = _mm_blend_epi16(efgh, cdab, 0xf0);
+        let abef_save = abef;
+        let cdgh_save = cdgh;
+
+        let chunk_ptr = chunk.as_ptr() as *const __m128i;
+        let mut w = [_mm_setzero_si128(); 4];
+        for (i, w) in w.iter_mut().enumerate() {
+            *w = _mm_shuffle_epi8(_mm_loadu_si128(chunk_ptr.add(i)), mask);
+        }
+
+        // each iteration computes four rounds
+        for i in 0..16 {
+            if i >= 4 {
+                w[i % 4] = schedule(w[i % 4], w[(i + 1) % 4], w[(i + 2) % 4], w[(i + 3) % 4]);
+            }
+            let k = _mm_loadu_si128(K.as_ptr().add(4 * i) as *const __m128i);
+            let t = _mm_add_epi32(w[i % 4], k);
+            cdgh = _mm_sha256rnds2_epu32(cdgh, abef, t);
+            abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32(t, 0x0e));
+        }
+
+        abef = _mm_add_epi32(abef, abef_save);
+        cdgh = _mm_add_epi32(cdgh, cdgh_save);
+
+        let feba = _mm_shuffle_epi32(abef, 0x1b);
+        let dchg = _mm_shuffle_epi32(cdgh, 0xb1);
+        _mm_storeu_si128(state_ptr, _mm_blend_epi16(feba, dchg, 0xf0));
+        _mm_storeu_si128(state_ptr.add(1), _mm_alignr_epi8(dchg, feba, 8));
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use super::{Chunk, K};
+
+    use std::arch::aarch64::*;
+
+    /// Compress one chunk with ARMv8 cryptographic extensions
+    #[target_feature(enable = "sha2")]
+    pub unsafe fn compress(state: &mut [u32; 8], chunk: &Chunk) {
+        let mut abcd = vld1q_u32(state.as_ptr());
+        let mut efgh = vld1q_u32(state.as_ptr().add(4));
+        let abcd_save = abcd;
+        let efgh_save = efgh;
+
+        let mut w = [vdupq_n_u32(0); 4];
+        for (i, w) in w.iter_mut().enumerate() {
+            // message words are stored in big endian
+            *w = vreinterpretq_u32_u8(vrev32q_u8(vld1q_u8(chunk.as_ptr().add(16 * i))));
+        }
+
+        // each iteration computes four rounds
+        for i in 0..16 {
+            if i >= 4 {
+                w[i % 4] = vsha256su1q_u32(
+                    vsha256su0q_u32(w[i % 4], w[(i + 1) % 4]),
+                    w[(i + 2) % 4],
+                    w[(i + 3) % 4],
+                );
+            }
+            let t = vaddq_u32(w[i % 4], vld1q_u32(K.as_ptr().add(4 * i)));
+            let abcd_prev = abcd;
+            abcd = vsha256hq_u32(abcd_prev, efgh, t);
+            efgh = vsha256h2q_u32(efgh, abcd_prev, t);
+        }
+
+        vst1q_u32(state.as_mut_ptr(), vaddq_u32(abcd, abcd_save));
+        vst1q_u32(state.as_mut_ptr().add(4), vaddq_u32(efgh, efgh_save));
+    }
+}
+
+/// Auxiliary vector entry type with the second word of hardware capabilities (Linux)
+#[cfg(any(target_arch = "arm", test))]
+const AT_HWCAP2: usize = 26;
+/// Bit of `AT_HWCAP2` reporting SHA256 instructions in AArch32 state (Linux)
+#[cfg(any(target_arch = "arm", test))]
+const HWCAP2_SHA2: usize = 1 << 3;
+
+/// Find value of `AT_HWCAP2` in auxiliary vector `auxv` (as read from `/proc/self/auxv`) which
+/// consists of pairs of native words (type and value)
+#[cfg(any(target_arch = "arm", test))]
+fn auxv_hwcap2(auxv: &[u8]) -> Option<usize> {
+    use std::convert::TryInto;
+    use std::mem::size_of;
+
+    auxv.chunks_exact(2 * size_of::<usize>())
+        .map(|entry| {
+            let (key, value) = entry.split_at(size_of::<usize>());
+            (
+                usize::from_ne_bytes(key.try_into().expect("BUG: auxv entry size")),
+                usize::from_ne_bytes(value.try_into().expect("BUG: auxv entry size")),
+            )
+        })
+        .find(|(key, _)| *key == AT_HWCAP2)
+        .map(|(_, value)| value)
+}
+
+#[cfg(target_arch = "arm")]
+mod arch {
+    use super::{Chunk, K};
+
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        /// Feature detection macro of Rust standard library is not available for 32-bit ARM so
+        /// hardware capabilities are read from the auxiliary vector provided by the kernel
+        pub static ref IS_SUPPORTED: bool = std::fs::read("/proc/self/auxv")
+            .ok()
+            .and_then(|auxv| super::auxv_hwcap2(&auxv))
+            .map_or(false, |hwcap2| hwcap2 & super::HWCAP2_SHA2 != 0);
+    }
+
+    /// Four rounds with message words in `$w`. The state is held in q0 (ABCD) and q1 (EFGH) and
+    /// the next round constants are loaded from `{k}`.
+    macro_rules! rounds {
+        ($w:literal) => {
+            concat!(
+                "vld1.32 {{d6-d7}}, [{k}]!\n",
+                "vadd.i32 q2, ",
+                $w,
+                ", q3\n",
+                "vmov q3, q0\n",
+                "sha256h.32 q0, q1, q2\n",
+                "sha256h2.32 q1, q3, q2\n",
+            )
+        };
+    }
+
+    /// Compute next four words of the message schedule into `$w0` from the previous sixteen
+    /// words followed by four rounds with them
+    macro_rules! schedule_rounds {
+        ($w0:literal, $w1:literal, $w2:literal, $w3:literal) => {
+            concat!(
+                "sha256su0.32 ",
+                $w0,
+                ", ",
+                $w1,
+                "\n",
+                "sha256su1.32 ",
+                $w0,
+                ", ",
+                $w2,
+                ", ",
+                $w3,
+                "\n",
+                rounds!($w0),
+            )
+        };
+    }
+
+    /// Compress one chunk with ARMv8 cryptographic extensions in AArch32 state. Intrinsics of
+    /// the extensions are not available for 32-bit ARM so the whole compression is written in
+    /// assembly. The target does not have to enable NEON, only registers d0-d15 (q0-q7) are used
+    /// and callee saved d8-d15 are preserved on the stack.
+    pub unsafe fn compress(state: &mut [u32; 8], chunk: &Chunk) {
+        std::arch::asm!(
+            ".arch armv8-a",
+            ".fpu crypto-neon-fp-armv8",
+            "vpush {{d8-d15}}",
+            "vld1.32 {{d0-d3}}, [{state}]",
+            // message words are stored in big endian
+            "vld1.8 {{d8-d11}}, [{chunk}]!",
+            "vld1.8 {{d12-d15}}, [{chunk}]",
+            "vrev32.8 q4, q4",
+            "vrev32.8 q5, q5",
+            "vrev32.8 q6, q6",
+            "vrev32.8 q7, q7",
+            rounds!("q4"),
+            rounds!("q5"),
+            rounds!("q6"),
+            rounds!("q7"),
+            schedule_rounds!("q4", "q5", "q6", "q7"),
+            schedule_rounds!("q5", "q6", "q7", "q4"),
+            schedule_rounds!("q6", "q7", "q4", "q5"),
+            schedule_rounds!("q7", "q4", "q5", "q6"),
+            schedule_rounds!("q4", "q5", "q6", "q7"),
+            schedule_rounds!("q5", "q6", "q7", "q4"),
+            schedule_rounds!("q6", "q7", "q4", "q5"),
+            schedule_rounds!("q7", "q4", "q5", "q6"),
+            schedule_rounds!("q4", "q5", "q6", "q7"),
+            schedule_rounds!("q5", "q6", "q7", "q4"),
+            schedule_rounds!("q6", "q7", "q4", "q5"),
+            schedule_rounds!("q7", "q4", "q5", "q6"),
+            // add the original state
+            "vld1.32 {{d4-d7}}, [{state}]",
+            "vadd.i32 q0, q0, q2",
+            "vadd.i32 q1, q1, q3",
+            "vst1.32 {{d0-d3}}, [{state}]",
+            "vpop {{d8-d15}}",
+            state = in(reg) state.as_mut_ptr(),
+            chunk = inout(reg) chunk.as_ptr() => _,
+            k = inout(reg) K.as_ptr() => _,
+            out("d0") _,
+            out("d1") _,
+            out("d2") _,
+            out("d3") _,
+            out("d4") _,
+            out("d5") _,
+            out("d6") _,
+            out("d7") _,
+        );
+    }
+}
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "arm"
+)))]
+mod arch {
+    use super::Chunk;
+
+    pub unsafe fn compress(_state: &mut [u32; 8], _chunk: &Chunk) {
+        unreachable!("BUG: SHA256 extensions are not supported on this architecture")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{HashTrait, TestBlockGenerator, TEST_BLOCKS};
+
+    use bitcoin_hashes::{sha256, HashEngine};
+
+    use std::convert::TryInto;
+
+    fn sw_midstate(chunk: &Chunk) -> [u8; SHA256_DIGEST_SIZE] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(chunk);
+        engine.midstate()
+    }
+
+    #[test]
+    fn test_auxv_hwcap2() {
+        let auxv: Vec<u8> = [(16, 0x0003_b0d6), (AT_HWCAP2, 0x0000_001f), (0, 0)]
+            .iter()
+            .flat_map(|&(key, value): &(usize, usize)| {
+                key.to_ne_bytes()
+                    .iter()
+                    .chain(value.to_ne_bytes().iter())
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let hwcap2 = auxv_hwcap2(&auxv).expect("BUG: missing AT_HWCAP2");
+        assert_eq!(hwcap2, 0x1f);
+        assert_ne!(hwcap2 & HWCAP2_SHA2, 0);
+
+        // kernels of ARMv7 cores do not provide the entry at all
+        assert_eq!(auxv_hwcap2(&auxv[..2 * std::mem::size_of::<usize>()]), None);
+        assert_eq!(auxv_hwcap2(&[]), None);
+    }
+
+    #[test]
+    fn test_midstate() {
+        if !is_supported() {
+            assert!(midstate(&[0; BLOCK_HEADER_CHUNK1_SIZE]).is_none());
+            return;
+        }
+
+        let random_blocks = TestBlockGenerator::new(0).take(64);
+        for block in TEST_BLOCKS.iter().cloned().chain(random_blocks) {
+            let chunk: Chunk = block.header_bytes[..BLOCK_HEADER_CHUNK1_SIZE]
+                .try_into()
+                .expect("BUG: chunk size");
+
+            assert_eq!(midstate(&chunk), Some(sw_midstate(&chunk)));
+            assert_eq!(midstate(&chunk).map(Into::into), Some(block.midstate));
+        }
+
+        // chunks with all bits set and cleared
+        for &byte in [0x00, 0xff].iter() {
+            let chunk = [byte; BLOCK_HEADER_CHUNK1_SIZE];
+            assert_eq!(midstate(&chunk), Some(sw_midstate(&chunk)));
+        }
+    }
+}
@@ -20,7 +20,9 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
-use super::{DHash, FromHex, Midstate, Target};
+use super::{BlockHeader, DHash, FromHex, HashTrait, MeetsTarget, Midstate, Target};
+
+use bitcoin_hashes::{hex::ToHex, sha256};
 
 use lazy_static::lazy_static;
 
@@ -55,21 +57,68 @@ impl TestBlock {
         time: u32,
         bits: u32,
         nonce: u32,
-        header_bytes: [u8; 80],
         icarus_bytes: [u8; 64],
     ) -> Self {
+        let previous_hash = DHash::from_hex(previous_hash).expect("parse hex");
+        let merkle_root = DHash::from_hex(merkle_root).expect("parse hex");
+        let header = BlockHeader {
+            version,
+            previous_hash: previous_hash.into_inner(),
+            merkle_root: merkle_root.into_inner(),
+            time,
+            bits,
+            nonce,
+        };
+
         Self {
             hash: DHash::from_hex(hash).expect("parse hex"),
             hash_str: hash,
             midstate: Midstate::from_hex(midstate).expect("parse hex"),
             midstate_str: midstate,
             version,
-            previous_hash: DHash::from_hex(previous_hash).expect("parse hex"),
-            merkle_root: DHash::from_hex(merkle_root).expect("parse hex"),
+            previous_hash,
+            merkle_root,
             time,
             bits,
             target: Target::from_compact(bits).expect("network difficulty"),
             nonce,
+            header_bytes: header.into_bytes(),
+            icarus_bytes,
+        }
+    }
+}
+
+impl TestBlock {
+    /// Build test block from a solved `header`. All derived fields are computed from the header.
+    /// The hexadecimal strings are leaked to satisfy the `'static` lifetime of the fields which is
+    /// fine for test fixtures.
+    pub fn from_header(header: BlockHeader) -> Self {
+        let hash = header.hash();
+        let midstate = header.midstate();
+        let header_bytes = header.into_bytes();
+
+        // Icarus work consists of midstate words in reverse order followed by the last 12 bytes
+        // of the header (merkle root tail, time and bits) in reverse order
+        let mut icarus_bytes = [0u8; 64];
+        for (i, word) in midstate.as_ref().chunks(4).rev().enumerate() {
+            icarus_bytes[i * 4..(i + 1) * 4].copy_from_slice(word);
+        }
+        for (i, chunk) in header_bytes[64..76].chunks(4).rev().enumerate() {
+            icarus_bytes[52 + i * 4..52 + (i + 1) * 4].copy_from_slice(chunk);
+        }
+
+        Self {
+            hash,
+            hash_str: Box::leak(hash.to_hex().into_boxed_str()),
+            midstate,
+            midstate_str: Box::leak(midstate.to_hex().into_boxed_str()),
+            version: header.version,
+            previous_hash: DHash::from_slice(&header.previous_hash).expect("BUG: hash size"),
+            merkle_root: DHash::from_slice(&header.merkle_root).expect("BUG: hash size"),
+            time: header.time,
+            bits: header.bits,
+            target: Target::from_compact(header.bits).expect("BUG: invalid compact target"),
+            nonce: header.nonce,
             header_bytes,
             icarus_bytes,
         }
@@ -95,13 +144,6 @@ lazy_static! {
             1332160020,
             436941447,
             2726756608,
-            [ 0x01, 0x00, 0x00, 0x00, 0xb3, 0xae, 0xc1, 0x0c, 0xfb, 0x91, 0xd3, 0x9d, 0x00, 0x5f,
-              0x1a, 0x1e, 0x2a, 0x12, 0x7a, 0x81, 0xe4, 0xaf, 0x24, 0x5f, 0xc0, 0xc4, 0xb6, 0xd0,
-              0x88, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7e, 0x6e, 0xbb, 0xf2, 0x03, 0x5c,
-              0xab, 0x93, 0x76, 0x13, 0x8a, 0x28, 0xef, 0x23, 0x1f, 0x05, 0x5f, 0xc9, 0xd6, 0x75,
-              0x3f, 0xdb, 0x0f, 0x83, 0x09, 0xf3, 0xe9, 0xa0, 0x2f, 0xa7, 0x22, 0xce, 0x14, 0x26,
-              0x67, 0x4f, 0x87, 0x32, 0x0b, 0x1a, 0x00, 0x01, 0x87, 0xa2,
-            ],
             [ 0x46, 0x79, 0xba, 0x4e, 0xc9, 0x98, 0x76, 0xbf, 0x4b, 0xfe, 0x08, 0x60, 0x82, 0xb4,
               0x00, 0x25, 0x4d, 0xf6, 0xc3, 0x56, 0x45, 0x14, 0x71, 0x13, 0x9a, 0x3a, 0xfa, 0x71,
               0xe4, 0x8f, 0x54, 0x4a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -121,13 +163,6 @@ lazy_static! {
             1305998791,
             440711666,
             2504433986,
-            [ 0x01, 0x00, 0x00, 0x00, 0x81, 0xcd, 0x02, 0xab, 0x7e, 0x56, 0x9e, 0x8b, 0xcd, 0x93,
-              0x17, 0xe2, 0xfe, 0x99, 0xf2, 0xde, 0x44, 0xd4, 0x9a, 0xb2, 0xb8, 0x85, 0x1b, 0xa4,
-              0xa3, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe3, 0x20, 0xb6, 0xc2, 0xff, 0xfc,
-              0x8d, 0x75, 0x04, 0x23, 0xdb, 0x8b, 0x1e, 0xb9, 0x42, 0xae, 0x71, 0x0e, 0x95, 0x1e,
-              0xd7, 0x97, 0xf7, 0xaf, 0xfc, 0x88, 0x92, 0xb0, 0xf1, 0xfc, 0x12, 0x2b, 0xc7, 0xf5,
-              0xd7, 0x4d, 0xf2, 0xb9, 0x44, 0x1a, 0x42, 0xa1, 0x46, 0x95,
-            ],
             [ 0x7a, 0x78, 0xda, 0x2d, 0xcd, 0x5b, 0xce, 0x69, 0x2f, 0x56, 0xa9, 0xda, 0x07, 0xe8,
               0x6e, 0x37, 0x2d, 0x28, 0x10, 0xa0, 0x16, 0xe6, 0x69, 0xba, 0x05, 0xc5, 0x67, 0x13,
               0x95, 0x24, 0xc5, 0x93, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -146,13 +181,6 @@ lazy_static! {
             1555576766,
             388761373,
             4115486663,
-            [ 0x00, 0x00, 0x00, 0x20, 0x5d, 0x72, 0xc1, 0x7e, 0x52, 0x80, 0x4a, 0x88, 0xcb, 0xda,
-              0xf6, 0x4f, 0x1c, 0xab, 0xf2, 0xdf, 0x94, 0x3c, 0x5b, 0x18, 0x17, 0x2b, 0x26, 0x00,
-              0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x14, 0xef, 0x21, 0x43, 0xf9, 0xec,
-              0x66, 0x16, 0x82, 0xe8, 0xbd, 0xb0, 0xf3, 0x27, 0x93, 0x7c, 0x06, 0x13, 0x98, 0x02,
-              0xda, 0x1f, 0x7c, 0x0c, 0x77, 0x30, 0xd0, 0xd1, 0x04, 0x9e, 0xee, 0x70, 0xbe, 0x37,
-              0xb8, 0x5c, 0x1d, 0x07, 0x2c, 0x17, 0xc7, 0x57, 0x4d, 0xf5,
-            ],
             [ 0x60, 0xf7, 0x2b, 0x57, 0x40, 0x30, 0x1c, 0x8a, 0xc3, 0x2d, 0xb4, 0xa8, 0xf6, 0xec,
               0xa1, 0x29, 0xac, 0xbb, 0x1c, 0x5a, 0x84, 0xcf, 0x59, 0x0f, 0x5d, 0xfc, 0x12, 0x23,
               0x9a, 0x83, 0x78, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -162,3 +190,81 @@ lazy_static! {
         )
     ];
 }
+
+/// Generator of random test blocks with a known nonce used for property based testing. The
+/// headers are derived deterministically from the seed so any failure can be reproduced. The
+/// nonce is brute forced to meet a low target (8 leading zero bits by default) which makes the
+/// generation cheap.
+#[derive(Debug, Clone)]
+pub struct TestBlockGenerator {
+    seed: u64,
+    counter: u64,
+    bits: u32,
+}
+
+impl TestBlockGenerator {
+    /// Number of leading zero bits of the default target
+    pub const DEFAULT_ZERO_BITS: usize = 8;
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            counter: 0,
+            bits: Target::from(uint::U256::max_value() >> Self::DEFAULT_ZERO_BITS).into_compact(),
+        }
+    }
+
+    /// Use `target` for all following blocks. The precision of the target is reduced to its compact
+    /// representation stored in the header.
+    pub fn target(mut self, target: Target) -> Self {
+        self.bits = target.into_compact();
+        self
+    }
+
+    /// Pseudo random bytes derived from the seed
+    fn next_bytes(&mut self) -> [u8; 32] {
+        let mut input = [0u8; 16];
+        input[..8].copy_from_slice(&self.seed.to_le_bytes());
+        input[8..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        sha256::Hash::hash(&input).into_inner()
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let bytes = self.next_bytes();
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    pub fn next_block(&mut self) -> TestBlock {
+        let target = Target::from_compact(self.bits).expect("BUG: invalid compact target");
+        let mut header = BlockHeader {
+            // BIP9 version with cleared bits which are allowed to be rolled so the first rolled
+            // version corresponds to the block
+            version: 0x20000000,
+            previous_hash: self.next_bytes(),
+            merkle_root: self.next_bytes(),
+            // any time after the genesis block
+            time: 1_231_006_505 + self.next_u32() % 0x4000_0000,
+            bits: self.bits,
+            nonce: self.next_u32(),
+        };
+        loop {
+            for _ in 0..=u32::MAX {
+                if header.hash().meets(&target) {
+                    return TestBlock::from_header(header);
+                }
+                header.nonce = header.nonce.wrapping_add(1);
+            }
+            // the whole nonce space has been exhausted (possible only for very low targets)
+            header.time += 1;
+        }
+    }
+}
+
+impl Iterator for TestBlockGenerator {
+    type Item = TestBlock;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_block())
+    }
+}
@@ -50,10 +50,12 @@ const REMOVE_POOL: &str = "removepool";
 const STATS: &str = "stats";
 const ESTATS: &str = "estats";
 const CHECK: &str = "check";
+const PRIVILEGED: &str = "privileged";
 const COIN: &str = "coin";
 const ASC_COUNT: &str = "asccount";
 const ASC: &str = "asc";
 const LCD: &str = "lcd";
+const ZERO: &str = "zero";
 
 // List of all standard commands which can be optionally implemented.
 pub const DEVDETAILS: &str = "devdetails";
@@ -62,6 +64,14 @@ pub const DEVDETAILS: &str = "devdetails";
 pub const TEMPCTRL: &str = "tempctrl";
 pub const TEMPS: &str = "temps";
 pub const FANS: &str = "fans";
+pub const HASHBOARDS: &str = "hashboards";
+pub const CHIPREGS: &str = "chipregs";
+pub const CHIPREGSET: &str = "chipregset";
+pub const HASHBOARD_START: &str = "hashboardstart";
+pub const HASHBOARD_STOP: &str = "hashboardstop";
+pub const PROFILES: &str = "profiles";
+pub const PROFILE_SET: &str = "profileset";
+pub const PSU: &str = "psu";
 
 pub type Result<T> = std::result::Result<T, response::Error>;
 /// Type describing command table
@@ -99,6 +109,7 @@ pub trait Handler: Send + Sync {
     async fn handle_asc_count(&self) -> Result<response::AscCount>;
     async fn handle_asc(&self, parameter: Option<&json::Value>) -> Result<response::Asc>;
     async fn handle_lcd(&self) -> Result<response::Lcd>;
+    async fn handle_zero(&self, parameter: Option<&json::Value>) -> Result<response::Zero>;
 }
 
 /// Holds an incoming API command
@@ -125,6 +136,7 @@ pub enum HandlerType {
     Parameter(ParameterHandler),
     Version,
     Check,
+    Privileged,
 }
 
 impl HandlerType {
@@ -134,6 +146,7 @@ impl HandlerType {
             HandlerType::Parameter(_) => true,
             HandlerType::Version => false,
             HandlerType::Check => true,
+            HandlerType::Privileged => false,
         }
     }
 }
@@ -253,6 +266,8 @@ where
             Box::new(|command, parameter| Self::check_pool_id(command, parameter));
         let check_asc: ParameterCheckHandler =
             Box::new(|command, parameter| Self::check_asc(command, parameter));
+        let check_zero: ParameterCheckHandler =
+            Box::new(|command, parameter| Self::check_zero(command, parameter));
 
         let mut commands = commands![
             // generic commands
@@ -272,9 +287,11 @@ where
             (ASC_COUNT: ParameterLess -> handler.handle_asc_count),
             (ASC: Parameter(check_asc) -> handler.handle_asc),
             (LCD: ParameterLess -> handler.handle_lcd),
+            (ZERO: Parameter(check_zero) -> handler.handle_zero),
             // special built-in commands
             (VERSION: BuiltIn(Version)),
-            (CHECK: BuiltIn(Check))
+            (CHECK: BuiltIn(Check)),
+            (PRIVILEGED: BuiltIn(Privileged))
         ];
 
         if let Some(custom_commands) = custom_commands.into() {
@@ -325,6 +342,18 @@ where
         }
     }
 
+    fn check_zero(_command: &str, parameter: &Option<&json::Value>) -> Result<()> {
+        const ARG_COUNT: usize = 2;
+        match parameter {
+            Some(json::Value::String(value))
+                if value.splitn(ARG_COUNT, super::PARAMETER_DELIMITER).count() == ARG_COUNT =>
+            {
+                Ok(())
+            }
+            _ => Err(response::ErrorCode::MissingZeroParameters.into()),
+        }
+    }
+
     fn handle_version(&self) -> Result<response::Version> {
         Ok(response::Version {
             signature: self.miner_signature.to_string(),
@@ -374,6 +403,8 @@ where
                             HandlerType::Check => {
                                 self.handle_check(parameter).map(|response| response.into())
                             }
+                            // Every client of the API has full access
+                            HandlerType::Privileged => Ok(response::Privileged.into()),
                         },
                         Err(response) => Err(response),
                     }
@@ -113,6 +113,7 @@ pub enum StatusCode {
     Version = 22,
     SwitchPool = 27,
     MineConfig = 33,
+    Privileged = 46,
     EnablePool = 47,
     DisablePool = 48,
     AddPool = 55,
@@ -124,11 +125,21 @@ pub enum StatusCode {
     AscCount = 104,
     Asc = 106,
     Lcd = 125,
+    ZeroSummary = 96,
+    ZeroNoSummary = 97,
 
     // extended command status codes
     TempCtrl = 200,
     Temps = 201,
     Fans = 202,
+    Hashboards = 203,
+    ChipRegisters = 204,
+    ChipRegisterSet = 205,
+    HashboardStart = 206,
+    HashboardStop = 207,
+    Profiles = 208,
+    ProfileSet = 209,
+    Psu = 210,
 
     // info status codes
     PoolAlreadyEnabled = 49,
@@ -145,6 +156,8 @@ pub enum StatusCode {
     MissingAddPoolDetails = 52,
     InvalidAddPoolDetails = 53,
     MissingCheckCmd = 71,
+    MissingZeroParameters = 94,
+    InvalidZeroParameter = 95,
     InvalidAscId = 107,
 
     // special value which is added to the custom status codes
@@ -201,6 +214,8 @@ pub enum ErrorCode {
     InvalidAddPoolDetails(String),
     MissingCheckCmd,
     InvalidAscId(i32, i32),
+    MissingZeroParameters,
+    InvalidZeroParameter(String),
 }
 
 impl From<ErrorCode> for Dispatch {
@@ -303,6 +318,14 @@ impl From<ErrorCode> for Error {
                     idx_requested, idx_last
                 ),
             ),
+            ErrorCode::MissingZeroParameters => (
+                StatusCode::MissingZeroParameters,
+                "Missing zero parameters".to_string(),
+            ),
+            ErrorCode::InvalidZeroParameter(parameter) => (
+                StatusCode::InvalidZeroParameter,
+                format!("Invalid zero parameter '{}'", parameter),
+            ),
         };
 
         Self {
@@ -639,6 +662,18 @@ impl From<Version> for Dispatch {
     }
 }
 
+pub struct Privileged;
+
+impl From<Privileged> for Dispatch {
+    fn from(_: Privileged) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::Privileged.into(),
+            "Privileged access OK".to_string(),
+            None,
+        )
+    }
+}
+
 pub struct SwitchPool {
     pub idx: usize,
     pub url: String,
@@ -1011,6 +1046,26 @@ impl From<Lcd> for Dispatch {
     }
 }
 
+pub struct Zero {
+    pub which: String,
+    pub summary: bool,
+}
+
+impl From<Zero> for Dispatch {
+    fn from(zero: Zero) -> Self {
+        let (code, summary) = if zero.summary {
+            (StatusCode::ZeroSummary, "with")
+        } else {
+            (StatusCode::ZeroNoSummary, "without")
+        };
+        Dispatch::from_success::<()>(
+            code.into(),
+            format!("Zeroed {} stats {} summary", zero.which, summary),
+            None,
+        )
+    }
+}
+
 pub struct Body<S: Serialize> {
     pub name: &'static str,
     pub list: Vec<S>,
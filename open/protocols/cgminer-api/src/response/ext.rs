@@ -125,3 +125,216 @@ impl From<Fans> for Dispatch {
         )
     }
 }
+
+/// Hashboard connector of the control board
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct Hashboard {
+    #[serde(rename = "HASHBOARD")]
+    pub idx: i32,
+    #[serde(rename = "ID")]
+    pub id: i32,
+    /// Hashboard is plugged into the connector
+    #[serde(rename = "Present")]
+    pub present: bool,
+    /// Hashboard is enabled in the configuration
+    #[serde(rename = "Enabled")]
+    pub enabled: bool,
+    /// Hashboard is currently mining
+    #[serde(rename = "Running")]
+    pub running: bool,
+    /// Number of chips detected on the hashboard when it was started the last time
+    #[serde(rename = "Chips")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chips: Option<u32>,
+}
+
+pub struct Hashboards {
+    pub list: Vec<Hashboard>,
+}
+
+impl From<Hashboards> for Dispatch {
+    fn from(hashboards: Hashboards) -> Self {
+        let present_count = hashboards.list.iter().filter(|h| h.present).count();
+        Dispatch::from_success(
+            StatusCode::Hashboards.into(),
+            format!("{} Hashboard(s)", present_count),
+            Some(Body {
+                name: "HASHBOARDS",
+                list: hashboards.list,
+            }),
+        )
+    }
+}
+
+/// Raw value of a chip register read for diagnostics
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct ChipRegister {
+    #[serde(rename = "REGISTER")]
+    pub idx: i32,
+    /// Register name (empty if the register is not known)
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Register number
+    #[serde(rename = "Register")]
+    pub register: u32,
+    #[serde(rename = "Value")]
+    pub value: u32,
+}
+
+pub struct ChipRegisters {
+    /// Hashboard connector number
+    pub hashboard_id: i32,
+    /// Address of the chip on the hash chain
+    pub chip: u32,
+    pub list: Vec<ChipRegister>,
+}
+
+impl From<ChipRegisters> for Dispatch {
+    fn from(chip_registers: ChipRegisters) -> Self {
+        Dispatch::from_success(
+            StatusCode::ChipRegisters.into(),
+            format!(
+                "{} Register(s) of chip {} on hashboard {}",
+                chip_registers.list.len(),
+                chip_registers.chip,
+                chip_registers.hashboard_id
+            ),
+            Some(Body {
+                name: "CHIPREGS",
+                list: chip_registers.list,
+            }),
+        )
+    }
+}
+
+/// Result of writing a chip register
+pub struct ChipRegisterSet {
+    pub hashboard_id: i32,
+    pub chip: u32,
+    /// Register value read back after the write
+    pub register: ChipRegister,
+}
+
+impl From<ChipRegisterSet> for Dispatch {
+    fn from(chip_register_set: ChipRegisterSet) -> Self {
+        Dispatch::from_success(
+            StatusCode::ChipRegisterSet.into(),
+            format!(
+                "Register {:#04x} of chip {} on hashboard {} set",
+                chip_register_set.register.register,
+                chip_register_set.chip,
+                chip_register_set.hashboard_id
+            ),
+            Some(Body {
+                name: "CHIPREGSET",
+                list: vec![chip_register_set.register],
+            }),
+        )
+    }
+}
+
+pub struct HashboardStart {
+    /// Hashboard connector number
+    pub id: i32,
+}
+
+impl From<HashboardStart> for Dispatch {
+    fn from(hashboard_start: HashboardStart) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::HashboardStart.into(),
+            format!("Starting hashboard {}", hashboard_start.id),
+            None,
+        )
+    }
+}
+
+pub struct HashboardStop {
+    /// Hashboard connector number
+    pub id: i32,
+}
+
+impl From<HashboardStop> for Dispatch {
+    fn from(hashboard_stop: HashboardStop) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::HashboardStop.into(),
+            format!("Hashboard {} stopped", hashboard_stop.id),
+            None,
+        )
+    }
+}
+
+/// Performance profile from the configuration
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct Profile {
+    #[serde(rename = "PROFILE")]
+    pub idx: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Profile is used by all running hashboards
+    #[serde(rename = "Active")]
+    pub active: bool,
+}
+
+pub struct Profiles {
+    pub list: Vec<Profile>,
+}
+
+impl From<Profiles> for Dispatch {
+    fn from(profiles: Profiles) -> Self {
+        let profile_count = profiles.list.len();
+        Dispatch::from_success(
+            StatusCode::Profiles.into(),
+            format!("{} Profile(s)", profile_count),
+            Some(Body {
+                name: "PROFILES",
+                list: profiles.list,
+            }),
+        )
+    }
+}
+
+pub struct ProfileSet {
+    /// Name of the newly active profile
+    pub name: String,
+}
+
+impl From<ProfileSet> for Dispatch {
+    fn from(profile_set: ProfileSet) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::ProfileSet.into(),
+            format!("Profile '{}' activated", profile_set.name),
+            None,
+        )
+    }
+}
+
+/// Input measurements of power supply read over PMBus
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct Psu {
+    #[serde(rename = "PSU")]
+    pub idx: i32,
+    #[serde(rename = "InputVoltage")]
+    pub input_voltage: f64,
+    #[serde(rename = "InputCurrent")]
+    pub input_current: f64,
+    #[serde(rename = "InputPower")]
+    pub input_power: f64,
+}
+
+pub struct Psus {
+    pub list: Vec<Psu>,
+}
+
+impl From<Psus> for Dispatch {
+    fn from(psus: Psus) -> Self {
+        let psu_count = psus.list.len();
+        Dispatch::from_success(
+            StatusCode::Psu.into(),
+            format!("{} PSU(s)", psu_count),
+            Some(Body {
+                name: "PSU",
+                list: psus.list,
+            }),
+        )
+    }
+}
@@ -159,6 +159,26 @@ async fn test_single() {
     assert_json_eq(&response, &expected);
 }
 
+#[tokio::test]
+async fn test_privileged() {
+    let command: json::Value = json::json!({
+        "command": "privileged"
+    });
+    let response = codec_roundtrip(command, None).await;
+    let expected = json::json!({
+        "STATUS": [{
+            "STATUS": "S",
+            "When": 0,
+            "Code": 46,
+            "Msg": "Privileged access OK",
+            "Description": "TestMiner v1.0",
+        }],
+        "id": 1
+    });
+
+    assert_json_eq(&response, &expected);
+}
+
 #[tokio::test]
 async fn test_multiple() {
     let command: json::Value = json::json!({
@@ -206,6 +226,51 @@ async fn test_multiple() {
     assert_json_eq(&response, &expected);
 }
 
+#[tokio::test]
+async fn test_zero() {
+    for &(parameter, code, msg) in &[
+        ("all,true", 96, "Zeroed all stats with summary"),
+        ("shares,false", 97, "Zeroed shares stats without summary"),
+    ] {
+        let command: json::Value = json::json!({
+            "command": "zero",
+            "parameter": parameter
+        });
+        let response = codec_roundtrip(command, None).await;
+        let expected = json::json!({
+            "STATUS": [{
+                "STATUS": "S",
+                "When": 0,
+                "Code": code,
+                "Msg": msg,
+                "Description": "TestMiner v1.0",
+            }],
+            "id": 1
+        });
+
+        assert_json_eq(&response, &expected);
+    }
+
+    // the summary flag is mandatory
+    let command: json::Value = json::json!({
+        "command": "zero",
+        "parameter": "all"
+    });
+    let response = codec_roundtrip(command, None).await;
+    let expected = json::json!({
+        "STATUS": [{
+            "STATUS": "E",
+            "When": 0,
+            "Code": 94,
+            "Msg": "Missing zero parameters",
+            "Description": "TestMiner v1.0",
+        }],
+        "id": 1
+    });
+
+    assert_json_eq(&response, &expected);
+}
+
 #[tokio::test]
 async fn test_single_custom_command() {
     let handler = Arc::new(TestCustomHandler);
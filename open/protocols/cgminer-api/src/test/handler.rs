@@ -338,4 +338,18 @@ impl command::Handler for BasicTest {
             user: "".to_string(),
         })
     }
+
+    async fn handle_zero(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::Zero> {
+        let mut args = parameter
+            .and_then(|value| value.as_str())
+            .expect("BUG: missing ZERO parameter")
+            .splitn(2, crate::PARAMETER_DELIMITER);
+        Ok(response::Zero {
+            which: args.next().unwrap_or_default().to_string(),
+            summary: args.next() == Some("true"),
+        })
+    }
 }
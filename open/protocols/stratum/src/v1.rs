@@ -84,6 +84,13 @@ pub trait Handler: 'static + Send {
 
     async fn visit_set_difficulty(&mut self, _id: &MessageId, _payload: &messages::SetDifficulty) {}
 
+    async fn visit_suggest_difficulty(
+        &mut self,
+        _id: &MessageId,
+        _payload: &messages::SuggestDifficulty,
+    ) {
+    }
+
     async fn visit_notify(&mut self, _id: &MessageId, _payload: &messages::Notify) {}
 
     async fn visit_set_version_mask(
@@ -94,6 +101,13 @@ pub trait Handler: 'static + Send {
     }
 
     async fn visit_submit(&mut self, _id: &MessageId, _payload: &messages::Submit) {}
+
+    async fn visit_client_reconnect(
+        &mut self,
+        _id: &MessageId,
+        _payload: &messages::ClientReconnect,
+    ) {
+    }
 }
 
 pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protocol>> {
@@ -112,6 +126,10 @@ pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protoco
                     as Box<dyn AnyPayload<Protocol>>,
                 Method::Subscribe => Box::new(messages::Subscribe::try_from(request)?)
                     as Box<dyn AnyPayload<Protocol>>,
+                Method::ExtranonceSubscribe => {
+                    Box::new(messages::ExtranonceSubscribe::try_from(request)?)
+                        as Box<dyn AnyPayload<Protocol>>
+                }
                 Method::Submit => {
                     Box::new(messages::Submit::try_from(request)?) as Box<dyn AnyPayload<Protocol>>
                 }
@@ -121,11 +139,17 @@ pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protoco
                     as Box<dyn AnyPayload<Protocol>>,
                 Method::SetExtranonce => Box::new(messages::SetExtranonce::try_from(request)?)
                     as Box<dyn AnyPayload<Protocol>>,
+                Method::SuggestDifficulty => {
+                    Box::new(messages::SuggestDifficulty::try_from(request)?)
+                        as Box<dyn AnyPayload<Protocol>>
+                }
                 Method::Notify => {
                     Box::new(messages::Notify::try_from(request)?) as Box<dyn AnyPayload<Protocol>>
                 }
                 Method::SetVersionMask => Box::new(messages::SetVersionMask::try_from(request)?)
                     as Box<dyn AnyPayload<Protocol>>,
+                Method::ClientReconnect => Box::new(messages::ClientReconnect::try_from(request)?)
+                    as Box<dyn AnyPayload<Protocol>>,
                 _ => {
                     return Err(ErrorKind::Rpc(format!("Unsupported request {:?}", request)).into())
                 }
@@ -182,6 +206,12 @@ impl AsRef<Vec<u8>> for HexBytes {
     }
 }
 
+impl From<Vec<u8>> for HexBytes {
+    fn from(value: Vec<u8>) -> Self {
+        HexBytes(value)
+    }
+}
+
 /// fix for error on odd-length hex sequences
 /// FIXME: find a nicer solution
 fn hex_decode(s: &str) -> std::result::Result<Vec<u8>, FromHexError> {
@@ -73,6 +73,11 @@ impl Frame {
         Ok(())
     }
 
+    /// Payload of the frame without consuming it (e.g. for recording of raw frames)
+    pub fn payload(&self) -> &Payload<Protocol> {
+        &self.0
+    }
+
     /// Consumes the frame providing its payload
     pub fn into_inner(self) -> Payload<Protocol> {
         self.0
@@ -334,6 +334,23 @@ impl SetDifficulty {
 }
 
 impl_conversion_request!(SetDifficulty, Method::SetDifficulty, visit_set_difficulty);
+
+/// Difficulty the client asks the upstream stratum server to start with, sent before
+/// authorization. The server is free to ignore it.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct SuggestDifficulty(pub [f64; 1]);
+
+impl SuggestDifficulty {
+    pub fn value(&self) -> f64 {
+        self.0[0]
+    }
+}
+
+impl_conversion_request!(
+    SuggestDifficulty,
+    Method::SuggestDifficulty,
+    visit_suggest_difficulty
+);
 //#[derive(Deserialize)]
 //struct Helper(#[serde(with = "DurationDef")] Duration);
 //
@@ -346,6 +363,10 @@ impl JobId {
     pub fn from_str(job_id: &str) -> Self {
         Self(String::from(job_id))
     }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
 }
 
 /// Leading part of the coinbase transaction
@@ -389,6 +410,32 @@ pub struct Notify(
 
 // TODO consider making the attributes return new type references, it would be less prone to typos
 impl Notify {
+    /// Build notification from `prev_hash` in block header byte order and transaction hashes of
+    /// `merkle_branch`
+    pub fn new(
+        job_id: JobId,
+        prev_hash: &[u8],
+        coin_base_1: &[u8],
+        coin_base_2: &[u8],
+        merkle_branch: Vec<Vec<u8>>,
+        version: u32,
+        bits: u32,
+        time: u32,
+        clean_jobs: bool,
+    ) -> Self {
+        Self(
+            job_id,
+            PrevHash(prev_hash.into()),
+            CoinBase1(HexBytes(coin_base_1.into())),
+            CoinBase2(HexBytes(coin_base_2.into())),
+            MerkleBranch(merkle_branch.into_iter().map(HexBytes).collect()),
+            Version(HexU32Be(version)),
+            Bits(HexU32Be(bits)),
+            Time(HexU32Be(time)),
+            clean_jobs,
+        )
+    }
+
     pub fn job_id(&self) -> &str {
         &(self.0).0
     }
@@ -446,6 +493,50 @@ impl_conversion_request!(
     visit_set_version_mask
 );
 
+/// Server asks the client to reconnect, optionally to another host and port after waiting for
+/// given number of seconds. All parameters are optional and some servers send the port and the
+/// wait time as strings.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ClientReconnect(
+    #[serde(default)] pub Option<String>,
+    #[serde(default)] pub Option<serde_json::Value>,
+    #[serde(default)] pub Option<serde_json::Value>,
+);
+
+impl ClientReconnect {
+    /// Parse number that may be sent as JSON number or string
+    fn value_to_u64(value: Option<&serde_json::Value>) -> Option<u64> {
+        match value? {
+            serde_json::Value::Number(number) => number.as_u64(),
+            serde_json::Value::String(string) => string.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// New host or `None` when the client should reconnect to the current one
+    pub fn host(&self) -> Option<&str> {
+        self.0.as_deref().filter(|host| !host.is_empty())
+    }
+
+    /// New port or `None` when the client should reconnect to the current one
+    pub fn port(&self) -> Option<u16> {
+        Self::value_to_u64(self.1.as_ref())
+            .filter(|port| *port != 0)
+            .and_then(|port| u16::try_from(port).ok())
+    }
+
+    /// Number of seconds the client should wait before reconnecting
+    pub fn wait_time(&self) -> u64 {
+        Self::value_to_u64(self.2.as_ref()).unwrap_or(0)
+    }
+}
+
+impl_conversion_request!(
+    ClientReconnect,
+    Method::ClientReconnect,
+    visit_client_reconnect
+);
+
 /// Combined username and worker
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct UserName(String);
@@ -458,10 +549,45 @@ pub struct ExtraNonce2(HexBytes);
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct Nonce(HexU32Be);
 
+/// Byte order convention of `ntime` and `nonce` fields in submitted shares. Pools that expect
+/// a different convention reject the shares as if they were invalid even for correct solutions.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SubmitByteOrder {
+    /// Values are serialized as big endian hexadecimal numbers (standard stratum V1)
+    BigEndian,
+    /// Values are serialized in the same byte order as in the block header
+    LittleEndian,
+}
+
+impl SubmitByteOrder {
+    /// Convert native value to the value that is serialized as big endian hexadecimal number
+    #[inline]
+    fn canonicalize(self, value: u32) -> u32 {
+        match self {
+            Self::BigEndian => value,
+            Self::LittleEndian => value.swap_bytes(),
+        }
+    }
+}
+
+impl Default for SubmitByteOrder {
+    fn default() -> Self {
+        Self::BigEndian
+    }
+}
+
 /// New mining job notification
 /// TODO generate the field accessors
+/// Miners that do not roll the version omit the version bits completely
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
-pub struct Submit(UserName, JobId, ExtraNonce2, Time, Nonce, Version);
+pub struct Submit(
+    UserName,
+    JobId,
+    ExtraNonce2,
+    Time,
+    Nonce,
+    #[serde(default, skip_serializing_if = "Option::is_none")] Option<Version>,
+);
 
 impl Submit {
     pub fn new(
@@ -471,14 +597,35 @@ impl Submit {
         time: u32,
         nonce: u32,
         version: u32,
+    ) -> Self {
+        Self::with_byte_order(
+            user_name,
+            job_id,
+            extra_nonce2,
+            time,
+            nonce,
+            version,
+            Default::default(),
+        )
+    }
+
+    /// Build submit message with `time` and `nonce` serialized according to `byte_order`
+    pub fn with_byte_order(
+        user_name: String,
+        job_id: JobId,
+        extra_nonce2: &[u8],
+        time: u32,
+        nonce: u32,
+        version: u32,
+        byte_order: SubmitByteOrder,
     ) -> Self {
         Self(
             UserName(user_name),
             job_id,
             ExtraNonce2(HexBytes(extra_nonce2.into())),
-            Time(HexU32Be(time)),
-            Nonce(HexU32Be(nonce)),
-            Version(HexU32Be(version)),
+            Time(HexU32Be(byte_order.canonicalize(time))),
+            Nonce(HexU32Be(byte_order.canonicalize(nonce))),
+            Some(Version(HexU32Be(version))),
         )
     }
 
@@ -502,8 +649,9 @@ impl Submit {
         ((self.4).0).0
     }
 
-    pub fn version(&self) -> u32 {
-        ((self.5).0).0
+    /// Version bits rolled by the miner (if any)
+    pub fn version(&self) -> Option<u32> {
+        self.5.as_ref().map(|version| (version.0).0)
     }
 }
 
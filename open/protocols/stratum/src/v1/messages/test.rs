@@ -24,7 +24,7 @@ use std::str::FromStr;
 
 use super::*;
 use crate::test_utils::v1::*;
-use crate::v1::rpc::Rpc;
+use crate::v1::rpc::{Request, Rpc};
 
 #[test]
 fn test_build_subscribe_from_rpc_request() {
@@ -85,3 +85,136 @@ fn test_subscribe_malformed_result_json() {
         Rpc::Request(_) => (),
     }
 }
+
+#[test]
+fn test_build_submit_default_byte_order() {
+    let submit = Submit::new(
+        "braiins.worker0".to_string(),
+        JobId::from_str("ahoj"),
+        &[0, 0, 0, 0],
+        0x5d10bc0a,
+        0x0443c37b,
+        0,
+    );
+    assert_eq!(build_mining_submit(), submit, "Submit request mismatch");
+
+    let params = serde_json::to_value(submit).expect("Cannot serialize submit");
+    assert_eq!(params[3], "5d10bc0a", "Time mismatch");
+    assert_eq!(params[4], "0443c37b", "Nonce mismatch");
+}
+
+#[test]
+fn test_build_submit_little_endian_byte_order() {
+    let submit = Submit::with_byte_order(
+        "braiins.worker0".to_string(),
+        JobId::from_str("ahoj"),
+        &[0, 0, 0, 0],
+        0x5d10bc0a,
+        0x0443c37b,
+        0,
+        SubmitByteOrder::LittleEndian,
+    );
+    let params = serde_json::to_value(submit).expect("Cannot serialize submit");
+    assert_eq!(params[3], "0abc105d", "Time mismatch");
+    assert_eq!(params[4], "7bc34304", "Nonce mismatch");
+}
+
+#[test]
+fn test_submit_without_version() {
+    let request = match Rpc::from_str(concat!(
+        r#"{"id":3,"method":"mining.submit","#,
+        r#""params":["braiins.worker0","ahoj","00000000","5d10bc0a","0443c37b"]}"#
+    ))
+    .expect("Cannot parse submit")
+    {
+        Rpc::Request(request) => request,
+        Rpc::Response(_) => panic!("Request expected"),
+    };
+    let submit = Submit::try_from(request).expect("Conversion failed");
+    assert_eq!(submit.version(), None);
+    assert_eq!(submit.nonce(), 0x0443c37b);
+    assert_eq!(build_mining_submit().version(), Some(0));
+}
+
+#[test]
+fn test_build_notify() {
+    let notify = build_mining_notify();
+    let built_notify = Notify::new(
+        JobId::from_str(notify.job_id()),
+        notify.prev_hash(),
+        notify.coin_base_1(),
+        notify.coin_base_2(),
+        vec![],
+        notify.version(),
+        notify.bits(),
+        notify.time(),
+        notify.clean_jobs(),
+    );
+    assert_eq!(built_notify, notify);
+    let params = serde_json::to_value(built_notify).expect("Cannot serialize notify");
+    assert_eq!(
+        params[1],
+        "13f46cc7bf03a16697170dbb9d15680b7e75fcf10846037f171d7f6b00000000"
+    );
+}
+
+#[test]
+fn test_suggest_difficulty() {
+    let rpc = Rpc::from(Request {
+        id: Some(3),
+        payload: SuggestDifficulty([8192.0])
+            .try_into()
+            .expect("Cannot convert suggest difficulty"),
+    });
+    let json = serde_json::to_string(&rpc).expect("Cannot serialize suggest difficulty");
+    assert_eq!(
+        json,
+        r#"{"id":3,"method":"mining.suggest_difficulty","params":[8192.0]}"#
+    );
+
+    let request = match Rpc::from_str(&json).expect("Cannot parse suggest difficulty") {
+        Rpc::Request(request) => request,
+        Rpc::Response(_) => panic!("Request expected"),
+    };
+    let suggest_difficulty = SuggestDifficulty::try_from(request).expect("Conversion failed");
+    assert_eq!(suggest_difficulty.value(), 8192.0);
+}
+
+#[test]
+fn test_client_reconnect() {
+    for (json, host, port, wait_time) in &[
+        (
+            r#"{"id":null,"method":"client.reconnect","params":["eu.example.com",3333,5]}"#,
+            Some("eu.example.com"),
+            Some(3333),
+            5,
+        ),
+        (
+            r#"{"id":null,"method":"client.reconnect","params":["eu.example.com","3334","0"]}"#,
+            Some("eu.example.com"),
+            Some(3334),
+            0,
+        ),
+        (
+            r#"{"id":null,"method":"client.reconnect","params":[]}"#,
+            None,
+            None,
+            0,
+        ),
+        (
+            r#"{"id":null,"method":"client.reconnect","params":["",0]}"#,
+            None,
+            None,
+            0,
+        ),
+    ] {
+        let request = match Rpc::from_str(json).expect("Cannot parse reconnect request") {
+            Rpc::Request(request) => request,
+            Rpc::Response(_) => panic!("Request expected"),
+        };
+        let reconnect = ClientReconnect::try_from(request).expect("Conversion failed");
+        assert_eq!(reconnect.host(), *host);
+        assert_eq!(reconnect.port(), *port);
+        assert_eq!(reconnect.wait_time(), *wait_time);
+    }
+}
@@ -59,6 +59,10 @@ pub enum Method {
     Notify,
     #[serde(rename = "mining.set_version_mask")]
     SetVersionMask,
+    #[serde(rename = "mining.suggest_difficulty")]
+    SuggestDifficulty,
+    #[serde(rename = "client.reconnect")]
+    ClientReconnect,
     /// Catch all variant
     #[serde(other)]
     Unknown,
@@ -21,15 +21,18 @@
 // contact us at opensource@braiins.com.
 
 //! Stratum version 2 top level module
+pub mod compression;
 pub mod error;
 pub mod framing;
 #[macro_use]
 pub mod macros;
 pub mod extensions;
+pub mod job_negotiation;
 pub mod messages;
 pub mod noise;
 pub mod serialization;
 pub mod telemetry;
+pub mod template_distribution;
 pub mod types;
 
 use self::messages::MessageType;
@@ -45,7 +48,7 @@ use ii_async_compat::prelude::*;
 use ii_logging::macros::*;
 use ii_wire;
 
-pub use self::framing::codec::Codec;
+pub use self::framing::codec::{Codec, CompressionSwitch};
 pub use self::framing::{Frame, Framing};
 
 /// Tcp stream that produces/consumes V2 frames
@@ -143,6 +146,27 @@ pub trait Handler: 'static + Send {
     ) {
     }
 
+    async fn visit_open_extended_mining_channel(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &messages::OpenExtendedMiningChannel,
+    ) {
+    }
+
+    async fn visit_open_extended_mining_channel_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &messages::OpenExtendedMiningChannelSuccess,
+    ) {
+    }
+
+    async fn visit_open_extended_mining_channel_error(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &messages::OpenExtendedMiningChannelError,
+    ) {
+    }
+
     async fn visit_update_channel(
         &mut self,
         _header: &framing::Header,
@@ -164,6 +188,13 @@ pub trait Handler: 'static + Send {
     ) {
     }
 
+    async fn visit_submit_shares_extended(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &messages::SubmitSharesExtended,
+    ) {
+    }
+
     async fn visit_submit_shares_success(
         &mut self,
         _header: &framing::Header,
@@ -185,6 +216,13 @@ pub trait Handler: 'static + Send {
     ) {
     }
 
+    async fn visit_new_extended_mining_job(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &messages::NewExtendedMiningJob,
+    ) {
+    }
+
     async fn visit_set_new_prev_hash(
         &mut self,
         _header: &framing::Header,
@@ -199,6 +237,9 @@ pub trait Handler: 'static + Send {
     ) {
     }
 
+    async fn visit_reconnect(&mut self, _header: &framing::Header, _payload: &messages::Reconnect) {
+    }
+
     // TODO the methods below will be removed once we will split off a separate handler
     //  type for the telemetry extension and refactor message handling completely
     async fn visit_open_telemetry_channel(
@@ -242,6 +283,118 @@ pub trait Handler: 'static + Send {
         _payload: &telemetry::messages::SubmitTelemetryDataError,
     ) {
     }
+
+    async fn visit_allocate_mining_job_token(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::AllocateMiningJobToken,
+    ) {
+    }
+
+    async fn visit_allocate_mining_job_token_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::AllocateMiningJobTokenSuccess,
+    ) {
+    }
+
+    async fn visit_commit_mining_job(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::CommitMiningJob,
+    ) {
+    }
+
+    async fn visit_commit_mining_job_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::CommitMiningJobSuccess,
+    ) {
+    }
+
+    async fn visit_commit_mining_job_error(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::CommitMiningJobError,
+    ) {
+    }
+
+    async fn visit_identify_transactions(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::IdentifyTransactions,
+    ) {
+    }
+
+    async fn visit_identify_transactions_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::IdentifyTransactionsSuccess,
+    ) {
+    }
+
+    async fn visit_provide_missing_transactions(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::ProvideMissingTransactions,
+    ) {
+    }
+
+    async fn visit_provide_missing_transactions_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &job_negotiation::messages::ProvideMissingTransactionsSuccess,
+    ) {
+    }
+
+    async fn visit_coinbase_output_data_size(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::CoinbaseOutputDataSize,
+    ) {
+    }
+
+    async fn visit_new_template(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::NewTemplate,
+    ) {
+    }
+
+    async fn visit_template_set_new_prev_hash(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::SetNewPrevHash,
+    ) {
+    }
+
+    async fn visit_request_transaction_data(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::RequestTransactionData,
+    ) {
+    }
+
+    async fn visit_request_transaction_data_success(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::RequestTransactionDataSuccess,
+    ) {
+    }
+
+    async fn visit_request_transaction_data_error(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::RequestTransactionDataError,
+    ) {
+    }
+
+    async fn visit_submit_solution(
+        &mut self,
+        _header: &framing::Header,
+        _payload: &template_distribution::messages::SubmitSolution,
+    ) {
+    }
 }
 
 /// Consumes `frame` and produces a Message object based on the payload type
@@ -287,12 +440,30 @@ pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protoco
         MessageType::OpenStandardMiningChannelError => {
             Box::new(messages::OpenStandardMiningChannelError::try_from(frame)?)
         }
+        MessageType::OpenExtendedMiningChannel => {
+            Box::new(messages::OpenExtendedMiningChannel::try_from(frame)?)
+        }
+        MessageType::OpenExtendedMiningChannelSuccess => {
+            Box::new(messages::OpenExtendedMiningChannelSuccess::try_from(frame)?)
+        }
+        MessageType::OpenExtendedMiningChannelError => {
+            Box::new(messages::OpenExtendedMiningChannelError::try_from(frame)?)
+        }
         MessageType::NewMiningJob => Box::new(messages::NewMiningJob::try_from(frame)?),
+        MessageType::NewExtendedMiningJob => {
+            Box::new(messages::NewExtendedMiningJob::try_from(frame)?)
+        }
         MessageType::SetNewPrevHash => Box::new(messages::SetNewPrevHash::try_from(frame)?),
         MessageType::SetTarget => Box::new(messages::SetTarget::try_from(frame)?),
+        MessageType::Reconnect => Box::new(messages::Reconnect::try_from(frame)?),
+        MessageType::UpdateChannel => Box::new(messages::UpdateChannel::try_from(frame)?),
+        MessageType::UpdateChannelError => Box::new(messages::UpdateChannelError::try_from(frame)?),
         MessageType::SubmitSharesStandard => {
             Box::new(messages::SubmitSharesStandard::try_from(frame)?)
         }
+        MessageType::SubmitSharesExtended => {
+            Box::new(messages::SubmitSharesExtended::try_from(frame)?)
+        }
         MessageType::SubmitSharesSuccess => {
             Box::new(messages::SubmitSharesSuccess::try_from(frame)?)
         }
@@ -0,0 +1,326 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Optional compression of frame payloads negotiated by `SETUP_CONNECTION_FLAG_COMPRESSION`
+//! during connection setup. Only payloads of job related messages are compressed (see
+//! `is_eligible`) and the frame is marked by a dedicated bit of the extension type field. The
+//! codec decompresses such frames transparently once the compression has been negotiated and
+//! frames without the bit are passed unchanged so that peers that do not support compression keep
+//! working.
+//!
+//! The payload is compressed with a simple LZ77 scheme which consists of tokens:
+//! - `0b0xxx_xxxx` followed by `x + 1` literal bytes
+//! - `0b1xxx_xxxx` followed by 16-bit little endian distance of a back reference with length
+//!   `x + MIN_MATCH`
+
+use bytes::{buf::BufMut, BytesMut};
+
+use ii_async_compat::bytes;
+
+use super::error::ErrorKind;
+use super::extensions;
+use super::framing::{Header, MsgType};
+use super::messages::MessageType;
+use crate::error::Result;
+
+/// `SetupConnection` flag that signals support of compressed frame payloads. The upstream
+/// confirms the support by setting the same flag in `SetupConnectionSuccess`.
+pub const SETUP_CONNECTION_FLAG_COMPRESSION: u32 = 1 << 31;
+
+const MATCH_FLAG: u8 = 0x80;
+const MAX_LITERALS: usize = 0x80;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+/// Maximum distance of a back reference
+const WINDOW_SIZE: usize = 4096;
+/// Number of bits of hash of `MIN_MATCH` bytes used for looking up match candidates
+const HASH_BITS: usize = 12;
+/// Maximum number of candidates examined for each position
+const MAX_CHAIN_LENGTH: usize = 32;
+/// Marks empty hash chain
+const NO_POSITION: usize = usize::max_value();
+
+/// Check whether both sides have agreed on compression
+pub fn is_negotiated(requested_flags: u32, confirmed_flags: u32) -> bool {
+    requested_flags & confirmed_flags & SETUP_CONNECTION_FLAG_COMPRESSION != 0
+}
+
+/// Compression is applied only to messages that carry jobs
+pub fn is_eligible(extension_type: u16, msg_type: MsgType) -> bool {
+    extension_type == extensions::BASE
+        && (msg_type == MessageType::NewMiningJob as MsgType
+            || msg_type == MessageType::NewExtendedMiningJob as MsgType
+            || msg_type == MessageType::SetNewPrevHash as MsgType)
+}
+
+fn flush_literals(dst: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        dst.push((chunk.len() - 1) as u8);
+        dst.extend_from_slice(chunk);
+    }
+}
+
+/// Hash chains of positions with the same hash of their first `MIN_MATCH` bytes. Only a bounded
+/// number of the most recent candidates is examined so the compression is linear in the size of
+/// the data.
+struct Matcher<'a> {
+    src: &'a [u8],
+    /// The most recent position for each hash value
+    head: Vec<usize>,
+    /// Previous position with the same hash for each position of `src`
+    prev: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        Self {
+            src,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            prev: vec![NO_POSITION; src.len()],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> Option<usize> {
+        let bytes = self.src.get(pos..pos + MIN_MATCH)?;
+        let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        Some((value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize)
+    }
+
+    /// Add position `pos` to its hash chain
+    fn insert(&mut self, pos: usize) {
+        if let Some(hash) = self.hash(pos) {
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Find the longest match for data at `pos` and return its distance and length
+    fn find_match(&self, pos: usize) -> (usize, usize) {
+        let max_length = (self.src.len() - pos).min(MAX_MATCH);
+        let mut best = (0, 0);
+        let mut candidate = match self.hash(pos) {
+            Some(hash) => self.head[hash],
+            None => return best,
+        };
+
+        for _ in 0..MAX_CHAIN_LENGTH {
+            if candidate == NO_POSITION || pos - candidate > WINDOW_SIZE {
+                break;
+            }
+            // NOTE: the match may overlap current position because the decompression copies the
+            // referenced data byte by byte
+            let length = self.src[candidate..]
+                .iter()
+                .zip(&self.src[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.1 {
+                best = (pos - candidate, length);
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+        best
+    }
+}
+
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut dst = Vec::with_capacity(src.len());
+    let mut matcher = Matcher::new(src);
+    let mut literals_start = 0;
+    let mut pos = 0;
+
+    while pos < src.len() {
+        let (distance, length) = matcher.find_match(pos);
+        if length >= MIN_MATCH {
+            flush_literals(&mut dst, &src[literals_start..pos]);
+            dst.push(MATCH_FLAG | (length - MIN_MATCH) as u8);
+            dst.extend_from_slice(&(distance as u16).to_le_bytes());
+            for i in pos..pos + length {
+                matcher.insert(i);
+            }
+            pos += length;
+            literals_start = pos;
+        } else {
+            matcher.insert(pos);
+            pos += 1;
+        }
+    }
+    flush_literals(&mut dst, &src[literals_start..]);
+    dst
+}
+
+pub fn decompress(src: &[u8]) -> Result<Vec<u8>> {
+    let mut dst = Vec::with_capacity(2 * src.len());
+    let mut i = 0;
+
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+        if token & MATCH_FLAG == 0 {
+            let length = token as usize + 1;
+            let literals = src
+                .get(i..i + length)
+                .ok_or_else(|| ErrorKind::Compression("truncated literals".to_string()))?;
+            dst.extend_from_slice(literals);
+            i += length;
+        } else {
+            let length = (token & !MATCH_FLAG) as usize + MIN_MATCH;
+            let distance = src
+                .get(i..i + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+                .ok_or_else(|| ErrorKind::Compression("truncated back reference".to_string()))?;
+            i += 2;
+            if distance == 0 || distance > dst.len() {
+                Err(ErrorKind::Compression(format!(
+                    "invalid back reference distance {}",
+                    distance
+                )))?;
+            }
+            let start = dst.len() - distance;
+            for j in start..start + length {
+                let byte = dst[j];
+                dst.push(byte);
+            }
+        }
+        if dst.len() > Header::MAX_LEN as usize {
+            Err(ErrorKind::Compression(
+                "decompressed payload is too large".to_string(),
+            ))?;
+        }
+    }
+    Ok(dst)
+}
+
+/// Build serialized frame from raw header fields and payload
+fn build_frame(extension_field: u16, msg_type: MsgType, payload: &[u8]) -> BytesMut {
+    let mut frame = BytesMut::with_capacity(Header::SIZE + payload.len());
+    frame.put_u16_le(extension_field);
+    frame.put_u8(msg_type);
+    frame.put_uint_le(payload.len() as u64, Header::LEN_SIZE);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Compress payload of serialized `frame` when it is eligible for compression and it actually
+/// saves some space
+pub(crate) fn compress_frame(frame: BytesMut) -> BytesMut {
+    let extension_field = u16::from_le_bytes([frame[0], frame[1]]);
+    let msg_type = frame[2];
+    if !is_eligible(extension_field & !Header::CHANNEL_MSG_MASK, msg_type) {
+        return frame;
+    }
+    let payload = compress(&frame[Header::SIZE..]);
+    if payload.len() >= frame.len() - Header::SIZE {
+        return frame;
+    }
+    build_frame(
+        extension_field | Header::COMPRESSED_MSG_MASK,
+        msg_type,
+        &payload[..],
+    )
+}
+
+/// Decompress payload of serialized `frame` when it is marked as compressed. Compressed frames
+/// are rejected when the compression has not been `negotiated`.
+pub(crate) fn decompress_frame(frame: BytesMut, negotiated: bool) -> Result<BytesMut> {
+    let extension_field = u16::from_le_bytes([frame[0], frame[1]]);
+    if extension_field & Header::COMPRESSED_MSG_MASK == 0 {
+        return Ok(frame);
+    }
+    if !negotiated {
+        Err(ErrorKind::Compression(
+            "compressed frame received without negotiated compression".to_string(),
+        ))?;
+    }
+    let payload = decompress(&frame[Header::SIZE..])?;
+    Ok(build_frame(
+        extension_field & !Header::COMPRESSED_MSG_MASK,
+        frame[2],
+        &payload[..],
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() {
+        let mut data = vec![0u8; 64];
+        data.extend((0..200).map(|i| (i % 7) as u8));
+        data.extend_from_slice(b"merkle path merkle path merkle path");
+        data.extend((0..300).map(|i| (i * 31 % 251) as u8));
+
+        for src in &[vec![], vec![0xaa], data] {
+            let compressed = compress(&src[..]);
+            assert_eq!(
+                &decompress(&compressed[..]).expect("BUG: cannot decompress"),
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        // highly repetitive data has to be compressed with long back references
+        let src: Vec<u8> = (0..4096).map(|i| (i % 16) as u8).collect();
+        let compressed = compress(&src[..]);
+        assert!(compressed.len() < src.len() / 16);
+        assert_eq!(
+            decompress(&compressed[..]).expect("BUG: cannot decompress"),
+            src
+        );
+
+        // back references can't exceed the window
+        let mut src: Vec<u8> = (0..64).collect();
+        src.extend((0..WINDOW_SIZE).map(|i| (i * 31 % 251) as u8 | 0x80));
+        src.extend(0..64);
+        assert_eq!(
+            decompress(&compress(&src[..])[..]).expect("BUG: cannot decompress"),
+            src
+        );
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        // back reference before the start of the data
+        assert!(decompress(&[0x00, 0xaa, 0x80, 0x02, 0x00]).is_err());
+        // truncated literals
+        assert!(decompress(&[0x03, 0xaa]).is_err());
+        // truncated distance
+        assert!(decompress(&[0x00, 0xaa, 0x80, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_negotiation() {
+        assert!(is_negotiated(
+            SETUP_CONNECTION_FLAG_COMPRESSION,
+            SETUP_CONNECTION_FLAG_COMPRESSION
+        ));
+        // upstream without support of compression doesn't confirm the flag
+        assert!(!is_negotiated(SETUP_CONNECTION_FLAG_COMPRESSION, 0));
+        assert!(!is_negotiated(0, SETUP_CONNECTION_FLAG_COMPRESSION));
+    }
+}
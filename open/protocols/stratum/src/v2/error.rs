@@ -31,4 +31,7 @@ pub enum ErrorKind {
 
     #[fail(display = "Channel not operational: {}", _0)]
     ChannelNotOperational(String),
+
+    #[fail(display = "Compression error: {}", _0)]
+    Compression(String),
 }
@@ -26,3 +26,10 @@
 pub const BASE: u16 = 0x0000;
 /// Telemetry extension
 pub const TELEMETRY: u16 = 0x0001;
+/// Job Negotiation protocol between the miner and the pool. It is a separate sub-protocol in the
+/// specification, this implementation distinguishes its messages by the extension type.
+pub const JOB_NEGOTIATION: u16 = 0x0002;
+/// Template Distribution protocol between the miner and the template provider (e.g. bitcoind).
+/// It is a separate sub-protocol in the specification, this implementation distinguishes its
+/// messages by the extension type.
+pub const TEMPLATE_DISTRIBUTION: u16 = 0x0003;
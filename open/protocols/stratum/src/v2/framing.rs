@@ -62,7 +62,10 @@ impl Header {
     pub const MAX_LEN: u32 = 0xffffff;
     /// Bit position of the 'is_channel_message' flag
     const CHANNEL_MSG_SHIFT: usize = 15;
-    const CHANNEL_MSG_MASK: u16 = 1u16 << Self::CHANNEL_MSG_SHIFT;
+    pub(crate) const CHANNEL_MSG_MASK: u16 = 1u16 << Self::CHANNEL_MSG_SHIFT;
+    /// Bit position of the flag marking compressed payload (see `v2::compression`)
+    const COMPRESSED_MSG_SHIFT: usize = 14;
+    pub(crate) const COMPRESSED_MSG_MASK: u16 = 1u16 << Self::COMPRESSED_MSG_SHIFT;
 
     pub fn new(
         is_channel_message: bool,
@@ -209,6 +212,11 @@ impl Frame {
         Ok(())
     }
 
+    /// Payload of the frame without consuming it (e.g. for recording of raw frames)
+    pub fn payload(&self) -> &Payload<Protocol> {
+        &self.payload
+    }
+
     /// Consumes the frame providing its header and payload
     pub fn split(self) -> (Header, Payload<Protocol>) {
         (self.header, self.payload)
@@ -26,15 +26,37 @@ use tokio_util::codec::{Decoder, Encoder};
 
 use ii_async_compat::{bytes, tokio_util};
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use super::{Frame, Header};
 use crate::error::Error;
-use crate::v2::noise;
+use crate::v2::{compression, noise};
+
+/// Switch of frame compression shared with the codec. It allows enabling the compression from the
+/// connection handler which negotiates it after the framed connection has been split into
+/// a stream and a sink.
+#[derive(Debug, Clone, Default)]
+pub struct CompressionSwitch(Arc<AtomicBool>);
+
+impl CompressionSwitch {
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
 #[derive(Debug)]
 pub struct Codec {
     /// Optional noise codec that handles encryption/decryption of messages
     noise_codec: Option<noise::Codec>,
     stratum_codec: LengthDelimitedCodec,
+    /// Compress payloads of eligible messages and decompress incoming frames. Compressed frames
+    /// are rejected until the compression has been negotiated.
+    compression: CompressionSwitch,
 }
 
 impl Codec {
@@ -61,8 +83,19 @@ impl Codec {
                 // Actual header length is not counted in the length field
                 .length_adjustment(Header::SIZE as isize)
                 .new_codec(),
+            compression: Default::default(),
         }
     }
+
+    /// Enable compression once it has been negotiated with the peer
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compression.set(enabled);
+    }
+
+    /// Returns switch which controls compression of this codec
+    pub fn compression_switch(&self) -> CompressionSwitch {
+        self.compression.clone()
+    }
 }
 
 impl Default for Codec {
@@ -91,7 +124,7 @@ impl Decoder for Codec {
         };
 
         let mut bytes = match stratum_bytes {
-            Some(bytes) => bytes,
+            Some(bytes) => compression::decompress_frame(bytes, self.compression.is_enabled())?,
             None => return Ok(None),
         };
         Frame::deserialize(&mut bytes).map(Some)
@@ -109,6 +142,9 @@ impl Encoder for Codec {
     ) -> std::result::Result<(), Self::Error> {
         let mut encoded_frame = BytesMut::new();
         item.serialize(&mut encoded_frame)?;
+        if self.compression.is_enabled() {
+            encoded_frame = compression::compress_frame(encoded_frame);
+        }
         match self.noise_codec {
             Some(ref mut noise_codec) => noise_codec.encode(encoded_frame, dst)?,
             None => dst.unsplit(encoded_frame),
@@ -148,6 +184,63 @@ mod test {
         );
     }
 
+    /// Compressed frame is transparently decoded by a codec with negotiated compression and frames
+    /// that are not eligible for compression are sent unchanged
+    #[test]
+    fn test_codec_compression() {
+        use crate::v2::extensions;
+        use crate::v2::messages::MessageType;
+
+        let mut payload = BytesMut::new();
+        payload.extend_from_slice(&[0x55; 64]);
+        let mut codec = Codec::default();
+        codec.set_compression(true);
+
+        for (msg_type, compressed) in &[
+            (MessageType::NewMiningJob, true),
+            (MessageType::SetTarget, false),
+        ] {
+            let frame = Frame::from_serialized_payload(
+                true,
+                extensions::BASE,
+                *msg_type as u8,
+                payload.clone(),
+            );
+            let expected_frame = Frame::from_serialized_payload(
+                true,
+                extensions::BASE,
+                *msg_type as u8,
+                payload.clone(),
+            );
+
+            let mut buffer = BytesMut::new();
+            codec
+                .encode(frame, &mut buffer)
+                .expect("BUG: Codec failed to encode message");
+            assert_eq!(
+                buffer.len() < Header::SIZE + payload.len(),
+                *compressed,
+                "BUG: unexpected size of encoded frame: {:x?}",
+                buffer
+            );
+
+            // peer that hasn't negotiated compression accepts only uncompressed frames
+            let mut peer_buffer = buffer.clone();
+            assert_eq!(
+                Codec::default().decode(&mut peer_buffer).is_ok(),
+                !*compressed
+            );
+
+            let mut peer_codec = Codec::default();
+            peer_codec.compression_switch().set(true);
+            let decoded_frame = peer_codec
+                .decode(&mut buffer)
+                .expect("BUG: Codec failed to decode message")
+                .expect("BUG: No frame provided");
+            assert_eq!(expected_frame, decoded_frame);
+        }
+    }
+
     /// Attempt to build a V2 codec with noise Codec that is still in handshake mode (=contains
     /// no noise transport) must result in panic
     #[test]
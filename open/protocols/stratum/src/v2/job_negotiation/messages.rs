@@ -0,0 +1,296 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Job Negotiation protocol messages exchanged with the pool
+
+#[cfg(not(feature = "v2json"))]
+use crate::v2::serialization;
+use crate::{
+    error::{Error, Result},
+    v2::{error, extensions, framing, types::*, Protocol},
+    AnyPayload, Message,
+};
+use async_trait::async_trait;
+use packed_struct::prelude::*;
+use packed_struct_codegen::PrimitiveEnum_u8;
+use serde;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "v2json")]
+use serde_json as serialization;
+use std::convert::TryFrom;
+
+use ii_logging::macros::*;
+
+/// Generates conversion for job negotiation protocol messages
+macro_rules! impl_job_negotiation_message_conversion {
+    ($message:tt, $is_channel_msg:expr, $handler_fn:tt) => {
+        impl_message_conversion!(
+            extensions::JOB_NEGOTIATION,
+            $message,
+            $is_channel_msg,
+            $handler_fn
+        );
+    };
+}
+
+/// All message recognized by the protocol
+#[derive(PrimitiveEnum_u8, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MessageType {
+    AllocateMiningJobToken = 0x50,
+    AllocateMiningJobTokenSuccess = 0x51,
+    IdentifyTransactions = 0x53,
+    IdentifyTransactionsSuccess = 0x54,
+    ProvideMissingTransactions = 0x55,
+    ProvideMissingTransactionsSuccess = 0x56,
+    CommitMiningJob = 0x57,
+    CommitMiningJobSuccess = 0x58,
+    CommitMiningJobError = 0x59,
+}
+
+/// Request of a token that authorizes the next `CommitMiningJob`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllocateMiningJobToken {
+    pub user_identifier: Str0_255,
+    pub request_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllocateMiningJobTokenSuccess {
+    pub request_id: u32,
+    pub mining_job_token: Bytes0_255,
+    /// Maximum size of coinbase outputs the pool adds to the committed jobs
+    pub coinbase_output_max_additional_size: u32,
+    /// Mining on the committed job may start before the pool accepts it
+    pub async_mining_allowed: bool,
+}
+
+/// Proposal of a job built from the template of the client. Transactions are identified only by
+/// their short hashes, the pool asks for the full transaction data when it does not know them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CommitMiningJob {
+    pub request_id: u32,
+    pub mining_job_token: Bytes0_255,
+    pub version: u32,
+    pub coinbase_tx_version: u32,
+    pub coinbase_prefix: Bytes0_255,
+    pub coinbase_tx_input_n_sequence: u32,
+    pub coinbase_tx_value_remaining: u64,
+    pub coinbase_tx_outputs: Bytes0_64k,
+    pub coinbase_tx_locktime: u32,
+    pub min_extranonce_size: u16,
+    /// Nonce of the short transaction hashes
+    pub tx_short_hash_nonce: u64,
+    pub tx_short_hash_list: Seq0_64k<u64>,
+    /// Hash of all full transaction hashes of the job in the block order
+    pub tx_hash_list_hash: Uint256Bytes,
+    pub excess_data: Bytes0_64k,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CommitMiningJobSuccess {
+    pub request_id: u32,
+    /// Token identifying the accepted job in `SetCustomMiningJob`
+    pub new_mining_job_token: Bytes0_255,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CommitMiningJobError {
+    pub request_id: u32,
+    pub error_code: Str0_255,
+    pub error_details: Bytes0_64k,
+}
+
+/// Request of full hashes of all transactions of the committed job
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IdentifyTransactions {
+    pub request_id: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IdentifyTransactionsSuccess {
+    pub request_id: u32,
+    pub tx_data_hashes: Seq0_64k<Uint256Bytes>,
+}
+
+/// Request of transactions unknown to the pool given by their positions in the committed job
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvideMissingTransactions {
+    pub request_id: u32,
+    pub unknown_tx_position_list: Seq0_64k<u16>,
+}
+
+/// TODO: the specification allows transactions up to 16 MB, the serialization currently
+///  supports only sequences with up to 16 bit length prefix
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvideMissingTransactionsSuccess {
+    pub request_id: u32,
+    pub transaction_list: Seq0_64k<Bytes0_64k>,
+}
+
+impl_job_negotiation_message_conversion!(
+    AllocateMiningJobToken,
+    false,
+    visit_allocate_mining_job_token
+);
+impl_job_negotiation_message_conversion!(
+    AllocateMiningJobTokenSuccess,
+    false,
+    visit_allocate_mining_job_token_success
+);
+impl_job_negotiation_message_conversion!(CommitMiningJob, false, visit_commit_mining_job);
+impl_job_negotiation_message_conversion!(
+    CommitMiningJobSuccess,
+    false,
+    visit_commit_mining_job_success
+);
+impl_job_negotiation_message_conversion!(
+    CommitMiningJobError,
+    false,
+    visit_commit_mining_job_error
+);
+impl_job_negotiation_message_conversion!(IdentifyTransactions, false, visit_identify_transactions);
+impl_job_negotiation_message_conversion!(
+    IdentifyTransactionsSuccess,
+    false,
+    visit_identify_transactions_success
+);
+impl_job_negotiation_message_conversion!(
+    ProvideMissingTransactions,
+    false,
+    visit_provide_missing_transactions
+);
+impl_job_negotiation_message_conversion!(
+    ProvideMissingTransactionsSuccess,
+    false,
+    visit_provide_missing_transactions_success
+);
+
+impl_build_message_from_frame!(
+    "job negotiation",
+    AllocateMiningJobToken,
+    AllocateMiningJobTokenSuccess,
+    CommitMiningJob,
+    CommitMiningJobSuccess,
+    CommitMiningJobError,
+    IdentifyTransactions,
+    IdentifyTransactionsSuccess,
+    ProvideMissingTransactions,
+    ProvideMissingTransactionsSuccess,
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::payload::SerializablePayload;
+
+    use crate::v2::Handler;
+    use bytes::{buf::BufMutExt, BytesMut};
+    use ii_async_compat::{bytes, tokio};
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_provide_missing_transactions_serialization() {
+        const PROVIDE_MISSING_TRANSACTIONS_SERIALIZED: &[u8] = &[
+            0x2a, 0x00, 0x00, 0x00, // request_id
+            0x02, 0x00, // unknown_tx_position_list length
+            0x01, 0x00, 0x34, 0x12, // unknown_tx_position_list
+        ];
+        let message = ProvideMissingTransactions {
+            request_id: 42,
+            unknown_tx_position_list: Seq0_64k::from_vec(vec![0x0001, 0x1234]),
+        };
+        let mut writer = BytesMut::new().writer();
+        message
+            .serialize_to_writer(&mut writer)
+            .expect("Cannot serialize message");
+        assert_eq!(
+            BytesMut::from(PROVIDE_MISSING_TRANSACTIONS_SERIALIZED),
+            writer.into_inner()
+        );
+
+        let deserialized =
+            ProvideMissingTransactions::try_from(PROVIDE_MISSING_TRANSACTIONS_SERIALIZED)
+                .expect("Deserialization failed");
+        assert_eq!(deserialized, message, "Deserialization is not correct");
+    }
+
+    /// Handler that captures the visited job proposal
+    struct CommitMiningJobHandler(Option<CommitMiningJob>);
+
+    #[async_trait]
+    impl Handler for CommitMiningJobHandler {
+        async fn visit_commit_mining_job(
+            &mut self,
+            _header: &framing::Header,
+            payload: &CommitMiningJob,
+        ) {
+            self.0 = Some(payload.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_message_from_frame() {
+        let message = CommitMiningJob {
+            request_id: 1,
+            mining_job_token: Bytes0_255::from_slice(&[0xaa, 0xbb]),
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: Bytes0_255::from_slice(&[0x03, 0x01, 0x02, 0x03]),
+            coinbase_tx_input_n_sequence: 0xffff_ffff,
+            coinbase_tx_value_remaining: 625_000_000,
+            coinbase_tx_outputs: Bytes0_64k::new(),
+            coinbase_tx_locktime: 0,
+            min_extranonce_size: 8,
+            tx_short_hash_nonce: 0,
+            tx_short_hash_list: Seq0_64k::from_vec(vec![0x0102_0304_0506_0708]),
+            tx_hash_list_hash: Uint256Bytes([0x11; 32]),
+            excess_data: Bytes0_64k::new(),
+        };
+        let frame: framing::Frame = message.clone().try_into().expect("Cannot create frame");
+        assert_eq!(frame.header.extension_type, extensions::JOB_NEGOTIATION);
+        assert_eq!(
+            frame.header.msg_type,
+            MessageType::CommitMiningJob.to_primitive()
+        );
+
+        // Build the frame from raw bytes so that the payload actually gets deserialized
+        let mut writer = BytesMut::new().writer();
+        message
+            .serialize_to_writer(&mut writer)
+            .expect("Cannot serialize message");
+        let frame = framing::Frame::from_serialized_payload(
+            false,
+            extensions::JOB_NEGOTIATION,
+            MessageType::CommitMiningJob.to_primitive(),
+            writer.into_inner(),
+        );
+
+        let built_message = build_message_from_frame(frame).expect("Cannot build message");
+        assert_eq!(
+            built_message.header.extension_type,
+            extensions::JOB_NEGOTIATION
+        );
+        let mut handler = CommitMiningJobHandler(None);
+        built_message.accept(&mut handler).await;
+        assert_eq!(handler.0, Some(message));
+    }
+}
@@ -83,3 +83,50 @@ macro_rules! impl_message_conversion {
         }
     };
 }
+
+/// Generates `build_message_from_frame()` that consumes a frame and produces a Message object
+/// based on the payload type
+/// `protocol_name` - name of the protocol used in the trace message
+/// `message` - identifiers (tokens) of all messages recognized by the protocol
+#[macro_export]
+macro_rules! impl_build_message_from_frame {
+    ($protocol_name:expr, $($message:ident),+ $(,)?) => {
+        /// Consumes `frame` and produces a Message object based on the payload type
+        pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protocol>> {
+            trace!(
+                "V2: building {} message from frame {:x?}",
+                $protocol_name,
+                frame
+            );
+
+            // Payload that already contains deserialized message can be returned directly
+            if frame.payload.is_serializable() {
+                let (header, payload) = frame.split();
+                let serializable_payload = payload
+                    .into_serializable()
+                    .expect("BUG: cannot convert payload into serializable");
+
+                return Ok(Message {
+                    header,
+                    payload: serializable_payload,
+                });
+            }
+            // Header will be consumed by the subsequent transformation of the frame into the
+            // actual payload for further handling. Therefore we create a copy for constructing
+            // a Message<Protocol>
+            let header = frame.header.clone();
+            // Deserialize the payload based on its type specified in the header
+            let payload: Box<dyn AnyPayload<Protocol>> =
+                match MessageType::from_primitive(frame.header.msg_type).ok_or(
+                    error::ErrorKind::UnknownMessage(
+                        format!("Unexpected payload type, full header: {:x?}", frame.header)
+                            .into(),
+                    ),
+                )? {
+                    $(MessageType::$message => Box::new($message::try_from(frame)?),)+
+                };
+
+            Ok(Message { header, payload })
+        }
+    };
+}
@@ -133,11 +133,50 @@ pub struct OpenStandardMiningChannelError {
     pub code: Str0_32,
 }
 
+/// Extended channels allow the downstream node to roll the extranonce part of the coinbase
+/// transaction and to compute the merkle root on its own
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct UpdateChannel;
+pub struct OpenExtendedMiningChannel {
+    pub req_id: u32,
+    pub user: Str1_255,
+    pub nominal_hashrate: f32,
+    pub max_target: Uint256Bytes,
+    /// Minimal size of the extranonce the downstream node needs for rolling
+    pub min_extranonce_size: u16,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct UpdateChannelError;
+pub struct OpenExtendedMiningChannelSuccess {
+    pub req_id: u32,
+    pub channel_id: u32,
+    /// Initial target for mining
+    pub target: Uint256Bytes,
+    /// Size of the extranonce following `extranonce_prefix` available to the downstream node
+    pub extranonce_size: u16,
+    pub extranonce_prefix: Bytes0_32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpenExtendedMiningChannelError {
+    pub req_id: u32,
+    pub code: Str0_32,
+}
+
+/// Notifies the upstream node about a change of the channel. The server answers a new
+/// `max_target` by sending `SetTarget` once it has accepted it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UpdateChannel {
+    pub channel_id: u32,
+    pub nominal_hashrate: f32,
+    /// Maximal target (minimal difficulty) the downstream node requests for the channel
+    pub max_target: Uint256Bytes,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UpdateChannelError {
+    pub channel_id: u32,
+    pub code: Str0_32,
+}
 
 pub struct CloseChannel;
 
@@ -152,6 +191,20 @@ pub struct SubmitSharesStandard {
     pub version: u32,
 }
 
+/// Share of an extended channel carries the extranonce rolled by the downstream node (without
+/// the extranonce prefix of the channel)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubmitSharesExtended {
+    pub channel_id: u32,
+    pub seq_num: u32,
+    pub job_id: u32,
+
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+    pub extranonce: Bytes0_32,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SubmitSharesSuccess {
     pub channel_id: u32,
@@ -176,7 +229,20 @@ pub struct NewMiningJob {
     pub merkle_root: Uint256Bytes,
 }
 
-pub struct NewExtendedMiningJob;
+/// Job for extended channels. The coinbase transaction is serialized as `coinbase_tx_prefix`,
+/// extranonce prefix of the channel, extranonce rolled by the downstream node and
+/// `coinbase_tx_suffix`. The merkle root is computed from its hash and `merkle_path`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewExtendedMiningJob {
+    pub channel_id: u32,
+    pub job_id: u32,
+    pub future_job: bool,
+    pub version: u32,
+    pub version_rolling_allowed: bool,
+    pub merkle_path: Seq0_255<Uint256Bytes>,
+    pub coinbase_tx_prefix: Bytes0_64k,
+    pub coinbase_tx_suffix: Bytes0_64k,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SetNewPrevHash {
@@ -191,7 +257,14 @@ pub struct SetNewPrevHash {
 
 pub struct SetCustomMiningJob;
 pub struct SetCustomMiningJobSuccess;
-pub struct Reconnect;
+
+/// Instructs the downstream node to reconnect to another server. Empty `new_host` or zero
+/// `new_port` keep the current host or port, respectively.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Reconnect {
+    pub new_host: Str0_255,
+    pub new_port: u16,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SetTarget {
@@ -223,11 +296,33 @@ impl_base_message_conversion!(
     false,
     visit_open_standard_mining_channel_error
 );
+impl_base_message_conversion!(
+    OpenExtendedMiningChannel,
+    false,
+    visit_open_extended_mining_channel
+);
+impl_base_message_conversion!(
+    OpenExtendedMiningChannelSuccess,
+    false,
+    visit_open_extended_mining_channel_success
+);
+impl_base_message_conversion!(
+    OpenExtendedMiningChannelError,
+    false,
+    visit_open_extended_mining_channel_error
+);
 impl_base_message_conversion!(UpdateChannel, true, visit_update_channel);
 impl_base_message_conversion!(UpdateChannelError, true, visit_update_channel_error);
 impl_base_message_conversion!(SubmitSharesStandard, true, visit_submit_shares_standard);
+impl_base_message_conversion!(SubmitSharesExtended, true, visit_submit_shares_extended);
 impl_base_message_conversion!(SubmitSharesSuccess, true, visit_submit_shares_success);
 impl_base_message_conversion!(SubmitSharesError, true, visit_submit_shares_error);
 impl_base_message_conversion!(NewMiningJob, true, visit_new_mining_job);
+impl_base_message_conversion!(
+    NewExtendedMiningJob,
+    true,
+    visit_new_extended_mining_job
+);
 impl_base_message_conversion!(SetNewPrevHash, true, visit_set_new_prev_hash);
 impl_base_message_conversion!(SetTarget, true, visit_set_target);
+impl_base_message_conversion!(Reconnect, false, visit_reconnect);
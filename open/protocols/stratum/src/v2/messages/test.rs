@@ -55,3 +55,124 @@ fn test_serialize_setup_connection() {
         serialized_message
     );
 }
+
+#[test]
+fn test_reconnect_serialization() {
+    const RECONNECT_SERIALIZED: &[u8] = &[
+        0x0b, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm', // new_host
+        0x0d, 0x0d, // new_port
+    ];
+    let message = Reconnect {
+        new_host: Str0_255::from_str("example.com"),
+        new_port: 3341,
+    };
+    let mut writer = bytes::BytesMut::new().writer();
+    message
+        .serialize_to_writer(&mut writer)
+        .expect("Cannot serialize message");
+    assert_eq!(BytesMut::from(RECONNECT_SERIALIZED), writer.into_inner());
+
+    let deserialized = Reconnect::try_from(RECONNECT_SERIALIZED).expect("Deserialization failed");
+    assert_eq!(deserialized, message, "Deserialization is not correct");
+}
+
+#[test]
+fn test_update_channel_serialization() {
+    const UPDATE_CHANNEL_SERIALIZED: &[u8] = &[
+        0x01, 0x00, 0x00, 0x00, // channel_id
+        0x28, 0x6b, 0x6e, 0x4e, // nominal_hashrate
+        0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+        0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+        0xaa, 0xaa, // max_target
+    ];
+    let message = UpdateChannel {
+        channel_id: 1,
+        nominal_hashrate: 1e9,
+        max_target: Uint256Bytes([0xaa; 32]),
+    };
+    let mut writer = bytes::BytesMut::new().writer();
+    message
+        .serialize_to_writer(&mut writer)
+        .expect("Cannot serialize message");
+    assert_eq!(
+        BytesMut::from(UPDATE_CHANNEL_SERIALIZED),
+        writer.into_inner()
+    );
+
+    let deserialized =
+        UpdateChannel::try_from(UPDATE_CHANNEL_SERIALIZED).expect("Deserialization failed");
+    assert_eq!(deserialized, message, "Deserialization is not correct");
+}
+
+#[test]
+fn test_new_extended_mining_job_serialization() {
+    const NEW_EXTENDED_MINING_JOB_SERIALIZED: &[u8] = &[
+        0x01, 0x00, 0x00, 0x00, // channel_id
+        0x02, 0x00, 0x00, 0x00, // job_id
+        0x01, // future_job
+        0x00, 0x00, 0x00, 0x20, // version
+        0x01, // version_rolling_allowed
+        0x01, // merkle_path length
+        0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+        0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+        0xaa, 0xaa, // merkle_path[0]
+        0x02, 0x00, 0x01, 0x02, // coinbase_tx_prefix
+        0x01, 0x00, 0x03, // coinbase_tx_suffix
+    ];
+    let message = NewExtendedMiningJob {
+        channel_id: 1,
+        job_id: 2,
+        future_job: true,
+        version: 0x2000_0000,
+        version_rolling_allowed: true,
+        merkle_path: Seq0_255::from_vec(vec![Uint256Bytes([0xaa; 32])]),
+        coinbase_tx_prefix: Bytes0_64k::from_slice(&[0x01, 0x02]),
+        coinbase_tx_suffix: Bytes0_64k::from_slice(&[0x03]),
+    };
+    let mut writer = bytes::BytesMut::new().writer();
+    message
+        .serialize_to_writer(&mut writer)
+        .expect("Cannot serialize message");
+    assert_eq!(
+        BytesMut::from(NEW_EXTENDED_MINING_JOB_SERIALIZED),
+        writer.into_inner()
+    );
+
+    let deserialized = NewExtendedMiningJob::try_from(NEW_EXTENDED_MINING_JOB_SERIALIZED)
+        .expect("Deserialization failed");
+    assert_eq!(deserialized, message, "Deserialization is not correct");
+}
+
+#[test]
+fn test_submit_shares_extended_serialization() {
+    const SUBMIT_SHARES_EXTENDED_SERIALIZED: &[u8] = &[
+        0x01, 0x00, 0x00, 0x00, // channel_id
+        0x05, 0x00, 0x00, 0x00, // seq_num
+        0x02, 0x00, 0x00, 0x00, // job_id
+        0x78, 0x56, 0x34, 0x12, // nonce
+        0x00, 0xf1, 0x5e, 0x5f, // ntime
+        0x00, 0xe0, 0xff, 0x3f, // version
+        0x04, 0xde, 0xad, 0xbe, 0xef, // extranonce
+    ];
+    let message = SubmitSharesExtended {
+        channel_id: 1,
+        seq_num: 5,
+        job_id: 2,
+        nonce: 0x1234_5678,
+        ntime: 0x5f5e_f100,
+        version: 0x3fff_e000,
+        extranonce: Bytes0_32::from_slice(&[0xde, 0xad, 0xbe, 0xef]),
+    };
+    let mut writer = bytes::BytesMut::new().writer();
+    message
+        .serialize_to_writer(&mut writer)
+        .expect("Cannot serialize message");
+    assert_eq!(
+        BytesMut::from(SUBMIT_SHARES_EXTENDED_SERIALIZED),
+        writer.into_inner()
+    );
+
+    let deserialized = SubmitSharesExtended::try_from(SUBMIT_SHARES_EXTENDED_SERIALIZED)
+        .expect("Deserialization failed");
+    assert_eq!(deserialized, message, "Deserialization is not correct");
+}
@@ -166,12 +166,14 @@ impl handshake::Step for Initiator {
                 let in_msg = in_msg.ok_or(ErrorKind::Noise("No message arrived".to_string()))?;
                 let signature_len = self.handshake_state.read_message(&in_msg.inner, &mut buf)?;
                 self.verify_remote_static_key_signature(BytesMut::from(&buf[..signature_len]))
-                    .context("Certificate signature verification")?;
+                    .map_err(|e| {
+                        ErrorKind::Noise(format!("Certificate signature verification: {}", e))
+                    })?;
                 handshake::StepResult::Done
             }
-            _ => {
-                panic!("BUG: No more steps that can be done by the Initiator in Noise handshake");
-            }
+            _ => Err(ErrorKind::Noise(
+                "No more steps that can be done by the Initiator in Noise handshake".to_string(),
+            ))?,
         };
         self.stage += 1;
         Ok(result)
@@ -244,9 +246,9 @@ impl handshake::Step for Responder {
                 handshake::StepResult::NoMoreReply(handshake::Message::new(noise_bytes))
             }
             2 => handshake::StepResult::Done,
-            _ => {
-                panic!("BUG: No more steps that can be done by the Initiator in Noise handshake");
-            }
+            _ => Err(ErrorKind::Noise(
+                "No more steps that can be done by the Responder in Noise handshake".to_string(),
+            ))?,
         };
         self.stage += 1;
         Ok(result)
@@ -433,6 +435,51 @@ pub(crate) mod test {
         assert_eq!(&message[..], &decrypted_msg, "Messages don't match");
     }
 
+    /// Verifies that the initiator refuses a responder whose static key has not been signed by the
+    /// expected authority and that the failure is reported as a noise error
+    #[test]
+    fn test_handshake_unknown_authority() {
+        let (signature_noise_message, _authority_keypair, static_keypair) =
+            build_serialized_signature_noise_message_and_keypairs();
+        let (_, foreign_authority_keypair, _) =
+            build_serialized_signature_noise_message_and_keypairs();
+
+        let mut initiator = Initiator::new(foreign_authority_keypair.public);
+        let mut responder = Responder::new(&static_keypair, signature_noise_message);
+
+        assert_eq!(
+            responder
+                .step(None, BytesMut::new())
+                .expect("BUG: responder failed in the first step"),
+            handshake::StepResult::ReceiveMessage
+        );
+        let initiator_out_msg = match initiator
+            .step(None, BytesMut::new())
+            .expect("BUG: initiator failed in the first step")
+        {
+            handshake::StepResult::ExpectReply(msg) => msg,
+            result => panic!("BUG: unexpected initiator step result {:?}", result),
+        };
+        let responder_out_msg = match responder
+            .step(Some(initiator_out_msg), BytesMut::new())
+            .expect("BUG: responder failed")
+        {
+            handshake::StepResult::NoMoreReply(msg) => msg,
+            result => panic!("BUG: unexpected responder step result {:?}", result),
+        };
+
+        let error = initiator
+            .step(Some(responder_out_msg), BytesMut::new())
+            .expect_err("BUG: initiator accepted certificate of unknown authority");
+        match error.kind() {
+            ErrorKind::Noise(_) => {}
+            kind => panic!("BUG: unexpected error kind {:?}", kind),
+        }
+
+        // No further steps can be performed by the initiator
+        assert!(initiator.step(None, BytesMut::new()).is_err());
+    }
+
     fn bind_test_server() -> Option<(ii_wire::Server, ii_wire::Address)> {
         const ADDR: &'static str = "127.0.0.1";
         const MIN_PORT: u16 = 9999;
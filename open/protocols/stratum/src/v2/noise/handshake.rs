@@ -23,6 +23,7 @@
 //! Provides necessary infrastructure to run handshake on a noise framed stream
 
 use bytes::BytesMut;
+use failure::Fail;
 use snow::HandshakeState;
 use std::time;
 
@@ -108,8 +109,26 @@ where
         Ok(Message::new(handshake_frame))
     }
 
-    /// Consumes the handshake object and drives the inner `Step`
+    /// Consumes the handshake object and drives the inner `Step`. Any failure that occurs during
+    /// the handshake (I/O, timeout, invalid message or certificate) is reported as
+    /// `ErrorKind::Noise` so that the transport user can tell a failed handshake apart from other
+    /// connection errors.
     pub(super) async fn run(
+        self,
+        handshake_stream: &mut super::NoiseFramedTcpStream,
+    ) -> Result<super::TransportMode> {
+        self.drive(handshake_stream)
+            .await
+            .map_err(|e| match e.kind() {
+                ErrorKind::Noise(_) => e,
+                _ => {
+                    let msg = e.to_string();
+                    e.into_inner().context(ErrorKind::Noise(msg)).into()
+                }
+            })
+    }
+
+    async fn drive(
         mut self,
         handshake_stream: &mut super::NoiseFramedTcpStream,
     ) -> Result<super::TransportMode> {
@@ -0,0 +1,220 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Template Distribution protocol messages exchanged with the template provider
+
+#[cfg(not(feature = "v2json"))]
+use crate::v2::serialization;
+use crate::{
+    error::{Error, Result},
+    v2::{error, extensions, framing, types::*, Protocol},
+    AnyPayload, Message,
+};
+use async_trait::async_trait;
+use packed_struct::prelude::*;
+use packed_struct_codegen::PrimitiveEnum_u8;
+use serde;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "v2json")]
+use serde_json as serialization;
+use std::convert::TryFrom;
+
+use ii_logging::macros::*;
+
+/// Generates conversion for template distribution protocol messages
+macro_rules! impl_template_distribution_message_conversion {
+    ($message:tt, $is_channel_msg:expr, $handler_fn:tt) => {
+        impl_message_conversion!(
+            extensions::TEMPLATE_DISTRIBUTION,
+            $message,
+            $is_channel_msg,
+            $handler_fn
+        );
+    };
+}
+
+/// All message recognized by the protocol
+#[derive(PrimitiveEnum_u8, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MessageType {
+    CoinbaseOutputDataSize = 0x70,
+    NewTemplate = 0x71,
+    SetNewPrevHash = 0x72,
+    RequestTransactionData = 0x73,
+    RequestTransactionDataSuccess = 0x74,
+    RequestTransactionDataError = 0x75,
+    SubmitSolution = 0x76,
+}
+
+/// Maximum size of additional coinbase outputs that the client adds to templates
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CoinbaseOutputDataSize {
+    pub coinbase_output_max_additional_size: u32,
+}
+
+/// Block template without the transactions. The transaction data can be requested with
+/// `RequestTransactionData`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NewTemplate {
+    pub template_id: u64,
+    /// The template is intended for the next `SetNewPrevHash`
+    pub future_template: bool,
+    pub version: u32,
+    pub coinbase_tx_version: u32,
+    /// Beginning of the coinbase input script (the block height)
+    pub coinbase_prefix: Bytes0_255,
+    pub coinbase_tx_input_sequence: u32,
+    /// Value available for the coinbase outputs added by the client
+    pub coinbase_tx_value_remaining: u64,
+    pub coinbase_tx_outputs_count: u32,
+    /// Serialized outputs that have to be included in the coinbase
+    pub coinbase_tx_outputs: Bytes0_64k,
+    pub coinbase_tx_locktime: u32,
+    /// Merkle path of the coinbase transaction
+    pub merkle_path: Seq0_255<Uint256Bytes>,
+}
+
+/// New block on the network, the referenced (future) template becomes the only valid one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SetNewPrevHash {
+    pub template_id: u64,
+    pub prev_hash: Uint256Bytes,
+    pub header_timestamp: u32,
+    pub n_bits: u32,
+    /// Network target
+    pub target: Uint256Bytes,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RequestTransactionData {
+    pub template_id: u64,
+}
+
+/// Transactions of the template (without the coinbase)
+/// TODO: the specification allows transactions up to 16 MB, the serialization currently
+///  supports only sequences with up to 16 bit length prefix
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RequestTransactionDataSuccess {
+    pub template_id: u64,
+    pub excess_data: Bytes0_64k,
+    pub transaction_list: Seq0_64k<Bytes0_64k>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RequestTransactionDataError {
+    pub template_id: u64,
+    pub error_code: Str0_255,
+}
+
+/// Solution of the template that meets the network target
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubmitSolution {
+    pub template_id: u64,
+    pub version: u32,
+    pub header_timestamp: u32,
+    pub header_nonce: u32,
+    pub coinbase_tx: Bytes0_64k,
+}
+
+impl_template_distribution_message_conversion!(
+    CoinbaseOutputDataSize,
+    false,
+    visit_coinbase_output_data_size
+);
+impl_template_distribution_message_conversion!(NewTemplate, false, visit_new_template);
+impl_template_distribution_message_conversion!(
+    SetNewPrevHash,
+    false,
+    visit_template_set_new_prev_hash
+);
+impl_template_distribution_message_conversion!(
+    RequestTransactionData,
+    false,
+    visit_request_transaction_data
+);
+impl_template_distribution_message_conversion!(
+    RequestTransactionDataSuccess,
+    false,
+    visit_request_transaction_data_success
+);
+impl_template_distribution_message_conversion!(
+    RequestTransactionDataError,
+    false,
+    visit_request_transaction_data_error
+);
+impl_template_distribution_message_conversion!(SubmitSolution, false, visit_submit_solution);
+
+impl_build_message_from_frame!(
+    "template distribution",
+    CoinbaseOutputDataSize,
+    NewTemplate,
+    SetNewPrevHash,
+    RequestTransactionData,
+    RequestTransactionDataSuccess,
+    RequestTransactionDataError,
+    SubmitSolution,
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::payload::SerializablePayload;
+
+    use bytes::{buf::BufMutExt, BytesMut};
+    use ii_async_compat::bytes;
+
+    #[test]
+    fn test_new_template_serialization() {
+        let message = NewTemplate {
+            template_id: 7,
+            future_template: true,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: Bytes0_255::from_slice(&[0x03, 0x01, 0x02, 0x03]),
+            coinbase_tx_input_sequence: 0xffff_ffff,
+            coinbase_tx_value_remaining: 625_000_000,
+            coinbase_tx_outputs_count: 1,
+            coinbase_tx_outputs: Bytes0_64k::from_slice(&[0xaa; 10]),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0_255::from_vec(vec![
+                Uint256Bytes([0x11; 32]),
+                Uint256Bytes([0x22; 32]),
+            ]),
+        };
+        let mut writer = BytesMut::new().writer();
+        message
+            .serialize_to_writer(&mut writer)
+            .expect("Cannot serialize message");
+        let serialized = writer.into_inner();
+
+        // template_id + future_template
+        assert_eq!(
+            &serialized[..9],
+            &[0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]
+        );
+        // merkle path is the last field, 1 byte length followed by the hashes
+        assert_eq!(serialized[serialized.len() - 65], 2);
+        assert_eq!(&serialized[serialized.len() - 32..], &[0x22; 32][..]);
+
+        let deserialized = NewTemplate::try_from(&serialized[..]).expect("Deserialization failed");
+        assert_eq!(deserialized, message, "Deserialization is not correct");
+    }
+}
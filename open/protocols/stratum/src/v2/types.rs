@@ -384,6 +384,15 @@ macro_rules! sized_seq_type {
 
         impl<T> Eq for $name<T> where T: Serialize + for<'dx> Deserialize<'dx> + PartialEq {}
 
+        impl<T> Clone for $name<T>
+        where
+            T: Serialize + for<'dx> Deserialize<'dx> + Clone,
+        {
+            fn clone(&self) -> Self {
+                Self(self.0.clone())
+            }
+        }
+
         impl<T> Debug for $name<T>
         where
             T: Serialize + for<'dx> Deserialize<'dx> + Debug,
@@ -43,6 +43,11 @@ impl Server {
 
         Ok(Server { tcp })
     }
+
+    /// Address the server is listening on (useful when bound to port 0)
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.tcp.local_addr()
+    }
 }
 
 impl Stream for Server {
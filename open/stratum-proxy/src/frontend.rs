@@ -65,6 +65,28 @@ pub struct Args {
     /// Secret key as counter part of the public key in the configured public certificate
     #[structopt(short = "s", long, parse(from_os_str), required_unless("insecure"))]
     pub secret_key_file: Option<PathBuf>,
+
+    /// Local template provider (e.g. bitcoind) whose templates are negotiated with the pool
+    #[structopt(
+        long,
+        value_name = "HOSTNAME:PORT",
+        requires_all(&["job-negotiator", "job-negotiation-user"]),
+        help = "Address of the template provider serving Template Distribution protocol"
+    )]
+    pub template_provider: Option<Address>,
+
+    /// Job negotiation endpoint of the pool
+    #[structopt(
+        long,
+        value_name = "HOSTNAME:PORT",
+        requires("template-provider"),
+        help = "Address of the pool endpoint serving Job Negotiation protocol"
+    )]
+    pub job_negotiator: Option<Address>,
+
+    /// User identifier presented to the pool when allocating mining job tokens
+    #[structopt(long, requires("template-provider"))]
+    pub job_negotiation_user: Option<String>,
 }
 
 impl Args {
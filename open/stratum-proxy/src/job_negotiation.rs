@@ -0,0 +1,871 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Client side of the Job Negotiation and Template Distribution protocols. The negotiator
+//! receives templates from a local template provider (e.g. bitcoind), downloads their transaction
+//! data and proposes them to the pool. Jobs accepted by the pool are emitted together with the
+//! token that identifies them in the mining protocol.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::hash::{BuildHasher, Hasher};
+
+use ii_async_compat::futures;
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+
+use bitcoin_hashes::{sha256, sha256d, Hash, HashEngine};
+
+use ii_stratum::v2::{
+    self,
+    job_negotiation::messages as jn,
+    template_distribution::messages as td,
+    types::{Bytes0_255, Bytes0_64k, Seq0_64k, Uint256Bytes},
+};
+
+use ii_async_compat::prelude::*;
+use ii_async_compat::select;
+use ii_logging::macros::*;
+use ii_wire::{Address, Connection};
+
+use crate::error::{Result, ResultExt};
+use crate::translation::SeqId;
+use crate::util;
+
+/// Minimum extranonce size required for the negotiated jobs
+pub const DEFAULT_MIN_EXTRANONCE_SIZE: u16 = 8;
+
+/// Short transaction hashes are truncated to 6 bytes
+const SHORT_TX_HASH_MASK: u64 = 0xffff_ffff_ffff;
+
+/// Template with downloaded transaction data
+#[derive(Clone, Debug)]
+struct Template {
+    template: td::NewTemplate,
+    /// Full transaction data in the block order
+    transactions: Vec<Vec<u8>>,
+    /// Transaction hashes in the block order
+    tx_hashes: Vec<sha256d::Hash>,
+}
+
+impl Template {
+    fn new(template: td::NewTemplate, transactions: Vec<Vec<u8>>) -> Self {
+        let tx_hashes = transactions
+            .iter()
+            .map(|tx| sha256d::Hash::hash(tx))
+            .collect();
+        Self {
+            template,
+            transactions,
+            tx_hashes,
+        }
+    }
+
+    /// Hash of all transaction hashes in the block order
+    fn tx_hash_list_hash(&self) -> Uint256Bytes {
+        let mut engine = sha256::Hash::engine();
+        for tx_hash in self.tx_hashes.iter() {
+            engine.input(&tx_hash[..]);
+        }
+        Uint256Bytes(sha256::Hash::from_engine(engine).into_inner())
+    }
+}
+
+/// Job accepted by the pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct NegotiatedJob {
+    pub template: td::NewTemplate,
+    /// Full transaction data in the block order
+    pub transactions: Vec<Vec<u8>>,
+    /// Token identifying the job in the mining protocol (`SetCustomMiningJob`)
+    pub mining_job_token: Vec<u8>,
+}
+
+/// SipHash-2-4 keys derived from the nonce of short transaction hashes
+fn short_tx_hash_keys(nonce: u64) -> (u64, u64) {
+    let digest = sha256::Hash::hash(&nonce.to_le_bytes()).into_inner();
+    let k0 = u64::from_le_bytes(digest[..8].try_into().expect("BUG: key size"));
+    let k1 = u64::from_le_bytes(digest[8..16].try_into().expect("BUG: key size"));
+    (k0, k1)
+}
+
+/// Computes short transaction hash as SipHash-2-4 of the transaction hash truncated to 6 bytes
+#[allow(deprecated)]
+fn short_tx_hash(keys: (u64, u64), tx_hash: &sha256d::Hash) -> u64 {
+    // the standard library implementation is deprecated only because it is not guaranteed to be
+    // used for `HashMap`, the algorithm itself is stable
+    let mut hasher = std::hash::SipHasher::new_with_keys(keys.0, keys.1);
+    hasher.write(&tx_hash[..]);
+    hasher.finish() & SHORT_TX_HASH_MASK
+}
+
+/// Negotiates jobs built from local templates with the pool. Incoming frames from both
+/// connections are passed to `handle_frame()`, responses are submitted to the respective
+/// queues.
+pub struct JobNegotiator {
+    /// Frames for the template provider
+    template_provider_tx: mpsc::Sender<v2::Frame>,
+    /// Frames for the pool
+    pool_tx: mpsc::Sender<v2::Frame>,
+    /// Jobs accepted by the pool
+    job_tx: mpsc::Sender<NegotiatedJob>,
+    user_identifier: String,
+    min_extranonce_size: u16,
+    /// Nonce for short transaction hashes, it is randomized per negotiator so that the
+    /// collisions are not correlated across the network
+    tx_short_hash_nonce: u64,
+    req_id: SeqId,
+    /// Token for the next job commit
+    mining_job_token: Option<Bytes0_255>,
+    /// Token allocation has been requested and no response arrived yet
+    token_requested: bool,
+    /// Templates waiting for their transaction data
+    pending_templates: HashMap<u64, td::NewTemplate>,
+    /// Template that is ready to be committed once there is a token
+    ready_template: Option<Template>,
+    /// Future templates with downloaded transaction data waiting for their `SetNewPrevHash`
+    future_templates: HashMap<u64, Template>,
+    /// Templates of jobs committed to the pool indexed by request ID
+    committed_jobs: HashMap<u32, Template>,
+    /// ID of the template that is built on top of the current best block
+    current_template_id: Option<u64>,
+}
+
+impl JobNegotiator {
+    pub fn new(
+        template_provider_tx: mpsc::Sender<v2::Frame>,
+        pool_tx: mpsc::Sender<v2::Frame>,
+        job_tx: mpsc::Sender<NegotiatedJob>,
+        user_identifier: String,
+    ) -> Self {
+        Self {
+            template_provider_tx,
+            pool_tx,
+            job_tx,
+            user_identifier,
+            min_extranonce_size: DEFAULT_MIN_EXTRANONCE_SIZE,
+            tx_short_hash_nonce: RandomState::new().build_hasher().finish(),
+            req_id: SeqId::new(),
+            mining_job_token: None,
+            token_requested: false,
+            pending_templates: HashMap::new(),
+            ready_template: None,
+            future_templates: HashMap::new(),
+            committed_jobs: HashMap::new(),
+            current_template_id: None,
+        }
+    }
+
+    /// Starts negotiation by requesting the first job token from the pool
+    pub fn start(&mut self) -> Result<()> {
+        self.request_mining_job_token()
+    }
+
+    /// Dispatches a frame received from either the pool or the template provider
+    pub async fn handle_frame(&mut self, frame: v2::Frame) -> Result<()> {
+        let message = match frame.header.extension_type {
+            v2::extensions::JOB_NEGOTIATION => jn::build_message_from_frame(frame)?,
+            v2::extensions::TEMPLATE_DISTRIBUTION => td::build_message_from_frame(frame)?,
+            extension_type => {
+                return Err(format!(
+                    "Unexpected extension type {:#06x}, full header: {:x?}",
+                    extension_type, frame.header
+                )
+                .into())
+            }
+        };
+        message.accept(self).await;
+        Ok(())
+    }
+
+    fn request_mining_job_token(&mut self) -> Result<()> {
+        if self.token_requested {
+            return Ok(());
+        }
+        let request = jn::AllocateMiningJobToken {
+            user_identifier: self
+                .user_identifier
+                .as_str()
+                .try_into()
+                .map_err(|_| "User identifier too long")?,
+            request_id: self.req_id.next(),
+        };
+        util::submit_message(&mut self.pool_tx, request)?;
+        self.token_requested = true;
+        Ok(())
+    }
+
+    /// Commits the ready template when there is a token for it
+    fn try_commit_mining_job(&mut self) -> Result<()> {
+        if self.ready_template.is_none() || self.mining_job_token.is_none() {
+            return Ok(());
+        }
+        let template = self.ready_template.take().expect("BUG: missing template");
+        let mining_job_token = self.mining_job_token.take().expect("BUG: missing token");
+
+        let keys = short_tx_hash_keys(self.tx_short_hash_nonce);
+        let tx_short_hash_list = template
+            .tx_hashes
+            .iter()
+            .map(|tx_hash| short_tx_hash(keys, tx_hash))
+            .collect::<Vec<_>>();
+        let request_id = self.req_id.next();
+        let commit = jn::CommitMiningJob {
+            request_id,
+            mining_job_token,
+            version: template.template.version,
+            coinbase_tx_version: template.template.coinbase_tx_version,
+            coinbase_prefix: template.template.coinbase_prefix.clone(),
+            coinbase_tx_input_n_sequence: template.template.coinbase_tx_input_sequence,
+            coinbase_tx_value_remaining: template.template.coinbase_tx_value_remaining,
+            coinbase_tx_outputs: template.template.coinbase_tx_outputs.clone(),
+            coinbase_tx_locktime: template.template.coinbase_tx_locktime,
+            min_extranonce_size: self.min_extranonce_size,
+            tx_short_hash_nonce: self.tx_short_hash_nonce,
+            tx_short_hash_list: tx_short_hash_list
+                .try_into()
+                .map_err(|_| "Too many transactions in template")?,
+            tx_hash_list_hash: template.tx_hash_list_hash(),
+            excess_data: Bytes0_64k::new(),
+        };
+        util::submit_message(&mut self.pool_tx, commit)?;
+        self.committed_jobs.insert(request_id, template);
+
+        // Have a token ready for the next template
+        self.request_mining_job_token()
+    }
+
+    fn handle_new_template(&mut self, template: &td::NewTemplate) -> Result<()> {
+        if !template.future_template {
+            self.current_template_id = Some(template.template_id);
+        }
+        self.pending_templates
+            .insert(template.template_id, template.clone());
+        util::submit_message(
+            &mut self.template_provider_tx,
+            td::RequestTransactionData {
+                template_id: template.template_id,
+            },
+        )
+    }
+
+    fn handle_transaction_data(&mut self, msg: &td::RequestTransactionDataSuccess) -> Result<()> {
+        let template = match self.pending_templates.remove(&msg.template_id) {
+            Some(template) => template,
+            None => {
+                debug!("Transaction data of stale template {}", msg.template_id);
+                return Ok(());
+            }
+        };
+        let transactions = msg
+            .transaction_list
+            .iter()
+            .map(|tx| tx.as_ref().to_vec())
+            .collect();
+        let template = Template::new(template, transactions);
+        if template.template.future_template {
+            self.future_templates.insert(msg.template_id, template);
+        } else if Some(msg.template_id) == self.current_template_id {
+            // The newest template always replaces the one that has not been committed yet
+            self.ready_template = Some(template);
+            self.try_commit_mining_job()?;
+        } else {
+            debug!("Transaction data of outdated template {}", msg.template_id);
+        }
+        Ok(())
+    }
+
+    /// Drops all templates and commits that are not built on top of the new best block
+    fn handle_new_prev_hash(&mut self, msg: &td::SetNewPrevHash) -> Result<()> {
+        self.current_template_id = Some(msg.template_id);
+        self.pending_templates
+            .retain(|template_id, _| *template_id == msg.template_id);
+        self.committed_jobs
+            .retain(|_, template| template.template.template_id == msg.template_id);
+        // The activated future template is the only one valid for the new block
+        match self.future_templates.remove(&msg.template_id) {
+            Some(template) => self.ready_template = Some(template),
+            None => {
+                if let Some(template) = self.ready_template.take() {
+                    if template.template.template_id == msg.template_id {
+                        self.ready_template = Some(template);
+                    }
+                }
+            }
+        }
+        self.future_templates.clear();
+        self.try_commit_mining_job()
+    }
+
+    fn handle_commit_success(&mut self, msg: &jn::CommitMiningJobSuccess) -> Result<()> {
+        let template = self
+            .committed_jobs
+            .remove(&msg.request_id)
+            .ok_or_else(|| format!("Unknown commit request ID {}", msg.request_id))?;
+        let job = NegotiatedJob {
+            template: template.template,
+            transactions: template.transactions,
+            mining_job_token: msg.new_mining_job_token.as_ref().to_vec(),
+        };
+        self.job_tx.try_send(job).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn handle_identify_transactions(&mut self, msg: &jn::IdentifyTransactions) -> Result<()> {
+        let template = self
+            .committed_jobs
+            .get(&msg.request_id)
+            .ok_or_else(|| format!("Unknown commit request ID {}", msg.request_id))?;
+        let tx_data_hashes = template
+            .tx_hashes
+            .iter()
+            .map(|tx_hash| Uint256Bytes(tx_hash.into_inner()))
+            .collect::<Vec<_>>();
+        util::submit_message(
+            &mut self.pool_tx,
+            jn::IdentifyTransactionsSuccess {
+                request_id: msg.request_id,
+                tx_data_hashes: Seq0_64k::from_vec(tx_data_hashes),
+            },
+        )
+    }
+
+    fn handle_provide_missing_transactions(
+        &mut self,
+        msg: &jn::ProvideMissingTransactions,
+    ) -> Result<()> {
+        let template = self
+            .committed_jobs
+            .get(&msg.request_id)
+            .ok_or_else(|| format!("Unknown commit request ID {}", msg.request_id))?;
+        let mut transaction_list = Vec::new();
+        for &position in msg.unknown_tx_position_list.iter() {
+            let tx = template
+                .transactions
+                .get(position as usize)
+                .ok_or_else(|| format!("Transaction position {} out of range", position))?;
+            transaction_list
+                .push(Bytes0_64k::try_from(tx.clone()).map_err(|_| "Too large transaction")?);
+        }
+        util::submit_message(
+            &mut self.pool_tx,
+            jn::ProvideMissingTransactionsSuccess {
+                request_id: msg.request_id,
+                transaction_list: Seq0_64k::from_vec(transaction_list),
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl v2::Handler for JobNegotiator {
+    async fn visit_allocate_mining_job_token_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &jn::AllocateMiningJobTokenSuccess,
+    ) {
+        trace!("visit_allocate_mining_job_token_success() {:?}", payload);
+        self.token_requested = false;
+        self.mining_job_token = Some(payload.mining_job_token.clone());
+        // The template provider has to leave space in coinbase for outputs of the pool
+        let data_size = td::CoinbaseOutputDataSize {
+            coinbase_output_max_additional_size: payload.coinbase_output_max_additional_size,
+        };
+        if let Err(e) = util::submit_message(&mut self.template_provider_tx, data_size) {
+            info!("Cannot submit CoinbaseOutputDataSize: {:?}", e);
+            return;
+        }
+        if let Err(e) = self.try_commit_mining_job() {
+            info!("Cannot commit mining job: {:?}", e);
+        }
+    }
+
+    async fn visit_commit_mining_job_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &jn::CommitMiningJobSuccess,
+    ) {
+        trace!("visit_commit_mining_job_success() {:?}", payload);
+        if let Err(e) = self.handle_commit_success(payload) {
+            info!("Cannot process CommitMiningJobSuccess: {:?}", e);
+        }
+    }
+
+    async fn visit_commit_mining_job_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &jn::CommitMiningJobError,
+    ) {
+        info!(
+            "Pool rejected job {}: {}",
+            payload.request_id,
+            payload.error_code.to_string()
+        );
+        self.committed_jobs.remove(&payload.request_id);
+    }
+
+    async fn visit_identify_transactions(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &jn::IdentifyTransactions,
+    ) {
+        trace!("visit_identify_transactions() {:?}", payload);
+        if let Err(e) = self.handle_identify_transactions(payload) {
+            info!("Cannot identify transactions: {:?}", e);
+        }
+    }
+
+    async fn visit_provide_missing_transactions(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &jn::ProvideMissingTransactions,
+    ) {
+        trace!("visit_provide_missing_transactions() {:?}", payload);
+        if let Err(e) = self.handle_provide_missing_transactions(payload) {
+            info!("Cannot provide missing transactions: {:?}", e);
+        }
+    }
+
+    async fn visit_new_template(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &td::NewTemplate,
+    ) {
+        trace!("visit_new_template() {:?}", payload);
+        if let Err(e) = self.handle_new_template(payload) {
+            info!("Cannot request transaction data: {:?}", e);
+        }
+    }
+
+    async fn visit_template_set_new_prev_hash(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &td::SetNewPrevHash,
+    ) {
+        trace!("visit_template_set_new_prev_hash() {:?}", payload);
+        if let Err(e) = self.handle_new_prev_hash(payload) {
+            info!("Cannot commit mining job: {:?}", e);
+        }
+    }
+
+    async fn visit_request_transaction_data_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &td::RequestTransactionDataSuccess,
+    ) {
+        trace!(
+            "visit_request_transaction_data_success() template_id={}",
+            payload.template_id
+        );
+        if let Err(e) = self.handle_transaction_data(payload) {
+            info!("Cannot commit mining job: {:?}", e);
+        }
+    }
+
+    async fn visit_request_transaction_data_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &td::RequestTransactionDataError,
+    ) {
+        info!(
+            "Template provider cannot provide transactions of template {}: {}",
+            payload.template_id,
+            payload.error_code.to_string()
+        );
+        self.pending_templates.remove(&payload.template_id);
+    }
+}
+
+/// Connects the negotiator to the template provider and to the job negotiation endpoint of the
+/// pool and drives it until either of the connections terminates
+pub async fn run(
+    template_provider_addr: Address,
+    pool_addr: Address,
+    user_identifier: String,
+    job_tx: mpsc::Sender<NegotiatedJob>,
+) -> Result<()> {
+    const MAX_CHANNEL_SIZE: usize = 10;
+
+    let template_provider = template_provider_addr
+        .connect()
+        .await
+        .context("Template provider connection")?;
+    let pool = pool_addr
+        .connect()
+        .await
+        .context("Job negotiation connection")?;
+    let (mut template_provider_conn_tx, mut template_provider_conn_rx) =
+        Connection::<v2::Framing>::new(template_provider)
+            .into_inner()
+            .split();
+    let (mut pool_conn_tx, mut pool_conn_rx) =
+        Connection::<v2::Framing>::new(pool).into_inner().split();
+    info!(
+        "Negotiating jobs of template provider {} with pool {}",
+        template_provider_addr, pool_addr
+    );
+
+    let (template_provider_tx, mut template_provider_rx) = mpsc::channel(MAX_CHANNEL_SIZE);
+    let (pool_tx, mut pool_rx) = mpsc::channel(MAX_CHANNEL_SIZE);
+    let mut negotiator = JobNegotiator::new(template_provider_tx, pool_tx, job_tx, user_identifier);
+    negotiator.start()?;
+
+    loop {
+        select! {
+            frame = template_provider_conn_rx.next().fuse() => match frame {
+                Some(frame) => negotiator.handle_frame(frame?).await?,
+                None => Err("Template provider disconnected")?,
+            },
+            frame = pool_conn_rx.next().fuse() => match frame {
+                Some(frame) => negotiator.handle_frame(frame?).await?,
+                None => Err("Pool disconnected")?,
+            },
+            frame = template_provider_rx.next().fuse() => {
+                let frame = frame.expect("BUG: negotiator dropped template provider queue");
+                template_provider_conn_tx.send(frame).await?;
+            },
+            frame = pool_rx.next().fuse() => {
+                let frame = frame.expect("BUG: negotiator dropped pool queue");
+                pool_conn_tx.send(frame).await?;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::stream::StreamExt;
+
+    use ii_async_compat::tokio;
+    use ii_stratum::v2::types::{Seq0_255, Str0_255};
+
+    struct TestNegotiator {
+        negotiator: JobNegotiator,
+        template_provider_rx: mpsc::Receiver<v2::Frame>,
+        pool_rx: mpsc::Receiver<v2::Frame>,
+        job_rx: mpsc::Receiver<NegotiatedJob>,
+    }
+
+    impl TestNegotiator {
+        fn new() -> Self {
+            let (template_provider_tx, template_provider_rx) = mpsc::channel(10);
+            let (pool_tx, pool_rx) = mpsc::channel(10);
+            let (job_tx, job_rx) = mpsc::channel(10);
+            Self {
+                negotiator: JobNegotiator::new(
+                    template_provider_tx,
+                    pool_tx,
+                    job_tx,
+                    "miner".to_string(),
+                ),
+                template_provider_rx,
+                pool_rx,
+                job_rx,
+            }
+        }
+
+        async fn receive<M>(&mut self, message: M)
+        where
+            M: TryInto<v2::Frame, Error = ii_stratum::error::Error>,
+        {
+            let frame = message.try_into().expect("Cannot create frame");
+            self.negotiator
+                .handle_frame(frame)
+                .await
+                .expect("Cannot handle frame");
+        }
+    }
+
+    /// Lets `handler` visit the next message submitted into `rx`
+    async fn expect_message<H>(rx: &mut mpsc::Receiver<v2::Frame>, handler: &mut H)
+    where
+        H: v2::Handler,
+    {
+        let frame = rx.next().await.expect("Missing message");
+        let message = match frame.header.extension_type {
+            v2::extensions::JOB_NEGOTIATION => jn::build_message_from_frame(frame),
+            _ => td::build_message_from_frame(frame),
+        }
+        .expect("Cannot build message");
+        message.accept(handler).await;
+    }
+
+    /// Captures messages submitted by the negotiator
+    #[derive(Default)]
+    struct Captured {
+        token_request: Option<jn::AllocateMiningJobToken>,
+        data_size: Option<td::CoinbaseOutputDataSize>,
+        data_request: Option<td::RequestTransactionData>,
+        commit: Option<jn::CommitMiningJob>,
+        missing: Option<jn::ProvideMissingTransactionsSuccess>,
+    }
+
+    #[async_trait]
+    impl v2::Handler for Captured {
+        async fn visit_allocate_mining_job_token(
+            &mut self,
+            _header: &v2::framing::Header,
+            payload: &jn::AllocateMiningJobToken,
+        ) {
+            self.token_request = Some(payload.clone());
+        }
+
+        async fn visit_coinbase_output_data_size(
+            &mut self,
+            _header: &v2::framing::Header,
+            payload: &td::CoinbaseOutputDataSize,
+        ) {
+            self.data_size = Some(payload.clone());
+        }
+
+        async fn visit_request_transaction_data(
+            &mut self,
+            _header: &v2::framing::Header,
+            payload: &td::RequestTransactionData,
+        ) {
+            self.data_request = Some(payload.clone());
+        }
+
+        async fn visit_commit_mining_job(
+            &mut self,
+            _header: &v2::framing::Header,
+            payload: &jn::CommitMiningJob,
+        ) {
+            self.commit = Some(payload.clone());
+        }
+
+        async fn visit_provide_missing_transactions_success(
+            &mut self,
+            _header: &v2::framing::Header,
+            payload: &jn::ProvideMissingTransactionsSuccess,
+        ) {
+            self.missing = Some(payload.clone());
+        }
+    }
+
+    fn build_new_template(template_id: u64) -> td::NewTemplate {
+        td::NewTemplate {
+            template_id,
+            future_template: false,
+            version: 0x2000_0000,
+            coinbase_tx_version: 2,
+            coinbase_prefix: Bytes0_255::from_slice(&[0x03, 0x01, 0x02, 0x03]),
+            coinbase_tx_input_sequence: 0xffff_ffff,
+            coinbase_tx_value_remaining: 625_000_000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: Bytes0_64k::new(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0_255::new(),
+        }
+    }
+
+    fn build_transaction_data(template_id: u64) -> td::RequestTransactionDataSuccess {
+        td::RequestTransactionDataSuccess {
+            template_id,
+            excess_data: Bytes0_64k::new(),
+            transaction_list: Seq0_64k::from_vec(vec![
+                Bytes0_64k::from_slice(&[0x01; 60]),
+                Bytes0_64k::from_slice(&[0x02; 80]),
+            ]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_job() {
+        let mut test = TestNegotiator::new();
+        let mut captured = Captured::default();
+
+        test.negotiator.start().expect("Cannot start negotiation");
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        let token_request = captured
+            .token_request
+            .take()
+            .expect("Missing token request");
+        assert_eq!(token_request.user_identifier.to_string(), "miner");
+
+        test.receive(jn::AllocateMiningJobTokenSuccess {
+            request_id: token_request.request_id,
+            mining_job_token: Bytes0_255::from_slice(&[0xaa]),
+            coinbase_output_max_additional_size: 100,
+            async_mining_allowed: true,
+        })
+        .await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+        assert_eq!(
+            captured.data_size.take(),
+            Some(td::CoinbaseOutputDataSize {
+                coinbase_output_max_additional_size: 100
+            })
+        );
+
+        test.receive(build_new_template(1)).await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+        assert_eq!(
+            captured.data_request.take(),
+            Some(td::RequestTransactionData { template_id: 1 })
+        );
+
+        // Transaction data of the current template triggers the commit and a request for
+        // a new token
+        test.receive(build_transaction_data(1)).await;
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        let commit = captured.commit.take().expect("Missing commit");
+        assert!(captured.token_request.take().is_some());
+        assert_eq!(commit.mining_job_token, Bytes0_255::from_slice(&[0xaa]));
+        assert_eq!(
+            commit.coinbase_prefix,
+            Bytes0_255::from_slice(&[0x03, 0x01, 0x02, 0x03])
+        );
+        assert_eq!(commit.tx_short_hash_list.len(), 2);
+        assert!(commit
+            .tx_short_hash_list
+            .iter()
+            .all(|short_hash| short_hash & !SHORT_TX_HASH_MASK == 0));
+
+        // The pool asks for the second transaction
+        test.receive(jn::ProvideMissingTransactions {
+            request_id: commit.request_id,
+            unknown_tx_position_list: Seq0_64k::from_vec(vec![1]),
+        })
+        .await;
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        let missing = captured.missing.take().expect("Missing transactions");
+        assert_eq!(
+            missing.transaction_list,
+            Seq0_64k::from_vec(vec![Bytes0_64k::from_slice(&[0x02; 80])])
+        );
+
+        test.receive(jn::CommitMiningJobSuccess {
+            request_id: commit.request_id,
+            new_mining_job_token: Bytes0_255::from_slice(&[0xbb, 0xcc]),
+        })
+        .await;
+        let job = test.job_rx.next().await.expect("Missing negotiated job");
+        assert_eq!(job.template, build_new_template(1));
+        assert_eq!(job.transactions, vec![vec![0x01; 60], vec![0x02; 80]]);
+        assert_eq!(job.mining_job_token, vec![0xbb, 0xcc]);
+    }
+
+    #[tokio::test]
+    async fn test_stale_template() {
+        let mut test = TestNegotiator::new();
+        let mut captured = Captured::default();
+
+        test.receive(build_new_template(1)).await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+
+        // New block arrives before the transaction data of the old template
+        test.receive(td::SetNewPrevHash {
+            template_id: 2,
+            prev_hash: Uint256Bytes([0; 32]),
+            header_timestamp: 0,
+            n_bits: 0,
+            target: Uint256Bytes([0xff; 32]),
+        })
+        .await;
+        test.receive(build_transaction_data(1)).await;
+        assert!(test.negotiator.pending_templates.is_empty());
+        assert!(test.negotiator.ready_template.is_none());
+
+        test.receive(td::RequestTransactionDataError {
+            template_id: 3,
+            error_code: Str0_255::from_str("template-id-not-found"),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_future_template() {
+        let mut test = TestNegotiator::new();
+        let mut captured = Captured::default();
+
+        // Template on the current block is ready but there is no token to commit it
+        test.receive(build_new_template(1)).await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+        test.receive(build_transaction_data(1)).await;
+
+        let mut future_template = build_new_template(2);
+        future_template.future_template = true;
+        future_template.version = 0x2000_0004;
+        test.receive(future_template).await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+        test.receive(build_transaction_data(2)).await;
+        assert_eq!(
+            test.negotiator
+                .ready_template
+                .as_ref()
+                .map(|template| template.template.template_id),
+            Some(1)
+        );
+
+        // The future template replaces the ready one when its block arrives
+        let new_prev_hash = |template_id| td::SetNewPrevHash {
+            template_id,
+            prev_hash: Uint256Bytes([0; 32]),
+            header_timestamp: 0,
+            n_bits: 0,
+            target: Uint256Bytes([0xff; 32]),
+        };
+        test.receive(new_prev_hash(2)).await;
+        assert!(test.negotiator.future_templates.is_empty());
+
+        test.negotiator.start().expect("Cannot start negotiation");
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        let token_request = captured
+            .token_request
+            .take()
+            .expect("Missing token request");
+        test.receive(jn::AllocateMiningJobTokenSuccess {
+            request_id: token_request.request_id,
+            mining_job_token: Bytes0_255::from_slice(&[0xaa]),
+            coinbase_output_max_additional_size: 100,
+            async_mining_allowed: true,
+        })
+        .await;
+        expect_message(&mut test.template_provider_rx, &mut captured).await;
+        expect_message(&mut test.pool_rx, &mut captured).await;
+        let commit = captured.commit.take().expect("Missing commit");
+        assert_eq!(commit.version, 0x2000_0004);
+        assert_eq!(test.negotiator.committed_jobs.len(), 1);
+
+        // Commits of the previous block are dropped
+        test.receive(new_prev_hash(3)).await;
+        assert!(test.negotiator.committed_jobs.is_empty());
+    }
+
+    #[test]
+    fn test_short_tx_hash() {
+        let tx_hash = sha256d::Hash::hash(&[0x01; 60]);
+        let keys = short_tx_hash_keys(0);
+        assert_eq!(short_tx_hash(keys, &tx_hash), short_tx_hash(keys, &tx_hash));
+        assert_ne!(
+            short_tx_hash(keys, &tx_hash),
+            short_tx_hash(short_tx_hash_keys(1), &tx_hash)
+        );
+        assert_eq!(short_tx_hash(keys, &tx_hash) & !SHORT_TX_HASH_MASK, 0);
+    }
+}
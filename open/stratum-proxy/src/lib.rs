@@ -29,6 +29,8 @@
 
 pub mod error;
 pub mod frontend;
+pub mod job_negotiation;
 pub mod server;
 pub mod translation;
 pub mod util;
+pub mod v1_translation;
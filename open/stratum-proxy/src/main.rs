@@ -28,11 +28,14 @@ use structopt::StructOpt;
 
 use ctrlc;
 
+use futures::channel::mpsc;
+use ii_async_compat::prelude::*;
 use ii_async_compat::tokio;
+use ii_logging::macros::*;
 use ii_stratum_proxy::{
     error::{Result, ResultExt},
     frontend::Args,
-    server,
+    job_negotiation, server,
 };
 
 #[tokio::main]
@@ -53,6 +56,34 @@ async fn main() -> Result<()> {
     )
     .context("Cannot bind the server")?;
 
+    if let Some(template_provider_addr) = args.template_provider {
+        let pool_addr = args
+            .job_negotiator
+            .expect("BUG: missing job negotiator address");
+        let user_identifier = args
+            .job_negotiation_user
+            .expect("BUG: missing job negotiation user");
+        let (job_tx, mut job_rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            if let Err(e) =
+                job_negotiation::run(template_provider_addr, pool_addr, user_identifier, job_tx)
+                    .await
+            {
+                error!("Job negotiation terminated: {}", e);
+            }
+        });
+        // Translated V1 upstream connections cannot mine custom jobs, the negotiated jobs are
+        // only reported
+        tokio::spawn(async move {
+            while let Some(job) = job_rx.next().await {
+                info!(
+                    "Negotiated job of template {} with token {:x?}",
+                    job.template.template_id, job.mining_job_token
+                );
+            }
+        });
+    }
+
     let quit = RefCell::new(server.quit_channel());
     ctrlc::set_handler(move || {
         // Received SIGINT, tell the server task to shut down:
@@ -39,7 +39,7 @@ use serde_json;
 use ii_stratum::v1;
 use ii_stratum::v2::{
     self,
-    types::{Bytes0_32, Uint256Bytes},
+    types::{Bytes0_32, Str0_255, Uint256Bytes},
 };
 
 use ii_logging::macros::*;
@@ -70,12 +70,22 @@ impl SeqId {
 pub struct V2ToV1TranslationOptions {
     /// Try to send `extranonce.subscribe` during handshake
     pub try_enable_xnsub: bool,
+    /// Byte order of `ntime` and `nonce` expected by the upstream V1 server
+    pub submit_byte_order: v1::messages::SubmitByteOrder,
+    /// Number of older pending V1 requests that a response may overtake before it is accounted
+    /// as out-of-order. Responses are always paired by their ID regardless of this setting.
+    pub out_of_order_grace: usize,
+    /// Difficulty sent with `mining.suggest_difficulty` right after subscribing
+    pub suggested_difficulty: Option<f64>,
 }
 
 impl Default for V2ToV1TranslationOptions {
     fn default() -> Self {
         Self {
             try_enable_xnsub: false,
+            submit_byte_order: Default::default(),
+            out_of_order_grace: 0,
+            suggested_difficulty: None,
         }
     }
 }
@@ -117,6 +127,8 @@ struct V1SubmitTemplate {
     job_id: v1::messages::JobId,
     time: u32,
     version: u32,
+    /// Extra nonce 2 rolled into coinbase of the job
+    extra_nonce2: Vec<u8>,
 }
 
 enum V1ResultOrError<'a> {
@@ -141,9 +153,13 @@ pub struct V2ToV1Translation {
     v1_req_id: SeqId,
     /// Mapping for pairing of incoming V1 message with original requests
     v1_req_map: V1ReqMap,
+    /// Number of V1 responses that arrived out of order with respect to their requests
+    v1_out_of_order_responses: u64,
 
     v1_extra_nonce1: Option<v1::ExtraNonce1>,
     v1_extra_nonce2_size: usize,
+    /// Next extra nonce 2 value rolled into coinbase of a new job
+    v1_extra_nonce2_next: u64,
     v1_authorized: bool,
     v1_xnsub_enabled: bool,
 
@@ -155,6 +171,10 @@ pub struct V2ToV1Translation {
     /// This allows immediate completion of channel open on V2.
     v1_deferred_notify: Option<v1::messages::Notify>,
 
+    /// Latest mining.notify payload that current V2 jobs are built from. It is needed for
+    /// rebuilding the job when the extra nonce changes.
+    v1_last_notify: Option<v1::messages::Notify>,
+
     /// Channel for sending out V2 responses
     v2_tx: mpsc::Sender<v2::Frame>,
     #[allow(dead_code)] // TODO: unused as of now
@@ -184,11 +204,6 @@ impl V2ToV1Translation {
     /// Default group channel
     const DEFAULT_GROUP_CHANNEL_ID: u32 = 0;
 
-    /// U256 in little endian
-    /// TODO: consolidate into common part/generalize
-    /// TODO: DIFF1 const target is broken, the last U64 word gets actually initialized to 0xffffffff, not sure why
-    const DIFF1_TARGET: uint::U256 = uint::U256([0, 0, 0, 0xffff0000u64]);
-
     pub fn new(
         v1_tx: mpsc::Sender<v1::Frame>,
         v2_tx: mpsc::Sender<v2::Frame>,
@@ -202,12 +217,15 @@ impl V2ToV1Translation {
             v1_tx,
             v1_req_id: SeqId::new(),
             v1_req_map: V1ReqMap::default(),
+            v1_out_of_order_responses: 0,
             v1_extra_nonce1: None,
             v1_extra_nonce2_size: 0,
+            v1_extra_nonce2_next: 0,
             v1_authorized: false,
             v1_force_future_jobs: true,
             v1_xnsub_enabled: false,
             v1_deferred_notify: None,
+            v1_last_notify: None,
             v2_tx,
             v2_req_id: SeqId::new(),
             v2_job_id: SeqId::new(),
@@ -303,6 +321,18 @@ impl V2ToV1Translation {
         util::submit_message(&mut self.v2_tx, msg)
     }
 
+    /// Ask the upstream V1 server for `difficulty`. The server announces the difficulty it has
+    /// accepted with `mining.set_difficulty`.
+    fn send_suggest_difficulty(&mut self, difficulty: f64) -> Result<()> {
+        let suggest_difficulty = v1::messages::SuggestDifficulty([difficulty]);
+        let v1_suggest_difficulty = self.v1_method_into_message(
+            suggest_difficulty,
+            Self::handle_suggest_difficulty_result,
+            Self::handle_suggest_difficulty_error,
+        );
+        util::submit_message(&mut self.v1_tx, v1_suggest_difficulty)
+    }
+
     /// Reports failure to open the channel and changes the translation state
     /// From this point on a new OpenStandardMiningChannel message is expected as an attempt to reopen the channel
     fn abort_open_channel(&mut self, err_msg: &str) {
@@ -317,6 +347,7 @@ impl V2ToV1Translation {
         self.v1_authorized = false;
         self.v1_extra_nonce1 = None;
         self.v1_extra_nonce2_size = 0;
+        self.v1_extra_nonce2_next = 0;
 
         if let Some(v2_channel_details) = self.v2_channel_details.as_ref() {
             let msg = v2::messages::OpenStandardMiningChannelError {
@@ -442,6 +473,26 @@ impl V2ToV1Translation {
         Ok(())
     }
 
+    fn handle_suggest_difficulty_result(
+        &mut self,
+        _id: &v1::MessageId,
+        payload: &v1::rpc::StratumResult,
+    ) -> Result<()> {
+        // Servers that honor the suggestion announce it with `mining.set_difficulty`
+        debug!("mining.suggest_difficulty result: {:?}", payload);
+        Ok(())
+    }
+
+    fn handle_suggest_difficulty_error(
+        &mut self,
+        _id: &v1::MessageId,
+        payload: &v1::rpc::StratumError,
+    ) -> Result<()> {
+        // The server keeps its default difficulty so there is no reason to fail the channel
+        info!("Server refused suggested difficulty: {}", payload.1);
+        Ok(())
+    }
+
     fn handle_subscribe_result(
         &mut self,
         id: &v1::MessageId,
@@ -461,6 +512,7 @@ impl V2ToV1Translation {
 
         self.v1_extra_nonce1 = Some(subscribe_result.extra_nonce_1().clone());
         self.v1_extra_nonce2_size = subscribe_result.extra_nonce_2_size().clone();
+        self.v1_extra_nonce2_next = 0;
 
         // In order to finalize the opening procedure we need 3 items: authorization,
         // subscription and difficulty
@@ -627,12 +679,15 @@ impl V2ToV1Translation {
         util::submit_message(&mut self.v2_tx, err_msg)
     }
 
-    /// Iterates the merkle branches and calculates block merkle root using the extra nonce 1.
-    /// Extra nonce 2 encodes the channel ID.
+    /// Iterates the merkle branches and calculates block merkle root using the extra nonce 1 and
+    /// `extra_nonce2` rolled for the job.
+    /// Some pools send an empty extra nonce 1, the coinbase is then just coinbase 1 + extra nonce
+    /// 2 + coinbase 2.
     /// TODO review, whether a Result has to be returned as missing enonce1 would be considered a bug
     fn calculate_merkle_root(
-        &mut self,
+        &self,
         payload: &v1::messages::Notify,
+        extra_nonce2: &[u8],
     ) -> crate::error::Result<sha256d::Hash> {
         // TODO get rid of extra nonce 1 cloning
         if let Some(v1_extra_nonce1) = self.v1_extra_nonce1.clone() {
@@ -640,15 +695,12 @@ impl V2ToV1Translation {
             let mut coin_base: BytesMut = BytesMut::with_capacity(
                 payload.coin_base_1().len()
                     + (v1_extra_nonce1.0).len()
-                    + self.v1_extra_nonce2_size
+                    + extra_nonce2.len()
                     + payload.coin_base_2().len(),
             );
             coin_base.extend_from_slice(payload.coin_base_1());
             coin_base.extend_from_slice(v1_extra_nonce1.0.as_ref());
-            coin_base.extend_from_slice(
-                Self::channel_to_extra_nonce2_bytes(Self::CHANNEL_ID, self.v1_extra_nonce2_size)
-                    .as_ref(),
-            );
+            coin_base.extend_from_slice(extra_nonce2);
             coin_base.extend_from_slice(payload.coin_base_2());
 
             let mut engine = sha256d::Hash::engine();
@@ -657,16 +709,12 @@ impl V2ToV1Translation {
             let cb_tx_hash = sha256d::Hash::from_engine(engine);
             trace!("Coinbase TX hash: {:x?} {:x?}", cb_tx_hash, coin_base);
 
-            let merkle_root =
-                payload
-                    .merkle_branch()
-                    .iter()
-                    .fold(cb_tx_hash, |curr_merkle_root, tx_hash| {
-                        let mut engine = sha256d::Hash::engine();
-                        engine.input(&curr_merkle_root.into_inner());
-                        engine.input(tx_hash.as_ref().as_slice());
-                        sha256d::Hash::from_engine(engine)
-                    });
+            let merkle_branch = payload
+                .merkle_branch()
+                .iter()
+                .map(|tx_hash| sha256d::Hash::from_slice(tx_hash.as_ref()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let merkle_root = ii_bitcoin::merkle::root(cb_tx_hash, &merkle_branch);
             trace!("Merkle root calculated: {:x?}", merkle_root);
             Ok(merkle_root)
         } else {
@@ -710,30 +758,42 @@ impl V2ToV1Translation {
         })
     }
 
-    /// Converts specified `channel_id` into extra nonce 2 with a specified
-    /// `v1_extra_nonce2_size`
-    /// TODO review the implementation 'how to efficiently render a u32 into a byte array'
+    /// Converts specified `value` into little endian extra nonce 2 with a specified
+    /// `v1_extra_nonce2_size`. Bytes beyond the size of `u64` are zero.
     #[inline]
-    fn channel_to_extra_nonce2_bytes(channel_id: u32, v1_extra_nonce2_size: usize) -> BytesMut {
-        let mut extra_nonce2: BytesMut = BytesMut::with_capacity(v1_extra_nonce2_size);
-
-        let channel_id_bytes = u32::to_le_bytes(channel_id);
-        if v1_extra_nonce2_size < size_of::<u32>() {
-            // TODO: what to do when server deliberately sends small extranonce?
-            if channel_id >= 1u32.wrapping_shl(8 * v1_extra_nonce2_size as u32) {
-                error!("BUG: channel_id doesn't fit into extranonce");
-            }
-            // Write just part of channel_id
-            extra_nonce2.extend_from_slice(&channel_id_bytes[0..v1_extra_nonce2_size]);
-        } else {
-            // Write full 32-bits of channel id and pad the rest
-            extra_nonce2.extend_from_slice(&channel_id_bytes);
-            let padding = v1_extra_nonce2_size - size_of::<u32>();
-            extra_nonce2.extend_from_slice(&vec![0; padding]);
-        }
+    fn extra_nonce2_bytes(value: u64, v1_extra_nonce2_size: usize) -> Vec<u8> {
+        let mut extra_nonce2 = Vec::with_capacity(v1_extra_nonce2_size);
+
+        let value_bytes = u64::to_le_bytes(value);
+        let value_size = v1_extra_nonce2_size.min(size_of::<u64>());
+        extra_nonce2.extend_from_slice(&value_bytes[0..value_size]);
+        extra_nonce2.resize(v1_extra_nonce2_size, 0);
         extra_nonce2
     }
 
+    /// Rolls extra nonce 2 for a new job so that no two jobs built on the same extra nonce 1
+    /// share a coinbase. All values of the space are used before any of them repeats, the
+    /// exhaustion is reported. Empty extra nonce 2 cannot be rolled at all.
+    fn roll_extra_nonce2(&mut self) -> Vec<u8> {
+        let size = self.v1_extra_nonce2_size;
+        if size == 0 {
+            return vec![];
+        }
+        let value = self.v1_extra_nonce2_next;
+        let bits = 8 * size.min(size_of::<u64>()) as u32;
+        self.v1_extra_nonce2_next = match value.checked_add(1) {
+            Some(next) if bits >= 64 || next < 1u64 << bits => next,
+            _ => {
+                warn!(
+                    "Extra nonce 2 space of {} bytes exhausted, reusing values",
+                    size
+                );
+                0
+            }
+        };
+        Self::extra_nonce2_bytes(value, size)
+    }
+
     /// Generates log trace entry and reject shares error reply to the client
     fn reject_shares(&mut self, payload: &v2::messages::SubmitSharesStandard, err_msg: String) {
         trace!("Unrecognized channel ID: {}", payload.channel_id);
@@ -755,7 +815,8 @@ impl V2ToV1Translation {
     }
 
     fn perform_notify(&mut self, payload: &v1::messages::Notify) -> Result<()> {
-        let merkle_root = self.calculate_merkle_root(payload)?;
+        let extra_nonce2 = self.roll_extra_nonce2();
+        let merkle_root = self.calculate_merkle_root(payload, &extra_nonce2)?;
 
         let v2_job = v2::messages::NewMiningJob {
             channel_id: Self::CHANNEL_ID,
@@ -793,6 +854,7 @@ impl V2ToV1Translation {
                     job_id: v1::messages::JobId::from_str(payload.job_id()),
                     time: payload.time(),
                     version: payload.version(),
+                    extra_nonce2,
                 },
             )
             .is_some()
@@ -807,9 +869,36 @@ impl V2ToV1Translation {
         if let Some(set_new_prev_hash) = maybe_set_new_prev_hash {
             util::submit_message(&mut self.v2_tx, set_new_prev_hash)?
         }
+        self.v1_last_notify = Some(payload.clone());
         Ok(())
     }
 
+    /// Number of V1 responses observed out of order beyond `out_of_order_grace`
+    pub fn out_of_order_response_count(&self) -> u64 {
+        self.v1_out_of_order_responses
+    }
+
+    /// Account the response as out-of-order when it overtakes more pending requests than allowed
+    /// by the grace. Request IDs are generated sequentially (with wrap around) so any pending ID
+    /// 'before' the response ID belongs to an older request.
+    fn check_response_order(&mut self, id: u32) {
+        if !self.v1_req_map.contains_key(&id) {
+            return;
+        }
+        let overtaken_requests = self
+            .v1_req_map
+            .keys()
+            .filter(|pending_id| (id.wrapping_sub(**pending_id) as i32) > 0)
+            .count();
+        if overtaken_requests > self.options.out_of_order_grace {
+            self.v1_out_of_order_responses += 1;
+            debug!(
+                "V1 response ID {} overtook {} pending request(s)",
+                id, overtaken_requests
+            );
+        }
+    }
+
     /// The result visitor takes care of detecting a spurious response without matching request
     /// and passes processing further
     /// TODO write a solid unit test covering all 3 scenarios that can go wrong
@@ -824,6 +913,8 @@ impl V2ToV1Translation {
         )))
         // find the ID in the request map
         .and_then(|id| {
+            // Responses are paired by ID only, the order of arrival doesn't matter
+            self.check_response_order(id);
             self.v1_req_map
                 .remove(&id)
                 .ok_or(Error::from(ii_stratum::error::Error::from(
@@ -902,8 +993,13 @@ impl v1::Handler for V2ToV1Translation {
             self.state,
             payload,
         );
-        let diff = payload.value() as u32;
-        self.v2_target = Some(Self::DIFF1_TARGET / diff);
+        match ii_bitcoin::Target::from_float_difficulty(payload.value().into()) {
+            Ok(target) => self.v2_target = Some(target.into_inner()),
+            Err(e) => {
+                info!("Ignoring invalid difficulty {}: {}", payload.value(), e);
+                return;
+            }
+        }
         if self.v1_authorized && self.v1_extra_nonce1.is_some() {
             // Initial set difficulty finalizes open channel if all preconditions are met
             if self.state == V2ToV1TranslationState::OpenStandardMiningChannelPending {
@@ -934,11 +1030,42 @@ impl v1::Handler for V2ToV1Translation {
             payload,
         );
 
+        if self.v1_extra_nonce1.as_ref() == Some(payload.extra_nonce_1())
+            && self.v1_extra_nonce2_size == payload.extra_nonce_2_size()
+        {
+            return;
+        }
         // Update extranonces.
-        // Changes are reflected after new mining job as per:
+        // Upstream applies the change to the next mining job as per:
         //   https://en.bitcoin.it/wiki/Stratum_mining_protocol#mining.set_extranonce
         self.v1_extra_nonce1 = Some(payload.extra_nonce_1().clone());
-        self.v1_extra_nonce2_size = payload.extra_nonce_2_size().clone();
+        self.v1_extra_nonce2_size = payload.extra_nonce_2_size();
+        self.v1_extra_nonce2_next = 0;
+
+        if self.state != V2ToV1TranslationState::Operational {
+            return;
+        }
+        // Shares of jobs built on the previous extra nonce would be invalid. The current job is
+        // rebuilt with the new extra nonce and sent together with a new prev hash so that the
+        // client drops all older jobs. Shares for the dropped jobs are rejected as they no
+        // longer have any V1 job registered.
+        if let Some(notify_payload) = self.v1_last_notify.clone() {
+            info!(
+                "Extra nonce changed, rebuilding job {}",
+                notify_payload.job_id()
+            );
+            self.v2_to_v1_job_map.clear();
+            self.perform_notify(&notify_payload)
+                .map_err(|e| {
+                    info!(
+                        "visit_set_extranonce: Sending rebuilt mining job failed error={:?} \
+                         id={:?} state={:?} payload:{:?}",
+                        e, id, self.state, payload,
+                    )
+                })
+                // Consume the error as there is no way this can be communicated further
+                .ok();
+        }
     }
 
     /// Composes a new mining job and sends it downstream
@@ -984,6 +1111,43 @@ impl v1::Handler for V2ToV1Translation {
             payload,
         );
     }
+
+    /// Forwards the reconnect request downstream, the V2 client decides whether it follows it
+    async fn visit_client_reconnect(
+        &mut self,
+        id: &v1::MessageId,
+        payload: &v1::messages::ClientReconnect,
+    ) {
+        trace!(
+            "visit_client_reconnect() id={:?} state={:?} payload:{:?}",
+            id,
+            self.state,
+            payload,
+        );
+
+        let new_host = match Str0_255::try_from(payload.host().unwrap_or_default()) {
+            Ok(new_host) => new_host,
+            Err(_) => {
+                info!("visit_client_reconnect: host name too long: {:?}", payload);
+                return;
+            }
+        };
+        if payload.wait_time() != 0 {
+            // V2 has no way to delay the reconnection, the client reconnects immediately
+            debug!(
+                "visit_client_reconnect: ignoring wait time {}s",
+                payload.wait_time()
+            );
+        }
+        let reconnect = v2::messages::Reconnect {
+            new_host,
+            new_port: payload.port().unwrap_or(0),
+        };
+        util::submit_message(&mut self.v2_tx, reconnect)
+            .map_err(|e| info!("visit_client_reconnect: cannot forward request: {}", e))
+            // Consume the error as there is no way this can be communicated further
+            .ok();
+    }
 }
 
 /// TODO: implement an internal state where in each state only a subset of visit methods is valid,
@@ -1126,6 +1290,13 @@ impl v2::Handler for V2ToV1Translation {
                 }
             }
 
+            if let Some(difficulty) = self.options.suggested_difficulty {
+                if let Err(submit_err) = self.send_suggest_difficulty(difficulty) {
+                    info!("Cannot send V1 mining.suggest_difficulty: {:?}", submit_err);
+                    return;
+                }
+            }
+
             let authorize = v1::messages::Authorize(payload.user.to_string(), "".to_string());
             let v1_authorize_message = self.v1_method_into_message(
                 authorize,
@@ -1139,6 +1310,41 @@ impl v2::Handler for V2ToV1Translation {
         }
     }
 
+    /// V1 has no notion of the maximal target of the channel so it is translated into the
+    /// difficulty suggested to the upstream server. The server that accepts it sends
+    /// `mining.set_difficulty` which is translated into `SetTarget` as usual.
+    async fn visit_update_channel(
+        &mut self,
+        header: &v2::framing::Header,
+        payload: &v2::messages::UpdateChannel,
+    ) {
+        trace!(
+            "visit_update_channel() header={:x?} state={:?} payload:{:?}",
+            header,
+            self.state,
+            payload,
+        );
+        let code = if payload.channel_id != Self::CHANNEL_ID {
+            "invalid-channel-id"
+        } else if self.state != V2ToV1TranslationState::Operational {
+            "channel-not-operational"
+        } else {
+            let max_target: ii_bitcoin::Target = payload.max_target.clone().into();
+            if let Err(submit_err) = self.send_suggest_difficulty(max_target.get_float_difficulty())
+            {
+                info!("Cannot send V1 mining.suggest_difficulty: {:?}", submit_err);
+            }
+            return;
+        };
+        let err_msg = v2::messages::UpdateChannelError {
+            channel_id: payload.channel_id,
+            code: code.try_into().expect("BUG: incorrect error message"),
+        };
+        if let Err(submit_err) = util::submit_message(&mut self.v2_tx, err_msg) {
+            info!("Cannot send UpdateChannelError message: {:?}", submit_err);
+        }
+    }
+
     /// The flow of share processing is as follows:
     ///
     /// - find corresponding job
@@ -1172,10 +1378,6 @@ impl v2::Handler for V2ToV1Translation {
             .v2_channel_details
             .clone()
             .expect("Missing channel details");
-        // TODO this is only here as we want to prevent locking up 'self' into multiple closures
-        // and causing borrow checker complains
-        let v1_extra_nonce2_size = self.v1_extra_nonce2_size;
-
         // Check job ID validity
         let v1_submit_template = self
             .v2_to_v1_job_map
@@ -1190,15 +1392,15 @@ impl v2::Handler for V2ToV1Translation {
         // Submit upstream V1 job based on the found job ID in the map
         match v1_submit_template {
             Ok(v1_submit_template) => {
-                let submit = v1::messages::Submit::new(
+                let submit = v1::messages::Submit::with_byte_order(
                     v2_channel_details.user.to_string(),
                     v1_submit_template.job_id.clone(),
-                    Self::channel_to_extra_nonce2_bytes(Self::CHANNEL_ID, v1_extra_nonce2_size)
-                        .as_ref(),
+                    &v1_submit_template.extra_nonce2,
                     payload.ntime,
                     payload.nonce,
                     // ensure the version bits in the template follow BIP320
                     payload.version & ii_stratum::BIP320_N_VERSION_MASK,
+                    self.options.submit_byte_order,
                 );
                 // Convert the method into a message + provide handling methods
                 let v1_submit_message = self.v1_method_into_message(
@@ -22,6 +22,8 @@
 
 use futures::stream::StreamExt;
 
+use std::str::FromStr;
+
 use ii_async_compat::tokio;
 
 use super::*;
@@ -138,6 +140,8 @@ async fn test_setup_connection_translate() {
         job_id: v1::messages::JobId::from_str(&test_utils::v1::MINING_NOTIFY_JOB_ID),
         time: test_utils::common::MINING_WORK_NTIME,
         version: test_utils::common::MINING_WORK_VERSION,
+        // first value of 4 bytes long extra nonce 2 space from the subscribe response
+        extra_nonce2: vec![0; 4],
     };
 
     let registered_submit_template = translation
@@ -165,6 +169,136 @@ async fn test_setup_connection_translate() {
     // });
 }
 
+/// Opens a channel with V1 responses to subscribe and authorize arriving in reverse order of the
+/// requests and returns the translation once the channel is operational
+async fn open_channel_with_reversed_responses(
+    options: V2ToV1TranslationOptions,
+) -> (
+    V2ToV1Translation,
+    mpsc::Receiver<v1::Frame>,
+    mpsc::Receiver<v2::Frame>,
+) {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    // subscribe and authorize requests
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+
+    // Authorize response overtakes the subscribe response
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_authorize_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_subscribe_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_set_difficulty_request_message(),
+    )
+    .await;
+    // The channel has to be open even though the responses arrived out of order
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    assert_eq!(
+        translation.state,
+        V2ToV1TranslationState::Operational,
+        "Channel not operational"
+    );
+    assert!(
+        translation.v1_req_map.is_empty(),
+        "Unresolved V1 requests: {:?}",
+        translation.v1_req_map.keys().collect::<Vec<_>>()
+    );
+    (translation, v1_rx, v2_rx)
+}
+
+#[tokio::test]
+async fn test_out_of_order_responses() {
+    let (translation, _, _) = open_channel_with_reversed_responses(Default::default()).await;
+    assert_eq!(translation.out_of_order_response_count(), 1);
+
+    // The same ordering is tolerated with grace
+    let (translation, _, _) = open_channel_with_reversed_responses(V2ToV1TranslationOptions {
+        out_of_order_grace: 1,
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(translation.out_of_order_response_count(), 0);
+}
+
+#[test]
+fn test_empty_extra_nonce_1() {
+    let (v1_tx, _v1_rx) = mpsc::channel(1);
+    let (v2_tx, _v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+    // Empty hex string from the subscribe response deserializes into an empty extra nonce 1
+    translation.v1_extra_nonce1 = Some(v1::ExtraNonce1(v1::HexBytes::from(String::new())));
+    translation.v1_extra_nonce2_size = 4;
+
+    let notify = test_utils::v1::build_mining_notify();
+    assert!(notify.merkle_branch().is_empty());
+    let extra_nonce2 = translation.roll_extra_nonce2();
+    let merkle_root = translation
+        .calculate_merkle_root(&notify, &extra_nonce2)
+        .expect("Cannot calculate merkle root");
+
+    let mut coin_base = notify.coin_base_1().to_vec();
+    coin_base.extend_from_slice(&extra_nonce2);
+    coin_base.extend_from_slice(notify.coin_base_2());
+    assert_eq!(
+        coin_base.len(),
+        notify.coin_base_1().len() + 4 + notify.coin_base_2().len()
+    );
+    // Without any merkle branches the merkle root is the coinbase transaction hash
+    assert_eq!(merkle_root, sha256d::Hash::hash(&coin_base));
+}
+
+#[test]
+fn test_roll_extra_nonce_2() {
+    let (v1_tx, _v1_rx) = mpsc::channel(1);
+    let (v2_tx, _v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+
+    translation.v1_extra_nonce2_size = 4;
+    assert_eq!(translation.roll_extra_nonce2(), vec![0, 0, 0, 0]);
+    assert_eq!(translation.roll_extra_nonce2(), vec![1, 0, 0, 0]);
+
+    // values beyond 64 bits are padded
+    translation.v1_extra_nonce2_size = 10;
+    assert_eq!(
+        translation.roll_extra_nonce2(),
+        vec![2, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+
+    // the whole space is used before the values repeat
+    translation.v1_extra_nonce2_size = 1;
+    translation.v1_extra_nonce2_next = 0xfe;
+    assert_eq!(translation.roll_extra_nonce2(), vec![0xfe]);
+    assert_eq!(translation.roll_extra_nonce2(), vec![0xff]);
+    assert_eq!(translation.roll_extra_nonce2(), vec![0x00]);
+
+    // there is nothing to roll in an empty extra nonce 2
+    translation.v1_extra_nonce2_size = 0;
+    assert!(translation.roll_extra_nonce2().is_empty());
+    assert!(translation.roll_extra_nonce2().is_empty());
+}
+
 #[test]
 fn test_diff_1_bitcoin_target() {
     // Difficulty 1 target in big-endian format
@@ -176,11 +310,159 @@ fn test_diff_1_bitcoin_target() {
     let expected_difficulty_1_target_uint256 =
         uint::U256::from_big_endian(&difficulty_1_target_bytes);
 
+    let difficulty_1_target = ii_bitcoin::Target::from_float_difficulty(1.0)
+        .expect("BUG: cannot convert difficulty")
+        .into_inner();
+
     assert_eq!(
-        expected_difficulty_1_target_uint256,
-        V2ToV1Translation::DIFF1_TARGET,
+        expected_difficulty_1_target_uint256, difficulty_1_target,
         "Bitcoin difficulty 1 targets don't match exp: {:x?}, actual:{:x?}",
-        expected_difficulty_1_target_uint256,
-        V2ToV1Translation::DIFF1_TARGET
+        expected_difficulty_1_target_uint256, difficulty_1_target
+    );
+}
+
+#[tokio::test]
+async fn test_client_reconnect_forwarded() {
+    let (v1_tx, _v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+
+    let reconnect = v1::rpc::Rpc::from_str(
+        r#"{"id":null,"method":"client.reconnect","params":["eu.example.com","3334",10]}"#,
+    )
+    .expect("Cannot parse reconnect request");
+    v1_simulate_incoming_message(&mut translation, reconnect).await;
+
+    let frame = v2_rx.next().await.expect("Reconnect message was expected");
+    let reconnect = v2::messages::Reconnect::try_from(frame).expect("Deserialization failed");
+    assert_eq!(reconnect.new_host.to_string(), "eu.example.com");
+    assert_eq!(reconnect.new_port, 3334);
+}
+
+#[tokio::test]
+async fn test_suggest_difficulty() {
+    let (v1_tx, mut v1_rx) = mpsc::channel(4);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let options = V2ToV1TranslationOptions {
+        suggested_difficulty: Some(4096.0),
+        ..Default::default()
+    };
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    // The suggestion is sent between subscribe and authorize requests
+    let mut methods = Vec::new();
+    for _ in 0..3 {
+        let frame = v1_rx.next().await.expect("V1 request was expected");
+        match v1::rpc::Rpc::try_from(frame).expect("Deserialization failed") {
+            v1::rpc::Rpc::Request(request) => {
+                if request.payload.method == v1::rpc::Method::SuggestDifficulty {
+                    let suggest_difficulty = v1::messages::SuggestDifficulty::try_from(request)
+                        .expect("Cannot convert suggest difficulty");
+                    assert_eq!(suggest_difficulty.value(), 4096.0);
+                    methods.push(v1::rpc::Method::SuggestDifficulty);
+                } else {
+                    methods.push(request.payload.method);
+                }
+            }
+            v1::rpc::Rpc::Response(_) => panic!("Request expected"),
+        }
+    }
+    assert_eq!(
+        methods,
+        vec![
+            v1::rpc::Method::Subscribe,
+            v1::rpc::Method::SuggestDifficulty,
+            v1::rpc::Method::Authorize
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_set_extranonce_rebuilds_job() {
+    let (mut translation, _v1_rx, mut v2_rx) =
+        open_channel_with_reversed_responses(Default::default()).await;
+
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_mining_notify_request_message(),
+    )
+    .await;
+    let frame = v2_rx.next().await.expect("NewMiningJob was expected");
+    let old_job = v2::messages::NewMiningJob::try_from(frame).expect("Deserialization failed");
+    v2_rx.next().await.expect("SetNewPrevHash was expected");
+
+    let set_extranonce = v1::rpc::Rpc::from_str(
+        r#"{"id":null,"method":"mining.set_extranonce","params":["deadbeef",4]}"#,
+    )
+    .expect("Cannot parse set extranonce request");
+    v1_simulate_incoming_message(&mut translation, set_extranonce).await;
+
+    // The job is rebuilt with new coinbase and all older jobs are dropped by new prev hash
+    let frame = v2_rx
+        .next()
+        .await
+        .expect("Rebuilt NewMiningJob was expected");
+    let new_job = v2::messages::NewMiningJob::try_from(frame).expect("Deserialization failed");
+    assert_ne!(new_job.job_id, old_job.job_id);
+    assert_ne!(new_job.merkle_root, old_job.merkle_root);
+    let frame = v2_rx.next().await.expect("SetNewPrevHash was expected");
+    let prev_hash = v2::messages::SetNewPrevHash::try_from(frame).expect("Deserialization failed");
+    assert_eq!(prev_hash.job_id, new_job.job_id);
+    assert!(translation.v2_to_v1_job_map.get(&old_job.job_id).is_none());
+    // new extra nonce 1 starts a new extra nonce 2 space
+    assert_eq!(
+        translation.v2_to_v1_job_map[&new_job.job_id].extra_nonce2,
+        vec![0; 4]
     );
+
+    // Repeated announcement of the same extra nonce has no effect
+    let set_extranonce = v1::rpc::Rpc::from_str(
+        r#"{"id":null,"method":"mining.set_extranonce","params":["deadbeef",4]}"#,
+    )
+    .expect("Cannot parse set extranonce request");
+    v1_simulate_incoming_message(&mut translation, set_extranonce).await;
+    assert!(v2_rx.try_next().is_err(), "No message was expected");
+}
+
+#[tokio::test]
+async fn test_update_channel_suggests_difficulty() {
+    let (mut translation, mut v1_rx, mut v2_rx) =
+        open_channel_with_reversed_responses(Default::default()).await;
+
+    let update_channel = v2::messages::UpdateChannel {
+        channel_id: V2ToV1Translation::CHANNEL_ID,
+        nominal_hashrate: 1e9,
+        max_target: ii_bitcoin::Target::from_pool_difficulty(1024).into(),
+    };
+    v2_simulate_incoming_message(&mut translation, update_channel.clone()).await;
+    let frame = v1_rx.next().await.expect("Suggest difficulty was expected");
+    match v1::rpc::Rpc::try_from(frame).expect("Deserialization failed") {
+        v1::rpc::Rpc::Request(request) => {
+            let suggest_difficulty = v1::messages::SuggestDifficulty::try_from(request)
+                .expect("Cannot convert suggest difficulty");
+            assert_eq!(suggest_difficulty.value(), 1024.0);
+        }
+        v1::rpc::Rpc::Response(_) => panic!("Request expected"),
+    }
+
+    // Unknown channel is refused with an error
+    let update_channel = v2::messages::UpdateChannel {
+        channel_id: V2ToV1Translation::CHANNEL_ID + 1,
+        ..update_channel
+    };
+    v2_simulate_incoming_message(&mut translation, update_channel).await;
+    let frame = v2_rx.next().await.expect("UpdateChannelError was expected");
+    let error = v2::messages::UpdateChannelError::try_from(frame).expect("Deserialization failed");
+    assert_eq!(error.code.to_string(), "invalid-channel-id");
+    assert!(v1_rx.try_next().is_err(), "No V1 request was expected");
 }
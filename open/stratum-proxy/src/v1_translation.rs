@@ -0,0 +1,1002 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Translation of Stratum V1 miners connected downstream to Stratum V2 upstream server. Each V1
+//! connection is served by one V2 extended mining channel so that the V1 miner can roll its
+//! extra nonce 2 within the extranonce provided by the upstream.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::{TryFrom, TryInto};
+
+use ii_async_compat::futures;
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+
+use ii_stratum::v1;
+use ii_stratum::v2::{
+    self,
+    types::{Bytes0_32, DeviceInfo, Str0_255},
+};
+
+use ii_logging::macros::*;
+
+use crate::error::Result;
+use crate::translation::SeqId;
+use crate::util;
+
+/// Stratum V1 error codes reported to the downstream miner
+const V1_ERROR_OTHER: i32 = 20;
+const V1_ERROR_JOB_NOT_FOUND: i32 = 21;
+const V1_ERROR_DUPLICATE_SHARE: i32 = 22;
+const V1_ERROR_LOW_DIFFICULTY_SHARE: i32 = 23;
+const V1_ERROR_UNAUTHORIZED: i32 = 24;
+const V1_ERROR_NOT_SUBSCRIBED: i32 = 25;
+
+pub struct V1ToV2TranslationOptions {
+    /// User of the upstream channels, it identifies the account that all downstream miners are
+    /// mining for
+    pub user: String,
+    /// Host and port of the upstream server as announced in `SetupConnection`
+    pub upstream_host: String,
+    pub upstream_port: u16,
+    /// Hashrate announced for each channel when opening it
+    pub nominal_hashrate: f32,
+    /// Minimal size of extra nonce 2 that the downstream miner rolls
+    pub min_extranonce_size: u16,
+}
+
+impl V1ToV2TranslationOptions {
+    pub const DEFAULT_NOMINAL_HASHRATE: f32 = 1e9;
+    pub const DEFAULT_MIN_EXTRANONCE_SIZE: u16 = 4;
+
+    pub fn new(user: String, upstream_host: String, upstream_port: u16) -> Self {
+        Self {
+            user,
+            upstream_host,
+            upstream_port,
+            nominal_hashrate: Self::DEFAULT_NOMINAL_HASHRATE,
+            min_extranonce_size: Self::DEFAULT_MIN_EXTRANONCE_SIZE,
+        }
+    }
+}
+
+/// States of the translation setup
+#[derive(PartialEq, Debug)]
+enum V1ToV2TranslationState {
+    /// `SetupConnection` has been sent upstream
+    ConnectionSetupPending,
+    /// Connection has been set up, waiting for `mining.subscribe`
+    ConnectionSetup,
+    /// `OpenExtendedMiningChannel` has been sent upstream
+    OpenExtendedMiningChannelPending,
+    /// Channel is operational
+    Operational,
+    /// The upstream refused the connection or the channel, the translation cannot continue
+    Failed,
+}
+
+/// Extended channel opened for the downstream miner
+#[derive(Debug)]
+struct Channel {
+    channel_id: u32,
+    /// Extra nonce 1 of the downstream miner
+    extranonce_prefix: Vec<u8>,
+    /// Size of extra nonce 2 of the downstream miner
+    extranonce_size: usize,
+}
+
+/// Object capable of translating Stratum V1 requests of one downstream miner into Stratum V2
+/// extended mining channel
+pub struct V1ToV2Translation {
+    state: V1ToV2TranslationState,
+
+    /// Channel for sending out V1 responses and notifications to the miner
+    v1_tx: mpsc::Sender<v1::Frame>,
+    /// ID of `mining.subscribe` that is answered once the channel is open
+    v1_subscribe_id: Option<u32>,
+    /// IDs of `mining.authorize` that are answered once the channel is open
+    v1_authorize_ids: Vec<u32>,
+    /// Version bits the miner is allowed to roll as negotiated by `mining.configure`
+    v1_version_mask: u32,
+
+    /// Channel for sending out V2 messages upstream
+    v2_tx: mpsc::Sender<v2::Frame>,
+    v2_req_id: SeqId,
+    v2_seq_num: SeqId,
+    channel: Option<Channel>,
+    target: Option<ii_bitcoin::Target>,
+    prev_hash: Option<v2::messages::SetNewPrevHash>,
+    /// Jobs built on current `prev_hash`
+    jobs: HashMap<u32, v2::messages::NewExtendedMiningJob>,
+    /// Jobs waiting for their `SetNewPrevHash`
+    future_jobs: HashMap<u32, v2::messages::NewExtendedMiningJob>,
+    /// Shares waiting for acknowledgement as pairs of V2 sequence number and V1 request ID
+    pending_submits: VecDeque<(u32, u32)>,
+    options: V1ToV2TranslationOptions,
+}
+
+impl V1ToV2Translation {
+    const PROTOCOL_VERSION: u16 = 2;
+
+    pub fn new(
+        v1_tx: mpsc::Sender<v1::Frame>,
+        v2_tx: mpsc::Sender<v2::Frame>,
+        options: V1ToV2TranslationOptions,
+    ) -> Self {
+        Self {
+            state: V1ToV2TranslationState::ConnectionSetupPending,
+            v1_tx,
+            v1_subscribe_id: None,
+            v1_authorize_ids: vec![],
+            v1_version_mask: 0,
+            v2_tx,
+            v2_req_id: SeqId::new(),
+            v2_seq_num: SeqId::new(),
+            channel: None,
+            target: None,
+            prev_hash: None,
+            jobs: Default::default(),
+            future_jobs: Default::default(),
+            pending_submits: Default::default(),
+            options,
+        }
+    }
+
+    /// Sets up the upstream connection, it has to be called before any V1 message is passed to
+    /// the translation
+    pub fn start(&mut self) -> Result<()> {
+        let msg = v2::messages::SetupConnection {
+            protocol: 0,
+            min_version: Self::PROTOCOL_VERSION,
+            max_version: Self::PROTOCOL_VERSION,
+            flags: 0,
+            endpoint_host: Str0_255::try_from(self.options.upstream_host.as_str())
+                .unwrap_or_default(),
+            endpoint_port: self.options.upstream_port,
+            device: DeviceInfo {
+                vendor: Str0_255::new(),
+                hw_rev: Str0_255::new(),
+                fw_ver: Str0_255::new(),
+                dev_id: Str0_255::new(),
+            },
+        };
+        util::submit_message(&mut self.v2_tx, msg)
+    }
+
+    /// The translation cannot continue and the downstream connection is to be closed
+    pub fn is_failed(&self) -> bool {
+        self.state == V1ToV2TranslationState::Failed
+    }
+
+    fn v1_send(&mut self, rpc: v1::rpc::Rpc) {
+        if let Err(e) = util::submit_message(&mut self.v1_tx, rpc) {
+            info!("Cannot send V1 message downstream: {}", e);
+        }
+    }
+
+    fn v1_send_result<T>(&mut self, id: u32, result: T)
+    where
+        T: TryInto<v1::rpc::ResponsePayload, Error = ii_stratum::error::Error>,
+    {
+        match result.try_into() {
+            Ok(payload) => self.v1_send(v1::rpc::Response { id, payload }.into()),
+            Err(e) => error!("BUG: cannot build V1 response: {}", e),
+        }
+    }
+
+    fn v1_send_error(&mut self, id: u32, code: i32, msg: &str) {
+        let payload = v1::rpc::ResponsePayload {
+            result: None,
+            error: Some(v1::rpc::StratumError(code, msg.to_string(), None)),
+        };
+        self.v1_send(v1::rpc::Response { id, payload }.into());
+    }
+
+    fn v1_send_notification<T>(&mut self, notification: T)
+    where
+        T: TryInto<v1::rpc::RequestPayload, Error = ii_stratum::error::Error>,
+    {
+        match notification.try_into() {
+            Ok(payload) => self.v1_send(v1::rpc::Request { id: None, payload }.into()),
+            Err(e) => error!("BUG: cannot build V1 notification: {}", e),
+        }
+    }
+
+    fn v2_send<T>(&mut self, msg: T)
+    where
+        T: TryInto<v2::Frame, Error = ii_stratum::error::Error>,
+    {
+        if let Err(e) = util::submit_message(&mut self.v2_tx, msg) {
+            info!("Cannot send V2 message upstream: {}", e);
+        }
+    }
+
+    /// Reports failure to all pending requests of the miner, nothing can be mined from now on
+    fn fail(&mut self, reason: &str) {
+        info!("V1->V2 translation failed: {}", reason);
+        self.state = V1ToV2TranslationState::Failed;
+        if let Some(id) = self.v1_subscribe_id.take() {
+            self.v1_send_error(id, V1_ERROR_OTHER, reason);
+        }
+        for id in std::mem::take(&mut self.v1_authorize_ids) {
+            self.v1_send_error(id, V1_ERROR_UNAUTHORIZED, reason);
+        }
+    }
+
+    fn open_channel(&mut self) {
+        let user = match self.options.user.as_str().try_into() {
+            Ok(user) => user,
+            Err(_) => {
+                self.fail("Invalid upstream user");
+                return;
+            }
+        };
+        let msg = v2::messages::OpenExtendedMiningChannel {
+            req_id: self.v2_req_id.next(),
+            user,
+            nominal_hashrate: self.options.nominal_hashrate,
+            max_target: ii_bitcoin::Target::default().into(),
+            min_extranonce_size: self.options.min_extranonce_size,
+        };
+        self.state = V1ToV2TranslationState::OpenExtendedMiningChannelPending;
+        self.v2_send(msg);
+    }
+
+    fn send_set_difficulty(&mut self) {
+        if let Some(target) = self.target {
+            let difficulty = target.get_float_difficulty() as f32;
+            self.v1_send_notification(v1::messages::SetDifficulty([difficulty]));
+        }
+    }
+
+    /// Builds V1 job from upstream `job` and the current prev hash
+    fn build_notify(
+        job: &v2::messages::NewExtendedMiningJob,
+        prev_hash: &v2::messages::SetNewPrevHash,
+        clean_jobs: bool,
+    ) -> v1::messages::Notify {
+        v1::messages::Notify::new(
+            v1::messages::JobId::from_str(&format!("{:x}", job.job_id)),
+            prev_hash.prev_hash.as_ref(),
+            &job.coinbase_tx_prefix,
+            &job.coinbase_tx_suffix,
+            job.merkle_path
+                .iter()
+                .map(|tx_hash| tx_hash.as_ref().to_vec())
+                .collect(),
+            job.version,
+            prev_hash.nbits,
+            prev_hash.min_ntime,
+            clean_jobs,
+        )
+    }
+
+    fn send_notify(&mut self, job_id: u32, clean_jobs: bool) {
+        if self.state != V1ToV2TranslationState::Operational {
+            return;
+        }
+        let notify = match (self.jobs.get(&job_id), self.prev_hash.as_ref()) {
+            (Some(job), Some(prev_hash)) => Self::build_notify(job, prev_hash, clean_jobs),
+            _ => return,
+        };
+        self.v1_send_notification(notify);
+    }
+
+    /// Sends the latest job to the miner that has just been subscribed
+    fn send_current_job(&mut self) {
+        if let Some(job_id) = self.jobs.keys().max().cloned() {
+            self.send_notify(job_id, true);
+        }
+    }
+
+    /// Maps error code of rejected share to V1 error code
+    fn v1_error_code(code: &str) -> i32 {
+        match code {
+            "invalid-job-id" | "stale-share" => V1_ERROR_JOB_NOT_FOUND,
+            "duplicate-share" => V1_ERROR_DUPLICATE_SHARE,
+            "difficulty-too-low" => V1_ERROR_LOW_DIFFICULTY_SHARE,
+            "invalid-channel-id" => V1_ERROR_UNAUTHORIZED,
+            _ => V1_ERROR_OTHER,
+        }
+    }
+
+    /// Builds upstream share from V1 `submit`
+    fn build_submit_shares(
+        &mut self,
+        submit: &v1::messages::Submit,
+    ) -> std::result::Result<v2::messages::SubmitSharesExtended, (i32, &'static str)> {
+        let channel = match self.channel.as_ref() {
+            Some(channel) if self.state == V1ToV2TranslationState::Operational => channel,
+            _ => return Err((V1_ERROR_NOT_SUBSCRIBED, "Not subscribed")),
+        };
+        let job = u32::from_str_radix(submit.job_id(), 16)
+            .ok()
+            .and_then(|job_id| self.jobs.get(&job_id))
+            .ok_or((V1_ERROR_JOB_NOT_FOUND, "Job not found"))?;
+        if submit.extra_nonce_2().len() != channel.extranonce_size {
+            return Err((V1_ERROR_OTHER, "Invalid extranonce2 size"));
+        }
+        let version = match submit.version() {
+            Some(version_bits) => {
+                (job.version & !self.v1_version_mask) | (version_bits & self.v1_version_mask)
+            }
+            None => job.version,
+        };
+
+        let channel_id = channel.channel_id;
+        let job_id = job.job_id;
+        let extranonce = Bytes0_32::try_from(submit.extra_nonce_2())
+            .map_err(|_| (V1_ERROR_OTHER, "Invalid extranonce2 size"))?;
+
+        Ok(v2::messages::SubmitSharesExtended {
+            channel_id,
+            seq_num: self.v2_seq_num.next(),
+            job_id,
+            nonce: submit.nonce(),
+            ntime: submit.time(),
+            version,
+            extranonce,
+        })
+    }
+}
+
+#[async_trait]
+impl v1::Handler for V1ToV2Translation {
+    /// Only version rolling is supported
+    async fn visit_configure(&mut self, id: &v1::MessageId, payload: &v1::messages::Configure) {
+        trace!("visit_configure() id={:?} payload:{:?}", id, payload);
+        let id = match id {
+            Some(id) => *id,
+            None => return,
+        };
+        let mut result = serde_json::Map::new();
+        for feature in payload.0.iter() {
+            if feature == "version-rolling" {
+                let requested_mask = payload.1["version-rolling.mask"]
+                    .as_str()
+                    .and_then(|mask| u32::from_str_radix(mask, 16).ok())
+                    .unwrap_or(ii_stratum::BIP320_N_VERSION_MASK);
+                self.v1_version_mask = requested_mask & ii_stratum::BIP320_N_VERSION_MASK;
+                result.insert(feature.clone(), true.into());
+                result.insert(
+                    "version-rolling.mask".to_string(),
+                    format!("{:08x}", self.v1_version_mask).into(),
+                );
+            } else {
+                result.insert(feature.clone(), false.into());
+            }
+        }
+        self.v1_send_result(id, v1::messages::ConfigureResult(result.into()));
+    }
+
+    async fn visit_subscribe(&mut self, id: &v1::MessageId, payload: &v1::messages::Subscribe) {
+        trace!(
+            "visit_subscribe() id={:?} state={:?} payload:{:?}",
+            id,
+            self.state,
+            payload
+        );
+        let id = match id {
+            Some(id) => *id,
+            None => return,
+        };
+        if self.v1_subscribe_id.is_some() || self.channel.is_some() {
+            self.v1_send_error(id, V1_ERROR_OTHER, "Already subscribed");
+            return;
+        }
+        self.v1_subscribe_id = Some(id);
+        match self.state {
+            V1ToV2TranslationState::ConnectionSetup => self.open_channel(),
+            V1ToV2TranslationState::Failed => self.fail("Upstream connection failed"),
+            // The channel is opened as soon as the connection is set up
+            _ => (),
+        }
+    }
+
+    /// The miner is mining for the user of the upstream channel, its own name is only logged
+    async fn visit_authorize(&mut self, id: &v1::MessageId, payload: &v1::messages::Authorize) {
+        trace!(
+            "visit_authorize() id={:?} state={:?} user:{}",
+            id,
+            self.state,
+            payload.name()
+        );
+        let id = match id {
+            Some(id) => *id,
+            None => return,
+        };
+        match self.state {
+            V1ToV2TranslationState::Operational => {
+                info!(
+                    "V1 miner '{}' mining on upstream channel of '{}'",
+                    payload.name(),
+                    self.options.user
+                );
+                self.v1_send_result(id, v1::messages::BooleanResult(true));
+            }
+            V1ToV2TranslationState::Failed => {
+                self.v1_send_error(id, V1_ERROR_UNAUTHORIZED, "Upstream channel failed")
+            }
+            _ => self.v1_authorize_ids.push(id),
+        }
+    }
+
+    /// Extra nonce never changes as it is given by the extended channel
+    async fn visit_extranonce_subscribe(
+        &mut self,
+        id: &v1::MessageId,
+        _payload: &v1::messages::ExtranonceSubscribe,
+    ) {
+        if let Some(id) = id {
+            self.v1_send_result(*id, v1::messages::BooleanResult(true));
+        }
+    }
+
+    async fn visit_submit(&mut self, id: &v1::MessageId, payload: &v1::messages::Submit) {
+        trace!("visit_submit() id={:?} payload:{:?}", id, payload);
+        let id = match id {
+            Some(id) => *id,
+            None => return,
+        };
+        match self.build_submit_shares(payload) {
+            Ok(submit_shares) => {
+                self.pending_submits.push_back((submit_shares.seq_num, id));
+                self.v2_send(submit_shares);
+            }
+            Err((code, msg)) => self.v1_send_error(id, code, msg),
+        }
+    }
+}
+
+#[async_trait]
+impl v2::Handler for V1ToV2Translation {
+    async fn visit_setup_connection_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SetupConnectionSuccess,
+    ) {
+        trace!("visit_setup_connection_success() payload:{:?}", payload);
+        if self.state != V1ToV2TranslationState::ConnectionSetupPending {
+            return;
+        }
+        self.state = V1ToV2TranslationState::ConnectionSetup;
+        if self.v1_subscribe_id.is_some() {
+            self.open_channel();
+        }
+    }
+
+    async fn visit_setup_connection_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SetupConnectionError,
+    ) {
+        self.fail(&format!(
+            "Upstream refused connection: {}",
+            payload.code.to_string()
+        ));
+    }
+
+    async fn visit_open_extended_mining_channel_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::OpenExtendedMiningChannelSuccess,
+    ) {
+        trace!(
+            "visit_open_extended_mining_channel_success() payload:{:?}",
+            payload
+        );
+        if self.state != V1ToV2TranslationState::OpenExtendedMiningChannelPending {
+            return;
+        }
+        let channel = Channel {
+            channel_id: payload.channel_id,
+            extranonce_prefix: payload.extranonce_prefix.to_vec(),
+            extranonce_size: payload.extranonce_size as usize,
+        };
+        self.state = V1ToV2TranslationState::Operational;
+        self.target = Some(payload.target.into());
+
+        if let Some(id) = self.v1_subscribe_id.take() {
+            let result = v1::messages::SubscribeResult(
+                vec![
+                    v1::messages::Subscription(
+                        "mining.set_difficulty".to_string(),
+                        format!("{:x}", channel.channel_id),
+                    ),
+                    v1::messages::Subscription(
+                        "mining.notify".to_string(),
+                        format!("{:x}", channel.channel_id),
+                    ),
+                ],
+                v1::ExtraNonce1(channel.extranonce_prefix.clone().into()),
+                channel.extranonce_size,
+            );
+            self.v1_send_result(id, result);
+        }
+        self.channel = Some(channel);
+        for id in std::mem::take(&mut self.v1_authorize_ids) {
+            self.v1_send_result(id, v1::messages::BooleanResult(true));
+        }
+        self.send_set_difficulty();
+        self.send_current_job();
+    }
+
+    async fn visit_open_extended_mining_channel_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::OpenExtendedMiningChannelError,
+    ) {
+        self.fail(&format!(
+            "Upstream refused channel: {}",
+            payload.code.to_string()
+        ));
+    }
+
+    async fn visit_new_extended_mining_job(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::NewExtendedMiningJob,
+    ) {
+        trace!("visit_new_extended_mining_job() payload:{:?}", payload);
+        if payload.future_job {
+            self.future_jobs.insert(payload.job_id, payload.clone());
+        } else if self.prev_hash.is_some() {
+            self.jobs.insert(payload.job_id, payload.clone());
+            self.send_notify(payload.job_id, false);
+        } else {
+            info!(
+                "Dropping upstream job {} without any prev hash",
+                payload.job_id
+            );
+        }
+    }
+
+    async fn visit_set_new_prev_hash(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SetNewPrevHash,
+    ) {
+        trace!("visit_set_new_prev_hash() payload:{:?}", payload);
+        let job = match self.future_jobs.remove(&payload.job_id) {
+            Some(job) => job,
+            None => match self.jobs.remove(&payload.job_id) {
+                Some(job) => job,
+                None => {
+                    info!(
+                        "Prev hash references unknown upstream job {}",
+                        payload.job_id
+                    );
+                    return;
+                }
+            },
+        };
+        // All jobs built on the previous block are invalid now
+        self.jobs.clear();
+        self.jobs.insert(job.job_id, job);
+        self.prev_hash = Some(payload.clone());
+        self.send_notify(payload.job_id, true);
+    }
+
+    async fn visit_set_target(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SetTarget,
+    ) {
+        trace!("visit_set_target() payload:{:?}", payload);
+        self.target = Some(payload.max_target.into());
+        if self.state == V1ToV2TranslationState::Operational {
+            self.send_set_difficulty();
+        }
+    }
+
+    /// Shares are acknowledged in the order of their submission, all shares up to
+    /// `last_seq_num` are accepted
+    async fn visit_submit_shares_success(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SubmitSharesSuccess,
+    ) {
+        trace!("visit_submit_shares_success() payload:{:?}", payload);
+        if !self
+            .pending_submits
+            .iter()
+            .any(|(seq_num, _)| *seq_num == payload.last_seq_num)
+        {
+            info!("Upstream accepted unknown share #{}", payload.last_seq_num);
+            return;
+        }
+        while let Some((seq_num, id)) = self.pending_submits.pop_front() {
+            self.v1_send_result(id, v1::messages::BooleanResult(true));
+            if seq_num == payload.last_seq_num {
+                break;
+            }
+        }
+    }
+
+    /// Shares preceding the rejected one are treated as accepted
+    async fn visit_submit_shares_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::SubmitSharesError,
+    ) {
+        trace!("visit_submit_shares_error() payload:{:?}", payload);
+        if !self
+            .pending_submits
+            .iter()
+            .any(|(seq_num, _)| *seq_num == payload.seq_num)
+        {
+            info!("Upstream rejected unknown share #{}", payload.seq_num);
+            return;
+        }
+        let code = payload.code.to_string();
+        while let Some((seq_num, id)) = self.pending_submits.pop_front() {
+            if seq_num == payload.seq_num {
+                self.v1_send_error(id, Self::v1_error_code(&code), &code);
+                break;
+            }
+            self.v1_send_result(id, v1::messages::BooleanResult(true));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::stream::StreamExt;
+
+    use ii_async_compat::tokio;
+    use ii_stratum::v2::types::{Bytes0_64k, Seq0_255, Str0_32, Uint256Bytes};
+
+    const CHANNEL_ID: u32 = 7;
+    const JOB_ID: u32 = 0x1f;
+    const JOB_VERSION: u32 = 0x2000_0000;
+    const EXTRANONCE_SIZE: u16 = 4;
+
+    struct Harness {
+        translation: V1ToV2Translation,
+        v1_rx: mpsc::Receiver<v1::Frame>,
+        v2_rx: mpsc::Receiver<v2::Frame>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let (v1_tx, v1_rx) = mpsc::channel(16);
+            let (v2_tx, v2_rx) = mpsc::channel(16);
+            let options = V1ToV2TranslationOptions::new(
+                "upstream.user".to_string(),
+                "pool".to_string(),
+                3336,
+            );
+            let mut translation = V1ToV2Translation::new(v1_tx, v2_tx, options);
+            translation.start().expect("BUG: cannot start translation");
+            Self {
+                translation,
+                v1_rx,
+                v2_rx,
+            }
+        }
+
+        async fn v1_incoming<M>(&mut self, id: Option<u32>, message: M)
+        where
+            M: TryInto<v1::rpc::RequestPayload, Error = ii_stratum::error::Error>,
+        {
+            let rpc: v1::rpc::Rpc = v1::rpc::Request {
+                id,
+                payload: message.try_into().expect("BUG: cannot build request"),
+            }
+            .into();
+            let frame: v1::Frame = rpc.try_into().expect("BUG: cannot build frame");
+            let msg = v1::build_message_from_frame(frame).expect("Deserialization failed");
+            msg.accept(&mut self.translation).await;
+        }
+
+        async fn v2_incoming<M>(&mut self, message: M)
+        where
+            M: TryInto<v2::Frame, Error = ii_stratum::error::Error>,
+        {
+            let frame: v2::Frame = message.try_into().expect("BUG: cannot build frame");
+            let msg = v2::build_message_from_frame(frame).expect("Deserialization failed");
+            msg.accept(&mut self.translation).await;
+        }
+
+        async fn v1_outgoing(&mut self) -> v1::rpc::Rpc {
+            let frame = self.v1_rx.next().await.expect("V1 message was expected");
+            v1::rpc::Rpc::try_from(frame).expect("Deserialization failed")
+        }
+
+        async fn v1_outgoing_response(&mut self, expected_id: u32) -> v1::rpc::ResponsePayload {
+            match self.v1_outgoing().await {
+                v1::rpc::Rpc::Response(response) => {
+                    assert_eq!(response.id, expected_id);
+                    response.payload
+                }
+                rpc => panic!("Unexpected V1 message {:?}", rpc),
+            }
+        }
+
+        async fn v1_outgoing_notification<M>(&mut self) -> M
+        where
+            M: TryFrom<v1::rpc::Request>,
+            <M as TryFrom<v1::rpc::Request>>::Error: std::fmt::Debug,
+        {
+            match self.v1_outgoing().await {
+                v1::rpc::Rpc::Request(request) => {
+                    M::try_from(request).expect("Unexpected V1 notification")
+                }
+                rpc => panic!("Unexpected V1 message {:?}", rpc),
+            }
+        }
+
+        async fn v2_outgoing<M>(&mut self) -> M
+        where
+            M: TryFrom<v2::Frame, Error = ii_stratum::error::Error>,
+        {
+            let frame = self.v2_rx.next().await.expect("V2 message was expected");
+            M::try_from(frame).expect("Unexpected V2 message")
+        }
+
+        fn assert_no_outgoing(&mut self) {
+            assert!(self.v1_rx.try_next().is_err());
+            assert!(self.v2_rx.try_next().is_err());
+        }
+
+        /// Runs the translation up to the point where the miner has been given its first job
+        async fn setup_operational() -> Self {
+            let mut harness = Self::new();
+            let _: v2::messages::SetupConnection = harness.v2_outgoing().await;
+            harness
+                .v2_incoming(v2::messages::SetupConnectionSuccess {
+                    used_version: 2,
+                    flags: 0,
+                })
+                .await;
+
+            let mut configure = v1::messages::Configure::new();
+            configure
+                .add_feature(v1::messages::VersionRolling::new(
+                    ii_stratum::BIP320_N_VERSION_MASK,
+                    0,
+                ))
+                .expect("BUG: cannot add feature");
+            harness.v1_incoming(Some(0), configure).await;
+            let payload = harness.v1_outgoing_response(0).await;
+            let result = payload.result.expect("Configure result was expected").0;
+            assert_eq!(result["version-rolling"], true);
+            assert_eq!(result["version-rolling.mask"], "1fffe000");
+
+            harness
+                .v1_incoming(Some(1), v1::messages::Subscribe(None, None, None, None))
+                .await;
+            let open: v2::messages::OpenExtendedMiningChannel = harness.v2_outgoing().await;
+            assert_eq!(open.user.to_string(), "upstream.user");
+            assert_eq!(open.min_extranonce_size, EXTRANONCE_SIZE);
+
+            harness
+                .v1_incoming(
+                    Some(2),
+                    v1::messages::Authorize("miner".to_string(), "x".to_string()),
+                )
+                .await;
+            harness.assert_no_outgoing();
+
+            harness
+                .v2_incoming(v2::messages::OpenExtendedMiningChannelSuccess {
+                    req_id: open.req_id,
+                    channel_id: CHANNEL_ID,
+                    target: ii_bitcoin::Target::default().into(),
+                    extranonce_size: EXTRANONCE_SIZE,
+                    extranonce_prefix: Bytes0_32::from_slice(&[0xca, 0xfe]),
+                })
+                .await;
+            let payload = harness.v1_outgoing_response(1).await;
+            let result = payload.result.expect("Subscribe result was expected").0;
+            assert_eq!(result[1], "cafe");
+            assert_eq!(result[2], EXTRANONCE_SIZE);
+            let payload = harness.v1_outgoing_response(2).await;
+            assert_eq!(payload.result.expect("Authorize result").0, true);
+            let _: v1::messages::SetDifficulty = harness.v1_outgoing_notification().await;
+
+            harness.v2_incoming(build_job(true)).await;
+            harness.assert_no_outgoing();
+            harness
+                .v2_incoming(v2::messages::SetNewPrevHash {
+                    channel_id: CHANNEL_ID,
+                    job_id: JOB_ID,
+                    prev_hash: Uint256Bytes([0x11; 32]),
+                    min_ntime: 0x5e00_0000,
+                    nbits: 0x1d00_ffff,
+                })
+                .await;
+            let notify: v1::messages::Notify = harness.v1_outgoing_notification().await;
+            assert_eq!(notify.job_id(), "1f");
+            assert_eq!(notify.version(), JOB_VERSION);
+            assert_eq!(notify.bits(), 0x1d00_ffff);
+            assert_eq!(notify.time(), 0x5e00_0000);
+            assert!(notify.clean_jobs());
+
+            harness
+        }
+    }
+
+    fn build_job(future_job: bool) -> v2::messages::NewExtendedMiningJob {
+        v2::messages::NewExtendedMiningJob {
+            channel_id: CHANNEL_ID,
+            job_id: JOB_ID,
+            future_job,
+            version: JOB_VERSION,
+            version_rolling_allowed: true,
+            merkle_path: Seq0_255::from_vec(vec![Uint256Bytes([0xaa; 32])]),
+            coinbase_tx_prefix: Bytes0_64k::from_slice(&[0x01, 0x02]),
+            coinbase_tx_suffix: Bytes0_64k::from_slice(&[0x03]),
+        }
+    }
+
+    fn build_submit(job_id: &str, extranonce2: &[u8]) -> v1::messages::Submit {
+        v1::messages::Submit::new(
+            "miner".to_string(),
+            v1::messages::JobId::from_str(job_id),
+            extranonce2,
+            0x5e00_0001,
+            0xdead_beef,
+            0x0000_2000,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_submit_translation() {
+        let mut harness = Harness::setup_operational().await;
+
+        harness
+            .v1_incoming(Some(3), build_submit("1f", &[0, 0, 0, 1]))
+            .await;
+        let submit: v2::messages::SubmitSharesExtended = harness.v2_outgoing().await;
+        assert_eq!(submit.channel_id, CHANNEL_ID);
+        assert_eq!(submit.job_id, JOB_ID);
+        assert_eq!(submit.nonce, 0xdead_beef);
+        assert_eq!(submit.ntime, 0x5e00_0001);
+        assert_eq!(submit.version, JOB_VERSION | 0x0000_2000);
+        assert_eq!(submit.extranonce.as_ref(), &[0, 0, 0, 1]);
+
+        harness
+            .v1_incoming(Some(4), build_submit("1f", &[0, 0, 0, 2]))
+            .await;
+        let second: v2::messages::SubmitSharesExtended = harness.v2_outgoing().await;
+        harness
+            .v1_incoming(Some(5), build_submit("1f", &[0, 0, 0, 3]))
+            .await;
+        let third: v2::messages::SubmitSharesExtended = harness.v2_outgoing().await;
+
+        // Acknowledging the second share accepts also the first one
+        harness
+            .v2_incoming(v2::messages::SubmitSharesSuccess {
+                channel_id: CHANNEL_ID,
+                last_seq_num: second.seq_num,
+                new_submits_accepted_count: 2,
+                new_shares_sum: 2,
+            })
+            .await;
+        for id in 3..=4 {
+            let payload = harness.v1_outgoing_response(id).await;
+            assert_eq!(payload.result.expect("Submit result").0, true);
+        }
+        harness
+            .v2_incoming(v2::messages::SubmitSharesError {
+                channel_id: CHANNEL_ID,
+                seq_num: third.seq_num,
+                code: Str0_32::from_str("duplicate-share"),
+            })
+            .await;
+        let payload = harness.v1_outgoing_response(5).await;
+        assert_eq!(
+            payload.error.expect("Submit error").0,
+            V1_ERROR_DUPLICATE_SHARE
+        );
+        harness.assert_no_outgoing();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_submit() {
+        let mut harness = Harness::setup_operational().await;
+
+        harness
+            .v1_incoming(Some(3), build_submit("20", &[0, 0, 0, 1]))
+            .await;
+        let payload = harness.v1_outgoing_response(3).await;
+        assert_eq!(
+            payload.error.expect("Submit error").0,
+            V1_ERROR_JOB_NOT_FOUND
+        );
+
+        harness
+            .v1_incoming(Some(4), build_submit("1f", &[0, 1]))
+            .await;
+        let payload = harness.v1_outgoing_response(4).await;
+        assert_eq!(payload.error.expect("Submit error").0, V1_ERROR_OTHER);
+        harness.assert_no_outgoing();
+    }
+
+    #[tokio::test]
+    async fn test_new_block() {
+        let mut harness = Harness::setup_operational().await;
+
+        // Job on current block is sent immediately
+        let mut job = build_job(false);
+        job.job_id = JOB_ID + 1;
+        harness.v2_incoming(job).await;
+        let notify: v1::messages::Notify = harness.v1_outgoing_notification().await;
+        assert_eq!(notify.job_id(), "20");
+        assert!(!notify.clean_jobs());
+
+        let mut job = build_job(true);
+        job.job_id = JOB_ID + 2;
+        harness.v2_incoming(job).await;
+        harness
+            .v2_incoming(v2::messages::SetNewPrevHash {
+                channel_id: CHANNEL_ID,
+                job_id: JOB_ID + 2,
+                prev_hash: Uint256Bytes([0x22; 32]),
+                min_ntime: 0x5e00_1000,
+                nbits: 0x1d00_ffff,
+            })
+            .await;
+        let notify: v1::messages::Notify = harness.v1_outgoing_notification().await;
+        assert_eq!(notify.job_id(), "21");
+        assert!(notify.clean_jobs());
+
+        // Jobs of the previous block are gone
+        harness
+            .v1_incoming(Some(3), build_submit("1f", &[0, 0, 0, 1]))
+            .await;
+        let payload = harness.v1_outgoing_response(3).await;
+        assert_eq!(
+            payload.error.expect("Submit error").0,
+            V1_ERROR_JOB_NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_refused() {
+        let mut harness = Harness::new();
+        let _: v2::messages::SetupConnection = harness.v2_outgoing().await;
+        harness
+            .v1_incoming(Some(1), v1::messages::Subscribe(None, None, None, None))
+            .await;
+        harness
+            .v2_incoming(v2::messages::SetupConnectionSuccess {
+                used_version: 2,
+                flags: 0,
+            })
+            .await;
+        let open: v2::messages::OpenExtendedMiningChannel = harness.v2_outgoing().await;
+        harness
+            .v2_incoming(v2::messages::OpenExtendedMiningChannelError {
+                req_id: open.req_id,
+                code: Str0_32::from_str("unknown-user"),
+            })
+            .await;
+        assert!(harness.translation.is_failed());
+        let payload = harness.v1_outgoing_response(1).await;
+        assert_eq!(payload.error.expect("Subscribe error").0, V1_ERROR_OTHER);
+    }
+}
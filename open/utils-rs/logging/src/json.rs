@@ -0,0 +1,230 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Drain formatting log records as JSON objects, one record per line
+//!
+//! Every record contains the timestamp (`ts`), `level`, `module` (the logging target which
+//! can be used for filtering), optional `tag`, `msg` and all key-value pairs attached to the
+//! record or to the logger.
+
+use std::cell::RefCell;
+use std::fmt::{self, Write as _};
+use std::io;
+
+use chrono::{SecondsFormat, Utc};
+use slog::{Drain, Key, OwnedKVList, Record, Serializer, KV};
+
+/// Write `value` as a quoted JSON string
+fn write_str(buf: &mut String, value: &str) {
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(buf, "\\u{:04x}", c as u32).expect("BUG: cannot write to string")
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+/// Serializer appending key-value pairs as JSON object members to a buffer
+struct JsonSerializer<'a> {
+    buf: &'a mut String,
+}
+
+impl<'a> JsonSerializer<'a> {
+    fn key(&mut self, key: Key) {
+        self.buf.push(',');
+        write_str(self.buf, key);
+        self.buf.push(':');
+    }
+
+    /// Emit value which is valid JSON literal as is
+    fn emit_raw(&mut self, key: Key, val: impl fmt::Display) -> slog::Result {
+        self.key(key);
+        write!(self.buf, "{}", val).expect("BUG: cannot write to string");
+        Ok(())
+    }
+}
+
+impl<'a> Serializer for JsonSerializer<'a> {
+    fn emit_bool(&mut self, key: Key, val: bool) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_usize(&mut self, key: Key, val: usize) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_isize(&mut self, key: Key, val: isize) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_u32(&mut self, key: Key, val: u32) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_i32(&mut self, key: Key, val: i32) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_u64(&mut self, key: Key, val: u64) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_i64(&mut self, key: Key, val: i64) -> slog::Result {
+        self.emit_raw(key, val)
+    }
+
+    fn emit_f64(&mut self, key: Key, val: f64) -> slog::Result {
+        if val.is_finite() {
+            self.emit_raw(key, val)
+        } else {
+            // NaN and infinity cannot be represented in JSON
+            self.emit_none(key)
+        }
+    }
+
+    fn emit_unit(&mut self, key: Key) -> slog::Result {
+        self.emit_none(key)
+    }
+
+    fn emit_none(&mut self, key: Key) -> slog::Result {
+        self.emit_raw(key, "null")
+    }
+
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        self.key(key);
+        write_str(self.buf, &val.to_string());
+        Ok(())
+    }
+}
+
+/// Drain writing records as JSON lines to `W`
+pub struct JsonFormat<W: io::Write> {
+    io: RefCell<W>,
+}
+
+impl<W: io::Write> JsonFormat<W> {
+    pub fn new(io: W) -> Self {
+        Self {
+            io: RefCell::new(io),
+        }
+    }
+
+    /// Format the whole record into one line (including the trailing newline)
+    fn format(&self, record: &Record, values: &OwnedKVList) -> Result<String, slog::Error> {
+        let mut buf = String::with_capacity(256);
+
+        buf.push_str("{\"ts\":");
+        write_str(
+            &mut buf,
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        );
+        buf.push_str(",\"level\":");
+        write_str(&mut buf, record.level().as_str());
+        buf.push_str(",\"module\":");
+        write_str(&mut buf, record.module());
+        if !record.tag().is_empty() {
+            buf.push_str(",\"tag\":");
+            write_str(&mut buf, record.tag());
+        }
+        buf.push_str(",\"msg\":");
+        write_str(&mut buf, &record.msg().to_string());
+
+        {
+            let mut serializer = JsonSerializer { buf: &mut buf };
+            record.kv().serialize(record, &mut serializer)?;
+            values.serialize(record, &mut serializer)?;
+        }
+        buf.push_str("}\n");
+
+        Ok(buf)
+    }
+}
+
+impl<W: io::Write> Drain for JsonFormat<W> {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let line = self.format(record, values).map_err(|e| match e {
+            slog::Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+        })?;
+        let mut io = self.io.borrow_mut();
+        io.write_all(line.as_bytes())?;
+        io.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use slog::{o, Logger};
+    use std::sync::{Arc, Mutex};
+
+    /// Writer shared between the drain and the test
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("BUG: lock").write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let buffer = Buffer::default();
+        let drain = Mutex::new(JsonFormat::new(buffer.clone())).fuse();
+        let logger = Logger::root(drain, o!("chain" => 6));
+
+        slog::info!(logger, #"tag", "hello \"world\"\n"; "nonces" => 42u64, "ratio" => 1.5, "ok" => true, "name" => "a\tb");
+        slog::warn!(logger, "nan"; "value" => std::f64::NAN);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        assert!(lines[0].starts_with("{\"ts\":\""));
+        assert!(lines[0].ends_with(concat!(
+            ",\"level\":\"INFO\",\"module\":\"ii_logging::json::test\",\"tag\":\"tag\"",
+            ",\"msg\":\"hello \\\"world\\\"\\n\"",
+            ",\"name\":\"a\\tb\",\"ok\":true,\"ratio\":1.5,\"nonces\":42,\"chain\":6}"
+        )));
+        assert!(lines[1].ends_with(
+            ",\"level\":\"WARN\",\"module\":\"ii_logging::json::test\",\"msg\":\"nan\",\"value\":null,\"chain\":6}"
+        ));
+    }
+}
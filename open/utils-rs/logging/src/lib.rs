@@ -42,7 +42,14 @@
 //! The global logger is also configured with `slog_envlogger`,
 //! that is, it applies filters set via the `RUST_LOG` env variable.
 //! Refer to the [`env_logger` documentation](https://docs.rs/env_logger/0.6.2/env_logger/)
-//! for more information.
+//! for more information. The same directives (e.g. `bosminer::client=debug,info`) can be set
+//! in `LoggingConfig::filter` to configure levels of particular modules without the env
+//! variable. Every record carries the path of the module it comes from, which serves as its
+//! logging target.
+//!
+//! Records are formatted either for humans or as JSON lines (`LoggingFormat::Json`) suitable
+//! for log shippers. In addition to the main target, logs can be shipped to remote servers
+//! (syslog or TCP), see `LoggingSink`.
 //!
 //! If no configuration is set with `set_logger_config()` et al.,
 //! the global logger will by default use `LoggingConfig::for_testing()`,
@@ -52,7 +59,8 @@
 
 use std::env;
 use std::fmt;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
+use std::io;
 use std::mem;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
@@ -69,6 +77,12 @@ use slog_term;
 pub use slog;
 pub use slog::Level;
 
+mod json;
+mod remote;
+
+pub use json::JsonFormat;
+pub use remote::{LoggingSink, SyslogFormat, TcpWriter};
+
 /// Logging target configuration: Where to log
 #[derive(Clone, Debug)]
 pub enum LoggingTarget {
@@ -82,6 +96,21 @@ pub enum LoggingTarget {
     None,
 }
 
+/// Logging format configuration: How to format records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoggingFormat {
+    /// Human readable lines
+    Full,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for LoggingFormat {
+    fn default() -> Self {
+        LoggingFormat::Full
+    }
+}
+
 /// Describes logger configuration which can be set in runtime
 #[derive(Clone, Debug)]
 pub struct LoggingConfig {
@@ -90,6 +119,14 @@ pub struct LoggingConfig {
     /// The default logging level,
     /// this may be altered with the RUST_LOG env var on startup.
     pub level: Level,
+    /// Optional `env_logger` style directives overriding `level` for particular modules
+    /// (e.g. `bosminer::client=debug,bosminer_am1_s9::tuner=trace`),
+    /// the RUST_LOG env var still takes precedence.
+    pub filter: Option<String>,
+    /// How to format records
+    pub format: LoggingFormat,
+    /// Remote servers receiving all records in addition to `target`
+    pub sinks: Vec<LoggingSink>,
     /// Channel size for the asynchronous drain, increasing the channel size prevents
     /// the drain to drop messages in case of logging bursts
     pub drain_channel_size: usize,
@@ -103,6 +140,9 @@ impl LoggingConfig {
         Self {
             target: LoggingTarget::File(env::temp_dir().join("test-log.txt")),
             level: Level::Trace,
+            filter: None,
+            format: LoggingFormat::Full,
+            sinks: vec![],
             drain_channel_size: Self::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE,
         }
     }
@@ -119,6 +159,9 @@ impl LoggingConfig {
             } else {
                 Level::Info
             },
+            filter: None,
+            format: LoggingFormat::Full,
+            sinks: vec![],
             drain_channel_size,
         }
     }
@@ -128,6 +171,9 @@ impl LoggingConfig {
         Self {
             target: LoggingTarget::None,
             level: Level::Error,
+            filter: None,
+            format: LoggingFormat::Full,
+            sinks: vec![],
             drain_channel_size: Self::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE,
         }
     }
@@ -188,7 +234,11 @@ pub fn setup_for_app(drain_channel_size: usize) -> FlushGuard {
 }
 
 /// Sets up envlogger filter for a drain, with proper default settings
-fn get_envlogger_drain<D: Drain>(drain: D, default_level: Level) -> EnvLogger<D> {
+fn get_envlogger_drain<D: Drain>(
+    drain: D,
+    default_level: Level,
+    filter: Option<&str>,
+) -> EnvLogger<D> {
     let builder = slog_envlogger::LogBuilder::new(drain);
     match env::var("RUST_LOG") {
         Ok(ref rust_log) if !rust_log.is_empty() => {
@@ -203,7 +253,12 @@ fn get_envlogger_drain<D: Drain>(drain: D, default_level: Level) -> EnvLogger<D>
             let filter_level = FilterLevel::from_usize(default_level.as_usize())
                 .expect("Internal error: Could not convert slog::Level to slog::FilterLevel");
 
-            builder.filter(None, filter_level).build()
+            let builder = builder.filter(None, filter_level);
+            match filter {
+                // Module directives are more specific so they override the default level
+                Some(filter) => builder.parse(filter).build(),
+                None => builder.build(),
+            }
         }
     }
 }
@@ -221,9 +276,18 @@ fn get_terminal_drain(stderr: bool) -> impl Drain<Ok = (), Err = impl fmt::Debug
     terminal_drain
 }
 
-/// Create file drain for logger
-fn get_file_drain(path: &Path) -> impl Drain<Ok = (), Err = impl fmt::Debug> {
-    let file = OpenOptions::new()
+/// Create JSON drain for logger, logging to either stderr or stdout
+fn get_json_terminal_drain(stderr: bool) -> JsonFormat<Box<dyn io::Write + Send>> {
+    if stderr {
+        JsonFormat::new(Box::new(io::stderr()))
+    } else {
+        JsonFormat::new(Box::new(io::stdout()))
+    }
+}
+
+/// Open file for logging
+fn open_log_file(path: &Path) -> File {
+    OpenOptions::new()
         .create(true)
         .write(true)
         .append(true)
@@ -236,8 +300,12 @@ fn get_file_drain(path: &Path) -> impl Drain<Ok = (), Err = impl fmt::Debug> {
                 e
             )
         })
-        .unwrap();
+        .unwrap()
+}
 
+/// Create file drain for logger
+fn get_file_drain(path: &Path) -> impl Drain<Ok = (), Err = impl fmt::Debug> {
+    let file = open_log_file(path);
     let file_decorator = slog_term::PlainDecorator::new(file);
     let file_drain = slog_term::FullFormat::new(file_decorator).build();
     file_drain
@@ -265,11 +333,21 @@ impl GuardedLogger {
     fn new(config: &LoggingConfig) -> GuardedLogger {
         use LoggingTarget::*;
 
-        match &config.target {
-            None => Self::with_discard(),
-            Stderr => Self::with_drain(config, get_terminal_drain(true)),
-            Stdout => Self::with_drain(config, get_terminal_drain(false)),
-            File(path) => Self::with_drain(config, get_file_drain(path)),
+        match (&config.target, config.format) {
+            (None, _) if config.sinks.is_empty() => Self::with_discard(),
+            (None, _) => Self::with_drain(config, Discard),
+            (Stderr, LoggingFormat::Full) => Self::with_drain(config, get_terminal_drain(true)),
+            (Stdout, LoggingFormat::Full) => Self::with_drain(config, get_terminal_drain(false)),
+            (File(path), LoggingFormat::Full) => Self::with_drain(config, get_file_drain(path)),
+            (Stderr, LoggingFormat::Json) => {
+                Self::with_drain(config, get_json_terminal_drain(true))
+            }
+            (Stdout, LoggingFormat::Json) => {
+                Self::with_drain(config, get_json_terminal_drain(false))
+            }
+            (File(path), LoggingFormat::Json) => {
+                Self::with_drain(config, JsonFormat::new(open_log_file(path)))
+            }
         }
     }
 
@@ -278,7 +356,11 @@ impl GuardedLogger {
         E: fmt::Debug,
         D: Drain<Ok = (), Err = E> + Send + 'static,
     {
-        let drain = get_envlogger_drain(drain, config.level);
+        let drain = remote::WithSinks {
+            drain,
+            sinks: config.sinks.iter().map(LoggingSink::drain).collect(),
+        };
+        let drain = get_envlogger_drain(drain, config.level, config.filter.as_deref());
         let (drain, guard) = Async::new(drain.fuse())
             .chan_size(config.drain_channel_size)
             .build_with_guard();
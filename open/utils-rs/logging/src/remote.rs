@@ -0,0 +1,500 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Drains shipping logs to remote servers
+//!
+//! Remote sinks never fail the logger: records which cannot be delivered are dropped and the
+//! connection is re-established later. Each sink delivers records from its own thread so that
+//! an unreachable server never stalls the `slog_async` thread. Records are dropped when the
+//! sink cannot keep up.
+
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write as _};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use slog::{Drain, Key, Level, Never, OwnedKVList, Record, Serializer, KV};
+
+use crate::json::JsonFormat;
+
+/// Where to ship logs in addition to the main logging target
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoggingSink {
+    /// RFC 5424 syslog messages sent over UDP to `server` (`host:port`) from the local
+    /// address `bind` (unspecified address of the server's family by default)
+    Syslog {
+        server: String,
+        bind: Option<IpAddr>,
+    },
+    /// JSON lines (see `LoggingFormat::Json`) sent over TCP to `host:port`
+    Tcp(String),
+}
+
+/// Boxed drain which can be combined with any other drain
+pub(crate) type SinkDrain = Box<dyn Drain<Ok = (), Err = Never> + Send>;
+
+impl LoggingSink {
+    pub(crate) fn drain(&self) -> SinkDrain {
+        match self {
+            LoggingSink::Syslog { server, bind } => {
+                Box::new(SyslogFormat::new(server, *bind).ignore_res())
+            }
+            LoggingSink::Tcp(address) => {
+                Box::new(JsonFormat::new(TcpWriter::new(address)).ignore_res())
+            }
+        }
+    }
+}
+
+/// Drain logging into `drain` and all sinks
+pub(crate) struct WithSinks<D> {
+    pub drain: D,
+    pub sinks: Vec<SinkDrain>,
+}
+
+impl<D: Drain<Ok = ()>> Drain for WithSinks<D> {
+    type Ok = ();
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<(), D::Err> {
+        for sink in self.sinks.iter() {
+            // Sinks never fail
+            let _ = sink.log(record, values);
+        }
+        self.drain.log(record, values)
+    }
+}
+
+/// Thread delivering records to remote server. The records are handed over through a bounded
+/// buffer and dropped when it is full.
+struct Sender {
+    tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl Sender {
+    /// Maximal number of records waiting for delivery
+    const BUFFER_SIZE: usize = 1024;
+
+    /// Start thread calling `deliver` for each record. The thread finishes when the sender is
+    /// dropped.
+    fn spawn<F>(name: &str, mut deliver: F) -> Self
+    where
+        F: FnMut(&[u8]) -> io::Result<()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(Self::BUFFER_SIZE);
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                for record in rx.iter() {
+                    // Records which cannot be delivered are dropped
+                    let _ = deliver(&record);
+                }
+            })
+            .expect("BUG: cannot spawn log sender thread");
+        Self { tx }
+    }
+
+    /// Queue `record` for delivery without blocking
+    fn send(&self, record: Vec<u8>) -> io::Result<()> {
+        self.tx.try_send(record).map_err(|e| match e {
+            mpsc::TrySendError::Full(_) => {
+                io::Error::new(io::ErrorKind::WouldBlock, "log sender buffer is full")
+            }
+            mpsc::TrySendError::Disconnected(_) => {
+                io::Error::new(io::ErrorKind::BrokenPipe, "log sender has stopped")
+            }
+        })
+    }
+}
+
+/// Try `connect` with all addresses `address` resolves to and return the first connection
+fn connect_any<T, F>(address: &str, mut connect: F) -> io::Result<T>
+where
+    F: FnMut(SocketAddr) -> io::Result<T>,
+{
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match connect(address) {
+            Ok(connection) => return Ok(connection),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing")
+    }))
+}
+
+/// Connection to remote server which is re-established on failure. The attempts are
+/// rate-limited so that records are not delayed by connecting to an unreachable server.
+struct Connection<T> {
+    address: String,
+    inner: Option<T>,
+    last_attempt: Option<Instant>,
+}
+
+impl<T> Connection<T> {
+    /// Minimal time between two connection attempts
+    const RETRY_PERIOD: Duration = Duration::from_secs(10);
+
+    fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            inner: None,
+            last_attempt: None,
+        }
+    }
+
+    /// Return current connection or try to establish a new one with `connect`
+    fn get<F>(&mut self, connect: F) -> io::Result<&mut T>
+    where
+        F: FnOnce(&str) -> io::Result<T>,
+    {
+        if self.inner.is_none() {
+            let now = Instant::now();
+            if let Some(last_attempt) = self.last_attempt {
+                if now.duration_since(last_attempt) < Self::RETRY_PERIOD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        "waiting for reconnect",
+                    ));
+                }
+            }
+            self.last_attempt = Some(now);
+            self.inner = Some(connect(&self.address)?);
+        }
+        Ok(self.inner.as_mut().expect("BUG: missing connection"))
+    }
+
+    /// Run `f` on the connection and drop the connection when it fails
+    fn with<F, G>(&mut self, connect: F, f: G) -> io::Result<()>
+    where
+        F: FnOnce(&str) -> io::Result<T>,
+        G: FnOnce(&mut T) -> io::Result<()>,
+    {
+        let result = f(self.get(connect)?);
+        if result.is_err() {
+            self.inner = None;
+        }
+        result
+    }
+}
+
+/// Writer sending data over TCP
+pub struct TcpWriter {
+    sender: Sender,
+}
+
+impl TcpWriter {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(3);
+
+    pub fn new(address: &str) -> Self {
+        let mut connection = Connection::new(address);
+        let sender = Sender::spawn("log-tcp", move |record| {
+            connection.with(Self::connect, |stream| stream.write_all(record))
+        });
+        Self { sender }
+    }
+
+    fn connect(address: &str) -> io::Result<TcpStream> {
+        connect_any(address, |address| {
+            let stream = TcpStream::connect_timeout(&address, Self::CONNECT_TIMEOUT)?;
+            stream.set_write_timeout(Some(Self::WRITE_TIMEOUT))?;
+            Ok(stream)
+        })
+    }
+}
+
+impl io::Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Records are written at once so a partially sent record is not continued on a new
+        // connection
+        self.write_all(buf).map(|_| buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.sender.send(buf.to_vec())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Records are flushed by the sender thread
+        Ok(())
+    }
+}
+
+/// Serializer appending key-value pairs as `key=value` to a buffer
+struct TextSerializer<'a> {
+    buf: &'a mut String,
+}
+
+impl<'a> Serializer for TextSerializer<'a> {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        write!(self.buf, " {}={}", key, val).expect("BUG: cannot write to string");
+        Ok(())
+    }
+}
+
+/// Drain sending records to syslog server as RFC 5424 messages over UDP
+pub struct SyslogFormat {
+    hostname: String,
+    app_name: String,
+    sender: Sender,
+}
+
+impl SyslogFormat {
+    /// Messages are sent with facility `daemon`
+    const FACILITY: u8 = 3;
+    /// Longer messages are truncated, RFC 5424 recommends receivers to support at least
+    /// this size
+    const MAX_MESSAGE_SIZE: usize = 2048;
+    /// The `-` means the value is unknown
+    const NIL_VALUE: &'static str = "-";
+
+    pub fn new(address: &str, bind: Option<IpAddr>) -> Self {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty())
+            .unwrap_or_else(|| Self::NIL_VALUE.to_string());
+        let app_name = env::args()
+            .next()
+            .as_ref()
+            .and_then(|path| Path::new(path).file_name())
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| Self::NIL_VALUE.to_string());
+
+        let mut connection = Connection::new(address);
+        let sender = Sender::spawn("log-syslog", move |message| {
+            connection.with(
+                |address| Self::connect(address, bind),
+                |socket| socket.send(message).map(|_| ()),
+            )
+        });
+
+        Self {
+            hostname,
+            app_name,
+            sender,
+        }
+    }
+
+    fn severity(level: Level) -> u8 {
+        match level {
+            Level::Critical => 2,
+            Level::Error => 3,
+            Level::Warning => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
+    fn connect(address: &str, bind: Option<IpAddr>) -> io::Result<UdpSocket> {
+        connect_any(address, |address| {
+            let bind = bind.unwrap_or_else(|| match address {
+                SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+                SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+            });
+            let socket = UdpSocket::bind((bind, 0))?;
+            socket.connect(address)?;
+            Ok(socket)
+        })
+    }
+
+    /// Format the record as syslog message, the `timestamp` is in RFC 3339 format
+    fn format(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+        timestamp: &str,
+    ) -> Result<String, slog::Error> {
+        let mut buf = String::with_capacity(256);
+
+        // HEADER: PRI VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID, no STRUCTURED-DATA
+        write!(
+            buf,
+            "<{}>1 {} {} {} {} {} {} {}: {}",
+            Self::FACILITY * 8 + Self::severity(record.level()),
+            timestamp,
+            self.hostname,
+            self.app_name,
+            process::id(),
+            Self::NIL_VALUE,
+            Self::NIL_VALUE,
+            record.module(),
+            record.msg()
+        )
+        .expect("BUG: cannot write to string");
+        {
+            let mut serializer = TextSerializer { buf: &mut buf };
+            record.kv().serialize(record, &mut serializer)?;
+            values.serialize(record, &mut serializer)?;
+        }
+
+        if buf.len() > Self::MAX_MESSAGE_SIZE {
+            let mut size = Self::MAX_MESSAGE_SIZE;
+            while !buf.is_char_boundary(size) {
+                size -= 1;
+            }
+            buf.truncate(size);
+        }
+        Ok(buf)
+    }
+}
+
+impl Drain for SyslogFormat {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let message = self
+            .format(record, values, &timestamp)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        self.sender.send(message.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use slog::{o, Logger};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_syslog() {
+        let server = UdpSocket::bind("127.0.0.1:0").expect("BUG: cannot bind");
+        let address = server.local_addr().expect("BUG: no address").to_string();
+        let sink = LoggingSink::Syslog {
+            server: address,
+            bind: None,
+        };
+        let drain = Mutex::new(sink.drain()).fuse();
+        let logger = Logger::root(drain, o!("chain" => 6));
+
+        slog::warn!(logger, "chip {} is hot", 12; "temperature" => 95);
+
+        let mut buf = [0u8; 4096];
+        let size = server.recv(&mut buf).expect("BUG: cannot receive");
+        let message = std::str::from_utf8(&buf[..size]).expect("BUG: invalid message");
+        // daemon.warning
+        assert!(message.starts_with("<28>1 "));
+        assert!(message.ends_with(&format!(
+            " {} - - ii_logging::remote::test: chip 12 is hot temperature=95 chain=6",
+            process::id()
+        )));
+
+        // Long message is truncated on character boundary
+        slog::info!(logger, "{}", "ř".repeat(SyslogFormat::MAX_MESSAGE_SIZE));
+        let size = server.recv(&mut buf).expect("BUG: cannot receive");
+        assert!(size <= SyslogFormat::MAX_MESSAGE_SIZE);
+        assert!(std::str::from_utf8(&buf[..size]).is_ok());
+    }
+
+    #[test]
+    fn test_syslog_bind() {
+        let server = UdpSocket::bind("127.0.0.1:0").expect("BUG: cannot bind");
+        let address = server.local_addr().expect("BUG: no address").to_string();
+        let bind = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let sink = LoggingSink::Syslog {
+            server: address,
+            bind: Some(bind),
+        };
+        let logger = Logger::root(Mutex::new(sink.drain()).fuse(), o!());
+
+        slog::info!(logger, "bound");
+        let mut buf = [0u8; 4096];
+        let (_, source) = server.recv_from(&mut buf).expect("BUG: cannot receive");
+        assert_eq!(source.ip(), bind);
+    }
+
+    #[test]
+    fn test_sender_full() {
+        // Delivery is stuck until the test finishes
+        let (unblock_tx, unblock_rx) = mpsc::channel::<()>();
+        let sender = Sender::spawn("test", move |_| {
+            let _ = unblock_rx.recv();
+            Ok(())
+        });
+
+        // One record is being delivered and the rest waits in the buffer
+        let results: Vec<_> = (0..Sender::BUFFER_SIZE + 2)
+            .map(|_| sender.send(vec![0]))
+            .collect();
+        let error = results
+            .into_iter()
+            .last()
+            .expect("BUG: no result")
+            .expect_err("BUG: full buffer accepted record");
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        drop(unblock_tx);
+    }
+
+    #[test]
+    fn test_tcp() {
+        let server = TcpListener::bind("127.0.0.1:0").expect("BUG: cannot bind");
+        let address = server.local_addr().expect("BUG: no address").to_string();
+        let drain = Mutex::new(LoggingSink::Tcp(address).drain()).fuse();
+        let logger = Logger::root(drain, o!());
+
+        slog::info!(logger, "first");
+        let (stream, _) = server.accept().expect("BUG: cannot accept");
+        slog::info!(logger, "second");
+
+        let mut lines = BufReader::new(stream).lines();
+        for msg in &["first", "second"] {
+            let line = lines
+                .next()
+                .expect("BUG: missing record")
+                .expect("BUG: cannot read");
+            assert!(line.ends_with(&format!(
+                ",\"level\":\"INFO\",\"module\":\"ii_logging::remote::test\",\"msg\":\"{}\"}}",
+                msg
+            )));
+        }
+    }
+
+    #[test]
+    fn test_unreachable() {
+        // Nobody listens on the port (it was just released), the records are dropped
+        let address = TcpListener::bind("127.0.0.1:0")
+            .expect("BUG: cannot bind")
+            .local_addr()
+            .expect("BUG: no address")
+            .to_string();
+        let drain = Mutex::new(LoggingSink::Tcp(address).drain()).fuse();
+        let logger = Logger::root(drain, o!());
+
+        slog::info!(logger, "dropped");
+        slog::info!(logger, "dropped again");
+    }
+}
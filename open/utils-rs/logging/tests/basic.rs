@@ -46,6 +46,9 @@ fn test_logging_basic() {
     let config = LoggingConfig {
         target: LoggingTarget::File(temp_file.path().into()),
         level: Level::Trace,
+        filter: None,
+        format: Default::default(),
+        sinks: vec![],
         drain_channel_size: LoggingConfig::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE,
     };
 
@@ -0,0 +1,75 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Test of JSON formatting and per-module filter directives.
+//!
+//! **Warning**: Each logging test needs to be in a separate files
+//! due to global LOGGER initialization
+
+use std::env;
+use std::fs;
+
+use ii_logging::macros::*;
+use ii_logging::{self, Level, LoggingConfig, LoggingFormat, LoggingTarget, LOGGER};
+
+use tempfile::NamedTempFile;
+
+mod component {
+    use ii_logging::macros::*;
+
+    pub fn log(msg: &str) {
+        debug!("{}", msg);
+    }
+}
+
+#[test]
+fn test_logging_json() {
+    env::set_var("RUST_LOG", "");
+
+    // Only records of the `component` module are logged at debug level
+    let temp_file = NamedTempFile::new().expect("Could not create temporary file");
+    let config = LoggingConfig {
+        target: LoggingTarget::File(temp_file.path().into()),
+        level: Level::Info,
+        filter: Some("json::component=debug".to_string()),
+        format: LoggingFormat::Json,
+        sinks: vec![],
+        drain_channel_size: LoggingConfig::ASYNC_LOGGER_DRAIN_CHANNEL_SIZE,
+    };
+
+    ii_logging::set_logger_config(config);
+    let flush_guard = LOGGER.take_guard();
+
+    component::log("component message");
+    debug!("filtered message");
+    info!("default message"; "chain" => 3);
+    drop(flush_guard);
+
+    let log_contents = fs::read_to_string(temp_file.path()).expect("Could not read back log file");
+    let lines: Vec<_> = log_contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0]
+        .contains(r#""level":"DEBUG","module":"json::component","msg":"component message"}"#));
+    assert!(
+        lines[1].contains(r#""level":"INFO","module":"json","msg":"default message","chain":3}"#)
+    );
+}
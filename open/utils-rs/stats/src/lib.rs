@@ -24,6 +24,22 @@
 
 use std::time::{Duration, Instant};
 
+/// Number of hashes represented by one share at difficulty 1 as used by pools for hashrate
+/// estimation
+const DIFFICULTY_1_HASHES: f64 = 4294967296.0;
+
+/// Compute hashrate (in H/s) implied by the sum of accepted share difficulties within a time
+/// `window`. This is the estimate pools report so it can be compared with the local measurement
+/// of the same window to detect share loss.
+pub fn pool_implied_hashrate(difficulty_sum: f64, window: Duration) -> f64 {
+    let secs = window.as_secs_f64();
+    if secs == 0.0 {
+        0.0
+    } else {
+        difficulty_sum * DIFFICULTY_1_HASHES / secs
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct WindowedTimeMeanState {
     /// Window interval
@@ -139,6 +155,35 @@ pub mod test {
         mean.insert(1.0, start);
     }
 
+    #[test]
+    fn test_pool_implied_hashrate() {
+        assert_eq!(pool_implied_hashrate(1.0, Duration::from_secs(0)), 0.0);
+        assert_eq!(
+            pool_implied_hashrate(1.0, Duration::from_secs(1)),
+            DIFFICULTY_1_HASHES
+        );
+
+        // synthetic stream of shares at difficulty 1024 submitted each second
+        let start = Instant::now();
+        let window = 60;
+        let difficulty = 1024.0;
+        let mut local_meter = WindowedTimeMeanState::new(window as f64);
+        let mut difficulty_sum = 0.0;
+        for i in 0..window {
+            local_meter.insert(
+                difficulty * DIFFICULTY_1_HASHES,
+                start + Duration::from_secs(i),
+            );
+            difficulty_sum += difficulty;
+        }
+
+        let local_hashrate = local_meter.measure(start + Duration::from_secs(window));
+        let pool_hashrate = pool_implied_hashrate(difficulty_sum, Duration::from_secs(window));
+        assert!((local_hashrate - pool_hashrate).abs() / pool_hashrate < 1e-9);
+        // roughly 4.4 TH/s
+        assert_eq!(pool_hashrate, difficulty * DIFFICULTY_1_HASHES);
+    }
+
     #[test]
     #[ignore]
     fn test_windowed_time_mean_3s() {